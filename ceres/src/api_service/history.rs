@@ -0,0 +1,147 @@
+//! Matching the lines of two versions of a file, to blame the lines on the commits adding them.
+
+/// The edit scripts longer than this are not searched, the lines are matched by the common
+/// prefix and suffix only.
+const MAX_EDITS: usize = 4096;
+
+/// For each line of `new`, the line of `old` it is kept from, by a shortest edit script.
+pub fn match_lines<T: PartialEq>(old: &[T], new: &[T]) -> Vec<Option<usize>> {
+    let mut matches = vec![None; new.len()];
+    let prefix = old.iter().zip(new).take_while(|(a, b)| a == b).count();
+    for (i, m) in matches.iter_mut().enumerate().take(prefix) {
+        *m = Some(i);
+    }
+    let (old, new) = (&old[prefix..], &new[prefix..]);
+    let suffix = old
+        .iter()
+        .rev()
+        .zip(new.iter().rev())
+        .take_while(|(a, b)| a == b)
+        .count();
+    for i in 1..=suffix {
+        matches[prefix + new.len() - i] = Some(prefix + old.len() - i);
+    }
+    let (old, new) = (&old[..old.len() - suffix], &new[..new.len() - suffix]);
+    for (x, y) in myers(old, new) {
+        matches[prefix + y] = Some(prefix + x);
+    }
+    matches
+}
+
+/// The pairs of equal lines of a shortest edit script from `a` to `b`, by Myers' O(ND) algorithm.
+fn myers<T: PartialEq>(a: &[T], b: &[T]) -> Vec<(usize, usize)> {
+    let (n, m) = (a.len() as isize, b.len() as isize);
+    if n == 0 || m == 0 {
+        return Vec::new();
+    }
+    let max = (n + m) as usize;
+    let offset = max as isize + 1;
+    // the furthest x of each diagonal k = x - y, at index k + offset
+    let mut v = vec![0isize; 2 * max + 3];
+    // for each d, the diagonals -d-1..=d+1 of `v` before the round d
+    let mut trace = Vec::new();
+    let furthest = |v: &[isize], k: isize, d: isize| {
+        let idx = (k + offset) as usize;
+        if k == -d || (k != d && v[idx - 1] < v[idx + 1]) {
+            k + 1 // down from the diagonal above, an insertion
+        } else {
+            k - 1 // right from the diagonal below, a deletion
+        }
+    };
+
+    'search: for d in 0..=max.min(MAX_EDITS) as isize {
+        trace.push(v[(offset - d - 1) as usize..=(offset + d + 1) as usize].to_vec());
+        for k in (-d..=d).step_by(2) {
+            let prev = furthest(&v, k, d);
+            let mut x = if prev == k + 1 {
+                v[(prev + offset) as usize]
+            } else {
+                v[(prev + offset) as usize] + 1
+            };
+            let mut y = x - k;
+            while x < n && y < m && a[x as usize] == b[y as usize] {
+                x += 1;
+                y += 1;
+            }
+            v[(k + offset) as usize] = x;
+            if x >= n && y >= m {
+                break 'search;
+            }
+        }
+        if d as usize == MAX_EDITS {
+            return Vec::new();
+        }
+    }
+
+    let mut pairs = Vec::new();
+    let (mut x, mut y) = (n, m);
+    for (d, snapshot) in trace.iter().enumerate().rev() {
+        let d = d as isize;
+        // `snapshot` starts at the diagonal -d-1
+        let at = |k: isize| snapshot[(k + d + 1) as usize];
+        let k = x - y;
+        let prev_k = if k == -d || (k != d && at(k - 1) < at(k + 1)) {
+            k + 1
+        } else {
+            k - 1
+        };
+        let prev_x = if d == 0 { 0 } else { at(prev_k) };
+        let prev_y = if d == 0 { 0 } else { prev_x - prev_k };
+        // the snake of the round d, then its edit
+        let (start_x, start_y) = if d == 0 {
+            (0, 0)
+        } else if prev_k == k + 1 {
+            (prev_x, prev_x - k)
+        } else {
+            (prev_x + 1, prev_x + 1 - k)
+        };
+        while x > start_x && y > start_y {
+            x -= 1;
+            y -= 1;
+            pairs.push((x as usize, y as usize));
+        }
+        x = prev_x;
+        y = prev_y;
+    }
+    pairs.reverse();
+    pairs
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn lines(text: &str) -> Vec<&str> {
+        text.lines().collect()
+    }
+
+    #[test]
+    fn test_match_lines() {
+        let old = lines("a\nb\nc\nd\ne");
+        let new = lines("a\nx\nc\nd\ny\ne\nf");
+        assert_eq!(
+            match_lines(&old, &new),
+            [Some(0), None, Some(2), Some(3), None, Some(4), None]
+        );
+
+        assert_eq!(match_lines(&old, &[]), []);
+        assert_eq!(match_lines(&[], &new), [None; 7]);
+        let moved = lines("e\na\nb\nc\nd");
+        assert_eq!(
+            match_lines(&old, &moved),
+            [None, Some(0), Some(1), Some(2), Some(3)]
+        );
+    }
+
+    #[test]
+    fn test_myers_is_shortest() {
+        let old = lines("a\nb\nc\na\nb\nb\na");
+        let new = lines("c\nb\na\nb\na\nc");
+        let pairs = myers(&old, &new);
+        // the shortest edit script of the example of Myers' paper has 5 edits
+        assert_eq!(old.len() + new.len() - 2 * pairs.len(), 5);
+        for (x, y) in pairs {
+            assert_eq!(old[x], new[y]);
+        }
+    }
+}
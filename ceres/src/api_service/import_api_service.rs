@@ -56,15 +56,22 @@ impl ApiHandler for ImportApiService {
             ))
         }
     }
-    async fn get_root_commit(&self) -> Commit {
+    async fn get_root_commit(&self) -> Result<Commit, GitError> {
         let storage = self.context.services.git_db_storage.clone();
-        let refs = storage.get_default_ref(&self.repo).await.unwrap().unwrap();
+        let refs = storage
+            .get_default_ref(&self.repo)
+            .await
+            .map_err(|e| GitError::CustomError(e.to_string()))?
+            .ok_or(GitError::ObjectNotFound(format!(
+                "the default ref of {}",
+                self.repo.repo_path
+            )))?;
         storage
             .get_commit_by_hash(&self.repo, &refs.ref_hash)
             .await
-            .unwrap()
-            .unwrap()
-            .into()
+            .map_err(|e| GitError::CustomError(e.to_string()))?
+            .map(Commit::from)
+            .ok_or(GitError::ObjectNotFound(refs.ref_hash))
     }
 
     async fn get_root_tree(&self) -> Tree {
@@ -149,6 +156,44 @@ impl ApiHandler for ImportApiService {
         Ok(commits.into_iter().map(|x| x.into()).collect())
     }
 
+    async fn get_trees_by_hashes(&self, hashes: Vec<String>) -> Result<Vec<Tree>, GitError> {
+        let storage = self.context.services.git_db_storage.clone();
+        let trees = storage
+            .get_trees_by_hashes(&self.repo, hashes)
+            .await
+            .map_err(|e| GitError::CustomError(e.to_string()))?;
+        Ok(trees.into_iter().map(|x| x.into()).collect())
+    }
+
+    async fn get_raw_blobs_by_hashes(
+        &self,
+        hashes: Vec<String>,
+    ) -> Result<Vec<raw_blob::Model>, GitError> {
+        self.context
+            .services
+            .mega_storage
+            .get_raw_blobs_by_hashes(hashes)
+            .await
+            .map_err(|e| GitError::CustomError(e.to_string()))
+    }
+
+    async fn get_raw_blob_sizes(
+        &self,
+        hashes: Vec<String>,
+    ) -> Result<HashMap<String, u64>, GitError> {
+        let sizes = self
+            .context
+            .services
+            .mega_storage
+            .get_raw_blob_sizes(hashes)
+            .await
+            .map_err(|e| GitError::CustomError(e.to_string()))?;
+        Ok(sizes
+            .into_iter()
+            .map(|(id, size)| (id, size as u64))
+            .collect())
+    }
+
     async fn traverse_commit_history(
         &self,
         path: &Path,
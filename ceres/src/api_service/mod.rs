@@ -1,14 +1,17 @@
 use std::{
-    collections::{HashMap, HashSet, VecDeque},
+    collections::{hash_map::Entry, BTreeSet, HashMap, HashSet, VecDeque},
     path::{Component, Path, PathBuf},
 };
 
 use axum::async_trait;
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
 
 use callisto::raw_blob;
 use common::errors::MegaError;
 use mercury::{
     errors::GitError,
+    hash::SHA1,
     internal::object::{
         commit::Commit,
         tree::{Tree, TreeItem, TreeItemMode},
@@ -17,12 +20,29 @@ use mercury::{
 use venus::monorepo::converter;
 
 use crate::model::{
-    create_file::CreateFileInfo, publish_path::PublishPathInfo, tree::{LatestCommitInfo, TreeBriefItem, TreeCommitItem, UserInfo}
+    blob::{BlobBatch, BlobData},
+    create_file::CreateFileInfo,
+    history::{BlameInfo, BlameLine, CommitHistory},
+    publish_path::PublishPathInfo,
+    tree::{LatestCommitInfo, TreeBriefItem, TreeCommitItem, TreeListing, TreeNode, UserInfo},
 };
 
+pub mod history;
 pub mod import_api_service;
 pub mod mono_api_service;
 
+/// The deepest listing of [`ApiHandler::get_tree_listing`].
+pub const MAX_TREE_DEPTH: usize = 32;
+/// The most entries of a listing, the directories past it are not expanded.
+pub const MAX_TREE_ENTRIES: usize = 10_000;
+/// The most blobs of [`ApiHandler::get_blob_batch`].
+pub const MAX_BLOB_BATCH: usize = 500;
+/// The most commits of a page of [`ApiHandler::get_path_history`].
+pub const MAX_HISTORY_PAGE: usize = 100;
+/// The most commits walked by [`ApiHandler::get_path_history`], [`ApiHandler::get_blame`] and
+/// [`ApiHandler::get_commits_between`], the lines left by a blame are blamed on the last one.
+pub const MAX_WALKED_COMMITS: usize = 10_000;
+
 #[async_trait]
 pub trait ApiHandler: Send + Sync {
     async fn create_monorepo_file(&self, file_info: CreateFileInfo) -> Result<(), GitError>;
@@ -33,7 +53,8 @@ pub trait ApiHandler: Send + Sync {
 
     fn strip_relative(&self, path: &Path) -> Result<PathBuf, GitError>;
 
-    async fn get_root_commit(&self) -> Commit;
+    /// The latest commit, an [`GitError::ObjectNotFound`] if there is no ref yet.
+    async fn get_root_commit(&self) -> Result<Commit, GitError>;
 
     async fn get_root_tree(&self) -> Tree;
 
@@ -55,6 +76,20 @@ pub trait ApiHandler: Send + Sync {
 
     async fn get_commits_by_hashes(&self, c_hashes: Vec<String>) -> Result<Vec<Commit>, GitError>;
 
+    /// The trees found among `hashes`, in no particular order.
+    async fn get_trees_by_hashes(&self, hashes: Vec<String>) -> Result<Vec<Tree>, GitError>;
+
+    async fn get_raw_blobs_by_hashes(
+        &self,
+        hashes: Vec<String>,
+    ) -> Result<Vec<raw_blob::Model>, GitError>;
+
+    /// The sizes in bytes of the blobs among `hashes` which are stored.
+    async fn get_raw_blob_sizes(
+        &self,
+        hashes: Vec<String>,
+    ) -> Result<HashMap<String, u64>, GitError>;
+
     async fn traverse_commit_history(
         &self,
         path: &Path,
@@ -69,7 +104,14 @@ pub trait ApiHandler: Send + Sync {
         if let Some(tree) = self.search_tree_by_path(parent).await? {
             if let Some(item) = tree.tree_items.into_iter().find(|x| x.name == filename) {
                 plain_text = match self.get_raw_blob_by_hash(&item.id.to_plain_str()).await {
-                    Ok(Some(model)) => String::from_utf8(model.data.unwrap()).unwrap(),
+                    Ok(Some(model)) => {
+                        String::from_utf8(model.data.unwrap_or_default()).map_err(|_| {
+                            GitError::CustomError(format!(
+                                "{} is not UTF-8 text, fetch it with the blobs API",
+                                file_path.display()
+                            ))
+                        })?
+                    }
                     _ => String::new(),
                 };
             }
@@ -92,6 +134,7 @@ pub trait ApiHandler: Send + Sync {
     async fn get_tree_info(&self, path: PathBuf) -> Result<Vec<TreeBriefItem>, GitError> {
         match self.search_tree_by_path(&path).await? {
             Some(tree) => {
+                let sizes = self.get_raw_blob_sizes(file_ids([&tree])).await?;
                 let mut items = Vec::new();
                 for item in tree.tree_items {
                     let mut info: TreeBriefItem = item.clone().into();
@@ -99,6 +142,7 @@ pub trait ApiHandler: Send + Sync {
                         .to_str()
                         .unwrap()
                         .clone_into(&mut info.path);
+                    info.size = sizes.get(&info.oid).copied();
                     items.push(info);
                 }
                 Ok(items)
@@ -151,7 +195,7 @@ pub trait ApiHandler: Send + Sync {
                         } else {
                             tracing::error!("failed fecth commit: {}", commit_id);
                             &self
-                                .traverse_commit_history(&path, self.get_root_commit().await, item)
+                                .traverse_commit_history(&path, self.get_root_commit().await?, item)
                                 .await
                         };
                         info.oid = commit.id.to_plain_str();
@@ -166,6 +210,518 @@ pub trait ApiHandler: Send + Sync {
         }
     }
 
+    /// Lists the directory `path` at a commit, or the tree `tree`, with the entries of its
+    /// subdirectories down to `depth` levels.
+    ///
+    /// The latest commit is used when neither is given, and `path` only names the entries of
+    /// `tree` when it is. Returns `None` if the commit, the tree or the directory does not exist.
+    async fn get_tree_listing(
+        &self,
+        path: PathBuf,
+        commit: Option<String>,
+        tree: Option<String>,
+        depth: usize,
+    ) -> Result<Option<TreeListing>, GitError> {
+        let (commit_id, start) = if let Some(tree) = tree {
+            (
+                String::new(),
+                self.get_trees_by_hashes(vec![tree]).await?.pop(),
+            )
+        } else {
+            let commit = match commit {
+                Some(commit) => self.get_commits_by_hashes(vec![commit]).await?.pop(),
+                None => Some(self.get_root_commit().await?),
+            };
+            let Some(commit) = commit else {
+                return Ok(None);
+            };
+            let relative_path = self.strip_relative(&path)?;
+            let mut search_tree = self
+                .get_trees_by_hashes(vec![commit.tree_id.to_plain_str()])
+                .await?
+                .pop();
+            for component in relative_path.components() {
+                let (Some(tree), Component::Normal(name)) = (&search_tree, component) else {
+                    continue;
+                };
+                search_tree = match tree
+                    .tree_items
+                    .iter()
+                    .find(|x| x.mode == TreeItemMode::Tree && x.name.as_str() == name)
+                {
+                    Some(item) => self
+                        .get_trees_by_hashes(vec![item.id.to_plain_str()])
+                        .await?
+                        .pop(),
+                    None => None,
+                };
+            }
+            (commit.id.to_plain_str(), search_tree)
+        };
+        let Some(start) = start else {
+            return Ok(None);
+        };
+
+        // load the trees level by level, so a level is either complete or left out
+        let depth = depth.clamp(1, MAX_TREE_DEPTH);
+        let mut trees: HashMap<String, Tree> = HashMap::new();
+        let mut entries = start.tree_items.len();
+        let mut truncated = false;
+        let mut level = vec![start.clone()];
+        for _ in 1..depth {
+            let subtrees: HashSet<String> = level
+                .iter()
+                .flat_map(|tree| tree.tree_items.iter())
+                .filter(|x| x.mode == TreeItemMode::Tree)
+                .map(|x| x.id.to_plain_str())
+                .collect();
+            let missing: Vec<String> = subtrees
+                .iter()
+                .filter(|id| !trees.contains_key(*id))
+                .cloned()
+                .collect();
+            let mut loaded: HashMap<String, Tree> = HashMap::new();
+            for tree in self.get_trees_by_hashes(missing).await? {
+                loaded.insert(tree.id.to_plain_str(), tree);
+            }
+            entries += loaded.values().map(|x| x.tree_items.len()).sum::<usize>();
+            if entries > MAX_TREE_ENTRIES {
+                truncated = true;
+                break;
+            }
+            trees.extend(loaded);
+            level = subtrees
+                .iter()
+                .filter_map(|id| trees.get(id).cloned())
+                .collect();
+            if level.is_empty() {
+                break;
+            }
+        }
+
+        let sizes = self
+            .get_raw_blob_sizes(file_ids(trees.values().chain([&start])))
+            .await?;
+        Ok(Some(TreeListing {
+            commit: commit_id,
+            path: path.to_str().unwrap().to_owned(),
+            oid: start.id.to_plain_str(),
+            truncated,
+            items: tree_nodes(&start, &path, &trees, &sizes, depth),
+        }))
+    }
+
+    /// The contents of the blobs `oids`, at most [`MAX_BLOB_BATCH`] of them.
+    async fn get_blob_batch(&self, oids: Vec<String>) -> Result<BlobBatch, GitError> {
+        if oids.len() > MAX_BLOB_BATCH {
+            return Err(GitError::CustomError(format!(
+                "at most {} blobs can be fetched at a time",
+                MAX_BLOB_BATCH
+            )));
+        }
+        let hashes: HashSet<String> = oids.iter().cloned().collect();
+        let mut found: HashMap<String, Vec<u8>> = self
+            .get_raw_blobs_by_hashes(hashes.into_iter().collect())
+            .await?
+            .into_iter()
+            .filter_map(|model| Some((model.sha1, model.data?)))
+            .collect();
+        let mut batch = BlobBatch {
+            blobs: Vec::new(),
+            missing: Vec::new(),
+        };
+        let mut seen = HashSet::new();
+        for oid in oids {
+            if !seen.insert(oid.clone()) {
+                continue;
+            }
+            match found.remove(&oid) {
+                Some(data) => batch.blobs.push(BlobData {
+                    oid,
+                    size: data.len(),
+                    data: STANDARD.encode(&data),
+                }),
+                None => batch.missing.push(oid),
+            }
+        }
+        Ok(batch)
+    }
+
+    /// The commits which changed `path`, the latest first, from `commit` or the latest commit.
+    ///
+    /// Like `git log <path>`, a merge is followed only into a parent with the same `path`, and
+    /// it is listed when there is none. `page` counts from 1.
+    async fn get_path_history(
+        &self,
+        path: PathBuf,
+        commit: Option<String>,
+        page: usize,
+        per_page: usize,
+    ) -> Result<CommitHistory, GitError> {
+        let names = self.path_names(&path)?;
+        let per_page = per_page.clamp(1, MAX_HISTORY_PAGE);
+        let page = page.max(1);
+        let wanted = page * per_page + 1;
+
+        let start = self.get_start_commit(commit).await?;
+        let mut commits = HashMap::new();
+        let mut trees = HashMap::new();
+        let mut pending = BTreeSet::from([(start.committer.timestamp, start.id)]);
+        commits.insert(start.id, start);
+        let mut seen = HashSet::new();
+        let mut found = Vec::new();
+        while let Some((_, id)) = pending.pop_last() {
+            if found.len() >= wanted || seen.len() >= MAX_WALKED_COMMITS {
+                break;
+            }
+            if !seen.insert(id) {
+                continue;
+            }
+            let commit = commits[&id].clone();
+            let entry = self
+                .find_path_in_tree(commit.tree_id, &names, &mut trees)
+                .await?;
+            self.load_commits(&commit.parent_commit_ids, &mut commits)
+                .await?;
+            let mut parents = Vec::new();
+            for parent_id in &commit.parent_commit_ids {
+                if let Some(parent) = commits.get(parent_id) {
+                    let tree_id = parent.tree_id;
+                    let parent_entry = self.find_path_in_tree(tree_id, &names, &mut trees).await?;
+                    parents.push((*parent_id, parent_entry));
+                }
+            }
+            let follow: Vec<SHA1> = match parents.iter().find(|(_, e)| *e == entry) {
+                Some((parent_id, _)) => vec![*parent_id],
+                None => {
+                    if entry.is_some() || !parents.is_empty() {
+                        found.push(commit);
+                    }
+                    parents.iter().map(|(parent_id, _)| *parent_id).collect()
+                }
+            };
+            for parent_id in follow {
+                pending.insert((commits[&parent_id].committer.timestamp, parent_id));
+            }
+        }
+
+        let has_more = found.len() > page * per_page;
+        let mut infos = Vec::new();
+        for commit in found.into_iter().skip((page - 1) * per_page).take(per_page) {
+            infos.push(self.convert_commit_to_info(commit)?);
+        }
+        Ok(CommitHistory {
+            path: path.to_str().unwrap().to_owned(),
+            page,
+            per_page,
+            has_more,
+            commits: infos,
+        })
+    }
+
+    /// The commit which added each line of the file `path`, at `commit` or the latest commit.
+    ///
+    /// The lines are followed back through the root commits, and a change merged from a merge
+    /// request is blamed on the root commit `update_parent_tree` made for it, which carries the
+    /// author and message of the merge request commit.
+    async fn get_blame(
+        &self,
+        path: PathBuf,
+        commit: Option<String>,
+    ) -> Result<BlameInfo, GitError> {
+        let names = self.path_names(&path)?;
+        let start = self.get_start_commit(commit).await?;
+        let mut trees = HashMap::new();
+        let oid = match self
+            .find_path_in_tree(start.tree_id, &names, &mut trees)
+            .await?
+        {
+            Some((oid, mode)) if is_file(mode) => oid,
+            _ => {
+                return Err(GitError::CustomError(
+                    "can't find the file under the commit".to_string(),
+                ))
+            }
+        };
+        let mut blobs = HashMap::new();
+        let content = self.load_blob_lines(oid, &mut blobs).await?.clone();
+
+        let start_id = start.id;
+        let mut commits = HashMap::from([(start_id, start)]);
+        let mut blamed: Vec<Option<SHA1>> = vec![None; content.len()];
+        // the commits which may have added lines, with the blob of the file and its lines, as
+        // pairs of the line of the blamed file and the line of the blob
+        let mut suspects: HashMap<SHA1, (SHA1, Vec<(usize, usize)>)> = HashMap::new();
+        suspects.insert(
+            start_id,
+            (oid, (0..content.len()).map(|i| (i, i)).collect()),
+        );
+        let mut walked = 0;
+        while let Some(id) = suspects
+            .keys()
+            .max_by_key(|id| (commits[*id].committer.timestamp, **id))
+            .copied()
+        {
+            let (blob, mut lines) = suspects.remove(&id).unwrap();
+            let commit = commits[&id].clone();
+            walked += 1;
+            let mut passed: Vec<(SHA1, SHA1, Vec<(usize, usize)>)> = Vec::new();
+            if walked < MAX_WALKED_COMMITS {
+                self.load_commits(&commit.parent_commit_ids, &mut commits)
+                    .await?;
+                let mut parents = Vec::new();
+                for parent_id in &commit.parent_commit_ids {
+                    let Some(parent) = commits.get(parent_id) else {
+                        continue;
+                    };
+                    let tree_id = parent.tree_id;
+                    match self.find_path_in_tree(tree_id, &names, &mut trees).await? {
+                        Some((parent_blob, mode)) if is_file(mode) => {
+                            parents.push((*parent_id, parent_blob))
+                        }
+                        _ => {}
+                    }
+                }
+                if let Some((parent_id, _)) = parents.iter().find(|(_, b)| *b == blob) {
+                    // the file is unchanged from this parent, all its lines come from there
+                    passed.push((*parent_id, blob, std::mem::take(&mut lines)));
+                } else {
+                    for (parent_id, parent_blob) in parents {
+                        if lines.is_empty() {
+                            break;
+                        }
+                        let old = self.load_blob_lines(parent_blob, &mut blobs).await?.clone();
+                        let matches = history::match_lines(&old, &blobs[&blob]);
+                        let (kept, rest): (Vec<_>, Vec<_>) =
+                            lines.into_iter().partition(|(_, i)| matches[*i].is_some());
+                        let kept = kept
+                            .into_iter()
+                            .map(|(line, i)| (line, matches[i].unwrap()))
+                            .collect();
+                        passed.push((parent_id, parent_blob, kept));
+                        lines = rest;
+                    }
+                }
+            }
+            for (line, _) in lines {
+                blamed[line] = Some(id);
+            }
+            for (parent_id, parent_blob, lines) in passed {
+                if !lines.is_empty() {
+                    let suspect = suspects
+                        .entry(parent_id)
+                        .or_insert_with(|| (parent_blob, Vec::new()));
+                    suspect.1.extend(lines);
+                }
+            }
+        }
+
+        let mut blamed_commits: Vec<&Commit> = blamed
+            .iter()
+            .flatten()
+            .collect::<HashSet<_>>()
+            .into_iter()
+            .map(|id| &commits[id])
+            .collect();
+        blamed_commits.sort_by_key(|c| std::cmp::Reverse((c.committer.timestamp, c.id)));
+        let lines = content
+            .into_iter()
+            .zip(blamed)
+            .enumerate()
+            .map(|(i, (content, id))| {
+                let commit = &commits[&id.unwrap()];
+                BlameLine {
+                    line: i + 1,
+                    content,
+                    commit: commit.id.to_plain_str(),
+                    author: commit.author.name.clone(),
+                }
+            })
+            .collect();
+        let mut infos = Vec::new();
+        for commit in blamed_commits {
+            infos.push(self.convert_commit_to_info(commit.clone())?);
+        }
+        Ok(BlameInfo {
+            path: path.to_str().unwrap().to_owned(),
+            commit: start_id.to_plain_str(),
+            oid: oid.to_plain_str(),
+            lines,
+            commits: infos,
+        })
+    }
+
+    /// The commits which changed `path` since `from`, up to `to` or the latest commit, the
+    /// latest first, and whether the walk stopped at [`MAX_WALKED_COMMITS`].
+    ///
+    /// Like `git log from..to <path>`, the commits reachable from `from` are left out, and all
+    /// the commits since the first one if `from` is `None`.
+    async fn get_commits_between(
+        &self,
+        path: PathBuf,
+        from: Option<String>,
+        to: Option<String>,
+    ) -> Result<(Vec<Commit>, bool), GitError> {
+        let names = self.path_names(&path)?;
+        let to = self.get_start_commit(to).await?;
+        let mut commits = HashMap::new();
+        let mut trees = HashMap::new();
+        let mut pending = BTreeSet::from([(to.committer.timestamp, to.id)]);
+        commits.insert(to.id, to);
+        // the commits reachable from `from`
+        let mut excluded = HashSet::new();
+        if let Some(from) = from {
+            let from = self.get_start_commit(Some(from)).await?;
+            excluded.insert(from.id);
+            pending.insert((from.committer.timestamp, from.id));
+            commits.insert(from.id, from);
+        }
+        let mut seen = HashSet::new();
+        let mut found = Vec::new();
+        let mut truncated = false;
+        // done once only the commits reachable from `from` are left
+        while pending.iter().any(|(_, id)| !excluded.contains(id)) {
+            if seen.len() >= MAX_WALKED_COMMITS {
+                truncated = true;
+                break;
+            }
+            let (_, id) = pending.pop_last().unwrap();
+            // walked again once it is known to be reachable from `from`
+            if !seen.insert((id, excluded.contains(&id))) {
+                continue;
+            }
+            let commit = commits[&id].clone();
+            self.load_commits(&commit.parent_commit_ids, &mut commits)
+                .await?;
+            let parent_ids: Vec<SHA1> = commit
+                .parent_commit_ids
+                .iter()
+                .filter(|id| commits.contains_key(*id))
+                .copied()
+                .collect();
+            if excluded.contains(&id) {
+                excluded.extend(parent_ids.iter().copied());
+            } else {
+                let entry = self
+                    .find_path_in_tree(commit.tree_id, &names, &mut trees)
+                    .await?;
+                let mut changed = entry.is_some() || !parent_ids.is_empty();
+                for parent_id in &parent_ids {
+                    let tree_id = commits[parent_id].tree_id;
+                    if self.find_path_in_tree(tree_id, &names, &mut trees).await? == entry {
+                        changed = false;
+                        break;
+                    }
+                }
+                if changed {
+                    found.push(commit);
+                }
+            }
+            for parent_id in parent_ids {
+                pending.insert((commits[&parent_id].committer.timestamp, parent_id));
+            }
+        }
+        // a commit walked before it was known to be reachable from `from`
+        found.retain(|commit| !excluded.contains(&commit.id));
+        Ok((found, truncated))
+    }
+
+    /// The names of the directories and file of `path`, from the root.
+    fn path_names(&self, path: &Path) -> Result<Vec<String>, GitError> {
+        Ok(self
+            .strip_relative(path)?
+            .components()
+            .filter_map(|c| match c {
+                Component::Normal(name) => Some(name.to_str().unwrap().to_owned()),
+                _ => None,
+            })
+            .collect())
+    }
+
+    /// The commit `commit`, or the latest commit if `None`.
+    async fn get_start_commit(&self, commit: Option<String>) -> Result<Commit, GitError> {
+        match commit {
+            Some(commit) => self
+                .get_commits_by_hashes(vec![commit])
+                .await?
+                .pop()
+                .ok_or(GitError::CustomError("can't find the commit".to_string())),
+            None => self.get_root_commit().await,
+        }
+    }
+
+    /// Load the commits of `ids` which are not in `commits` yet.
+    async fn load_commits(
+        &self,
+        ids: &[SHA1],
+        commits: &mut HashMap<SHA1, Commit>,
+    ) -> Result<(), GitError> {
+        let missing: Vec<String> = ids
+            .iter()
+            .filter(|id| !commits.contains_key(*id))
+            .map(|id| id.to_plain_str())
+            .collect();
+        if !missing.is_empty() {
+            for commit in self.get_commits_by_hashes(missing).await? {
+                commits.insert(commit.id, commit);
+            }
+        }
+        Ok(())
+    }
+
+    /// The id and mode of the entry at `names` under the tree `root`, `None` if there is none.
+    /// The trees read are kept in `trees`.
+    async fn find_path_in_tree(
+        &self,
+        root: SHA1,
+        names: &[String],
+        trees: &mut HashMap<SHA1, Tree>,
+    ) -> Result<Option<(SHA1, TreeItemMode)>, GitError> {
+        let (mut id, mut mode) = (root, TreeItemMode::Tree);
+        for name in names {
+            if mode != TreeItemMode::Tree {
+                return Ok(None);
+            }
+            if let Entry::Vacant(entry) = trees.entry(id) {
+                match self
+                    .get_trees_by_hashes(vec![id.to_plain_str()])
+                    .await?
+                    .pop()
+                {
+                    Some(tree) => entry.insert(tree),
+                    None => return Ok(None),
+                };
+            }
+            match trees[&id].tree_items.iter().find(|x| &x.name == name) {
+                Some(item) => (id, mode) = (item.id, item.mode),
+                None => return Ok(None),
+            }
+        }
+        Ok(Some((id, mode)))
+    }
+
+    /// The lines of the blob `oid`, kept in `blobs`.
+    async fn load_blob_lines<'a>(
+        &self,
+        oid: SHA1,
+        blobs: &'a mut HashMap<SHA1, Vec<String>>,
+    ) -> Result<&'a Vec<String>, GitError> {
+        if let Entry::Vacant(entry) = blobs.entry(oid) {
+            let data = self
+                .get_raw_blob_by_hash(&oid.to_plain_str())
+                .await
+                .map_err(|e| GitError::CustomError(e.to_string()))?
+                .and_then(|model| model.data)
+                .unwrap_or_default();
+            let lines = String::from_utf8_lossy(&data)
+                .lines()
+                .map(str::to_owned)
+                .collect();
+            entry.insert(lines);
+        }
+        Ok(&blobs[&oid])
+    }
+
     fn convert_commit_to_info(&self, commit: Commit) -> Result<LatestCommitInfo, GitError> {
         let message = commit.format_message();
         let committer = UserInfo {
@@ -395,3 +951,112 @@ pub trait ApiHandler: Send + Sync {
         Ok(false)
     }
 }
+
+/// The entries of `tree` at `path`, with the ones of the subdirectories found in `trees`
+/// down to `depth` levels, and the sizes of the files found in `sizes`.
+fn tree_nodes(
+    tree: &Tree,
+    path: &Path,
+    trees: &HashMap<String, Tree>,
+    sizes: &HashMap<String, u64>,
+    depth: usize,
+) -> Vec<TreeNode> {
+    tree.tree_items
+        .iter()
+        .map(|item| {
+            let mut info: TreeBriefItem = item.clone().into();
+            let item_path = path.join(&item.name);
+            item_path.to_str().unwrap().clone_into(&mut info.path);
+            info.size = sizes.get(&info.oid).copied();
+            let children = match trees.get(&item.id.to_plain_str()) {
+                Some(child) if depth > 1 && item.mode == TreeItemMode::Tree => {
+                    Some(tree_nodes(child, &item_path, trees, sizes, depth - 1))
+                }
+                _ => None,
+            };
+            TreeNode {
+                item: info,
+                children,
+            }
+        })
+        .collect()
+}
+
+/// The ids of the blobs in `trees`.
+fn file_ids<'a>(trees: impl IntoIterator<Item = &'a Tree>) -> Vec<String> {
+    let ids: HashSet<String> = trees
+        .into_iter()
+        .flat_map(|tree| tree.tree_items.iter())
+        .filter(|x| is_file(x.mode))
+        .map(|x| x.id.to_plain_str())
+        .collect();
+    ids.into_iter().collect()
+}
+
+/// Whether an entry of `mode` has the content of a file.
+fn is_file(mode: TreeItemMode) -> bool {
+    matches!(
+        mode,
+        TreeItemMode::Blob | TreeItemMode::BlobExecutable | TreeItemMode::Link
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashMap;
+    use std::path::Path;
+
+    use mercury::hash::SHA1;
+    use mercury::internal::object::tree::{Tree, TreeItem, TreeItemMode};
+
+    use super::tree_nodes;
+
+    fn tree(items: Vec<(TreeItemMode, SHA1, &str)>) -> Tree {
+        Tree::from_tree_items(
+            items
+                .into_iter()
+                .map(|(mode, id, name)| TreeItem {
+                    mode,
+                    id,
+                    name: name.to_owned(),
+                })
+                .collect(),
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_tree_nodes_depth() {
+        let blob = SHA1::new(b"blob");
+        let leaf = tree(vec![(TreeItemMode::Blob, blob, "lib.rs")]);
+        let src = tree(vec![(TreeItemMode::Tree, leaf.id, "bin")]);
+        let root = tree(vec![
+            (TreeItemMode::Blob, blob, "README.md"),
+            (TreeItemMode::Tree, src.id, "src"),
+        ]);
+        let trees: HashMap<String, Tree> = [&src, &leaf]
+            .into_iter()
+            .map(|x| (x.id.to_plain_str(), x.clone()))
+            .collect();
+        let sizes = HashMap::from([(blob.to_plain_str(), 4)]);
+
+        let nodes = tree_nodes(&root, Path::new("/project"), &trees, &sizes, 2);
+        assert_eq!(nodes.len(), 2);
+        assert!(nodes[0].children.is_none());
+        assert_eq!(nodes[0].item.oid, blob.to_plain_str());
+        assert_eq!(nodes[0].item.size, Some(4));
+        assert_eq!(nodes[1].item.size, None);
+        let src_nodes = nodes[1].children.as_ref().unwrap();
+        assert_eq!(src_nodes[0].item.path, "/project/src/bin");
+        assert_eq!(src_nodes[0].item.oid, leaf.id.to_plain_str());
+        // past the depth, even when the tree is known
+        assert!(src_nodes[0].children.is_none());
+
+        let nodes = tree_nodes(&root, Path::new("/project"), &trees, &sizes, 3);
+        let bin = &nodes[1].children.as_ref().unwrap()[0];
+        assert_eq!(
+            bin.children.as_ref().unwrap()[0].item.path,
+            "/project/src/bin/lib.rs"
+        );
+    }
+}
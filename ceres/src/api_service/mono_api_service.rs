@@ -1,4 +1,4 @@
-use std::collections::{HashMap, VecDeque};
+use std::collections::HashMap;
 use std::path::{Path, PathBuf};
 use std::str::FromStr;
 
@@ -14,13 +14,18 @@ use mercury::hash::SHA1;
 use mercury::internal::object::blob::Blob;
 use mercury::internal::object::commit::Commit;
 use mercury::internal::object::tree::{Tree, TreeItem, TreeItemMode};
+use mercury::internal::tree_diff::{diff_trees, ChangeKind, DiffOptions};
 use venus::import_repo::repo::Repo;
 use venus::monorepo::converter;
 
 use crate::api_service::ApiHandler;
+use crate::model::changelog::{Changelog, ChangelogEntry, ChangelogSection};
 use crate::model::create_file::CreateFileInfo;
 use crate::model::mr::{MRDetail, MrInfoItem};
 use crate::model::publish_path::PublishPathInfo;
+use crate::pack::{handler::HandlerStore, monorepo::MonoRepo};
+use crate::policy::{self, conventional, conventional::ConventionalCommit};
+use crate::search::SearchIndex;
 
 #[derive(Clone)]
 pub struct MonoApiService {
@@ -111,6 +116,7 @@ impl ApiHandler for MonoApiService {
         batch_save_model(storage.get_connection(), save_trees)
             .await
             .unwrap();
+        SearchIndex::new(self.context.clone()).spawn_update();
         Ok(())
     }
 
@@ -133,8 +139,19 @@ impl ApiHandler for MonoApiService {
         Ok(path.to_path_buf())
     }
 
-    async fn get_root_commit(&self) -> Commit {
-        unreachable!()
+    async fn get_root_commit(&self) -> Result<Commit, GitError> {
+        let storage = self.context.services.mega_storage.clone();
+        let refs = storage
+            .get_ref("/")
+            .await
+            .map_err(|e| GitError::CustomError(e.to_string()))?
+            .ok_or(GitError::ObjectNotFound("the ref of /".to_string()))?;
+        storage
+            .get_commit_by_hash(&refs.ref_commit_hash)
+            .await
+            .map_err(|e| GitError::CustomError(e.to_string()))?
+            .map(Commit::from)
+            .ok_or(GitError::ObjectNotFound(refs.ref_commit_hash))
     }
 
     async fn get_root_tree(&self) -> Tree {
@@ -201,6 +218,44 @@ impl ApiHandler for MonoApiService {
         Ok(commits.into_iter().map(|x| x.into()).collect())
     }
 
+    async fn get_trees_by_hashes(&self, hashes: Vec<String>) -> Result<Vec<Tree>, GitError> {
+        let storage = self.context.services.mega_storage.clone();
+        let trees = storage
+            .get_trees_by_hashes(hashes)
+            .await
+            .map_err(|e| GitError::CustomError(e.to_string()))?;
+        Ok(trees.into_iter().map(|x| x.into()).collect())
+    }
+
+    async fn get_raw_blobs_by_hashes(
+        &self,
+        hashes: Vec<String>,
+    ) -> Result<Vec<raw_blob::Model>, GitError> {
+        self.context
+            .services
+            .mega_storage
+            .get_raw_blobs_by_hashes(hashes)
+            .await
+            .map_err(|e| GitError::CustomError(e.to_string()))
+    }
+
+    async fn get_raw_blob_sizes(
+        &self,
+        hashes: Vec<String>,
+    ) -> Result<HashMap<String, u64>, GitError> {
+        let sizes = self
+            .context
+            .services
+            .mega_storage
+            .get_raw_blob_sizes(hashes)
+            .await
+            .map_err(|e| GitError::CustomError(e.to_string()))?;
+        Ok(sizes
+            .into_iter()
+            .map(|(id, size)| (id, size as u64))
+            .collect())
+    }
+
     async fn traverse_commit_history(&self, _: &Path, _: Commit, _: TreeItem) -> Commit {
         unreachable!()
     }
@@ -255,39 +310,34 @@ impl MonoApiService {
                 .unwrap()
                 .tree;
 
-            let mut tree_comparation = TreeComparation {
-                left_tree: VecDeque::new(),
-                result_file: vec![],
+            let to_tree_id =
+                SHA1::from_str(&to_tree_id).map_err(|e| MegaError::with_message(&e))?;
+            let from_tree_id =
+                SHA1::from_str(&from_tree_id).map_err(|e| MegaError::with_message(&e))?;
+            let monorepo = MonoRepo {
+                context: self.context.clone(),
+                path: PathBuf::from(&model.path),
+                from_hash: None,
+                to_hash: None,
             };
-            tree_comparation.left_tree.push_back((
-                SHA1::from_str(&to_tree_id).unwrap(),
-                Some(SHA1::from_str(&from_tree_id).unwrap()),
-                PathBuf::from(model.path),
-            ));
-            while let Some((new_tree, base_tree, mut current_path)) =
-                tree_comparation.left_tree.pop_front()
-            {
-                let new_tree: Tree = storage
-                    .get_tree_by_hash(&new_tree.to_plain_str())
-                    .await
-                    .unwrap()
-                    .unwrap()
-                    .into();
-
-                let base_tree = if let Some(base_tree) = base_tree {
-                    let base_tree: Tree = storage
-                        .get_tree_by_hash(&base_tree.to_plain_str())
-                        .await
-                        .unwrap()
-                        .unwrap()
-                        .into();
-                    Some(base_tree)
-                } else {
-                    None
-                };
-                tree_comparation.compare(new_tree, base_tree, &mut current_path);
+            let changes = diff_trees(
+                &HandlerStore(&monorepo),
+                Some(from_tree_id),
+                Some(to_tree_id),
+                &DiffOptions::default(),
+            )
+            .await
+            .map_err(|e| MegaError::with_message(&e.to_string()))?;
+            let base = PathBuf::from(model.path);
+            let mut files = vec![];
+            for change in changes {
+                // the old path of a renamed file is gone from the tree
+                if let (ChangeKind::Renamed, Some(old)) = (change.kind, &change.old) {
+                    files.push(base.join(&old.path));
+                }
+                files.push(base.join(change.path()));
             }
-            return Ok(tree_comparation.result_file);
+            return Ok(files);
         }
         Err(MegaError::with_message("Can not find related MR by id"))
     }
@@ -328,6 +378,7 @@ impl MonoApiService {
                     storage.remove_refs(&mr.path).await.unwrap();
                     // TODO: self.clean_dangling_commits().await;
                 }
+                SearchIndex::new(self.context.clone()).spawn_update();
             } else {
                 return Err(MegaError::with_message("ref hash conflict"));
             }
@@ -337,6 +388,98 @@ impl MonoApiService {
         Ok(())
     }
 
+    /// The changelog of `path` since the commit `from`, up to `to` or the latest commit.
+    ///
+    /// The commits are described by their stored Conventional Commit metadata, and the ones
+    /// without it, like the commits made before it was stored, by parsing their messages.
+    pub async fn changelog(
+        &self,
+        path: PathBuf,
+        from: Option<String>,
+        to: Option<String>,
+    ) -> Result<Changelog, GitError> {
+        let to = match to {
+            Some(to) => to,
+            None => self.get_root_commit().await?.id.to_plain_str(),
+        };
+        let (commits, truncated) = self
+            .get_commits_between(path.clone(), from.clone(), Some(to.clone()))
+            .await?;
+        let commit_ids = commits.iter().map(|c| c.id.to_plain_str()).collect();
+        let mut stored: HashMap<String, ConventionalCommit> = self
+            .context
+            .services
+            .conventional_commit_storage
+            .get_commits(commit_ids)
+            .await
+            .map_err(|e| GitError::CustomError(e.to_string()))?
+            .into_iter()
+            .map(|model| (model.commit_id.clone(), policy::from_model(model)))
+            .collect();
+
+        let mut sections: Vec<ChangelogSection> = Vec::new();
+        let mut breaking_changes = Vec::new();
+        let mut other_commits = Vec::new();
+        for commit in commits {
+            // the messages of merges are made by git
+            if commit.parent_commit_ids.len() > 1 {
+                continue;
+            }
+            let id = commit.id.to_plain_str();
+            let parsed = stored
+                .remove(&id)
+                .or_else(|| conventional::parse(conventional::message_text(&commit.message)).ok());
+            let Some(parsed) = parsed else {
+                other_commits.push(ChangelogEntry {
+                    commit: id,
+                    scope: None,
+                    description: commit.format_message(),
+                    breaking: false,
+                    breaking_note: None,
+                    author: commit.author.name,
+                    date: commit.committer.timestamp.to_string(),
+                });
+                continue;
+            };
+            let entry = ChangelogEntry {
+                commit: id,
+                scope: parsed.scope.clone(),
+                description: parsed.description.clone(),
+                breaking: parsed.breaking,
+                breaking_note: parsed.breaking_note().map(str::to_owned),
+                author: commit.author.name,
+                date: commit.committer.timestamp.to_string(),
+            };
+            if entry.breaking {
+                breaking_changes.push(entry.clone());
+            }
+            match sections
+                .iter_mut()
+                .find(|s| s.commit_type == parsed.commit_type)
+            {
+                Some(section) => section.entries.push(entry),
+                None => sections.push(ChangelogSection {
+                    commit_type: parsed.commit_type,
+                    entries: vec![entry],
+                }),
+            }
+        }
+        let types = &self.context.config.commit_policy.types;
+        sections.sort_by_key(|s| {
+            let position = types.iter().position(|t| *t == s.commit_type);
+            (position.unwrap_or(types.len()), s.commit_type.clone())
+        });
+        Ok(Changelog {
+            path: path.to_str().unwrap().to_owned(),
+            from: from.unwrap_or_default(),
+            to,
+            sections,
+            breaking_changes,
+            other_commits,
+            truncated,
+        })
+    }
+
     async fn update_parent_tree(
         &self,
         mut path: PathBuf,
@@ -377,6 +520,21 @@ impl MonoApiService {
                     p_ref.ref_commit_hash = p_commit.id.to_plain_str();
                     p_ref.ref_tree_hash = target_hash.to_plain_str();
                     storage.update_ref(p_ref).await.unwrap();
+                    // the root commit carries the message, keep its conventional metadata
+                    if let Ok(parsed) =
+                        conventional::parse(conventional::message_text(&commit.message))
+                    {
+                        let model = policy::to_model(&p_commit_id, parsed);
+                        if let Err(err) = self
+                            .context
+                            .services
+                            .conventional_commit_storage
+                            .save_commits(vec![model])
+                            .await
+                        {
+                            tracing::warn!("failed to save the conventional commit: {}", err);
+                        }
+                    }
                     storage.save_mega_commits(vec![p_commit]).await.unwrap();
                 } else {
                     storage.remove_ref(p_ref).await.unwrap();
@@ -398,91 +556,16 @@ impl MonoApiService {
     }
 }
 
-pub struct TreeComparation {
-    pub left_tree: VecDeque<(SHA1, Option<SHA1>, PathBuf)>,
-    pub result_file: Vec<PathBuf>,
-}
-
-impl TreeComparation {
-    // fn compare(&mut self, new: Tree, base: Option<Tree>) {
-    //     let new_set: HashSet<_> = new.tree_items.into_iter().collect();
-    //     let base_set = if let Some(tree) = base {
-    //         tree.tree_items.into_iter().collect()
-    //     } else {
-    //         HashSet::new()
-    //     };
-    //     let diff: Vec<_> = new_set.symmetric_difference(&base_set).cloned().collect();
-    //     for item in diff {
-    //         if item.mode == TreeItemMode::Tree {
-    //             let t_id = item.id.to_plain_str();
-    //             if !self.left_tree.contains(&t_id) {
-    //                 self.left_tree.push_back(t_id);
-    //             }
-    //         } else {
-    //             self.result_file.push(item.name)
-    //         }
-    //     }
-    // }
-
-    pub fn compare(&mut self, new: Tree, base: Option<Tree>, current_path: &mut PathBuf) {
-        let mut map_name_to_item = HashMap::new();
-        let mut map_id_to_item = HashMap::new();
-
-        if let Some(tree) = base {
-            for item in tree.tree_items {
-                map_name_to_item.insert(item.name.clone(), item.clone());
-                map_id_to_item.insert(item.id, item.clone());
-            }
-        }
-
-        for item in new.tree_items {
-            match (
-                map_name_to_item.get(&item.name),
-                map_id_to_item.get(&item.id),
-            ) {
-                (Some(base_item), _) if base_item.id != item.id => {
-                    self.process_diff(item, Some(base_item.id), current_path);
-                }
-                (_, Some(base_item)) if base_item.name != item.name => {
-                    self.process_diff(item, None, current_path);
-                }
-                (None, None) => {
-                    self.process_diff(item, None, current_path);
-                }
-                _ => {}
-            }
-        }
-    }
-
-    fn process_diff(&mut self, item: TreeItem, base_id: Option<SHA1>, current_path: &mut PathBuf) {
-        if item.mode == TreeItemMode::Tree {
-            if !self
-                .left_tree
-                .contains(&(item.id, base_id, current_path.to_path_buf()))
-            {
-                current_path.push(item.name);
-                self.left_tree
-                    .push_back((item.id, base_id, current_path.to_path_buf()));
-                current_path.pop();
-            }
-        } else {
-            current_path.push(item.name);
-            self.result_file.push(current_path.to_path_buf());
-            current_path.pop();
-        }
-    }
-}
-
 #[cfg(test)]
 mod test {
-    use std::{collections::VecDeque, path::PathBuf};
+    use std::path::PathBuf;
 
-    use mercury::{
-        hash::SHA1,
-        internal::object::tree::{Tree, TreeItem, TreeItemMode},
-    };
+    use common::config::Config;
+    use jupiter::context::Context;
+    use mercury::errors::GitError;
 
-    use crate::api_service::mono_api_service::TreeComparation;
+    use super::MonoApiService;
+    use crate::api_service::ApiHandler;
 
     #[test]
     pub fn test() {
@@ -495,65 +578,52 @@ mod test {
         }
     }
 
-    #[test]
-    fn test_compare_tree() {
-        let base = Tree::from_tree_items(vec![
-            TreeItem {
-                mode: TreeItemMode::Tree,
-                id: SHA1::new(&"src".as_bytes().to_vec()),
-                name: "src".to_string(),
-            },
-            TreeItem {
-                mode: TreeItemMode::Tree,
-                id: SHA1::new(&"mega".as_bytes().to_vec()),
-                name: "mega".to_string(),
-            },
-            TreeItem {
-                mode: TreeItemMode::Blob,
-                id: SHA1::new(&"delete.txt".as_bytes().to_vec()),
-                name: "delete.txt".to_string(),
-            },
-            TreeItem {
-                mode: TreeItemMode::Blob,
-                id: SHA1::new(&"README.md".as_bytes().to_vec()),
-                name: "README.md".to_string(),
-            },
-        ])
-        .unwrap();
+    #[tokio::test]
+    async fn test_no_root_ref() {
+        let dir = std::env::temp_dir().join(format!("mega-no-ref-{:016x}", rand::random::<u64>()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let mut config = Config::default();
+        config.database.db_path = dir.join("mega.db").to_str().unwrap().to_owned();
+        let service = MonoApiService {
+            context: Context::new(config).await,
+        };
+        let res = service
+            .get_tree_listing(PathBuf::from("/"), None, None, 1)
+            .await;
+        assert!(matches!(res, Err(GitError::ObjectNotFound(_))));
+        let _ = std::fs::remove_dir_all(&dir);
+    }
 
-        let new = Tree::from_tree_items(vec![
-            TreeItem {
-                mode: TreeItemMode::Tree,
-                id: SHA1::new(&"src".as_bytes().to_vec()),
-                name: "src_new".to_string(),
-            },
-            TreeItem {
-                mode: TreeItemMode::Tree,
-                id: SHA1::new(&"mega222".as_bytes().to_vec()),
-                name: "mega".to_string(),
-            },
-            TreeItem {
-                mode: TreeItemMode::Blob,
-                id: SHA1::new(&"Cargo.toml".as_bytes().to_vec()),
-                name: "Cargo.toml".to_string(),
-            },
-            TreeItem {
-                mode: TreeItemMode::Blob,
-                id: SHA1::new(&"README.md".as_bytes().to_vec()),
-                name: "README.md".to_string(),
-            },
-        ])
-        .unwrap();
-
-        let mut tree_comparation = TreeComparation {
-            left_tree: VecDeque::new(),
-            result_file: vec![],
+    #[tokio::test]
+    async fn test_listing_blob_sizes() {
+        let dir = std::env::temp_dir().join(format!("mega-sizes-{:016x}", rand::random::<u64>()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let mut config = Config::default();
+        config.database.db_path = dir.join("mega.db").to_str().unwrap().to_owned();
+        let service = MonoApiService {
+            context: Context::new(config).await,
         };
-        // let mut current_path = Path::new("/");
-        tree_comparation.compare(new, Some(base), &mut PathBuf::from("/"));
-        println!(
-            "files: {:?} left Tree: {:?}",
-            tree_comparation.result_file, tree_comparation.left_tree
-        );
+        service.context.services.mega_storage.init_monorepo().await;
+        let listing = service
+            .get_tree_listing(PathBuf::from("/"), None, None, 2)
+            .await
+            .unwrap()
+            .unwrap();
+        let files: Vec<_> = listing
+            .items
+            .iter()
+            .flat_map(|node| node.children.iter().flatten())
+            .filter(|node| node.item.content_type == "file")
+            .collect();
+        assert!(!files.is_empty());
+        for file in files {
+            let blob = service
+                .get_raw_blob_by_hash(&file.item.oid)
+                .await
+                .unwrap()
+                .unwrap();
+            assert_eq!(file.item.size, Some(blob.data.unwrap().len() as u64));
+        }
+        let _ = std::fs::remove_dir_all(&dir);
     }
 }
@@ -0,0 +1,236 @@
+//! Executables run as hooks, configured by path prefix in the `hooks` config.
+//!
+//! Like the git hooks, `pre-receive` and `post-receive` read `<old-id> <new-id> <ref-name>`
+//! lines from stdin and `update` gets them as arguments, and a non-zero exit status rejects the
+//! refs with the last line of the output as the reason. As a hook has no access to the
+//! storage, the objects of the push are written to a directory, one file named by id each,
+//! for the `pre-receive` and `update` hooks. The `post-receive` hooks run once the refs are
+//! updated, so they fetch the objects they need from the repo.
+
+use std::{
+    path::{Path, PathBuf},
+    process::Stdio,
+    time::Duration,
+};
+
+use async_trait::async_trait;
+use tokio::{io::AsyncWriteExt, process::Command};
+
+use common::{
+    config::{HookCommandConfig, HookStage},
+    errors::MegaError,
+};
+use mercury::internal::object::ObjectTrait;
+use venus::import_repo::import_refs::RefCommand;
+
+use crate::hooks::{Push, ReceiveHook};
+
+pub struct ExternalHook {
+    config: HookCommandConfig,
+    name: String,
+    timeout: Duration,
+    /// where the objects of the push are, if written for the stage of the hook
+    objects_dir: Option<PathBuf>,
+}
+
+impl ExternalHook {
+    /// A hook killed after `timeout` seconds, which finds the objects of the push in
+    /// `objects_dir` if given.
+    pub fn new(config: HookCommandConfig, timeout: u64, objects_dir: Option<&Path>) -> Self {
+        let name = config
+            .command
+            .file_name()
+            .unwrap_or(config.command.as_os_str())
+            .to_string_lossy()
+            .into_owned();
+        ExternalHook {
+            config,
+            name,
+            timeout: Duration::from_secs(timeout),
+            objects_dir: objects_dir.map(Path::to_owned),
+        }
+    }
+
+    /// Run the executable with `args` and `input` on stdin, fails with the reason to reject.
+    async fn run(&self, push: &Push<'_>, args: &[&str], input: String) -> Result<(), String> {
+        let mut command = Command::new(&self.config.command);
+        if let Some(dir) = &self.objects_dir {
+            command.env("MEGA_OBJECTS_DIR", dir);
+        }
+        let mut child = command
+            .args(args)
+            .env("MEGA_REPO_PATH", push.path)
+            .env("MEGA_PUSH_USER", push.username.unwrap_or_default())
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .kill_on_drop(true)
+            .spawn()
+            .map_err(|err| {
+                tracing::error!("can't run the hook {:?}: {}", self.config.command, err);
+                "the hook can't be run".to_owned()
+            })?;
+        let mut stdin = child.stdin.take().unwrap();
+        // a hook may exit without reading its input
+        let write = async move {
+            let _ = stdin.write_all(input.as_bytes()).await;
+        };
+        let output = async {
+            let (_, output) = tokio::join!(write, child.wait_with_output());
+            output
+        };
+        let output = match tokio::time::timeout(self.timeout, output).await {
+            Ok(Ok(output)) => output,
+            Ok(Err(err)) => return Err(err.to_string()),
+            Err(_) => return Err(format!("timed out after {}s", self.timeout.as_secs())),
+        };
+        tracing::debug!(
+            "hook {:?}: {}, {}",
+            self.config.command,
+            output.status,
+            String::from_utf8_lossy(&output.stderr)
+        );
+        if output.status.success() {
+            return Ok(());
+        }
+        let reason = [&output.stderr, &output.stdout]
+            .into_iter()
+            .find_map(|out| {
+                String::from_utf8_lossy(out)
+                    .lines()
+                    .rev()
+                    .find(|line| !line.trim().is_empty())
+                    .map(str::to_owned)
+            })
+            .unwrap_or_else(|| output.status.to_string());
+        Err(reason)
+    }
+}
+
+#[async_trait]
+impl ReceiveHook for ExternalHook {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    async fn pre_receive(&self, push: &Push<'_>) -> Result<(), String> {
+        if self.config.stage != HookStage::PreReceive {
+            return Ok(());
+        }
+        self.run(push, &[], ref_lines(push.commands)).await
+    }
+
+    async fn update(&self, push: &Push<'_>, command: &RefCommand) -> Result<(), String> {
+        if self.config.stage != HookStage::Update {
+            return Ok(());
+        }
+        let args = [
+            command.ref_name.as_str(),
+            command.old_id.as_str(),
+            command.new_id.as_str(),
+        ];
+        self.run(push, &args, String::new()).await
+    }
+
+    async fn post_receive(&self, push: &Push<'_>, updated: &[RefCommand]) {
+        if self.config.stage != HookStage::PostReceive {
+            return;
+        }
+        if let Err(reason) = self.run(push, &[], ref_lines(updated)).await {
+            tracing::warn!("post-receive hook {} failed: {}", self.name, reason);
+        }
+    }
+}
+
+/// The input of `pre-receive` and `post-receive`, a line for each ref.
+fn ref_lines(commands: &[RefCommand]) -> String {
+    commands
+        .iter()
+        .map(|c| format!("{} {} {}\n", c.old_id, c.new_id, c.ref_name))
+        .collect()
+}
+
+/// Write the objects of a push to `dir`, one file named by id each, and list them in the
+/// file `index` as `<type> <id>` lines.
+pub async fn write_objects(dir: &Path, push: &Push<'_>) -> Result<(), MegaError> {
+    tokio::fs::create_dir_all(dir).await?;
+    let mut index = String::new();
+    let objects = push.objects;
+    let commit_ids: Vec<String> = objects.commits.keys().cloned().collect();
+    for chunk in commit_ids.chunks(1000) {
+        for commit in push
+            .pack_handler
+            .get_commits_by_hashes(chunk.to_vec())
+            .await?
+        {
+            let data = commit
+                .to_data()
+                .map_err(|e| MegaError::with_message(&e.to_string()))?;
+            write_object(dir, &mut index, "commit", commit.id.to_plain_str(), &data).await?;
+        }
+    }
+    let tree_ids: Vec<String> = objects.trees.keys().cloned().collect();
+    for chunk in tree_ids.chunks(1000) {
+        for tree in push
+            .pack_handler
+            .get_trees_by_hashes(chunk.to_vec())
+            .await?
+        {
+            let data = tree
+                .to_data()
+                .map_err(|e| MegaError::with_message(&e.to_string()))?;
+            write_object(dir, &mut index, "tree", tree.id.to_plain_str(), &data).await?;
+        }
+    }
+    let blob_ids: Vec<String> = objects.blobs.iter().cloned().collect();
+    for chunk in blob_ids.chunks(1000) {
+        for blob in push
+            .pack_handler
+            .get_blobs_by_hashes(chunk.to_vec())
+            .await?
+        {
+            let data = blob.data.unwrap_or_default();
+            write_object(dir, &mut index, "blob", blob.sha1, &data).await?;
+        }
+    }
+    for (id, data) in &objects.tags {
+        write_object(dir, &mut index, "tag", id.clone(), data).await?;
+    }
+    tokio::fs::write(dir.join("index"), index).await?;
+    Ok(())
+}
+
+async fn write_object(
+    dir: &Path,
+    index: &mut String,
+    kind: &str,
+    id: String,
+    data: &[u8],
+) -> Result<(), MegaError> {
+    tokio::fs::write(dir.join(&id), data).await?;
+    index.push_str(&format!("{} {}\n", kind, id));
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_ref_lines() {
+        let commands = [
+            RefCommand::new("0".repeat(40), "1".repeat(40), "refs/heads/main".to_owned()),
+            RefCommand::new("1".repeat(40), "2".repeat(40), "refs/tags/v1".to_owned()),
+        ];
+        assert_eq!(
+            ref_lines(&commands),
+            format!(
+                "{} {} refs/heads/main\n{} {} refs/tags/v1\n",
+                "0".repeat(40),
+                "1".repeat(40),
+                "1".repeat(40),
+                "2".repeat(40)
+            )
+        );
+    }
+}
@@ -0,0 +1,198 @@
+//! Server side hooks of a push, like the git hooks of the same names.
+//!
+//! Once the pack is unpacked, the `pre-receive` hooks check the whole push and a rejection
+//! fails all its refs. Then the `update` hooks check each ref before it is updated, and the
+//! `post-receive` hooks are told about the refs updated. A hook is a [`ReceiveHook`] given
+//! to [`SmartProtocol::with_hooks`], or an executable of the `hooks` config, see [`external`].
+//!
+//! The objects of a push are stored before the hooks run, as they read them from storage.
+//! A rejected push leaves its objects stored but unreferenced, so the hooks keep content out
+//! of the refs, not out of the storage.
+//!
+//! [`SmartProtocol::with_hooks`]: crate::protocol::SmartProtocol::with_hooks
+
+use std::{
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use async_trait::async_trait;
+
+use common::config::{HookStage, HooksConfig};
+use venus::import_repo::import_refs::RefCommand;
+
+use crate::pack::{connectivity::ReceivedObjects, handler::PackHandler};
+
+pub mod external;
+
+use external::ExternalHook;
+
+/// A push as the hooks see it, once its objects are stored.
+pub struct Push<'a> {
+    /// the path of the repo pushed to
+    pub path: &'a Path,
+    /// the user authenticated by the transport, if any
+    pub username: Option<&'a str>,
+    pub commands: &'a [RefCommand],
+    /// the objects of the push, their content can be read from `pack_handler`
+    pub objects: &'a ReceivedObjects,
+    pub pack_handler: &'a Arc<dyn PackHandler>,
+}
+
+/// A hook of the pushes, the stages it doesn't implement accept everything.
+///
+/// A rejection is a reason of one line, reported to the client as `ng <ref> <reason>`.
+#[async_trait]
+pub trait ReceiveHook: Send + Sync {
+    /// The name of the hook in the rejections.
+    fn name(&self) -> &str;
+
+    /// Check the push before any ref is updated, a rejection fails all its refs.
+    async fn pre_receive(&self, _push: &Push<'_>) -> Result<(), String> {
+        Ok(())
+    }
+
+    /// Check the update of a ref, a rejection fails it.
+    async fn update(&self, _push: &Push<'_>, _command: &RefCommand) -> Result<(), String> {
+        Ok(())
+    }
+
+    /// Told about the refs the push updated.
+    async fn post_receive(&self, _push: &Push<'_>, _updated: &[RefCommand]) {}
+}
+
+/// The hooks of a push, the given ones first.
+pub struct Hooks {
+    hooks: Vec<Arc<dyn ReceiveHook>>,
+    /// the objects of the push written for the external `pre-receive` and `update` hooks,
+    /// removed with the hooks
+    objects_dir: Option<PathBuf>,
+    /// the objects couldn't be written, so the external hooks can't check the push
+    objects_missing: bool,
+}
+
+impl Hooks {
+    /// The hooks of a push to `push.path`, `hooks` and the external ones of `config`.
+    /// `cache_dir` is where the objects are written for the external hooks.
+    pub async fn for_push(
+        hooks: &[Arc<dyn ReceiveHook>],
+        config: &HooksConfig,
+        cache_dir: &Path,
+        push: &Push<'_>,
+    ) -> Self {
+        let mut hooks = hooks.to_vec();
+        let commands: Vec<_> = config
+            .commands
+            .iter()
+            .filter(|command| push.path.starts_with(&command.path))
+            .collect();
+        let mut objects_dir = None;
+        let mut objects_missing = false;
+        // the post-receive hooks don't check the push, so the objects are only written
+        // for the other stages
+        if commands
+            .iter()
+            .any(|command| command.stage != HookStage::PostReceive)
+        {
+            let dir = cache_dir.join(format!("hook-{:016x}", rand::random::<u64>()));
+            if let Err(err) = external::write_objects(&dir, push).await {
+                tracing::error!("failed to write the objects for the hooks: {}", err);
+                objects_missing = true;
+            }
+            objects_dir = Some(dir);
+        }
+        for command in commands {
+            let dir = match command.stage {
+                HookStage::PostReceive => None,
+                _ => objects_dir.as_deref(),
+            };
+            hooks.push(Arc::new(ExternalHook::new(
+                command.clone(),
+                config.timeout,
+                dir,
+            )));
+        }
+        Hooks {
+            hooks,
+            objects_dir,
+            objects_missing,
+        }
+    }
+
+    pub async fn pre_receive(&self, push: &Push<'_>) -> Result<(), String> {
+        if self.objects_missing {
+            return Err("the push can't be checked by the hooks".to_owned());
+        }
+        for hook in &self.hooks {
+            hook.pre_receive(push)
+                .await
+                .map_err(|reason| rejection(HookStage::PreReceive, hook.name(), &reason))?;
+        }
+        Ok(())
+    }
+
+    pub async fn update(&self, push: &Push<'_>, command: &RefCommand) -> Result<(), String> {
+        for hook in &self.hooks {
+            hook.update(push, command)
+                .await
+                .map_err(|reason| rejection(HookStage::Update, hook.name(), &reason))?;
+        }
+        Ok(())
+    }
+
+    pub async fn post_receive(&self, push: &Push<'_>, updated: &[RefCommand]) {
+        if updated.is_empty() {
+            return;
+        }
+        for hook in &self.hooks {
+            hook.post_receive(push, updated).await;
+        }
+    }
+}
+
+impl Drop for Hooks {
+    fn drop(&mut self) {
+        let Some(dir) = self.objects_dir.take() else {
+            return;
+        };
+        match tokio::runtime::Handle::try_current() {
+            Ok(runtime) => {
+                runtime.spawn(async move {
+                    if let Err(err) = tokio::fs::remove_dir_all(&dir).await {
+                        tracing::warn!("failed to remove the objects of the hooks: {}", err);
+                    }
+                });
+            }
+            Err(_) => {
+                let _ = std::fs::remove_dir_all(&dir);
+            }
+        }
+    }
+}
+
+/// The reason of a rejection in the report-status, on one line.
+fn rejection(stage: HookStage, name: &str, reason: &str) -> String {
+    let reason = reason.split_whitespace().collect::<Vec<_>>().join(" ");
+    format!("{} hook {} declined: {}", stage_name(stage), name, reason)
+}
+
+fn stage_name(stage: HookStage) -> &'static str {
+    match stage {
+        HookStage::PreReceive => "pre-receive",
+        HookStage::Update => "update",
+        HookStage::PostReceive => "post-receive",
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_rejection() {
+        assert_eq!(
+            rejection(HookStage::Update, "size", "file too large:\n  big.bin\n"),
+            "update hook size declined: file too large: big.bin"
+        );
+    }
+}
@@ -78,6 +78,16 @@ pub async fn git_upload_pack(
         yield Ok::<_, Infallible>(Bytes::copy_from_slice(&protocol_buf));
         // send packdata with sideband64k
         while let Some(chunk) = send_pack_data.next().await {
+            let chunk = match chunk {
+                Ok(chunk) => chunk,
+                Err(err) => {
+                    tracing::error!("failed to send the pack: {}", err);
+                    if let Some(bytes_out) = pack_protocol.build_side_band_error(&err.to_string()) {
+                        yield Ok::<_, Infallible>(bytes_out);
+                    }
+                    return;
+                }
+            };
             let mut reader = chunk.as_slice();
             loop {
                 let mut temp = BytesMut::new();
@@ -166,5 +176,3 @@ pub fn build_res_header(content_type: String) -> Builder {
     }
     resp
 }
-#[cfg(test)]
-mod tests {}
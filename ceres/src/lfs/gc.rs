@@ -0,0 +1,356 @@
+//! Garbage collection and integrity scrub for LFS objects.
+//!
+//! An LFS object is referenced when a pointer file naming its oid is reachable from a ref of
+//! the monorepo, the target of an open merge request, or a ref of any import repo. Objects
+//! which are not referenced and are older than `lfs.gc_grace_period` are removed together
+//! with their split relations, and chunks that are no longer used by any remaining object
+//! are removed from the raw storage.
+//!
+//! Chunks written by an upload which failed before its relations were saved are not
+//! tracked in the database, so they can't be found here.
+
+use std::collections::{HashMap, HashSet};
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use chrono::{Duration, Utc};
+use serde::{Deserialize, Serialize};
+
+use callisto::{db_enums::MergeStatus, lfs_split_relation};
+use common::errors::MegaError;
+use jupiter::{
+    context::Context,
+    raw_storage::RawStorage,
+    storage::{mega_storage::MegaStorage, GitStorageProvider},
+};
+use mercury::internal::object::tree::TreeItemMode;
+use venus::import_repo::repo::Repo;
+
+use crate::lfs::pointer::{LfsPointer, LFS_POINTER_MAX_SIZE};
+use crate::pack::{handler::PackHandler, import_repo::ImportRepo, monorepo::MonoRepo};
+
+const BATCH_SIZE: usize = 1000;
+const BLOB_BATCH_SIZE: usize = 100;
+
+#[derive(Serialize, Deserialize, Debug, Default, Clone)]
+pub struct GcOptions {
+    /// Only report what would be deleted.
+    #[serde(default)]
+    pub dry_run: bool,
+    /// Re-hash the bytes of every kept object and chunk.
+    #[serde(default)]
+    pub scrub: bool,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default, Clone)]
+pub struct GcReport {
+    pub dry_run: bool,
+    pub referenced_objects: usize,
+    pub scanned_objects: usize,
+    pub skipped_in_grace_period: usize,
+    pub deleted_objects: Vec<String>,
+    pub deleted_chunks: Vec<String>,
+    /// Stored objects or chunks whose content doesn't match their sha256 name or size.
+    pub corrupted_objects: Vec<String>,
+    /// Objects or chunks recorded in the database but absent from the raw storage.
+    pub missing_objects: Vec<String>,
+}
+
+pub async fn lfs_gc(
+    context: Context,
+    lfs_storage: Arc<dyn RawStorage>,
+    repo_name: &str,
+    options: GcOptions,
+) -> Result<GcReport, MegaError> {
+    let referenced = collect_referenced_oids(&context).await?;
+    let db = context.services.lfs_storage.clone();
+    let objects = db.get_all_lfs_objects().await?;
+    let relations = db.get_all_lfs_relations().await?;
+
+    let mut report = GcReport {
+        dry_run: options.dry_run,
+        referenced_objects: referenced.len(),
+        scanned_objects: objects.len(),
+        ..Default::default()
+    };
+
+    let grace_period = Duration::seconds(context.config.lfs.gc_grace_period as i64);
+    let now = Utc::now().naive_utc();
+    let mut kept = vec![];
+    let mut expired = vec![];
+    for object in objects {
+        if referenced.contains(&object.oid) {
+            kept.push(object);
+        } else if now - object.created_at < grace_period {
+            report.skipped_in_grace_period += 1;
+            kept.push(object);
+        } else {
+            expired.push(object);
+        }
+    }
+
+    let mut relations_by_oid: HashMap<String, Vec<lfs_split_relation::Model>> = HashMap::new();
+    for relation in relations {
+        relations_by_oid
+            .entry(relation.ori_oid.clone())
+            .or_default()
+            .push(relation);
+    }
+
+    // ids in raw storage which are still in use, a small object may be its own only chunk
+    let mut kept_ids: HashSet<String> = HashSet::new();
+    for object in &kept {
+        if object.splited {
+            if let Some(rels) = relations_by_oid.remove(&object.oid) {
+                kept_ids.extend(rels.iter().map(|r| r.sub_oid.clone()));
+                if options.scrub {
+                    for relation in &rels {
+                        scrub_stored(
+                            &lfs_storage,
+                            repo_name,
+                            &relation.sub_oid,
+                            relation.size,
+                            &mut report,
+                        )
+                        .await?;
+                    }
+                }
+            }
+        } else {
+            kept_ids.insert(object.oid.clone());
+            if options.scrub {
+                scrub_stored(
+                    &lfs_storage,
+                    repo_name,
+                    &object.oid,
+                    object.size,
+                    &mut report,
+                )
+                .await?;
+            }
+        }
+    }
+
+    let mut deleted_ids = HashSet::new();
+    for object in expired {
+        let rels = relations_by_oid.remove(&object.oid).unwrap_or_default();
+        if !object.splited {
+            delete_stored(&lfs_storage, repo_name, &object.oid, &kept_ids, &options).await?;
+        }
+        remove_relations(
+            &context,
+            &lfs_storage,
+            repo_name,
+            rels,
+            &kept_ids,
+            &mut deleted_ids,
+            &mut report,
+            &options,
+        )
+        .await?;
+        if !options.dry_run {
+            db.delete_lfs_object(object.oid.clone()).await?;
+        }
+        report.deleted_objects.push(object.oid);
+    }
+
+    // relations left here belong to objects whose meta row is already gone
+    for (_, rels) in relations_by_oid {
+        remove_relations(
+            &context,
+            &lfs_storage,
+            repo_name,
+            rels,
+            &kept_ids,
+            &mut deleted_ids,
+            &mut report,
+            &options,
+        )
+        .await?;
+    }
+
+    Ok(report)
+}
+
+#[allow(clippy::too_many_arguments)]
+async fn remove_relations(
+    context: &Context,
+    lfs_storage: &Arc<dyn RawStorage>,
+    repo_name: &str,
+    relations: Vec<lfs_split_relation::Model>,
+    kept_ids: &HashSet<String>,
+    deleted_ids: &mut HashSet<String>,
+    report: &mut GcReport,
+    options: &GcOptions,
+) -> Result<(), MegaError> {
+    for relation in relations {
+        if !kept_ids.contains(&relation.sub_oid) && deleted_ids.insert(relation.sub_oid.clone()) {
+            delete_stored(lfs_storage, repo_name, &relation.sub_oid, kept_ids, options).await?;
+            report.deleted_chunks.push(relation.sub_oid.clone());
+        }
+        if !options.dry_run {
+            context
+                .services
+                .lfs_storage
+                .delete_lfs_relation(relation)
+                .await?;
+        }
+    }
+    Ok(())
+}
+
+async fn delete_stored(
+    lfs_storage: &Arc<dyn RawStorage>,
+    repo_name: &str,
+    id: &str,
+    kept_ids: &HashSet<String>,
+    options: &GcOptions,
+) -> Result<(), MegaError> {
+    if options.dry_run || kept_ids.contains(id) || !lfs_storage.exist_object(repo_name, id) {
+        return Ok(());
+    }
+    lfs_storage.delete_object(repo_name, id).await
+}
+
+async fn scrub_stored(
+    lfs_storage: &Arc<dyn RawStorage>,
+    repo_name: &str,
+    id: &str,
+    size: i64,
+    report: &mut GcReport,
+) -> Result<(), MegaError> {
+    if !lfs_storage.exist_object(repo_name, id) {
+        report.missing_objects.push(id.to_owned());
+        return Ok(());
+    }
+    let bytes = lfs_storage.get_object(repo_name, id).await?;
+    if bytes.len() as i64 != size || sha256::digest(bytes.as_ref()) != id {
+        tracing::warn!("lfs object {} is corrupted", id);
+        report.corrupted_objects.push(id.to_owned());
+    }
+    Ok(())
+}
+
+/// Collect the oids of all LFS pointers reachable from the monorepo and import repos.
+async fn collect_referenced_oids(context: &Context) -> Result<HashSet<String>, MegaError> {
+    let mut referenced = HashSet::new();
+    let mut visited = HashSet::new();
+
+    let mega_storage = context.services.mega_storage.clone();
+    let mut tips: Vec<String> = mega_storage
+        .get_all_refs()
+        .await?
+        .into_iter()
+        .map(|r| r.ref_commit_hash)
+        .collect();
+    tips.extend(
+        mega_storage
+            .get_mr_by_status(vec![MergeStatus::Open])
+            .await?
+            .into_iter()
+            .map(|mr| mr.to_hash),
+    );
+    let monorepo = MonoRepo {
+        context: context.clone(),
+        path: PathBuf::from("/"),
+        from_hash: None,
+        to_hash: None,
+    };
+    collect_pointers(
+        &monorepo,
+        &mega_storage,
+        tips,
+        &mut visited,
+        &mut referenced,
+    )
+    .await?;
+
+    let git_db_storage = context.services.git_db_storage.clone();
+    for model in git_db_storage.get_all_git_repos().await? {
+        let repo: Repo = model.into();
+        let tips = git_db_storage
+            .get_ref(&repo)
+            .await?
+            .into_iter()
+            .map(|r| r.ref_hash)
+            .collect();
+        let import_repo = ImportRepo {
+            context: context.clone(),
+            repo,
+        };
+        collect_pointers(
+            &import_repo,
+            &mega_storage,
+            tips,
+            &mut visited,
+            &mut referenced,
+        )
+        .await?;
+    }
+    Ok(referenced)
+}
+
+/// Walk the commits and trees of `handler` from `tips`, blobs are read from `mega_storage`
+/// only if they are small enough to be pointers.
+async fn collect_pointers(
+    handler: &dyn PackHandler,
+    mega_storage: &MegaStorage,
+    tips: Vec<String>,
+    visited: &mut HashSet<String>,
+    referenced: &mut HashSet<String>,
+) -> Result<(), MegaError> {
+    let mut commit_ids: Vec<String> = tips
+        .into_iter()
+        .filter(|id| visited.insert(id.clone()))
+        .collect();
+    let mut tree_ids = vec![];
+    while !commit_ids.is_empty() {
+        let pending = std::mem::take(&mut commit_ids);
+        for chunk in pending.chunks(BATCH_SIZE) {
+            for commit in handler.get_commits_by_hashes(chunk.to_vec()).await? {
+                let tree_id = commit.tree_id.to_plain_str();
+                if visited.insert(tree_id.clone()) {
+                    tree_ids.push(tree_id);
+                }
+                for parent in commit.parent_commit_ids {
+                    let parent = parent.to_plain_str();
+                    if visited.insert(parent.clone()) {
+                        commit_ids.push(parent);
+                    }
+                }
+            }
+        }
+    }
+
+    let mut blob_ids = vec![];
+    while !tree_ids.is_empty() {
+        let pending = std::mem::take(&mut tree_ids);
+        for chunk in pending.chunks(BATCH_SIZE) {
+            for tree in handler.get_trees_by_hashes(chunk.to_vec()).await? {
+                for item in tree.tree_items {
+                    let id = item.id.to_plain_str();
+                    if !visited.insert(id.clone()) {
+                        continue;
+                    }
+                    match item.mode {
+                        TreeItemMode::Tree => tree_ids.push(id),
+                        TreeItemMode::Blob | TreeItemMode::BlobExecutable => blob_ids.push(id),
+                        TreeItemMode::Commit | TreeItemMode::Link => {}
+                    }
+                }
+            }
+        }
+    }
+
+    for chunk in blob_ids.chunks(BLOB_BATCH_SIZE) {
+        for blob in mega_storage
+            .get_small_raw_blobs_by_hashes(chunk.to_vec(), LFS_POINTER_MAX_SIZE)
+            .await?
+        {
+            if let Some(pointer) = blob.data.as_deref().and_then(LfsPointer::parse) {
+                referenced.insert(pointer.oid);
+            }
+        }
+    }
+    Ok(())
+}
@@ -15,7 +15,9 @@ use crate::lfs::lfs_structs::{
     BatchRequest, LockList, LockRequest, ObjectError, UnlockRequest, VerifiableLockList,
     VerifiableLockRequest,
 };
-use crate::lfs::lfs_structs::{Link, Lock, LockListQuery, MetaObject, Representation, RequestVars};
+use crate::lfs::lfs_structs::{
+    Link, Lock, LockListQuery, MetaObject, Representation, RequestVars, User,
+};
 use crate::lfs::LfsConfig;
 
 use super::lfs_structs::ChunkRepresentation;
@@ -51,6 +53,7 @@ pub async fn lfs_retrieve_lock(
 pub async fn lfs_verify_lock(
     config: &LfsConfig,
     req: VerifiableLockRequest,
+    user: Option<String>,
 ) -> Result<VerifiableLockList, MegaError> {
     let mut limit = req.limit.unwrap_or(0);
     if limit == 0 {
@@ -75,7 +78,7 @@ pub async fn lfs_verify_lock(
             lock_list.next_cursor = next_cursor;
 
             for lock in locks.iter() {
-                if Option::is_none(&lock.owner) {
+                if lock_owned_by(lock, user.as_deref()) {
                     lock_list.ours.push(lock.clone());
                 } else {
                     lock_list.theirs.push(lock.clone());
@@ -87,7 +90,11 @@ pub async fn lfs_verify_lock(
     Ok(lock_list)
 }
 
-pub async fn lfs_create_lock(config: &LfsConfig, req: LockRequest) -> Result<Lock, GitLFSError> {
+pub async fn lfs_create_lock(
+    config: &LfsConfig,
+    req: LockRequest,
+    user: Option<String>,
+) -> Result<Lock, GitLFSError> {
     let res = lfs_get_filtered_locks(
         config.context.services.lfs_storage.clone(),
         &req.refs.name,
@@ -120,7 +127,7 @@ pub async fn lfs_create_lock(config: &LfsConfig, req: LockRequest) -> Result<Loc
             random_num
         },
         path: req.path.to_owned(),
-        owner: None,
+        owner: user.map(|name| User { name }),
         locked_at: {
             let locked_at: DateTime<Utc> = Utc::now();
             locked_at.to_rfc3339()
@@ -145,6 +152,7 @@ pub async fn lfs_delete_lock(
     config: &LfsConfig,
     id: &str,
     unlock_request: UnlockRequest,
+    user: Option<String>,
 ) -> Result<Lock, GitLFSError> {
     if id.is_empty() {
         return Err(GitLFSError::GeneralError("Invalid lock id!".to_string()));
@@ -152,7 +160,7 @@ pub async fn lfs_delete_lock(
     let res = delete_lock(
         config.context.services.lfs_storage.clone(),
         &unlock_request.refs.name,
-        None,
+        user,
         id,
         unlock_request.force.unwrap_or(false),
     )
@@ -177,6 +185,39 @@ pub async fn lfs_delete_lock(
     }
 }
 
+/// Find one of the `locks` that covers one of the `paths`.
+///
+/// Used by receive-pack with the [`lfs_foreign_locks`] of a ref, to reject pushes that modify
+/// files locked by another owner.
+pub fn lfs_find_foreign_lock(locks: Vec<Lock>, paths: &[String]) -> Option<Lock> {
+    locks.into_iter().find(|lock| {
+        let lock_path = lock.path.trim_start_matches("./").trim_start_matches('/');
+        paths.iter().any(|path| path == lock_path)
+    })
+}
+
+/// Retrieve the locks on `refspec` which are held by someone other than `user`.
+pub async fn lfs_foreign_locks(
+    storage: Arc<LfsStorage>,
+    refspec: &str,
+    user: Option<&str>,
+) -> Result<Vec<Lock>, GitLFSError> {
+    let locks = match storage.get_lock_by_id(refspec).await {
+        Ok(Some(val)) if !val.data.is_empty() => serde_json::from_str::<Vec<Lock>>(&val.data)
+            .map_err(|e| GitLFSError::GeneralError(e.to_string()))?,
+        Ok(_) => vec![],
+        Err(_) => {
+            return Err(GitLFSError::GeneralError(
+                "Lookup operation failed!".to_string(),
+            ))
+        }
+    };
+    Ok(locks
+        .into_iter()
+        .filter(|lock| !lock_owned_by(lock, user))
+        .collect())
+}
+
 /// Process batch request.
 pub async fn lfs_process_batch(
     config: &LfsConfig,
@@ -488,6 +529,15 @@ async fn lfs_file_exist(config: &LfsConfig, meta: &MetaObject) -> bool {
     }
 }
 
+/// A lock without an owner was created before ownership was recorded, it belongs to no one
+/// and only a forced unlock removes it.
+fn lock_owned_by(lock: &Lock, user: Option<&str>) -> bool {
+    match (&lock.owner, user) {
+        (Some(owner), Some(user)) => owner.name == user,
+        _ => false,
+    }
+}
+
 async fn lfs_get_filtered_locks(
     storage: Arc<LfsStorage>,
     refspec: &str,
@@ -663,6 +713,7 @@ async fn lfs_put_meta(
         size: meta.size.to_owned(),
         exist: true,
         splited,
+        created_at: chrono::Utc::now().naive_utc(),
     };
 
     let res = storage.new_lfs_object(meta_to).await;
@@ -686,7 +737,7 @@ async fn lfs_delete_meta(storage: Arc<LfsStorage>, v: &RequestVars) -> Result<()
 async fn delete_lock(
     storage: Arc<LfsStorage>,
     repo: &str,
-    _user: Option<String>,
+    user: Option<String>,
     id: &str,
     force: bool,
 ) -> Result<Lock, GitLFSError> {
@@ -715,7 +766,7 @@ async fn delete_lock(
 
             for lock in locks_from_data.iter() {
                 if lock.id == *id {
-                    if Option::is_some(&lock.owner) && !force {
+                    if !lock_owned_by(lock, user.as_deref()) && !force {
                         return Err(GitLFSError::GeneralError("".to_string()));
                     }
                     lock.id.clone_into(&mut lock_to_delete.id);
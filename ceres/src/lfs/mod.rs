@@ -2,8 +2,13 @@ use std::sync::Arc;
 
 use jupiter::{context::Context, raw_storage::RawStorage};
 
+pub mod gc;
 pub mod handler;
 pub mod lfs_structs;
+pub mod pointer;
+
+/// Namespace of lfs objects in the raw storage.
+pub const LFS_REPO_NAME: &str = "repo_name";
 
 #[derive(Clone)]
 pub struct LfsConfig {
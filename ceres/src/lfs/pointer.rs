@@ -0,0 +1,100 @@
+//! Git LFS pointer files, see <https://github.com/git-lfs/git-lfs/blob/main/docs/spec.md>.
+//!
+//! A pointer is the small text blob which is committed in place of the real file content:
+//! ```text
+//! version https://git-lfs.github.com/spec/v1
+//! oid sha256:4d7a214614ab2935c943f9e0ff69d22eadbb8f32b1258daaa5e2ca24d17e2393
+//! size 12345
+//! ```
+
+pub const LFS_POINTER_VERSION: &str = "https://git-lfs.github.com/spec/v1";
+
+/// Pointer files are required to be smaller than 1024 bytes by the spec.
+pub const LFS_POINTER_MAX_SIZE: usize = 1024;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LfsPointer {
+    pub oid: String,
+    pub size: i64,
+}
+
+impl LfsPointer {
+    pub fn new(oid: String, size: i64) -> Self {
+        LfsPointer { oid, size }
+    }
+
+    /// Parse a blob as a pointer file, return `None` if the content is not a valid pointer.
+    pub fn parse(data: &[u8]) -> Option<Self> {
+        if data.len() >= LFS_POINTER_MAX_SIZE {
+            return None;
+        }
+        let text = std::str::from_utf8(data).ok()?;
+        let mut lines = text.lines();
+
+        // `version` must be the first key, the other keys are sorted alphabetically
+        if lines.next()?.strip_prefix("version ")? != LFS_POINTER_VERSION {
+            return None;
+        }
+        let mut oid = None;
+        let mut size = None;
+        for line in lines {
+            let (key, value) = line.split_once(' ')?;
+            match key {
+                "oid" => {
+                    let hex = value.strip_prefix("sha256:")?;
+                    if hex.len() != 64 || !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+                        return None;
+                    }
+                    oid = Some(hex.to_lowercase());
+                }
+                "size" => size = Some(value.parse::<i64>().ok()?),
+                _ => {}
+            }
+        }
+        Some(LfsPointer {
+            oid: oid?,
+            size: size?,
+        })
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        format!(
+            "version {}\noid sha256:{}\nsize {}\n",
+            LFS_POINTER_VERSION, self.oid, self.size
+        )
+        .into_bytes()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::LfsPointer;
+
+    const OID: &str = "4d7a214614ab2935c943f9e0ff69d22eadbb8f32b1258daaa5e2ca24d17e2393";
+
+    #[test]
+    fn test_parse_pointer() {
+        let data = format!(
+            "version https://git-lfs.github.com/spec/v1\noid sha256:{}\nsize 12345\n",
+            OID
+        );
+        let pointer = LfsPointer::parse(data.as_bytes()).unwrap();
+        assert_eq!(pointer.oid, OID);
+        assert_eq!(pointer.size, 12345);
+        assert_eq!(pointer.to_bytes(), data.into_bytes());
+    }
+
+    #[test]
+    fn test_parse_not_pointer() {
+        assert!(LfsPointer::parse(b"fn main() {}\n").is_none());
+        assert!(LfsPointer::parse(
+            b"version https://git-lfs.github.com/spec/v1\noid sha256:1234\nsize 1\n"
+        )
+        .is_none());
+        let no_size = format!(
+            "version https://git-lfs.github.com/spec/v1\noid sha256:{}\n",
+            OID
+        );
+        assert!(LfsPointer::parse(no_size.as_bytes()).is_none());
+    }
+}
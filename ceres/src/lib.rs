@@ -1,6 +1,9 @@
 pub mod api_service;
+pub mod hooks;
 pub mod http;
 pub mod lfs;
 pub mod pack;
+pub mod policy;
 pub mod protocol;
+pub mod search;
 pub mod model;
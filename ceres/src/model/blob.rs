@@ -0,0 +1,23 @@
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct BlobBatchRequest {
+    /// the ids of the blobs
+    pub oids: Vec<String>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct BlobBatch {
+    /// the blobs found, in the order they were asked
+    pub blobs: Vec<BlobData>,
+    /// the ids of the blobs not found, or not kept in the database
+    pub missing: Vec<String>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct BlobData {
+    pub oid: String,
+    pub size: usize,
+    /// the content, in base64
+    pub data: String,
+}
@@ -0,0 +1,38 @@
+use serde::{Deserialize, Serialize};
+
+/// The changes to a path between two commits, from their Conventional Commit messages.
+#[derive(Serialize, Deserialize)]
+pub struct Changelog {
+    pub path: String,
+    /// the commit the changes are since, all the commits up to `to` if empty
+    pub from: String,
+    pub to: String,
+    /// the changes grouped by type, in the order of the configured types then by name
+    pub sections: Vec<ChangelogSection>,
+    /// the breaking changes, which are in their sections too
+    pub breaking_changes: Vec<ChangelogEntry>,
+    /// the commits whose messages are not Conventional Commits, with their first line
+    pub other_commits: Vec<ChangelogEntry>,
+    /// whether older commits were left out, as too many commits were walked
+    pub truncated: bool,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct ChangelogSection {
+    /// like `feat` or `fix`
+    pub commit_type: String,
+    /// the changes, the latest first
+    pub entries: Vec<ChangelogEntry>,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct ChangelogEntry {
+    pub commit: String,
+    pub scope: Option<String>,
+    pub description: String,
+    pub breaking: bool,
+    /// the `BREAKING CHANGE` footer
+    pub breaking_note: Option<String>,
+    pub author: String,
+    pub date: String,
+}
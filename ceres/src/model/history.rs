@@ -0,0 +1,37 @@
+use serde::{Deserialize, Serialize};
+
+use crate::model::tree::LatestCommitInfo;
+
+/// A page of the commits which changed a path, the latest first.
+#[derive(Serialize, Deserialize)]
+pub struct CommitHistory {
+    pub path: String,
+    pub page: usize,
+    pub per_page: usize,
+    /// whether there are older commits, on the next pages
+    pub has_more: bool,
+    pub commits: Vec<LatestCommitInfo>,
+}
+
+/// The lines of a file, with the commits which added them.
+#[derive(Serialize, Deserialize)]
+pub struct BlameInfo {
+    pub path: String,
+    /// the commit the file was blamed at
+    pub commit: String,
+    /// the id of the blob of the file
+    pub oid: String,
+    pub lines: Vec<BlameLine>,
+    /// the commits of the lines, the latest first
+    pub commits: Vec<LatestCommitInfo>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct BlameLine {
+    /// the line number, from 1
+    pub line: usize,
+    pub content: String,
+    /// the commit which added the line
+    pub commit: String,
+    pub author: String,
+}
@@ -1,5 +1,9 @@
+pub mod blob;
+pub mod changelog;
 pub mod create_file;
+pub mod history;
 pub mod mr;
 pub mod publish_path;
 pub mod query;
+pub mod search;
 pub mod tree;
@@ -15,6 +15,93 @@ pub struct BlobContentQuery {
     pub path: String,
 }
 
+#[derive(Debug, Deserialize)]
+pub struct TreeAtQuery {
+    #[serde(default = "default_path")]
+    pub path: String,
+    /// the commit to resolve `path` in, the latest one by default
+    pub commit: Option<String>,
+    /// the id of the tree to list, `path` then only names its entries
+    pub tree: Option<String>,
+    /// how many levels of directories to list, 1 for the entries of `path` only
+    #[serde(default = "default_depth")]
+    pub depth: usize,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct HistoryQuery {
+    #[serde(default = "default_path")]
+    pub path: String,
+    /// the commit to start from, the latest one by default
+    pub commit: Option<String>,
+    /// the page, from 1
+    #[serde(default = "default_page")]
+    pub page: usize,
+    #[serde(default = "default_per_page")]
+    pub per_page: usize,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct BlameQuery {
+    pub path: String,
+    /// the commit to blame the file at, the latest one by default
+    pub commit: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SearchQuery {
+    pub q: String,
+    /// whether `q` is a regex, it is a literal by default
+    #[serde(default)]
+    pub regex: bool,
+    #[serde(default)]
+    pub case_sensitive: bool,
+    /// search the names of the definitions instead of the lines of the files
+    #[serde(default)]
+    pub symbol: bool,
+    /// only search the files under this path
+    #[serde(default = "default_path")]
+    pub path: String,
+    /// only search the files of these languages, separated by `,`
+    pub lang: Option<String>,
+    /// the lines of context around a matching line
+    #[serde(default = "default_context")]
+    pub context: usize,
+    /// the most files, or symbols, to return
+    #[serde(default = "default_limit")]
+    pub limit: usize,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ChangelogQuery {
+    #[serde(default = "default_path")]
+    pub path: String,
+    /// the commit the changes are since, all the commits by default
+    pub from: Option<String>,
+    /// the commit the changes are up to, the latest one by default
+    pub to: Option<String>,
+}
+
+fn default_context() -> usize {
+    2
+}
+
+fn default_limit() -> usize {
+    50
+}
+
+fn default_page() -> usize {
+    1
+}
+
+fn default_per_page() -> usize {
+    20
+}
+
+fn default_depth() -> usize {
+    1
+}
+
 fn default_path() -> String {
     "/".to_string()
 }
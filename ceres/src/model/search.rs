@@ -0,0 +1,43 @@
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize)]
+pub struct SearchResult {
+    /// the commit of the `/` ref the index is at, empty before the first indexing
+    pub commit: String,
+    pub files: Vec<FileMatch>,
+    pub symbols: Vec<SymbolMatch>,
+    /// whether there are more results than returned, or files were left unsearched
+    pub truncated: bool,
+}
+
+/// The matching lines of a file.
+#[derive(Serialize, Deserialize)]
+pub struct FileMatch {
+    pub path: String,
+    /// the id of the blob of the file
+    pub oid: String,
+    pub language: Option<String>,
+    pub lines: Vec<LineMatch>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct LineMatch {
+    /// the line number, from 1
+    pub line: usize,
+    pub content: String,
+    /// the lines of context before the line
+    pub before: Vec<String>,
+    /// the lines of context after the line
+    pub after: Vec<String>,
+}
+
+/// A definition whose name matches the query.
+#[derive(Serialize, Deserialize)]
+pub struct SymbolMatch {
+    pub name: String,
+    /// like `function`, `struct` or `class`
+    pub kind: String,
+    pub path: String,
+    pub line: usize,
+    pub oid: String,
+}
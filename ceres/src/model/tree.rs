@@ -57,6 +57,13 @@ pub struct TreeBriefItem {
     pub name: String,
     pub path: String,
     pub content_type: String,
+    /// the id of the blob or tree
+    pub oid: String,
+    /// the git mode, like `100644`, `100755` or `120000` for a symlink
+    pub mode: String,
+    /// the size of the content of a file in bytes, so clients don't download it to know
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub size: Option<u64>,
 }
 
 impl From<TreeItem> for TreeBriefItem {
@@ -69,6 +76,9 @@ impl From<TreeItem> for TreeBriefItem {
             } else {
                 "file".to_owned()
             },
+            oid: value.id.to_plain_str(),
+            mode: String::from_utf8_lossy(value.mode.to_bytes()).into_owned(),
+            size: None,
         }
     }
 }
@@ -79,3 +89,27 @@ pub struct MRFileTree {
     pub id: i64,
     pub children: Vec<MRFileTree>,
 }
+
+/// A directory at a commit or tree id, with the entries of its subdirectories down to a depth.
+///
+/// The ids never change their content, so a client can cache the listing of a tree by its id.
+#[derive(Serialize, Deserialize)]
+pub struct TreeListing {
+    /// the commit `path` was resolved in, empty when a tree id was asked
+    pub commit: String,
+    pub path: String,
+    /// the id of the tree at `path`
+    pub oid: String,
+    /// whether subdirectories within the depth were left out, to keep the response small
+    pub truncated: bool,
+    pub items: Vec<TreeNode>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct TreeNode {
+    #[serde(flatten)]
+    pub item: TreeBriefItem,
+    /// the entries of a directory, `None` past the depth
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub children: Option<Vec<TreeNode>>,
+}
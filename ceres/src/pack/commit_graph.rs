@@ -0,0 +1,636 @@
+//! In-memory commit graph and reachability bitmaps, used to negotiate fetches.
+//!
+//! The graph and bitmaps are kept by
+//! [`CommitGraphStorage`](jupiter::storage::commit_graph_storage::CommitGraphStorage), see there
+//! for how they are stored.
+//! Import repos build the bitmaps of their ref tips on push, so fetches count the objects to
+//! send and find the objects the client has with set operations.
+
+use std::collections::{BTreeMap, BinaryHeap, HashMap, HashSet};
+use std::sync::Arc;
+
+use tokio::sync::Mutex;
+
+use callisto::commit_graph;
+use common::errors::MegaError;
+use mercury::internal::object::types::ObjectType;
+
+use crate::pack::{connectivity::ReceivedObjects, import_repo::ImportRepo};
+
+/// The locks held while positions are given to the objects of a repo, by repo id, as the
+/// positions must be unique in a repo. A lock is only kept while someone holds it.
+static BITMAP_OBJECTS: std::sync::Mutex<BTreeMap<i64, Arc<Mutex<()>>>> =
+    std::sync::Mutex::new(BTreeMap::new());
+
+/// The lock of the bitmap positions of a repo, its entry is removed with the last holder.
+struct BitmapObjectsLock {
+    repo_id: i64,
+    lock: Arc<Mutex<()>>,
+}
+
+impl BitmapObjectsLock {
+    fn new(repo_id: i64) -> Self {
+        let lock = BITMAP_OBJECTS
+            .lock()
+            .unwrap()
+            .entry(repo_id)
+            .or_default()
+            .clone();
+        BitmapObjectsLock { repo_id, lock }
+    }
+}
+
+impl Drop for BitmapObjectsLock {
+    fn drop(&mut self) {
+        let mut locks = BITMAP_OBJECTS.lock().unwrap();
+        // the lock is only cloned out of the map while it is locked, so no one else has it
+        // when the map and this are the only holders
+        if Arc::strong_count(&self.lock) == 2 {
+            locks.remove(&self.repo_id);
+        }
+    }
+}
+
+/// A commit in the graph.
+#[derive(Debug, Clone, PartialEq)]
+pub struct GraphNode {
+    /// 1 for root commits, otherwise one more than the highest generation of the parents,
+    /// so a commit always has a higher generation than its ancestors.
+    pub generation: i64,
+    pub parents: Vec<String>,
+}
+
+#[derive(Debug, Default)]
+pub struct CommitGraph {
+    nodes: HashMap<String, GraphNode>,
+}
+
+impl From<Vec<commit_graph::Model>> for CommitGraph {
+    fn from(models: Vec<commit_graph::Model>) -> Self {
+        let mut graph = CommitGraph::default();
+        graph.extend(models);
+        graph
+    }
+}
+
+impl CommitGraph {
+    pub fn extend(&mut self, models: Vec<commit_graph::Model>) {
+        for model in models {
+            let parents = serde_json::from_value(model.parents_id).unwrap_or_default();
+            self.nodes.insert(
+                model.commit_id,
+                GraphNode {
+                    generation: model.generation,
+                    parents,
+                },
+            );
+        }
+    }
+
+    pub fn contains(&self, commit_id: &str) -> bool {
+        self.nodes.contains_key(commit_id)
+    }
+
+    pub fn get(&self, commit_id: &str) -> Option<&GraphNode> {
+        self.nodes.get(commit_id)
+    }
+
+    /// Add commits to the graph, in any order. A commit is only added once all of its parents
+    /// are in the graph, the others are left out.
+    ///
+    /// Returns the added commits, parents first.
+    pub fn add_commits(
+        &mut self,
+        mut commits: HashMap<String, Vec<String>>,
+    ) -> Vec<(String, GraphNode)> {
+        let mut added = vec![];
+        loop {
+            let ready: Vec<String> = commits
+                .iter()
+                .filter(|(_, parents)| parents.iter().all(|p| self.nodes.contains_key(p)))
+                .map(|(id, _)| id.clone())
+                .collect();
+            if ready.is_empty() {
+                break;
+            }
+            for id in ready {
+                let parents = commits.remove(&id).unwrap();
+                let generation = parents
+                    .iter()
+                    .map(|p| self.nodes[p].generation)
+                    .max()
+                    .unwrap_or(0)
+                    + 1;
+                let node = GraphNode {
+                    generation,
+                    parents,
+                };
+                self.nodes.insert(id.clone(), node.clone());
+                added.push((id, node));
+            }
+        }
+        added
+    }
+
+    /// The commits reachable from `want` but not from `have`, newest first.
+    /// Commits of `want` and `have` which aren't in the graph are ignored.
+    ///
+    /// Commits are visited from the highest generation down, so a commit is only visited after
+    /// all of its children and knows whether `have` reaches it. The walk stops once every
+    /// commit left is reachable from `have`.
+    ///
+    /// Returns `None` when the walk reaches a parent which isn't in the graph, the graph only
+    /// holds the generations above it and has to be loaded further down.
+    pub fn missing(&self, want: &[String], have: &[String]) -> Option<Vec<String>> {
+        let mut walk = Walk {
+            graph: self,
+            flags: HashMap::new(),
+            queue: BinaryHeap::new(),
+            want_only: 0,
+        };
+        for id in want {
+            walk.mark(id, WANT);
+        }
+        for id in have {
+            walk.mark(id, HAVE);
+        }
+        let mut missing = vec![];
+        while walk.want_only > 0 {
+            let (_, id) = walk.queue.pop().unwrap();
+            let flag = walk.flags[id];
+            if flag == WANT {
+                walk.want_only -= 1;
+                missing.push(id.to_owned());
+            }
+            for parent in &self.nodes[id].parents {
+                if !self.nodes.contains_key(parent) {
+                    return None;
+                }
+                walk.mark(parent, flag);
+            }
+        }
+        Some(missing)
+    }
+}
+
+const WANT: u8 = 1;
+const HAVE: u8 = 2;
+
+/// State of [`CommitGraph::missing`].
+struct Walk<'a> {
+    graph: &'a CommitGraph,
+    /// whether a commit is reachable from `want`, `have` or both
+    flags: HashMap<&'a str, u8>,
+    /// commits to visit by generation, every commit is queued once
+    queue: BinaryHeap<(i64, &'a str)>,
+    /// queued commits reachable from `want` only
+    want_only: usize,
+}
+
+impl<'a> Walk<'a> {
+    fn mark(&mut self, id: &'a str, flag: u8) {
+        let Some(node) = self.graph.nodes.get(id) else {
+            return;
+        };
+        let old = self.flags.get(id).copied().unwrap_or(0);
+        let new = old | flag;
+        if new == old {
+            return;
+        }
+        self.flags.insert(id, new);
+        if old == 0 {
+            self.queue.push((node.generation, id));
+        }
+        if old == WANT {
+            self.want_only -= 1;
+        }
+        if new == WANT {
+            self.want_only += 1;
+        }
+    }
+}
+
+/// A set of object positions, the bit of an object is at its position in `bitmap_object`.
+///
+/// The set of a commit holds every object reachable from it, so a tree in the set implies
+/// all of its entries.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Bitmap {
+    words: Vec<u64>,
+}
+
+impl Bitmap {
+    pub fn insert(&mut self, position: usize) {
+        let word = position / 64;
+        if word >= self.words.len() {
+            self.words.resize(word + 1, 0);
+        }
+        self.words[word] |= 1 << (position % 64);
+    }
+
+    pub fn contains(&self, position: usize) -> bool {
+        self.words
+            .get(position / 64)
+            .is_some_and(|w| w & (1 << (position % 64)) != 0)
+    }
+
+    pub fn union_with(&mut self, other: &Bitmap) {
+        if other.words.len() > self.words.len() {
+            self.words.resize(other.words.len(), 0);
+        }
+        for (w, o) in self.words.iter_mut().zip(&other.words) {
+            *w |= o;
+        }
+    }
+
+    /// Keep only the positions also in `other`.
+    pub fn intersect_with(&mut self, other: &Bitmap) {
+        self.words.truncate(other.words.len());
+        for (w, o) in self.words.iter_mut().zip(&other.words) {
+            *w &= o;
+        }
+    }
+
+    /// The positions in `self` but not in `other`.
+    pub fn difference(&self, other: &Bitmap) -> Bitmap {
+        let words = self
+            .words
+            .iter()
+            .enumerate()
+            .map(|(i, w)| w & !other.words.get(i).copied().unwrap_or(0))
+            .collect();
+        Bitmap { words }
+    }
+
+    pub fn len(&self) -> usize {
+        self.words.iter().map(|w| w.count_ones() as usize).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.words.iter().all(|w| *w == 0)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = usize> + '_ {
+        self.words.iter().enumerate().flat_map(|(i, w)| {
+            (0..64)
+                .filter(move |bit| w & (1 << bit) != 0)
+                .map(move |bit| i * 64 + bit)
+        })
+    }
+
+    /// Little-endian words, without the trailing empty ones.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let len = self
+            .words
+            .iter()
+            .rposition(|w| *w != 0)
+            .map_or(0, |i| i + 1);
+        self.words[..len]
+            .iter()
+            .flat_map(|w| w.to_le_bytes())
+            .collect()
+    }
+
+    pub fn from_bytes(data: &[u8]) -> Bitmap {
+        let words = data
+            .chunks(8)
+            .map(|chunk| {
+                let mut word = [0; 8];
+                word[..chunk.len()].copy_from_slice(chunk);
+                u64::from_le_bytes(word)
+            })
+            .collect();
+        Bitmap { words }
+    }
+}
+
+impl ImportRepo {
+    /// Build the reachability bitmaps of the new ref tips of a push.
+    ///
+    /// The received objects are given positions first. A tip gets no bitmap when the history of
+    /// the push starts from a commit without one, or a received tree points to a stored tree
+    /// outside of those bitmaps, as the objects of the tree would have to be read from storage.
+    pub async fn build_bitmaps(
+        &self,
+        received: &ReceivedObjects,
+        tips: Vec<String>,
+    ) -> Result<(), MegaError> {
+        let storage = self.context.services.commit_graph_storage.clone();
+        let repo_id = self.repo.repo_id;
+        let tips: Vec<String> = tips
+            .into_iter()
+            .filter(|tip| received.commits.contains_key(tip))
+            .collect();
+        if tips.is_empty() {
+            return Ok(());
+        }
+
+        let ids: Vec<String> = received
+            .commits
+            .keys()
+            .chain(received.trees.keys())
+            .chain(received.blobs.iter())
+            .cloned()
+            .collect();
+        let mut positions = {
+            let lock = BitmapObjectsLock::new(repo_id);
+            let _guard = lock.lock.lock().await;
+            let mut positions = storage.get_bitmap_positions(repo_id, ids.clone()).await?;
+            let new_ids = ids
+                .into_iter()
+                .filter(|id| !positions.contains_key(id))
+                .collect();
+            positions.extend(storage.add_bitmap_objects(repo_id, new_ids).await?);
+            positions
+        };
+        // stored objects the received trees point to
+        let stored: HashSet<String> = received
+            .trees
+            .values()
+            .flatten()
+            .map(|(id, _)| id)
+            .filter(|id| !positions.contains_key(*id))
+            .cloned()
+            .collect();
+        positions.extend(
+            storage
+                .get_bitmap_positions(repo_id, stored.into_iter().collect())
+                .await?,
+        );
+
+        // the bitmaps of the commits the push starts from
+        let outside: HashSet<String> = received
+            .commits
+            .values()
+            .flat_map(|(_, parents)| parents)
+            .filter(|id| !received.commits.contains_key(*id))
+            .cloned()
+            .collect();
+        let outside: HashMap<String, Bitmap> = storage
+            .get_reachability_bitmaps(repo_id, outside.into_iter().collect())
+            .await?
+            .into_iter()
+            .map(|b| (b.commit_id, Bitmap::from_bytes(&b.bitmap)))
+            .collect();
+
+        for tip in tips {
+            match tip_bitmap(&tip, received, &positions, &outside) {
+                Some(bitmap) => {
+                    storage
+                        .save_reachability_bitmap(repo_id, &tip, bitmap.to_bytes(), bitmap.len())
+                        .await?
+                }
+                None => tracing::debug!("no reachability bitmap for {}", tip),
+            }
+        }
+        Ok(())
+    }
+
+    /// The ids of the objects reachable from `have`, `None` if a `have` commit has no bitmap.
+    ///
+    /// Only the bitmaps of `want` and `have` are read. When all of `want` have one, only the
+    /// objects also reachable from `want` are returned, the others are of no use to a fetch.
+    pub async fn reachable_objects(
+        &self,
+        want: &[String],
+        have: &[String],
+    ) -> Result<Option<HashSet<String>>, MegaError> {
+        let storage = self.context.services.commit_graph_storage.clone();
+        let repo_id = self.repo.repo_id;
+        let want: HashSet<&String> = want.iter().collect();
+        let have: HashSet<&String> = have.iter().collect();
+        let bitmaps: HashMap<String, Bitmap> = storage
+            .get_reachability_bitmaps(
+                repo_id,
+                want.union(&have).map(|id| id.to_string()).collect(),
+            )
+            .await?
+            .into_iter()
+            .map(|b| (b.commit_id, Bitmap::from_bytes(&b.bitmap)))
+            .collect();
+        let union = |ids: &HashSet<&String>| {
+            ids.iter().try_fold(Bitmap::default(), |mut union, id| {
+                union.union_with(bitmaps.get(*id)?);
+                Some(union)
+            })
+        };
+        let Some(mut reachable) = union(&have) else {
+            return Ok(None);
+        };
+        if let Some(wanted) = union(&want) {
+            reachable.intersect_with(&wanted);
+        }
+
+        let positions: Vec<usize> = reachable.iter().collect();
+        let objects = storage
+            .get_bitmap_objects_at(repo_id, positions.clone())
+            .await?;
+        Ok(positions
+            .iter()
+            .map(|position| objects.get(position).cloned())
+            .collect())
+    }
+}
+
+/// The objects reachable from a received commit, `None` if they can't be found from the
+/// received objects and the bitmaps of the commits the push starts from.
+fn tip_bitmap(
+    tip: &str,
+    received: &ReceivedObjects,
+    positions: &HashMap<String, usize>,
+    outside: &HashMap<String, Bitmap>,
+) -> Option<Bitmap> {
+    let mut bitmap = Bitmap::default();
+    let mut trees = vec![];
+    let mut commits = vec![tip];
+    let mut visited = HashSet::new();
+    while let Some(id) = commits.pop() {
+        if !visited.insert(id) {
+            continue;
+        }
+        match received.commits.get(id) {
+            Some((tree, parents)) => {
+                bitmap.insert(*positions.get(id)?);
+                trees.push(tree.as_str());
+                commits.extend(parents.iter().map(String::as_str));
+            }
+            None => bitmap.union_with(outside.get(id)?),
+        }
+    }
+
+    // a tree in the bitmap already has its entries in it
+    while let Some(id) = trees.pop() {
+        let position = *positions.get(id)?;
+        if bitmap.contains(position) {
+            continue;
+        }
+        bitmap.insert(position);
+        for (item, obj_type) in received.trees.get(id)? {
+            if *obj_type == ObjectType::Tree {
+                trees.push(item);
+            } else {
+                bitmap.insert(*positions.get(item)?);
+            }
+        }
+    }
+    Some(bitmap)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::sync::Arc;
+
+    use mercury::internal::object::{
+        blob::Blob,
+        commit::Commit,
+        tree::{Tree, TreeItem, TreeItemMode},
+    };
+    use mercury::internal::pack::entry::Entry;
+
+    use super::{tip_bitmap, Bitmap, BitmapObjectsLock, CommitGraph, BITMAP_OBJECTS};
+    use crate::pack::connectivity::ReceivedObjects;
+
+    fn graph(commits: &[(&str, &[&str])]) -> CommitGraph {
+        let mut graph = CommitGraph::default();
+        let commits: HashMap<String, Vec<String>> = commits
+            .iter()
+            .map(|(id, parents)| {
+                let parents = parents.iter().map(|p| p.to_string()).collect();
+                (id.to_string(), parents)
+            })
+            .collect();
+        let len = commits.len();
+        assert_eq!(graph.add_commits(commits).len(), len);
+        graph
+    }
+
+    fn ids(ids: &[&str]) -> Vec<String> {
+        ids.iter().map(|id| id.to_string()).collect()
+    }
+
+    #[test]
+    fn test_generation() {
+        // a - b - d
+        //  \- c -/
+        let graph = graph(&[("d", &["b", "c"]), ("c", &["a"]), ("b", &["a"]), ("a", &[])]);
+        assert_eq!(graph.get("a").unwrap().generation, 1);
+        assert_eq!(graph.get("d").unwrap().generation, 3);
+
+        let mut graph = graph;
+        let orphan = HashMap::from([("e".to_string(), ids(&["x"]))]);
+        assert!(graph.add_commits(orphan).is_empty());
+        assert!(!graph.contains("e"));
+    }
+
+    #[test]
+    fn test_missing() {
+        // a - b - c - e - f
+        //      \- d -/
+        let graph = graph(&[
+            ("a", &[]),
+            ("b", &["a"]),
+            ("c", &["b"]),
+            ("d", &["b"]),
+            ("e", &["c", "d"]),
+            ("f", &["e"]),
+        ]);
+        assert_eq!(
+            graph.missing(&ids(&["f"]), &ids(&["c"])),
+            Some(ids(&["f", "e", "d"]))
+        );
+        assert_eq!(graph.missing(&ids(&["f"]), &ids(&["e"])), Some(ids(&["f"])));
+        assert_eq!(graph.missing(&ids(&["c"]), &ids(&["f"])), Some(vec![]));
+        // unknown commits are ignored
+        assert_eq!(
+            graph.missing(&ids(&["b", "x"]), &ids(&["y"])),
+            Some(ids(&["b", "a"]))
+        );
+
+        // the generations from b up only, the walk stops before it needs a
+        let mut partial = CommitGraph::default();
+        for id in ["b", "c", "d", "e", "f"] {
+            partial
+                .nodes
+                .insert(id.to_string(), graph.get(id).unwrap().clone());
+        }
+        assert_eq!(
+            partial.missing(&ids(&["f"]), &ids(&["c"])),
+            Some(ids(&["f", "e", "d"]))
+        );
+        assert_eq!(partial.missing(&ids(&["b"]), &ids(&["y"])), None);
+    }
+
+    #[test]
+    fn test_bitmap_objects_lock() {
+        let first = BitmapObjectsLock::new(-1);
+        let second = BitmapObjectsLock::new(-1);
+        assert!(Arc::ptr_eq(&first.lock, &second.lock));
+        drop(first);
+        assert!(BITMAP_OBJECTS.lock().unwrap().contains_key(&-1));
+        drop(second);
+        assert!(!BITMAP_OBJECTS.lock().unwrap().contains_key(&-1));
+    }
+
+    #[test]
+    fn test_bitmap() {
+        let mut a = Bitmap::default();
+        a.insert(1);
+        a.insert(70);
+        let mut b = Bitmap::default();
+        b.insert(70);
+        b.insert(200);
+
+        assert_eq!(a.difference(&b).iter().collect::<Vec<_>>(), vec![1]);
+        a.union_with(&b);
+        assert_eq!(a.iter().collect::<Vec<_>>(), vec![1, 70, 200]);
+        assert_eq!(a.len(), 3);
+        assert!(a.contains(200) && !a.contains(2));
+        assert_eq!(Bitmap::from_bytes(&a.to_bytes()), a);
+        assert!(b.difference(&a).is_empty());
+        b.insert(3);
+        b.intersect_with(&a);
+        assert_eq!(b.iter().collect::<Vec<_>>(), vec![70, 200]);
+    }
+
+    #[test]
+    fn test_tip_bitmap() {
+        let tree_of = |blobs: &[&Blob]| {
+            let items = blobs
+                .iter()
+                .enumerate()
+                .map(|(i, b)| TreeItem::new(TreeItemMode::Blob, b.id, format!("{}.txt", i)))
+                .collect();
+            Tree::from_tree_items(items).unwrap()
+        };
+        // stored: a commit with blob `a`, at positions 0 to 2
+        let a = Blob::from_content("a");
+        let base = Commit::from_tree_id(tree_of(&[&a]).id, vec![], "base");
+        let base_bitmap = Bitmap::from_bytes(&[0b111]);
+
+        // pushed: a commit on top of it with the blobs `a` and `b`
+        let b = Blob::from_content("b");
+        let tree = tree_of(&[&a, &b]);
+        let commit = Commit::from_tree_id(tree.id, vec![base.id], "next");
+        let mut received = ReceivedObjects::default();
+        for entry in [
+            Entry::from(b.clone()),
+            tree.clone().into(),
+            commit.clone().into(),
+        ] {
+            received.record(&entry).unwrap();
+        }
+        let mut positions = HashMap::from([(a.id.to_plain_str(), 0)]);
+        for (i, id) in [b.id, tree.id, commit.id].iter().enumerate() {
+            positions.insert(id.to_plain_str(), 3 + i);
+        }
+        let tip = commit.id.to_plain_str();
+
+        let outside = HashMap::from([(base.id.to_plain_str(), base_bitmap)]);
+        let bitmap = tip_bitmap(&tip, &received, &positions, &outside).unwrap();
+        assert_eq!(bitmap.iter().collect::<Vec<_>>(), vec![0, 1, 2, 3, 4, 5]);
+
+        // without the bitmap of the parent
+        assert!(tip_bitmap(&tip, &received, &positions, &HashMap::new()).is_none());
+    }
+}
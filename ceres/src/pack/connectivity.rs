@@ -0,0 +1,139 @@
+use std::collections::{HashMap, HashSet};
+
+use mercury::{
+    errors::GitError,
+    internal::{
+        object::{
+            commit::Commit,
+            tree::{Tree, TreeItemMode},
+            types::ObjectType,
+            ObjectTrait,
+        },
+        pack::{entry::Entry, utils::calculate_object_hash},
+    },
+};
+
+/// Links between the objects received in a push.
+///
+/// The receiver records every entry while it is being saved, so the refs can be checked
+/// for connectivity afterwards without reading the new objects back from storage.
+#[derive(Debug, Default)]
+pub struct ReceivedObjects {
+    /// commit id -> (tree id, parent ids)
+    pub commits: HashMap<String, (String, Vec<String>)>,
+    /// tree id -> sub trees and blobs, submodules are skipped
+    pub trees: HashMap<String, Vec<(String, ObjectType)>>,
+    pub blobs: HashSet<String>,
+    /// commit id -> raw message, for the commit policies
+    pub messages: HashMap<String, String>,
+    /// tag id -> raw content, kept whole for the hooks as the tags of a push are few and small
+    pub tags: HashMap<String, Vec<u8>>,
+}
+
+impl ReceivedObjects {
+    /// Re-hash the entry and record its links, fails if the content doesn't match the claimed id.
+    pub fn record(&mut self, entry: &Entry) -> Result<(), GitError> {
+        if calculate_object_hash(entry.hash.kind(), entry.obj_type, &entry.data) != entry.hash {
+            return Err(GitError::InvalidHashValue(entry.hash.to_plain_str()));
+        }
+        let id = entry.hash.to_plain_str();
+        match entry.obj_type {
+            ObjectType::Commit => {
+                let commit = Commit::from_bytes(&entry.data, entry.hash)?;
+                let parents = commit
+                    .parent_commit_ids
+                    .iter()
+                    .map(|p| p.to_plain_str())
+                    .collect();
+                self.messages.insert(id.clone(), commit.message);
+                self.commits
+                    .insert(id, (commit.tree_id.to_plain_str(), parents));
+            }
+            ObjectType::Tree => {
+                let tree = Tree::from_bytes(&entry.data, entry.hash)?;
+                let items = tree
+                    .tree_items
+                    .iter()
+                    .filter_map(|item| match item.mode {
+                        TreeItemMode::Tree => Some((item.id.to_plain_str(), ObjectType::Tree)),
+                        TreeItemMode::Blob | TreeItemMode::BlobExecutable | TreeItemMode::Link => {
+                            Some((item.id.to_plain_str(), ObjectType::Blob))
+                        }
+                        TreeItemMode::Commit => None,
+                    })
+                    .collect();
+                self.trees.insert(id, items);
+            }
+            ObjectType::Blob => {
+                self.blobs.insert(id);
+            }
+            ObjectType::Tag => {
+                self.tags.insert(id, entry.data.clone());
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use mercury::internal::object::types::ObjectType;
+    use mercury::internal::object::{
+        blob::Blob,
+        commit::Commit,
+        tree::{Tree, TreeItem, TreeItemMode},
+    };
+    use mercury::internal::pack::{entry::Entry, utils::calculate_object_hash};
+
+    use super::ReceivedObjects;
+
+    #[test]
+    fn test_record_links() {
+        let blob = Blob::from_content("hello");
+        let tree = Tree::from_tree_items(vec![TreeItem::new(
+            TreeItemMode::Blob,
+            blob.id,
+            "a.txt".to_string(),
+        )])
+        .unwrap();
+        let commit = Commit::from_tree_id(tree.id, vec![], "init");
+        let tag_data = format!(
+            "object {}\ntype commit\ntag v1\ntagger a <a@b.c> 0 +0000\n\nv1\n",
+            commit.id
+        )
+        .into_bytes();
+        let tag = Entry {
+            obj_type: ObjectType::Tag,
+            hash: calculate_object_hash(commit.id.kind(), ObjectType::Tag, &tag_data),
+            data: tag_data.clone(),
+        };
+
+        let mut received = ReceivedObjects::default();
+        for entry in [
+            Entry::from(blob.clone()),
+            Entry::from(tree.clone()),
+            Entry::from(commit.clone()),
+            tag.clone(),
+        ] {
+            received.record(&entry).unwrap();
+        }
+        assert!(received.blobs.contains(&blob.id.to_plain_str()));
+        assert_eq!(
+            received.trees[&tree.id.to_plain_str()][0].0,
+            blob.id.to_plain_str()
+        );
+        assert_eq!(
+            received.commits[&commit.id.to_plain_str()],
+            (tree.id.to_plain_str(), vec![])
+        );
+        assert_eq!(received.tags[&tag.hash.to_plain_str()], tag_data);
+    }
+
+    #[test]
+    fn test_record_hash_mismatch() {
+        let mut entry = Entry::from(Blob::from_content("hello"));
+        entry.data = b"tampered".to_vec();
+        assert!(ReceivedObjects::default().record(&entry).is_err());
+    }
+}
@@ -0,0 +1,353 @@
+//! Verify the objects of the monorepo or an import repo stored in the database.
+//!
+//! The objects are read from the refs down and checked by [`Fsck`], so rows whose data
+//! doesn't hash to their id, malformed objects and objects which are referenced but not
+//! stored are reported. Only reachable objects are read, so none is reported as dangling.
+//!
+//! The bytes hashed are the ones stored: the data of trees and blobs, and the columns of
+//! commits and tags joined as they were split. The rows aren't parsed into objects first,
+//! which would fail on the malformed ones and hash what they were re-serialized to.
+
+use std::collections::HashSet;
+use std::path::{Component, Path, PathBuf};
+use std::str::FromStr;
+use std::sync::Arc;
+
+use serde::{Deserialize, Serialize};
+
+use callisto::{git_commit, git_tag, mega_commit, mega_tag};
+use common::{errors::MegaError, utils::MEGA_BRANCH_NAME};
+use jupiter::{
+    context::Context,
+    storage::{git_db_storage::GitDbStorage, mega_storage::MegaStorage, GitStorageProvider},
+};
+use mercury::{
+    hash::{HashKind, SHA1},
+    internal::{
+        fsck::{Fsck, FsckReport},
+        object::{tree::Tree, types::ObjectType},
+    },
+};
+use venus::import_repo::repo::Repo;
+
+const BATCH_SIZE: usize = 1000;
+
+#[derive(Serialize, Deserialize, Debug, Default, Clone)]
+pub struct FsckOptions {
+    /// A path of the monorepo, or the path of an import repo under `monorepo.import_dir`.
+    pub path: String,
+}
+
+/// Check the objects reachable from the refs of the repo at `path`.
+///
+/// A monorepo path without its own ref is checked from its tree in the main ref.
+pub async fn fsck_repo(context: Context, path: &str) -> Result<FsckReport, MegaError> {
+    let path = PathBuf::from(path);
+    let import_dir = context.config.monorepo.import_dir.clone();
+    if path.starts_with(&import_dir) && path != import_dir {
+        let storage = context.services.git_db_storage.clone();
+        let repo: Repo = storage
+            .find_git_repo_exact_match(path.to_str().unwrap())
+            .await?
+            .ok_or_else(|| MegaError::with_message(&format!("repo not found: {:?}", path)))?
+            .into();
+        let roots = storage
+            .get_ref(&repo)
+            .await?
+            .into_iter()
+            .map(|r| (r.ref_name, r.ref_hash))
+            .collect();
+        let objects = StoredObjects::Import(storage, context.services.mega_storage.clone(), repo);
+        fsck_objects(&objects, roots).await
+    } else {
+        let storage = context.services.mega_storage.clone();
+        let roots = match storage.get_ref(path.to_str().unwrap()).await? {
+            Some(refs) => vec![(MEGA_BRANCH_NAME.to_owned(), refs.ref_commit_hash)],
+            None => {
+                let tree_id = find_tree(&context, &path).await?;
+                vec![(path.to_string_lossy().into_owned(), tree_id)]
+            }
+        };
+        fsck_objects(&StoredObjects::Mono(storage), roots).await
+    }
+}
+
+/// The id of the tree of a monorepo directory, in the tree of the root ref.
+async fn find_tree(context: &Context, path: &Path) -> Result<String, MegaError> {
+    let storage = context.services.mega_storage.clone();
+    let not_found = || MegaError::with_message(&format!("path not found: {:?}", path));
+    let root = storage.get_ref("/").await?.ok_or_else(not_found)?;
+    let mut tree_id = root.ref_tree_hash;
+    for component in path.components() {
+        let Component::Normal(name) = component else {
+            continue;
+        };
+        let tree: Tree = storage
+            .get_tree_by_hash(&tree_id)
+            .await?
+            .ok_or_else(not_found)?
+            .into();
+        tree_id = tree
+            .tree_items
+            .iter()
+            .find(|item| item.name == name.to_string_lossy())
+            .ok_or_else(not_found)?
+            .id
+            .to_plain_str();
+    }
+    Ok(tree_id)
+}
+
+/// The rows of the objects of a repo, as the ids and stored bytes of the objects.
+enum StoredObjects {
+    Mono(Arc<MegaStorage>),
+    /// the blobs of import repos are in the raw blobs of the mega storage
+    Import(Arc<GitDbStorage>, Arc<MegaStorage>, Repo),
+}
+
+impl StoredObjects {
+    async fn commits(&self, ids: Vec<String>) -> Result<Vec<(String, Vec<u8>)>, MegaError> {
+        Ok(match self {
+            StoredObjects::Mono(storage) => storage
+                .get_commits_by_hashes(&ids)
+                .await?
+                .into_iter()
+                .map(|c: mega_commit::Model| {
+                    let data =
+                        commit_data(&c.tree, &c.parents_id, c.author, c.committer, c.content);
+                    (c.commit_id, data)
+                })
+                .collect(),
+            StoredObjects::Import(storage, _, repo) => storage
+                .get_commits_by_hashes(repo, &ids)
+                .await?
+                .into_iter()
+                .map(|c: git_commit::Model| {
+                    let data =
+                        commit_data(&c.tree, &c.parents_id, c.author, c.committer, c.content);
+                    (c.commit_id, data)
+                })
+                .collect(),
+        })
+    }
+
+    async fn trees(&self, ids: Vec<String>) -> Result<Vec<(String, Vec<u8>)>, MegaError> {
+        Ok(match self {
+            StoredObjects::Mono(storage) => storage
+                .get_trees_by_hashes(ids)
+                .await?
+                .into_iter()
+                .map(|t| (t.tree_id, t.sub_trees))
+                .collect(),
+            StoredObjects::Import(storage, _, repo) => storage
+                .get_trees_by_hashes(repo, ids)
+                .await?
+                .into_iter()
+                .map(|t| (t.tree_id, t.sub_trees))
+                .collect(),
+        })
+    }
+
+    async fn tags(&self, ids: Vec<String>) -> Result<Vec<(String, Vec<u8>)>, MegaError> {
+        Ok(match self {
+            StoredObjects::Mono(storage) => storage
+                .get_tags_by_hashes(ids)
+                .await?
+                .into_iter()
+                .map(|t: mega_tag::Model| {
+                    let data = tag_data(
+                        &t.object_id,
+                        &t.object_type,
+                        &t.tag_name,
+                        &t.tagger,
+                        &t.message,
+                    );
+                    (t.tag_id, data)
+                })
+                .collect(),
+            StoredObjects::Import(storage, _, repo) => storage
+                .get_tags_by_hashes(repo, ids)
+                .await?
+                .into_iter()
+                .map(|t: git_tag::Model| {
+                    let data = tag_data(
+                        &t.object_id,
+                        &t.object_type,
+                        &t.tag_name,
+                        &t.tagger,
+                        &t.message,
+                    );
+                    (t.tag_id, data)
+                })
+                .collect(),
+        })
+    }
+
+    /// The blobs, `None` for the ones whose content is in the raw storage.
+    async fn blobs(&self, ids: Vec<String>) -> Result<Vec<(String, Option<Vec<u8>>)>, MegaError> {
+        let storage = match self {
+            StoredObjects::Mono(storage) | StoredObjects::Import(_, storage, _) => storage,
+        };
+        Ok(storage
+            .get_raw_blobs_by_hashes(ids)
+            .await?
+            .into_iter()
+            .map(|b| (b.sha1, b.data))
+            .collect())
+    }
+}
+
+/// The bytes of a commit from the columns of its row, the signatures and the message are
+/// stored as they were in the object.
+fn commit_data(
+    tree: &str,
+    parents: &serde_json::Value,
+    author: Option<String>,
+    committer: Option<String>,
+    message: Option<String>,
+) -> Vec<u8> {
+    let mut data = format!("tree {}\n", tree);
+    for parent in parents.as_array().into_iter().flatten() {
+        data.push_str(&format!("parent {}\n", parent.as_str().unwrap_or_default()));
+    }
+    data.push_str(&format!(
+        "{}\n{}\n{}",
+        author.unwrap_or_default(),
+        committer.unwrap_or_default(),
+        message.unwrap_or_default()
+    ));
+    data.into_bytes()
+}
+
+/// The bytes of a tag from the columns of its row.
+fn tag_data(object: &str, object_type: &str, name: &str, tagger: &str, message: &str) -> Vec<u8> {
+    format!(
+        "object {}\ntype {}\ntag {}\n{}\n{}",
+        object, object_type, name, tagger, message
+    )
+    .into_bytes()
+}
+
+/// Read the objects from `roots`, the names and ids of the refs, and check them.
+async fn fsck_objects(
+    objects: &StoredObjects,
+    roots: Vec<(String, String)>,
+) -> Result<FsckReport, MegaError> {
+    let kind = roots
+        .first()
+        .and_then(|(_, id)| HashKind::from_size(id.len() / 2))
+        .unwrap_or_default();
+    let mut fsck = Fsck::new(kind);
+    let mut visited = HashSet::new();
+    // a ref may point to a commit or a tag, and to a tree for a directory of the monorepo
+    let mut unknown = vec![];
+    for (name, id) in roots {
+        let hash = parse_id(&id)?;
+        fsck.add_root(&name, hash);
+        if visited.insert(hash) {
+            unknown.push(id);
+        }
+    }
+
+    let (mut commits, mut trees, mut blobs, mut tags) = (vec![], vec![], vec![], vec![]);
+    commits.extend(unknown.iter().cloned());
+    trees.extend(unknown.iter().cloned());
+    tags.extend(unknown);
+    while !(commits.is_empty() && trees.is_empty() && blobs.is_empty() && tags.is_empty()) {
+        let mut links = vec![];
+        for chunk in std::mem::take(&mut commits).chunks(BATCH_SIZE) {
+            for (id, data) in objects.commits(chunk.to_vec()).await? {
+                links.extend(fsck.check(parse_id(&id)?, ObjectType::Commit, &data));
+            }
+        }
+        for chunk in std::mem::take(&mut trees).chunks(BATCH_SIZE) {
+            for (id, data) in objects.trees(chunk.to_vec()).await? {
+                links.extend(fsck.check(parse_id(&id)?, ObjectType::Tree, &data));
+            }
+        }
+        for chunk in std::mem::take(&mut tags).chunks(BATCH_SIZE) {
+            for (id, data) in objects.tags(chunk.to_vec()).await? {
+                links.extend(fsck.check(parse_id(&id)?, ObjectType::Tag, &data));
+            }
+        }
+        for chunk in std::mem::take(&mut blobs).chunks(BATCH_SIZE) {
+            for (id, data) in objects.blobs(chunk.to_vec()).await? {
+                let id = parse_id(&id)?;
+                match data {
+                    Some(data) => {
+                        fsck.check(id, ObjectType::Blob, &data);
+                    }
+                    // the content is in the raw storage
+                    None => fsck.found(id, ObjectType::Blob),
+                }
+            }
+        }
+
+        for (id, obj_type) in links {
+            if !visited.insert(id) {
+                continue;
+            }
+            let id = id.to_plain_str();
+            match obj_type {
+                ObjectType::Commit => commits.push(id),
+                ObjectType::Tree => trees.push(id),
+                ObjectType::Blob => blobs.push(id),
+                ObjectType::Tag => tags.push(id),
+                _ => {}
+            }
+        }
+    }
+    Ok(fsck.finish())
+}
+
+fn parse_id(id: &str) -> Result<SHA1, MegaError> {
+    SHA1::from_str(id).map_err(|e| MegaError::with_message(&e))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use callisto::{git_commit, git_tag};
+    use mercury::hash::SHA1;
+    use mercury::internal::object::{
+        commit::Commit, signature::Signature, tag::Tag, types::ObjectType, ObjectTrait,
+    };
+
+    use super::{commit_data, tag_data};
+
+    #[test]
+    fn test_stored_data() {
+        let tree = SHA1::from_str("4b825dc642cb6eb9a060e54bf8d69288fbee4904").unwrap();
+        let parent = SHA1::from_str("5bb8ee25bac1014c15abc49c56d1ee0aab1050cb").unwrap();
+        let commit = Commit::from_tree_id(tree, vec![parent], "\nfix: something");
+        let row: git_commit::Model = commit.clone().into();
+        let data = commit_data(
+            &row.tree,
+            &row.parents_id,
+            row.author,
+            row.committer,
+            row.content,
+        );
+        assert_eq!(data, commit.to_data().unwrap());
+
+        let tag = Tag {
+            id: SHA1::default(),
+            object_hash: commit.id,
+            object_type: ObjectType::Commit,
+            tag_name: String::from("v1.0"),
+            tagger: Signature::from_data(b"tagger mega <admin@mega.com> 1728000000 +0800".to_vec())
+                .unwrap(),
+            message: String::from("\nrelease"),
+        };
+        let row: git_tag::Model = tag.clone().into();
+        let data = tag_data(
+            &row.object_id,
+            &row.object_type,
+            &row.tag_name,
+            &row.tagger,
+            &row.message,
+        );
+        assert_eq!(data, tag.to_data().unwrap());
+    }
+}
@@ -1,37 +1,55 @@
 use std::{
-    collections::HashSet,
+    collections::{HashMap, HashSet},
+    path::Path,
     pin::Pin,
-    sync::{
-        atomic::{AtomicUsize, Ordering},
-        mpsc::Receiver,
-    },
+    sync::{mpsc::Receiver, Arc},
 };
 
 use async_trait::async_trait;
 use bytes::Bytes;
 use futures::Stream;
-use tokio_stream::wrappers::ReceiverStream;
+use tokio::task::JoinHandle;
 
 use callisto::raw_blob;
-use common::{config::PackConfig, errors::MegaError, utils::ZERO_ID};
-use mercury::internal::pack::Pack;
+use common::{
+    config::PackConfig,
+    errors::MegaError,
+    utils::{is_zero_id, ZERO_ID},
+};
+use jupiter::storage::commit_graph_storage::CommitGraphStorage;
+use mercury::internal::pack::{ObjectLookup, Pack};
 use mercury::{
     errors::GitError,
+    hash::{HashKind, SHA1},
     internal::{
         object::{
             blob::Blob,
+            commit::Commit,
             tree::{Tree, TreeItemMode},
+            types::ObjectType,
+        },
+        pack::{
+            encode::{DeltaStore, EncodeEntry, PackEncoder},
+            entry::Entry,
         },
-        pack::entry::Entry,
+        tree_diff::{diff_trees, DiffOptions, TreeStore},
     },
 };
 use venus::import_repo::import_refs::{RefCommand, Refs};
 
+use crate::pack::{commit_graph::CommitGraph, connectivity::ReceivedObjects};
+
+/// The pack sent to a client in chunks, a failure while producing it ends the stream with
+/// the error.
+pub type PackDataStream = Pin<Box<dyn Stream<Item = Result<Vec<u8>, GitError>> + Send>>;
+
 #[async_trait]
 pub trait PackHandler: Send + Sync {
     async fn head_hash(&self) -> (String, Vec<Refs>);
 
-    async fn handle_receiver(&self, rx: Receiver<Entry>) -> Result<(), GitError>;
+    /// Save the received objects into storage, every entry is re-hashed and recorded
+    /// so the refs can be checked by [`PackHandler::check_connectivity`] later.
+    async fn handle_receiver(&self, rx: Receiver<Entry>) -> Result<ReceivedObjects, GitError>;
 
     /// Asynchronously retrieves the full pack data for the specified repository path.
     /// This function collects commits and nodes from the storage and packs them into
@@ -39,15 +57,31 @@ pub trait PackHandler: Send + Sync {
     /// only sends all the data related to this repository.
     ///
     /// # Returns
-    /// * `Result<Vec<u8>, GitError>` - The packed binary data as a vector of bytes.
+    /// * `Result<PackDataStream, GitError>` - The packed binary data in chunks.
     ///
-    async fn full_pack(&self) -> Result<ReceiverStream<Vec<u8>>, GitError>;
+    async fn full_pack(&self) -> Result<PackDataStream, GitError>;
+
+    /// Whether the pack file of a push should be kept, see [`PackHandler::save_pack`].
+    fn keep_pack(&self) -> bool {
+        false
+    }
+
+    /// Keep the pack file of a push after its objects are saved by
+    /// [`PackHandler::handle_receiver`], `hash_kind` is the object format of the pack.
+    async fn save_pack(&self, _pack_file: &Path, _hash_kind: HashKind) -> Result<(), GitError> {
+        Ok(())
+    }
 
+    /// Pack the objects reachable from `want` but not from `have`. With `thin`, the client
+    /// asked for a thin pack, so objects of `have` are used as delta bases without being sent.
     async fn incremental_pack(
         &self,
         want: Vec<String>,
         have: Vec<String>,
-    ) -> Result<ReceiverStream<Vec<u8>>, GitError>;
+        thin: bool,
+    ) -> Result<PackDataStream, GitError>;
+
+    async fn get_commits_by_hashes(&self, hashes: Vec<String>) -> Result<Vec<Commit>, MegaError>;
 
     async fn get_trees_by_hashes(&self, hashes: Vec<String>) -> Result<Vec<Tree>, MegaError>;
 
@@ -60,8 +94,171 @@ pub trait PackHandler: Send + Sync {
 
     async fn check_commit_exist(&self, hash: &str) -> bool;
 
+    /// Return the hashes among `hashes` which are not stored as blobs.
+    async fn find_missing_blobs(&self, hashes: Vec<String>) -> Result<Vec<String>, MegaError>;
+
     async fn check_default_branch(&self) -> bool;
 
+    /// The storage of the commit graph, and the id of the graph of this repo in it.
+    fn commit_graph_storage(&self) -> (Arc<CommitGraphStorage>, i64);
+
+    /// Load the commit graph with `commit_ids`, and the ancestors which had to be added to it.
+    /// Commits which aren't stored are ignored.
+    ///
+    /// Commits missing from the graph, like the commits of repos created before it, are read
+    /// from storage one generation at a time and added to it.
+    async fn load_commit_graph(&self, commit_ids: Vec<String>) -> Result<CommitGraph, MegaError> {
+        let (storage, graph_id) = self.commit_graph_storage();
+        let mut graph: CommitGraph = storage
+            .get_commit_graph_nodes(graph_id, commit_ids.clone())
+            .await?
+            .into();
+
+        // commits out of the graph to their parents
+        let mut new_commits = HashMap::new();
+        let mut frontier: Vec<String> = commit_ids
+            .into_iter()
+            .filter(|id| !graph.contains(id))
+            .collect();
+        while !frontier.is_empty() {
+            let mut parents = HashSet::new();
+            for commit in self.get_commits_by_hashes(frontier).await? {
+                let ids: Vec<String> = commit
+                    .parent_commit_ids
+                    .iter()
+                    .map(|p| p.to_plain_str())
+                    .collect();
+                parents.extend(ids.iter().cloned());
+                new_commits.insert(commit.id.to_plain_str(), ids);
+            }
+            parents.retain(|id| !graph.contains(id) && !new_commits.contains_key(id));
+            if !parents.is_empty() {
+                let nodes = storage
+                    .get_commit_graph_nodes(graph_id, parents.iter().cloned().collect())
+                    .await?;
+                graph.extend(nodes);
+                parents.retain(|id| !graph.contains(id));
+            }
+            frontier = parents.into_iter().collect();
+        }
+
+        let added: Vec<_> = graph
+            .add_commits(new_commits)
+            .into_iter()
+            .map(|(id, node)| (id, node.generation, node.parents))
+            .collect();
+        if !added.is_empty() {
+            storage.save_commit_graph(graph_id, added).await?;
+        }
+        Ok(graph)
+    }
+
+    /// Load the part of the commit graph a fetch of `want` with `have` walks, with the commits
+    /// reachable from `want` but not from `have`, newest first.
+    ///
+    /// The generations between the lowest and the highest of `want` and `have` are loaded
+    /// first, then twice as many below them each time the walk goes past what is loaded.
+    async fn fetch_graph(
+        &self,
+        want: &[String],
+        have: &[String],
+    ) -> Result<(CommitGraph, Vec<String>), MegaError> {
+        let (storage, graph_id) = self.commit_graph_storage();
+        let mut graph = self.load_commit_graph([want, have].concat()).await?;
+        let generations: Vec<i64> = want
+            .iter()
+            .chain(have)
+            .filter_map(|id| graph.get(id).map(|node| node.generation))
+            .collect();
+        let (Some(&low), Some(&high)) = (generations.iter().min(), generations.iter().max()) else {
+            return Ok((graph, vec![]));
+        };
+        let (mut low, mut high) = (low, high);
+        loop {
+            graph.extend(
+                storage
+                    .get_commit_graph_generations(graph_id, low, high)
+                    .await?,
+            );
+            if let Some(missing) = graph.missing(want, have) {
+                return Ok((graph, missing));
+            }
+            if low <= 1 {
+                return Err(MegaError::with_message(
+                    "the commit graph misses the parents of some commits",
+                ));
+            }
+            let span = high - low + 1;
+            high = low - 1;
+            low = (low - 2 * span).max(1);
+        }
+    }
+
+    /// Index a push once its refs are updated to `tips`: add the received commits to the
+    /// commit graph, so fetches don't have to read them one by one.
+    async fn index_push(
+        &self,
+        received: &ReceivedObjects,
+        _tips: Vec<String>,
+    ) -> Result<(), MegaError> {
+        self.load_commit_graph(received.commits.keys().cloned().collect())
+            .await?;
+        Ok(())
+    }
+
+    /// Find a stored blob, tree or commit by hash.
+    async fn get_object(&self, hash: &str) -> Result<Option<Entry>, MegaError> {
+        let hashes = vec![hash.to_owned()];
+        if let Some(blob) = self.get_blobs_by_hashes(hashes.clone()).await?.pop() {
+            return Ok(Some(Blob::from(blob).into()));
+        }
+        if let Some(tree) = self.get_trees_by_hashes(hashes.clone()).await?.pop() {
+            return Ok(Some(tree.into()));
+        }
+        Ok(self
+            .get_commits_by_hashes(hashes)
+            .await?
+            .pop()
+            .map(|commit| commit.into()))
+    }
+
+    /// The object format of the repo, known from the length of its head id.
+    /// A repo without refs uses SHA-1, the default object format, the protocol advertises
+    /// the `monorepo.object_format` config for them instead.
+    async fn hash_kind(&self) -> HashKind {
+        let (head_hash, _) = self.head_hash().await;
+        match HashKind::from_size(head_hash.len() / 2) {
+            Some(kind) if !is_zero_id(&head_hash) => kind,
+            _ => HashKind::default(),
+        }
+    }
+
+    /// A pack encoder with the delta and memory settings of `pack_config`, writing the pack
+    /// in the object format of the repo.
+    async fn pack_encoder(
+        &self,
+        pack_config: &PackConfig,
+        sender: tokio::sync::mpsc::Sender<Vec<u8>>,
+    ) -> PackEncoder {
+        let encoder = PackEncoder::new(pack_config.delta_window, sender)
+            .with_max_depth(pack_config.delta_depth)
+            .with_spill(
+                1024 * 1024 * 1024 * pack_config.pack_encode_mem_size,
+                pack_config.pack_encode_cache_path.clone(),
+            )
+            .with_hash_kind(self.hash_kind().await);
+        match self.delta_store().await {
+            Some(store) => encoder.with_delta_store(store),
+            None => encoder,
+        }
+    }
+
+    /// Where the encoder of [`PackHandler::pack_encoder`] finds deltas to send again,
+    /// `None` when no delta is stored.
+    async fn delta_store(&self) -> Option<Arc<dyn DeltaStore>> {
+        None
+    }
+
     fn find_head_hash(&self, refs: Vec<Refs>) -> (String, Vec<Refs>) {
         let mut head_hash = ZERO_ID.to_string();
         for git_ref in refs.iter() {
@@ -72,50 +269,150 @@ pub trait PackHandler: Send + Sync {
         (head_hash, refs)
     }
 
+    /// Check that every object reachable from `new_id` was either received in this push or is
+    /// already in storage, so that a ref never points to an incomplete history.
+    ///
+    /// Objects found in storage were checked when they were received, so the walk stops there.
+    async fn check_connectivity(
+        &self,
+        new_id: &str,
+        received: &ReceivedObjects,
+    ) -> Result<(), GitError> {
+        let to_git_err = |e: MegaError| GitError::CustomError(e.to_string());
+        let mut visited = HashSet::new();
+
+        let mut commit_ids = vec![new_id.to_owned()];
+        let mut tree_ids = vec![];
+        while let Some(id) = commit_ids.pop() {
+            if !visited.insert(id.clone()) {
+                continue;
+            }
+            match received.commits.get(&id) {
+                Some((tree_id, parents)) => {
+                    tree_ids.push(tree_id.clone());
+                    commit_ids.extend(parents.iter().cloned());
+                }
+                None => {
+                    if !self.check_commit_exist(&id).await {
+                        return Err(GitError::ObjectNotFound(id));
+                    }
+                }
+            }
+        }
+
+        let mut stored_trees = vec![];
+        let mut blob_ids = HashSet::new();
+        while let Some(id) = tree_ids.pop() {
+            if !visited.insert(id.clone()) {
+                continue;
+            }
+            match received.trees.get(&id) {
+                Some(items) => {
+                    for (item_id, obj_type) in items {
+                        if *obj_type == ObjectType::Tree {
+                            tree_ids.push(item_id.clone());
+                        } else if !received.blobs.contains(item_id) {
+                            blob_ids.insert(item_id.clone());
+                        }
+                    }
+                }
+                None => stored_trees.push(id),
+            }
+        }
+        for chunk in stored_trees.chunks(1000) {
+            let found: HashSet<String> = self
+                .get_trees_by_hashes(chunk.to_vec())
+                .await
+                .map_err(to_git_err)?
+                .into_iter()
+                .map(|t| t.id.to_plain_str())
+                .collect();
+            if let Some(missing) = chunk.iter().find(|id| !found.contains(*id)) {
+                return Err(GitError::ObjectNotFound(missing.clone()));
+            }
+        }
+        let blob_ids: Vec<String> = blob_ids.into_iter().collect();
+        for chunk in blob_ids.chunks(1000) {
+            let missing = self
+                .find_missing_blobs(chunk.to_vec())
+                .await
+                .map_err(to_git_err)?;
+            if let Some(missing) = missing.into_iter().next() {
+                return Err(GitError::ObjectNotFound(missing));
+            }
+        }
+        Ok(())
+    }
+
+    /// Collect the paths of all files which differ between the trees of two commits.
+    ///
+    /// Paths are relative to the repository root and use `/` as separator. A missing commit
+    /// (e.g. the zero id of a newly created ref) is treated as an empty tree.
+    async fn changed_paths(&self, old_id: &str, new_id: &str) -> Result<Vec<String>, MegaError> {
+        let commits = self
+            .get_commits_by_hashes(vec![old_id.to_owned(), new_id.to_owned()])
+            .await?;
+        let mut roots = vec![];
+        for id in [old_id, new_id] {
+            let commit = commits.iter().find(|c| c.id.to_plain_str() == id);
+            roots.push(commit.map(|c| c.tree_id));
+        }
+        let new_tree = roots.pop().unwrap();
+        let old_tree = roots.pop().unwrap();
+
+        let changes = diff_trees(
+            &HandlerStore(self),
+            old_tree,
+            new_tree,
+            &DiffOptions::plain(),
+        )
+        .await
+        .map_err(|e| MegaError::with_message(&e.to_string()))?;
+        Ok(changes
+            .into_iter()
+            .map(|change| change.path().to_owned())
+            .collect())
+    }
+
+    /// Decode a received pack, the bases of a thin pack are found by `base_lookup`,
+    /// see [`storage_lookup`].
+    ///
+    /// Decoded entries are sent through a channel of `channel_message_size` entries, and the
+    /// stream is read only as fast as they are received. The returned task ends with the
+    /// error of the decoding, like a broken stream or a corrupted pack.
     async fn unpack_stream(
         &self,
         pack_config: &PackConfig,
         stream: Pin<Box<dyn Stream<Item = Result<Bytes, axum::Error>> + Send>>,
-    ) -> Result<Receiver<Entry>, GitError> {
-        let (sender, receiver) = std::sync::mpsc::channel();
+        base_lookup: ObjectLookup,
+        hash_kind: HashKind,
+    ) -> Result<(Receiver<Entry>, JoinHandle<Result<(), GitError>>), GitError> {
+        let (sender, receiver) = std::sync::mpsc::sync_channel(pack_config.channel_message_size);
+        let cache_path = pack_config.pack_decode_cache_path.clone();
         let p = Pack::new(
             None,
             Some(1024 * 1024 * 1024 * pack_config.pack_decode_mem_size),
-            Some(pack_config.pack_decode_cache_path.clone()),
+            Some(cache_path.clone()),
             pack_config.clean_cache_after_decode,
-        );
-        tokio::spawn(async move {
-            p.decode_stream(stream, sender).await;
-        });
-        Ok(receiver)
-    }
-
-    async fn traverse_for_count(
-        &self,
-        tree: Tree,
-        exist_objs: &HashSet<String>,
-        counted_obj: &mut HashSet<String>,
-        obj_num: &AtomicUsize,
-    ) {
-        let mut search_tree_ids = vec![];
-        let mut search_blob_ids = vec![];
-        for item in &tree.tree_items {
-            let hash = item.id.to_plain_str();
-            if !exist_objs.contains(&hash) && counted_obj.insert(hash.clone()) {
-                if item.mode == TreeItemMode::Tree {
-                    search_tree_ids.push(hash.clone())
-                } else {
-                    search_blob_ids.push(hash.clone());
-                }
+        )
+        .with_base_lookup(base_lookup)
+        .with_hash_kind(hash_kind);
+        let decoding = tokio::spawn(async move {
+            let p = p.decode_stream(stream, sender).await?;
+            let spill = p.spill_metrics();
+            if spill.spilled_objects > 0 {
+                tracing::info!(
+                    "unpack of {} objects spilled {} objects ({} bytes) to {:?}, {} read back",
+                    p.number,
+                    spill.spilled_objects,
+                    spill.spilled_bytes,
+                    cache_path,
+                    spill.loaded_objects
+                );
             }
-        }
-        obj_num.fetch_add(search_blob_ids.len(), Ordering::SeqCst);
-        let trees = self.get_trees_by_hashes(search_tree_ids).await.unwrap();
-        for t in trees {
-            self.traverse_for_count(t, exist_objs, counted_obj, obj_num)
-                .await;
-        }
-        obj_num.fetch_add(1, Ordering::SeqCst);
+            Ok(())
+        });
+        Ok((receiver, decoding))
     }
 
     /// Traverse a tree structure asynchronously.
@@ -140,10 +437,11 @@ pub trait PackHandler: Send + Sync {
         &self,
         tree: Tree,
         exist_objs: &mut HashSet<String>,
-        sender: Option<&tokio::sync::mpsc::Sender<Entry>>,
+        sender: Option<&tokio::sync::mpsc::Sender<EncodeEntry>>,
     ) {
         let mut search_tree_ids = vec![];
         let mut search_blob_ids = vec![];
+        let mut blob_names = HashMap::new();
 
         for item in &tree.tree_items {
             let hash = item.id.to_plain_str();
@@ -151,6 +449,7 @@ pub trait PackHandler: Send + Sync {
                 if item.mode == TreeItemMode::Tree {
                     search_tree_ids.push(hash);
                 } else {
+                    blob_names.insert(hash.clone(), item.name.as_str());
                     search_blob_ids.push(hash);
                 }
             }
@@ -159,8 +458,12 @@ pub trait PackHandler: Send + Sync {
         if let Some(sender) = sender {
             let blobs = self.get_blobs_by_hashes(search_blob_ids).await.unwrap();
             for b in blobs {
+                let name = blob_names.get(&b.sha1).copied().unwrap_or_default();
                 let blob: Blob = b.into();
-                sender.send(blob.into()).await.unwrap();
+                sender
+                    .send(EncodeEntry::with_name(blob.into(), name))
+                    .await
+                    .unwrap();
             }
         }
 
@@ -173,4 +476,194 @@ pub trait PackHandler: Send + Sync {
             sender.send(tree.into()).await.unwrap();
         }
     }
+
+    /// Send the delta bases of a thin pack to the encoder: the objects of the `have` trees with
+    /// the same name as a new object of the `want` trees. The client has them, so deltas against
+    /// them are sent without the bases.
+    ///
+    /// `exist_objs` are the objects reachable from `have`.
+    async fn send_thin_bases(
+        &self,
+        have_trees: Vec<Tree>,
+        want_trees: Vec<Tree>,
+        exist_objs: &HashSet<String>,
+        sender: &tokio::sync::mpsc::Sender<EncodeEntry>,
+    ) -> Result<(), MegaError> {
+        // name of every item of the have trees to (hash, is tree), root trees have an empty name
+        let mut have_items: HashMap<String, (String, bool)> = HashMap::new();
+        for tree in &have_trees {
+            have_items.insert(String::new(), (tree.id.to_plain_str(), true));
+        }
+        let mut visited = HashSet::new();
+        let mut trees = have_trees;
+        while !trees.is_empty() {
+            let mut sub_trees = vec![];
+            for tree in trees {
+                for item in tree.tree_items {
+                    let hash = item.id.to_plain_str();
+                    let is_tree = item.mode == TreeItemMode::Tree;
+                    if is_tree && visited.insert(hash.clone()) {
+                        sub_trees.push(hash.clone());
+                    }
+                    have_items.insert(item.name, (hash, is_tree));
+                }
+            }
+            trees = self.get_trees_by_hashes(sub_trees).await?;
+        }
+
+        // bases by hash, to their name
+        let mut blob_bases = HashMap::new();
+        let mut tree_bases = HashMap::new();
+        let mut add_base = |name: &str, hash: &str, is_tree: bool| {
+            if let Some((base, base_is_tree)) = have_items.get(name) {
+                if base != hash && *base_is_tree == is_tree {
+                    let bases = if is_tree {
+                        &mut tree_bases
+                    } else {
+                        &mut blob_bases
+                    };
+                    bases.insert(base.clone(), name.to_owned());
+                }
+            }
+        };
+        let mut trees = vec![];
+        for tree in want_trees {
+            if !exist_objs.contains(&tree.id.to_plain_str()) {
+                add_base("", &tree.id.to_plain_str(), true);
+                trees.push(tree);
+            }
+        }
+        let mut visited = HashSet::new();
+        while !trees.is_empty() {
+            let mut sub_trees = vec![];
+            for tree in trees {
+                for item in tree.tree_items {
+                    let hash = item.id.to_plain_str();
+                    if exist_objs.contains(&hash) || !visited.insert(hash.clone()) {
+                        continue;
+                    }
+                    let is_tree = item.mode == TreeItemMode::Tree;
+                    add_base(&item.name, &hash, is_tree);
+                    if is_tree {
+                        sub_trees.push(hash);
+                    }
+                }
+            }
+            trees = self.get_trees_by_hashes(sub_trees).await?;
+        }
+
+        let blobs = self
+            .get_blobs_by_hashes(blob_bases.keys().cloned().collect())
+            .await?;
+        for b in blobs {
+            let name = blob_bases.get(&b.sha1).cloned().unwrap_or_default();
+            let blob: Blob = b.into();
+            let _ = sender
+                .send(EncodeEntry::thin_base(blob.into(), &name))
+                .await;
+        }
+        let trees = self
+            .get_trees_by_hashes(tree_bases.keys().cloned().collect())
+            .await?;
+        for tree in trees {
+            let name = tree_bases
+                .get(&tree.id.to_plain_str())
+                .cloned()
+                .unwrap_or_default();
+            let _ = sender
+                .send(EncodeEntry::thin_base(tree.into(), &name))
+                .await;
+        }
+        Ok(())
+    }
+}
+
+/// An [`ObjectLookup`] which reads the bases of thin packs from the storage of `handler`.
+///
+/// The lookup blocks on the current runtime, so it must be called outside of async code,
+/// like the decoding of packs.
+pub fn storage_lookup(handler: Arc<dyn PackHandler>) -> ObjectLookup {
+    let runtime = tokio::runtime::Handle::current();
+    Arc::new(move |hash| {
+        runtime
+            .block_on(handler.get_object(&hash.to_plain_str()))
+            .unwrap_or_else(|err| {
+                tracing::error!("failed to look up the delta base {}: {}", hash, err);
+                None
+            })
+    })
+}
+
+/// A [`TreeStore`] reading the trees and blobs of a diff from the storage of a handler.
+pub struct HandlerStore<'a, H: PackHandler + ?Sized>(pub &'a H);
+
+#[async_trait]
+impl<H: PackHandler + ?Sized> TreeStore for HandlerStore<'_, H> {
+    async fn load_tree(&self, id: &SHA1) -> Result<Tree, GitError> {
+        self.0
+            .get_trees_by_hashes(vec![id.to_plain_str()])
+            .await
+            .map_err(|e| GitError::CustomError(e.to_string()))?
+            .pop()
+            .ok_or(GitError::ObjectNotFound(id.to_plain_str()))
+    }
+
+    async fn load_blob(&self, id: &SHA1) -> Result<Vec<u8>, GitError> {
+        let blob = self
+            .0
+            .get_blobs_by_hashes(vec![id.to_plain_str()])
+            .await
+            .map_err(|e| GitError::CustomError(e.to_string()))?
+            .pop()
+            .ok_or(GitError::ObjectNotFound(id.to_plain_str()))?;
+        Ok(Blob::from(blob).data)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::path::PathBuf;
+
+    use common::config::Config;
+    use jupiter::context::Context;
+
+    use super::PackHandler;
+    use crate::pack::monorepo::MonoRepo;
+
+    #[tokio::test]
+    async fn test_fetch_graph() {
+        let dir = std::env::temp_dir().join(format!("mega-graph-{:016x}", rand::random::<u64>()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let mut config = Config::default();
+        config.database.db_path = dir.join("mega.db").to_str().unwrap().to_owned();
+        let repo = MonoRepo {
+            context: Context::new(config).await,
+            path: PathBuf::from("/"),
+            from_hash: None,
+            to_hash: None,
+        };
+        // c1 - c2 - ... - c20
+        let id = |i: i64| format!("c{}", i);
+        let nodes = (1..=20)
+            .map(|i| {
+                let parents = if i == 1 { vec![] } else { vec![id(i - 1)] };
+                (id(i), i, parents)
+            })
+            .collect();
+        let (storage, graph_id) = repo.commit_graph_storage();
+        storage.save_commit_graph(graph_id, nodes).await.unwrap();
+
+        let (graph, missing) = repo.fetch_graph(&[id(20)], &[id(18)]).await.unwrap();
+        assert_eq!(missing, [id(20), id(19)]);
+        // only the generations the walk needs are loaded
+        assert!(!graph.contains(&id(17)));
+
+        let (_, missing) = repo.fetch_graph(&[id(3)], &[id(20)]).await.unwrap();
+        assert!(missing.is_empty());
+        // the walk goes past the loaded generations down to the root
+        let (graph, missing) = repo.fetch_graph(&[id(20)], &[]).await.unwrap();
+        assert_eq!(missing.len(), 20);
+        assert!(graph.contains(&id(1)));
+        let _ = std::fs::remove_dir_all(&dir);
+    }
 }
@@ -1,11 +1,8 @@
 use std::{
     collections::{HashMap, HashSet},
-    path::PathBuf,
+    path::{Path, PathBuf},
     str::FromStr,
-    sync::{
-        atomic::{AtomicUsize, Ordering},
-        mpsc::Receiver,
-    },
+    sync::{mpsc::Receiver, Arc},
 };
 
 use async_trait::async_trait;
@@ -17,7 +14,7 @@ use callisto::{mega_tree, raw_blob};
 use common::errors::MegaError;
 use jupiter::{
     context::Context,
-    storage::{batch_save_model, GitStorageProvider},
+    storage::{batch_save_model, commit_graph_storage::CommitGraphStorage, GitStorageProvider},
 };
 use mercury::{
     errors::GitError,
@@ -26,7 +23,10 @@ use mercury::{
         pack::entry::Entry,
     },
 };
-use mercury::{hash::SHA1, internal::pack::encode::PackEncoder};
+use mercury::{
+    hash::{HashKind, SHA1},
+    internal::pack::encode::{DeltaStore, EncodeEntry},
+};
 use venus::import_repo::{
     import_refs::{CommandType, RefCommand, Refs},
     repo::Repo,
@@ -34,7 +34,10 @@ use venus::import_repo::{
 
 use crate::{
     api_service::{mono_api_service::MonoApiService, ApiHandler},
-    pack::handler::PackHandler,
+    pack::{
+        connectivity::ReceivedObjects,
+        handler::{PackDataStream, PackHandler},
+    },
 };
 
 pub struct ImportRepo {
@@ -56,152 +59,99 @@ impl PackHandler for ImportRepo {
         self.find_head_hash(refs)
     }
 
-    async fn handle_receiver(&self, receiver: Receiver<Entry>) -> Result<(), GitError> {
+    async fn handle_receiver(
+        &self,
+        receiver: Receiver<Entry>,
+    ) -> Result<ReceivedObjects, GitError> {
         self.create_monorepo_parent().await.unwrap();
         let storage = self.context.services.git_db_storage.clone();
+        let mut received = ReceivedObjects::default();
         let mut entry_list = vec![];
         let mut join_tasks = vec![];
         for entry in receiver {
+            received.record(&entry)?;
             entry_list.push(entry);
             if entry_list.len() >= 1000 {
                 let stg_clone = storage.clone();
                 let repo_clone = self.repo.clone();
-                let handle = tokio::spawn(async move {
-                    stg_clone.save_entry(&repo_clone, entry_list).await.unwrap();
-                });
+                let handle =
+                    tokio::spawn(
+                        async move { stg_clone.save_entry(&repo_clone, entry_list).await },
+                    );
                 join_tasks.push(handle);
                 entry_list = vec![];
             }
         }
-        join_all(join_tasks).await;
-        storage.save_entry(&self.repo, entry_list).await.unwrap();
-        Ok(())
+        for res in join_all(join_tasks).await {
+            res.map_err(|e| GitError::CustomError(e.to_string()))?
+                .map_err(|e| GitError::CustomError(e.to_string()))?;
+        }
+        storage
+            .save_entry(&self.repo, entry_list)
+            .await
+            .map_err(|e| GitError::CustomError(e.to_string()))?;
+        Ok(received)
     }
 
-    async fn full_pack(&self) -> Result<ReceiverStream<Vec<u8>>, GitError> {
-        let pack_config = &self.context.config.pack;
-        let (entry_tx, entry_rx) = mpsc::channel(pack_config.channel_message_size);
-        let (stream_tx, stream_rx) = mpsc::channel(pack_config.channel_message_size);
-
-        let storage = self.context.services.git_db_storage.clone();
-        let total = storage.get_obj_count_by_repo_id(&self.repo).await;
-        let encoder = PackEncoder::new(total, 0, stream_tx);
-        encoder.encode_async(entry_rx).await.unwrap();
-
-        let repo = self.repo.clone();
-        tokio::spawn(async move {
-            let mut commit_stream = storage.get_commits_by_repo_id(&repo).await.unwrap();
-
-            while let Some(model) = commit_stream.next().await {
-                match model {
-                    Ok(m) => {
-                        let c: Commit = m.into();
-                        let entry = c.into();
-                        entry_tx.send(entry).await.unwrap();
-                    }
-                    Err(err) => eprintln!("Error: {:?}", err),
-                }
-            }
-            tracing::info!("send commits end");
-
-            let mut tree_stream = storage.get_trees_by_repo_id(&repo).await.unwrap();
-            while let Some(model) = tree_stream.next().await {
-                match model {
-                    Ok(m) => {
-                        let t: Tree = m.into();
-                        let entry = t.into();
-                        entry_tx.send(entry).await.unwrap();
-                    }
-                    Err(err) => eprintln!("Error: {:?}", err),
-                }
-            }
-            tracing::info!("send trees end");
-
-            let mut bid_stream = storage.get_blobs_by_repo_id(&repo).await.unwrap();
-            let mut bids = vec![];
-            while let Some(model) = bid_stream.next().await {
-                match model {
-                    Ok(m) => bids.push(m.blob_id),
-                    Err(err) => eprintln!("Error: {:?}", err),
-                }
+    async fn full_pack(&self) -> Result<PackDataStream, GitError> {
+        if self.keep_pack() {
+            match self.covering_packs().await {
+                Ok(Some(packs)) => return Ok(self.stream_stored_packs(packs)),
+                Ok(None) => self.spawn_repack(),
+                Err(err) => tracing::warn!("failed to check the stored packs: {}", err),
             }
+        }
+        self.encode_full_pack().await
+    }
 
-            let mut blob_handler = vec![];
-            for chunk in bids.chunks(10000) {
-                let stg_clone = storage.clone();
-                let sender_clone = entry_tx.clone();
-                let chunk_clone = chunk.to_vec();
-                let handler = tokio::spawn(async move {
-                    let mut blob_stream = stg_clone.get_raw_blobs(chunk_clone).await.unwrap();
-                    while let Some(model) = blob_stream.next().await {
-                        match model {
-                            Ok(m) => {
-                                // todo handle storage type
-                                let b: Blob = m.into();
-                                let entry: Entry = b.into();
-                                sender_clone.send(entry).await.unwrap();
-                            }
-                            Err(err) => eprintln!("Error: {:?}", err),
-                        }
-                    }
-                });
-                blob_handler.push(handler);
-            }
-            join_all(blob_handler).await;
-            tracing::info!("send blobs end");
+    fn keep_pack(&self) -> bool {
+        self.context.config.pack.store_import_packs
+    }
 
-            let tags = storage.get_tags_by_repo_id(&repo).await.unwrap();
-            for m in tags.into_iter() {
-                let c: Tag = m.into();
-                let entry: Entry = c.into();
-                entry_tx.send(entry).await.unwrap();
+    async fn delta_store(&self) -> Option<Arc<dyn DeltaStore>> {
+        match self.stored_pack_deltas().await {
+            Ok(deltas) => deltas.map(|d| Arc::new(d) as Arc<dyn DeltaStore>),
+            Err(err) => {
+                tracing::warn!("failed to load the stored deltas: {}", err);
+                None
             }
-            drop(entry_tx);
-            tracing::info!("sending all object end...");
-        });
+        }
+    }
 
-        Ok(ReceiverStream::new(stream_rx))
+    async fn save_pack(&self, pack_file: &Path, hash_kind: HashKind) -> Result<(), GitError> {
+        self.store_pack(pack_file, hash_kind).await?;
+        let packs = self
+            .context
+            .services
+            .git_db_storage
+            .get_git_packs(&self.repo)
+            .await
+            .map_err(|e| GitError::CustomError(e.to_string()))?;
+        if packs.len() >= self.context.config.pack.repack_threshold {
+            self.spawn_repack();
+        }
+        Ok(())
     }
 
     async fn incremental_pack(
         &self,
         want: Vec<String>,
         have: Vec<String>,
-    ) -> Result<ReceiverStream<Vec<u8>>, GitError> {
-        let mut want_clone = want.clone();
+        thin: bool,
+    ) -> Result<PackDataStream, GitError> {
         let pack_config = &self.context.config.pack;
         let storage = self.context.services.git_db_storage.clone();
-        let obj_num = AtomicUsize::new(0);
 
         let mut exist_objs = HashSet::new();
 
-        let mut want_commits: Vec<Commit> = storage
-            .get_commits_by_hashes(&self.repo, &want_clone)
+        // find the commits that client does not have in the commit graph
+        let to_git_err = |e: MegaError| GitError::CustomError(e.to_string());
+        let (graph, missing) = self.fetch_graph(&want, &have).await.map_err(to_git_err)?;
+        let have: Vec<String> = have.into_iter().filter(|id| graph.contains(id)).collect();
+        let want_commits = self
+            .get_commits_by_hashes(missing)
             .await
-            .unwrap()
-            .into_iter()
-            .map(|x| x.into())
-            .collect();
-        let mut traversal_list: Vec<Commit> = want_commits.clone();
-
-        // traverse commit's all parents to find the commit that client does not have
-        while let Some(temp) = traversal_list.pop() {
-            for p_commit_id in temp.parent_commit_ids {
-                let p_commit_id = p_commit_id.to_plain_str();
-
-                if !have.contains(&p_commit_id) && !want_clone.contains(&p_commit_id) {
-                    let parent: Commit = storage
-                        .get_commit_by_hash(&self.repo, &p_commit_id)
-                        .await
-                        .unwrap()
-                        .unwrap()
-                        .into();
-                    want_commits.push(parent.clone());
-                    want_clone.push(p_commit_id);
-                    traversal_list.push(parent);
-                }
-            }
-        }
+            .map_err(to_git_err)?;
 
         let want_tree_ids = want_commits
             .iter()
@@ -215,8 +165,6 @@ impl PackHandler for ImportRepo {
             .map(|m| (SHA1::from_str(&m.tree_id).unwrap(), m.into()))
             .collect();
 
-        obj_num.fetch_add(want_commits.len(), Ordering::SeqCst);
-
         let have_commits = storage
             .get_commits_by_hashes(&self.repo, &have)
             .await
@@ -227,28 +175,36 @@ impl PackHandler for ImportRepo {
                 have_commits.iter().map(|x| x.tree.clone()).collect(),
             )
             .await
-            .unwrap();
-        // traverse to get exist_objs
-        for have_tree in have_trees {
-            self.traverse(have_tree.into(), &mut exist_objs, None).await;
-        }
-
-        let mut counted_obj = HashSet::new();
-        // traverse for get obj nums
-        for c in want_commits.clone() {
-            self.traverse_for_count(
-                want_trees.get(&c.tree_id).unwrap().clone(),
-                &exist_objs,
-                &mut counted_obj,
-                &obj_num,
-            )
-            .await;
+            .unwrap()
+            .into_iter()
+            .map(|m| m.into())
+            .collect::<Vec<Tree>>();
+        match self.reachable_objects(&want, &have).await {
+            Ok(Some(have_objs)) => exist_objs = have_objs,
+            reachable => {
+                if let Err(err) = reachable {
+                    tracing::warn!("failed to read the reachability bitmaps: {}", err);
+                }
+                // traverse to get exist_objs
+                for have_tree in have_trees.clone() {
+                    self.traverse(have_tree, &mut exist_objs, None).await;
+                }
+            }
         }
         let (entry_tx, entry_rx) = mpsc::channel(pack_config.channel_message_size);
         let (stream_tx, stream_rx) = mpsc::channel(pack_config.channel_message_size);
-        let encoder = PackEncoder::new(obj_num.into_inner(), 0, stream_tx);
+        let encoder = self.pack_encoder(pack_config, stream_tx).await;
         encoder.encode_async(entry_rx).await.unwrap();
 
+        if thin {
+            let want_trees = want_trees.values().cloned().collect();
+            if let Err(err) = self
+                .send_thin_bases(have_trees, want_trees, &exist_objs, &entry_tx)
+                .await
+            {
+                tracing::warn!("failed to find the bases of a thin pack: {}", err);
+            }
+        }
         for c in want_commits {
             self.traverse(
                 want_trees.get(&c.tree_id).unwrap().clone(),
@@ -260,7 +216,19 @@ impl PackHandler for ImportRepo {
         }
         drop(entry_tx);
 
-        Ok(ReceiverStream::new(stream_rx))
+        Ok(Box::pin(ReceiverStream::new(stream_rx).map(Ok)))
+    }
+
+    async fn get_commits_by_hashes(&self, hashes: Vec<String>) -> Result<Vec<Commit>, MegaError> {
+        Ok(self
+            .context
+            .services
+            .git_db_storage
+            .get_commits_by_hashes(&self.repo, &hashes)
+            .await?
+            .into_iter()
+            .map(|x| x.into())
+            .collect())
     }
 
     async fn get_trees_by_hashes(&self, hashes: Vec<String>) -> Result<Vec<Tree>, MegaError> {
@@ -287,6 +255,19 @@ impl PackHandler for ImportRepo {
             .await
     }
 
+    async fn find_missing_blobs(&self, hashes: Vec<String>) -> Result<Vec<String>, MegaError> {
+        let found: HashSet<String> = self
+            .context
+            .services
+            .git_db_storage
+            .get_blobs_by_hashes(&self.repo, hashes.clone())
+            .await?
+            .into_iter()
+            .map(|b| b.blob_id)
+            .collect();
+        Ok(hashes.into_iter().filter(|h| !found.contains(h)).collect())
+    }
+
     async fn update_refs(&self, refs: &RefCommand) -> Result<(), GitError> {
         let storage = self.context.services.git_db_storage.clone();
         match refs.command_type {
@@ -318,9 +299,118 @@ impl PackHandler for ImportRepo {
         let storage = self.context.services.git_db_storage.clone();
         storage.default_branch_exist(&self.repo).await.unwrap()
     }
+
+    fn commit_graph_storage(&self) -> (Arc<CommitGraphStorage>, i64) {
+        (
+            self.context.services.commit_graph_storage.clone(),
+            self.repo.repo_id,
+        )
+    }
+
+    async fn index_push(
+        &self,
+        received: &ReceivedObjects,
+        tips: Vec<String>,
+    ) -> Result<(), MegaError> {
+        self.load_commit_graph(received.commits.keys().cloned().collect())
+            .await?;
+        self.build_bitmaps(received, tips).await
+    }
 }
 
 impl ImportRepo {
+    /// Encode every object of the repo from the database into a pack.
+    pub async fn encode_full_pack(&self) -> Result<PackDataStream, GitError> {
+        let pack_config = &self.context.config.pack;
+        let (entry_tx, entry_rx) = mpsc::channel::<EncodeEntry>(pack_config.channel_message_size);
+        let (stream_tx, stream_rx) = mpsc::channel(pack_config.channel_message_size);
+
+        let storage = self.context.services.git_db_storage.clone();
+        let encoder = self.pack_encoder(pack_config, stream_tx).await;
+        encoder.encode_async(entry_rx).await.unwrap();
+
+        let repo = self.repo.clone();
+        tokio::spawn(async move {
+            let mut commit_stream = storage.get_commits_by_repo_id(&repo).await.unwrap();
+
+            while let Some(model) = commit_stream.next().await {
+                match model {
+                    Ok(m) => {
+                        let c: Commit = m.into();
+                        let entry = c.into();
+                        entry_tx.send(entry).await.unwrap();
+                    }
+                    Err(err) => eprintln!("Error: {:?}", err),
+                }
+            }
+            tracing::info!("send commits end");
+
+            let mut tree_stream = storage.get_trees_by_repo_id(&repo).await.unwrap();
+            while let Some(model) = tree_stream.next().await {
+                match model {
+                    Ok(m) => {
+                        let t: Tree = m.into();
+                        let entry = t.into();
+                        entry_tx.send(entry).await.unwrap();
+                    }
+                    Err(err) => eprintln!("Error: {:?}", err),
+                }
+            }
+            tracing::info!("send trees end");
+
+            let mut bid_stream = storage.get_blobs_by_repo_id(&repo).await.unwrap();
+            let mut bids = vec![];
+            // file names of the blobs, for the delta search to pair up versions of a file
+            let mut names = HashMap::new();
+            while let Some(model) = bid_stream.next().await {
+                match model {
+                    Ok(m) => {
+                        names.insert(m.blob_id.clone(), m.name);
+                        bids.push(m.blob_id);
+                    }
+                    Err(err) => eprintln!("Error: {:?}", err),
+                }
+            }
+            let names = Arc::new(names);
+
+            let mut blob_handler = vec![];
+            for chunk in bids.chunks(10000) {
+                let stg_clone = storage.clone();
+                let sender_clone = entry_tx.clone();
+                let chunk_clone = chunk.to_vec();
+                let names = names.clone();
+                let handler = tokio::spawn(async move {
+                    let mut blob_stream = stg_clone.get_raw_blobs(chunk_clone).await.unwrap();
+                    while let Some(model) = blob_stream.next().await {
+                        match model {
+                            Ok(m) => {
+                                // todo handle storage type
+                                let name = names.get(&m.sha1).cloned().unwrap_or_default();
+                                let b: Blob = m.into();
+                                let entry = EncodeEntry::with_name(b.into(), &name);
+                                sender_clone.send(entry).await.unwrap();
+                            }
+                            Err(err) => eprintln!("Error: {:?}", err),
+                        }
+                    }
+                });
+                blob_handler.push(handler);
+            }
+            join_all(blob_handler).await;
+            tracing::info!("send blobs end");
+
+            let tags = storage.get_tags_by_repo_id(&repo).await.unwrap();
+            for m in tags.into_iter() {
+                let c: Tag = m.into();
+                entry_tx.send(c.into()).await.unwrap();
+            }
+            drop(entry_tx);
+            tracing::info!("sending all object end...");
+        });
+
+        Ok(Box::pin(ReceiverStream::new(stream_rx).map(Ok)))
+    }
+
     // create monorepo parent for preserve import repo
     async fn create_monorepo_parent(&self) -> Result<(), GitError> {
         let path = PathBuf::from(self.repo.repo_path.clone());
@@ -1,3 +1,7 @@
+pub mod commit_graph;
+pub mod connectivity;
+pub mod fsck;
 pub mod handler;
 pub mod import_repo;
 pub mod monorepo;
+pub mod pack_store;
@@ -2,22 +2,21 @@ use std::{
     collections::{HashMap, HashSet},
     path::{Component, PathBuf},
     str::FromStr,
-    sync::{
-        atomic::{AtomicUsize, Ordering},
-        mpsc::Receiver,
-    },
+    sync::{mpsc::Receiver, Arc},
     vec,
 };
 
 use async_trait::async_trait;
-use futures::future::join_all;
+use futures::{future::join_all, StreamExt};
 use tokio::sync::mpsc;
 use tokio_stream::wrappers::ReceiverStream;
 
 use callisto::raw_blob;
 use common::{errors::MegaError, utils::MEGA_BRANCH_NAME};
-use jupiter::context::Context;
-use mercury::internal::pack::encode::PackEncoder;
+use jupiter::{
+    context::Context,
+    storage::commit_graph_storage::{CommitGraphStorage, MONOREPO_GRAPH_ID},
+};
 use mercury::{
     errors::GitError,
     hash::SHA1,
@@ -31,7 +30,10 @@ use venus::{
     monorepo::mr::MergeRequest,
 };
 
-use crate::pack::handler::PackHandler;
+use crate::pack::{
+    connectivity::ReceivedObjects,
+    handler::{PackDataStream, PackHandler},
+};
 
 pub struct MonoRepo {
     pub context: Context,
@@ -115,12 +117,15 @@ impl PackHandler for MonoRepo {
         self.find_head_hash(refs)
     }
 
-    async fn handle_receiver(&self, receiver: Receiver<Entry>) -> Result<(), GitError> {
+    async fn handle_receiver(
+        &self,
+        receiver: Receiver<Entry>,
+    ) -> Result<ReceivedObjects, GitError> {
         let storage = self.context.services.mega_storage.clone();
 
         let (mut mr, mr_exist) = self.get_mr().await;
 
-        let mut unpack_res = Ok(());
+        let mut unpack_res = Ok(ReceivedObjects::default());
         if mr_exist {
             if mr.from_hash == self.from_hash.clone().unwrap() {
                 let to_hash = self.to_hash.clone().unwrap();
@@ -164,10 +169,9 @@ impl PackHandler for MonoRepo {
     }
 
     // monorepo full pack should follow the shallow clone command 'git clone --depth=1'
-    async fn full_pack(&self) -> Result<ReceiverStream<Vec<u8>>, GitError> {
+    async fn full_pack(&self) -> Result<PackDataStream, GitError> {
         let pack_config = &self.context.config.pack;
         let storage = self.context.services.mega_storage.clone();
-        let obj_num = AtomicUsize::new(0);
 
         let refs = storage
             .get_ref(self.path.to_str().unwrap())
@@ -186,62 +190,36 @@ impl PackHandler for MonoRepo {
             .unwrap()
             .unwrap()
             .into();
-        self.traverse_for_count(tree.clone(), &HashSet::new(), &mut HashSet::new(), &obj_num)
-            .await;
-
-        obj_num.fetch_add(1, Ordering::SeqCst);
-
         let (entry_tx, entry_rx) = mpsc::channel(pack_config.channel_message_size);
         let (stream_tx, stream_rx) = mpsc::channel(pack_config.channel_message_size);
 
-        let encoder = PackEncoder::new(obj_num.into_inner(), 0, stream_tx);
+        let encoder = self.pack_encoder(pack_config, stream_tx).await;
         encoder.encode_async(entry_rx).await.unwrap();
         self.traverse(tree, &mut HashSet::new(), Some(&entry_tx))
             .await;
         entry_tx.send(commit.into()).await.unwrap();
         drop(entry_tx);
-        Ok(ReceiverStream::new(stream_rx))
+        Ok(Box::pin(ReceiverStream::new(stream_rx).map(Ok)))
     }
 
     async fn incremental_pack(
         &self,
         want: Vec<String>,
         have: Vec<String>,
-    ) -> Result<ReceiverStream<Vec<u8>>, GitError> {
-        let mut want_clone = want.clone();
+        thin: bool,
+    ) -> Result<PackDataStream, GitError> {
         let pack_config = &self.context.config.pack;
         let storage = self.context.services.mega_storage.clone();
-        let obj_num = AtomicUsize::new(0);
 
         let mut exist_objs = HashSet::new();
 
-        let mut want_commits: Vec<Commit> = storage
-            .get_commits_by_hashes(&want_clone)
+        // find the commits that client does not have in the commit graph
+        let to_git_err = |e: MegaError| GitError::CustomError(e.to_string());
+        let (_, missing) = self.fetch_graph(&want, &have).await.map_err(to_git_err)?;
+        let want_commits = self
+            .get_commits_by_hashes(missing)
             .await
-            .unwrap()
-            .into_iter()
-            .map(|x| x.into())
-            .collect();
-        let mut traversal_list: Vec<Commit> = want_commits.clone();
-
-        // traverse commit's all parents to find the commit that client does not have
-        while let Some(temp) = traversal_list.pop() {
-            for p_commit_id in temp.parent_commit_ids {
-                let p_commit_id = p_commit_id.to_plain_str();
-
-                if !have.contains(&p_commit_id) && !want_clone.contains(&p_commit_id) {
-                    let parent: Commit = storage
-                        .get_commit_by_hash(&p_commit_id)
-                        .await
-                        .unwrap()
-                        .unwrap()
-                        .into();
-                    want_commits.push(parent.clone());
-                    want_clone.push(p_commit_id);
-                    traversal_list.push(parent);
-                }
-            }
-        }
+            .map_err(to_git_err)?;
 
         let want_tree_ids = want_commits
             .iter()
@@ -255,33 +233,32 @@ impl PackHandler for MonoRepo {
             .map(|m| (SHA1::from_str(&m.tree_id).unwrap(), m.into()))
             .collect();
 
-        obj_num.fetch_add(want_commits.len(), Ordering::SeqCst);
-
         let have_commits = storage.get_commits_by_hashes(&have).await.unwrap();
         let have_trees = storage
             .get_trees_by_hashes(have_commits.iter().map(|x| x.tree.clone()).collect())
             .await
-            .unwrap();
-        for have_tree in have_trees {
-            self.traverse(have_tree.into(), &mut exist_objs, None).await;
+            .unwrap()
+            .into_iter()
+            .map(|m| m.into())
+            .collect::<Vec<Tree>>();
+        for have_tree in have_trees.clone() {
+            self.traverse(have_tree, &mut exist_objs, None).await;
         }
 
-        let mut counted_obj = HashSet::new();
-        // traverse for get obj nums
-        for c in want_commits.clone() {
-            self.traverse_for_count(
-                want_trees.get(&c.tree_id).unwrap().clone(),
-                &exist_objs,
-                &mut counted_obj,
-                &obj_num,
-            )
-            .await;
-        }
         let (entry_tx, entry_rx) = mpsc::channel(pack_config.channel_message_size);
         let (stream_tx, stream_rx) = mpsc::channel(pack_config.channel_message_size);
-        let encoder = PackEncoder::new(obj_num.into_inner(), 0, stream_tx);
+        let encoder = self.pack_encoder(pack_config, stream_tx).await;
         encoder.encode_async(entry_rx).await.unwrap();
 
+        if thin {
+            let want_trees = want_trees.values().cloned().collect();
+            if let Err(err) = self
+                .send_thin_bases(have_trees, want_trees, &exist_objs, &entry_tx)
+                .await
+            {
+                tracing::warn!("failed to find the bases of a thin pack: {}", err);
+            }
+        }
         for c in want_commits {
             self.traverse(
                 want_trees.get(&c.tree_id).unwrap().clone(),
@@ -293,7 +270,19 @@ impl PackHandler for MonoRepo {
         }
         drop(entry_tx);
 
-        Ok(ReceiverStream::new(stream_rx))
+        Ok(Box::pin(ReceiverStream::new(stream_rx).map(Ok)))
+    }
+
+    async fn get_commits_by_hashes(&self, hashes: Vec<String>) -> Result<Vec<Commit>, MegaError> {
+        Ok(self
+            .context
+            .services
+            .mega_storage
+            .get_commits_by_hashes(&hashes)
+            .await?
+            .into_iter()
+            .map(|x| x.into())
+            .collect())
     }
 
     async fn get_trees_by_hashes(&self, hashes: Vec<String>) -> Result<Vec<Tree>, MegaError> {
@@ -335,9 +324,29 @@ impl PackHandler for MonoRepo {
             .is_some()
     }
 
+    async fn find_missing_blobs(&self, hashes: Vec<String>) -> Result<Vec<String>, MegaError> {
+        let found: HashSet<String> = self
+            .context
+            .services
+            .mega_storage
+            .get_mega_blobs_by_hashes(hashes.clone())
+            .await?
+            .into_iter()
+            .map(|b| b.blob_id)
+            .collect();
+        Ok(hashes.into_iter().filter(|h| !found.contains(h)).collect())
+    }
+
     async fn check_default_branch(&self) -> bool {
         true
     }
+
+    fn commit_graph_storage(&self) -> (Arc<CommitGraphStorage>, i64) {
+        (
+            self.context.services.commit_graph_storage.clone(),
+            MONOREPO_GRAPH_ID,
+        )
+    }
 }
 
 impl MonoRepo {
@@ -369,12 +378,14 @@ impl MonoRepo {
         )
     }
 
-    async fn save_entry(&self, receiver: Receiver<Entry>) -> Result<(), GitError> {
+    async fn save_entry(&self, receiver: Receiver<Entry>) -> Result<ReceivedObjects, GitError> {
         let storage = self.context.services.mega_storage.clone();
+        let mut received = ReceivedObjects::default();
         let mut entry_list = Vec::new();
         let mut join_tasks = vec![];
         let mut current_commit_id = String::new();
         for entry in receiver {
+            received.record(&entry)?;
             if current_commit_id.is_empty() {
                 if entry.obj_type == ObjectType::Commit {
                     current_commit_id = entry.hash.to_plain_str();
@@ -389,7 +400,7 @@ impl MonoRepo {
                     let stg_clone = storage.clone();
                     let commit_id = current_commit_id.clone();
                     let handle = tokio::spawn(async move {
-                        stg_clone.save_entry(&commit_id, entry_list).await.unwrap();
+                        stg_clone.save_entry(&commit_id, entry_list).await
                     });
                     join_tasks.push(handle);
                     entry_list = vec![];
@@ -397,8 +408,14 @@ impl MonoRepo {
             }
             entry_list.push(entry);
         }
-        join_all(join_tasks).await;
-        storage.save_entry(&current_commit_id, entry_list).await.unwrap();
-        Ok(())
+        for res in join_all(join_tasks).await {
+            res.map_err(|e| GitError::CustomError(e.to_string()))?
+                .map_err(|e| GitError::CustomError(e.to_string()))?;
+        }
+        storage
+            .save_entry(&current_commit_id, entry_list)
+            .await
+            .map_err(|e| GitError::CustomError(e.to_string()))?;
+        Ok(received)
     }
 }
@@ -0,0 +1,483 @@
+//! Pack file storage for import repos.
+//!
+//! Besides the rows of every object, the packs pushed to an import repo are kept in raw
+//! storage as `<checksum>.pack` and `<checksum>.idx`, and the offset of every object is
+//! recorded in `git_pack_object`. When the stored packs hold every object of the repo exactly
+//! once, a clone is served by streaming them instead of encoding the objects again.
+//!
+//! Each push adds a pack, so the packs of a repo are merged into one once their number reaches
+//! `pack.repack_threshold`. A clone of a repo whose packs don't cover it, e.g. a repo imported
+//! before packs were kept, also triggers a repack in the background.
+//!
+//! The deltas of the stored packs are sent again when a pack is encoded for a fetch or a
+//! repack, see [`StoredPackDeltas`].
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    fs::File,
+    io::{BufReader, Cursor, Read},
+    path::Path,
+    str::FromStr,
+    sync::{Arc, Mutex},
+};
+
+use async_trait::async_trait;
+use flate2::read::ZlibDecoder;
+use tokio::{io::AsyncWriteExt, sync::mpsc};
+use tokio_stream::{wrappers::ReceiverStream, StreamExt};
+
+use callisto::git_pack;
+use common::{config::PackConfig, errors::MegaError};
+use jupiter::{raw_storage::RawStorage, storage::git_db_storage::GitDbStorage};
+use mercury::{
+    errors::GitError,
+    hash::{HashKind, ObjectHasher, SHA1},
+    internal::{
+        object::types::ObjectType,
+        pack::{
+            encode::{encode_pack_object, DeltaStore, StoredDelta},
+            index::build_index_v2,
+            utils::{read_offset_encoding, read_type_and_varint_size},
+            ObjectLookup, Pack,
+        },
+    },
+};
+use venus::import_repo::repo::Repo;
+
+use crate::pack::{
+    handler::{storage_lookup, PackDataStream, PackHandler},
+    import_repo::ImportRepo,
+};
+
+/// Size of the messages when streaming stored packs.
+const PACK_CHUNK_SIZE: usize = 64 * 1024;
+
+/// Bytes read at the offset of a stored delta, enough for the header and most deltas.
+const DELTA_READ_SIZE: u64 = 16 * 1024;
+
+/// Ids of the repos being repacked.
+static REPACKING: Mutex<Vec<i64>> = Mutex::new(Vec::new());
+
+fn to_git_err(e: MegaError) -> GitError {
+    GitError::CustomError(e.to_string())
+}
+
+impl ImportRepo {
+    /// Name of the repo in raw storage. Paths of import repos may be nested, so the id is used.
+    fn pack_storage_name(&self) -> String {
+        format!("packs/{}", self.repo.repo_id)
+    }
+
+    /// Index the pack file, keep it in raw storage with its `.idx` and record the object offsets.
+    /// `hash_kind` is the object format of the pack.
+    /// Returns the pack checksum, which is also the name of the files.
+    ///
+    /// The bases of a thin pack are appended to it first. They are stored in other packs too,
+    /// so the packs of the repo won't cover it until the next repack.
+    pub async fn store_pack(
+        &self,
+        pack_file: &Path,
+        hash_kind: HashKind,
+    ) -> Result<String, GitError> {
+        let pack_config = self.context.config.pack.clone();
+        let path = pack_file.to_path_buf();
+        let lookup = storage_lookup(Arc::new(ImportRepo {
+            context: self.context.clone(),
+            repo: self.repo.clone(),
+        }));
+        let (objects, signature) = tokio::task::spawn_blocking(move || {
+            index_pack_file(&path, &pack_config, lookup, hash_kind)
+        })
+        .await
+        .map_err(|e| GitError::CustomError(e.to_string()))??;
+
+        let data = tokio::fs::read(pack_file).await?;
+        let index = build_index_v2(&objects, &signature, Cursor::new(&data), data.len())?;
+        let pack_id = signature.to_plain_str();
+        let storage = self.context.services.git_db_storage.clone();
+        let name = self.pack_storage_name();
+        storage
+            .raw_storage
+            .put_object(&name, &format!("{}.pack", pack_id), &data)
+            .await
+            .map_err(to_git_err)?;
+        storage
+            .raw_storage
+            .put_object(&name, &format!("{}.idx", pack_id), &index)
+            .await
+            .map_err(to_git_err)?;
+
+        let objects = objects
+            .into_iter()
+            .map(|(hash, offset)| (hash.to_plain_str(), offset))
+            .collect();
+        storage
+            .save_git_pack(&self.repo, &pack_id, data.len(), objects)
+            .await
+            .map_err(to_git_err)?;
+        Ok(pack_id)
+    }
+
+    /// The stored packs, if together they hold every object of the repo exactly once.
+    pub async fn covering_packs(&self) -> Result<Option<Vec<git_pack::Model>>, MegaError> {
+        let storage = self.context.services.git_db_storage.clone();
+        let packs = storage.get_git_packs(&self.repo).await?;
+        if packs.is_empty() {
+            return Ok(None);
+        }
+        let packed: u64 = packs.iter().map(|p| p.object_count as u64).sum();
+        let distinct = storage.get_pack_object_count(&self.repo).await?;
+        let total = storage.get_obj_count_by_repo_id(&self.repo).await as u64;
+        Ok((packed == distinct && distinct == total).then_some(packs))
+    }
+
+    /// The deltas of the stored packs, `None` if the repo has no pack.
+    pub async fn stored_pack_deltas(&self) -> Result<Option<StoredPackDeltas>, MegaError> {
+        let storage = self.context.services.git_db_storage.clone();
+        let packs = storage.get_git_packs(&self.repo).await?;
+        let Some(first) = packs.first() else {
+            return Ok(None);
+        };
+        let kind = HashKind::from_size(first.pack_id.len() / 2).unwrap_or_default();
+        Ok(Some(StoredPackDeltas {
+            raw_storage: storage.raw_storage.clone(),
+            name: self.pack_storage_name(),
+            storage,
+            repo: self.repo.clone(),
+            kind,
+            pack_index: packs
+                .iter()
+                .enumerate()
+                .map(|(i, p)| (p.pack_id.clone(), i as u32))
+                .collect(),
+            packs: packs
+                .into_iter()
+                .map(|p| (p.pack_id, p.size as u64))
+                .collect(),
+            objects: Mutex::new(HashMap::new()),
+            offsets: Mutex::new(HashMap::new()),
+        }))
+    }
+
+    /// Send the stored packs as a single pack.
+    ///
+    /// The objects of every pack are sent as they are, under a new header and checksum.
+    /// Offset deltas stay valid as they are relative to the delta object itself, and thin packs
+    /// are completed by [`ImportRepo::store_pack`], so the bases of ref deltas are always sent.
+    pub fn stream_stored_packs(&self, packs: Vec<git_pack::Model>) -> PackDataStream {
+        let (stream_tx, stream_rx) = mpsc::channel(self.context.config.pack.channel_message_size);
+        let raw_storage = self.context.services.git_db_storage.raw_storage.clone();
+        let name = self.pack_storage_name();
+        tokio::spawn(async move {
+            let total: i64 = packs.iter().map(|p| p.object_count).sum();
+            let kind = packs
+                .first()
+                .and_then(|p| HashKind::from_size(p.pack_id.len() / 2))
+                .unwrap_or_default();
+            let mut header = b"PACK".to_vec();
+            header.extend_from_slice(&2u32.to_be_bytes());
+            header.extend_from_slice(&(total as u32).to_be_bytes());
+            let mut hasher = ObjectHasher::new(kind);
+            hasher.update(&header);
+            if stream_tx.send(Ok(header)).await.is_err() {
+                return;
+            }
+            for pack in packs {
+                let object_id = format!("{}.pack", pack.pack_id);
+                // skip the 12 bytes header and the checksum
+                let mut offset = 12;
+                let end = (pack.size as u64).saturating_sub(kind.size() as u64);
+                while offset < end {
+                    let len = (end - offset).min(PACK_CHUNK_SIZE as u64) as usize;
+                    let chunk = match raw_storage
+                        .get_object_range(&name, &object_id, offset, len)
+                        .await
+                    {
+                        Ok(chunk) => chunk,
+                        Err(err) => {
+                            tracing::error!("failed to read pack {}: {}", pack.pack_id, err);
+                            let err = format!("failed to read pack {}", pack.pack_id);
+                            let _ = stream_tx.send(Err(GitError::CustomError(err))).await;
+                            return;
+                        }
+                    };
+                    hasher.update(&chunk);
+                    if stream_tx.send(Ok(chunk.to_vec())).await.is_err() {
+                        return;
+                    }
+                    offset += len as u64;
+                }
+            }
+            let _ = stream_tx.send(Ok(hasher.finalize().to_data())).await;
+        });
+        Box::pin(ReceiverStream::new(stream_rx))
+    }
+
+    /// Merge the stored packs of the repo into one.
+    ///
+    /// The new pack is encoded from the database, so it also holds the objects which were
+    /// received before packs were kept. Packs stored while repacking are left as they are.
+    pub async fn repack(&self) -> Result<(), GitError> {
+        let storage = self.context.services.git_db_storage.clone();
+        if let Some(packs) = self.covering_packs().await.map_err(to_git_err)? {
+            if packs.len() == 1 {
+                return Ok(());
+            }
+        }
+        if storage.get_obj_count_by_repo_id(&self.repo).await == 0 {
+            return Ok(());
+        }
+        let old_packs = storage
+            .get_git_packs(&self.repo)
+            .await
+            .map_err(to_git_err)?;
+
+        let cache_path = &self.context.config.pack.pack_decode_cache_path;
+        tokio::fs::create_dir_all(cache_path).await?;
+        let pack_file = cache_path.join(format!("repack-{}.pack", self.repo.repo_id));
+        let mut file = tokio::fs::File::create(&pack_file).await?;
+        let mut stream = self.encode_full_pack().await?;
+        while let Some(chunk) = stream.next().await {
+            file.write_all(&chunk?).await?;
+        }
+        file.flush().await?;
+        drop(file);
+        let result = self.store_pack(&pack_file, self.hash_kind().await).await;
+        let _ = tokio::fs::remove_file(&pack_file).await;
+        let pack_id = result?;
+
+        let old_ids: Vec<String> = old_packs
+            .into_iter()
+            .map(|p| p.pack_id)
+            .filter(|id| *id != pack_id)
+            .collect();
+        storage
+            .delete_git_packs(&self.repo, old_ids.clone())
+            .await
+            .map_err(to_git_err)?;
+        let name = self.pack_storage_name();
+        for id in old_ids {
+            for file_name in [format!("{}.pack", id), format!("{}.idx", id)] {
+                if let Err(err) = storage.raw_storage.delete_object(&name, &file_name).await {
+                    tracing::warn!("failed to delete {}: {}", file_name, err);
+                }
+            }
+        }
+        tracing::info!("repacked {} into {}", self.repo.repo_name, pack_id);
+        Ok(())
+    }
+
+    /// Run [`ImportRepo::repack`] in the background, unless the repo is being repacked already.
+    pub fn spawn_repack(&self) {
+        let repo_id = self.repo.repo_id;
+        {
+            let mut repacking = REPACKING.lock().unwrap();
+            if repacking.contains(&repo_id) {
+                return;
+            }
+            repacking.push(repo_id);
+        }
+        let import_repo = ImportRepo {
+            context: self.context.clone(),
+            repo: self.repo.clone(),
+        };
+        tokio::spawn(async move {
+            if let Err(err) = import_repo.repack().await {
+                tracing::error!("failed to repack {}: {}", import_repo.repo.repo_name, err);
+            }
+            REPACKING.lock().unwrap().retain(|id| *id != repo_id);
+        });
+    }
+}
+
+/// The deltas of the stored packs of a repo, read from raw storage when the encoder asks
+/// for them. A delta is only sent again if its base is sent before it, so the pack stays
+/// valid whatever the stored packs hold.
+///
+/// Only the objects being sent are looked up, batch by batch, see [`DeltaStore::prepare`].
+/// The base of an offset delta is found among them, a base which isn't sent is of no use.
+pub struct StoredPackDeltas {
+    raw_storage: Arc<dyn RawStorage>,
+    name: String,
+    storage: Arc<GitDbStorage>,
+    repo: Repo,
+    kind: HashKind,
+    /// the position of every pack in `packs`, by id
+    pack_index: HashMap<String, u32>,
+    /// id and size of every pack
+    packs: Vec<(String, u64)>,
+    /// the pack and offset of the objects looked up
+    objects: Mutex<HashMap<SHA1, (u32, u64)>>,
+    /// the object at each offset, to find the bases of offset deltas
+    offsets: Mutex<HashMap<(u32, u64), SHA1>>,
+}
+
+impl StoredPackDeltas {
+    /// Read the object at `offset` of the pack, `None` if it isn't a delta.
+    ///
+    /// The header and the delta are read at once, unless the delta is larger than
+    /// [`DELTA_READ_SIZE`].
+    async fn read_delta(&self, pack: u32, offset: u64) -> Result<Option<StoredDelta>, GitError> {
+        let (pack_id, size) = &self.packs[pack as usize];
+        let object_id = format!("{}.pack", pack_id);
+        let end = size.saturating_sub(self.kind.size() as u64);
+        let read = |offset: u64, len: u64| {
+            let len = len.min(end.saturating_sub(offset)) as usize;
+            self.raw_storage
+                .get_object_range(&self.name, &object_id, offset, len)
+        };
+
+        let mut data = read(offset, DELTA_READ_SIZE)
+            .await
+            .map_err(to_git_err)?
+            .to_vec();
+        // the type and size, then the offset or the id of the base
+        let (base, header_len, delta_size) = {
+            let mut reader = Cursor::new(&data[..]);
+            let mut header_len = 0;
+            let (obj_type, delta_size) = read_type_and_varint_size(&mut reader, &mut header_len)?;
+            let base = match ObjectType::from_u8(obj_type)? {
+                ObjectType::OffsetDelta => {
+                    let (distance, len) = read_offset_encoding(&mut reader)?;
+                    header_len += len;
+                    offset
+                        .checked_sub(distance)
+                        .and_then(|base_offset| {
+                            self.offsets
+                                .lock()
+                                .unwrap()
+                                .get(&(pack, base_offset))
+                                .copied()
+                        })
+                        .ok_or_else(|| {
+                            GitError::InvalidPackFile(format!("no delta base at {}", offset))
+                        })?
+                }
+                ObjectType::HashDelta => {
+                    let mut hash = vec![0; self.kind.size()];
+                    reader.read_exact(&mut hash)?;
+                    header_len += hash.len();
+                    SHA1::from_bytes(&hash)?
+                }
+                _ => return Ok(None),
+            };
+            (base, header_len, delta_size)
+        };
+
+        // the compressed delta is at most a little larger than the delta
+        let max_len = header_len as u64 + delta_size as u64 + (delta_size as u64 >> 11) + 64;
+        if max_len > data.len() as u64 {
+            let rest = read(offset + data.len() as u64, max_len - data.len() as u64)
+                .await
+                .map_err(to_git_err)?;
+            data.extend_from_slice(&rest);
+        }
+        let mut delta = Vec::with_capacity(delta_size);
+        ZlibDecoder::new(&data[header_len..]).read_to_end(&mut delta)?;
+        if delta.len() != delta_size {
+            return Err(GitError::InvalidPackFile(format!(
+                "the delta at {} has {} bytes, expected {}",
+                offset,
+                delta.len(),
+                delta_size
+            )));
+        }
+        Ok(Some(StoredDelta { base, data: delta }))
+    }
+}
+
+#[async_trait]
+impl DeltaStore for StoredPackDeltas {
+    async fn prepare(&self, ids: &[SHA1]) {
+        let object_ids = ids.iter().map(|id| id.to_plain_str()).collect();
+        let objects = match self.storage.get_pack_objects(&self.repo, object_ids).await {
+            Ok(objects) => objects,
+            Err(err) => {
+                tracing::warn!("failed to look up the stored deltas: {}", err);
+                return;
+            }
+        };
+        let mut found = self.objects.lock().unwrap();
+        let mut offsets = self.offsets.lock().unwrap();
+        for object in objects {
+            let (Some(&pack), Ok(id)) = (
+                self.pack_index.get(&object.pack_id),
+                SHA1::from_str(&object.object_id),
+            ) else {
+                continue;
+            };
+            let offset = object.pack_offset as u64;
+            found.insert(id, (pack, offset));
+            offsets.insert((pack, offset), id);
+        }
+    }
+
+    async fn stored_delta(&self, id: &SHA1) -> Option<StoredDelta> {
+        let (pack, offset) = self.objects.lock().unwrap().get(id).copied()?;
+        self.read_delta(pack, offset).await.unwrap_or_else(|err| {
+            tracing::warn!("failed to read the stored delta of {}: {}", id, err);
+            None
+        })
+    }
+}
+
+/// Decode the pack file to find the offset of every object, sorted by object id.
+/// A thin pack is completed with its bases, see [`complete_thin_pack`].
+fn index_pack_file(
+    pack_file: &Path,
+    pack_config: &PackConfig,
+    lookup: ObjectLookup,
+    hash_kind: HashKind,
+) -> Result<(BTreeMap<SHA1, usize>, SHA1), GitError> {
+    let objects = Arc::new(Mutex::new(BTreeMap::new()));
+    let objects_c = objects.clone();
+    let mut pack = Pack::new(
+        None,
+        Some(1024 * 1024 * 1024 * pack_config.pack_decode_mem_size),
+        Some(pack_config.pack_decode_cache_path.clone()),
+        pack_config.clean_cache_after_decode,
+    )
+    .with_base_lookup(lookup.clone())
+    .with_hash_kind(hash_kind);
+    let mut reader = BufReader::new(File::open(pack_file)?);
+    pack.decode(&mut reader, move |entry, offset| {
+        objects_c.lock().unwrap().insert(entry.hash, offset);
+    })?;
+    drop(reader);
+    let mut objects = std::mem::take(&mut *objects.lock().unwrap());
+    if pack.thin_bases.is_empty() {
+        return Ok((objects, pack.signature));
+    }
+    let signature = complete_thin_pack(
+        pack_file,
+        &pack.thin_bases,
+        &lookup,
+        &mut objects,
+        hash_kind,
+    )?;
+    Ok((objects, signature))
+}
+
+/// Append the bases of a thin pack to it, like `git index-pack --fix-thin`, so that every
+/// ref delta of the pack has its base in the pack. Returns the new pack checksum.
+fn complete_thin_pack(
+    pack_file: &Path,
+    bases: &[SHA1],
+    lookup: &ObjectLookup,
+    objects: &mut BTreeMap<SHA1, usize>,
+    hash_kind: HashKind,
+) -> Result<SHA1, GitError> {
+    let mut data = std::fs::read(pack_file)?;
+    data.truncate(data.len() - hash_kind.size());
+    let object_num = u32::from_be_bytes(data[8..12].try_into().unwrap()) + bases.len() as u32;
+    data[8..12].copy_from_slice(&object_num.to_be_bytes());
+    for hash in bases {
+        let entry = lookup(*hash).ok_or_else(|| GitError::ObjectNotFound(hash.to_plain_str()))?;
+        objects.insert(*hash, data.len());
+        data.extend(encode_pack_object(&entry));
+    }
+    let signature = SHA1::new_with_kind(hash_kind, &data);
+    data.extend_from_slice(signature.as_bytes());
+    std::fs::write(pack_file, &data)?;
+    Ok(signature)
+}
@@ -0,0 +1,208 @@
+//! A parser of [Conventional Commits](https://www.conventionalcommits.org/en/v1.0.0/) messages.
+//!
+//! A message is a header `<type>[(<scope>)][!]: <description>`, then an optional body and
+//! footers, each after a blank line. The footers are the last paragraph when its first line
+//! is `<token>: <value>` or `<token> #<value>`, a line which is not a footer continues the
+//! value of the footer before it.
+
+use std::sync::OnceLock;
+
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ConventionalCommit {
+    /// lowercased, like `feat` or `fix`
+    pub commit_type: String,
+    pub scope: Option<String>,
+    /// marked by `!` in the header or by a `BREAKING CHANGE` footer
+    pub breaking: bool,
+    pub description: String,
+    pub body: Option<String>,
+    pub footers: Vec<Footer>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Footer {
+    pub token: String,
+    pub value: String,
+}
+
+impl ConventionalCommit {
+    /// The value of the `BREAKING CHANGE` footer, which describes the breaking change.
+    pub fn breaking_note(&self) -> Option<&str> {
+        self.footers
+            .iter()
+            .find(|f| is_breaking_token(&f.token))
+            .map(|f| f.value.as_str())
+    }
+}
+
+fn header_pattern() -> &'static Regex {
+    static PATTERN: OnceLock<Regex> = OnceLock::new();
+    PATTERN.get_or_init(|| {
+        Regex::new(r"^(?P<type>[A-Za-z][A-Za-z0-9-]*)(?:\((?P<scope>[^()]*)\))?(?P<breaking>!)?: (?P<description>.*)$").unwrap()
+    })
+}
+
+fn footer_pattern() -> &'static Regex {
+    static PATTERN: OnceLock<Regex> = OnceLock::new();
+    PATTERN.get_or_init(|| {
+        Regex::new(r"^(?P<token>BREAKING CHANGE|[A-Za-z][A-Za-z0-9-]*)(?:: | #)(?P<value>.*)$")
+            .unwrap()
+    })
+}
+
+fn is_breaking_token(token: &str) -> bool {
+    token == "BREAKING CHANGE" || token == "BREAKING-CHANGE"
+}
+
+/// The message of a raw commit message, without the headers before it like `gpgsig`.
+pub fn message_text(message: &str) -> &str {
+    if let Some(text) = message.strip_prefix('\n') {
+        return text;
+    }
+    match message.find("\n\n") {
+        Some(end) => &message[end + 2..],
+        None => message,
+    }
+}
+
+/// Parse a commit message, or tell why it is not a Conventional Commit.
+pub fn parse(message: &str) -> Result<ConventionalCommit, String> {
+    let mut lines = message.trim().lines().map(str::trim_end);
+    let header = lines.next().unwrap_or_default();
+    let captures = header_pattern().captures(header).ok_or_else(|| {
+        format!(
+            "the header `{}` is not `<type>[(<scope>)][!]: <description>`",
+            header
+        )
+    })?;
+    let scope = captures.name("scope").map(|s| s.as_str().trim().to_owned());
+    if scope.as_deref() == Some("") {
+        return Err("the scope is empty".to_owned());
+    }
+    let description = captures["description"].trim().to_owned();
+    if description.is_empty() {
+        return Err("the description is empty".to_owned());
+    }
+
+    let rest: Vec<&str> = lines.collect();
+    if rest.first().is_some_and(|line| !line.is_empty()) {
+        return Err("the header is not followed by a blank line".to_owned());
+    }
+    // the last paragraph is the footers if it starts with one
+    let last_paragraph = rest
+        .iter()
+        .rposition(|line| line.is_empty())
+        .map_or(rest.len(), |i| i + 1);
+    let (body, footer_lines) = match rest.get(last_paragraph) {
+        Some(line) if footer_pattern().is_match(line) => rest.split_at(last_paragraph),
+        _ => (&rest[..], &[][..]),
+    };
+
+    let mut footers: Vec<Footer> = Vec::new();
+    for line in footer_lines {
+        match footer_pattern().captures(line) {
+            Some(captures) => footers.push(Footer {
+                token: captures["token"].to_owned(),
+                value: captures["value"].trim().to_owned(),
+            }),
+            None => {
+                let footer = footers.last_mut().unwrap();
+                footer.value.push('\n');
+                footer.value.push_str(line);
+            }
+        }
+    }
+    let body = body.join("\n").trim().to_owned();
+    let breaking =
+        captures.name("breaking").is_some() || footers.iter().any(|f| is_breaking_token(&f.token));
+    Ok(ConventionalCommit {
+        commit_type: captures["type"].to_ascii_lowercase(),
+        scope,
+        breaking,
+        description,
+        body: (!body.is_empty()).then_some(body),
+        footers,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse_header() {
+        let commit = parse("feat(api): add the changelog endpoint\n").unwrap();
+        assert_eq!(commit.commit_type, "feat");
+        assert_eq!(commit.scope.as_deref(), Some("api"));
+        assert_eq!(commit.description, "add the changelog endpoint");
+        assert!(!commit.breaking);
+        assert!(commit.body.is_none() && commit.footers.is_empty());
+
+        let commit = parse("Fix!: drop the old config").unwrap();
+        assert_eq!(commit.commit_type, "fix");
+        assert!(commit.scope.is_none());
+        assert!(commit.breaking);
+
+        for message in [
+            "add the changelog endpoint",
+            "feat add the changelog endpoint",
+            "feat:add the changelog endpoint",
+            "feat(): add the changelog endpoint",
+            "feat: ",
+            "",
+            "feat: one\nsecond line of the header",
+        ] {
+            assert!(parse(message).is_err(), "{:?}", message);
+        }
+    }
+
+    #[test]
+    fn test_parse_body_and_footers() {
+        let message = "\
+refactor(storage)!: store footers as JSON
+
+Footers were stored one per row.
+
+Reviewed-by: Alice
+BREAKING CHANGE: the footers table is gone,
+  run the migration first
+Refs #42
+";
+        let commit = parse(message).unwrap();
+        assert!(commit.breaking);
+        assert_eq!(
+            commit.body.as_deref(),
+            Some("Footers were stored one per row.")
+        );
+        let tokens: Vec<_> = commit.footers.iter().map(|f| f.token.as_str()).collect();
+        assert_eq!(tokens, ["Reviewed-by", "BREAKING CHANGE", "Refs"]);
+        assert_eq!(
+            commit.breaking_note(),
+            Some("the footers table is gone,\n  run the migration first")
+        );
+        assert_eq!(commit.footers[2].value, "42");
+
+        // only the last paragraph can be the footers, and only if it starts with one
+        let commit = parse("docs: explain\n\nfirst\n\nsecond: line one\nline two").unwrap();
+        assert_eq!(commit.footers.len(), 1);
+        let commit = parse("docs: explain\n\nsee the guide for more: it helps").unwrap();
+        assert!(commit.footers.is_empty());
+        assert_eq!(
+            commit.body.as_deref(),
+            Some("see the guide for more: it helps")
+        );
+        let commit = parse("fix: x\n\nBREAKING-CHANGE: renamed").unwrap();
+        assert!(commit.breaking);
+    }
+
+    #[test]
+    fn test_message_text() {
+        assert_eq!(message_text("\nfeat: x\n"), "feat: x\n");
+        let signed =
+            "gpgsig -----BEGIN PGP SIGNATURE-----\n \n -----END PGP SIGNATURE-----\n\nfix: y\n";
+        assert_eq!(message_text(signed), "fix: y\n");
+    }
+}
@@ -0,0 +1,176 @@
+//! Policies on the commits of a push.
+//!
+//! With `commit_policy.conventional_commits` a ref update is rejected unless the messages of
+//! its new commits are Conventional Commits of an allowed type. The parsed messages of the
+//! commits a push adds to its updated refs are stored either way, to build changelogs from them.
+
+use std::collections::HashSet;
+
+use callisto::conventional_commit;
+use common::{config::CommitPolicyConfig, utils::generate_id};
+
+use crate::pack::connectivity::ReceivedObjects;
+
+pub mod conventional;
+
+use conventional::ConventionalCommit;
+
+/// Check the messages of the commits received for the update of a ref to `new_id`.
+///
+/// Only the commits of the push are checked, the ones stored before are accepted already,
+/// and merge commits are skipped as their messages are made by git.
+pub fn check_commits(
+    config: &CommitPolicyConfig,
+    new_id: &str,
+    received: &ReceivedObjects,
+) -> Result<(), String> {
+    if !config.conventional_commits {
+        return Ok(());
+    }
+    let mut pending = vec![new_id.to_owned()];
+    let mut seen = HashSet::new();
+    while let Some(id) = pending.pop() {
+        if !seen.insert(id.clone()) {
+            continue;
+        }
+        let Some((_, parents)) = received.commits.get(&id) else {
+            continue;
+        };
+        if parents.len() <= 1 {
+            let message = received.messages.get(&id).map_or("", String::as_str);
+            check_message(config, message)
+                .map_err(|reason| format!("commit {} {}", &id[..id.len().min(7)], reason))?;
+        }
+        pending.extend(parents.iter().cloned());
+    }
+    Ok(())
+}
+
+fn check_message(config: &CommitPolicyConfig, message: &str) -> Result<(), String> {
+    let commit = conventional::parse(conventional::message_text(message))
+        .map_err(|reason| format!("is not a conventional commit: {}", reason))?;
+    if !config.types.is_empty() && !config.types.contains(&commit.commit_type) {
+        return Err(format!(
+            "has the type `{}`, expected one of {}",
+            commit.commit_type,
+            config.types.join(", ")
+        ));
+    }
+    Ok(())
+}
+
+/// The metadata of the received commits reachable from `tips`, the new ids of the refs
+/// updated, whose messages are Conventional Commits. The commits of rejected refs are left out.
+pub fn received_commits(
+    received: &ReceivedObjects,
+    tips: &[String],
+) -> Vec<conventional_commit::Model> {
+    let mut pending = tips.to_vec();
+    let mut seen = HashSet::new();
+    let mut models = vec![];
+    while let Some(id) = pending.pop() {
+        if !seen.insert(id.clone()) {
+            continue;
+        }
+        let Some((_, parents)) = received.commits.get(&id) else {
+            continue;
+        };
+        let message = received.messages.get(&id).map_or("", String::as_str);
+        if let Ok(commit) = conventional::parse(conventional::message_text(message)) {
+            models.push(to_model(&id, commit));
+        }
+        pending.extend(parents.iter().cloned());
+    }
+    models
+}
+
+/// The stored metadata of the commit `commit_id`.
+pub fn to_model(commit_id: &str, commit: ConventionalCommit) -> conventional_commit::Model {
+    conventional_commit::Model {
+        id: generate_id(),
+        commit_id: commit_id.to_owned(),
+        commit_type: commit.commit_type,
+        scope: commit.scope,
+        breaking: commit.breaking,
+        description: commit.description,
+        body: commit.body,
+        footers: serde_json::to_string(&commit.footers).unwrap(),
+        created_at: chrono::Utc::now().naive_utc(),
+    }
+}
+
+/// The commit of stored metadata.
+pub fn from_model(model: conventional_commit::Model) -> ConventionalCommit {
+    ConventionalCommit {
+        commit_type: model.commit_type,
+        scope: model.scope,
+        breaking: model.breaking,
+        description: model.description,
+        body: model.body,
+        footers: serde_json::from_str(&model.footers).unwrap_or_default(),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn received(commits: &[(&str, &[&str], &str)]) -> ReceivedObjects {
+        let mut received = ReceivedObjects::default();
+        for (id, parents, message) in commits {
+            let parents = parents.iter().map(|p| p.to_string()).collect();
+            received
+                .commits
+                .insert(id.to_string(), (String::new(), parents));
+            received
+                .messages
+                .insert(id.to_string(), format!("\n{}\n", message));
+        }
+        received
+    }
+
+    #[test]
+    fn test_check_commits() {
+        let mut config = CommitPolicyConfig {
+            conventional_commits: true,
+            ..Default::default()
+        };
+        let received = received(&[
+            ("c3", &["c2"], "fix(pack): check the deltas"),
+            ("c2", &["c1", "x1"], "Merge branch 'main'"),
+            ("c1", &["c0"], "update readme"),
+            ("x1", &["c0"], "feat: add x"),
+        ]);
+        let err = check_commits(&config, "c3", &received).unwrap_err();
+        assert!(err.starts_with("commit c1 is not a conventional commit"));
+        assert!(check_commits(&config, "x1", &received).is_ok());
+        // the commits which aren't received are stored already
+        assert!(check_commits(&config, "c0", &received).is_ok());
+
+        config.types = vec!["fix".to_owned()];
+        let err = check_commits(&config, "x1", &received).unwrap_err();
+        assert_eq!(err, "commit x1 has the type `feat`, expected one of fix");
+
+        config.conventional_commits = false;
+        assert!(check_commits(&config, "c3", &received).is_ok());
+    }
+
+    #[test]
+    fn test_received_commits() {
+        let received = received(&[
+            (
+                "c2",
+                &["c1"],
+                "feat(api)!: rename\n\nBREAKING CHANGE: paths changed",
+            ),
+            ("c1", &[], "initial commit"),
+            ("r1", &["c2"], "fix: rejected"),
+        ]);
+        let models = received_commits(&received, &["c2".to_owned()]);
+        assert_eq!(models.len(), 1);
+        assert_eq!(models[0].commit_id, "c2");
+        let commit = from_model(models[0].clone());
+        assert!(commit.breaking);
+        assert_eq!(commit.breaking_note(), Some("paths changed"));
+    }
+}
@@ -2,10 +2,12 @@ use core::fmt;
 use std::{path::PathBuf, str::FromStr, sync::Arc};
 
 use callisto::db_enums::RefType;
-use common::{errors::MegaError, utils::ZERO_ID};
+use common::errors::MegaError;
 use jupiter::context::Context;
+use mercury::hash::HashKind;
 use venus::{import_repo::import_refs::RefCommand, import_repo::repo::Repo};
 
+use crate::hooks::ReceiveHook;
 use crate::pack::{handler::PackHandler, import_repo::ImportRepo, monorepo::MonoRepo};
 
 pub mod smart;
@@ -19,6 +21,11 @@ pub struct SmartProtocol {
     // only needed in ssh protocal
     pub service_type: ServiceType,
     pub context: Context,
+    // the identity authenticated by the transport layer, used as the owner of lfs locks,
+    // none of the transports authenticate users yet so it stays empty
+    pub username: Option<String>,
+    // the hooks run for a push besides the external ones of the config
+    pub hooks: Vec<Arc<dyn ReceiveHook>>,
 }
 
 #[derive(Debug, PartialEq, Clone, Copy, Default)]
@@ -74,6 +81,8 @@ pub enum Capability {
     OfsDelta,
     DeepenSince,
     DeepenNot,
+    ThinPack,
+    ObjectFormat(HashKind),
 }
 
 impl FromStr for Capability {
@@ -91,7 +100,11 @@ impl FromStr for Capability {
             "no-done" => Ok(Capability::NoDone),
             "deepen-since" => Ok(Capability::DeepenSince),
             "deepen-not" => Ok(Capability::DeepenNot),
-            _ => Err(()),
+            "thin-pack" => Ok(Capability::ThinPack),
+            _ => match s.strip_prefix("object-format=") {
+                Some(kind) => kind.parse().map(Capability::ObjectFormat).map_err(|_| ()),
+                None => Err(()),
+            },
         }
     }
 }
@@ -127,9 +140,17 @@ impl SmartProtocol {
             command_list: Vec::new(),
             service_type: ServiceType::ReceivePack,
             context,
+            username: None,
+            hooks: Vec::new(),
         }
     }
 
+    /// Run `hooks` for the pushes, see [`crate::hooks`].
+    pub fn with_hooks(mut self, hooks: Vec<Arc<dyn ReceiveHook>>) -> Self {
+        self.hooks = hooks;
+        self
+    }
+
     pub fn mock() -> Self {
         let context = Context::mock();
         SmartProtocol {
@@ -139,6 +160,8 @@ impl SmartProtocol {
             command_list: Vec::new(),
             service_type: ServiceType::ReceivePack,
             context,
+            username: None,
+            hooks: Vec::new(),
         }
     }
 
@@ -1,16 +1,27 @@
+use std::path::{Path, PathBuf};
 use std::pin::Pin;
+use std::sync::Arc;
 
 use anyhow::Result;
 use bytes::{Buf, BufMut, Bytes, BytesMut};
-use futures::Stream;
-use tokio_stream::wrappers::ReceiverStream;
+use futures::{Stream, StreamExt};
+use tokio::io::AsyncWriteExt;
 
 use callisto::db_enums::RefType;
-
-use crate::protocol::ZERO_ID;
-use crate::protocol::{
-    Capability, RefCommand, ServiceType, SideBind, SmartProtocol, TransportProtocol,
+use common::utils::is_zero_id;
+use mercury::{
+    errors::GitError,
+    hash::{HashKind, SHA1},
 };
+use venus::import_repo::import_refs::{CommandType, RefCommand};
+
+use crate::hooks::{Hooks, Push};
+use crate::lfs::handler::{lfs_find_foreign_lock, lfs_foreign_locks};
+use crate::lfs::lfs_structs::Lock;
+use crate::pack::connectivity::ReceivedObjects;
+use crate::pack::handler::{storage_lookup, PackDataStream, PackHandler};
+use crate::policy;
+use crate::protocol::{Capability, ServiceType, SideBind, SmartProtocol, TransportProtocol};
 
 const LF: char = '\n';
 
@@ -22,7 +33,7 @@ pub const PKT_LINE_END_MARKER: &[u8; 4] = b"0000";
 
 // The atomic, report-status, report-status-v2, delete-refs, quiet,
 // and push-cert capabilities are sent and recognized by the receive-pack (push to server) process.
-const RECEIVE_CAP_LIST: &str = "report-status report-status-v2 delete-refs quiet atomic no-thin ";
+const RECEIVE_CAP_LIST: &str = "report-status report-status-v2 delete-refs quiet atomic ";
 
 // The ofs-delta and side-band-64k capabilities are sent and recognized by both upload-pack and receive-pack protocols.
 // The agent and session-id capabilities may optionally be sent in both protocols.
@@ -30,7 +41,7 @@ const COMMON_CAP_LIST: &str = "side-band-64k ofs-delta agent=mega/0.1.0";
 
 // All other capabilities are only recognized by the upload-pack (fetch from server) process.
 const UPLOAD_CAP_LIST: &str =
-    "shallow deepen-since deepen-not deepen-relative multi_ack_detailed no-done include-tag ";
+    "shallow deepen-since deepen-not deepen-relative multi_ack_detailed no-done include-tag thin-pack ";
 
 impl SmartProtocol {
     /// # Retrieves the information about Git references (refs) for the specified service type.
@@ -64,15 +75,17 @@ impl SmartProtocol {
 
         // The stream MUST include capability declarations behind a NUL on the first ref.
         let (head_hash, git_refs) = pack_handler.head_hash().await;
-        let name = if head_hash == ZERO_ID {
-            "capabilities^{}"
+        let hash_kind = self.repo_hash_kind(&pack_handler).await;
+        let (head_hash, name) = if is_zero_id(&head_hash) {
+            (SHA1::zero(hash_kind).to_plain_str(), "capabilities^{}")
         } else {
-            "HEAD"
+            (head_hash, "HEAD")
         };
         let cap_list = match service_type {
             ServiceType::UploadPack => format!("{}{}", UPLOAD_CAP_LIST, COMMON_CAP_LIST),
             ServiceType::ReceivePack => format!("{}{}", RECEIVE_CAP_LIST, COMMON_CAP_LIST),
         };
+        let cap_list = format!("{} object-format={}", cap_list, hash_kind);
         let pkt_line = format!("{}{}{}{}{}{}", head_hash, SP, name, NUL, cap_list, LF);
         let mut ref_list = vec![pkt_line];
 
@@ -88,7 +101,7 @@ impl SmartProtocol {
     pub async fn git_upload_pack(
         &mut self,
         upload_request: &mut Bytes,
-    ) -> Result<(ReceiverStream<Vec<u8>>, BytesMut)> {
+    ) -> Result<(PackDataStream, BytesMut)> {
         let pack_handler = self.pack_handler().await;
 
         let mut want: Vec<String> = Vec::new();
@@ -110,11 +123,21 @@ impl SmartProtocol {
             let commands = &dst[0..4];
 
             match commands {
-                b"want" => {
-                    want.push(String::from_utf8(dst[5..45].to_vec()).unwrap());
-                }
-                b"have" => {
-                    have.push(String::from_utf8(dst[5..45].to_vec()).unwrap());
+                b"want" | b"have" => {
+                    // the object id is 40 or 64 hex digits depending on the object format,
+                    // the first line carries the capabilities after it
+                    let line = String::from_utf8(dst[5..].to_vec()).unwrap();
+                    let line = line.trim_end();
+                    let (id, caps) = line.split_once(' ').unwrap_or((line, ""));
+                    if commands == b"want" {
+                        want.push(id.to_owned());
+                    } else {
+                        have.push(id.to_owned());
+                    }
+                    if !read_first_line {
+                        self.parse_capabilities(caps);
+                        read_first_line = true;
+                    }
                 }
                 b"done" => break,
                 other => {
@@ -125,10 +148,6 @@ impl SmartProtocol {
                     continue;
                 }
             };
-            if !read_first_line {
-                self.parse_capabilities(&String::from_utf8(dst[46..].to_vec()).unwrap());
-                read_first_line = true;
-            }
         }
 
         tracing::info!(
@@ -159,7 +178,11 @@ impl SmartProtocol {
                     }
                 }
                 pack_data = pack_handler
-                    .incremental_pack(want.clone(), have)
+                    .incremental_pack(
+                        want.clone(),
+                        have,
+                        self.capabilities.contains(&Capability::ThinPack),
+                    )
                     .await
                     .unwrap();
 
@@ -179,9 +202,7 @@ impl SmartProtocol {
                 }
             } else {
                 tracing::error!("capability unsupported");
-                // init a empty receiverstream
-                let (_, rx) = tokio::sync::mpsc::channel::<Vec<u8>>(1);
-                pack_data = ReceiverStream::new(rx);
+                pack_data = Box::pin(futures::stream::empty());
             }
             add_pkt_line_string(&mut protocol_buf, format!("ACK {} \n", last_common_commit));
         }
@@ -211,9 +232,39 @@ impl SmartProtocol {
         // After receiving the pack data from the sender, the receiver sends a report
         let mut report_status = BytesMut::new();
         let pack_handler = self.pack_handler().await;
+        let pack_file = if pack_handler.keep_pack() {
+            let path = self
+                .context
+                .config
+                .pack
+                .pack_decode_cache_path
+                .join(format!("receive-{:016x}.pack", rand::random::<u64>()));
+            Some(path)
+        } else {
+            None
+        };
+        let data_stream = match &pack_file {
+            Some(path) => match tee_pack_file(data_stream, path).await {
+                Ok(stream) => stream,
+                Err((stream, err)) => {
+                    tracing::warn!("can't keep the pack file {:?}: {}", path, err);
+                    stream
+                }
+            },
+            None => data_stream,
+        };
         //1. unpack progress
-        let receiver = pack_handler
-            .unpack_stream(&self.context.config.pack, data_stream)
+        let hash_kind = match self.object_format() {
+            Some(kind) => kind,
+            None => self.repo_hash_kind(&pack_handler).await,
+        };
+        let (receiver, decoding) = pack_handler
+            .unpack_stream(
+                &self.context.config.pack,
+                data_stream,
+                storage_lookup(pack_handler.clone()),
+                hash_kind,
+            )
             .await
             .unwrap();
 
@@ -224,29 +275,128 @@ impl SmartProtocol {
             handle.block_on(async { ph_clone.handle_receiver(receiver).await })
         })
         .await.unwrap();
+        // a broken pack or stream ends the entries early, report why if the entries were stored,
+        // an error of the handler is what stopped the decoding otherwise
+        let decoded = decoding.await;
+        let unpack_result = match (unpack_result, decoded) {
+            (Err(err), _) => Err(err),
+            (Ok(received), Ok(Ok(()))) => Ok(received),
+            (Ok(_), Ok(Err(err))) => Err(err),
+            (Ok(_), Err(err)) => Err(GitError::CustomError(err.to_string())),
+        };
 
-        // write "unpack ok\n to report"
-        add_pkt_line_string(&mut report_status, "unpack ok\n".to_owned());
+        if let Some(path) = pack_file {
+            if unpack_result.is_ok() && path.exists() {
+                if let Err(err) = pack_handler.save_pack(&path, hash_kind).await {
+                    tracing::error!("failed to keep the pack file: {}", err);
+                }
+            }
+            let _ = std::fs::remove_file(&path);
+        }
+
+        // write "unpack ok\n" to report, or the reason why objects were not stored
+        match unpack_result {
+            Ok(_) => add_pkt_line_string(&mut report_status, "unpack ok\n".to_owned()),
+            Err(ref err) => {
+                tracing::error!("unpack failed: {}", err);
+                add_pkt_line_string(&mut report_status, format!("unpack {}\n", err))
+            }
+        }
 
         let mut default_exist = pack_handler.check_default_branch().await;
+        let mut tips = vec![];
+        let mut updated = vec![];
+
+        let no_objects = ReceivedObjects::default();
+        let push = Push {
+            path: &self.path,
+            username: self.username.as_deref(),
+            commands: &self.command_list,
+            objects: unpack_result.as_ref().unwrap_or(&no_objects),
+            pack_handler: &pack_handler,
+        };
+        let hooks = Hooks::for_push(
+            &self.hooks,
+            &self.context.config.hooks,
+            &self.context.config.pack.pack_decode_cache_path,
+            &push,
+        )
+        .await;
+        // a rejection by a pre-receive hook fails every ref of the push
+        let declined = match unpack_result {
+            Ok(_) => hooks.pre_receive(&push).await.err(),
+            Err(_) => None,
+        };
 
         //2. update each refs and build report
         for mut command in self.command_list.clone() {
-            if command.ref_type == RefType::Tag {
+            if let Some(reason) = &declined {
+                command.failed(reason.clone());
+            } else if command.ref_type == RefType::Tag {
                 // just update if refs type is tag
+                if let Err(reason) = hooks.update(&push, &command).await {
+                    command.failed(reason);
+                    add_pkt_line_string(&mut report_status, command.get_status());
+                    continue;
+                }
                 pack_handler.update_refs(&command).await.unwrap();
+                updated.push(command.clone());
             } else {
                 // Updates can be unsuccessful for a number of reasons.
                 // a.The reference can have changed since the reference discovery phase was originally sent, meaning someone pushed in the meantime.
                 // b.The reference being pushed could be a non-fast-forward reference and the update hooks or configuration could be set to not allow that, etc.
                 // c.Also, some references can be updated while others can be rejected.
                 match unpack_result {
-                    Ok(_) => {
+                    Ok(ref received) => {
+                        if command.command_type != CommandType::Delete {
+                            if let Err(err) = pack_handler
+                                .check_connectivity(&command.new_id, received)
+                                .await
+                            {
+                                command.failed(format!("missing necessary objects: {}", err));
+                                add_pkt_line_string(&mut report_status, command.get_status());
+                                continue;
+                            }
+                        }
+                        if command.command_type != CommandType::Delete {
+                            if let Err(reason) = policy::check_commits(
+                                &self.context.config.commit_policy,
+                                &command.new_id,
+                                received,
+                            ) {
+                                command.failed(reason);
+                                add_pkt_line_string(&mut report_status, command.get_status());
+                                continue;
+                            }
+                        }
+                        match self.find_foreign_lock(&pack_handler, &command).await {
+                            Ok(None) => {}
+                            Ok(Some(lock)) => {
+                                let owner = lock.owner.map_or("no one".to_owned(), |u| u.name);
+                                command.failed(format!("{} is locked by {}", lock.path, owner));
+                                add_pkt_line_string(&mut report_status, command.get_status());
+                                continue;
+                            }
+                            Err(reason) => {
+                                command.failed(reason);
+                                add_pkt_line_string(&mut report_status, command.get_status());
+                                continue;
+                            }
+                        }
+                        if let Err(reason) = hooks.update(&push, &command).await {
+                            command.failed(reason);
+                            add_pkt_line_string(&mut report_status, command.get_status());
+                            continue;
+                        }
                         if !default_exist {
                             command.default_branch = true;
                             default_exist = true;
                         }
                         pack_handler.update_refs(&command).await.unwrap();
+                        if command.command_type != CommandType::Delete {
+                            tips.push(command.new_id.clone());
+                        }
+                        updated.push(command.clone());
                     }
                     Err(ref err) => {
                         command.failed(err.to_string());
@@ -255,6 +405,17 @@ impl SmartProtocol {
             }
             add_pkt_line_string(&mut report_status, command.get_status());
         }
+        if let Ok(ref received) = unpack_result {
+            let commits = policy::received_commits(received, &tips);
+            if let Err(err) = pack_handler.index_push(received, tips).await {
+                tracing::warn!("failed to index the push: {}", err);
+            }
+            let storage = self.context.services.conventional_commit_storage.clone();
+            if let Err(err) = storage.save_commits(commits).await {
+                tracing::warn!("failed to save the conventional commits: {}", err);
+            }
+        }
+        hooks.post_receive(&push, &updated).await;
         report_status.put(&PKT_LINE_END_MARKER[..]);
         let length = report_status.len();
         let mut buf = self.build_side_band_format(report_status, length);
@@ -262,6 +423,39 @@ impl SmartProtocol {
         Ok(buf.into())
     }
 
+    /// Check the files modified by a ref update against the LFS locks of that ref.
+    ///
+    /// Returns the first lock held by someone other than the pushing user which covers a
+    /// changed path. When the locks can't be checked the update fails with the reason.
+    async fn find_foreign_lock(
+        &self,
+        pack_handler: &Arc<dyn PackHandler>,
+        command: &RefCommand,
+    ) -> Result<Option<Lock>, String> {
+        if command.command_type == CommandType::Delete {
+            return Ok(None);
+        }
+        let lfs_storage = self.context.services.lfs_storage.clone();
+        let username = self.username.as_deref();
+        let locks = lfs_foreign_locks(lfs_storage, &command.ref_name, username)
+            .await
+            .map_err(|err| {
+                tracing::error!("failed to load lfs locks: {}", err);
+                "the lfs locks can't be checked".to_owned()
+            })?;
+        if locks.is_empty() {
+            return Ok(None);
+        }
+        let paths = pack_handler
+            .changed_paths(&command.old_id, &command.new_id)
+            .await
+            .map_err(|err| {
+                tracing::error!("failed to compute changed paths: {}", err);
+                "the changed paths can't be checked against the lfs locks".to_owned()
+            })?;
+        Ok(lfs_find_foreign_lock(locks, &paths))
+    }
+
     /// # Builds the packet data in the sideband format if the SideBand/64k capability is enabled.
     ///
     /// If the `SideBand` or `SideBand64k` capability is present in the `capabilities` vector,
@@ -294,6 +488,23 @@ impl SmartProtocol {
         from_bytes
     }
 
+    /// The message of an error which ends the pack, on sideband 3 when sideband is in use,
+    /// the client can't be told otherwise.
+    pub fn build_side_band_error(&self, message: &str) -> Option<Bytes> {
+        let capabilities = &self.capabilities;
+        if capabilities.contains(&Capability::SideBand)
+            || capabilities.contains(&Capability::SideBand64k)
+        {
+            let mut to_bytes = BytesMut::new();
+            to_bytes.put(Bytes::from(format!("{:04x}", message.len() + 6)));
+            to_bytes.put_u8(SideBind::Error.value());
+            to_bytes.put(message.as_bytes());
+            to_bytes.put_u8(b'\n');
+            return Some(to_bytes.freeze());
+        }
+        None
+    }
+
     pub fn build_smart_reply(&self, ref_list: &Vec<String>, service: String) -> BytesMut {
         let mut pkt_line_stream = BytesMut::new();
         if self.transport_protocol == TransportProtocol::Http {
@@ -318,6 +529,31 @@ impl SmartProtocol {
         }
     }
 
+    /// The object format of the repo, the `monorepo.object_format` config for a repo without
+    /// refs yet, which takes the format of its first push.
+    async fn repo_hash_kind(&self, pack_handler: &Arc<dyn PackHandler>) -> HashKind {
+        let (head_hash, _) = pack_handler.head_hash().await;
+        if !is_zero_id(&head_hash) {
+            return pack_handler.hash_kind().await;
+        }
+        let object_format = &self.context.config.monorepo.object_format;
+        object_format.parse().unwrap_or_else(|_| {
+            tracing::warn!(
+                "unknown monorepo.object_format {}, sha1 is used",
+                object_format
+            );
+            HashKind::default()
+        })
+    }
+
+    /// The object format the client asked for with the `object-format` capability.
+    pub fn object_format(&self) -> Option<HashKind> {
+        self.capabilities.iter().find_map(|cap| match cap {
+            Capability::ObjectFormat(kind) => Some(*kind),
+            _ => None,
+        })
+    }
+
     // the first line contains the capabilities
     pub fn parse_ref_command(&self, pkt_line: &mut Bytes) -> RefCommand {
         RefCommand::new(
@@ -328,6 +564,47 @@ impl SmartProtocol {
     }
 }
 
+type PackStream = Pin<Box<dyn Stream<Item = Result<Bytes, axum::Error>> + Send>>;
+
+/// Copy the pack data into `path` while it is being decoded,
+/// the stream is given back if the file can't be created.
+async fn tee_pack_file(
+    stream: PackStream,
+    path: &Path,
+) -> std::result::Result<PackStream, (PackStream, std::io::Error)> {
+    let file = match path.parent() {
+        Some(dir) => tokio::fs::create_dir_all(dir).await,
+        None => Ok(()),
+    };
+    let file = match file {
+        Ok(()) => tokio::fs::File::create(path).await,
+        Err(err) => Err(err),
+    };
+    let mut file = match file {
+        Ok(file) => file,
+        Err(err) => return Err((stream, err)),
+    };
+    let path = PathBuf::from(path);
+    Ok(Box::pin(async_stream::stream! {
+        let mut stream = stream;
+        let mut failed = false;
+        while let Some(chunk) = stream.next().await {
+            if let (Ok(data), false) = (&chunk, failed) {
+                if let Err(err) = file.write_all(data).await {
+                    tracing::warn!("failed to write pack file {:?}: {}", path, err);
+                    failed = true;
+                }
+            }
+            yield chunk;
+        }
+        if !failed {
+            if let Err(err) = file.flush().await {
+                tracing::warn!("failed to write pack file {:?}: {}", path, err);
+            }
+        }
+    }))
+}
+
 fn read_until_white_space(bytes: &mut Bytes) -> String {
     let mut buf = Vec::new();
     while bytes.has_remaining() {
@@ -396,12 +673,24 @@ pub fn read_pkt_line(bytes: &mut Bytes) -> (usize, Bytes) {
 
 #[cfg(test)]
 pub mod test {
+    use std::path::PathBuf;
+
     use bytes::{Bytes, BytesMut};
     use callisto::db_enums::RefType;
+    use common::config::Config;
+    use jupiter::context::Context;
+    use mercury::hash::HashKind;
+    use mercury::internal::object::{
+        blob::Blob,
+        commit::Commit,
+        tree::{Tree, TreeItem, TreeItemMode},
+    };
+    use mercury::internal::pack::{encode::PackEncoder, entry::Entry};
+    use tokio::sync::mpsc;
     use venus::import_repo::import_refs::{CommandType, RefCommand};
 
     use crate::protocol::smart::{add_pkt_line_string, read_pkt_line, read_until_white_space};
-    use crate::protocol::{Capability, SmartProtocol};
+    use crate::protocol::{Capability, SmartProtocol, TransportProtocol};
 
     #[test]
     pub fn test_read_pkt_line() {
@@ -479,4 +768,66 @@ pub mod test {
             vec![Capability::ReportStatusv2, Capability::SideBand64k]
         );
     }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_push_sha256_to_empty_repo() {
+        let dir = std::env::temp_dir().join(format!("mega-sha256-{:016x}", rand::random::<u64>()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let mut config = Config::default();
+        config.database.db_path = dir.join("mega.db").to_str().unwrap().to_owned();
+        config.storage.raw_obj_local_path = dir.join("objects");
+        config.pack.pack_decode_cache_path = dir.join("cache");
+        config.monorepo.object_format = String::from("sha256");
+        let context = Context::new(config).await;
+        context.services.mega_storage.init_monorepo().await;
+        let path = PathBuf::from("/third-part/sha256.git");
+        let mut smart = SmartProtocol::new(path, context, TransportProtocol::Http);
+
+        let info_refs = smart.git_info_refs().await;
+        let info_refs = String::from_utf8_lossy(&info_refs);
+        assert!(info_refs.contains(&format!("{} capabilities^{{}}", "0".repeat(64))));
+        assert!(info_refs.contains("object-format=sha256"));
+
+        let blob = Blob::from_content_with_kind(b"hello".to_vec(), HashKind::Sha256);
+        let tree = Tree::from_tree_items(vec![TreeItem::new(
+            TreeItemMode::Blob,
+            blob.id,
+            "a.txt".to_string(),
+        )])
+        .unwrap();
+        let commit = Commit::from_tree_id(tree.id, vec![], "init");
+        let (tx, mut rx) = mpsc::channel(100);
+        let (entry_tx, entry_rx) = mpsc::channel::<Entry>(100);
+        let mut encoder = PackEncoder::new(10, tx).with_hash_kind(HashKind::Sha256);
+        for entry in [blob.into(), tree.into(), Entry::from(commit.clone())] {
+            entry_tx.send(entry).await.unwrap();
+        }
+        drop(entry_tx);
+        encoder.encode(entry_rx).await.unwrap();
+        let mut pack_data = Vec::new();
+        while let Some(chunk) = rx.recv().await {
+            pack_data.extend(chunk);
+        }
+
+        let mut protocol = BytesMut::new();
+        add_pkt_line_string(
+            &mut protocol,
+            format!(
+                "{} {} refs/heads/main\0report-status object-format=sha256\n",
+                "0".repeat(64),
+                commit.id
+            ),
+        );
+        smart.git_receive_pack_protocol(protocol.freeze());
+        let data_stream = Box::pin(futures::stream::once(async { Ok(Bytes::from(pack_data)) }));
+        let report = smart.git_receive_pack_stream(data_stream).await.unwrap();
+        let report = String::from_utf8_lossy(&report);
+        assert!(report.contains("unpack ok"), "{}", report);
+        assert!(report.contains("ok refs/heads/main"), "{}", report);
+
+        let pack_handler = smart.pack_handler().await;
+        assert_eq!(pack_handler.hash_kind().await, HashKind::Sha256);
+        assert_eq!(pack_handler.head_hash().await.0, commit.id.to_plain_str());
+        let _ = std::fs::remove_dir_all(&dir);
+    }
 }
@@ -0,0 +1,453 @@
+//! Code search of the monorepo.
+//!
+//! The files reachable from the `/` ref are indexed by the trigrams of their blobs and the
+//! symbols they define, see [`jupiter::storage::search_storage`]. [`SearchIndex::update`]
+//! indexes the changes since the last indexed commit, it runs after every merge. A query
+//! finds the blobs with all its trigrams, then matches their lines.
+
+pub mod symbol;
+pub mod trigram;
+
+use std::collections::{HashMap, HashSet};
+use std::path::PathBuf;
+use std::str::FromStr;
+
+use regex::{Regex, RegexBuilder};
+use tokio::sync::Mutex;
+
+use common::errors::MegaError;
+use jupiter::context::Context;
+use mercury::hash::SHA1;
+use mercury::internal::object::tree::TreeItemMode;
+use mercury::internal::tree_diff::{diff_trees, DiffOptions};
+
+use crate::model::query::SearchQuery;
+use crate::model::search::{FileMatch, LineMatch, SearchResult, SymbolMatch};
+use crate::pack::{handler::HandlerStore, monorepo::MonoRepo};
+
+/// The ref which is indexed.
+const INDEX_REF: &str = "/";
+/// Larger blobs are not indexed, and not searched.
+pub const MAX_INDEXED_SIZE: usize = 1024 * 1024;
+/// The most files, or symbols, of a search.
+pub const MAX_SEARCH_RESULTS: usize = 200;
+/// The most lines of context around a matching line.
+pub const MAX_CONTEXT_LINES: usize = 10;
+/// The most matching lines returned for a file.
+const MAX_LINES_PER_FILE: usize = 20;
+/// The most files read by a search, when the query has no trigram they are all candidates.
+const MAX_SCANNED_FILES: usize = 5_000;
+/// The most symbols read by a symbol search.
+const MAX_SCANNED_SYMBOLS: usize = 10_000;
+/// The blobs read from storage at a time.
+const BLOB_BATCH_SIZE: usize = 100;
+
+/// Updates run one at a time, a merge during an update is indexed by the next one.
+static INDEX_LOCK: Mutex<()> = Mutex::const_new(());
+
+#[derive(Clone)]
+pub struct SearchIndex {
+    pub context: Context,
+}
+
+impl SearchIndex {
+    pub fn new(context: Context) -> Self {
+        SearchIndex { context }
+    }
+
+    /// Update the index in the background.
+    pub fn spawn_update(&self) {
+        let index = self.clone();
+        tokio::spawn(async move {
+            if let Err(e) = index.update().await {
+                tracing::error!("failed to update the search index: {}", e);
+            }
+        });
+    }
+
+    /// Index the files changed from the indexed commit to the commit of the `/` ref.
+    pub async fn update(&self) -> Result<(), MegaError> {
+        let _guard = INDEX_LOCK.lock().await;
+        let mega_storage = self.context.services.mega_storage.clone();
+        let storage = self.context.services.search_storage.clone();
+        let Some(refs) = mega_storage.get_ref(INDEX_REF).await? else {
+            return Ok(());
+        };
+        let head = storage.get_head(INDEX_REF).await?;
+        if head
+            .as_ref()
+            .is_some_and(|head| head.commit_id == refs.ref_commit_hash)
+        {
+            return Ok(());
+        }
+        let old_tree = match head {
+            Some(head) => {
+                Some(SHA1::from_str(&head.tree_id).map_err(|e| MegaError::with_message(&e))?)
+            }
+            None => None,
+        };
+        let new_tree =
+            SHA1::from_str(&refs.ref_tree_hash).map_err(|e| MegaError::with_message(&e))?;
+
+        let monorepo = MonoRepo {
+            context: self.context.clone(),
+            path: PathBuf::from(INDEX_REF),
+            from_hash: None,
+            to_hash: None,
+        };
+        let changes = diff_trees(
+            &HandlerStore(&monorepo),
+            old_tree,
+            Some(new_tree),
+            &DiffOptions::plain(),
+        )
+        .await
+        .map_err(|e| MegaError::with_message(&e.to_string()))?;
+
+        let mut removed = Vec::new();
+        let mut saved = Vec::new();
+        let mut old_blobs = Vec::new();
+        for change in changes {
+            let path = format!("/{}", change.path());
+            if let Some(old) = &change.old {
+                old_blobs.push(old.id.to_plain_str());
+            }
+            match change.new {
+                Some(new) if new.mode != TreeItemMode::Commit => {
+                    let language = symbol::language(&path).map(str::to_owned);
+                    saved.push((path, new.id.to_plain_str(), language));
+                }
+                _ => removed.push(path),
+            }
+        }
+
+        // a blob is indexed once, with the language of one of its files
+        let mut blob_languages: HashMap<String, Option<String>> = HashMap::new();
+        for (_, blob_id, language) in &saved {
+            blob_languages
+                .entry(blob_id.clone())
+                .or_insert_with(|| language.clone());
+        }
+        let indexed = storage
+            .get_indexed_blobs(blob_languages.keys().cloned().collect())
+            .await?;
+        let new_blobs: Vec<String> = blob_languages
+            .keys()
+            .filter(|id| !indexed.contains(*id))
+            .cloned()
+            .collect();
+        for chunk in new_blobs.chunks(BLOB_BATCH_SIZE) {
+            let mut contents: HashMap<String, Vec<u8>> = mega_storage
+                .get_raw_blobs_by_hashes(chunk.to_vec())
+                .await?
+                .into_iter()
+                .map(|model| (model.sha1, model.data.unwrap_or_default()))
+                .collect();
+            for blob_id in chunk {
+                let data = contents.remove(blob_id).unwrap_or_default();
+                let (trigrams, symbols) = if is_searchable(&data) {
+                    let symbols = match &blob_languages[blob_id] {
+                        Some(language) => {
+                            symbol::symbols(language, &String::from_utf8_lossy(&data))
+                        }
+                        None => Vec::new(),
+                    };
+                    (trigram::trigrams(&data), symbols)
+                } else {
+                    (HashSet::new(), Vec::new())
+                };
+                storage
+                    .save_blob_index(
+                        blob_id,
+                        data.len(),
+                        !trigrams.is_empty(),
+                        trigrams.into_iter().collect(),
+                        symbols
+                            .into_iter()
+                            .map(|s| (s.name, s.kind, s.line as i32))
+                            .collect(),
+                    )
+                    .await?;
+            }
+        }
+
+        storage.remove_files(removed).await?;
+        storage.save_files(saved).await?;
+        storage.remove_unused_blobs(old_blobs).await?;
+        storage
+            .save_head(INDEX_REF, &refs.ref_commit_hash, &refs.ref_tree_hash)
+            .await
+    }
+
+    /// Search the lines of the indexed files, or the names of their symbols.
+    pub async fn search(&self, query: &SearchQuery) -> Result<SearchResult, MegaError> {
+        if query.q.is_empty() {
+            return Err(MegaError::with_message("the query is empty"));
+        }
+        let pattern = if query.regex {
+            query.q.clone()
+        } else {
+            regex::escape(&query.q)
+        };
+        let matcher = RegexBuilder::new(&pattern)
+            .case_insensitive(!query.case_sensitive)
+            .build()
+            .map_err(|e| MegaError::with_message(&format!("invalid query: {}", e)))?;
+        let filter = FileFilter::new(query);
+        let commit = self
+            .context
+            .services
+            .search_storage
+            .get_head(INDEX_REF)
+            .await?
+            .map(|head| head.commit_id)
+            .unwrap_or_default();
+        let mut result = SearchResult {
+            commit,
+            files: Vec::new(),
+            symbols: Vec::new(),
+            truncated: false,
+        };
+        let limit = query.limit.clamp(1, MAX_SEARCH_RESULTS);
+        if query.symbol {
+            self.search_symbols(&pattern, &matcher, &filter, limit, &mut result)
+                .await?;
+        } else {
+            let context = query.context.min(MAX_CONTEXT_LINES);
+            self.search_lines(&pattern, &matcher, &filter, limit, context, &mut result)
+                .await?;
+        }
+        Ok(result)
+    }
+
+    async fn search_lines(
+        &self,
+        pattern: &str,
+        matcher: &Regex,
+        filter: &FileFilter,
+        limit: usize,
+        context: usize,
+        result: &mut SearchResult,
+    ) -> Result<(), MegaError> {
+        let storage = self.context.services.search_storage.clone();
+        let trigrams = trigram::query_trigrams(pattern, filter.case_insensitive);
+        let blob_ids = if trigrams.is_empty() {
+            None
+        } else {
+            Some(storage.find_blobs_by_trigrams(&trigrams).await?)
+        };
+        let files: Vec<_> = storage
+            .get_files(
+                blob_ids,
+                &filter.path,
+                &filter.languages,
+                MAX_SCANNED_FILES as u64,
+            )
+            .await?
+            .into_iter()
+            .filter(|file| filter.matches_path(&file.path))
+            .collect();
+        result.truncated = files.len() >= MAX_SCANNED_FILES;
+
+        for chunk in files.chunks(BLOB_BATCH_SIZE) {
+            let blob_ids: HashSet<String> = chunk.iter().map(|f| f.blob_id.clone()).collect();
+            let contents: HashMap<String, Vec<u8>> = self
+                .context
+                .services
+                .mega_storage
+                .get_raw_blobs_by_hashes(blob_ids.into_iter().collect())
+                .await?
+                .into_iter()
+                .filter_map(|model| Some((model.sha1, model.data?)))
+                .collect();
+            for file in chunk {
+                let Some(data) = contents.get(&file.blob_id) else {
+                    continue;
+                };
+                if !is_searchable(data) {
+                    continue;
+                }
+                let lines = match_lines(&String::from_utf8_lossy(data), matcher, context);
+                if lines.is_empty() {
+                    continue;
+                }
+                if result.files.len() == limit {
+                    result.truncated = true;
+                    return Ok(());
+                }
+                result.files.push(FileMatch {
+                    path: file.path.clone(),
+                    oid: file.blob_id.clone(),
+                    language: file.language.clone(),
+                    lines,
+                });
+            }
+        }
+        Ok(())
+    }
+
+    async fn search_symbols(
+        &self,
+        pattern: &str,
+        matcher: &Regex,
+        filter: &FileFilter,
+        limit: usize,
+        result: &mut SearchResult,
+    ) -> Result<(), MegaError> {
+        let storage = self.context.services.search_storage.clone();
+        // the names are filtered by the longest literal of the query first
+        let needle = trigram::required_literals(pattern, false)
+            .into_iter()
+            .max_by_key(|x| x.len())
+            .map(|x| like_pattern(&String::from_utf8_lossy(&x)))
+            .unwrap_or_default();
+        let symbols: Vec<_> = storage
+            .find_symbols(&needle, MAX_SCANNED_SYMBOLS as u64)
+            .await?
+            .into_iter()
+            .filter(|symbol| matcher.is_match(&symbol.name))
+            .collect();
+        result.truncated = symbols.len() >= MAX_SCANNED_SYMBOLS;
+
+        let blob_ids: HashSet<String> = symbols.iter().map(|s| s.blob_id.clone()).collect();
+        let mut files: HashMap<String, Vec<_>> = HashMap::new();
+        for file in storage
+            .get_files_by_blobs(blob_ids.into_iter().collect())
+            .await?
+        {
+            if filter.matches_path(&file.path) && filter.matches_language(&file.language) {
+                files.entry(file.blob_id.clone()).or_default().push(file);
+            }
+        }
+        for symbol in symbols {
+            for file in files.get(&symbol.blob_id).into_iter().flatten() {
+                if result.symbols.len() == limit {
+                    result.truncated = true;
+                    return Ok(());
+                }
+                result.symbols.push(SymbolMatch {
+                    name: symbol.name.clone(),
+                    kind: symbol.kind.clone(),
+                    path: file.path.clone(),
+                    line: symbol.line as usize,
+                    oid: symbol.blob_id.clone(),
+                });
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The path and languages a search is limited to.
+struct FileFilter {
+    /// the directory, or file, without a trailing `/`, empty for the root
+    path: String,
+    languages: Vec<String>,
+    case_insensitive: bool,
+}
+
+impl FileFilter {
+    fn new(query: &SearchQuery) -> Self {
+        let path = query.path.trim_end_matches('/');
+        let path = if path.is_empty() || path.starts_with('/') {
+            path.to_owned()
+        } else {
+            format!("/{}", path)
+        };
+        let languages = query
+            .lang
+            .iter()
+            .flat_map(|lang| lang.split(','))
+            .map(|lang| lang.trim().to_ascii_lowercase())
+            .filter(|lang| !lang.is_empty())
+            .collect();
+        FileFilter {
+            path,
+            languages,
+            case_insensitive: !query.case_sensitive,
+        }
+    }
+
+    fn matches_path(&self, path: &str) -> bool {
+        path.strip_prefix(&self.path)
+            .is_some_and(|rest| rest.is_empty() || rest.starts_with('/'))
+    }
+
+    fn matches_language(&self, language: &Option<String>) -> bool {
+        self.languages.is_empty()
+            || language
+                .as_ref()
+                .is_some_and(|language| self.languages.contains(language))
+    }
+}
+
+/// Binary and large blobs are not searched.
+fn is_searchable(data: &[u8]) -> bool {
+    data.len() <= MAX_INDEXED_SIZE && !data[..data.len().min(8000)].contains(&0)
+}
+
+/// A `LIKE` pattern containing `literal` in lowercase: a non-ASCII character matches any
+/// character, as databases lowercase them differently.
+fn like_pattern(literal: &str) -> String {
+    literal
+        .chars()
+        .map(|c| {
+            if c.is_ascii() {
+                c.to_ascii_lowercase()
+            } else {
+                '_'
+            }
+        })
+        .collect()
+}
+
+/// The lines of `text` matching `matcher`, with `context` lines around them.
+fn match_lines(text: &str, matcher: &Regex, context: usize) -> Vec<LineMatch> {
+    let lines: Vec<&str> = text.lines().collect();
+    let to_strings = |lines: &[&str]| lines.iter().map(|x| x.to_string()).collect();
+    lines
+        .iter()
+        .enumerate()
+        .filter(|(_, line)| matcher.is_match(line))
+        .take(MAX_LINES_PER_FILE)
+        .map(|(i, line)| LineMatch {
+            line: i + 1,
+            content: line.to_string(),
+            before: to_strings(&lines[i.saturating_sub(context)..i]),
+            after: to_strings(&lines[i + 1..(i + 1 + context).min(lines.len())]),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_match_lines() {
+        let text = "one\ntwo\nthree\nfour\nTwo";
+        let matcher = RegexBuilder::new("two")
+            .case_insensitive(true)
+            .build()
+            .unwrap();
+        let lines = match_lines(text, &matcher, 1);
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[0].line, 2);
+        assert_eq!(lines[0].before, ["one"]);
+        assert_eq!(lines[0].after, ["three"]);
+        assert_eq!(lines[1].content, "Two");
+        assert!(lines[1].after.is_empty());
+    }
+
+    #[test]
+    fn test_file_filter() {
+        let query: SearchQuery =
+            serde_json::from_str(r#"{"q": "x", "path": "project/", "lang": "Rust, go"}"#).unwrap();
+        let filter = FileFilter::new(&query);
+        assert!(filter.matches_path("/project/src/lib.rs"));
+        assert!(!filter.matches_path("/projects/lib.rs"));
+        assert!(filter.matches_language(&Some("go".to_owned())));
+        assert!(!filter.matches_language(&None));
+        assert_eq!(like_pattern("Größe_"), "gr__e_");
+    }
+}
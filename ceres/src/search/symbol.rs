@@ -0,0 +1,194 @@
+//! The language of a file by its extension, and the symbols it defines.
+//!
+//! Symbols are found line by line with a few patterns for each language, like the tags of
+//! ctags: the definitions of functions, types, modules and macros.
+
+use std::collections::HashMap;
+use std::sync::OnceLock;
+
+use regex::Regex;
+
+/// `(language, patterns)`, a pattern names the symbol `name` and the keyword `kind`.
+const SYMBOL_PATTERNS: &[(&str, &[&str])] = &[
+    (
+        "rust",
+        &[
+            r#"^\s*(?:pub(?:\([^)]*\))?\s+)?(?:(?:const|async|unsafe|extern\s+"[^"]*")\s+)*(?P<kind>fn|struct|enum|union|trait|type|mod|const|static)\s+(?P<name>[A-Za-z_][A-Za-z0-9_]*)"#,
+            r"^\s*(?P<kind>macro_rules)!\s*(?P<name>[A-Za-z_][A-Za-z0-9_]*)",
+        ],
+    ),
+    (
+        "go",
+        &[
+            r"^(?P<kind>func)\s+(?:\([^)]*\)\s*)?(?P<name>[A-Za-z_][A-Za-z0-9_]*)",
+            r"^(?P<kind>type)\s+(?P<name>[A-Za-z_][A-Za-z0-9_]*)",
+        ],
+    ),
+    (
+        "python",
+        &[r"^\s*(?:async\s+)?(?P<kind>def|class)\s+(?P<name>[A-Za-z_][A-Za-z0-9_]*)"],
+    ),
+    (
+        "javascript",
+        &[
+            r"^\s*(?:export\s+)?(?:default\s+)?(?:async\s+)?(?P<kind>function|class)\s*\*?\s*(?P<name>[A-Za-z_$][A-Za-z0-9_$]*)",
+        ],
+    ),
+    (
+        "typescript",
+        &[
+            r"^\s*(?:export\s+)?(?:default\s+)?(?:declare\s+)?(?:abstract\s+)?(?:async\s+)?(?P<kind>function|class|interface|type|enum)\s*\*?\s*(?P<name>[A-Za-z_$][A-Za-z0-9_$]*)",
+        ],
+    ),
+    (
+        "java",
+        &[
+            r"^\s*(?:(?:public|protected|private|static|final|abstract|sealed)\s+)*(?P<kind>class|interface|enum|record)\s+(?P<name>[A-Za-z_][A-Za-z0-9_]*)",
+        ],
+    ),
+    (
+        "c",
+        &[
+            r"^\s*(?:typedef\s+)?(?P<kind>struct|enum|union)\s+(?P<name>[A-Za-z_][A-Za-z0-9_]*)\s*\{",
+            r"^\s*#\s*(?P<kind>define)\s+(?P<name>[A-Za-z_][A-Za-z0-9_]*)",
+        ],
+    ),
+    (
+        "cpp",
+        &[
+            r"^\s*(?:typedef\s+)?(?P<kind>struct|enum|union|class|namespace)\s+(?P<name>[A-Za-z_][A-Za-z0-9_]*)\s*(?:final\s*)?[:{]",
+            r"^\s*#\s*(?P<kind>define)\s+(?P<name>[A-Za-z_][A-Za-z0-9_]*)",
+        ],
+    ),
+];
+
+/// A definition found in a file.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Symbol {
+    pub name: String,
+    /// like `function`, `struct` or `class`
+    pub kind: String,
+    /// the line number, from 1
+    pub line: usize,
+}
+
+/// The language of a file by its extension, like `rust` for `lib.rs`.
+pub fn language(path: &str) -> Option<&'static str> {
+    let name = path.rsplit('/').next().unwrap_or(path);
+    let language = match name.rsplit_once('.')?.1.to_ascii_lowercase().as_str() {
+        "rs" => "rust",
+        "go" => "go",
+        "py" => "python",
+        "js" | "jsx" | "mjs" | "cjs" => "javascript",
+        "ts" | "tsx" | "mts" => "typescript",
+        "java" => "java",
+        "kt" | "kts" => "kotlin",
+        "c" | "h" => "c",
+        "cc" | "cpp" | "cxx" | "hh" | "hpp" | "hxx" => "cpp",
+        "sh" | "bash" => "shell",
+        "sql" => "sql",
+        "md" => "markdown",
+        "toml" => "toml",
+        "json" => "json",
+        "yaml" | "yml" => "yaml",
+        "html" | "htm" => "html",
+        "css" | "scss" => "css",
+        "proto" => "protobuf",
+        _ => return None,
+    };
+    Some(language)
+}
+
+fn patterns() -> &'static HashMap<&'static str, Vec<Regex>> {
+    static PATTERNS: OnceLock<HashMap<&'static str, Vec<Regex>>> = OnceLock::new();
+    PATTERNS.get_or_init(|| {
+        SYMBOL_PATTERNS
+            .iter()
+            .map(|(language, patterns)| {
+                let regexes = patterns.iter().map(|p| Regex::new(p).unwrap()).collect();
+                (*language, regexes)
+            })
+            .collect()
+    })
+}
+
+/// The kind of a symbol by the keyword defining it.
+fn kind(keyword: &str) -> &str {
+    match keyword {
+        "fn" | "func" | "def" => "function",
+        "mod" => "module",
+        "const" | "static" => "constant",
+        "macro_rules" | "define" => "macro",
+        keyword => keyword,
+    }
+}
+
+/// The symbols defined in `text`, a file of `language`.
+pub fn symbols(language: &str, text: &str) -> Vec<Symbol> {
+    let Some(patterns) = patterns().get(language) else {
+        return Vec::new();
+    };
+    let mut symbols = Vec::new();
+    for (i, line) in text.lines().enumerate() {
+        if let Some(captures) = patterns.iter().find_map(|p| p.captures(line)) {
+            symbols.push(Symbol {
+                name: captures["name"].to_owned(),
+                kind: kind(&captures["kind"]).to_owned(),
+                line: i + 1,
+            });
+        }
+    }
+    symbols
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_language() {
+        assert_eq!(language("/project/src/lib.rs"), Some("rust"));
+        assert_eq!(language("web/App.TSX"), Some("typescript"));
+        assert_eq!(language("Makefile"), None);
+        assert_eq!(language("dir.rs/README"), None);
+    }
+
+    #[test]
+    fn test_symbols() {
+        let text = "\
+pub(crate) async fn search() {}
+struct Index;
+impl Index {
+    pub const fn new() -> Self {}
+}
+const MAX: usize = 1;
+macro_rules! ensure {
+";
+        let found: Vec<_> = symbols("rust", text)
+            .into_iter()
+            .map(|s| (s.name, s.kind, s.line))
+            .collect();
+        let expected = [
+            ("search", "function", 1),
+            ("Index", "struct", 2),
+            ("new", "function", 4),
+            ("MAX", "constant", 6),
+            ("ensure", "macro", 7),
+        ];
+        assert_eq!(found.len(), expected.len());
+        for ((name, kind, line), (e_name, e_kind, e_line)) in found.iter().zip(expected) {
+            assert_eq!(
+                (name.as_str(), kind.as_str(), *line),
+                (e_name, e_kind, e_line)
+            );
+        }
+
+        let go = symbols(
+            "go",
+            "func (s *Server) Serve() error {\ntype Config struct {",
+        );
+        assert_eq!(go[0].name, "Serve");
+        assert_eq!(go[1].kind, "type");
+        assert!(symbols("markdown", "# fn title").is_empty());
+    }
+}
@@ -0,0 +1,181 @@
+//! Trigrams of file contents, and the trigrams a match of a query must contain.
+//!
+//! A trigram is three bytes lowercased as ASCII, packed in an `i32`. Lowercasing lets the
+//! index serve case-insensitive queries too.
+
+use std::collections::HashSet;
+
+use regex_syntax::hir::{Class, Hir, HirKind};
+use regex_syntax::ParserBuilder;
+
+/// The most trigrams of a query, any subset of them finds the same files and some more.
+const MAX_QUERY_TRIGRAMS: usize = 32;
+
+fn pack(t: &[u8]) -> i32 {
+    (t[0].to_ascii_lowercase() as i32) << 16
+        | (t[1].to_ascii_lowercase() as i32) << 8
+        | t[2].to_ascii_lowercase() as i32
+}
+
+/// The distinct trigrams of `data`.
+pub fn trigrams(data: &[u8]) -> HashSet<i32> {
+    data.windows(3).map(pack).collect()
+}
+
+/// The trigrams every line matching the regex `pattern` contains, empty when there is no such
+/// trigram, like for `a.*b`, or the pattern is not understood here.
+pub fn query_trigrams(pattern: &str, case_insensitive: bool) -> Vec<i32> {
+    let mut trigrams = Vec::new();
+    for literal in required_literals(pattern, case_insensitive) {
+        for t in literal.windows(3).map(pack) {
+            if !trigrams.contains(&t) {
+                trigrams.push(t);
+            }
+        }
+    }
+    trigrams.truncate(MAX_QUERY_TRIGRAMS);
+    trigrams
+}
+
+/// The lowercased literals every match of the regex `pattern` contains.
+///
+/// The pattern is parsed with ASCII classes, only to find literals: with `case_insensitive`
+/// the bytes which can also match a non-ASCII character, like `k` matching the Kelvin sign,
+/// end a literal, as the non-ASCII ones do.
+pub fn required_literals(pattern: &str, case_insensitive: bool) -> Vec<Vec<u8>> {
+    let hir = match ParserBuilder::new()
+        .unicode(false)
+        .utf8(false)
+        .case_insensitive(case_insensitive)
+        .build()
+        .parse(pattern)
+    {
+        Ok(hir) => hir,
+        Err(_) => return Vec::new(),
+    };
+    let mut literals = Vec::new();
+    collect_literals(&hir, case_insensitive, &mut literals);
+    literals.retain(|x| x.len() >= 3);
+    literals
+}
+
+fn collect_literals(hir: &Hir, case_insensitive: bool, literals: &mut Vec<Vec<u8>>) {
+    match hir.kind() {
+        HirKind::Capture(capture) => collect_literals(&capture.sub, case_insensitive, literals),
+        HirKind::Repetition(repetition) if repetition.min > 0 => {
+            collect_literals(&repetition.sub, case_insensitive, literals)
+        }
+        HirKind::Concat(subs) => {
+            let mut run = Vec::new();
+            for sub in subs {
+                match literal_bytes(sub, case_insensitive) {
+                    Some(bytes) => {
+                        for byte in bytes {
+                            match byte {
+                                Some(byte) => run.push(byte),
+                                None => literals.push(std::mem::take(&mut run)),
+                            }
+                        }
+                    }
+                    None => {
+                        literals.push(std::mem::take(&mut run));
+                        collect_literals(sub, case_insensitive, literals);
+                    }
+                }
+            }
+            literals.push(run);
+        }
+        _ => {
+            if let Some(bytes) = literal_bytes(hir, case_insensitive) {
+                literals.extend(
+                    bytes
+                        .split(Option::is_none)
+                        .map(|run| run.iter().flatten().copied().collect()),
+                );
+            }
+        }
+    }
+}
+
+/// The bytes `hir` always matches, `None` for a byte ending a literal, or `None` if it
+/// matches something else. Assertions like `\b` match no byte.
+fn literal_bytes(hir: &Hir, case_insensitive: bool) -> Option<Vec<Option<u8>>> {
+    let byte = |b: u8| {
+        let b = b.to_ascii_lowercase();
+        if case_insensitive && (!b.is_ascii() || b == b'k' || b == b's') {
+            None
+        } else {
+            Some(b)
+        }
+    };
+    match hir.kind() {
+        HirKind::Empty | HirKind::Look(_) => Some(Vec::new()),
+        HirKind::Literal(literal) => Some(literal.0.iter().map(|b| byte(*b)).collect()),
+        HirKind::Capture(capture) => literal_bytes(&capture.sub, case_insensitive),
+        HirKind::Class(class) => {
+            // a class of one byte in both cases, like the letters of `(?i)abc`
+            let mut bytes = HashSet::new();
+            match class {
+                Class::Bytes(class) => {
+                    for range in class.iter() {
+                        if range.end() - range.start() > 1 {
+                            return None;
+                        }
+                        bytes.insert(range.start().to_ascii_lowercase());
+                        bytes.insert(range.end().to_ascii_lowercase());
+                    }
+                }
+                Class::Unicode(class) => {
+                    for range in class.iter() {
+                        let (start, end) = (range.start(), range.end());
+                        if !end.is_ascii() || end as u32 - start as u32 > 1 {
+                            return None;
+                        }
+                        bytes.insert((start as u8).to_ascii_lowercase());
+                        bytes.insert((end as u8).to_ascii_lowercase());
+                    }
+                }
+            }
+            match bytes.into_iter().collect::<Vec<_>>()[..] {
+                [b] => Some(vec![byte(b)]),
+                _ => None,
+            }
+        }
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_required_literals() {
+        assert_eq!(required_literals("fn main", false), [b"fn main".to_vec()]);
+        assert_eq!(
+            required_literals(r"Foo\w+Bar(baz)+", false),
+            [b"foo".to_vec(), b"bar".to_vec(), b"baz".to_vec()]
+        );
+        assert!(required_literals("foo|bar", false).is_empty());
+        assert!(required_literals("(abc)?x", false).is_empty());
+        assert_eq!(required_literals(r"\bmain\(", false), [b"main(".to_vec()]);
+        // `k` and `s` can match non-ASCII characters when the case is ignored
+        assert_eq!(required_literals("TaskQueue", true), [b"queue".to_vec()]);
+        assert!(required_literals("(", false).is_empty());
+    }
+
+    #[test]
+    fn test_query_trigrams() {
+        let data = b"pub fn Search_Index() {}";
+        let index = trigrams(data);
+        for query in ["search_index", r"fn\s+search_index\(", "SEARCH"] {
+            let query = query_trigrams(query, true);
+            assert!(!query.is_empty());
+            assert!(query.iter().all(|t| index.contains(t)));
+        }
+        assert!(query_trigrams("search_indey", false)
+            .iter()
+            .any(|t| !index.contains(t)));
+        assert!(query_trigrams("a.*b", false).is_empty());
+    }
+}
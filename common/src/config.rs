@@ -19,6 +19,12 @@ pub struct Config {
     pub pack: PackConfig,
     pub ztm: ZTMConfig,
     pub lfs: LFSConfig,
+    #[serde(default)]
+    pub commit_policy: CommitPolicyConfig,
+    #[serde(default)]
+    pub hooks: HooksConfig,
+    #[serde(default)]
+    pub admin: AdminConfig,
 }
 
 impl Config {
@@ -195,24 +201,35 @@ impl Default for StorageConfig {
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
 pub struct MonoConfig {
     pub import_dir: PathBuf,
+    /// The object format advertised for the repos without refs yet, `sha1` or `sha256`.
+    pub object_format: String,
 }
 
 impl Default for MonoConfig {
     fn default() -> Self {
         Self {
             import_dir: PathBuf::from("/third-part"),
+            object_format: String::from("sha1"),
         }
     }
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
 pub struct PackConfig {
     pub pack_decode_mem_size: usize,
     pub pack_decode_cache_path: PathBuf,
     pub clean_cache_after_decode: bool,
     pub channel_message_size: usize,
+    pub store_import_packs: bool,
+    pub repack_threshold: usize,
+    pub delta_window: usize,
+    pub delta_depth: usize,
+    pub pack_encode_mem_size: usize,
+    pub pack_encode_cache_path: PathBuf,
 }
 
 impl Default for PackConfig {
@@ -222,6 +239,12 @@ impl Default for PackConfig {
             pack_decode_cache_path: PathBuf::from("/tmp/.mega/cache"),
             clean_cache_after_decode: true,
             channel_message_size: 1_000_000,
+            store_import_packs: true,
+            repack_threshold: 10,
+            delta_window: 10,
+            delta_depth: 50,
+            pack_encode_mem_size: 1,
+            pack_encode_cache_path: PathBuf::from("/tmp/.mega/encode_cache"),
         }
     }
 }
@@ -244,9 +267,11 @@ impl Default for ZTMConfig {
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
 pub struct LFSConfig {
     pub enable_split: bool,
     pub split_size: usize,
+    pub gc_grace_period: u64,
 }
 
 impl Default for LFSConfig {
@@ -254,6 +279,69 @@ impl Default for LFSConfig {
         Self {
             enable_split: true,
             split_size: 1024 * 1024 * 1024,
+            gc_grace_period: 7 * 24 * 60 * 60,
         }
     }
 }
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct CommitPolicyConfig {
+    pub conventional_commits: bool,
+    pub types: Vec<String>,
+}
+
+impl Default for CommitPolicyConfig {
+    fn default() -> Self {
+        Self {
+            conventional_commits: false,
+            types: [
+                "feat", "fix", "docs", "style", "refactor", "perf", "test", "build", "ci", "chore",
+                "revert",
+            ]
+            .map(String::from)
+            .to_vec(),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct HooksConfig {
+    /// The seconds an external hook may run, the refs it checks are rejected past it.
+    pub timeout: u64,
+    pub commands: Vec<HookCommandConfig>,
+}
+
+impl Default for HooksConfig {
+    fn default() -> Self {
+        Self {
+            timeout: 60,
+            commands: Vec::new(),
+        }
+    }
+}
+
+/// The admin API is only served to the requests sending `token` as
+/// `Authorization: Bearer <token>`, and it is disabled while `token` is empty.
+#[derive(Serialize, Deserialize, Debug, Default, Clone)]
+#[serde(default)]
+pub struct AdminConfig {
+    pub token: String,
+}
+
+/// An executable run as a hook of the pushes to the repos under `path`.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct HookCommandConfig {
+    pub stage: HookStage,
+    pub path: PathBuf,
+    pub command: PathBuf,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum HookStage {
+    PreReceive,
+    Update,
+    PostReceive,
+}
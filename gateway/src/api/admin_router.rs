@@ -0,0 +1,86 @@
+use std::sync::Arc;
+
+use axum::{
+    extract::State,
+    http::{header::AUTHORIZATION, HeaderMap, StatusCode},
+    routing::post,
+    Json, Router,
+};
+
+use ceres::lfs::{
+    gc::{self, GcOptions, GcReport},
+    LFS_REPO_NAME,
+};
+use ceres::pack::fsck::{self, FsckOptions};
+use common::model::CommonResult;
+use jupiter::raw_storage::local_storage::LocalStorage;
+use mercury::internal::fsck::FsckReport;
+
+use crate::api::ApiServiceState;
+
+pub fn routers() -> Router<ApiServiceState> {
+    Router::new()
+        .route("/admin/lfs/gc", post(lfs_gc))
+        .route("/admin/fsck", post(fsck))
+}
+
+/// Reject the requests without the token of the `admin` config.
+fn check_admin(state: &ApiServiceState, headers: &HeaderMap) -> Result<(), (StatusCode, String)> {
+    let token = &state.context.config.admin.token;
+    if token.is_empty() {
+        return Err((
+            StatusCode::FORBIDDEN,
+            "the admin API is disabled, set admin.token to enable it".to_owned(),
+        ));
+    }
+    let given = headers
+        .get(AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .unwrap_or_default();
+    // compare every byte, so the time taken doesn't tell how much of the token matched
+    let matched = given.len() == token.len()
+        && given
+            .bytes()
+            .zip(token.bytes())
+            .fold(0, |diff, (a, b)| diff | (a ^ b))
+            == 0;
+    if !matched {
+        return Err((StatusCode::UNAUTHORIZED, "invalid admin token".to_owned()));
+    }
+    Ok(())
+}
+
+/// Remove unreferenced lfs objects and optionally verify the stored bytes,
+/// use `dry_run` to preview the result without deleting anything.
+async fn lfs_gc(
+    state: State<ApiServiceState>,
+    headers: HeaderMap,
+    Json(options): Json<GcOptions>,
+) -> Result<Json<CommonResult<GcReport>>, (StatusCode, String)> {
+    check_admin(&state, &headers)?;
+    let lfs_storage = Arc::new(LocalStorage::init(
+        state.context.config.storage.lfs_obj_local_path.clone(),
+    ));
+    let res = gc::lfs_gc(state.context.clone(), lfs_storage, LFS_REPO_NAME, options).await;
+    let res = match res {
+        Ok(data) => CommonResult::success(Some(data)),
+        Err(err) => CommonResult::failed(&err.to_string()),
+    };
+    Ok(Json(res))
+}
+
+/// Verify the objects reachable from the refs of the monorepo path or import repo at `path`.
+async fn fsck(
+    state: State<ApiServiceState>,
+    headers: HeaderMap,
+    Json(options): Json<FsckOptions>,
+) -> Result<Json<CommonResult<FsckReport>>, (StatusCode, String)> {
+    check_admin(&state, &headers)?;
+    let res = fsck::fsck_repo(state.context.clone(), &options.path).await;
+    let res = match res {
+        Ok(data) => CommonResult::success(Some(data)),
+        Err(err) => CommonResult::failed(&err.to_string()),
+    };
+    Ok(Json(res))
+}
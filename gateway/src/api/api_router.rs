@@ -6,14 +6,24 @@ use axum::{
     Json, Router,
 };
 
+use ceres::api_service::ApiHandler;
 use ceres::model::{
+    blob::{BlobBatch, BlobBatchRequest},
+    changelog::Changelog,
     create_file::CreateFileInfo,
+    history::{BlameInfo, CommitHistory},
     publish_path::PublishPathInfo,
-    query::{BlobContentQuery, CodePreviewQuery},
-    tree::{LatestCommitInfo, TreeBriefItem, TreeCommitItem},
+    query::{
+        BlameQuery, BlobContentQuery, ChangelogQuery, CodePreviewQuery, HistoryQuery, SearchQuery,
+        TreeAtQuery,
+    },
+    search::SearchResult,
+    tree::{LatestCommitInfo, TreeBriefItem, TreeCommitItem, TreeListing},
 };
+use ceres::search::SearchIndex;
 use common::model::CommonResult;
 
+use crate::api::admin_router;
 use crate::api::mr_router;
 use crate::api::ApiServiceState;
 
@@ -24,10 +34,19 @@ pub fn routers() -> Router<ApiServiceState> {
         .route("/latest-commit", get(get_latest_commit))
         .route("/tree/commit-info", get(get_tree_commit_info))
         .route("/tree", get(get_tree_info))
+        .route("/tree/at", get(get_tree_at))
         .route("/blob", get(get_blob_object))
+        .route("/blobs", post(get_blobs))
+        .route("/blob/history", get(get_blob_history))
+        .route("/blame", get(get_blame))
+        .route("/search", get(search))
+        .route("/changelog", get(get_changelog))
         .route("/publish", post(publish_path_to_repo));
 
-    Router::new().merge(router).merge(mr_router::routers())
+    Router::new()
+        .merge(router)
+        .merge(mr_router::routers())
+        .merge(admin_router::routers())
 }
 
 async fn get_blob_object(
@@ -47,6 +66,77 @@ async fn get_blob_object(
     Ok(Json(res))
 }
 
+async fn get_blobs(
+    state: State<ApiServiceState>,
+    Json(json): Json<BlobBatchRequest>,
+) -> Result<Json<CommonResult<BlobBatch>>, (StatusCode, String)> {
+    let res = state.monorepo().get_blob_batch(json.oids).await;
+    let res = match res {
+        Ok(data) => CommonResult::success(Some(data)),
+        Err(err) => CommonResult::failed(&err.to_string()),
+    };
+    Ok(Json(res))
+}
+
+async fn get_blob_history(
+    Query(query): Query<HistoryQuery>,
+    state: State<ApiServiceState>,
+) -> Result<Json<CommonResult<CommitHistory>>, (StatusCode, String)> {
+    let res = state
+        .api_handler(query.path.clone().into())
+        .await
+        .get_path_history(query.path.into(), query.commit, query.page, query.per_page)
+        .await;
+    let res = match res {
+        Ok(data) => CommonResult::success(Some(data)),
+        Err(err) => CommonResult::failed(&err.to_string()),
+    };
+    Ok(Json(res))
+}
+
+async fn get_blame(
+    Query(query): Query<BlameQuery>,
+    state: State<ApiServiceState>,
+) -> Result<Json<CommonResult<BlameInfo>>, (StatusCode, String)> {
+    let res = state
+        .api_handler(query.path.clone().into())
+        .await
+        .get_blame(query.path.into(), query.commit)
+        .await;
+    let res = match res {
+        Ok(data) => CommonResult::success(Some(data)),
+        Err(err) => CommonResult::failed(&err.to_string()),
+    };
+    Ok(Json(res))
+}
+
+async fn search(
+    Query(query): Query<SearchQuery>,
+    state: State<ApiServiceState>,
+) -> Result<Json<CommonResult<SearchResult>>, (StatusCode, String)> {
+    let res = SearchIndex::new(state.context.clone()).search(&query).await;
+    let res = match res {
+        Ok(data) => CommonResult::success(Some(data)),
+        Err(err) => CommonResult::failed(&err.to_string()),
+    };
+    Ok(Json(res))
+}
+
+async fn get_changelog(
+    Query(query): Query<ChangelogQuery>,
+    state: State<ApiServiceState>,
+) -> Result<Json<CommonResult<Changelog>>, (StatusCode, String)> {
+    let res = state
+        .monorepo()
+        .changelog(query.path.into(), query.from, query.to)
+        .await;
+    let res = match res {
+        Ok(data) => CommonResult::success(Some(data)),
+        Err(err) => CommonResult::failed(&err.to_string()),
+    };
+    Ok(Json(res))
+}
+
 // async fn get_origin_object(
 //     Query(query): Query<HashMap<String, String>>,
 //     state: State<ApiServiceState>,
@@ -116,6 +206,23 @@ async fn get_tree_info(
     Ok(Json(res))
 }
 
+async fn get_tree_at(
+    Query(query): Query<TreeAtQuery>,
+    state: State<ApiServiceState>,
+) -> Result<Json<CommonResult<TreeListing>>, (StatusCode, String)> {
+    let res = state
+        .api_handler(query.path.clone().into())
+        .await
+        .get_tree_listing(query.path.into(), query.commit, query.tree, query.depth)
+        .await;
+    let res = match res {
+        Ok(Some(data)) => CommonResult::success(Some(data)),
+        Ok(None) => CommonResult::failed("can't find the tree"),
+        Err(err) => CommonResult::failed(&err.to_string()),
+    };
+    Ok(Json(res))
+}
+
 async fn get_tree_commit_info(
     Query(query): Query<CodePreviewQuery>,
     state: State<ApiServiceState>,
@@ -6,6 +6,7 @@ use ceres::api_service::{
 use jupiter::context::Context;
 use venus::import_repo::repo::Repo;
 
+pub mod admin_router;
 pub mod api_router;
 pub mod mr_router;
 
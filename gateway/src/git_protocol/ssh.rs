@@ -208,6 +208,17 @@ impl SshServer {
         session.data(channel, String::from_utf8(buf.to_vec()).unwrap().into());
 
         while let Some(chunk) = send_pack_data.next().await {
+            let chunk = match chunk {
+                Ok(chunk) => chunk,
+                Err(err) => {
+                    tracing::error!("failed to send the pack: {}", err);
+                    if let Some(bytes_out) = smart_protocol.build_side_band_error(&err.to_string())
+                    {
+                        session.data(channel, bytes_out.to_vec().into());
+                    }
+                    return;
+                }
+            };
             let mut reader = chunk.as_slice();
             loop {
                 let mut temp = BytesMut::new();
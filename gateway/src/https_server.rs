@@ -23,7 +23,7 @@ use tower_http::cors::{Any, CorsLayer};
 use tower_http::decompression::RequestDecompressionLayer;
 use tower_http::trace::TraceLayer;
 
-use ceres::lfs::LfsConfig;
+use ceres::lfs::{LfsConfig, LFS_REPO_NAME};
 use ceres::protocol::{SmartProtocol, TransportProtocol};
 use common::config::Config;
 use common::model::{CommonOptions, GetParams};
@@ -77,7 +77,7 @@ impl From<AppState> for LfsConfig {
             lfs_storage: Arc::new(LocalStorage::init(
                 value.context.config.storage.lfs_obj_local_path,
             )),
-            repo_name: String::from("repo_name"),
+            repo_name: String::from(LFS_REPO_NAME),
             enable_split: value.context.config.lfs.enable_split,
             split_size: value.context.config.lfs.split_size,
         }
@@ -102,7 +102,9 @@ pub async fn lfs_verify_lock(
         .await
         .unwrap_or_else(|_| Json(VerifiableLockRequest::default()));
 
-    let result = handler::lfs_verify_lock(config, request.0).await;
+    // The transports don't authenticate users yet, a name taken from the request could be
+    // anyone's, so no lock is treated as owned by the caller until a verified identity exists.
+    let result = handler::lfs_verify_lock(config, request.0, None).await;
     match result {
         Ok(lock_list) => {
             let body = serde_json::to_string(&lock_list).unwrap_or_default();
@@ -129,7 +131,7 @@ pub async fn lfs_create_lock(
         .await
         .unwrap_or_else(|_| Json(LockRequest::default()));
 
-    let result = handler::lfs_create_lock(config, request.0).await;
+    let result = handler::lfs_create_lock(config, request.0, None).await;
     match result {
         Ok(lock) => {
             let lock_response = LockResponse {
@@ -164,7 +166,7 @@ pub async fn lfs_delete_lock(
         .await
         .unwrap_or_else(|_| Json(UnlockRequest::default()));
 
-    let result = handler::lfs_delete_lock(config, id, request.0).await;
+    let result = handler::lfs_delete_lock(config, id, request.0, None).await;
 
     match result {
         Ok(lock) => {
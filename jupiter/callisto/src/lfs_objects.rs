@@ -10,6 +10,7 @@ pub struct Model {
     pub size: i64,
     pub exist: bool,
     pub splited: bool,
+    pub created_at: DateTime,
 }
 
 #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
@@ -2,10 +2,15 @@
 
 pub mod prelude;
 
+pub mod bitmap_object;
+pub mod commit_graph;
+pub mod conventional_commit;
 pub mod db_enums;
 pub mod git_blob;
 pub mod git_commit;
 pub mod git_issue;
+pub mod git_pack;
+pub mod git_pack_object;
 pub mod git_pr;
 pub mod git_repo;
 pub mod git_tag;
@@ -24,5 +29,11 @@ pub mod mega_refs;
 pub mod mega_tag;
 pub mod mega_tree;
 pub mod raw_blob;
+pub mod reachability_bitmap;
+pub mod search_blob;
+pub mod search_file;
+pub mod search_head;
+pub mod search_symbol;
+pub mod search_trigram;
 pub mod ztm_node;
 pub mod ztm_repo_info;
@@ -1,8 +1,13 @@
 //! `SeaORM` Entity. Generated by sea-orm-codegen 0.11.3
 
+pub use crate::bitmap_object::Entity as BitmapObject;
+pub use crate::commit_graph::Entity as CommitGraph;
+pub use crate::conventional_commit::Entity as ConventionalCommit;
 pub use crate::git_blob::Entity as GitBlob;
 pub use crate::git_commit::Entity as GitCommit;
 pub use crate::git_issue::Entity as GitIssue;
+pub use crate::git_pack::Entity as GitPack;
+pub use crate::git_pack_object::Entity as GitPackObject;
 pub use crate::git_pr::Entity as GitPr;
 pub use crate::git_repo::Entity as GitRepo;
 pub use crate::git_tag::Entity as GitTag;
@@ -20,3 +25,9 @@ pub use crate::mega_refs::Entity as MegaRefs;
 pub use crate::mega_tag::Entity as MegaTag;
 pub use crate::mega_tree::Entity as MegaTree;
 pub use crate::raw_blob::Entity as RawObjects;
+pub use crate::reachability_bitmap::Entity as ReachabilityBitmap;
+pub use crate::search_blob::Entity as SearchBlob;
+pub use crate::search_file::Entity as SearchFile;
+pub use crate::search_head::Entity as SearchHead;
+pub use crate::search_symbol::Entity as SearchSymbol;
+pub use crate::search_trigram::Entity as SearchTrigram;
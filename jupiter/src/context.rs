@@ -3,8 +3,10 @@ use std::sync::Arc;
 use common::config::Config;
 
 use crate::storage::{
-    git_db_storage::GitDbStorage, init::database_connection, lfs_storage::LfsStorage,
-    mega_storage::MegaStorage, ztm_storage::ZTMStorage,
+    commit_graph_storage::CommitGraphStorage,
+    conventional_commit_storage::ConventionalCommitStorage, git_db_storage::GitDbStorage,
+    init::database_connection, lfs_storage::LfsStorage, mega_storage::MegaStorage,
+    search_storage::SearchStorage, ztm_storage::ZTMStorage,
 };
 
 #[derive(Clone)]
@@ -34,6 +36,9 @@ pub struct Service {
     pub git_db_storage: Arc<GitDbStorage>,
     pub lfs_storage: Arc<LfsStorage>,
     pub ztm_storage: Arc<ZTMStorage>,
+    pub commit_graph_storage: Arc<CommitGraphStorage>,
+    pub search_storage: Arc<SearchStorage>,
+    pub conventional_commit_storage: Arc<ConventionalCommitStorage>,
 }
 
 impl Service {
@@ -48,6 +53,11 @@ impl Service {
             ),
             lfs_storage: Arc::new(LfsStorage::new(connection.clone()).await),
             ztm_storage: Arc::new(ZTMStorage::new(connection.clone()).await),
+            commit_graph_storage: Arc::new(CommitGraphStorage::new(connection.clone()).await),
+            search_storage: Arc::new(SearchStorage::new(connection.clone()).await),
+            conventional_commit_storage: Arc::new(
+                ConventionalCommitStorage::new(connection.clone()).await,
+            ),
         }
     }
 
@@ -61,6 +71,9 @@ impl Service {
             git_db_storage: Arc::new(GitDbStorage::mock()),
             lfs_storage: Arc::new(LfsStorage::mock()),
             ztm_storage: Arc::new(ZTMStorage::mock()),
+            commit_graph_storage: Arc::new(CommitGraphStorage::mock()),
+            search_storage: Arc::new(SearchStorage::mock()),
+            conventional_commit_storage: Arc::new(ConventionalCommitStorage::mock()),
         })
     }
 }
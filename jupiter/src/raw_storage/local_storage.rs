@@ -4,6 +4,7 @@ use std::path::{Path, PathBuf};
 
 use async_trait::async_trait;
 use bytes::Bytes;
+use tokio::io::{AsyncReadExt, AsyncSeekExt, SeekFrom};
 
 use callisto::db_enums::StorageType;
 use common::errors::MegaError;
@@ -79,6 +80,24 @@ impl RawStorage for LocalStorage {
         Ok(Bytes::from(buffer))
     }
 
+    async fn get_object_range(
+        &self,
+        repo_name: &str,
+        object_id: &str,
+        offset: u64,
+        len: usize,
+    ) -> Result<Bytes, MegaError> {
+        let path = Path::new(&self.base_path)
+            .join(repo_name)
+            .join("objects")
+            .join(self.transform_path(object_id));
+        let mut file = tokio::fs::File::open(&path).await?;
+        file.seek(SeekFrom::Start(offset)).await?;
+        let mut buffer = vec![0; len];
+        file.read_exact(&mut buffer).await?;
+        Ok(Bytes::from(buffer))
+    }
+
     async fn put_object(
         &self,
         repo_name: &str,
@@ -97,6 +116,14 @@ impl RawStorage for LocalStorage {
         Ok(path.to_str().unwrap().to_string())
     }
 
+    async fn delete_object(&self, repo_name: &str, object_id: &str) -> Result<(), MegaError> {
+        let path = Path::new(&self.base_path)
+            .join(repo_name)
+            .join("objects")
+            .join(self.transform_path(object_id));
+        Ok(fs::remove_file(path)?)
+    }
+
     fn exist_object(&self, repo_name: &str, object_id: &str) -> bool {
         let path = Path::new(&self.base_path)
             .join(repo_name)
@@ -127,6 +154,15 @@ mod tests {
         assert!(local_storage.put_object("", &oid, &content).await.is_ok());
 
         assert!(local_storage.exist_object("", &oid));
+        let range = local_storage
+            .get_object_range("", &oid, 5, 7)
+            .await
+            .unwrap();
+        assert_eq!(&range[..], b"content");
+        assert!(local_storage
+            .get_object_range("", &oid, 5, 8)
+            .await
+            .is_err());
     }
 
     #[tokio::test]
@@ -50,6 +50,28 @@ pub trait RawStorage: Sync + Send {
 
     async fn get_object(&self, repo_name: &str, object_id: &str) -> Result<Bytes, MegaError>;
 
+    /// Read `len` bytes of an object from `offset`, to read a large object in pieces.
+    async fn get_object_range(
+        &self,
+        repo_name: &str,
+        object_id: &str,
+        offset: u64,
+        len: usize,
+    ) -> Result<Bytes, MegaError> {
+        let data = self.get_object(repo_name, object_id).await?;
+        let start = offset as usize;
+        if start.saturating_add(len) > data.len() {
+            return Err(MegaError::with_message(&format!(
+                "object {} has {} bytes, can't read {} bytes at {}",
+                object_id,
+                data.len(),
+                len,
+                offset
+            )));
+        }
+        Ok(data.slice(start..start + len))
+    }
+
     async fn put_object(
         &self,
         repo_name: &str,
@@ -57,6 +79,8 @@ pub trait RawStorage: Sync + Send {
         body_content: &[u8],
     ) -> Result<String, MegaError>;
 
+    async fn delete_object(&self, repo_name: &str, object_id: &str) -> Result<(), MegaError>;
+
     // async fn parse_blob_link(&self, data: Vec<u8>) -> Result<BlobLink, MegaError> {
     //     let mut reader = BufReader::new(data.as_slice());
     //     let mut blink = BlobLink::default();
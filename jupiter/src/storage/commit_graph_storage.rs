@@ -0,0 +1,215 @@
+//! Storage of the commit graph and the reachability bitmaps of repos.
+//!
+//! The commit graph keeps the generation number and the parents of every commit, so the
+//! commits a client is missing are found in memory instead of one query per commit.
+//! Reachability bitmaps record, for a ref tip, every object reachable from it as a bit at the
+//! position of the object in `bitmap_object`.
+//!
+//! The monorepo has a single graph, stored under [`MONOREPO_GRAPH_ID`].
+
+use std::{collections::HashMap, sync::Arc};
+
+use sea_orm::{
+    ColumnTrait, DatabaseConnection, EntityTrait, IntoActiveModel, PaginatorTrait, QueryFilter,
+};
+
+use callisto::{bitmap_object, commit_graph, reachability_bitmap};
+use common::{errors::MegaError, utils::generate_id};
+
+use crate::storage::batch_save_model;
+
+/// The repo id of the commit graph of the monorepo, import repos use their own id.
+pub const MONOREPO_GRAPH_ID: i64 = 0;
+
+#[derive(Clone)]
+pub struct CommitGraphStorage {
+    pub connection: Arc<DatabaseConnection>,
+}
+
+impl CommitGraphStorage {
+    pub fn get_connection(&self) -> &DatabaseConnection {
+        &self.connection
+    }
+
+    pub async fn new(connection: Arc<DatabaseConnection>) -> Self {
+        CommitGraphStorage { connection }
+    }
+
+    pub fn mock() -> Self {
+        CommitGraphStorage {
+            connection: Arc::new(DatabaseConnection::default()),
+        }
+    }
+
+    /// Get the graph nodes of a repo with a generation from `low` to `high`, both included.
+    pub async fn get_commit_graph_generations(
+        &self,
+        repo_id: i64,
+        low: i64,
+        high: i64,
+    ) -> Result<Vec<commit_graph::Model>, MegaError> {
+        Ok(commit_graph::Entity::find()
+            .filter(commit_graph::Column::RepoId.eq(repo_id))
+            .filter(commit_graph::Column::Generation.between(low, high))
+            .all(self.get_connection())
+            .await?)
+    }
+
+    /// Get the graph nodes of some commits, the commits which aren't in the graph are skipped.
+    pub async fn get_commit_graph_nodes(
+        &self,
+        repo_id: i64,
+        commit_ids: Vec<String>,
+    ) -> Result<Vec<commit_graph::Model>, MegaError> {
+        let mut nodes = vec![];
+        for chunk in commit_ids.chunks(1000) {
+            nodes.extend(
+                commit_graph::Entity::find()
+                    .filter(commit_graph::Column::RepoId.eq(repo_id))
+                    .filter(commit_graph::Column::CommitId.is_in(chunk.to_vec()))
+                    .all(self.get_connection())
+                    .await?,
+            );
+        }
+        Ok(nodes)
+    }
+
+    /// Add commits to the graph, the parents of a commit must be saved with or before it.
+    pub async fn save_commit_graph(
+        &self,
+        repo_id: i64,
+        nodes: Vec<(String, i64, Vec<String>)>,
+    ) -> Result<(), MegaError> {
+        let models: Vec<commit_graph::ActiveModel> = nodes
+            .into_iter()
+            .map(|(commit_id, generation, parents)| {
+                commit_graph::Model {
+                    id: generate_id(),
+                    repo_id,
+                    commit_id,
+                    generation,
+                    parents_id: parents.into(),
+                }
+                .into_active_model()
+            })
+            .collect();
+        batch_save_model(self.get_connection(), models).await
+    }
+
+    /// Get the ids of the objects at some bitmap positions, by position.
+    pub async fn get_bitmap_objects_at(
+        &self,
+        repo_id: i64,
+        positions: Vec<usize>,
+    ) -> Result<HashMap<usize, String>, MegaError> {
+        let mut objects = HashMap::new();
+        for chunk in positions.chunks(1000) {
+            let models = bitmap_object::Entity::find()
+                .filter(bitmap_object::Column::RepoId.eq(repo_id))
+                .filter(
+                    bitmap_object::Column::Position
+                        .is_in(chunk.iter().map(|position| *position as i64)),
+                )
+                .all(self.get_connection())
+                .await?;
+            objects.extend(
+                models
+                    .into_iter()
+                    .map(|o| (o.position as usize, o.object_id)),
+            );
+        }
+        Ok(objects)
+    }
+
+    /// Find the bitmap positions of some objects, the objects without one are skipped.
+    pub async fn get_bitmap_positions(
+        &self,
+        repo_id: i64,
+        object_ids: Vec<String>,
+    ) -> Result<HashMap<String, usize>, MegaError> {
+        let mut positions = HashMap::new();
+        for chunk in object_ids.chunks(1000) {
+            let objects = bitmap_object::Entity::find()
+                .filter(bitmap_object::Column::RepoId.eq(repo_id))
+                .filter(bitmap_object::Column::ObjectId.is_in(chunk.to_vec()))
+                .all(self.get_connection())
+                .await?;
+            positions.extend(
+                objects
+                    .into_iter()
+                    .map(|o| (o.object_id, o.position as usize)),
+            );
+        }
+        Ok(positions)
+    }
+
+    /// Give the objects the positions after the last one, returns their positions.
+    ///
+    /// Positions must be unique in a repo, so callers have to serialize the calls for a repo.
+    pub async fn add_bitmap_objects(
+        &self,
+        repo_id: i64,
+        object_ids: Vec<String>,
+    ) -> Result<HashMap<String, usize>, MegaError> {
+        let start = bitmap_object::Entity::find()
+            .filter(bitmap_object::Column::RepoId.eq(repo_id))
+            .count(self.get_connection())
+            .await? as usize;
+        let positions: HashMap<String, usize> = object_ids
+            .into_iter()
+            .enumerate()
+            .map(|(i, id)| (id, start + i))
+            .collect();
+        let models: Vec<bitmap_object::ActiveModel> = positions
+            .iter()
+            .map(|(id, position)| {
+                bitmap_object::Model {
+                    id: generate_id(),
+                    repo_id,
+                    object_id: id.clone(),
+                    position: *position as i64,
+                }
+                .into_active_model()
+            })
+            .collect();
+        batch_save_model(self.get_connection(), models).await?;
+        Ok(positions)
+    }
+
+    /// Get the reachability bitmaps of some commits, the commits without one are skipped.
+    pub async fn get_reachability_bitmaps(
+        &self,
+        repo_id: i64,
+        commit_ids: Vec<String>,
+    ) -> Result<Vec<reachability_bitmap::Model>, MegaError> {
+        let mut bitmaps = vec![];
+        for chunk in commit_ids.chunks(1000) {
+            bitmaps.extend(
+                reachability_bitmap::Entity::find()
+                    .filter(reachability_bitmap::Column::RepoId.eq(repo_id))
+                    .filter(reachability_bitmap::Column::CommitId.is_in(chunk.to_vec()))
+                    .all(self.get_connection())
+                    .await?,
+            );
+        }
+        Ok(bitmaps)
+    }
+
+    pub async fn save_reachability_bitmap(
+        &self,
+        repo_id: i64,
+        commit_id: &str,
+        bitmap: Vec<u8>,
+        object_count: usize,
+    ) -> Result<(), MegaError> {
+        let model = reachability_bitmap::Model {
+            id: generate_id(),
+            repo_id,
+            commit_id: commit_id.to_owned(),
+            bitmap,
+            object_count: object_count as i64,
+            created_at: chrono::Utc::now().naive_utc(),
+        };
+        batch_save_model(self.get_connection(), vec![model.into_active_model()]).await
+    }
+}
@@ -0,0 +1,59 @@
+//! Storage of the Conventional Commit metadata parsed from commit messages, to build
+//! changelogs without parsing the messages again.
+
+use std::sync::Arc;
+
+use sea_orm::{ColumnTrait, DatabaseConnection, EntityTrait, QueryFilter};
+
+use callisto::conventional_commit;
+use common::errors::MegaError;
+
+use crate::storage::batch_save_model;
+
+#[derive(Clone)]
+pub struct ConventionalCommitStorage {
+    pub connection: Arc<DatabaseConnection>,
+}
+
+impl ConventionalCommitStorage {
+    pub fn get_connection(&self) -> &DatabaseConnection {
+        &self.connection
+    }
+
+    pub async fn new(connection: Arc<DatabaseConnection>) -> Self {
+        ConventionalCommitStorage { connection }
+    }
+
+    pub fn mock() -> Self {
+        ConventionalCommitStorage {
+            connection: Arc::new(DatabaseConnection::default()),
+        }
+    }
+
+    /// Save the metadata of commits, the commits saved already are skipped.
+    pub async fn save_commits(
+        &self,
+        models: Vec<conventional_commit::Model>,
+    ) -> Result<(), MegaError> {
+        let models: Vec<conventional_commit::ActiveModel> =
+            models.into_iter().map(Into::into).collect();
+        batch_save_model(self.get_connection(), models).await
+    }
+
+    /// The metadata of the commits among `commit_ids` which have it.
+    pub async fn get_commits(
+        &self,
+        commit_ids: Vec<String>,
+    ) -> Result<Vec<conventional_commit::Model>, MegaError> {
+        let mut models = vec![];
+        for chunk in commit_ids.chunks(1000) {
+            models.extend(
+                conventional_commit::Entity::find()
+                    .filter(conventional_commit::Column::CommitId.is_in(chunk.to_vec()))
+                    .all(self.get_connection())
+                    .await?,
+            );
+        }
+        Ok(models)
+    }
+}
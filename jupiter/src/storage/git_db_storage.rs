@@ -8,11 +8,15 @@ use sea_orm::{
     ActiveModelTrait, ColumnTrait, DatabaseConnection, DbBackend, DbErr, EntityTrait,
     IntoActiveModel, QueryFilter, QueryTrait, Set,
 };
-use sea_orm::{PaginatorTrait, QueryOrder};
+use sea_orm::{PaginatorTrait, QueryOrder, QuerySelect};
 
-use callisto::{git_blob, git_commit, git_repo, git_tag, git_tree, import_refs, raw_blob};
+use callisto::{
+    git_blob, git_commit, git_pack, git_pack_object, git_repo, git_tag, git_tree, import_refs,
+    raw_blob,
+};
 use common::config::StorageConfig;
 use common::errors::MegaError;
+use common::utils::generate_id;
 use mercury::internal::object::GitObjectModel;
 use mercury::internal::pack::entry::Entry;
 use venus::import_repo::import_refs::RefCommand;
@@ -158,21 +162,11 @@ impl GitDbStorage {
             }
         });
 
-        batch_save_model(self.get_connection(), commits.into_inner().unwrap())
-            .await
-            .unwrap();
-        batch_save_model(self.get_connection(), trees.into_inner().unwrap())
-            .await
-            .unwrap();
-        batch_save_model(self.get_connection(), blobs.into_inner().unwrap())
-            .await
-            .unwrap();
-        batch_save_model(self.get_connection(), raw_blobs.into_inner().unwrap())
-            .await
-            .unwrap();
-        batch_save_model(self.get_connection(), tags.into_inner().unwrap())
-            .await
-            .unwrap();
+        batch_save_model(self.get_connection(), commits.into_inner().unwrap()).await?;
+        batch_save_model(self.get_connection(), trees.into_inner().unwrap()).await?;
+        batch_save_model(self.get_connection(), blobs.into_inner().unwrap()).await?;
+        batch_save_model(self.get_connection(), raw_blobs.into_inner().unwrap()).await?;
+        batch_save_model(self.get_connection(), tags.into_inner().unwrap()).await?;
         Ok(())
     }
 
@@ -219,6 +213,10 @@ impl GitDbStorage {
         Ok(result)
     }
 
+    pub async fn get_all_git_repos(&self) -> Result<Vec<git_repo::Model>, MegaError> {
+        Ok(git_repo::Entity::find().all(self.get_connection()).await?)
+    }
+
     pub async fn save_git_repo(&self, repo: Repo) -> Result<(), MegaError> {
         let model: git_repo::Model = repo.into();
         let a_model = model.into_active_model();
@@ -347,6 +345,18 @@ impl GitDbStorage {
             .unwrap())
     }
 
+    pub async fn get_tags_by_hashes(
+        &self,
+        repo: &Repo,
+        hashes: Vec<String>,
+    ) -> Result<Vec<git_tag::Model>, MegaError> {
+        Ok(git_tag::Entity::find()
+            .filter(git_tag::Column::RepoId.eq(repo.repo_id))
+            .filter(git_tag::Column::TagId.is_in(hashes))
+            .all(self.get_connection())
+            .await?)
+    }
+
     pub async fn get_obj_count_by_repo_id(&self, repo: &Repo) -> usize {
         let c_count = git_commit::Entity::find()
             .filter(git_commit::Column::RepoId.eq(repo.repo_id))
@@ -376,4 +386,112 @@ impl GitDbStorage {
             .try_into()
             .unwrap()
     }
+
+    /// Record a pack kept in raw storage together with the offset of every object in it.
+    ///
+    /// The objects are saved before the pack, so a listed pack always has a complete index.
+    pub async fn save_git_pack(
+        &self,
+        repo: &Repo,
+        pack_id: &str,
+        size: usize,
+        objects: Vec<(String, usize)>,
+    ) -> Result<(), MegaError> {
+        let object_count = objects.len() as i64;
+        let models: Vec<git_pack_object::ActiveModel> = objects
+            .into_iter()
+            .map(|(object_id, offset)| {
+                git_pack_object::Model {
+                    id: generate_id(),
+                    repo_id: repo.repo_id,
+                    pack_id: pack_id.to_owned(),
+                    object_id,
+                    pack_offset: offset as i64,
+                }
+                .into_active_model()
+            })
+            .collect();
+        batch_save_model(self.get_connection(), models).await?;
+
+        let pack = git_pack::Model {
+            id: generate_id(),
+            repo_id: repo.repo_id,
+            pack_id: pack_id.to_owned(),
+            object_count,
+            size: size as i64,
+            created_at: chrono::Utc::now().naive_utc(),
+        };
+        batch_save_model(self.get_connection(), vec![pack.into_active_model()]).await
+    }
+
+    /// Get the stored packs of a repo, oldest first.
+    pub async fn get_git_packs(&self, repo: &Repo) -> Result<Vec<git_pack::Model>, MegaError> {
+        Ok(git_pack::Entity::find()
+            .filter(git_pack::Column::RepoId.eq(repo.repo_id))
+            .order_by_asc(git_pack::Column::Id)
+            .all(self.get_connection())
+            .await?)
+    }
+
+    /// Find the pack and offset of an object.
+    pub async fn get_pack_object(
+        &self,
+        repo: &Repo,
+        object_id: &str,
+    ) -> Result<Option<git_pack_object::Model>, MegaError> {
+        Ok(git_pack_object::Entity::find()
+            .filter(git_pack_object::Column::RepoId.eq(repo.repo_id))
+            .filter(git_pack_object::Column::ObjectId.eq(object_id))
+            .one(self.get_connection())
+            .await?)
+    }
+
+    /// Find the packs and offsets of some objects, an object may be in several packs.
+    pub async fn get_pack_objects(
+        &self,
+        repo: &Repo,
+        object_ids: Vec<String>,
+    ) -> Result<Vec<git_pack_object::Model>, MegaError> {
+        let mut objects = vec![];
+        for chunk in object_ids.chunks(1000) {
+            objects.extend(
+                git_pack_object::Entity::find()
+                    .filter(git_pack_object::Column::RepoId.eq(repo.repo_id))
+                    .filter(git_pack_object::Column::ObjectId.is_in(chunk.to_vec()))
+                    .all(self.get_connection())
+                    .await?,
+            );
+        }
+        Ok(objects)
+    }
+
+    /// Count the distinct objects in the stored packs of a repo.
+    pub async fn get_pack_object_count(&self, repo: &Repo) -> Result<u64, MegaError> {
+        Ok(git_pack_object::Entity::find()
+            .filter(git_pack_object::Column::RepoId.eq(repo.repo_id))
+            .select_only()
+            .column(git_pack_object::Column::ObjectId)
+            .distinct()
+            .count(self.get_connection())
+            .await?)
+    }
+
+    /// Remove the records of packs, the pack files are not touched.
+    pub async fn delete_git_packs(
+        &self,
+        repo: &Repo,
+        pack_ids: Vec<String>,
+    ) -> Result<(), MegaError> {
+        git_pack::Entity::delete_many()
+            .filter(git_pack::Column::RepoId.eq(repo.repo_id))
+            .filter(git_pack::Column::PackId.is_in(pack_ids.clone()))
+            .exec(self.get_connection())
+            .await?;
+        git_pack_object::Entity::delete_many()
+            .filter(git_pack_object::Column::RepoId.eq(repo.repo_id))
+            .filter(git_pack_object::Column::PackId.is_in(pack_ids))
+            .exec(self.get_connection())
+            .await?;
+        Ok(())
+    }
 }
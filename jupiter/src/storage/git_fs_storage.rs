@@ -89,7 +89,7 @@ impl GitFsStorage {
                 .unwrap();
             let (type_num, _) = utils::read_type_and_size(&mut Cursor::new(&data)).unwrap();
             let obj_type = ObjectType::from_u8(type_num).unwrap();
-            let hash = SHA1::new(&data.to_vec());
+            let hash = SHA1::new(&data);
             res.push(Entry {
                 obj_type,
                 data: data.to_vec(),
@@ -58,6 +58,18 @@ impl LfsStorage {
         Ok(result)
     }
 
+    pub async fn get_all_lfs_objects(&self) -> Result<Vec<lfs_objects::Model>, MegaError> {
+        Ok(lfs_objects::Entity::find()
+            .all(self.get_connection())
+            .await?)
+    }
+
+    pub async fn get_all_lfs_relations(&self) -> Result<Vec<lfs_split_relation::Model>, MegaError> {
+        Ok(lfs_split_relation::Entity::find()
+            .all(self.get_connection())
+            .await?)
+    }
+
     pub async fn get_lfs_relations(
         &self,
         oid: String,
@@ -1,6 +1,7 @@
 use std::sync::{Arc, Mutex};
 
 use rayon::iter::{IntoParallelRefIterator, ParallelIterator};
+use sea_orm::sea_query::Expr;
 use sea_orm::ActiveValue::NotSet;
 use sea_orm::{
     ActiveModelTrait, ColumnTrait, DatabaseConnection, EntityTrait, IntoActiveModel, QueryFilter,
@@ -9,7 +10,8 @@ use sea_orm::{
 
 use callisto::db_enums::{ConvType, MergeStatus};
 use callisto::{
-    mega_blob, mega_commit, mega_mr, mega_mr_comment, mega_mr_conv, mega_refs, mega_tree, raw_blob,
+    mega_blob, mega_commit, mega_mr, mega_mr_comment, mega_mr_conv, mega_refs, mega_tag, mega_tree,
+    raw_blob,
 };
 use common::config::StorageConfig;
 use common::errors::MegaError;
@@ -97,6 +99,11 @@ impl MegaStorage {
         Ok(result.map(|model| model.into()))
     }
 
+    pub async fn get_all_refs(&self) -> Result<Vec<MegaRefs>, MegaError> {
+        let result = mega_refs::Entity::find().all(self.get_connection()).await?;
+        Ok(result.into_iter().map(|model| model.into()).collect())
+    }
+
     pub async fn update_ref(&self, refs: MegaRefs) -> Result<(), MegaError> {
         let ref_data: mega_refs::Model = refs.into();
         let mut ref_data: mega_refs::ActiveModel = ref_data.into();
@@ -255,21 +262,11 @@ impl MegaStorage {
             }
         });
 
-        batch_save_model(self.get_connection(), commits.into_inner().unwrap())
-            .await
-            .unwrap();
-        batch_save_model(self.get_connection(), trees.into_inner().unwrap())
-            .await
-            .unwrap();
-        batch_save_model(self.get_connection(), blobs.into_inner().unwrap())
-            .await
-            .unwrap();
-        batch_save_model(self.get_connection(), raw_blobs.into_inner().unwrap())
-            .await
-            .unwrap();
-        batch_save_model(self.get_connection(), tags.into_inner().unwrap())
-            .await
-            .unwrap();
+        batch_save_model(self.get_connection(), commits.into_inner().unwrap()).await?;
+        batch_save_model(self.get_connection(), trees.into_inner().unwrap()).await?;
+        batch_save_model(self.get_connection(), blobs.into_inner().unwrap()).await?;
+        batch_save_model(self.get_connection(), raw_blobs.into_inner().unwrap()).await?;
+        batch_save_model(self.get_connection(), tags.into_inner().unwrap()).await?;
         Ok(())
     }
 
@@ -361,6 +358,16 @@ impl MegaStorage {
             .unwrap())
     }
 
+    pub async fn get_tags_by_hashes(
+        &self,
+        hashes: Vec<String>,
+    ) -> Result<Vec<mega_tag::Model>, MegaError> {
+        Ok(mega_tag::Entity::find()
+            .filter(mega_tag::Column::TagId.is_in(hashes))
+            .all(self.get_connection())
+            .await?)
+    }
+
     pub async fn get_mega_blobs_by_hashes(
         &self,
         hashes: Vec<String>,
@@ -383,6 +390,40 @@ impl MegaStorage {
             .unwrap())
     }
 
+    /// The raw blobs among `hashes` whose data is shorter than `max_size` bytes.
+    pub async fn get_small_raw_blobs_by_hashes(
+        &self,
+        hashes: Vec<String>,
+        max_size: usize,
+    ) -> Result<Vec<raw_blob::Model>, MegaError> {
+        Ok(raw_blob::Entity::find()
+            .filter(raw_blob::Column::Sha1.is_in(hashes))
+            .filter(Expr::cust(format!("LENGTH(data) < {}", max_size)))
+            .all(self.get_connection())
+            .await?)
+    }
+
+    /// The sizes in bytes of the raw blobs among `hashes`, without loading their data.
+    pub async fn get_raw_blob_sizes(
+        &self,
+        hashes: Vec<String>,
+    ) -> Result<Vec<(String, i64)>, MegaError> {
+        let mut sizes = Vec::new();
+        for chunk in hashes.chunks(1000) {
+            let found: Vec<(String, Option<i64>)> = raw_blob::Entity::find()
+                .select_only()
+                .column(raw_blob::Column::Sha1)
+                // LENGTH is an int4 in postgres
+                .column_as(Expr::cust("CAST(LENGTH(data) AS BIGINT)"), "size")
+                .filter(raw_blob::Column::Sha1.is_in(chunk.to_vec()))
+                .into_tuple()
+                .all(self.get_connection())
+                .await?;
+            sizes.extend(found.into_iter().filter_map(|(id, size)| Some((id, size?))));
+        }
+        Ok(sizes)
+    }
+
     pub async fn get_raw_blob_by_hash(
         &self,
         hash: &str,
@@ -1,14 +1,17 @@
+pub mod commit_graph_storage;
+pub mod conventional_commit_storage;
 pub mod git_db_storage;
 pub mod git_fs_storage;
 pub mod init;
 pub mod lfs_storage;
 pub mod mega_storage;
+pub mod search_storage;
 pub mod ztm_storage;
 
 use async_trait::async_trait;
 
 use common::errors::MegaError;
-use sea_orm::{sea_query::OnConflict, ActiveModelTrait, ConnectionTrait, EntityTrait};
+use sea_orm::{sea_query::OnConflict, ActiveModelTrait, ConnectionTrait, DbErr, EntityTrait};
 use venus::{
     import_repo::import_refs::{RefCommand, Refs},
     import_repo::repo::Repo,
@@ -91,6 +94,12 @@ where
             .exec(connection);
         results.push(res);
     }
-    futures::future::join_all(results).await;
+    for res in futures::future::join_all(results).await {
+        match res {
+            // every row of the chunk conflicts and is skipped by `do_nothing`
+            Ok(_) | Err(DbErr::RecordNotInserted) => {}
+            Err(err) => return Err(err.into()),
+        }
+    }
     Ok(())
 }
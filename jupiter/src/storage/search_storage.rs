@@ -0,0 +1,303 @@
+//! Storage of the code search index of the monorepo.
+//!
+//! A file of the indexed tree is kept as its path and blob, and a blob is indexed once however
+//! many paths it has: `search_trigram` lists the blobs containing each trigram of lowercased
+//! bytes, and `search_symbol` the definitions found in it. `search_head` records the commit the
+//! index is at, so it is updated from the changes since then.
+
+use std::{collections::HashSet, sync::Arc};
+
+use sea_orm::{
+    sea_query::{Expr, Func, OnConflict},
+    ColumnTrait, DatabaseConnection, EntityTrait, IntoActiveModel, QueryFilter, QueryOrder,
+    QuerySelect,
+};
+
+use callisto::{search_blob, search_file, search_head, search_symbol, search_trigram};
+use common::{errors::MegaError, utils::generate_id};
+
+use crate::storage::{batch_save_model, batch_save_model_with_conflict};
+
+#[derive(Clone)]
+pub struct SearchStorage {
+    pub connection: Arc<DatabaseConnection>,
+}
+
+impl SearchStorage {
+    pub fn get_connection(&self) -> &DatabaseConnection {
+        &self.connection
+    }
+
+    pub async fn new(connection: Arc<DatabaseConnection>) -> Self {
+        SearchStorage { connection }
+    }
+
+    pub fn mock() -> Self {
+        SearchStorage {
+            connection: Arc::new(DatabaseConnection::default()),
+        }
+    }
+
+    /// The commit the index of the ref `path` is at.
+    pub async fn get_head(&self, path: &str) -> Result<Option<search_head::Model>, MegaError> {
+        Ok(search_head::Entity::find()
+            .filter(search_head::Column::Path.eq(path))
+            .one(self.get_connection())
+            .await?)
+    }
+
+    pub async fn save_head(
+        &self,
+        path: &str,
+        commit_id: &str,
+        tree_id: &str,
+    ) -> Result<(), MegaError> {
+        let model = search_head::Model {
+            id: generate_id(),
+            path: path.to_owned(),
+            commit_id: commit_id.to_owned(),
+            tree_id: tree_id.to_owned(),
+            updated_at: chrono::Utc::now().naive_utc(),
+        };
+        batch_save_model_with_conflict(
+            self.get_connection(),
+            vec![model.into_active_model()],
+            OnConflict::column(search_head::Column::Path)
+                .update_columns([
+                    search_head::Column::CommitId,
+                    search_head::Column::TreeId,
+                    search_head::Column::UpdatedAt,
+                ])
+                .to_owned(),
+        )
+        .await
+    }
+
+    /// The blobs among `blob_ids` which are indexed already.
+    pub async fn get_indexed_blobs(
+        &self,
+        blob_ids: Vec<String>,
+    ) -> Result<HashSet<String>, MegaError> {
+        let mut indexed = HashSet::new();
+        for chunk in blob_ids.chunks(1000) {
+            let ids: Vec<String> = search_blob::Entity::find()
+                .select_only()
+                .column(search_blob::Column::BlobId)
+                .filter(search_blob::Column::BlobId.is_in(chunk.to_vec()))
+                .into_tuple()
+                .all(self.get_connection())
+                .await?;
+            indexed.extend(ids);
+        }
+        Ok(indexed)
+    }
+
+    /// Index a blob with its trigrams and symbols, `(name, kind, line)`.
+    ///
+    /// The blob is recorded last, so a blob left half indexed is indexed again.
+    pub async fn save_blob_index(
+        &self,
+        blob_id: &str,
+        size: usize,
+        is_text: bool,
+        trigrams: Vec<i32>,
+        symbols: Vec<(String, String, i32)>,
+    ) -> Result<(), MegaError> {
+        let trigrams: Vec<search_trigram::ActiveModel> = trigrams
+            .into_iter()
+            .map(|trigram| {
+                search_trigram::Model {
+                    id: generate_id(),
+                    trigram,
+                    blob_id: blob_id.to_owned(),
+                }
+                .into_active_model()
+            })
+            .collect();
+        batch_save_model(self.get_connection(), trigrams).await?;
+
+        search_symbol::Entity::delete_many()
+            .filter(search_symbol::Column::BlobId.eq(blob_id))
+            .exec(self.get_connection())
+            .await?;
+        let symbols: Vec<search_symbol::ActiveModel> = symbols
+            .into_iter()
+            .map(|(name, kind, line)| {
+                search_symbol::Model {
+                    id: generate_id(),
+                    blob_id: blob_id.to_owned(),
+                    name,
+                    kind,
+                    line,
+                }
+                .into_active_model()
+            })
+            .collect();
+        batch_save_model(self.get_connection(), symbols).await?;
+
+        let blob = search_blob::Model {
+            id: generate_id(),
+            blob_id: blob_id.to_owned(),
+            size: size as i64,
+            is_text,
+        };
+        batch_save_model(self.get_connection(), vec![blob.into_active_model()]).await
+    }
+
+    /// Add files to the index, or point them to a new blob, `(path, blob_id, language)`.
+    pub async fn save_files(
+        &self,
+        files: Vec<(String, String, Option<String>)>,
+    ) -> Result<(), MegaError> {
+        let models: Vec<search_file::ActiveModel> = files
+            .into_iter()
+            .map(|(path, blob_id, language)| {
+                search_file::Model {
+                    id: generate_id(),
+                    path,
+                    blob_id,
+                    language,
+                }
+                .into_active_model()
+            })
+            .collect();
+        batch_save_model_with_conflict(
+            self.get_connection(),
+            models,
+            OnConflict::column(search_file::Column::Path)
+                .update_columns([search_file::Column::BlobId, search_file::Column::Language])
+                .to_owned(),
+        )
+        .await
+    }
+
+    pub async fn remove_files(&self, paths: Vec<String>) -> Result<(), MegaError> {
+        for chunk in paths.chunks(1000) {
+            search_file::Entity::delete_many()
+                .filter(search_file::Column::Path.is_in(chunk.to_vec()))
+                .exec(self.get_connection())
+                .await?;
+        }
+        Ok(())
+    }
+
+    /// Remove the index of the blobs among `blob_ids` which no file has anymore.
+    pub async fn remove_unused_blobs(&self, blob_ids: Vec<String>) -> Result<(), MegaError> {
+        for chunk in blob_ids.chunks(1000) {
+            let used: HashSet<String> = search_file::Entity::find()
+                .select_only()
+                .column(search_file::Column::BlobId)
+                .filter(search_file::Column::BlobId.is_in(chunk.to_vec()))
+                .into_tuple::<String>()
+                .all(self.get_connection())
+                .await?
+                .into_iter()
+                .collect();
+            let unused: Vec<String> = chunk
+                .iter()
+                .filter(|id| !used.contains(*id))
+                .cloned()
+                .collect();
+            if unused.is_empty() {
+                continue;
+            }
+            search_trigram::Entity::delete_many()
+                .filter(search_trigram::Column::BlobId.is_in(unused.clone()))
+                .exec(self.get_connection())
+                .await?;
+            search_symbol::Entity::delete_many()
+                .filter(search_symbol::Column::BlobId.is_in(unused.clone()))
+                .exec(self.get_connection())
+                .await?;
+            search_blob::Entity::delete_many()
+                .filter(search_blob::Column::BlobId.is_in(unused))
+                .exec(self.get_connection())
+                .await?;
+        }
+        Ok(())
+    }
+
+    /// The blobs containing all the `trigrams`.
+    pub async fn find_blobs_by_trigrams(&self, trigrams: &[i32]) -> Result<Vec<String>, MegaError> {
+        Ok(search_trigram::Entity::find()
+            .select_only()
+            .column(search_trigram::Column::BlobId)
+            .filter(search_trigram::Column::Trigram.is_in(trigrams.to_vec()))
+            .group_by(search_trigram::Column::BlobId)
+            .having(
+                Expr::expr(Func::count(Expr::col(search_trigram::Column::Trigram)))
+                    .eq(trigrams.len() as i64),
+            )
+            .into_tuple()
+            .all(self.get_connection())
+            .await?)
+    }
+
+    /// The files under `path_prefix` in one of `languages`, all languages if empty, and
+    /// with one of `blob_ids` unless `None`. They are ordered by path, at most `limit`.
+    pub async fn get_files(
+        &self,
+        blob_ids: Option<Vec<String>>,
+        path_prefix: &str,
+        languages: &[String],
+        limit: u64,
+    ) -> Result<Vec<search_file::Model>, MegaError> {
+        let mut query = search_file::Entity::find()
+            .filter(search_file::Column::Path.starts_with(path_prefix))
+            .order_by_asc(search_file::Column::Path);
+        if !languages.is_empty() {
+            query = query.filter(search_file::Column::Language.is_in(languages.to_vec()));
+        }
+        let Some(blob_ids) = blob_ids else {
+            return Ok(query.limit(limit).all(self.get_connection()).await?);
+        };
+        let mut files = vec![];
+        for chunk in blob_ids.chunks(1000) {
+            files.extend(
+                query
+                    .clone()
+                    .filter(search_file::Column::BlobId.is_in(chunk.to_vec()))
+                    .limit(limit)
+                    .all(self.get_connection())
+                    .await?,
+            );
+        }
+        files.sort_by(|a, b| a.path.cmp(&b.path));
+        files.truncate(limit as usize);
+        Ok(files)
+    }
+
+    /// The files of some blobs, to find where their symbols are.
+    pub async fn get_files_by_blobs(
+        &self,
+        blob_ids: Vec<String>,
+    ) -> Result<Vec<search_file::Model>, MegaError> {
+        let mut files = vec![];
+        for chunk in blob_ids.chunks(1000) {
+            files.extend(
+                search_file::Entity::find()
+                    .filter(search_file::Column::BlobId.is_in(chunk.to_vec()))
+                    .all(self.get_connection())
+                    .await?,
+            );
+        }
+        Ok(files)
+    }
+
+    /// The symbols whose lowercased name contains `pattern`, a `LIKE` pattern, at most `limit`.
+    pub async fn find_symbols(
+        &self,
+        pattern: &str,
+        limit: u64,
+    ) -> Result<Vec<search_symbol::Model>, MegaError> {
+        Ok(search_symbol::Entity::find()
+            .filter(
+                Expr::expr(Func::lower(Expr::col(search_symbol::Column::Name)))
+                    .like(format!("%{}%", pattern)),
+            )
+            .order_by_asc(search_symbol::Column::Name)
+            .limit(limit)
+            .all(self.get_connection())
+            .await?)
+    }
+}
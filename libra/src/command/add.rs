@@ -1,10 +1,14 @@
 use std::path::{Path, PathBuf};
 use clap::Parser;
+use mercury::hash::HashKind;
 use mercury::internal::object::blob::Blob;
 use crate::command::status;
+use crate::internal::config::Config;
 use mercury::internal::index::{Index, IndexEntry};
 use crate::utils::object_ext::BlobExt;
 
+use crate::utils::sparse::SparseCheckout;
+use crate::utils::lfs::LfsAttributes;
 use crate::utils::{path, util};
 
 #[derive(Parser, Debug)]
@@ -48,7 +52,7 @@ pub async fn execute(args: AddArgs) {
     }
 
     // index vs worktree
-    let mut changes = status::changes_to_be_staged(); // to workdir
+    let mut changes = status::changes_to_be_staged().await; // to workdir
     // filter paths to fit `pathspec` that user inputs
     changes.new = util::filter_to_fit_paths(&changes.new, &paths);
     // if `--all` & <pathspec> is given, it will update `index` as well, so no need to filter `deleted` & `modified`
@@ -64,16 +68,41 @@ pub async fn execute(args: AddArgs) {
         files.extend(changes.new);
     }
 
+    let kind = Config::object_format().await;
     let index_file = path::index();
-    let mut index = Index::load(&index_file).unwrap();
+    let mut index = Index::load(&index_file, kind).unwrap();
+    // like git, the new files outside the sparse checkout are not added
+    let mut outside_sparse = Vec::new();
+    if let Some(sparse) = SparseCheckout::load() {
+        files.retain(|file| {
+            let outside = !sparse.includes(file) && !index.tracked(&util::path_to_string(file), 0);
+            if outside {
+                outside_sparse.push(file.clone());
+            }
+            !outside
+        });
+    }
+    let attributes = LfsAttributes::load();
     for file in &files {
-        add_a_file(file, &mut index, args.verbose).await;
+        add_a_file(file, &mut index, kind, &attributes, args.verbose).await;
+    }
+    index.save(&index_file, kind).unwrap();
+
+    if !outside_sparse.is_empty() {
+        println!("The following paths are outside of your sparse-checkout definition, so will not be added to the index:");
+        outside_sparse.iter().for_each(|file| println!("{}", file.display()));
+        println!("hint: Update the definition with `libra sparse-checkout add` to add them.");
     }
-    index.save(&index_file).unwrap();
 }
 
 /// `file` path must relative to the working directory
-async fn add_a_file(file: &Path, index: &mut Index, verbose: bool) {
+async fn add_a_file(
+    file: &Path,
+    index: &mut Index,
+    kind: HashKind,
+    attributes: &LfsAttributes,
+    verbose: bool,
+) {
     let workdir = util::working_dir();
     if !util::is_sub_path(file, &workdir) {
         // file is not in the working directory
@@ -106,7 +135,7 @@ async fn add_a_file(file: &Path, index: &mut Index, verbose: bool) {
         // file exists
         if !index.tracked(file_str, 0) {
             // file is not tracked
-            let blob = Blob::from_file(&file_abs);
+            let blob = blob_from_file(&file_abs, kind, attributes);
             blob.save();
             index.add(IndexEntry::new_from_file(file, blob.id, &workdir).unwrap());
             if verbose {
@@ -116,7 +145,7 @@ async fn add_a_file(file: &Path, index: &mut Index, verbose: bool) {
             // file is tracked, maybe modified
             if index.is_modified(file_str, 0, &workdir) {
                 // file is modified(meta), but content may not change
-                let blob = Blob::from_file(&file_abs);
+                let blob = blob_from_file(&file_abs, kind, attributes);
                 if !index.verify_hash(file_str, 0, &blob.id) {
                     // content is changed
                     blob.save();
@@ -129,6 +158,15 @@ async fn add_a_file(file: &Path, index: &mut Index, verbose: bool) {
         }
     }
 }
+/// apply the `filter=lfs` attribute, LFS file is stored as a pointer blob
+fn blob_from_file(file_abs: &Path, kind: HashKind, attributes: &LfsAttributes) -> Blob {
+    if attributes.is_tracked(file_abs) {
+        Blob::from_lfs_file(file_abs, kind)
+    } else {
+        Blob::from_file(file_abs, kind)
+    }
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
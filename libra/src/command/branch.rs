@@ -195,7 +195,7 @@ pub async fn get_target_commit(branch_or_commit: &str) -> Result<SHA1, Box<dyn s
 
     if possible_branches.is_empty() {
         let storage = ClientStorage::init(utils::path::objects());
-        let possible_commits = storage.search(branch_or_commit);
+        let possible_commits = storage.search(branch_or_commit, Config::object_format().await);
         if possible_commits.len() > 1 || possible_commits.is_empty() {
             return Err(
                 format!("fatal: {} is not something we can merge", branch_or_commit).into(),
@@ -9,6 +9,7 @@ use crate::internal::head::Head;
 use clap::Parser;
 
 use crate::utils::path_ext::PathExt;
+use crate::utils::sparse::SparseCheckout;
 use crate::utils::util;
 
 use super::fetch::{self};
@@ -22,6 +23,11 @@ pub struct CloneArgs {
 
     /// The local path to clone the repository to
     pub local_path: Option<String>,
+
+    /// Enable sparse checkout, only the files in the root directory are checked out.
+    /// See `libra sparse-checkout` to check out more directories
+    #[clap(long)]
+    pub sparse: bool,
 }
 
 pub async fn execute(args: CloneArgs) {
@@ -61,7 +67,7 @@ pub async fn execute(args: CloneArgs) {
 
     // CAUTION: change [current_dir] to the repo directory
     env::set_current_dir(&local_path).unwrap();
-    command::init::execute().await;
+    command::init::execute(command::init::InitArgs::default()).await;
 
     /* fetch remote */
     let remote_config = RemoteConfig {
@@ -71,6 +77,9 @@ pub async fn execute(args: CloneArgs) {
     fetch::fetch_repository(&remote_config).await;
 
     /* setup */
+    if args.sparse {
+        SparseCheckout::default().save().unwrap();
+    }
     setup(remote_repo.clone()).await;
 }
 
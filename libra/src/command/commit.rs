@@ -2,13 +2,15 @@ use std::str::FromStr;
 use std::{collections::HashSet, path::PathBuf};
 
 use crate::internal::branch::Branch;
+use crate::internal::config::Config;
 use crate::internal::head::Head;
 use crate::utils::client_storage::ClientStorage;
 use crate::utils::path;
 use crate::utils::util;
+use mercury::internal::index::cache_tree::CacheTree;
 use mercury::internal::index::Index;
 use clap::Parser;
-use mercury::hash::SHA1;
+use mercury::hash::{HashKind, SHA1};
 use mercury::internal::object::commit::Commit;
 use mercury::internal::object::tree::{Tree, TreeItem, TreeItemMode};
 use mercury::internal::object::ObjectTrait;
@@ -26,7 +28,8 @@ pub struct CommitArgs {
 
 pub async fn execute(args: CommitArgs) {
     /* check args */
-    let index = Index::load(path::index()).unwrap();
+    let kind = Config::object_format().await;
+    let mut index = Index::load(path::index(), kind).unwrap();
     let storage = ClientStorage::init(path::objects());
     let tracked_entries = index.tracked_entries(0);
     if tracked_entries.is_empty() && !args.allow_empty {
@@ -34,7 +37,14 @@ pub async fn execute(args: CommitArgs) {
     }
 
     /* Create tree */
-    let tree = create_tree(&index, &storage, "".into()).await;
+    let mut cache_tree = index
+        .cache_tree()
+        .cloned()
+        .unwrap_or_else(|| CacheTree::new(""));
+    let tree = create_tree(&index, &mut cache_tree, &storage, "".into(), kind).await;
+    // keep the tree ids, so the next commit and `status` can skip the unchanged directories
+    index.set_cache_tree(Some(cache_tree));
+    index.save(path::index(), kind).unwrap();
 
     /* Create & save commit objects */
     let parents_commit_ids = get_parents_ids().await;
@@ -52,7 +62,25 @@ pub async fn execute(args: CommitArgs) {
 }
 
 /// recursively create tree from index's tracked entries
-async fn create_tree(index: &Index, storage: &ClientStorage, current_root: PathBuf) -> Tree {
+/// - the trees still valid in `cache_tree` are reused, the created ones are recorded in it
+/// - `kind`: the object format of the repository
+async fn create_tree(
+    index: &Index,
+    cache_tree: &mut CacheTree,
+    storage: &ClientStorage,
+    current_root: PathBuf,
+    kind: HashKind,
+) -> Tree {
+    let dir = util::path_to_string(&current_root);
+    if let Some(id) = cache_tree.tree_id(&dir) {
+        if let Ok(tree) = storage
+            .get(&id)
+            .and_then(|data| Tree::from_bytes(&data, id))
+        {
+            return tree;
+        }
+    }
+
     // blob created when add file to index
     let get_blob_entry = |path: &PathBuf| {
         let name = util::path_to_string(path);
@@ -100,8 +128,10 @@ async fn create_tree(index: &Index, storage: &ClientStorage, current_root: PathB
 
             let sub_tree = Box::pin(create_tree(
                 index,
+                cache_tree,
                 storage,
                 current_root.clone().join(process_path),
+                kind,
             ))
             .await;
             tree_items.push(TreeItem {
@@ -115,13 +145,16 @@ async fn create_tree(index: &Index, storage: &ClientStorage, current_root: PathB
         // `from_tree_items` can't create empty tree, so use `from_bytes` instead
         if tree_items.is_empty() {
             // git create a no zero hash for empty tree, didn't know method. use default SHA1 temporarily
-            Tree::from_bytes(&[], SHA1::default()).unwrap()
+            Tree::from_bytes(&[], SHA1::zero(kind)).unwrap()
         } else {
             Tree::from_tree_items(tree_items).unwrap()
         }
     };
     // save
     save_object(&tree, &tree.id).unwrap();
+    if !tree.tree_items.is_empty() {
+        cache_tree.insert(&dir, path_entries.len(), tree.id);
+    }
     tree
 }
 
@@ -164,11 +197,13 @@ mod test {
 
     #[tokio::test]
     async fn test_create_tree() {
-        let index = Index::from_file("../tests/data/index/index-760").unwrap();
+        let index = Index::from_file("../tests/data/index/index-760", HashKind::Sha1).unwrap();
         println!("{:?}", index.tracked_entries(0).len());
         test::setup_with_new_libra().await;
         let storage = ClientStorage::init(path::objects());
-        let tree = create_tree(&index, &storage, "".into()).await;
+        let mut cache_tree = CacheTree::new("");
+        let tree = create_tree(&index, &mut cache_tree, &storage, "".into(), HashKind::Sha1).await;
+        assert_eq!(cache_tree.tree_id(""), Some(tree.id));
 
         assert!(storage.get(&tree.id).is_ok());
         for item in tree.tree_items.iter() {
@@ -6,7 +6,10 @@ use ceres::protocol::ServiceType::UploadPack;
 use clap::Parser;
 use indicatif::ProgressBar;
 use mercury::internal::object::commit::Commit;
-use mercury::{errors::GitError, hash::SHA1};
+use mercury::{
+    errors::GitError,
+    hash::SHA1,
+};
 use tokio::io::{AsyncRead, AsyncReadExt};
 use tokio_util::io::StreamReader;
 use url::Url;
@@ -88,6 +91,11 @@ pub async fn fetch_repository(remote_config: &RemoteConfig) {
         tracing::warn!("fetch empty, no refs found");
         return;
     }
+    let kind = Config::object_format().await;
+    if refs[0]._hash.len() != kind.hex_len() {
+        eprintln!("fatal: mismatched object format: the local repository uses {}", kind);
+        return;
+    }
 
     let want = refs
         .iter()
@@ -97,7 +105,7 @@ pub async fn fetch_repository(remote_config: &RemoteConfig) {
     let have = current_have().await;
 
     let mut result_stream = http_client
-        .fetch_objects(&have, &want, auth.to_owned())
+        .fetch_objects(&have, &want, auth.to_owned(), kind)
         .await
         .unwrap();
 
@@ -143,9 +151,10 @@ pub async fn fetch_repository(remote_config: &RemoteConfig) {
 
     /* save pack file */
     let pack_file = {
-        let hash = SHA1::new(&pack_data[..pack_data.len() - 20].to_vec());
+        let trailer = pack_data.len() - kind.size();
+        let hash = SHA1::new_with_kind(kind, &pack_data[..trailer]);
 
-        let checksum = SHA1::from_bytes(&pack_data[pack_data.len() - 20..]);
+        let checksum = SHA1::from_bytes(&pack_data[trailer..]).unwrap();
         assert_eq!(hash, checksum);
         let checksum = checksum.to_plain_str();
         println!("checksum: {}", checksum);
@@ -164,7 +173,13 @@ pub async fn fetch_repository(remote_config: &RemoteConfig) {
         pack_file,
         index_file: None,
         index_version: None,
-    });
+        rev_index: false,
+        object_format: Some(kind),
+    })
+    .await;
+    if let Err(e) = utils::util::objects_storage().write_multi_pack_index(kind) {
+        tracing::warn!("failed to write multi-pack-index: {}", e);
+    }
 
     /* update reference  */
     for reference in refs.iter().filter(|r| r._ref.starts_with("refs/heads")) {
@@ -0,0 +1,142 @@
+use clap::Parser;
+
+use mercury::internal::fsck::{Fsck, FsckIssueKind, FsckReport};
+use mercury::internal::index::cache_tree::CacheTree;
+use mercury::internal::index::Index;
+
+use crate::internal::branch::Branch;
+use crate::internal::config::Config;
+use crate::internal::head::Head;
+use crate::utils::{path, util};
+
+#[derive(Parser, Debug)]
+pub struct FsckArgs {
+    /// Don't report the objects that nothing references
+    #[clap(long)]
+    pub no_dangling: bool,
+}
+
+pub async fn execute(args: FsckArgs) {
+    let report = check_repository().await;
+    for issue in &report.issues {
+        if args.no_dangling && issue.kind == FsckIssueKind::Dangling {
+            continue;
+        }
+        println!("{}", issue);
+    }
+    if !report.is_ok() {
+        eprintln!(
+            "fatal: {} errors in {} objects",
+            report.errors().count(),
+            report.objects
+        );
+    }
+}
+
+/// Check every object of the repository, and their connectivity from the branches,
+/// the remote-tracking branches, a detached HEAD and the index.
+pub async fn check_repository() -> FsckReport {
+    let kind = Config::object_format().await;
+    let storage = util::objects_storage();
+    let mut fsck = Fsck::new(kind);
+    for id in storage.list_objects(kind) {
+        match storage.get_with_type(&id) {
+            Ok((data, obj_type)) => {
+                fsck.check(id, obj_type, &data);
+            }
+            Err(e) => fsck.unreadable(id, e.to_string()),
+        }
+    }
+
+    for branch in Branch::list_branches(None).await {
+        fsck.add_root(&format!("refs/heads/{}", branch.name), branch.commit);
+    }
+    for remote in Config::all_remote_configs().await {
+        for branch in Branch::list_branches(Some(&remote.name)).await {
+            let name = format!("refs/remotes/{}/{}", remote.name, branch.name);
+            fsck.add_root(&name, branch.commit);
+        }
+    }
+    if let Head::Detached(commit) = Head::current().await {
+        fsck.add_root("HEAD", commit);
+    }
+
+    match Index::load(path::index(), kind) {
+        Ok(index) => {
+            for entry in index.tracked_entries(0) {
+                // submodule commits live in another repository
+                if entry.mode != 0o160000 {
+                    fsck.add_root(&format!("index:{}", entry.name), entry.hash);
+                }
+            }
+            if let Some(cache_tree) = index.cache_tree() {
+                add_cache_tree_roots(&mut fsck, cache_tree, "");
+            }
+        }
+        Err(e) => eprintln!("error: invalid index: {}", e),
+    }
+    fsck.finish()
+}
+
+/// The trees of the cached tree extension are written, so they must exist.
+fn add_cache_tree_roots(fsck: &mut Fsck, tree: &CacheTree, dir: &str) {
+    if let Some((_, id)) = tree.valid {
+        fsck.add_root(&format!("index cache-tree:{}/", dir), id);
+    }
+    for subtree in &tree.subtrees {
+        let dir = if dir.is_empty() {
+            subtree.name.clone()
+        } else {
+            format!("{}/{}", dir, subtree.name)
+        };
+        add_cache_tree_roots(fsck, subtree, &dir);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use mercury::internal::object::blob::Blob;
+    use mercury::internal::object::commit::Commit;
+    use mercury::internal::object::tree::{Tree, TreeItem, TreeItemMode};
+
+    use super::*;
+    use crate::command::{format_commit_msg, save_object};
+    use crate::utils::test;
+
+    #[tokio::test]
+    async fn test_check_repository() {
+        test::setup_with_new_libra().await;
+        let blob = Blob::from_content("hello");
+        save_object(&blob, &blob.id).unwrap();
+        let item = TreeItem::new(TreeItemMode::Blob, blob.id, "hello.txt".to_string());
+        let tree = Tree::from_tree_items(vec![item]).unwrap();
+        save_object(&tree, &tree.id).unwrap();
+        let commit = Commit::from_tree_id(tree.id, vec![], &format_commit_msg("init", None));
+        save_object(&commit, &commit.id).unwrap();
+        Branch::update_branch("master", &commit.id.to_plain_str(), None).await;
+
+        let report = check_repository().await;
+        assert_eq!(report.objects, 3);
+        assert!(report.issues.is_empty(), "{:?}", report.issues);
+
+        let dangling = Blob::from_content("dangling");
+        save_object(&dangling, &dangling.id).unwrap();
+        let missing = Commit::from_tree_id(tree.id, vec![], &format_commit_msg("lost", None));
+        Branch::update_branch("broken", &missing.id.to_plain_str(), None).await;
+
+        let report = check_repository().await;
+        let mut kinds: Vec<_> = report
+            .issues
+            .iter()
+            .map(|i| (i.kind, i.id.clone()))
+            .collect();
+        kinds.sort_by_key(|(_, id)| id.clone());
+        let mut expected = vec![
+            (FsckIssueKind::Dangling, dangling.id.to_string()),
+            (FsckIssueKind::Missing, missing.id.to_string()),
+        ];
+        expected.sort_by_key(|(_, id)| id.clone());
+        assert_eq!(kinds, expected);
+        assert!(!report.is_ok());
+    }
+}
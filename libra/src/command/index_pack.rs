@@ -1,14 +1,15 @@
 use std::collections::BTreeMap;
-use std::io::Write;
 use std::path::PathBuf;
 use std::sync::{Arc, Mutex};
 
-use byteorder::{BigEndian, WriteBytesExt};
 use clap::Parser;
-use sha1::{Digest, Sha1};
 
-use mercury::internal::pack::Pack;
+use mercury::internal::pack::{index, Pack};
+use mercury::internal::pack::index::{PackIndex, ReverseIndex};
 use mercury::errors::GitError;
+use mercury::hash::{HashKind, SHA1};
+
+use crate::internal::config::Config;
 
 #[derive(Parser, Debug)]
 pub struct IndexPackArgs {
@@ -24,9 +25,21 @@ pub struct IndexPackArgs {
     /// It allows to force the version for the generated pack index
     #[clap(long, required = false)]
     pub index_version: Option<u8>,
+
+    /// Also write a reverse index (`.rev`) next to the index file
+    #[clap(long)]
+    pub rev_index: bool,
+
+    /// The object format of the pack, `sha1` or `sha256`, the one of the repository by default
+    #[clap(long, required = false)]
+    pub object_format: Option<HashKind>,
 }
 
-pub fn execute(args: IndexPackArgs) {
+pub async fn execute(args: IndexPackArgs) {
+    let kind = match args.object_format {
+        Some(kind) => kind,
+        None => Config::object_format().await,
+    };
     let pack_file = args.pack_file;
     let index_file = args.index_file.unwrap_or_else(|| {
         if !pack_file.ends_with(".pack") {
@@ -43,81 +56,75 @@ pub fn execute(args: IndexPackArgs) {
         return;
     }
 
-    if let Some(version) = args.index_version {
-        match version {
-            1 => build_index_v1(&pack_file, &index_file).unwrap(),
-            2 => println!("support later"),
-            _ => eprintln!("fatal: unsupported index version"),
+    // default version = 2, the same as git
+    let result = match args.index_version.unwrap_or(2) {
+        1 => build_index_v1(&pack_file, &index_file, kind),
+        2 => build_index_v2(&pack_file, &index_file, kind),
+        _ => {
+            eprintln!("fatal: unsupported index version");
+            return;
+        }
+    };
+    if let Err(e) = result {
+        eprintln!("fatal: {}", e);
+        return;
+    }
+    if args.rev_index {
+        if let Err(e) = build_rev_index(&index_file, kind) {
+            eprintln!("fatal: {}", e);
         }
-    } else {
-        // default version = 1
-        build_index_v1(&pack_file, &index_file).unwrap();
     }
 }
 
-/// Build index file for pack file, version 1
-/// [pack-format](https://git-scm.com/docs/pack-format)
-pub fn build_index_v1(pack_file: &str, index_file: &str) -> Result<(), GitError> {
+/// Decode the pack file of the object format `kind` to get the offset of every object,
+/// sorted by hash, and the pack checksum.
+fn decode_pack_objects(pack_file: &str, kind: HashKind) -> Result<(BTreeMap<SHA1, usize>, SHA1), GitError> {
     let pack_path = PathBuf::from(pack_file);
     let tmp_path = pack_path.parent().unwrap();
     let pack_file = std::fs::File::open(pack_file)?;
     let mut pack_reader = std::io::BufReader::new(pack_file);
     let obj_map = Arc::new(Mutex::new(BTreeMap::new())); // sorted by hash
     let obj_map_c = obj_map.clone();
-    let mut pack = Pack::new(Some(8), Some(1024 * 1024 * 1024), Some(tmp_path.to_path_buf()), true);
+    let mut pack = Pack::new(Some(8), Some(1024 * 1024 * 1024), Some(tmp_path.to_path_buf()), true)
+        .with_hash_kind(kind);
     pack.decode(&mut pack_reader, move |entry, offset| {
         obj_map_c.lock().unwrap().insert(entry.hash, offset);
     })?;
 
-    let mut index_hash = Sha1::new();
-    let mut index_file = std::fs::File::create(index_file)?;
-    // fan-out table
-    // The header consists of 256 4-byte network byte order integers.
-    // N-th entry of this table records the number of objects in the corresponding pack,
-    // the first byte of whose object name is less than or equal to N.
-    // This is called the first-level fan-out table.
-    let mut i: u8 = 0;
-    let mut cnt: u32 = 0;
-    let mut fan_out = Vec::with_capacity(256 * 4);
     let obj_map = Arc::try_unwrap(obj_map).unwrap().into_inner().unwrap();
-    for (hash, _) in obj_map.iter() { // sorted
-        let first_byte = hash.0[0];
-        while first_byte > i { // `while` rather than `if` to fill the gap, e.g. 0, 1, 2, 2, 2, 6
-            fan_out.write_u32::<BigEndian>(cnt)?;
-            i += 1;
-        }
-        cnt += 1;
-    }
-    // fill the rest
-    loop {
-        fan_out.write_u32::<BigEndian>(cnt)?;
-        if i == 255 {
-            break;
-        }
-        i += 1;
-    }
-    index_hash.update(&fan_out);
-    index_file.write_all(&fan_out)?;
-
-    // 4-byte network byte order integer, recording where the
-    // object is stored in the pack-file as the offset from the beginning.
-    // one object name of the appropriate size (20 bytes).
-    for (hash, offset) in obj_map {
-        let mut buf = Vec::with_capacity(24);
-        buf.write_u32::<BigEndian>(offset as u32)?;
-        buf.write_all(&hash.0)?;
-
-        index_hash.update(&buf);
-        index_file.write_all(&buf)?;
-    }
+    Ok((obj_map, pack.signature))
+}
 
-    index_hash.update(pack.signature.0);
-    // A copy of the pack checksum at the end of the corresponding pack-file.
-    index_file.write_all(&pack.signature.0)?;
-    let index_hash:[u8; 20] = index_hash.finalize().into();
-    // Index checksum of all of the above.
-    index_file.write_all(&index_hash)?;
+/// Build index file for pack file, version 1
+/// [pack-format](https://git-scm.com/docs/pack-format)
+pub fn build_index_v1(pack_file: &str, index_file: &str, kind: HashKind) -> Result<(), GitError> {
+    let (obj_map, signature) = decode_pack_objects(pack_file, kind)?;
+    let index = index::build_index_v1(&obj_map, &signature);
+    std::fs::write(index_file, index)?;
 
     tracing::debug!("Index file is written to {:?}", index_file);
     Ok(())
+}
+
+/// Build index file for pack file, version 2, with 64-bit offsets and the CRC32 of objects
+pub fn build_index_v2(pack_file: &str, index_file: &str, kind: HashKind) -> Result<(), GitError> {
+    let (obj_map, signature) = decode_pack_objects(pack_file, kind)?;
+    let pack_size = std::fs::metadata(pack_file)?.len() as usize;
+    let pack_reader = std::io::BufReader::new(std::fs::File::open(pack_file)?);
+    let index = index::build_index_v2(&obj_map, &signature, pack_reader, pack_size)?;
+    std::fs::write(index_file, index)?;
+
+    tracing::debug!("Index file is written to {:?}", index_file);
+    Ok(())
+}
+
+/// Build the reverse index (`.rev`) of the pack of an index file
+pub fn build_rev_index(index_file: &str, kind: HashKind) -> Result<(), GitError> {
+    let index = PackIndex::open(index_file.as_ref(), kind)?;
+    let rev = ReverseIndex::build(&index);
+    let rev_file = PathBuf::from(index_file).with_extension("rev");
+    std::fs::write(&rev_file, rev.to_bytes(&index.pack_hash()))?;
+
+    tracing::debug!("Reverse index file is written to {:?}", rev_file);
+    Ok(())
 }
\ No newline at end of file
@@ -5,6 +5,9 @@
 // Import necessary standard libraries
 use std::{env, fs, io};
 
+use clap::Parser;
+use mercury::hash::HashKind;
+
 // Import necessary libraries from sea_orm
 use sea_orm::{ActiveModelTrait, DbConn, DbErr, Set, TransactionTrait};
 
@@ -13,16 +16,23 @@ use crate::internal::db;
 use crate::internal::model::{config, reference};
 use crate::utils::util::{DATABASE, ROOT_DIR};
 
+#[derive(Parser, Debug, Default)]
+pub struct InitArgs {
+    /// The hash algorithm of the object ids, `sha1` or `sha256`
+    #[clap(long, default_value = "sha1")]
+    pub object_format: HashKind,
+}
+
 /// Execute the init function
-pub async fn execute() {
-    init().await.unwrap();
+pub async fn execute(args: InitArgs) {
+    init(args).await.unwrap();
 }
 
 /// Initialize a new Libra repository
 /// This function creates the necessary directories and files for a new Libra repository.
 /// It also sets up the database and the initial configuration.
 #[allow(dead_code)]
-pub async fn init() -> io::Result<()> {
+pub async fn init(args: InitArgs) -> io::Result<()> {
     // Get the current directory
     let cur_dir = env::current_dir()?;
     // Join the current directory with the root directory
@@ -55,7 +65,7 @@ pub async fn init() -> io::Result<()> {
     let conn = db::create_database(database.to_str().unwrap()).await?;
 
     // Create config table
-    init_config(&conn).await.unwrap();
+    init_config(&conn, args.object_format).await.unwrap();
 
     // Create HEAD
     reference::ActiveModel {
@@ -79,14 +89,16 @@ pub async fn init() -> io::Result<()> {
 
 /// Initialize the configuration for the Libra repository
 /// This function creates the necessary configuration entries in the database.
-async fn init_config(conn: &DbConn) -> Result<(), DbErr> {
+/// A repository with a non-default object format records it in `extensions.objectformat`,
+/// which requires `repositoryformatversion` 1.
+async fn init_config(conn: &DbConn, object_format: HashKind) -> Result<(), DbErr> {
     // Begin a new transaction
     let txn = conn.begin().await?;
 
     // Define the configuration entries for non-Windows systems
     #[cfg(not(target_os = "windows"))]
         let entries = [
-        ("repositoryformatversion", "0"),
+        ("repositoryformatversion", format_version(object_format)),
         ("filemode", "true"),
         ("bare", "false"),
         ("logallrefupdates", "true"),
@@ -95,7 +107,7 @@ async fn init_config(conn: &DbConn) -> Result<(), DbErr> {
     // Define the configuration entries for Windows systems
     #[cfg(target_os = "windows")]
         let entries = [
-        ("repositoryformatversion", "0"),
+        ("repositoryformatversion", format_version(object_format)),
         ("filemode", "false"), // no filemode on windows
         ("bare", "false"),
         ("logallrefupdates", "true"),
@@ -114,11 +126,29 @@ async fn init_config(conn: &DbConn) -> Result<(), DbErr> {
         };
         entry.insert(&txn).await?;
     }
+    if object_format != HashKind::Sha1 {
+        config::ActiveModel {
+            configuration: Set("extensions".to_owned()),
+            key: Set("objectformat".to_owned()),
+            value: Set(object_format.to_string()),
+            ..Default::default()
+        }
+        .insert(&txn)
+        .await?;
+    }
     // Commit the transaction
     txn.commit().await?;
     Ok(())
 }
 
+/// Repositories with extensions must have format version 1, or older clients will misread them
+fn format_version(object_format: HashKind) -> &'static str {
+    match object_format {
+        HashKind::Sha1 => "0",
+        _ => "1",
+    }
+}
+
 /// Set a directory as hidden on Windows systems
 /// This function uses the `attrib` command to set the directory as hidden.
 #[cfg(target_os = "windows")]
@@ -139,8 +169,12 @@ fn set_dir_hidden(_dir: &str) -> io::Result<()> {
 /// Unit tests for the init module
 #[cfg(test)]
 mod tests {
-    use super::init;
-    use crate::utils::test;
+    use super::{init, InitArgs};
+    use crate::internal::db::establish_connection;
+    use crate::internal::model::config;
+    use crate::utils::{path, test};
+    use mercury::hash::HashKind;
+    use sea_orm::{ColumnTrait, EntityTrait, QueryFilter};
 
     /// Test the init function
     #[tokio::test]
@@ -149,6 +183,36 @@ mod tests {
         test::setup_clean_testing_env();
 
         // Run the init function
-        init().await.unwrap();
+        init(InitArgs::default()).await.unwrap();
+    }
+
+    /// Test the init function with the sha256 object format
+    #[tokio::test]
+    async fn test_init_sha256() {
+        test::setup_clean_testing_env();
+
+        init(InitArgs {
+            object_format: HashKind::Sha256,
+        })
+        .await
+        .unwrap();
+
+        // the global connection may belong to the repo of an earlier test
+        let conn = establish_connection(path::database().to_str().unwrap())
+            .await
+            .unwrap();
+        let value = |key: &'static str| {
+            let conn = &conn;
+            async move {
+                config::Entity::find()
+                    .filter(config::Column::Key.eq(key))
+                    .one(conn)
+                    .await
+                    .unwrap()
+                    .map(|c| c.value)
+            }
+        };
+        assert_eq!(value("objectformat").await, Some("sha256".to_owned()));
+        assert_eq!(value("repositoryformatversion").await, Some("1".to_owned()));
     }
 }
\ No newline at end of file
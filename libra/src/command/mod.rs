@@ -3,6 +3,7 @@ pub mod branch;
 pub mod clone;
 pub mod commit;
 pub mod fetch;
+pub mod fsck;
 pub mod index_pack;
 pub mod init;
 pub mod log;
@@ -12,6 +13,7 @@ pub mod push;
 pub mod remote;
 pub mod remove;
 pub mod restore;
+pub mod sparse_checkout;
 pub mod status;
 pub mod switch;
 
@@ -120,7 +122,7 @@ mod test {
     #[tokio::test]
     async fn test_save_load_object() {
         test::setup_with_new_libra().await;
-        let object = Commit::from_tree_id(SHA1::new(&vec![1; 20]), vec![], "Commit_1");
+        let object = Commit::from_tree_id(SHA1::new(&[1; 20]), vec![], "Commit_1");
         save_object(&object, &object.id).unwrap();
         let _ = load_object::<Commit>(&object.id).unwrap();
     }
@@ -6,6 +6,7 @@ use clap::Parser;
 use colored::Colorize;
 use tokio::sync::mpsc;
 use url::Url;
+use ceres::lfs::pointer::LfsPointer;
 use ceres::protocol::ServiceType::ReceivePack;
 use ceres::protocol::smart::{add_pkt_line_string, read_pkt_line};
 use mercury::errors::GitError;
@@ -13,14 +14,18 @@ use mercury::hash::SHA1;
 use mercury::internal::object::blob::Blob;
 use mercury::internal::object::commit::Commit;
 use mercury::internal::object::tree::{Tree, TreeItemMode};
+use mercury::internal::object::types::ObjectType;
 use mercury::internal::pack::encode::PackEncoder;
 use mercury::internal::pack::entry::Entry;
+use mercury::internal::tree_diff::{diff_trees, DiffOptions};
 use crate::command::{ask_basic_auth, branch};
 use crate::internal::branch::Branch;
 use crate::internal::config::Config;
 use crate::internal::head::Head;
 use crate::internal::protocol::https_client::{BasicAuth, HttpsClient};
+use crate::internal::protocol::lfs_client::LFSClient;
 use crate::internal::protocol::ProtocolClient;
+use crate::utils::{lfs, util};
 use crate::utils::object_ext::{BlobExt, CommitExt, TreeExt};
 
 #[derive(Parser, Debug)]
@@ -71,6 +76,7 @@ pub async fn execute(args: PushArgs) {
     }
     let repo_url = repo_url.unwrap();
 
+    let kind = Config::object_format().await;
     let branch = args.refspec.unwrap_or(branch);
     let commit_hash = Branch::find_branch(&branch, None).await.unwrap().commit.to_plain_str();
 
@@ -96,8 +102,8 @@ pub async fn execute(args: PushArgs) {
         .unwrap_or_else(|| format!("refs/heads/{}", branch));
 
     let tracked_ref = refs.iter().find(|r| r._ref == tracked_branch);
-    // [0; 20] if new branch
-    let remote_hash = tracked_ref.map(|r| r._hash.clone()).unwrap_or(SHA1::default().to_plain_str());
+    // zero id if new branch
+    let remote_hash = tracked_ref.map(|r| r._hash.clone()).unwrap_or(SHA1::zero(kind).to_plain_str());
     if remote_hash == commit_hash {
         println!("Everything up-to-date");
         return;
@@ -115,13 +121,31 @@ pub async fn execute(args: PushArgs) {
     let objs = incremental_objs(
         SHA1::from_str(&commit_hash).unwrap(),
         SHA1::from_str(&remote_hash).unwrap()
-    );
+    ).await;
+
+    // upload LFS objects before updating the ref, like `pre-push` hook of git-lfs
+    let lfs_pointers: Vec<LfsPointer> = objs
+        .iter()
+        .filter(|entry| entry.obj_type == ObjectType::Blob)
+        .filter_map(|entry| LfsPointer::parse(&entry.data))
+        .filter(|pointer| lfs::lfs_object_exist(&pointer.oid))
+        .collect();
+    if !lfs_pointers.is_empty() {
+        let lfs_client = LFSClient::from_url(&url);
+        if let Err(e) = lfs_client.push_objects(&lfs_pointers, auth.clone()).await {
+            eprintln!("fatal: failed to push LFS objects: {}", e);
+            return;
+        }
+    }
 
     // let (tx, rx) = mpsc::channel::<Entry>();
-    let (entry_tx, entry_rx) = mpsc::channel(1_000_000);
+    let (entry_tx, entry_rx) = mpsc::channel::<Entry>(1_000_000);
     let (stream_tx, mut stream_rx) = mpsc::channel(1_000_000);
     
-    let encoder = PackEncoder::new(objs.len(), 0, stream_tx); // TODO: diff slow, so window_size = 0
+    let (window, depth) = Config::pack_delta().await;
+    let encoder = PackEncoder::new(window, stream_tx)
+        .with_max_depth(depth)
+        .with_hash_kind(kind);
     encoder.encode_async(entry_rx).await.unwrap();
 
     for entry in objs {
@@ -185,7 +209,7 @@ fn collect_history_commits(commit_id: &SHA1) -> HashSet<SHA1> {
     commits
 }
 
-fn incremental_objs(local_ref: SHA1, remote_ref: SHA1) -> HashSet<Entry> {
+async fn incremental_objs(local_ref: SHA1, remote_ref: SHA1) -> HashSet<Entry> {
     tracing::debug!("local_ref: {}, remote_ref: {}", local_ref, remote_ref);
 
     // just fast-forward optimization
@@ -211,7 +235,7 @@ fn incremental_objs(local_ref: SHA1, remote_ref: SHA1) -> HashSet<Entry> {
             for i in 0..commits.len() - 1 {
                 let old_tree = Commit::load(&commits[i]).tree_id;
                 let new_commit = Commit::load(&commits[i + 1]);
-                objs.extend(diff_tree_objs(Some(&old_tree), &new_commit.tree_id));
+                objs.extend(diff_tree_objs(Some(&old_tree), &new_commit.tree_id).await);
                 objs.insert(new_commit.into());
             }
             return objs;
@@ -239,7 +263,7 @@ fn incremental_objs(local_ref: SHA1, remote_ref: SHA1) -> HashSet<Entry> {
         }
         for parent in parents.iter() {
             let parent_tree = Commit::load(parent).tree_id;
-            objs.extend(diff_tree_objs(Some(&parent_tree), &commit.tree_id));
+            objs.extend(diff_tree_objs(Some(&parent_tree), &commit.tree_id).await);
             if !exist_commits.contains(parent) {
                 queue.push_back(*parent);
             }
@@ -253,7 +277,7 @@ fn incremental_objs(local_ref: SHA1, remote_ref: SHA1) -> HashSet<Entry> {
     // root commit has no parent
     if let Some(root_commit) = root_commit {
         let root_tree = Commit::load(&root_commit).tree_id;
-        objs.extend(diff_tree_objs(None, &root_tree));
+        objs.extend(diff_tree_objs(None, &root_tree).await);
     }
 
     println!("Counting objects: {} done.", objs.len());
@@ -262,40 +286,35 @@ fn incremental_objs(local_ref: SHA1, remote_ref: SHA1) -> HashSet<Entry> {
 
 /// calc objects that in `new_tree` but not in `old_tree`
 /// - if `old_tree` is None, return all objects in `new_tree` (include tree itself)
-fn diff_tree_objs(old_tree: Option<&SHA1>, new_tree: &SHA1) -> HashSet<Entry> { // TODO: skip objs that has been added in caller
+async fn diff_tree_objs(old_tree: Option<&SHA1>, new_tree: &SHA1) -> HashSet<Entry> { // TODO: skip objs that has been added in caller
     let mut objs = HashSet::new();
-    if let Some(old_tree) = old_tree {
-        if old_tree == new_tree {
-            return objs;
-        }
+    if old_tree == Some(new_tree) {
+        return objs;
     }
+    objs.insert(Tree::load(new_tree).into()); // tree itself
 
-    let new_tree = Tree::load(new_tree);
-    objs.insert(new_tree.clone().into()); // tree itself
-
-    let old_items = match old_tree {
-        Some(tree) => {
-            let tree = Tree::load(tree);
-            tree.tree_items.iter()
-                .map(|item| item.id)
-                .collect::<HashSet<_>>()
-        }
-        None => HashSet::new()
+    // the added or changed trees and files, a moved file is sent again
+    let options = DiffOptions {
+        include_trees: true,
+        ..DiffOptions::plain()
     };
-
-    for item in new_tree.tree_items.iter() {
-        if !old_items.contains(&item.id) {
-            match item.mode {
-                TreeItemMode::Tree => {
-                    objs.extend(diff_tree_objs(None, &item.id)); //TODO optimize, find same name tree
-                }
-                _ => { // TODO: submodule (TreeItemMode: Commit)
-                    if item.mode == TreeItemMode::Commit { // (160000)| Gitlink (Submodule)
-                        eprintln!("fatal: submodule not supported");
-                    }
-                    let blob = Blob::load(&item.id);
-                    objs.insert(blob.into());
-                }
+    let changes = diff_trees(
+        &util::objects_storage(),
+        old_tree.copied(),
+        Some(*new_tree),
+        &options,
+    )
+    .await
+    .unwrap();
+    for item in changes.into_iter().filter_map(|change| change.new) {
+        match item.mode {
+            TreeItemMode::Tree => {
+                objs.insert(Tree::load(&item.id).into());
+            }
+            // TODO: submodule (TreeItemMode: Commit)
+            TreeItemMode::Commit => eprintln!("fatal: submodule not supported"), // (160000)| Gitlink (Submodule)
+            _ => {
+                objs.insert(Blob::load(&item.id).into());
             }
         }
     }
@@ -7,6 +7,7 @@ use colored::Colorize;
 use mercury::errors::GitError;
 
 use mercury::internal::index::Index;
+use crate::internal::config::Config;
 use crate::utils::path_ext::PathExt;
 use crate::utils::{path, util};
 
@@ -22,12 +23,13 @@ pub struct RemoveArgs {
     recursive: bool,
 }
 
-pub fn execute(args: RemoveArgs) -> Result<(), GitError> {
+pub async fn execute(args: RemoveArgs) -> Result<(), GitError> {
     if !util::check_repo_exist() {
         return Ok(());
     }
+    let kind = Config::object_format().await;
     let idx_file = path::index();
-    let mut index = Index::load(&idx_file)?;
+    let mut index = Index::load(&idx_file, kind)?;
     // check if pathspec is all in index
     if !validate_pathspec(&args.pathspec, &index) {
         return Ok(());
@@ -59,7 +61,7 @@ pub fn execute(args: RemoveArgs) -> Result<(), GitError> {
             }
         }
     }
-    index.save(&idx_file)?;
+    index.save(&idx_file, kind)?;
     Ok(())
 }
 
@@ -1,18 +1,26 @@
 use crate::internal::branch::Branch;
+use crate::internal::config::Config;
 use crate::internal::head::Head;
+use crate::internal::protocol::lfs_client::LFSClient;
+use crate::internal::protocol::ProtocolClient;
 use mercury::internal::index::{Index, IndexEntry};
 use crate::utils::object_ext::{BlobExt, CommitExt, TreeExt};
 use crate::utils::path_ext::PathExt;
+use crate::utils::sparse::SparseCheckout;
+use crate::utils::lfs::{self, LfsAttributes};
 use crate::utils::{path, util};
+use ceres::lfs::pointer::LfsPointer;
 use clap::Parser;
 use std::collections::{HashMap, HashSet};
 use std::fs;
 use std::path::PathBuf;
-use mercury::hash::SHA1;
+use mercury::errors::GitError;
+use mercury::hash::{HashKind, SHA1};
 use mercury::internal::object::blob::Blob;
 use mercury::internal::object::commit::Commit;
 use mercury::internal::object::tree::Tree;
 use mercury::internal::object::types::ObjectType;
+use url::Url;
 
 #[derive(Parser, Debug)]
 pub struct RestoreArgs {
@@ -50,6 +58,7 @@ pub async fn execute(args: RestoreArgs) {
         source = Some(HEAD.to_string());
     }
 
+    let kind = Config::object_format().await;
     let storage = util::objects_storage();
     let target_commit: Option<SHA1> = match source {
         None => {
@@ -66,7 +75,7 @@ pub async fn execute(args: RestoreArgs) {
                 Some(Branch::find_branch(src, None).await.unwrap().commit)
             } else {
                 // [Commit Hash, e.g. a1b2c3d4] || [Wrong Branch Name]
-                let objs = storage.search(src);
+                let objs = storage.search(src, kind);
                 // TODO hash can be `commit` or `tree`
                 if objs.len() != 1 || !storage.is_object_type(&objs[0], ObjectType::Commit) {
                     None // Wrong Commit Hash
@@ -83,7 +92,7 @@ pub async fn execute(args: RestoreArgs) {
         if source.is_none() {
             // only this situation, restore from [Index]
             assert!(!staged);
-            let index = Index::load(path::index()).unwrap();
+            let index = Index::load(path::index(), kind).unwrap();
             index
                 .tracked_entries(0)
                 .into_iter()
@@ -97,7 +106,7 @@ pub async fn execute(args: RestoreArgs) {
                 tree.get_plain_items()
             } else {
                 let src = source.unwrap();
-                if storage.search(&src).len() != 1 {
+                if storage.search(&src, kind).len() != 1 {
                     eprintln!("fatal: could not resolve {}", src);
                 } else {
                     eprintln!("fatal: reference is not a commit: {}", src);
@@ -117,10 +126,13 @@ pub async fn execute(args: RestoreArgs) {
     // The order is very important
     // `restore_worktree` will decide whether to delete the file based on whether it is tracked in the index.
     if worktree {
-        restore_worktree(&paths, &target_blobs);
+        if let Err(e) = fetch_lfs_objects(&paths, &target_blobs).await {
+            eprintln!("warning: failed to download LFS objects: {}", e);
+        }
+        restore_worktree(&paths, &target_blobs, kind);
     }
     if staged {
-        restore_index(&paths, &target_blobs);
+        restore_index(&paths, &target_blobs, kind);
     }
 }
 
@@ -136,10 +148,46 @@ fn preprocess_blobs(blobs: &[(PathBuf, SHA1)]) -> HashMap<PathBuf, SHA1> {
 
 /// restore a blob to file
 /// - `path` : to workdir
-fn restore_to_file(hash: &SHA1, path: &PathBuf) {
+pub fn restore_to_file(hash: &SHA1, path: &PathBuf) {
     let blob = Blob::load(hash);
     let path_abs = util::workdir_to_absolute(path);
-    util::write_file(&blob.data, &path_abs).unwrap();
+    let data = lfs::smudge(blob.data); // LFS pointer to real content
+    util::write_file(&data, &path_abs).unwrap();
+}
+
+/// Download the missing LFS objects of `target_blobs` in `filter` from the remote of current branch
+/// - failure is not fatal, the pointer files will be restored instead
+async fn fetch_lfs_objects(
+    filter: &[PathBuf],
+    target_blobs: &[(PathBuf, SHA1)],
+) -> Result<(), GitError> {
+    let sparse = SparseCheckout::load();
+    let pointers: Vec<LfsPointer> = target_blobs
+        .iter()
+        .filter(|(path, _)| util::is_sub_of_paths(util::workdir_to_absolute(path), filter))
+        .filter(|(path, _)| sparse.as_ref().is_none_or(|sparse| sparse.includes(path)))
+        .filter_map(|(_, hash)| LfsPointer::parse(&Blob::load(hash).data))
+        .filter(|pointer| !lfs::lfs_object_exist(&pointer.oid))
+        .collect();
+    if pointers.is_empty() {
+        return Ok(());
+    }
+
+    let remote = match Head::current().await {
+        Head::Branch(name) => Config::get("branch", Some(&name), "remote").await,
+        Head::Detached(_) => None,
+    }
+    .unwrap_or_else(|| "origin".to_string());
+    let url = match Config::get("remote", Some(&remote), "url").await {
+        Some(url) => url,
+        None => {
+            eprintln!("warning: no remote to download {} LFS objects", pointers.len());
+            return Ok(());
+        }
+    };
+    let url = Url::parse(&url)
+        .map_err(|e| GitError::CustomError(format!("invalid URL '{}': {}", url, e)))?;
+    LFSClient::from_url(&url).download_objects(&pointers, None).await
 }
 
 /// Get the deleted files in the worktree(vs Index), filtered by `filters`
@@ -162,8 +210,11 @@ fn get_worktree_deleted_files_in_filters(
 /// Restore the worktree
 /// - `filter`: abs or relative to current (user input)
 /// - `target_blobs`: to workdir path
-pub fn restore_worktree(filter: &Vec<PathBuf>, target_blobs: &[(PathBuf, SHA1)]) {
+/// - the files outside the sparse checkout are not restored
+/// - `kind`: the object format of the repository
+pub fn restore_worktree(filter: &Vec<PathBuf>, target_blobs: &[(PathBuf, SHA1)], kind: HashKind) {
     let target_blobs = preprocess_blobs(target_blobs);
+    let sparse = SparseCheckout::load();
     let deleted_files = get_worktree_deleted_files_in_filters(filter, &target_blobs);
 
     {
@@ -191,11 +242,16 @@ pub fn restore_worktree(filter: &Vec<PathBuf>, target_blobs: &[(PathBuf, SHA1)])
     let mut file_paths = util::integrate_pathspec(filter);
     file_paths.extend(deleted_files);
 
-    let index = Index::load(path::index()).unwrap();
+    let index = Index::load(path::index(), kind).unwrap();
+    let attributes = LfsAttributes::load();
     for path_wd in &file_paths {
         let path_abs = util::workdir_to_absolute(path_wd);
         if !path_abs.exists() {
             // file not exist, deleted or illegal
+            if sparse.as_ref().is_some_and(|sparse| !sparse.includes(path_wd)) {
+                // outside the sparse checkout, see `sparse-checkout`
+                continue;
+            }
             if target_blobs.contains_key(path_wd) {
                 // file in target_blobs (deleted), need to restore
                 restore_to_file(&target_blobs[path_wd], path_wd);
@@ -206,7 +262,7 @@ pub fn restore_worktree(filter: &Vec<PathBuf>, target_blobs: &[(PathBuf, SHA1)])
         } else {
             // file exists
             let path_wd_str = path_wd.to_string_or_panic();
-            let hash = util::calc_file_blob_hash(&path_abs).unwrap();
+            let hash = util::calc_file_blob_hash(&path_abs, kind, &attributes).unwrap();
             if target_blobs.contains_key(path_wd) {
                 // both in target & worktree: 1. modified 2. same
                 if hash != target_blobs[path_wd] {
@@ -243,11 +299,15 @@ fn get_index_deleted_files_in_filters(
         .collect() // HashSet auto deduplication
 }
 
-pub fn restore_index(filter: &Vec<PathBuf>, target_blobs: &[(PathBuf, SHA1)]) {
+/// Restore the index
+/// - the entries outside the sparse checkout get the skip-worktree bit
+/// - `kind`: the object format of the repository
+pub fn restore_index(filter: &Vec<PathBuf>, target_blobs: &[(PathBuf, SHA1)], kind: HashKind) {
     let target_blobs = preprocess_blobs(target_blobs);
+    let sparse = SparseCheckout::load();
 
     let idx_file = path::index();
-    let mut index = Index::load(&idx_file).unwrap();
+    let mut index = Index::load(&idx_file, kind).unwrap();
     let deleted_files_index = get_index_deleted_files_in_filters(&index, filter, &target_blobs);
 
     let mut file_paths = util::filter_to_fit_paths(&index.tracked_files(), filter);
@@ -263,10 +323,13 @@ pub fn restore_index(filter: &Vec<PathBuf>, target_blobs: &[(PathBuf, SHA1)]) {
                 let hash = target_blobs[path];
                 let blob = Blob::load(&hash);
                 index.add(IndexEntry::new_from_blob(
-                    path_str,
+                    path_str.clone(),
                     hash,
                     blob.data.len() as u32,
                 ));
+                if sparse.as_ref().is_some_and(|sparse| !sparse.includes(path)) {
+                    index.set_skip_worktree(&path_str, 0, true);
+                }
             } else {
                 eprintln!(
                     "fatal: pathspec '{}' did not match any files",
@@ -282,10 +345,13 @@ pub fn restore_index(filter: &Vec<PathBuf>, target_blobs: &[(PathBuf, SHA1)]) {
                     // modified
                     let blob = Blob::load(&hash);
                     index.update(IndexEntry::new_from_blob(
-                        path_str,
+                        path_str.clone(),
                         hash,
                         blob.data.len() as u32,
                     ));
+                    if sparse.as_ref().is_some_and(|sparse| !sparse.includes(path)) {
+                        index.set_skip_worktree(&path_str, 0, true);
+                    }
                 } // else: same, keep
             } else {
                 // not in target but in index: need to delete
@@ -293,5 +359,5 @@ pub fn restore_index(filter: &Vec<PathBuf>, target_blobs: &[(PathBuf, SHA1)]) {
             }
         }
     }
-    index.save(&idx_file).unwrap(); // DO NOT forget to save
+    index.save(&idx_file, kind).unwrap(); // DO NOT forget to save
 }
@@ -0,0 +1,193 @@
+use std::fs;
+use std::path::Path;
+
+use clap::Subcommand;
+use mercury::hash::HashKind;
+use mercury::internal::index::{Index, IndexEntry};
+
+use crate::command::restore;
+use crate::internal::config::Config;
+use crate::utils::lfs::LfsAttributes;
+use crate::utils::sparse::SparseCheckout;
+use crate::utils::{path, util};
+
+#[derive(Subcommand, Debug)]
+pub enum SparseCheckoutCmds {
+    /// Enable sparse checkout, only the files in the root directory are checked out at first
+    Init,
+    /// Check out only the files of the given directories, and the files in their parents
+    Set {
+        /// The directories, relative to the root of the working tree
+        #[clap(required = true)]
+        dirs: Vec<String>,
+    },
+    /// Add directories to the sparse checkout
+    Add {
+        /// The directories, relative to the root of the working tree
+        #[clap(required = true)]
+        dirs: Vec<String>,
+    },
+    /// List the directories of the sparse checkout
+    List,
+    /// Disable sparse checkout and check out all the files again
+    Disable,
+}
+
+pub async fn execute(command: SparseCheckoutCmds) {
+    if !util::check_repo_exist() {
+        return;
+    }
+    let kind = Config::object_format().await;
+    let sparse = match command {
+        SparseCheckoutCmds::Init => SparseCheckout::load().unwrap_or_default(),
+        SparseCheckoutCmds::Set { dirs } => match with_dirs(SparseCheckout::default(), &dirs) {
+            Some(sparse) => sparse,
+            None => return,
+        },
+        SparseCheckoutCmds::Add { dirs } => {
+            let Some(sparse) = SparseCheckout::load() else {
+                eprintln!("fatal: no sparse-checkout to add to");
+                return;
+            };
+            match with_dirs(sparse, &dirs) {
+                Some(sparse) => sparse,
+                None => return,
+            }
+        }
+        SparseCheckoutCmds::List => {
+            match SparseCheckout::load() {
+                Some(sparse) => sparse.dirs.iter().for_each(|dir| println!("{}", dir)),
+                None => eprintln!("fatal: this worktree is not sparse"),
+            }
+            return;
+        }
+        SparseCheckoutCmds::Disable => {
+            update_worktree(None, kind);
+            if let Err(e) = SparseCheckout::remove() {
+                eprintln!("fatal: failed to disable sparse checkout: {}", e);
+            }
+            return;
+        }
+    };
+    if let Err(e) = sparse.save() {
+        eprintln!("fatal: failed to write the sparse-checkout file: {}", e);
+        return;
+    }
+    update_worktree(Some(&sparse), kind);
+}
+
+fn with_dirs(mut sparse: SparseCheckout, dirs: &[String]) -> Option<SparseCheckout> {
+    for dir in dirs {
+        if !sparse.add_dir(dir) {
+            eprintln!("fatal: '{}' is not a directory of the working tree", dir);
+            return None;
+        }
+    }
+    Some(sparse)
+}
+
+/// Check out the files of the index in `sparse`, all of them if `None`, and remove the others from
+/// the working tree, setting their skip-worktree bit.
+/// - the files with local changes are kept, and checked out
+/// - `kind`: the object format of the repository
+pub fn update_worktree(sparse: Option<&SparseCheckout>, kind: HashKind) {
+    let workdir = util::working_dir();
+    let index_file = path::index();
+    let mut index = Index::load(&index_file, kind).unwrap();
+    let attributes = LfsAttributes::load();
+    let entries: Vec<_> = index
+        .tracked_entries(0)
+        .into_iter()
+        .map(|entry| (entry.name.clone(), entry.hash, entry.flags.skip_worktree))
+        .collect();
+
+    let mut kept = Vec::new();
+    for (name, hash, skipped) in entries {
+        let included = sparse.is_none_or(|sparse| sparse.includes(&name));
+        let file = Path::new(&name);
+        let file_abs = workdir.join(file);
+        if included && skipped {
+            restore::restore_to_file(&hash, &file.to_path_buf());
+            index.update(IndexEntry::new_from_file(file, hash, &workdir).unwrap());
+        } else if !included && !skipped {
+            if file_abs.exists() {
+                if index.is_modified(&name, 0, &workdir)
+                    && util::calc_file_blob_hash(&file_abs, kind, &attributes).unwrap() != hash
+                {
+                    kept.push(name);
+                    continue;
+                }
+                fs::remove_file(&file_abs).unwrap();
+                util::clear_empty_dir(&file_abs);
+            }
+            index.set_skip_worktree(&name, 0, true);
+        }
+    }
+    index.save(&index_file, kind).unwrap();
+
+    if !kept.is_empty() {
+        println!(
+            "warning: the following files have local changes, they are kept in the working tree:"
+        );
+        kept.iter().for_each(|name| println!("\t{}", name));
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::command::add::{self, AddArgs};
+    use crate::command::status;
+    use crate::utils::test;
+
+    #[tokio::test]
+    async fn test_sparse_checkout() {
+        test::setup_with_new_libra().await;
+        test::ensure_file("top.txt", Some("top"));
+        test::ensure_file("sparse_a/a.txt", Some("a"));
+        test::ensure_file("sparse_b/b.txt", Some("b"));
+        test::ensure_file("sparse_b/c/c.txt", Some("c"));
+        add::execute(AddArgs {
+            pathspec: vec![String::from(".")],
+            all: false,
+            update: false,
+            verbose: false,
+        })
+        .await;
+
+        execute(SparseCheckoutCmds::Set {
+            dirs: vec![String::from("sparse_a")],
+        })
+        .await;
+        assert!(Path::new("top.txt").exists());
+        assert!(Path::new("sparse_a/a.txt").exists());
+        assert!(!Path::new("sparse_b").exists());
+        let index = Index::load(path::index(), HashKind::Sha1).unwrap();
+        assert!(index.skip_worktree("sparse_b/c/c.txt", 0));
+        assert!(!index.skip_worktree("sparse_a/a.txt", 0));
+        // the files outside the sparse checkout are not deleted
+        assert!(status::changes_to_be_staged().await.deleted.is_empty());
+
+        execute(SparseCheckoutCmds::Add {
+            dirs: vec![String::from("sparse_b/c")],
+        })
+        .await;
+        assert!(Path::new("sparse_b/c/c.txt").exists());
+        assert!(Path::new("sparse_b/b.txt").exists());
+        assert_eq!(SparseCheckout::load().unwrap().dirs.len(), 2);
+
+        execute(SparseCheckoutCmds::Set {
+            dirs: vec![String::from("sparse_b/c")],
+        })
+        .await;
+        assert!(!Path::new("sparse_a").exists());
+
+        execute(SparseCheckoutCmds::Disable).await;
+        assert!(Path::new("sparse_a/a.txt").exists());
+        assert!(SparseCheckout::load().is_none());
+        let index = Index::load(path::index(), HashKind::Sha1).unwrap();
+        assert!(!index.skip_worktree("sparse_a/a.txt", 0));
+        let changes = status::changes_to_be_staged().await;
+        assert!(changes.deleted.is_empty() && changes.modified.is_empty());
+    }
+}
@@ -1,15 +1,27 @@
-use std::collections::HashSet;
-use std::path::PathBuf;
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
 
+use async_trait::async_trait;
 use colored::Colorize;
 use path_abs::PathInfo;
 
+use mercury::errors::GitError;
+use mercury::hash::{HashKind, SHA1};
+use mercury::internal::index::cache_tree::CacheTree;
+use mercury::internal::index::untracked_cache::{StatData, UntrackedCache, UntrackedDir};
+use mercury::internal::index::IndexEntry;
 use mercury::internal::object::commit::Commit;
-use mercury::internal::object::tree::Tree;
+use mercury::internal::object::tree::{Tree, TreeItem, TreeItemMode};
+use mercury::internal::tree_diff::{diff_trees, ChangeKind, DiffOptions, TreeStore};
 
+use crate::internal::config::Config;
 use crate::internal::head::Head;
 use mercury::internal::index::Index;
-use crate::utils::object_ext::{CommitExt, TreeExt};
+use crate::utils::client_storage::ClientStorage;
+use crate::utils::object_ext::CommitExt;
+use crate::utils::lfs::LfsAttributes;
 use crate::utils::{path, util};
 
 /// path: to workdir
@@ -48,7 +60,7 @@ pub async fn execute() {
     // TODO .gitignore
     match Head::current().await {
         Head::Detached(commit) => {
-            println!("HEAD detached at {}", String::from_utf8_lossy(&commit.0[0..7]));
+            println!("HEAD detached at {}", String::from_utf8_lossy(&commit.as_bytes()[0..7]));
         }
         Head::Branch(branch) => {
             println!("On branch {}", branch);
@@ -61,7 +73,7 @@ pub async fn execute() {
 
     // to cur_dir relative path
     let staged = changes_to_be_committed().await.to_relative();
-    let unstaged = changes_to_be_staged().to_relative();
+    let unstaged = changes_to_be_staged().await.to_relative();
     if staged.is_empty() && unstaged.is_empty() {
         println!("nothing to commit, working tree clean");
         return;
@@ -112,7 +124,7 @@ pub async fn execute() {
  */
 pub async fn changes_to_be_committed() -> Changes {
     let mut changes = Changes::default();
-    let index = Index::load(path::index()).unwrap();
+    let index = Index::load(path::index(), Config::object_format().await).unwrap();
     let head_commit = Head::current_commit().await;
     let tracked_files = index.tracked_files();
 
@@ -123,54 +135,285 @@ pub async fn changes_to_be_committed() -> Changes {
 
     let head_commit = head_commit.unwrap();
     let commit = Commit::load(&head_commit);
-    let tree = Tree::load(&commit.tree_id);
-    let tree_files = tree.get_plain_items();
-
-    for (item_path, item_hash) in tree_files.iter() {
-        let item_str = item_path.to_str().unwrap();
-        if index.tracked(item_str, 0) {
-            if !index.verify_hash(item_str, 0, item_hash) {
-                changes.modified.push(item_path.clone());
-            }
-        } else {
-            // in the last commit but not in the index
-            changes.deleted.push(item_path.clone());
+    let mut trees = IndexTrees {
+        trees: HashMap::new(),
+        storage: ClientStorage::init(path::objects()),
+    };
+    let entries: Vec<_> = index
+        .tracked_entries(0)
+        .into_iter()
+        .map(|entry| (entry.name.as_str(), entry))
+        .collect();
+    let index_tree = trees.build("", &entries, index.cache_tree());
+    // the directories still valid in the cache tree have the ids of the committed trees,
+    // so the diff skips them without reading them
+    let diff = diff_trees(
+        &trees,
+        Some(commit.tree_id),
+        index_tree,
+        &DiffOptions::plain(),
+    )
+    .await
+    .unwrap();
+    for change in diff {
+        let path = PathBuf::from(change.path());
+        match change.kind {
+            ChangeKind::Added => changes.new.push(path),
+            ChangeKind::Deleted => changes.deleted.push(path),
+            _ => changes.modified.push(path),
         }
     }
-    let tree_files_set: HashSet<PathBuf> = tree_files.into_iter().map(|(path, _)| path).collect();
-    // `new` means the files in index but not in the last commit
-    changes.new = tracked_files.into_iter()
-        .filter(|path| !tree_files_set.contains(path))
-        .collect();
 
     changes
 }
 
+/// The trees of the index, built in memory to be compared with the tree of HEAD, and the
+/// trees of the storage for the directories still valid in the index's `cache_tree`.
+struct IndexTrees {
+    trees: HashMap<SHA1, Tree>,
+    storage: ClientStorage,
+}
+
+impl IndexTrees {
+    /// Build the tree of `dir` (to workdir) from `entries`, the tracked entries under it with
+    /// their paths relative to `dir`, in the order of the index like `commit` does.
+    /// - return the id of the tree, `None` if there are no entries
+    fn build<'a>(
+        &mut self,
+        dir: &str,
+        entries: &[(&'a str, &'a IndexEntry)],
+        cache_tree: Option<&CacheTree>,
+    ) -> Option<SHA1> {
+        if entries.is_empty() {
+            return None;
+        }
+        if let Some(id) = cache_tree.and_then(|t| t.tree_id(dir)) {
+            return Some(id); // unchanged since it was committed, in the storage already
+        }
+        let mut items = Vec::new();
+        let mut i = 0;
+        while i < entries.len() {
+            let (name, entry) = entries[i];
+            let Some((sub, _)) = name.split_once('/') else {
+                items.push(TreeItem {
+                    name: name.to_string(),
+                    mode: TreeItemMode::tree_item_type_from_bytes(
+                        format!("{:o}", entry.mode).as_bytes(),
+                    )
+                    .unwrap(),
+                    id: entry.hash,
+                });
+                i += 1;
+                continue;
+            };
+            // the entries of a sub-dir are contiguous, as the index is sorted by path
+            let prefix = format!("{}/", sub);
+            let end = entries[i..]
+                .iter()
+                .position(|(name, _)| !name.starts_with(&prefix))
+                .map_or(entries.len(), |n| i + n);
+            let sub_entries: Vec<_> = entries[i..end]
+                .iter()
+                .map(|&(name, entry)| (&name[prefix.len()..], entry))
+                .collect();
+            let sub_dir = if dir.is_empty() {
+                sub.to_string()
+            } else {
+                format!("{}/{}", dir, sub)
+            };
+            if let Some(id) = self.build(&sub_dir, &sub_entries, cache_tree) {
+                items.push(TreeItem {
+                    name: sub.to_string(),
+                    mode: TreeItemMode::Tree,
+                    id,
+                });
+            }
+            i = end;
+        }
+        let tree = Tree::from_tree_items(items).unwrap();
+        let id = tree.id;
+        self.trees.insert(id, tree);
+        Some(id)
+    }
+}
+
+#[async_trait]
+impl TreeStore for IndexTrees {
+    async fn load_tree(&self, id: &SHA1) -> Result<Tree, GitError> {
+        match self.trees.get(id) {
+            Some(tree) => Ok(tree.clone()),
+            None => self.storage.load_tree(id).await,
+        }
+    }
+
+    async fn load_blob(&self, id: &SHA1) -> Result<Vec<u8>, GitError> {
+        self.storage.load_blob(id).await
+    }
+}
+
 /// Compare the difference between `index` and the `workdir`
-pub fn changes_to_be_staged() -> Changes {
+pub async fn changes_to_be_staged() -> Changes {
     let mut changes = Changes::default();
     let workdir = util::working_dir();
-    let index = Index::load(path::index()).unwrap();
+    let kind = Config::object_format().await;
+    let mut index = Index::load(path::index(), kind).unwrap();
+    let attributes = LfsAttributes::load();
     let tracked_files = index.tracked_files();
     for file in tracked_files.iter() {
         let file_str = file.to_str().unwrap();
+        if index.skip_worktree(file_str, 0) {
+            continue; // not checked out, see `sparse-checkout`
+        }
         let file_abs = util::workdir_to_absolute(file);
         if !file_abs.exists() {
             changes.deleted.push(file.clone());
         } else if index.is_modified(file_str, 0, &workdir) {
             // only calc the hash if the file is modified (metadata), for optimization
-            let file_hash = util::calc_file_blob_hash(&file_abs).unwrap();
+            let file_hash = util::calc_file_blob_hash(&file_abs, kind, &attributes).unwrap();
             if !index.verify_hash(file_str, 0, &file_hash) {
                 changes.modified.push(file.clone());
             }
         }
     }
-    let files = util::list_workdir_files().unwrap(); // to workdir
-    for file in files.iter() {
-        if !index.tracked(file.to_str().unwrap(), 0) {
-            // file not tracked in `index`
-            changes.new.push(file.clone());
-        }
+
+    // the untracked cache is only valid for this workdir
+    let ident = format!("libra {}\0", workdir.display()).into_bytes();
+    let mut untracked_cache = index
+        .untracked_cache()
+        .filter(|cache| cache.ident == ident)
+        .cloned()
+        .unwrap_or_else(|| UntrackedCache::new(&ident, kind));
+    let root = untracked_cache
+        .root
+        .get_or_insert_with(|| UntrackedDir::new(""));
+    let updated = refresh_untracked(&index, &workdir, Path::new(""), root, kind).unwrap();
+    collect_untracked(root, Path::new(""), &mut changes.new); // files not tracked in `index`
+    if updated {
+        index.set_untracked_cache(Some(untracked_cache));
+        index.save(path::index(), kind).unwrap();
     }
     changes
-}
\ No newline at end of file
+}
+
+/// Refresh the untracked files of `dir` (to workdir) and its sub-dirs in `cached`.
+/// Only the directories whose stat data changed since they were cached are read again,
+/// the others just have their sub-dirs checked.
+/// - `kind`: the object format of the repository
+/// - return whether `cached` changed
+fn refresh_untracked(
+    index: &Index,
+    workdir: &Path,
+    dir: &Path,
+    cached: &mut UntrackedDir,
+    kind: HashKind,
+) -> io::Result<bool> {
+    let dir_abs = workdir.join(dir);
+    let stat = StatData::from_metadata(&fs::metadata(&dir_abs)?);
+    let mut updated = false;
+    if !cached.valid || cached.stat.as_ref().map(|(s, _)| s) != Some(&stat) {
+        let mut untracked = Vec::new();
+        let mut dirs = Vec::new();
+        for entry in fs::read_dir(&dir_abs)? {
+            let entry = entry?;
+            let name = entry.file_name().to_string_lossy().to_string();
+            if entry.path().is_dir() {
+                if name == util::ROOT_DIR {
+                    continue; // ignore `.libra`
+                }
+                // keep the cache of the sub-dirs, they are checked below
+                let sub = match cached.dirs.iter().position(|d| d.name == name) {
+                    Some(i) => cached.dirs.swap_remove(i),
+                    None => UntrackedDir::new(&name),
+                };
+                dirs.push(sub);
+            } else if !index.tracked(&util::path_to_string(&dir.join(&name)), 0) {
+                untracked.push(name);
+            }
+        }
+        untracked.sort();
+        dirs.sort_by(|a, b| a.name.cmp(&b.name));
+        cached.untracked = untracked;
+        cached.dirs = dirs;
+        cached.valid = true;
+        cached.stat = Some((stat, SHA1::zero(kind))); // no per-dir exclude file yet
+        updated = true;
+    }
+    for sub in cached.dirs.iter_mut() {
+        updated |= refresh_untracked(index, workdir, &dir.join(&sub.name), sub, kind)?;
+    }
+    Ok(updated)
+}
+
+/// Collect the untracked files (to workdir) of the refreshed cache
+fn collect_untracked(cached: &UntrackedDir, dir: &Path, files: &mut Vec<PathBuf>) {
+    files.extend(cached.untracked.iter().map(|name| dir.join(name)));
+    for sub in &cached.dirs {
+        collect_untracked(sub, &dir.join(&sub.name), files);
+    }
+}
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::command::add::{self, AddArgs};
+    use crate::command::commit::{self, CommitArgs};
+    use crate::utils::test;
+
+    #[tokio::test]
+    async fn test_changes_to_be_committed() {
+        test::setup_with_new_libra().await;
+        // the workdir is kept between the tests
+        let _ = fs::remove_dir_all("changed");
+        test::ensure_file("kept/a.txt", Some("a"));
+        test::ensure_file("changed/b.txt", Some("b"));
+        test::ensure_file("changed/c.txt", Some("c"));
+        let add_all = || AddArgs {
+            pathspec: vec![],
+            all: true,
+            update: false,
+            verbose: false,
+        };
+        add::execute(add_all()).await;
+        commit::execute(CommitArgs {
+            message: String::from("init"),
+            allow_empty: false,
+        })
+        .await;
+        assert!(changes_to_be_committed().await.is_empty());
+
+        test::ensure_file("changed/b.txt", Some("b2"));
+        fs::remove_file("changed/c.txt").unwrap();
+        test::ensure_file("changed/d.txt", Some("d"));
+        add::execute(add_all()).await;
+        let changes = changes_to_be_committed().await;
+        assert_eq!(changes.modified, vec![PathBuf::from("changed/b.txt")]);
+        assert_eq!(changes.deleted, vec![PathBuf::from("changed/c.txt")]);
+        assert_eq!(changes.new, vec![PathBuf::from("changed/d.txt")]);
+    }
+
+    #[tokio::test]
+    async fn test_changes_to_be_staged_with_untracked_cache() {
+        test::setup_with_new_libra().await;
+        test::ensure_file("status_dir/a.txt", Some("a"));
+        test::ensure_file("status_dir/sub/b.txt", Some("b"));
+
+        let changes = changes_to_be_staged().await;
+        assert!(changes.new.contains(&PathBuf::from("status_dir/a.txt")));
+        assert!(changes.new.contains(&PathBuf::from("status_dir/sub/b.txt")));
+        let index = Index::load(path::index(), HashKind::Sha1).unwrap();
+        assert!(index.untracked_cache().is_some());
+
+        // the cached directories are invalidated by the index and by the new files
+        add::execute(AddArgs {
+            pathspec: vec![String::from("status_dir/a.txt")],
+            all: false,
+            update: false,
+            verbose: false,
+        })
+        .await;
+        test::ensure_file("status_dir/sub/c.txt", Some("c"));
+        let changes = changes_to_be_staged().await;
+        assert!(!changes.new.contains(&PathBuf::from("status_dir/a.txt")));
+        assert!(changes.new.contains(&PathBuf::from("status_dir/sub/b.txt")));
+        assert!(changes.new.contains(&PathBuf::from("status_dir/sub/c.txt")));
+    }
+}
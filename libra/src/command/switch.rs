@@ -3,7 +3,7 @@ use mercury::hash::SHA1;
 
 use crate::{
     command::branch,
-    internal::{branch::Branch, head::Head},
+    internal::{branch::Branch, config::Config, head::Head},
     utils::util::{self, get_commit_base},
 };
 
@@ -26,7 +26,7 @@ pub struct SwitchArgs {
 
 pub async fn execute(args: SwitchArgs) {
     // check status
-    let unstaged = status::changes_to_be_staged();
+    let unstaged = status::changes_to_be_staged().await;
     if !unstaged.deleted.is_empty() || !unstaged.modified.is_empty() {
         status::execute().await;
         eprintln!("fatal: uncommitted changes, can't switch branch");
@@ -44,7 +44,8 @@ pub async fn execute(args: SwitchArgs) {
         }
         None => match args.detach {
             true => {
-                let commit_base = get_commit_base(&args.branch.unwrap());
+                let kind = Config::object_format().await;
+                let commit_base = get_commit_base(&args.branch.unwrap(), kind);
                 if commit_base.is_err() {
                     eprintln!("{}", commit_base.unwrap());
                     return;
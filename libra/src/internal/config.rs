@@ -1,6 +1,8 @@
 use std::collections::HashSet;
 use std::mem::swap;
 
+use mercury::hash::HashKind;
+use mercury::internal::pack::encode::DEFAULT_DELTA_DEPTH;
 use sea_orm::ActiveValue::Set;
 use sea_orm::{ActiveModelTrait, ColumnTrait, EntityTrait, QueryFilter};
 
@@ -54,6 +56,28 @@ impl Config {
         values.first().map(|c| c.value.to_owned())
     }
 
+    /// The object format of the repository, set by `init --object-format`
+    pub async fn object_format() -> HashKind {
+        Self::get("extensions", None, "objectformat")
+            .await
+            .and_then(|format| format.parse().ok())
+            .unwrap_or_default()
+    }
+
+    /// The delta window and depth of the packs sent by `push`, `pack.window` and `pack.depth`
+    /// like git, 10 and 50 by default
+    pub async fn pack_delta() -> (usize, usize) {
+        let window = Self::get("pack", None, "window")
+            .await
+            .and_then(|window| window.parse().ok())
+            .unwrap_or(10);
+        let depth = Self::get("pack", None, "depth")
+            .await
+            .and_then(|depth| depth.parse().ok())
+            .unwrap_or(DEFAULT_DELTA_DEPTH);
+        (window, depth)
+    }
+
     /// Get all configuration values
     /// - e.g. remote.origin.url can be multiple
     pub async fn get_all(configuration: &str, name: Option<&str>, key: &str) -> Vec<String> {
@@ -10,7 +10,7 @@ use reqwest::{Body, Response};
 use std::io::Error as IoError;
 use tokio_util::bytes::BytesMut;
 use url::Url;
-use mercury::hash::SHA1;
+use mercury::hash::HashKind;
 
 /// A Git protocol client that communicates with a Git server over HTTPS.
 /// Only support `SmartProtocol` now, see https://www.git-scm.com/docs/http-protocol for protocol details.
@@ -122,10 +122,11 @@ impl HttpsClient {
                 }
             }
             let pkt_line = String::from_utf8(pkt_line.to_vec()).unwrap();
-            let (hash, mut refs) = pkt_line.split_at(40); // hex SHA1 string is 40 bytes
+            // the hex id is 40 or 64 bytes, depending on the object format
+            let (hash, mut refs) = pkt_line.split_once(' ').unwrap_or((&pkt_line, ""));
             refs = refs.trim();
             if !read_first_line {
-                if hash == SHA1::default().to_plain_str() {
+                if hash.bytes().all(|b| b == b'0') {
                     break; // empty repo, return empty list // TODO: parse capability
                 }
                 let (head, caps) = refs.split_once('\0').unwrap();
@@ -166,10 +167,11 @@ impl HttpsClient {
         have: &Vec<String>,
         want: &Vec<String>,
         auth: Option<BasicAuth>,
+        kind: HashKind,
     ) -> Result<impl StreamExt<Item = Result<Bytes, IoError>>, IoError> {
         // POST $GIT_URL/git-upload-pack HTTP/1.0
         let url = self.url.join("git-upload-pack").unwrap();
-        let body = generate_upload_pack_content(have, want).await;
+        let body = generate_upload_pack_content(have, want, kind).await;
         tracing::debug!("fetch_objects with body: {:?}", body);
 
         let mut req = self
@@ -215,12 +217,19 @@ impl HttpsClient {
         request.send().await
     }
 }
-/// for fetching
-async fn generate_upload_pack_content(have: &Vec<String>, want: &Vec<String>) -> Bytes {
+/// for fetching, `kind` is the object format of the repository
+async fn generate_upload_pack_content(
+    have: &Vec<String>,
+    want: &Vec<String>,
+    kind: HashKind,
+) -> Bytes {
     let mut buf = BytesMut::new();
     let mut write_first_line = false;
 
-    let capability = ["side-band-64k", "ofs-delta"].join(" ");
+    let mut capability = ["side-band-64k", "ofs-delta"].join(" ");
+    if kind != HashKind::Sha1 {
+        capability.push_str(&format!(" object-format={}", kind));
+    }
     for w in want {
         if !write_first_line {
             add_pkt_line_string(
@@ -287,7 +296,10 @@ mod tests {
         let want = refs.iter().map(|r| r._hash.clone()).collect();
 
         let have = vec!["81a162e7b725bbad2adfe01879fd57e0119406b9".to_string()];
-        let mut result_stream = client.fetch_objects(&have, &want, None).await.unwrap();
+        let mut result_stream = client
+            .fetch_objects(&have, &want, None, HashKind::Sha1)
+            .await
+            .unwrap();
 
         let mut buffer = vec![];
         while let Some(item) = result_stream.next().await {
@@ -323,7 +335,7 @@ mod tests {
         let have = vec!["1c05d7f7dd70e38150bfd2d5fb8fb969e2eb9851".to_string()];
         // **want MUST change to one of the refs in the remote repo, such as `refs/heads/main` before running the test**
         let want = vec!["7ef152d43162e28b3177f6df380112f6412f5b42".to_string()];
-        let body = generate_upload_pack_content(&have, &want).await;
+        let body = generate_upload_pack_content(&have, &want, HashKind::Sha1).await;
         tracing::info!("upload-pack content: {:?}", body);
         let mut cmd = tokio::process::Command::new("/usr/bin/git-upload-pack");
         cmd.arg("..");
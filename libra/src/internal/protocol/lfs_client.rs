@@ -0,0 +1,337 @@
+use super::ProtocolClient;
+use crate::internal::protocol::https_client::BasicAuth;
+use crate::utils::lfs;
+use ceres::lfs::lfs_structs::{BatchRequest, BatchResponse, FetchchunkResponse, Link, RequestVars};
+use ceres::lfs::pointer::LfsPointer;
+use mercury::errors::GitError;
+use reqwest::header::{ACCEPT, CONTENT_TYPE};
+use reqwest::{RequestBuilder, Response};
+use serde::de::DeserializeOwned;
+use url::Url;
+
+const LFS_CONTENT_TYPE: &str = "application/vnd.git-lfs+json";
+
+/// A Git LFS client that transfers objects with the `basic` transfer adapter.
+/// see https://github.com/git-lfs/git-lfs/blob/main/docs/api/batch.md
+pub struct LFSClient {
+    /// the LFS endpoint: `$GIT_URL/info/lfs/`
+    pub(crate) url: Url,
+    pub(crate) client: reqwest::Client,
+}
+
+impl ProtocolClient for LFSClient {
+    /// create client from the url of git repository
+    fn from_url(url: &Url) -> Self {
+        let mut url = url.clone();
+        if !url.path().ends_with('/') {
+            url.set_path(&format!("{}/", url.path()));
+        }
+        let url = url.join("info/lfs/").unwrap();
+        let client = reqwest::Client::builder().http1_only().build().unwrap();
+        Self { url, client }
+    }
+}
+
+impl LFSClient {
+    /// Upload the LFS objects (in `.libra/lfs/objects`) which the server doesn't have yet.
+    pub async fn push_objects(
+        &self,
+        pointers: &[LfsPointer],
+        auth: Option<BasicAuth>,
+    ) -> Result<(), GitError> {
+        let batch = self.batch("upload", pointers, auth.clone()).await?;
+        for object in batch.objects {
+            if let Some(err) = object.error {
+                return Err(GitError::NetworkError(format!(
+                    "LFS object {}: {}",
+                    object.oid, err.message
+                )));
+            }
+            // no `upload` action means the server already has it
+            let link = match object
+                .actions
+                .and_then(|mut actions| actions.remove("upload"))
+            {
+                Some(link) => link,
+                None => continue,
+            };
+            let data = tokio::fs::read(lfs::lfs_object_path(&object.oid)).await?;
+            println!(
+                "Uploading LFS object: {} ({} bytes)",
+                object.oid,
+                data.len()
+            );
+            let request = self.with_link(self.client.put(&link.href), &link, &auth);
+            check_response(request.body(data).send().await)?;
+        }
+        Ok(())
+    }
+
+    /// Download LFS objects into `.libra/lfs/objects`.
+    /// If the server runs in `split` mode, objects are fetched chunk by chunk.
+    pub async fn download_objects(
+        &self,
+        pointers: &[LfsPointer],
+        auth: Option<BasicAuth>,
+    ) -> Result<(), GitError> {
+        let mut split_enabled = true;
+        let mut rest = vec![];
+        for pointer in pointers {
+            if split_enabled {
+                match self.download_chunks(pointer, &auth).await? {
+                    Some(data) => {
+                        save_verified(pointer, &data)?;
+                        continue;
+                    }
+                    None => split_enabled = false,
+                }
+            }
+            rest.push(pointer.clone());
+        }
+        if rest.is_empty() {
+            return Ok(());
+        }
+
+        let batch = self.batch("download", &rest, auth.clone()).await?;
+        for object in batch.objects {
+            if let Some(err) = object.error {
+                return Err(GitError::ObjectNotFound(format!(
+                    "LFS object {}: {}",
+                    object.oid, err.message
+                )));
+            }
+            let link = object
+                .actions
+                .and_then(|mut actions| actions.remove("download"))
+                .ok_or_else(|| {
+                    GitError::NetworkError(format!("no download link for {}", object.oid))
+                })?;
+            println!("Downloading LFS object: {}", object.oid);
+            let request = self.with_link(self.client.get(&link.href), &link, &auth);
+            let data = read_bytes(check_response(request.send().await)?).await?;
+            let pointer = LfsPointer::new(object.oid, object.size);
+            save_verified(&pointer, &data)?;
+        }
+        Ok(())
+    }
+
+    /// Fetch the object by chunks, return `None` if the server doesn't support `objects/chunkids`
+    async fn download_chunks(
+        &self,
+        pointer: &LfsPointer,
+        auth: &Option<BasicAuth>,
+    ) -> Result<Option<Vec<u8>>, GitError> {
+        let body = RequestVars {
+            oid: pointer.oid.clone(),
+            size: pointer.size,
+            ..Default::default()
+        };
+        let url = self.url.join("objects/chunkids").unwrap();
+        let request = self.json_request(self.client.post(url), &body, auth);
+        let res = request
+            .send()
+            .await
+            .map_err(|e| GitError::NetworkError(e.to_string()))?;
+        if !res.status().is_success() {
+            tracing::debug!("chunk api is not available: {}", res.status());
+            return Ok(None);
+        }
+        let chunks: FetchchunkResponse = read_json(res).await?;
+        check_chunks(pointer, &chunks)?;
+
+        println!(
+            "Downloading LFS object: {} ({} chunks)",
+            pointer.oid,
+            chunks.chunks.len()
+        );
+        let mut data = vec![0u8; pointer.size as usize];
+        for chunk in chunks.chunks {
+            let request = self.with_link(self.client.get(&chunk.link.href), &chunk.link, auth);
+            let bytes = read_bytes(check_response(request.send().await)?).await?;
+            if bytes.len() as i64 != chunk.size || sha256::digest(bytes.as_slice()) != chunk.sub_oid
+            {
+                return Err(GitError::InvalidHashValue(chunk.sub_oid));
+            }
+            let offset = chunk.offset as usize;
+            data[offset..offset + bytes.len()].copy_from_slice(&bytes);
+        }
+        Ok(Some(data))
+    }
+
+    /// POST $LFS_URL/objects/batch
+    async fn batch(
+        &self,
+        operation: &str,
+        pointers: &[LfsPointer],
+        auth: Option<BasicAuth>,
+    ) -> Result<BatchResponse, GitError> {
+        let body = BatchRequest {
+            operation: operation.to_string(),
+            transfers: vec!["basic".to_string()],
+            objects: pointers
+                .iter()
+                .map(|p| RequestVars {
+                    oid: p.oid.clone(),
+                    size: p.size,
+                    ..Default::default()
+                })
+                .collect(),
+            hash_algo: "sha256".to_string(),
+            enable_split: None,
+        };
+        let url = self.url.join("objects/batch").unwrap();
+        let request = self.json_request(self.client.post(url), &body, &auth);
+        read_json(check_response(request.send().await)?).await
+    }
+
+    fn json_request<T: serde::Serialize>(
+        &self,
+        request: RequestBuilder,
+        body: &T,
+        auth: &Option<BasicAuth>,
+    ) -> RequestBuilder {
+        let mut request = request
+            .header(ACCEPT, LFS_CONTENT_TYPE)
+            .header(CONTENT_TYPE, LFS_CONTENT_TYPE)
+            .body(serde_json::to_vec(body).unwrap());
+        if let Some(auth) = auth {
+            request = request.basic_auth(auth.username.clone(), Some(auth.password.clone()));
+        }
+        request
+    }
+
+    /// apply the headers of action link
+    fn with_link(
+        &self,
+        mut request: RequestBuilder,
+        link: &Link,
+        auth: &Option<BasicAuth>,
+    ) -> RequestBuilder {
+        for (key, value) in &link.header {
+            request = request.header(key, value);
+        }
+        if let Some(auth) = auth {
+            if !link.header.contains_key("Authorization") {
+                request = request.basic_auth(auth.username.clone(), Some(auth.password.clone()));
+            }
+        }
+        request
+    }
+}
+
+fn check_response(res: Result<Response, reqwest::Error>) -> Result<Response, GitError> {
+    let res = res.map_err(|e| GitError::NetworkError(e.to_string()))?;
+    if res.status() == 401 {
+        return Err(GitError::UnAuthorized(
+            "May need to provide username and password".to_string(),
+        ));
+    }
+    if !res.status().is_success() {
+        return Err(GitError::NetworkError(format!(
+            "LFS request failed, status code: {}",
+            res.status()
+        )));
+    }
+    Ok(res)
+}
+
+async fn read_bytes(res: Response) -> Result<Vec<u8>, GitError> {
+    res.bytes()
+        .await
+        .map(|b| b.to_vec())
+        .map_err(|e| GitError::NetworkError(e.to_string()))
+}
+
+async fn read_json<T: DeserializeOwned>(res: Response) -> Result<T, GitError> {
+    let data = read_bytes(res).await?;
+    serde_json::from_slice(&data).map_err(|e| GitError::NetworkError(e.to_string()))
+}
+
+/// check the chunks sent by the server cover the object of `pointer` one after the other,
+/// so they can be copied to their offsets
+fn check_chunks(pointer: &LfsPointer, chunks: &FetchchunkResponse) -> Result<(), GitError> {
+    let invalid = |reason: String| {
+        GitError::CustomError(format!(
+            "invalid chunks of LFS object {}: {}",
+            pointer.oid, reason
+        ))
+    };
+    if chunks.size != pointer.size {
+        return Err(invalid(format!(
+            "the size is {}, expected {}",
+            chunks.size, pointer.size
+        )));
+    }
+    let mut ranges: Vec<(i64, i64)> = chunks.chunks.iter().map(|c| (c.offset, c.size)).collect();
+    ranges.sort_unstable();
+    let mut end = 0;
+    for (offset, size) in ranges {
+        if offset != end || size < 0 || size > pointer.size - end {
+            return Err(invalid(format!(
+                "a chunk of {} bytes at offset {}",
+                size, offset
+            )));
+        }
+        end += size;
+    }
+    if end != pointer.size {
+        return Err(invalid(format!("the chunks end at {}", end)));
+    }
+    Ok(())
+}
+
+/// check the sha256 of downloaded content before saving it
+fn save_verified(pointer: &LfsPointer, data: &[u8]) -> Result<(), GitError> {
+    if sha256::digest(data) != pointer.oid {
+        return Err(GitError::InvalidHashValue(pointer.oid.clone()));
+    }
+    lfs::save_lfs_object(&pointer.oid, data)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use ceres::lfs::lfs_structs::ChunkRepresentation;
+
+    #[test]
+    fn test_lfs_url() {
+        let url = Url::parse("http://localhost:8000/project/repo.git").unwrap();
+        let client = LFSClient::from_url(&url);
+        assert_eq!(
+            client.url.join("objects/batch").unwrap().as_str(),
+            "http://localhost:8000/project/repo.git/info/lfs/objects/batch"
+        );
+    }
+
+    #[test]
+    fn test_check_chunks() {
+        let pointer = LfsPointer {
+            oid: "0".repeat(64),
+            size: 10,
+        };
+        let response = |size: i64, ranges: &[(i64, i64)]| FetchchunkResponse {
+            oid: pointer.oid.clone(),
+            size,
+            chunks: ranges
+                .iter()
+                .map(|&(offset, size)| ChunkRepresentation {
+                    sub_oid: String::new(),
+                    offset,
+                    size,
+                    link: Link {
+                        href: String::new(),
+                        header: Default::default(),
+                        expires_at: String::new(),
+                    },
+                })
+                .collect(),
+        };
+        assert!(check_chunks(&pointer, &response(10, &[(4, 6), (0, 4)])).is_ok());
+        assert!(check_chunks(&pointer, &response(20, &[(0, 10), (10, 10)])).is_err());
+        assert!(check_chunks(&pointer, &response(10, &[(0, 4), (2, 8)])).is_err());
+        assert!(check_chunks(&pointer, &response(10, &[(0, 4), (6, 4)])).is_err());
+        assert!(check_chunks(&pointer, &response(10, &[(0, 4), (4, 100)])).is_err());
+        assert!(check_chunks(&pointer, &response(10, &[(0, 4)])).is_err());
+    }
+}
@@ -26,7 +26,7 @@ enum Commands {
 
     // Init and Clone are the only commands that can be executed without a repository
     #[command(about = "Initialize a new repository")]
-    Init,
+    Init(command::init::InitArgs),
     #[command(about = "Clone a repository into a new directory")]
     Clone(command::clone::CloneArgs),
 
@@ -58,6 +58,10 @@ enum Commands {
 
     #[command(subcommand, about = "Manage set of tracked repositories")]
     Remote(command::remote::RemoteCmds),
+    #[command(subcommand, about = "Reduce the working tree to a subset of directories")]
+    SparseCheckout(command::sparse_checkout::SparseCheckoutCmds),
+    #[command(about = "Verify the connectivity and validity of the objects in the database")]
+    Fsck(command::fsck::FsckArgs),
 
     // other hidden commands
     #[command(
@@ -73,7 +77,7 @@ enum Commands {
 async fn main() {
     let args = Cli::parse();
     // TODO: try check repo before parsing
-    if let Commands::Init = args.command {
+    if let Commands::Init(_) = args.command {
     } else if let Commands::Clone(_) = args.command {
     } else if !utils::util::check_repo_exist() {
         return;
@@ -90,10 +94,10 @@ async fn main() {
     }
     // parse the command and execute the corresponding function with it's args
     match args.command {
-        Commands::Init => command::init::execute().await,
+        Commands::Init(args) => command::init::execute(args).await,
         Commands::Clone(args) => command::clone::execute(args).await,
         Commands::Add(args) => command::add::execute(args).await,
-        Commands::Rm(args) => command::remove::execute(args).unwrap(),
+        Commands::Rm(args) => command::remove::execute(args).await.unwrap(),
         Commands::Restore(args) => command::restore::execute(args).await,
         Commands::Status => command::status::execute().await,
         Commands::Log(args) => command::log::execute(args).await,
@@ -102,10 +106,12 @@ async fn main() {
         Commands::Switch(args) => command::switch::execute(args).await,
         Commands::Merge(args) => command::merge::execute(args).await,
         Commands::Push(args) => command::push::execute(args).await,
-        Commands::IndexPack(args) => command::index_pack::execute(args),
+        Commands::IndexPack(args) => command::index_pack::execute(args).await,
         Commands::Fetch(args) => command::fetch::execute(args).await,
         Commands::Remote(cmd) => command::remote::execute(cmd).await,
         Commands::Pull(args) => command::pull::execute(args).await,
+        Commands::SparseCheckout(cmd) => command::sparse_checkout::execute(cmd).await,
+        Commands::Fsck(args) => command::fsck::execute(args).await,
     }
 }
 
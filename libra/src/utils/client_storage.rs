@@ -3,19 +3,24 @@ use std::collections::HashSet;
 use std::io::prelude::*;
 use std::path::{Path, PathBuf};
 use std::str::FromStr;
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
+use std::time::SystemTime;
 
-use byteorder::{BigEndian, ReadBytesExt};
+use async_trait::async_trait;
 use flate2::Compression;
 use flate2::read::ZlibDecoder;
 use flate2::write::ZlibEncoder;
 
 use mercury::internal::pack::cache_object::CacheObject;
+use mercury::internal::pack::index::PackIndex;
+use mercury::internal::pack::multi_pack_index::{build_multi_pack_index, MultiPackIndex};
 use mercury::internal::pack::Pack;
 use mercury::errors::GitError;
-use mercury::hash::SHA1;
+use mercury::hash::{HashKind, SHA1};
+use mercury::internal::object::tree::Tree;
 use mercury::internal::object::types::ObjectType;
-use mercury::utils::read_sha1;
+use mercury::internal::object::ObjectTrait;
+use mercury::internal::tree_diff::TreeStore;
 
 use crate::command;
 
@@ -51,7 +56,7 @@ impl ClientStorage {
         if self.exist_loosely(obj_id) {
             let raw_data = self.read_raw_data(obj_id)?;
             let data = Self::decompress_zlib(&raw_data)?;
-            let (obj_type, _, _) = Self::parse_header(&data);
+            let (obj_type, _, _) = Self::parse_header(&data)?;
             ObjectType::from_string(&obj_type)
         } else {
             self.get_from_pack(obj_id).unwrap()
@@ -68,16 +73,21 @@ impl ClientStorage {
         }
     }
 
-    /// Search objects that start with `obj_id`, loose & pack
-    pub fn search(&self, obj_id: &str) -> Vec<SHA1> {
-        let mut objs = self.list_objects_pack();
-        objs.extend(self.list_objects_loose());
-
-        objs.into_iter()
+    /// Search objects that start with `obj_id`, loose & pack, `kind` is the object format of the repo
+    pub fn search(&self, obj_id: &str, kind: HashKind) -> Vec<SHA1> {
+        self.list_objects(kind)
+            .into_iter()
             .filter(|x| x.to_plain_str().starts_with(obj_id))
             .collect()
     }
 
+    /// List all objects, loose & pack, `kind` is the object format of the repo
+    pub fn list_objects(&self, kind: HashKind) -> Vec<SHA1> {
+        let mut objs = self.list_objects_pack(kind);
+        objs.extend(self.list_objects_loose());
+        objs.into_iter().collect()
+    }
+
     /// list all objects' hash in `objects`
     fn list_objects_loose(&self) -> Vec<SHA1> {
         let mut objects = Vec::new();
@@ -101,11 +111,11 @@ impl ClientStorage {
     }
 
     /// List all objects' hash in PACKs
-    fn list_objects_pack(&self) -> HashSet<SHA1> {
-        let idxes = self.list_all_idx();
+    fn list_objects_pack(&self, kind: HashKind) -> HashSet<SHA1> {
+        let idxes = self.list_all_idx(kind);
         let mut objs = HashSet::new();
         for idx in idxes {
-            let res = Self::list_idx_objects(&idx).unwrap();
+            let res = Self::list_idx_objects(&idx, kind).unwrap();
             for obj in res {
                 objs.insert(obj);
             }
@@ -130,19 +140,25 @@ impl ClientStorage {
         Ok(decompressed_data)
     }
 
-    fn parse_header(data: &[u8]) -> (String, usize, usize) {
+    fn parse_header(data: &[u8]) -> Result<(String, usize, usize), GitError> {
+        let invalid = |msg: &str| GitError::InvalidObjectInfo(msg.to_string());
         let end_of_header = data.iter()
             .position(|&b| b == b'\0')
-            .expect("Invalid object: no header terminator");
+            .ok_or_else(|| invalid("Invalid object: no header terminator"))?;
         let header_str = std::str::from_utf8(&data[..end_of_header])
-            .expect("Invalid UTF-8 in header");
+            .map_err(|_| invalid("Invalid UTF-8 in header"))?;
 
         let mut parts = header_str.splitn(2, ' ');
-        let obj_type = parts.next().expect("No object type in header").to_string();
-        let size_str = parts.next().expect("No size in header");
-        let size = size_str.parse::<usize>().expect("Invalid size in header");
-        assert_eq!(size, data.len() - 1 - end_of_header, "Invalid object size");
-        (obj_type, size, end_of_header)
+        let obj_type = parts
+            .next()
+            .ok_or_else(|| invalid("No object type in header"))?
+            .to_string();
+        let size_str = parts.next().ok_or_else(|| invalid("No size in header"))?;
+        let size = size_str.parse::<usize>().map_err(|_| invalid("Invalid size in header"))?;
+        if size != data.len() - 1 - end_of_header {
+            return Err(invalid("Invalid object size"));
+        }
+        Ok((obj_type, size, end_of_header))
     }
 
     fn read_raw_data(&self, obj_id: &SHA1) -> Result<Vec<u8>, io::Error> {
@@ -154,17 +170,21 @@ impl ClientStorage {
     }
 
     pub fn get(&self, object_id: &SHA1) -> Result<Vec<u8>, GitError> {
+        self.get_with_type(object_id).map(|(data, _)| data)
+    }
+
+    /// Get the content and the type of an object, loose or in PACKs
+    pub fn get_with_type(&self, object_id: &SHA1) -> Result<(Vec<u8>, ObjectType), GitError> {
         if self.exist_loosely(object_id) {
             let raw_data = self.read_raw_data(object_id)?;
             let data = Self::decompress_zlib(&raw_data)?;
 
             // skip & check header
-            let (_, _, end_of_header) = Self::parse_header(&data);
-            Ok(data[end_of_header + 1..].to_vec())
+            let (obj_type, _, end_of_header) = Self::parse_header(&data)?;
+            let obj_type = ObjectType::from_string(&obj_type)?;
+            Ok((data[end_of_header + 1..].to_vec(), obj_type))
         } else {
-            // Ok(self.get_from_pack(object_id)?.unwrap().0)
-            self.get_from_pack(object_id).unwrap()
-                .map(|x| x.0)
+            self.get_from_pack(object_id)?
                 .ok_or(GitError::ObjectNotFound(object_id.to_plain_str()))
         }
     }
@@ -196,15 +216,57 @@ impl ClientStorage {
     }
 }
 
+/// Index files read from disk, with the modification time and size they were read at.
+type IndexCache<T> = Mutex<Vec<(PathBuf, SystemTime, u64, Arc<T>)>>;
+
+static PACK_INDEXES: IndexCache<PackIndex> = Mutex::new(Vec::new());
+static MULTI_PACK_INDEXES: IndexCache<MultiPackIndex> = Mutex::new(Vec::new());
+
+/// Read an index file, or reuse it if it was read before and isn't modified since.
+fn load_index<T>(
+    cache: &IndexCache<T>,
+    path: &Path,
+    open: impl FnOnce(&Path) -> Result<T, GitError>,
+) -> Result<Arc<T>, GitError> {
+    let metadata = fs::metadata(path)?;
+    let (modified, size) = (metadata.modified()?, metadata.len());
+    let mut cache = cache.lock().unwrap();
+    if let Some((_, _, _, index)) = cache
+        .iter()
+        .find(|(p, m, s, _)| p == path && *m == modified && *s == size)
+    {
+        return Ok(index.clone());
+    }
+    let index = Arc::new(open(path)?);
+    cache.retain(|(p, _, _, _)| p != path);
+    cache.push((path.to_path_buf(), modified, size, index.clone()));
+    Ok(index)
+}
+
+fn file_name(path: &Path) -> String {
+    path.file_name().unwrap().to_string_lossy().into_owned()
+}
+
 // TODO refactor to `PackReader`
 impl ClientStorage {
+    fn pack_dir(&self) -> PathBuf {
+        self.base_path.join("pack")
+    }
+
+    fn multi_pack_index_path(&self) -> PathBuf {
+        self.pack_dir().join("multi-pack-index")
+    }
+
     /// List all .pack files in `pack` directory
     fn list_all_packs(&self) -> Vec<PathBuf> {
-        let pack_dir = self.base_path.join("pack");
+        let pack_dir = self.pack_dir();
         let mut packs = Vec::new();
+        if !pack_dir.exists() {
+            return packs;
+        }
         for entry in fs::read_dir(pack_dir).unwrap() {
             let path = entry.unwrap().path();
-            if path.is_file() && path.extension().unwrap() == "pack" {
+            if path.is_file() && path.extension().is_some_and(|ext| ext == "pack") {
                 packs.push(path);
             }
         }
@@ -212,112 +274,97 @@ impl ClientStorage {
     }
 
     /// List all .idx files in `pack` directory
-    /// - If .idx file not exists, build it
-    fn list_all_idx(&self) -> Vec<PathBuf> {
+    /// - If .idx file not exists, build it for the object format `kind`
+    fn list_all_idx(&self, kind: HashKind) -> Vec<PathBuf> {
         let packs = self.list_all_packs();
         let mut idxs = Vec::new();
         for pack in packs {
             let idx = pack.with_extension("idx");
             if !idx.exists() {
-                command::index_pack::build_index_v1(pack.to_str().unwrap(), idx.to_str().unwrap()).unwrap();
+                command::index_pack::build_index_v2(pack.to_str().unwrap(), idx.to_str().unwrap(), kind).unwrap();
             }
             idxs.push(idx);
         }
         idxs
     }
 
-    /// Get object from PACKs by hash, if not found, return None
-    fn get_from_pack(&self, obj_id: &SHA1) -> Result<Option<(Vec<u8>, ObjectType)>, GitError> {
-        let idxes = self.list_all_idx(); // list or build
-        for idx in idxes {
-            let res = Self::read_pack_by_idx(&idx, obj_id)?;
-            if let Some(data) = res {
-                return Ok(Some((data.data_decompress.clone(), data.obj_type)));
-            }
+    /// Write the `multi-pack-index` of all packs, so that an object is found with one lookup
+    /// instead of one per pack. `kind` is the object format of the repo.
+    pub fn write_multi_pack_index(&self, kind: HashKind) -> Result<(), GitError> {
+        let mut indexes = Vec::new();
+        for idx in self.list_all_idx(kind) {
+            let index = load_index(&PACK_INDEXES, &idx, |path| PackIndex::open(path, kind))?;
+            indexes.push((file_name(&idx), index));
         }
-
-        Ok(None)
-    }
-
-    fn read_idx_fanout(idx_file: &Path) -> Result<[u32; 256], io::Error> {
-        let mut idx_file = fs::File::open(idx_file)?;
-        // const FANOUT: usize = 256 * 4;
-        let mut fanout: [u32; 256] = [0; 256]; // 256 * 4 bytes
-        let mut buf = [0; 4];
-        fanout.iter_mut().for_each(|x| {
-            idx_file.read_exact(&mut buf).unwrap();
-            *x = u32::from_be_bytes(buf);
-        });
-        Ok(fanout)
+        let packs: Vec<(String, &PackIndex)> = indexes
+            .iter()
+            .map(|(name, index)| (name.clone(), index.as_ref()))
+            .collect();
+        fs::write(self.multi_pack_index_path(), build_multi_pack_index(&packs))?;
+        Ok(())
     }
 
-    /// List all objects hash in .idx file
-    fn list_idx_objects(idx_file: &Path) -> Result<Vec<SHA1>, io::Error> {
-        let fanout: [u32; 256] = Self::read_idx_fanout(idx_file)?;
-        let mut idx_file = fs::File::open(idx_file)?;
-
-        let mut objs = Vec::new();
-        for _ in 0..fanout[255] {
-            let _offset = idx_file.read_u32::<BigEndian>()?;
-            let hash = read_sha1(&mut idx_file)?;
-
-            objs.push(hash);
+    /// The `multi-pack-index`, if it indexes exactly the packs of `idxes`.
+    fn multi_pack_index(&self, idxes: &[PathBuf]) -> Result<Option<Arc<MultiPackIndex>>, GitError> {
+        let path = self.multi_pack_index_path();
+        if !path.exists() {
+            return Ok(None);
         }
-        Ok(objs)
+        let midx = load_index(&MULTI_PACK_INDEXES, &path, MultiPackIndex::open)?;
+        let mut names: Vec<String> = idxes.iter().map(|idx| file_name(idx)).collect();
+        names.sort();
+        Ok((midx.pack_names() == names.as_slice()).then_some(midx))
     }
 
-    /// Read object `offset` from .idx file by `hash`
-    fn read_idx(idx_file: &Path, obj_id: &SHA1) -> Result<Option<u64>, io::Error> {
-        let fanout: [u32; 256] = Self::read_idx_fanout(idx_file)?;
-        let mut idx_file = fs::File::open(idx_file)?;
-
-        let first_byte = obj_id.0[0];
-        let start = if first_byte == 0 {
-            0
-        } else {
-            fanout[first_byte as usize - 1] as usize
-        };
-        let end = fanout[first_byte as usize] as usize;
-
-        const FANOUT: usize = 256 * 4;
-        idx_file.seek(io::SeekFrom::Start((FANOUT + 24 * start) as u64))?;
-        for _ in start..end {
-            let offset = idx_file.read_u32::<BigEndian>()?;
-            let hash = read_sha1(&mut idx_file)?;
-
-            if &hash == obj_id {
-                return Ok(Some(offset as u64));
+    /// Find the pack file and the offset of an object.
+    /// The `multi-pack-index` is used if it's up to date, or else the index of every pack.
+    fn find_in_packs(&self, obj_id: &SHA1) -> Result<Option<(PathBuf, u64)>, GitError> {
+        let kind = obj_id.kind(); // the packs are of the same object format as the id
+        let idxes = self.list_all_idx(kind); // list or build
+        if let Some(midx) = self.multi_pack_index(&idxes)? {
+            return Ok(midx.find(obj_id).map(|(pack_id, offset)| {
+                let idx = self.pack_dir().join(&midx.pack_names()[pack_id]);
+                (idx.with_extension("pack"), offset)
+            }));
+        }
+        for idx in idxes {
+            let index = load_index(&PACK_INDEXES, &idx, |path| PackIndex::open(path, kind))?;
+            if let Some(offset) = index.find(obj_id) {
+                return Ok(Some((idx.with_extension("pack"), offset)));
             }
         }
-
         Ok(None)
     }
 
-    /// Get object from pack by .idx file
-    fn read_pack_by_idx(idx_file: &Path, obj_id: &SHA1) -> Result<Option<CacheObject>, GitError> {
-        let pack_file = idx_file.with_extension("pack");
-        let res = Self::read_idx(idx_file, obj_id)?;
-        match res {
-            None => Ok(None),
-            Some(offset) => {
-                let res = Self::read_pack_obj(&pack_file, offset)?;
-                Ok(Some(res))
+    /// Get object from PACKs by hash, if not found, return None
+    fn get_from_pack(&self, obj_id: &SHA1) -> Result<Option<(Vec<u8>, ObjectType)>, GitError> {
+        match self.find_in_packs(obj_id)? {
+            Some((pack_file, offset)) => {
+                let obj = Self::read_pack_obj(&pack_file, offset, obj_id.kind())?;
+                Ok(Some((obj.data_decompress.clone(), obj.obj_type)))
             }
+            None => Ok(None),
         }
     }
 
-    /// Read object from pack file, with offset
-    fn read_pack_obj(pack_file: &Path, offset: u64) -> Result<CacheObject, GitError> {
+    /// List all objects hash in .idx file
+    fn list_idx_objects(idx_file: &Path, kind: HashKind) -> Result<Vec<SHA1>, GitError> {
+        let index = load_index(&PACK_INDEXES, idx_file, |path| PackIndex::open(path, kind))?;
+        Ok(index.hashes().collect())
+    }
+
+    /// Read object from pack file of the object format `kind`, with offset
+    fn read_pack_obj(pack_file: &Path, offset: u64, kind: HashKind) -> Result<CacheObject, GitError> {
         let file = fs::File::open(pack_file)?;
         let mut pack_reader = io::BufReader::new(&file);
         pack_reader.seek(io::SeekFrom::Start(offset))?;
-        let mut pack = Pack::new(None, None, None, false);
+        let mut pack = Pack::new(None, None, None, false).with_hash_kind(kind);
         let mut offset = offset as usize;
         let obj = pack.decode_pack_object(&mut pack_reader, &mut offset)?;
         match obj.obj_type {
             ObjectType::OffsetDelta => {
                 let base_offset = obj.base_offset;
-                let base_obj = Self::read_pack_obj(pack_file, base_offset as u64)?;
+                let base_obj = Self::read_pack_obj(pack_file, base_offset as u64, kind)?;
                 let base_obj = Arc::new(base_obj);
                 let new_obj = Pack::rebuild_delta(obj, base_obj);
                 Ok(new_obj)
@@ -325,9 +372,11 @@ impl ClientStorage {
             ObjectType::HashDelta => {
                 let base_hash = obj.base_ref;
                 let idx_file = pack_file.with_extension("idx");
-                let base_offset = Self::read_idx(&idx_file, &base_hash)?.unwrap();
+                let base_offset = load_index(&PACK_INDEXES, &idx_file, |path| PackIndex::open(path, kind))?
+                    .find(&base_hash)
+                    .ok_or(GitError::ObjectNotFound(base_hash.to_plain_str()))?;
 
-                let base_obj = Self::read_pack_obj(pack_file, base_offset)?;
+                let base_obj = Self::read_pack_obj(pack_file, base_offset, kind)?;
                 let base_obj = Arc::new(base_obj);
                 let new_obj = Pack::rebuild_delta(obj, base_obj);
                 Ok(new_obj)
@@ -337,15 +386,33 @@ impl ClientStorage {
     }
 }
 
+#[async_trait]
+impl TreeStore for ClientStorage {
+    async fn load_tree(&self, id: &SHA1) -> Result<Tree, GitError> {
+        match self.get_with_type(id)? {
+            (data, ObjectType::Tree) => Tree::from_bytes(&data, *id),
+            _ => Err(GitError::InvalidTreeObject),
+        }
+    }
+
+    async fn load_blob(&self, id: &SHA1) -> Result<Vec<u8>, GitError> {
+        self.get(id)
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use std::{env, fs};
     use std::path::PathBuf;
 
+    use mercury::hash::{HashKind, SHA1};
     use mercury::internal::object::blob::Blob;
     use mercury::internal::object::ObjectTrait;
     use mercury::internal::object::types::ObjectType;
+    use mercury::internal::pack::encode::encode_pack_object;
+    use mercury::internal::pack::entry::Entry;
 
+    use crate::command;
     use crate::utils::{test, util};
 
     use super::ClientStorage;
@@ -377,7 +444,7 @@ mod tests {
         let client_storage = ClientStorage::init(source.clone());
         assert!(client_storage.put(&blob.id, &blob.data, blob.get_type()).is_ok());
 
-        let objs = client_storage.search("5dd01c177");
+        let objs = client_storage.search("5dd01c177", HashKind::Sha1);
 
         assert_eq!(objs.len(), 1);
     }
@@ -427,6 +494,43 @@ mod tests {
 
     #[test]
     fn test_get_from_pack() {
-        unimplemented!();
+        let source = env::temp_dir().join("libra_test_get_from_pack").join("objects");
+        let _ = fs::remove_dir_all(&source);
+        let client_storage = ClientStorage::init(source.clone());
+        fs::create_dir_all(source.join("pack")).unwrap();
+
+        // a pack of two undeltified blobs
+        let blobs = [Blob::from_content("Hello, world!"), Blob::from_content("Hello, pack!")];
+        let mut pack = b"PACK".to_vec();
+        pack.extend_from_slice(&2u32.to_be_bytes());
+        pack.extend_from_slice(&(blobs.len() as u32).to_be_bytes());
+        for blob in &blobs {
+            pack.extend(encode_pack_object(&Entry::from(blob.clone())));
+        }
+        let checksum = SHA1::new(&pack);
+        pack.extend_from_slice(checksum.as_bytes());
+
+        let pack_file = source.join("pack").join(format!("pack-{}.pack", checksum));
+        fs::write(&pack_file, &pack).unwrap();
+        let idx_file = pack_file.with_extension("idx");
+        command::index_pack::build_index_v2(
+            pack_file.to_str().unwrap(),
+            idx_file.to_str().unwrap(),
+            HashKind::Sha1,
+        )
+        .unwrap();
+
+        for blob in &blobs {
+            assert_eq!(client_storage.get(&blob.id).unwrap(), blob.data);
+        }
+        // the same objects through the multi-pack-index
+        client_storage.write_multi_pack_index(HashKind::Sha1).unwrap();
+        assert!(source.join("pack").join("multi-pack-index").exists());
+        for blob in &blobs {
+            assert_eq!(client_storage.get(&blob.id).unwrap(), blob.data);
+            assert_eq!(client_storage.get_object_type(&blob.id).unwrap(), ObjectType::Blob);
+        }
+        assert!(client_storage.get(&SHA1::new(b"missing")).is_err());
+        assert_eq!(client_storage.search(&blobs[1].id.to_plain_str()[..8], HashKind::Sha1).len(), 1);
     }
 }
@@ -0,0 +1,188 @@
+//! Git LFS support in the working tree.
+//!
+//! Files matching a `filter=lfs` pattern of `.gitattributes` are stored as pointer blobs,
+//! while the real contents are kept in `.libra/lfs/objects`, using the same layout as git-lfs.
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use ceres::lfs::pointer::LfsPointer;
+
+use crate::utils::{path, util};
+
+pub const ATTRIBUTES_FILE: &str = ".gitattributes";
+
+/// `.libra/lfs/objects/ab/cd/abcd...`
+pub fn lfs_object_path(oid: &str) -> PathBuf {
+    path::lfs_objects()
+        .join(&oid[0..2])
+        .join(&oid[2..4])
+        .join(oid)
+}
+
+pub fn lfs_object_exist(oid: &str) -> bool {
+    lfs_object_path(oid).exists()
+}
+
+/// Save the content into lfs objects, the `oid` must be sha256 of the `data`
+pub fn save_lfs_object(oid: &str, data: &[u8]) -> io::Result<()> {
+    let path = lfs_object_path(oid);
+    if !path.exists() {
+        util::write_file(data, &path)?;
+    }
+    Ok(())
+}
+
+/// Parse the lfs rules of `.gitattributes` content
+/// - `true`: `filter=lfs` is set, `false`: the filter is unset or set to others
+fn parse_lfs_attributes(content: &str) -> Vec<(String, bool)> {
+    content
+        .lines()
+        .filter_map(|line| {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                return None;
+            }
+            let mut parts = line.split_whitespace();
+            let pattern = parts.next()?;
+            let mut lfs = None;
+            for attr in parts {
+                if attr == "filter=lfs" {
+                    lfs = Some(true);
+                } else if attr == "-filter" || attr == "!filter" || attr.starts_with("filter=") {
+                    lfs = Some(false);
+                }
+            }
+            lfs.map(|lfs| (pattern.to_string(), lfs))
+        })
+        .collect()
+}
+
+/// The lfs rules of the `.gitattributes` at the root of workdir, loaded once by a command
+/// for all the files it checks
+#[derive(Debug, Default)]
+pub struct LfsAttributes {
+    rules: Vec<(String, bool)>,
+}
+
+impl LfsAttributes {
+    pub fn load() -> Self {
+        let content =
+            fs::read_to_string(util::working_dir().join(ATTRIBUTES_FILE)).unwrap_or_default();
+        LfsAttributes {
+            rules: parse_lfs_attributes(&content),
+        }
+    }
+
+    /// Check if the file should be stored in LFS, later rules take precedence
+    /// - `path`: absolute or relative to current dir
+    pub fn is_tracked(&self, path: impl AsRef<Path>) -> bool {
+        if self.rules.is_empty() {
+            return false;
+        }
+        let path = util::to_workdir_path(path);
+        let path = path.to_str().unwrap().replace('\\', "/");
+        self.rules
+            .iter()
+            .rev()
+            .find(|(pattern, _)| match_attribute_pattern(pattern, &path))
+            .is_some_and(|(_, lfs)| *lfs)
+    }
+}
+
+/// Match a path (to workdir, separated by `/`) with a `.gitattributes` pattern
+/// - pattern without `/` matches the file name at any level
+fn match_attribute_pattern(pattern: &str, path: &str) -> bool {
+    if pattern.contains('/') {
+        wildmatch(pattern.trim_start_matches('/').as_bytes(), path.as_bytes())
+    } else {
+        let name = path.rsplit('/').next().unwrap_or(path);
+        wildmatch(pattern.as_bytes(), name.as_bytes())
+    }
+}
+
+/// glob matching: `*` & `?` don't match `/`, `**` matches any dirs
+fn wildmatch(pattern: &[u8], text: &[u8]) -> bool {
+    match pattern.first() {
+        None => text.is_empty(),
+        Some(b'*') if pattern.get(1) == Some(&b'*') => {
+            let rest = pattern[2..].strip_prefix(b"/").unwrap_or(&pattern[2..]);
+            (0..=text.len()).any(|i| wildmatch(rest, &text[i..]))
+        }
+        Some(b'*') => {
+            let rest = &pattern[1..];
+            for i in 0..=text.len() {
+                if wildmatch(rest, &text[i..]) {
+                    return true;
+                }
+                if i < text.len() && text[i] == b'/' {
+                    break;
+                }
+            }
+            false
+        }
+        Some(b'?') => !text.is_empty() && text[0] != b'/' && wildmatch(&pattern[1..], &text[1..]),
+        Some(c) => text.first() == Some(c) && wildmatch(&pattern[1..], &text[1..]),
+    }
+}
+
+/// Generate the pointer of the content
+pub fn generate_pointer(data: &[u8]) -> LfsPointer {
+    LfsPointer::new(sha256::digest(data), data.len() as i64)
+}
+
+/// `clean` filter: store the content in lfs objects and return the pointer file
+pub fn clean(data: &[u8]) -> io::Result<Vec<u8>> {
+    let pointer = generate_pointer(data);
+    save_lfs_object(&pointer.oid, data)?;
+    Ok(pointer.to_bytes())
+}
+
+/// `smudge` filter: replace the pointer file with the real content if it exists locally,
+/// otherwise the data is returned as is
+pub fn smudge(data: Vec<u8>) -> Vec<u8> {
+    if let Some(pointer) = LfsPointer::parse(&data) {
+        if let Ok(content) = fs::read(lfs_object_path(&pointer.oid)) {
+            return content;
+        }
+        eprintln!(
+            "warning: LFS object {} is missing, keep the pointer file",
+            pointer.oid
+        );
+    }
+    data
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse_lfs_attributes() {
+        let content =
+            "# comment\n*.bin filter=lfs diff=lfs merge=lfs -text\n*.txt text\nlocal.bin -filter\n";
+        let attrs = parse_lfs_attributes(content);
+        assert_eq!(
+            attrs,
+            vec![
+                ("*.bin".to_string(), true),
+                ("local.bin".to_string(), false)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_match_attribute_pattern() {
+        assert!(match_attribute_pattern("*.bin", "a.bin"));
+        assert!(match_attribute_pattern("*.bin", "dir/sub/a.bin"));
+        assert!(!match_attribute_pattern("*.bin", "a.bin.txt"));
+        assert!(match_attribute_pattern("assets/*.png", "assets/a.png"));
+        assert!(!match_attribute_pattern("assets/*.png", "assets/sub/a.png"));
+        assert!(match_attribute_pattern(
+            "assets/**/*.png",
+            "assets/sub/a.png"
+        ));
+        assert!(match_attribute_pattern("/data/?.dat", "data/1.dat"));
+        assert!(!match_attribute_pattern("/data/?.dat", "data/10.dat"));
+    }
+}
@@ -3,4 +3,6 @@ pub(crate) mod test;
 pub(crate) mod path;
 pub(crate) mod object_ext;
 pub(crate) mod path_ext;
-pub(crate) mod client_storage;
\ No newline at end of file
+pub(crate) mod client_storage;
+pub(crate) mod lfs;
+pub(crate) mod sparse;
\ No newline at end of file
@@ -1,12 +1,12 @@
 use std::path::{Path, PathBuf};
 
-use mercury::hash::SHA1;
+use mercury::hash::{HashKind, SHA1};
 use mercury::internal::object::blob::Blob;
 use mercury::internal::object::commit::Commit;
 use mercury::internal::object::ObjectTrait;
 use mercury::internal::object::tree::{Tree, TreeItemMode};
 
-use crate::utils::util;
+use crate::utils::{lfs, util};
 
 pub trait TreeExt {
     fn load(hash: &SHA1) -> Tree;
@@ -19,7 +19,8 @@ pub trait CommitExt {
 
 pub trait BlobExt {
     fn load(hash: &SHA1) -> Blob;
-    fn from_file(path: impl AsRef<Path>) -> Blob;
+    fn from_file(path: impl AsRef<Path>, kind: HashKind) -> Blob;
+    fn from_lfs_file(path: impl AsRef<Path>, kind: HashKind) -> Blob;
     fn save(&self) -> SHA1;
 }
 
@@ -70,9 +71,17 @@ impl BlobExt for Blob {
 
     /// Create a blob from a file
     /// - `path`: absolute  or relative path to current dir
-    fn from_file(path: impl AsRef<Path>) -> Blob {
+    /// - `kind`: the object format of the repository
+    fn from_file(path: impl AsRef<Path>, kind: HashKind) -> Blob {
         let file_content = std::fs::read_to_string(path).unwrap();
-        Blob::from_content(&file_content)
+        Blob::from_content_with_kind(file_content.into_bytes(), kind)
+    }
+
+    /// Create a pointer blob from a LFS file, and save the content to lfs objects
+    /// - `path`: absolute  or relative path to current dir
+    fn from_lfs_file(path: impl AsRef<Path>, kind: HashKind) -> Blob {
+        let file_content = std::fs::read(path).unwrap();
+        Blob::from_content_with_kind(lfs::clean(&file_content).unwrap(), kind)
     }
 
     fn save(&self) -> SHA1 {
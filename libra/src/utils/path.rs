@@ -7,7 +7,14 @@ pub fn index() -> PathBuf {
 pub fn objects() -> PathBuf {
     util::storage_path().join("objects")
 }
+pub fn lfs_objects() -> PathBuf {
+    util::storage_path().join("lfs").join("objects")
+}
 
 pub fn database() -> PathBuf {
     util::storage_path().join(util::DATABASE)
-}
\ No newline at end of file
+}
+/// the cone mode patterns of sparse checkout, enabled while the file exists
+pub fn sparse_checkout() -> PathBuf {
+    util::storage_path().join("info").join("sparse-checkout")
+}
@@ -0,0 +1,146 @@
+//! Sparse checkout in cone mode.
+//!
+//! The directories are kept in `.libra/info/sparse-checkout` as the patterns git writes in cone mode,
+//! and sparse checkout is enabled while the file exists. The files in the root directory are always
+//! checked out, with the files under the directories and the files directly in their parents.
+use std::collections::{BTreeSet, HashSet};
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use crate::utils::{path, util};
+
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct SparseCheckout {
+    /// the directories checked out with all their files, to workdir, separated by `/`
+    pub dirs: BTreeSet<String>,
+}
+
+impl SparseCheckout {
+    /// The sparse checkout of the repository, `None` if it is disabled
+    pub fn load() -> Option<SparseCheckout> {
+        let content = fs::read_to_string(path::sparse_checkout()).ok()?;
+        Some(Self::parse(&content))
+    }
+
+    pub fn save(&self) -> io::Result<()> {
+        util::write_file(self.to_patterns().as_bytes(), &path::sparse_checkout())
+    }
+
+    /// Disable sparse checkout, the files must be checked out before
+    pub fn remove() -> io::Result<()> {
+        fs::remove_file(path::sparse_checkout())
+    }
+
+    /// Read the directories of the cone mode patterns, the ones only listed as parents are left out
+    fn parse(content: &str) -> SparseCheckout {
+        let mut dirs = BTreeSet::new();
+        let mut parents = HashSet::new();
+        for line in content.lines().map(str::trim) {
+            if let Some(parent) = line.strip_prefix('!').and_then(|l| l.strip_suffix("/*/")) {
+                parents.insert(parent.trim_start_matches('/').to_string());
+            } else if line.len() > 1 && line.starts_with('/') && line.ends_with('/') {
+                dirs.insert(line.trim_matches('/').to_string());
+            }
+        }
+        dirs.retain(|dir| !parents.contains(dir));
+        SparseCheckout { dirs }
+    }
+
+    /// The patterns of git in cone mode, e.g. for `a/b`:
+    /// ```text
+    /// /*
+    /// !/*/
+    /// /a/
+    /// !/a/*/
+    /// /a/b/
+    /// ```
+    fn to_patterns(&self) -> String {
+        let mut all = self.dirs.clone();
+        for dir in &self.dirs {
+            all.extend(Path::new(dir).ancestors().skip(1).map(util::path_to_string));
+        }
+        all.remove("");
+
+        let mut patterns = String::from("/*\n!/*/\n");
+        for dir in all {
+            patterns.push_str(&format!("/{}/\n", dir));
+            if !self.dirs.contains(&dir) {
+                patterns.push_str(&format!("!/{}/*/\n", dir));
+            }
+        }
+        patterns
+    }
+
+    /// Add a directory (to workdir), the directories under it are merged into it
+    /// - return false if `dir` is not a directory of the workdir, like `..` or the root
+    pub fn add_dir(&mut self, dir: &str) -> bool {
+        let dir = dir.trim_start_matches("./").trim_matches('/');
+        if dir.is_empty()
+            || dir
+                .split('/')
+                .any(|c| c.is_empty() || c == "." || c == "..")
+        {
+            return false;
+        }
+        if self.dirs.iter().any(|d| is_under(dir, d)) {
+            return true;
+        }
+        self.dirs.retain(|d| !is_under(d, dir));
+        self.dirs.insert(dir.to_string());
+        true
+    }
+
+    /// Whether the file `path` (to workdir) is checked out
+    pub fn includes(&self, path: impl AsRef<Path>) -> bool {
+        let path = util::path_to_string(path.as_ref());
+        let parent = match path.rfind('/') {
+            Some(i) => &path[..i],
+            None => return true, // in the root directory
+        };
+        self.dirs
+            .iter()
+            .any(|dir| is_under(parent, dir) || is_under(dir, parent))
+    }
+}
+
+/// `path` is `dir` or under it
+fn is_under(path: &str, dir: &str) -> bool {
+    path.strip_prefix(dir)
+        .is_some_and(|rest| rest.is_empty() || rest.starts_with('/'))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_cone_patterns() {
+        let mut sparse = SparseCheckout::default();
+        assert!(sparse.add_dir("a/b/"));
+        assert!(sparse.add_dir("./c"));
+        assert!(sparse.add_dir("c/d")); // already under `c`
+        assert!(!sparse.add_dir("../e"));
+        assert!(!sparse.add_dir("/"));
+        let patterns = sparse.to_patterns();
+        assert_eq!(patterns, "/*\n!/*/\n/a/\n!/a/*/\n/a/b/\n/c/\n");
+        assert_eq!(SparseCheckout::parse(&patterns), sparse);
+
+        assert!(sparse.add_dir("a"));
+        assert_eq!(sparse.dirs.iter().collect::<Vec<_>>(), ["a", "c"]);
+    }
+
+    #[test]
+    fn test_includes() {
+        let mut sparse = SparseCheckout::default();
+        assert!(sparse.includes("README.md"));
+        assert!(!sparse.includes("a/x.txt"));
+
+        sparse.add_dir("a/b");
+        assert!(sparse.includes("a/x.txt")); // in a parent
+        assert!(sparse.includes("a/b/x.txt"));
+        assert!(sparse.includes("a/b/c/x.txt"));
+        assert!(!sparse.includes("a/c/x.txt"));
+        assert!(!sparse.includes("ab/x.txt"));
+    }
+}
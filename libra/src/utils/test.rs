@@ -92,7 +92,9 @@ pub fn setup_clean_testing_env() {
 /// switch to test dir and create a new .libra
 pub async fn setup_with_new_libra() {
     setup_clean_testing_env();
-    command::init::init().await.unwrap();
+    command::init::init(command::init::InitArgs::default())
+        .await
+        .unwrap();
 }
 
 pub fn init_debug_logger() {
@@ -4,10 +4,11 @@ use std::io::{BufReader, Read, Write};
 use std::path::{Path, PathBuf};
 use std::{env, fs, io};
 
-use mercury::hash::SHA1;
+use mercury::hash::{HashKind, SHA1};
 use mercury::internal::object::types::ObjectType;
 
 use crate::utils::client_storage::ClientStorage;
+use crate::utils::lfs::{self, LfsAttributes};
 use crate::utils::path;
 use crate::utils::path_ext::PathExt;
 
@@ -192,12 +193,22 @@ where
     workdir_to_relative(path, cur_dir())
 }
 
-pub fn calc_file_blob_hash(path: impl AsRef<Path>) -> io::Result<SHA1> {
-    let file = fs::File::open(path)?;
+/// The id of the blob of a file in a repository of the object format `kind`
+/// - `attributes`: the LFS rules of the repository, the blob of an LFS file is its pointer
+pub fn calc_file_blob_hash(
+    path: impl AsRef<Path>,
+    kind: HashKind,
+    attributes: &LfsAttributes,
+) -> io::Result<SHA1> {
+    let file = fs::File::open(path.as_ref())?;
     let mut reader = BufReader::new(file);
     let mut data = Vec::new();
     reader.read_to_end(&mut data)?;
-    Ok(SHA1::from_type_and_data(ObjectType::Blob, &data))
+    if attributes.is_tracked(path.as_ref()) {
+        // the blob of LFS file is the pointer
+        data = lfs::generate_pointer(&data).to_bytes();
+    }
+    Ok(SHA1::from_type_and_data_with_kind(kind, ObjectType::Blob, &data))
 }
 
 /// List all files in the given dir and its sub_dir, except `.libra`
@@ -223,12 +234,6 @@ pub fn list_files(path: &Path) -> io::Result<Vec<PathBuf>> {
     Ok(files)
 }
 
-/// list all files in the working dir(include sub_dir)
-/// - output: to workdir path
-pub fn list_workdir_files() -> io::Result<Vec<PathBuf>> {
-    list_files(&working_dir())
-}
-
 /// Integrate the input paths (relative, absolute, file, dir) to workdir paths
 /// - only include existing files
 pub fn integrate_pathspec(paths: &Vec<PathBuf>) -> HashSet<PathBuf> {
@@ -293,10 +298,11 @@ pub fn path_to_string(path: &Path) -> String {
 }
 
 /// extend hash, panic if not valid or ambiguous
-pub fn get_commit_base(commit_base: &str) -> Result<SHA1, String> {
+/// - `kind`: the object format of the repository
+pub fn get_commit_base(commit_base: &str, kind: HashKind) -> Result<SHA1, String> {
     let storage = objects_storage();
 
-    let commits = storage.search(commit_base);
+    let commits = storage.search(commit_base, kind);
     if commits.is_empty() {
         return Err(format!("fatal: invalid reference: {}", commit_base));
     } else if commits.len() > 1 {
@@ -0,0 +1,317 @@
+//! Delta generation with a rolling hash, in the way of git's `diff-delta.c`.
+//!
+//! The base is split into blocks of [`BLOCK_SIZE`] bytes, which are indexed by their hash.
+//! The target is scanned with a rolling hash of the same width, a hit is verified and
+//! extended in both directions, and emitted as a copy instruction. Bytes without a match
+//! are emitted as insert instructions.
+
+use std::collections::HashMap;
+
+/// Width of the indexed blocks and of the rolling hash window.
+pub const BLOCK_SIZE: usize = 16;
+
+/// Shortest common prefix which is worth a copy instruction.
+/// Small objects have no full block to match, so only a common prefix can be found for them.
+const MIN_PREFIX: usize = 4;
+
+/// Candidates kept for a block hash, repeated content (e.g. zeros) would make the scan quadratic.
+const MAX_BUCKET_LEN: usize = 64;
+
+/// Largest size of one copy instruction, git never emits larger ones.
+const MAX_COPY_SIZE: usize = 0x10000;
+
+/// Largest size of one insert instruction.
+const MAX_INSERT_SIZE: usize = 0x7f;
+
+const HASH_MULTIPLIER: u32 = 0x01000193;
+
+/// Index of the blocks of a base object.
+///
+/// The index doesn't keep the base, the same bytes must be passed to [`DeltaIndex::encode`].
+#[derive(Debug, Default)]
+pub struct DeltaIndex {
+    base_len: usize,
+    blocks: HashMap<u32, Vec<usize>>,
+    /// `HASH_MULTIPLIER ^ (BLOCK_SIZE - 1)`, to remove the leaving byte from the rolling hash.
+    leave_factor: u32,
+}
+
+fn block_hash(data: &[u8]) -> u32 {
+    data.iter().fold(0u32, |h, &c| {
+        h.wrapping_mul(HASH_MULTIPLIER).wrapping_add(c as u32 + 1)
+    })
+}
+
+impl DeltaIndex {
+    pub fn new(base: &[u8]) -> Self {
+        let mut blocks: HashMap<u32, Vec<usize>> = HashMap::new();
+        for pos in (0..base.len().saturating_sub(BLOCK_SIZE - 1)).step_by(BLOCK_SIZE) {
+            let bucket = blocks
+                .entry(block_hash(&base[pos..pos + BLOCK_SIZE]))
+                .or_default();
+            if bucket.len() < MAX_BUCKET_LEN {
+                bucket.push(pos);
+            }
+        }
+        let leave_factor = (1..BLOCK_SIZE).fold(1u32, |f, _| f.wrapping_mul(HASH_MULTIPLIER));
+        DeltaIndex {
+            base_len: base.len(),
+            blocks,
+            leave_factor,
+        }
+    }
+
+    /// Memory used by the index, roughly.
+    pub fn memory_used(&self) -> usize {
+        self.blocks.len() * (std::mem::size_of::<u32>() + std::mem::size_of::<Vec<usize>>())
+            + self
+                .blocks
+                .values()
+                .map(|b| b.capacity() * 8)
+                .sum::<usize>()
+    }
+
+    /// Encode `target` as a git delta against `base`, the object this index was built from.
+    ///
+    /// Returns `None` if the delta would be larger than `max_size`, or if less than half of the
+    /// target is copied from the base, as such a delta isn't worth the cost of resolving it.
+    pub fn encode(&self, base: &[u8], target: &[u8], max_size: usize) -> Option<Vec<u8>> {
+        assert_eq!(
+            base.len(),
+            self.base_len,
+            "delta index is built from another base"
+        );
+        let mut writer = DeltaWriter::new(base.len(), target.len(), max_size);
+
+        let mut pos = 0;
+        let prefix = base.iter().zip(target).take_while(|(a, b)| a == b).count();
+        if prefix >= MIN_PREFIX {
+            writer.copy(0, prefix)?;
+            pos = prefix;
+        }
+
+        let mut hash = None;
+        while pos < target.len() {
+            if pos + BLOCK_SIZE > target.len() {
+                writer.insert(&target[pos..])?;
+                break;
+            }
+            let h = match hash {
+                Some(h) => h,
+                None => block_hash(&target[pos..pos + BLOCK_SIZE]),
+            };
+            match self.find_match(base, target, pos, h, writer.pending_len()) {
+                Some((base_pos, back, len)) => {
+                    writer.unpend(back);
+                    writer.copy(base_pos - back, back + len)?;
+                    pos += len;
+                    hash = None;
+                }
+                None => {
+                    writer.pend(target[pos])?;
+                    if pos + BLOCK_SIZE < target.len() {
+                        let leave = (target[pos] as u32 + 1).wrapping_mul(self.leave_factor);
+                        hash = Some(
+                            h.wrapping_sub(leave)
+                                .wrapping_mul(HASH_MULTIPLIER)
+                                .wrapping_add(target[pos + BLOCK_SIZE] as u32 + 1),
+                        );
+                    }
+                    pos += 1;
+                }
+            }
+        }
+        writer.finish()
+    }
+
+    /// Find the longest match of the block at `pos` of the target.
+    /// Returns (base position, bytes matched backwards into the pending insert, length forwards).
+    fn find_match(
+        &self,
+        base: &[u8],
+        target: &[u8],
+        pos: usize,
+        hash: u32,
+        pending: usize,
+    ) -> Option<(usize, usize, usize)> {
+        let mut best: Option<(usize, usize, usize)> = None;
+        for &base_pos in self.blocks.get(&hash)? {
+            let len = base[base_pos..]
+                .iter()
+                .zip(&target[pos..])
+                .take_while(|(a, b)| a == b)
+                .count();
+            if len < BLOCK_SIZE {
+                continue; // hash collision
+            }
+            let back = (1..=pending.min(base_pos))
+                .take_while(|i| base[base_pos - i] == target[pos - i])
+                .count();
+            if best.is_none_or(|(_, b, l)| back + len > b + l) {
+                best = Some((base_pos, back, len));
+            }
+        }
+        best
+    }
+}
+
+/// Writes the delta instructions, and gives up once the delta exceeds the size limit.
+struct DeltaWriter {
+    out: Vec<u8>,
+    pending: Vec<u8>,
+    copied: usize,
+    target_len: usize,
+    max_size: usize,
+}
+
+impl DeltaWriter {
+    fn new(base_len: usize, target_len: usize, max_size: usize) -> Self {
+        let mut out = Vec::new();
+        write_size_encoding(&mut out, base_len);
+        write_size_encoding(&mut out, target_len);
+        DeltaWriter {
+            out,
+            pending: Vec::new(),
+            copied: 0,
+            target_len,
+            max_size,
+        }
+    }
+
+    fn pending_len(&self) -> usize {
+        self.pending.len()
+    }
+
+    fn check_size(&self) -> Option<()> {
+        // every pending byte costs at least itself
+        (self.out.len() + self.pending.len() <= self.max_size).then_some(())
+    }
+
+    fn pend(&mut self, byte: u8) -> Option<()> {
+        self.pending.push(byte);
+        self.check_size()
+    }
+
+    /// Take back the last `n` pending bytes, which are covered by a copy.
+    fn unpend(&mut self, n: usize) {
+        self.pending.truncate(self.pending.len() - n);
+    }
+
+    fn insert(&mut self, data: &[u8]) -> Option<()> {
+        self.pending.extend_from_slice(data);
+        self.check_size()
+    }
+
+    fn flush_pending(&mut self) {
+        for chunk in self.pending.chunks(MAX_INSERT_SIZE) {
+            self.out.push(chunk.len() as u8);
+            self.out.extend_from_slice(chunk);
+        }
+        self.pending.clear();
+    }
+
+    fn copy(&mut self, mut offset: usize, mut len: usize) -> Option<()> {
+        self.flush_pending();
+        self.copied += len;
+        while len > 0 {
+            let size = len.min(MAX_COPY_SIZE);
+            let mut instruction = 0x80u8;
+            let mut args = Vec::with_capacity(7);
+            for i in 0..4 {
+                let byte = ((offset >> (i * 8)) & 0xff) as u8;
+                if byte != 0 {
+                    instruction |= 1 << i;
+                    args.push(byte);
+                }
+            }
+            // a size of 0x10000 is encoded as zero, i.e. no size bytes at all
+            let encoded_size = if size == MAX_COPY_SIZE { 0 } else { size };
+            for i in 0..3 {
+                let byte = ((encoded_size >> (i * 8)) & 0xff) as u8;
+                if byte != 0 {
+                    instruction |= 1 << (4 + i);
+                    args.push(byte);
+                }
+            }
+            self.out.push(instruction);
+            self.out.extend_from_slice(&args);
+            offset += size;
+            len -= size;
+        }
+        self.check_size()
+    }
+
+    fn finish(mut self) -> Option<Vec<u8>> {
+        self.flush_pending();
+        if self.out.len() > self.max_size || self.copied * 2 <= self.target_len {
+            return None;
+        }
+        Some(self.out)
+    }
+}
+
+fn write_size_encoding(out: &mut Vec<u8>, mut number: usize) {
+    loop {
+        if number >> 7 > 0 {
+            out.push((number & 0x7f) as u8 | 0x80);
+        } else {
+            out.push(number as u8);
+            break;
+        }
+        number >>= 7;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use crate::decode::delta_decode;
+
+    use super::DeltaIndex;
+
+    fn check(base: &[u8], target: &[u8]) -> Vec<u8> {
+        let index = DeltaIndex::new(base);
+        let delta = index.encode(base, target, target.len()).expect("no delta");
+        let rebuilt = delta_decode(&mut Cursor::new(&delta), base).unwrap();
+        assert_eq!(rebuilt, target);
+        delta
+    }
+
+    #[test]
+    fn test_delta_index_roundtrip() {
+        let base: Vec<u8> = (0..20_000u32)
+            .flat_map(|i| (i % 251).to_le_bytes())
+            .collect();
+        // change some bytes in the middle and append some
+        let mut target = base.clone();
+        target[1000..1010].copy_from_slice(b"0123456789");
+        target.drain(30_000..30_100);
+        target.extend_from_slice(b"appended data at the end");
+        let delta = check(&base, &target);
+        assert!(delta.len() < 1000);
+    }
+
+    #[test]
+    fn test_delta_index_large_copy() {
+        // a single copy larger than one instruction can hold
+        let base: Vec<u8> = (0..200_000u32).map(|i| (i * 7 % 256) as u8).collect();
+        let mut target = base.clone();
+        target.push(1);
+        check(&base, &target);
+    }
+
+    #[test]
+    fn test_delta_index_prefix() {
+        check(b"hello, code,", b"hello, world.");
+    }
+
+    #[test]
+    fn test_delta_index_unrelated() {
+        let base = b"a completely different content, nothing in common";
+        let target = b"0123456789abcdefghijklmnopqrstuvwxyz";
+        assert!(DeltaIndex::new(base)
+            .encode(base, target, target.len())
+            .is_none());
+    }
+}
@@ -2,19 +2,85 @@
 //! Each Git object corresponds to a unique SHA-1 hash value, which is used to identify the object's
 //! location in the Git internal and mega database.
 //!
+//! Repositories may use SHA-256 instead, see [hash-function-transition](https://git-scm.com/docs/hash-function-transition).
+//! The algorithm of a repository is its object format, [`HashKind`], and an [`ObjectHash`] carries
+//! the algorithm it was computed with.
+//!
 
 use std::fmt::Display;
 
 use colored::Colorize;
 use serde::{Deserialize, Serialize};
-use sha1_smol::Digest;
+use sha1::Digest;
 
+use crate::errors::GitError;
 use crate::internal::object::types::ObjectType;
 
-/// The `SHA1` struct, encapsulating a `[u8; 20]` array, is specifically designed to represent Git hash IDs.
-/// In Git's context, these IDs are 40-character hexadecimal strings generated via the SHA-1 algorithm.
+/// The hash algorithm of object ids, named as the `object-format` in Git.
+#[derive(
+    Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, Default, Deserialize, Serialize,
+)]
+pub enum HashKind {
+    #[default]
+    Sha1,
+    Sha256,
+}
+
+impl HashKind {
+    /// Size of the binary hash in bytes.
+    pub const fn size(self) -> usize {
+        match self {
+            HashKind::Sha1 => 20,
+            HashKind::Sha256 => 32,
+        }
+    }
+
+    /// Length of the hexadecimal hash.
+    pub const fn hex_len(self) -> usize {
+        self.size() * 2
+    }
+
+    /// The name used by `object-format` and `extensions.objectformat`.
+    pub const fn as_str(self) -> &'static str {
+        match self {
+            HashKind::Sha1 => "sha1",
+            HashKind::Sha256 => "sha256",
+        }
+    }
+
+    /// The hash of a binary id of `size` bytes.
+    pub fn from_size(size: usize) -> Option<HashKind> {
+        match size {
+            20 => Some(HashKind::Sha1),
+            32 => Some(HashKind::Sha256),
+            _ => None,
+        }
+    }
+}
+
+impl Display for HashKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+impl std::str::FromStr for HashKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "sha1" => Ok(HashKind::Sha1),
+            "sha256" => Ok(HashKind::Sha256),
+            _ => Err(format!("unknown object format: {}", s)),
+        }
+    }
+}
+
+/// The `ObjectHash` enum, encapsulating a `[u8; 20]` or `[u8; 32]` array, is specifically designed to represent Git hash IDs.
+/// In Git's context, these IDs are 40-character hexadecimal strings generated via the SHA-1 algorithm,
+/// or 64-character ones generated via SHA-256 in repositories of the `sha256` object format.
 /// Each Git object receives a unique hash ID based on its content, serving as an identifier for its location
-/// within the Git internal database. Utilizing a dedicated struct for these hash IDs enhances code readability and
+/// within the Git internal database. Utilizing a dedicated type for these hash IDs enhances code readability and
 /// maintainability by providing a clear, structured format for their manipulation and storage.
 ///
 /// ### Change Log
@@ -26,14 +92,31 @@ use crate::internal::object::types::ObjectType;
 /// allows for easier adaptation to different hash algorithms while keeping the underlying implementation consistent and
 /// understandable. - Nov 26, 2023 (by @genedna)
 ///
-#[allow(unused)]
-#[derive(
-    Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, Default, Deserialize, Serialize,
-)]
-pub struct SHA1(pub [u8; 20]);
+/// The id now carries its algorithm, so ids of both formats share one type, and `SHA1` is kept as its
+/// alias for existing code.
+///
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, Deserialize, Serialize)]
+pub enum ObjectHash {
+    Sha1([u8; 20]),
+    Sha256([u8; 32]),
+}
+
+pub type SHA1 = ObjectHash;
+
+impl Default for ObjectHash {
+    fn default() -> Self {
+        ObjectHash::Sha1([0; 20])
+    }
+}
+
+impl AsRef<[u8]> for ObjectHash {
+    fn as_ref(&self) -> &[u8] {
+        self.as_bytes()
+    }
+}
 
 /// Display trait for SHA1, and colored output improve the readability in the terminal.
-impl Display for SHA1 {
+impl Display for ObjectHash {
     /// # Attention
     /// cause of the color chars for ,if you want to use the string without color ,
     /// please call the func:`to_plain_str()` rather than the func:`to_string()`
@@ -48,25 +131,47 @@ impl Display for SHA1 {
 /// Implementation of the `FromStr` trait for the `SHA1` type.
 ///
 /// To effectively use the `from_str` method for converting a string to a `SHA1` object, consider the following:
-///   1. The input string `s` should be a pre-calculated hexadecimal string, exactly 40 characters in length. This string
-///      represents a SHA1 hash and should conform to the standard SHA1 hash format.
+///   1. The input string `s` should be a pre-calculated hexadecimal string, exactly 40 characters in length, or
+///      64 characters for a SHA-256 id. The algorithm is known from the length.
 ///   2. It is necessary to explicitly import the `FromStr` trait to utilize the `from_str` method. Include the import
 ///      statement `use std::str::FromStr;` in your code before invoking the `from_str` function. This import ensures
 ///      that the `from_str` method is available for converting strings to `SHA1` objects.
-impl std::str::FromStr for SHA1 {
+impl std::str::FromStr for ObjectHash {
     type Err = String;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        let mut h = SHA1::default();
+        let bytes = hex::decode(s).map_err(|e| e.to_string())?;
+        ObjectHash::from_bytes(&bytes).map_err(|_| format!("invalid object id length: {}", s.len()))
+    }
+}
+
+/// Incremental hashing with the algorithm of a [`HashKind`], for pack and index checksums.
+#[derive(Clone)]
+pub enum ObjectHasher {
+    Sha1(sha1::Sha1),
+    Sha256(sha2::Sha256),
+}
 
-        let d = Digest::from_str(s);
+impl ObjectHasher {
+    pub fn new(kind: HashKind) -> Self {
+        match kind {
+            HashKind::Sha1 => ObjectHasher::Sha1(sha1::Sha1::new()),
+            HashKind::Sha256 => ObjectHasher::Sha256(sha2::Sha256::new()),
+        }
+    }
 
-        match d {
-            Ok(d) => h.0.copy_from_slice(d.bytes().as_slice()),
-            Err(e) => return Err(e.to_string()),
+    pub fn update(&mut self, data: impl AsRef<[u8]>) {
+        match self {
+            ObjectHasher::Sha1(hasher) => hasher.update(data),
+            ObjectHasher::Sha256(hasher) => hasher.update(data),
         }
+    }
 
-        Ok(h)
+    pub fn finalize(self) -> ObjectHash {
+        match self {
+            ObjectHasher::Sha1(hasher) => ObjectHash::Sha1(hasher.finalize().into()),
+            ObjectHasher::Sha256(hasher) => ObjectHash::Sha256(hasher.finalize().into()),
+        }
     }
 }
 
@@ -76,11 +181,11 @@ impl std::str::FromStr for SHA1 {
 ///
 /// 1. `new` Prefix:
 ///    Methods starting with `new` are used for computing an SHA-1 hash from given data, signifying the creation of
-///    a new `SHA1` instance. For example, `pub fn new(data: &Vec<u8>) -> SHA1` takes a byte vector and calculates its SHA-1 hash.
+///    a new `SHA1` instance. For example, `pub fn new(data: &[u8]) -> SHA1` takes a byte slice and calculates its SHA-1 hash.
 ///
 /// 2. `from` Prefix:
 ///    Methods beginning with `from` are intended for creating a `SHA1` instance from an existing, pre-calculated value.
-///    This implies direct derivation of the `SHA1` object from the provided input. For instance, `pub fn from_bytes(bytes: &[u8]) -> SHA1`
+///    This implies direct derivation of the `SHA1` object from the provided input. For instance, `pub fn from_bytes(bytes: &[u8]) -> Result<SHA1, GitError>`
 ///    constructs a `SHA1` from a 20-byte array representing an SHA-1 hash.
 ///
 /// 3. `to` Prefix:
@@ -91,45 +196,87 @@ impl std::str::FromStr for SHA1 {
 ///
 /// These method naming conventions (`new`, `from`, `to`) provide clarity and predictability in the API, making it easier for users
 /// to understand the intended use and functionality of each method within the `SHA1` struct.
-impl SHA1 {
-    /// Calculate the SHA-1 hash of `Vec<u8>` data, then create a Hash value
-    pub fn new(data: &Vec<u8>) -> SHA1 {
-        // Create a Sha1 object for calculating the SHA-1 hash
-        let s = sha1_smol::Sha1::from(data);
-        // Get the result of the hash
-        let sha1 = s.digest();
-        // Convert the result to a 20-byte array
-        let result = sha1.bytes();
+///
+/// Methods without a [`HashKind`] use SHA-1, the default object format. Code serving repositories
+/// of other formats passes the [`HashKind`] of the repository or pack, like `new_with_kind`.
+impl ObjectHash {
+    /// Calculate the SHA-1 hash of data, then create a Hash value
+    pub fn new(data: &[u8]) -> SHA1 {
+        Self::new_with_kind(HashKind::Sha1, data)
+    }
+
+    /// Calculate the hash of data with the algorithm of `kind`
+    pub fn new_with_kind(kind: HashKind, data: &[u8]) -> SHA1 {
+        let mut hasher = ObjectHasher::new(kind);
+        hasher.update(data);
+        hasher.finalize()
+    }
+
+    /// The SHA-1 id of an object, see [`ObjectHash::from_type_and_data_with_kind`]
+    pub fn from_type_and_data(object_type: ObjectType, data: &[u8]) -> SHA1 {
+        Self::from_type_and_data_with_kind(HashKind::Sha1, object_type, data)
+    }
+
+    /// The id of an object: the hash of "`<type> <size>\0<content>`"
+    pub fn from_type_and_data_with_kind(
+        kind: HashKind,
+        object_type: ObjectType,
+        data: &[u8],
+    ) -> SHA1 {
+        let mut hasher = ObjectHasher::new(kind);
+        hasher.update(object_type.to_bytes());
+        hasher.update(b" ");
+        hasher.update(data.len().to_string());
+        hasher.update(b"\0");
+        hasher.update(data);
+        hasher.finalize()
+    }
+
+    /// Create Hash from a byte array, which is a 20-byte (SHA-1) or 32-byte (SHA-256) array already calculated.
+    /// Fails for the other lengths.
+    pub fn from_bytes(bytes: &[u8]) -> Result<SHA1, GitError> {
+        match HashKind::from_size(bytes.len()) {
+            Some(HashKind::Sha1) => Ok(ObjectHash::Sha1(bytes.try_into().unwrap())),
+            Some(HashKind::Sha256) => Ok(ObjectHash::Sha256(bytes.try_into().unwrap())),
+            None => Err(GitError::InvalidHashValue(hex::encode(bytes))),
+        }
+    }
 
-        SHA1(result)
+    /// The zero id of `kind`, like the `0000000000000000000000000000000000000000` of a deleted ref
+    pub fn zero(kind: HashKind) -> SHA1 {
+        match kind {
+            HashKind::Sha1 => ObjectHash::Sha1([0; 20]),
+            HashKind::Sha256 => ObjectHash::Sha256([0; 32]),
+        }
     }
 
-    pub fn from_type_and_data(object_type: ObjectType, data: &Vec<u8>) -> SHA1 {
-        let mut d: Vec<u8> = Vec::new();
-        d.extend(object_type.to_data().unwrap());
-        d.push(b' ');
-        d.extend(data.len().to_string().as_bytes());
-        d.push(b'\x00');
-        d.extend(data);
-        SHA1::new(&d)
+    pub fn kind(&self) -> HashKind {
+        match self {
+            ObjectHash::Sha1(_) => HashKind::Sha1,
+            ObjectHash::Sha256(_) => HashKind::Sha256,
+        }
     }
 
-    /// Create Hash from a byte array, which is a 20-byte array already calculated
-    pub fn from_bytes(bytes: &[u8]) -> SHA1 {
-        let mut h = SHA1::default();
-        h.0.copy_from_slice(bytes);
+    /// Size of the binary id in bytes
+    pub fn size(&self) -> usize {
+        self.kind().size()
+    }
 
-        h
+    pub fn as_bytes(&self) -> &[u8] {
+        match self {
+            ObjectHash::Sha1(bytes) => bytes,
+            ObjectHash::Sha256(bytes) => bytes,
+        }
     }
 
     /// Export sha1 value to plain String without the color chars
     pub fn to_plain_str(self) -> String {
-        hex::encode(self.0)
+        hex::encode(self.as_bytes())
     }
 
     /// Export sha1 value to a byte array
     pub fn to_data(self) -> Vec<u8> {
-        self.0.to_vec()
+        self.as_bytes().to_vec()
     }
 }
 
@@ -142,7 +289,8 @@ mod tests {
     use std::str::FromStr;
     use std::{env, path::PathBuf};
 
-    use crate::hash::SHA1;
+    use crate::hash::{HashKind, ObjectHash, SHA1};
+    use crate::internal::object::types::ObjectType;
 
     #[test]
     fn test_sha1_new() {
@@ -150,7 +298,7 @@ mod tests {
         let data = "Hello, world!".as_bytes();
 
         // Generate SHA1 hash from the input data
-        let sha1 = SHA1::new(&data.to_vec());
+        let sha1 = SHA1::new(data);
 
         // Known SHA1 hash for "Hello, world!"
         let expected_sha1_hash = "943a702d06f34599aee1f8da8ef9f7296031d699";
@@ -169,7 +317,7 @@ mod tests {
         buffered.seek(SeekFrom::End(-20)).unwrap();
         let mut buffer = vec![0; 20];
         buffered.read_exact(&mut buffer).unwrap();
-        let signature = SHA1::from_bytes(buffer.as_ref());
+        let signature = SHA1::from_bytes(buffer.as_ref()).unwrap();
         assert_eq!(
             signature.to_plain_str(),
             "1d0e6c14760c956c173ede71cb28f33d921e232f"
@@ -181,7 +329,8 @@ mod tests {
         let sha1 = SHA1::from_bytes(&[
             0x8a, 0xb6, 0x86, 0xea, 0xfe, 0xb1, 0xf4, 0x47, 0x02, 0x73, 0x8c, 0x8b, 0x0f, 0x24,
             0xf2, 0x56, 0x7c, 0x36, 0xda, 0x6d,
-        ]);
+        ])
+        .unwrap();
 
         assert_eq!(
             sha1.to_plain_str(),
@@ -236,4 +385,41 @@ mod tests {
             Err(e) => println!("Error: {}", e),
         }
     }
+
+    #[test]
+    fn test_sha256() {
+        let hash = ObjectHash::new_with_kind(HashKind::Sha256, b"Hello, world!");
+        assert_eq!(hash.kind(), HashKind::Sha256);
+        assert_eq!(
+            hash.to_plain_str(),
+            "315f5bdb76d078c43b8ac0064e4a0164612b1fce77c869345bfc94c75894edd3"
+        );
+
+        let parsed = ObjectHash::from_str(&hash.to_plain_str()).unwrap();
+        assert_eq!(parsed, hash);
+        assert_eq!(ObjectHash::from_bytes(hash.as_bytes()).unwrap(), hash);
+        assert!(ObjectHash::from_bytes(&[0; 21]).is_err());
+        assert!(ObjectHash::from_str("8ab686eafeb1").is_err());
+    }
+
+    #[test]
+    fn test_object_id_of_kind() {
+        // `git hash-object` of an empty blob, in SHA-1 and SHA-256 repositories
+        let sha1 = ObjectHash::from_type_and_data_with_kind(HashKind::Sha1, ObjectType::Blob, &[]);
+        assert_eq!(
+            sha1.to_plain_str(),
+            "e69de29bb2d1d6434b8b29ae775ad8c2e48c5391"
+        );
+        let sha256 =
+            ObjectHash::from_type_and_data_with_kind(HashKind::Sha256, ObjectType::Blob, &[]);
+        assert_eq!(
+            sha256.to_plain_str(),
+            "473a0f4c3be8a93681a267e3b1e9a7dcda1185436fe141f7749120a303721813"
+        );
+        assert_eq!(HashKind::from_str("sha256").unwrap(), HashKind::Sha256);
+        assert_eq!(
+            ObjectHash::zero(HashKind::Sha256).to_plain_str(),
+            "0".repeat(64)
+        );
+    }
 }
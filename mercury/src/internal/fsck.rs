@@ -0,0 +1,877 @@
+//! Verify the integrity of a repository like `git fsck`.
+//!
+//! Every object is hashed again and its raw data validated: tree entries must have known modes,
+//! sane names and git's order, commits and tags must have their headers in order with valid
+//! identities. The objects they reference, and the objects of the refs, must exist with the
+//! expected type, and objects nothing references are reported as dangling.
+//!
+//! The checks don't use the parsers of [`crate::internal::object`], which expect well formed data.
+
+use std::cmp::Ordering;
+use std::collections::{HashMap, HashSet};
+use std::fmt::Display;
+use std::str::FromStr;
+
+use serde::{Deserialize, Serialize};
+
+use crate::hash::{HashKind, SHA1};
+use crate::internal::object::types::ObjectType;
+use crate::internal::pack::entry::Entry;
+use crate::internal::pack::utils::calculate_object_hash;
+
+/// A header of a commit or tag, as `(name, value)`.
+type Header<'a> = (&'a [u8], &'a [u8]);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum FsckSeverity {
+    /// the repository is corrupt
+    Error,
+    /// git would create or accept the object, but it is suspicious
+    Warning,
+    Info,
+}
+
+impl Display for FsckSeverity {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            FsckSeverity::Error => write!(f, "error"),
+            FsckSeverity::Warning => write!(f, "warning"),
+            FsckSeverity::Info => write!(f, "info"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum FsckIssueKind {
+    /// an object is stored but can't be read, like a loose object with a corrupt header
+    Unreadable,
+    /// the data doesn't hash to the id of the object
+    HashMismatch,
+    /// the headers of a commit are missing, malformed or out of order
+    BadCommit,
+    /// the headers of a tag are missing, malformed or out of order
+    BadTag,
+    /// the author, committer or tagger isn't `name <email> timestamp timezone`
+    BadIdent,
+    /// a tree entry can't be parsed, or its mode isn't a file, link, directory or submodule
+    BadTree,
+    /// a tree entry is a file with permissions other than 644 or 755
+    BadFilemode,
+    /// a tree entry mode has a leading zero
+    ZeroPaddedFilemode,
+    /// a tree entry name is empty, contains a slash, or is `.`, `..` or `.git`
+    BadTreeName,
+    /// a tree has two entries of the same name
+    DuplicateEntries,
+    /// the entries of a tree aren't in git's order
+    TreeNotSorted,
+    /// an object referenced by another object or a ref doesn't exist
+    Missing,
+    /// an object is referenced as another type, like the tree of a commit being a blob
+    WrongType,
+    /// an object isn't referenced by any other object or ref
+    Dangling,
+}
+
+impl FsckIssueKind {
+    pub fn severity(self) -> FsckSeverity {
+        match self {
+            FsckIssueKind::BadFilemode
+            | FsckIssueKind::ZeroPaddedFilemode
+            | FsckIssueKind::BadTreeName => FsckSeverity::Warning,
+            FsckIssueKind::Dangling => FsckSeverity::Info,
+            _ => FsckSeverity::Error,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FsckIssue {
+    pub kind: FsckIssueKind,
+    /// the type of the object with the issue, the expected one if it's missing,
+    /// `None` for a missing object pointed to by a ref or an unreadable object
+    pub object_type: Option<ObjectType>,
+    /// the id of the object with the issue
+    pub id: String,
+    pub message: String,
+}
+
+/// Formatted like the output of `git fsck`.
+impl Display for FsckIssue {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let object_type = self
+            .object_type
+            .map_or("object".to_string(), |t| t.to_string());
+        match self.kind {
+            FsckIssueKind::Missing => {
+                write!(f, "missing {} {} ({})", object_type, self.id, self.message)
+            }
+            FsckIssueKind::Dangling => write!(f, "dangling {} {}", object_type, self.id),
+            kind => write!(
+                f,
+                "{} in {} {}: {:?}: {}",
+                kind.severity(),
+                object_type,
+                self.id,
+                kind,
+                self.message
+            ),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FsckReport {
+    /// the number of objects checked
+    pub objects: usize,
+    pub issues: Vec<FsckIssue>,
+}
+
+impl FsckReport {
+    pub fn errors(&self) -> impl Iterator<Item = &FsckIssue> {
+        self.issues
+            .iter()
+            .filter(|issue| issue.kind.severity() == FsckSeverity::Error)
+    }
+
+    /// Whether no issue is an error, warnings and dangling objects are allowed.
+    pub fn is_ok(&self) -> bool {
+        self.errors().next().is_none()
+    }
+}
+
+/// Collects the objects of a repository and their issues, then checks the links between them.
+///
+/// Add every object with [`Fsck::check`] and the refs with [`Fsck::add_root`], in any order,
+/// then call [`Fsck::finish`].
+pub struct Fsck {
+    kind: HashKind,
+    /// the type of every object added
+    objects: HashMap<SHA1, ObjectType>,
+    /// the expected type of the referenced objects, and the first object referencing them
+    references: HashMap<SHA1, (ObjectType, SHA1)>,
+    /// the refs and the objects they point to
+    roots: Vec<(String, SHA1)>,
+    issues: Vec<FsckIssue>,
+}
+
+impl Fsck {
+    pub fn new(kind: HashKind) -> Self {
+        Fsck {
+            kind,
+            objects: HashMap::new(),
+            references: HashMap::new(),
+            roots: Vec::new(),
+            issues: Vec::new(),
+        }
+    }
+
+    /// Check an object given its id and raw data, without the `<type> <size>\0` header.
+    ///
+    /// Returns the objects it references and their expected types, for callers walking the
+    /// repository from its refs. Submodule commits aren't included.
+    pub fn check(
+        &mut self,
+        id: SHA1,
+        obj_type: ObjectType,
+        data: &[u8],
+    ) -> Vec<(SHA1, ObjectType)> {
+        self.objects.insert(id, obj_type);
+        let actual = calculate_object_hash(self.kind, obj_type, data);
+        if actual != id {
+            self.report(
+                FsckIssueKind::HashMismatch,
+                obj_type,
+                id,
+                format!("the {} hashes to {}", obj_type, actual),
+            );
+        }
+        let links = match obj_type {
+            ObjectType::Commit => self.check_commit(id, data),
+            ObjectType::Tree => self.check_tree(id, data),
+            ObjectType::Tag => self.check_tag(id, data),
+            _ => Vec::new(),
+        };
+        for &(link, link_type) in &links {
+            self.references.entry(link).or_insert((link_type, id));
+        }
+        links
+    }
+
+    /// Check an object of a pack, its deltas must have been resolved.
+    pub fn check_entry(&mut self, entry: &Entry) -> Vec<(SHA1, ObjectType)> {
+        self.check(entry.hash, entry.obj_type, &entry.data)
+    }
+
+    /// Add an object whose data isn't available, like a blob stored outside the database,
+    /// so it isn't reported missing.
+    pub fn found(&mut self, id: SHA1, obj_type: ObjectType) {
+        self.objects.insert(id, obj_type);
+    }
+
+    /// Report an object that is stored but can't be read.
+    pub fn unreadable(&mut self, id: SHA1, message: String) {
+        self.issues.push(FsckIssue {
+            kind: FsckIssueKind::Unreadable,
+            object_type: None,
+            id: id.to_string(),
+            message,
+        });
+    }
+
+    /// Add an object a ref points to, `name` is used to report it missing.
+    pub fn add_root(&mut self, name: &str, id: SHA1) {
+        self.roots.push((name.to_string(), id));
+    }
+
+    /// Report the missing, mistyped and dangling objects along with the issues of the objects.
+    pub fn finish(mut self) -> FsckReport {
+        let mut missing: Vec<_> = self
+            .references
+            .iter()
+            .filter_map(|(id, &(expected, referrer))| match self.objects.get(id) {
+                None => Some(FsckIssue {
+                    kind: FsckIssueKind::Missing,
+                    object_type: Some(expected),
+                    id: id.to_string(),
+                    message: format!("referenced by {}", referrer),
+                }),
+                Some(&actual) if actual != expected => Some(FsckIssue {
+                    kind: FsckIssueKind::WrongType,
+                    object_type: Some(actual),
+                    id: id.to_string(),
+                    message: format!("referenced as a {} by {}", expected, referrer),
+                }),
+                _ => None,
+            })
+            .collect();
+        for (name, id) in &self.roots {
+            if !self.objects.contains_key(id) {
+                missing.push(FsckIssue {
+                    kind: FsckIssueKind::Missing,
+                    object_type: None,
+                    id: id.to_string(),
+                    message: format!("pointed to by {}", name),
+                });
+            }
+        }
+        let roots: HashSet<_> = self.roots.iter().map(|(_, id)| *id).collect();
+        let mut dangling: Vec<_> = self
+            .objects
+            .iter()
+            .filter(|(id, _)| !self.references.contains_key(id) && !roots.contains(id))
+            .map(|(id, &obj_type)| FsckIssue {
+                kind: FsckIssueKind::Dangling,
+                object_type: Some(obj_type),
+                id: id.to_string(),
+                message: "not referenced by any object or ref".to_string(),
+            })
+            .collect();
+        missing.sort_by(|a, b| a.id.cmp(&b.id));
+        dangling.sort_by(|a, b| a.id.cmp(&b.id));
+        self.issues.extend(missing);
+        self.issues.extend(dangling);
+        FsckReport {
+            objects: self.objects.len(),
+            issues: self.issues,
+        }
+    }
+
+    fn report(&mut self, kind: FsckIssueKind, obj_type: ObjectType, id: SHA1, message: String) {
+        self.issues.push(FsckIssue {
+            kind,
+            object_type: Some(obj_type),
+            id: id.to_string(),
+            message,
+        });
+    }
+
+    /// Parse a hexadecimal id of this repository's format, git only writes lowercase ids.
+    fn parse_hex(&self, hex: &[u8]) -> Option<SHA1> {
+        let valid = hex.len() == self.kind.hex_len()
+            && hex.iter().all(|c| matches!(c, b'0'..=b'9' | b'a'..=b'f'));
+        valid
+            .then(|| SHA1::from_str(std::str::from_utf8(hex).ok()?).ok())
+            .flatten()
+    }
+
+    /// The headers of a commit or tag, split as `(name, value)`, the value of a header
+    /// continued on the following lines is the first line only.
+    /// Returns an error message if the headers don't end with an empty line or contain a NUL.
+    fn headers(data: &[u8]) -> Result<Vec<Header<'_>>, &'static str> {
+        let mut headers = Vec::new();
+        let mut rest = data;
+        loop {
+            let end = rest
+                .iter()
+                .position(|&c| c == b'\n')
+                .ok_or("the headers don't end with an empty line")?;
+            let line = &rest[..end];
+            rest = &rest[end + 1..];
+            if line.is_empty() {
+                break;
+            }
+            if line.contains(&0) {
+                return Err("a header contains a NUL byte");
+            }
+            if line[0] == b' ' {
+                // the continuation of a multi-line header, like `gpgsig`
+                if headers.is_empty() {
+                    return Err("the first header is a continuation line");
+                }
+                continue;
+            }
+            match line.iter().position(|&c| c == b' ') {
+                Some(space) => headers.push((&line[..space], &line[space + 1..])),
+                None => headers.push((line, &[][..])),
+            }
+        }
+        Ok(headers)
+    }
+
+    fn check_commit(&mut self, id: SHA1, data: &[u8]) -> Vec<(SHA1, ObjectType)> {
+        let headers = match Self::headers(data) {
+            Ok(headers) => headers,
+            Err(message) => {
+                self.report(
+                    FsckIssueKind::BadCommit,
+                    ObjectType::Commit,
+                    id,
+                    message.to_string(),
+                );
+                return Vec::new();
+            }
+        };
+        let mut links = Vec::new();
+        let mut headers = headers.into_iter().peekable();
+        match headers.next() {
+            Some((b"tree", value)) => match self.parse_hex(value) {
+                Some(tree) => links.push((tree, ObjectType::Tree)),
+                None => self.report(
+                    FsckIssueKind::BadCommit,
+                    ObjectType::Commit,
+                    id,
+                    "invalid tree id".to_string(),
+                ),
+            },
+            _ => {
+                self.report(
+                    FsckIssueKind::BadCommit,
+                    ObjectType::Commit,
+                    id,
+                    "the first header isn't the tree".to_string(),
+                );
+                return links;
+            }
+        }
+        while let Some((_, value)) = headers.next_if(|(name, _)| *name == b"parent") {
+            match self.parse_hex(value) {
+                Some(parent) => links.push((parent, ObjectType::Commit)),
+                None => self.report(
+                    FsckIssueKind::BadCommit,
+                    ObjectType::Commit,
+                    id,
+                    "invalid parent id".to_string(),
+                ),
+            }
+        }
+        for role in ["author", "committer"] {
+            match headers.next() {
+                Some((name, value)) if name == role.as_bytes() => {
+                    self.check_ident(ObjectType::Commit, id, role, value);
+                }
+                _ => {
+                    self.report(
+                        FsckIssueKind::BadCommit,
+                        ObjectType::Commit,
+                        id,
+                        format!("missing the {} header", role),
+                    );
+                    return links;
+                }
+            }
+        }
+        if headers.any(|(name, _)| name == b"author" || name == b"committer") {
+            self.report(
+                FsckIssueKind::BadCommit,
+                ObjectType::Commit,
+                id,
+                "more than one author or committer".to_string(),
+            );
+        }
+        links
+    }
+
+    fn check_tag(&mut self, id: SHA1, data: &[u8]) -> Vec<(SHA1, ObjectType)> {
+        let headers = match Self::headers(data) {
+            Ok(headers) => headers,
+            Err(message) => {
+                self.report(
+                    FsckIssueKind::BadTag,
+                    ObjectType::Tag,
+                    id,
+                    message.to_string(),
+                );
+                return Vec::new();
+            }
+        };
+        let mut headers = headers.into_iter();
+        let (Some((b"object", object)), Some((b"type", object_type)), Some((b"tag", name))) =
+            (headers.next(), headers.next(), headers.next())
+        else {
+            self.report(
+                FsckIssueKind::BadTag,
+                ObjectType::Tag,
+                id,
+                "the headers aren't object, type and tag".to_string(),
+            );
+            return Vec::new();
+        };
+        let Some(object) = self.parse_hex(object) else {
+            self.report(
+                FsckIssueKind::BadTag,
+                ObjectType::Tag,
+                id,
+                "invalid object id".to_string(),
+            );
+            return Vec::new();
+        };
+        let object_type = std::str::from_utf8(object_type)
+            .ok()
+            .and_then(|t| ObjectType::from_string(t).ok());
+        let Some(object_type) = object_type else {
+            self.report(
+                FsckIssueKind::BadTag,
+                ObjectType::Tag,
+                id,
+                "invalid object type".to_string(),
+            );
+            return Vec::new();
+        };
+        if name.is_empty() {
+            self.report(
+                FsckIssueKind::BadTag,
+                ObjectType::Tag,
+                id,
+                "empty tag name".to_string(),
+            );
+        }
+        match headers.next() {
+            Some((b"tagger", tagger)) => self.check_ident(ObjectType::Tag, id, "tagger", tagger),
+            // tags created by git before 0.99.1 have no tagger
+            _ => self.report(
+                FsckIssueKind::BadTag,
+                ObjectType::Tag,
+                id,
+                "missing the tagger".to_string(),
+            ),
+        }
+        vec![(object, object_type)]
+    }
+
+    /// Check `name <email> timestamp timezone`, like `fsck_ident` in git.
+    fn check_ident(&mut self, obj_type: ObjectType, id: SHA1, role: &str, ident: &[u8]) {
+        if let Err(message) = Self::parse_ident(ident) {
+            self.report(
+                FsckIssueKind::BadIdent,
+                obj_type,
+                id,
+                format!("{}: {}", role, message),
+            );
+        }
+    }
+
+    fn parse_ident(ident: &[u8]) -> Result<(), &'static str> {
+        let open = ident
+            .iter()
+            .position(|&c| c == b'<')
+            .ok_or("missing email")?;
+        if ident[..open].contains(&b'>') {
+            return Err("bad name");
+        }
+        if open > 0 && ident[open - 1] != b' ' {
+            return Err("missing space before email");
+        }
+        let close = open
+            + ident[open..]
+                .iter()
+                .position(|&c| c == b'>')
+                .ok_or("bad email")?;
+        if ident[open + 1..close].contains(&b'<') {
+            return Err("bad email");
+        }
+        let date = ident[close + 1..]
+            .strip_prefix(b" ")
+            .ok_or("missing space before date")?;
+        let (timestamp, timezone) = date
+            .iter()
+            .position(|&c| c == b' ')
+            .map(|space| (&date[..space], &date[space + 1..]))
+            .ok_or("missing space before timezone")?;
+        if timestamp.is_empty() || !timestamp.iter().all(u8::is_ascii_digit) {
+            return Err("bad date");
+        }
+        if timestamp[0] == b'0' && timestamp.len() > 1 {
+            return Err("zero-padded date");
+        }
+        if std::str::from_utf8(timestamp)
+            .unwrap()
+            .parse::<u64>()
+            .is_err()
+        {
+            return Err("date overflow");
+        }
+        let valid_timezone = timezone.len() == 5
+            && matches!(timezone[0], b'+' | b'-')
+            && timezone[1..].iter().all(u8::is_ascii_digit);
+        if !valid_timezone {
+            return Err("bad timezone");
+        }
+        Ok(())
+    }
+
+    fn check_tree(&mut self, id: SHA1, data: &[u8]) -> Vec<(SHA1, ObjectType)> {
+        let mut links = Vec::new();
+        let mut previous: Option<(&[u8], bool)> = None;
+        let mut rest = data;
+        while !rest.is_empty() {
+            let Some(space) = rest.iter().position(|&c| c == b' ') else {
+                self.report(
+                    FsckIssueKind::BadTree,
+                    ObjectType::Tree,
+                    id,
+                    "truncated entry".to_string(),
+                );
+                break;
+            };
+            let mode = &rest[..space];
+            let Some(nul) = rest[space..]
+                .iter()
+                .position(|&c| c == 0)
+                .map(|p| space + p)
+            else {
+                self.report(
+                    FsckIssueKind::BadTree,
+                    ObjectType::Tree,
+                    id,
+                    "truncated entry".to_string(),
+                );
+                break;
+            };
+            let name = &rest[space + 1..nul];
+            let Some(hash) = rest.get(nul + 1..nul + 1 + self.kind.size()) else {
+                self.report(
+                    FsckIssueKind::BadTree,
+                    ObjectType::Tree,
+                    id,
+                    "truncated entry".to_string(),
+                );
+                break;
+            };
+            // the slice has the size of the object format
+            let hash = SHA1::from_bytes(hash).unwrap();
+            rest = &rest[nul + 1 + self.kind.size()..];
+
+            let display_name = String::from_utf8_lossy(name).to_string();
+            let parsed_mode = std::str::from_utf8(mode)
+                .ok()
+                .filter(|m| !m.is_empty() && m.bytes().all(|c| matches!(c, b'0'..=b'7')))
+                .and_then(|m| u32::from_str_radix(m, 8).ok());
+            let Some(parsed_mode) = parsed_mode else {
+                self.report(
+                    FsckIssueKind::BadTree,
+                    ObjectType::Tree,
+                    id,
+                    format!("invalid mode of {}", display_name),
+                );
+                continue;
+            };
+            if mode[0] == b'0' {
+                self.report(
+                    FsckIssueKind::ZeroPaddedFilemode,
+                    ObjectType::Tree,
+                    id,
+                    format!("zero-padded mode of {}", display_name),
+                );
+            }
+            let is_dir = match parsed_mode {
+                0o100644 | 0o100755 => {
+                    links.push((hash, ObjectType::Blob));
+                    false
+                }
+                0o120000 => {
+                    links.push((hash, ObjectType::Blob));
+                    false
+                }
+                0o040000 => {
+                    links.push((hash, ObjectType::Tree));
+                    true
+                }
+                // a submodule commit lives in another repository
+                0o160000 => false,
+                mode if mode & 0o170000 == 0o100000 => {
+                    self.report(
+                        FsckIssueKind::BadFilemode,
+                        ObjectType::Tree,
+                        id,
+                        format!("{} has mode {:o}", display_name, mode),
+                    );
+                    links.push((hash, ObjectType::Blob));
+                    false
+                }
+                mode => {
+                    self.report(
+                        FsckIssueKind::BadTree,
+                        ObjectType::Tree,
+                        id,
+                        format!("{} has unknown mode {:o}", display_name, mode),
+                    );
+                    false
+                }
+            };
+
+            if name.is_empty() {
+                self.report(
+                    FsckIssueKind::BadTreeName,
+                    ObjectType::Tree,
+                    id,
+                    "empty entry name".to_string(),
+                );
+            } else if name.contains(&b'/') {
+                self.report(
+                    FsckIssueKind::BadTreeName,
+                    ObjectType::Tree,
+                    id,
+                    format!("{} contains a slash", display_name),
+                );
+            } else if name == b"." || name == b".." || name.eq_ignore_ascii_case(b".git") {
+                self.report(
+                    FsckIssueKind::BadTreeName,
+                    ObjectType::Tree,
+                    id,
+                    format!("entry named {}", display_name),
+                );
+            }
+
+            if let Some((previous_name, previous_is_dir)) = previous {
+                if previous_name == name {
+                    self.report(
+                        FsckIssueKind::DuplicateEntries,
+                        ObjectType::Tree,
+                        id,
+                        format!("{} appears twice", display_name),
+                    );
+                } else if compare_entries(previous_name, previous_is_dir, name, is_dir)
+                    == Ordering::Greater
+                {
+                    self.report(
+                        FsckIssueKind::TreeNotSorted,
+                        ObjectType::Tree,
+                        id,
+                        format!("{} isn't in order", display_name),
+                    );
+                }
+            }
+            previous = Some((name, is_dir));
+        }
+        links
+    }
+}
+
+/// Git orders tree entries by name, comparing directories as if their names ended with a slash.
+fn compare_entries(a: &[u8], a_is_dir: bool, b: &[u8], b_is_dir: bool) -> Ordering {
+    let suffix = |is_dir: bool| if is_dir { &b"/"[..] } else { &b""[..] };
+    a.iter()
+        .chain(suffix(a_is_dir))
+        .cmp(b.iter().chain(suffix(b_is_dir)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hash(obj_type: ObjectType, data: &[u8]) -> SHA1 {
+        calculate_object_hash(HashKind::Sha1, obj_type, data)
+    }
+
+    fn tree_entry(mode: &str, name: &str, id: SHA1) -> Vec<u8> {
+        let mut entry = format!("{} {}\0", mode, name).into_bytes();
+        entry.extend(id.as_bytes());
+        entry
+    }
+
+    fn kinds(report: &FsckReport) -> Vec<FsckIssueKind> {
+        report.issues.iter().map(|issue| issue.kind).collect()
+    }
+
+    #[test]
+    fn test_fsck_connected_repository() {
+        let mut fsck = Fsck::new(HashKind::Sha1);
+        let blob = b"hello\n";
+        let blob_id = hash(ObjectType::Blob, blob);
+        let tree = tree_entry("100644", "hello.txt", blob_id);
+        let tree_id = hash(ObjectType::Tree, &tree);
+        let commit = format!(
+            "tree {}\nauthor A U Thor <author@example.com> 1700000000 +0800\n\
+             committer A U Thor <author@example.com> 1700000000 +0800\n\nmessage\n",
+            tree_id
+        );
+        let commit_id = hash(ObjectType::Commit, commit.as_bytes());
+
+        assert_eq!(
+            fsck.check(commit_id, ObjectType::Commit, commit.as_bytes()),
+            vec![(tree_id, ObjectType::Tree)]
+        );
+        assert_eq!(
+            fsck.check(tree_id, ObjectType::Tree, &tree),
+            vec![(blob_id, ObjectType::Blob)]
+        );
+        fsck.check(blob_id, ObjectType::Blob, blob);
+        fsck.add_root("refs/heads/main", commit_id);
+
+        let report = fsck.finish();
+        assert_eq!(report.objects, 3);
+        assert!(report.issues.is_empty(), "{:?}", report.issues);
+    }
+
+    #[test]
+    fn test_fsck_missing_and_dangling() {
+        let mut fsck = Fsck::new(HashKind::Sha1);
+        let missing_blob = hash(ObjectType::Blob, b"missing");
+        let tree = tree_entry("100644", "a", missing_blob);
+        let tree_id = hash(ObjectType::Tree, &tree);
+        let dangling = hash(ObjectType::Blob, b"dangling");
+        fsck.check(tree_id, ObjectType::Tree, &tree);
+        fsck.check(dangling, ObjectType::Blob, b"dangling");
+        fsck.add_root("refs/heads/main", tree_id);
+        fsck.add_root("refs/heads/gone", hash(ObjectType::Commit, b"gone"));
+
+        let report = fsck.finish();
+        let mut kinds = kinds(&report);
+        kinds.sort_by_key(|kind| format!("{:?}", kind));
+        assert_eq!(
+            kinds,
+            vec![
+                FsckIssueKind::Dangling,
+                FsckIssueKind::Missing,
+                FsckIssueKind::Missing
+            ]
+        );
+        assert!(!report.is_ok());
+    }
+
+    #[test]
+    fn test_fsck_hash_mismatch_and_wrong_type() {
+        let mut fsck = Fsck::new(HashKind::Sha1);
+        let blob_id = hash(ObjectType::Blob, b"blob");
+        // a commit whose tree is a blob
+        let commit = format!(
+            "tree {}\nauthor A <a@b> 0 +0000\ncommitter A <a@b> 0 +0000\n\n",
+            blob_id
+        );
+        let commit_id = hash(ObjectType::Commit, commit.as_bytes());
+        fsck.check(commit_id, ObjectType::Commit, commit.as_bytes());
+        fsck.check(blob_id, ObjectType::Blob, b"corrupted");
+        fsck.add_root("HEAD", commit_id);
+
+        let report = fsck.finish();
+        assert_eq!(
+            kinds(&report),
+            vec![FsckIssueKind::HashMismatch, FsckIssueKind::WrongType]
+        );
+        assert_eq!(
+            report.issues[1].to_string(),
+            format!(
+                "error in blob {}: WrongType: referenced as a tree by {}",
+                blob_id, commit_id
+            )
+        );
+    }
+
+    #[test]
+    fn test_fsck_bad_tree() {
+        let blob_id = hash(ObjectType::Blob, b"blob");
+        let check = |entries: &[(&str, &str)]| {
+            let mut fsck = Fsck::new(HashKind::Sha1);
+            let tree: Vec<u8> = entries
+                .iter()
+                .flat_map(|(mode, name)| tree_entry(mode, name, blob_id))
+                .collect();
+            fsck.check(hash(ObjectType::Tree, &tree), ObjectType::Tree, &tree);
+            fsck.issues.iter().map(|i| i.kind).collect::<Vec<_>>()
+        };
+
+        assert_eq!(check(&[("100644", "a"), ("100755", "b")]), vec![]);
+        // `a.c` sorts before the directory `a`, as if it was named `a/`
+        assert_eq!(check(&[("100644", "a.c"), ("40000", "a")]), vec![]);
+        assert_eq!(
+            check(&[("40000", "a"), ("100644", "a.c")]),
+            vec![FsckIssueKind::TreeNotSorted]
+        );
+        assert_eq!(
+            check(&[("100644", "a"), ("100644", "a")]),
+            vec![FsckIssueKind::DuplicateEntries]
+        );
+        assert_eq!(check(&[("100664", "a")]), vec![FsckIssueKind::BadFilemode]);
+        assert_eq!(
+            check(&[("040000", "a")]),
+            vec![FsckIssueKind::ZeroPaddedFilemode]
+        );
+        assert_eq!(check(&[("170000", "a")]), vec![FsckIssueKind::BadTree]);
+        assert_eq!(
+            check(&[("100644", ".git")]),
+            vec![FsckIssueKind::BadTreeName]
+        );
+
+        let mut fsck = Fsck::new(HashKind::Sha1);
+        let truncated = &tree_entry("100644", "a", blob_id)[..10];
+        fsck.check(blob_id, ObjectType::Tree, truncated);
+        assert!(fsck.issues.iter().any(|i| i.kind == FsckIssueKind::BadTree));
+    }
+
+    #[test]
+    fn test_fsck_bad_commit_and_tag() {
+        let tree_id = hash(ObjectType::Tree, b"");
+        let check = |obj_type: ObjectType, data: String| {
+            let mut fsck = Fsck::new(HashKind::Sha1);
+            fsck.check(hash(obj_type, data.as_bytes()), obj_type, data.as_bytes());
+            fsck.issues.iter().map(|i| i.kind).collect::<Vec<_>>()
+        };
+
+        let signed = format!(
+            "tree {}\nauthor A <a@b> 1 +0000\ncommitter A <a@b> 1 +0000\n\
+             gpgsig -----BEGIN PGP SIGNATURE-----\n \n -----END PGP SIGNATURE-----\n\nmessage",
+            tree_id
+        );
+        assert_eq!(check(ObjectType::Commit, signed), vec![]);
+        let no_author = format!("tree {}\ncommitter A <a@b> 1 +0000\n\n", tree_id);
+        assert_eq!(
+            check(ObjectType::Commit, no_author),
+            vec![FsckIssueKind::BadCommit]
+        );
+        let bad_ident = format!(
+            "tree {}\nauthor A a@b 1 +0000\ncommitter A <a@b> 01 +0000\n\n",
+            tree_id
+        );
+        assert_eq!(
+            check(ObjectType::Commit, bad_ident),
+            vec![FsckIssueKind::BadIdent, FsckIssueKind::BadIdent]
+        );
+        let unterminated = format!("tree {}\nauthor A <a@b> 1 +0000", tree_id);
+        assert_eq!(
+            check(ObjectType::Commit, unterminated),
+            vec![FsckIssueKind::BadCommit]
+        );
+
+        let tag = format!(
+            "object {}\ntype tree\ntag v1\ntagger A <a@b> 1 -0700\n\nrelease\n",
+            tree_id
+        );
+        assert_eq!(check(ObjectType::Tag, tag), vec![]);
+        let bad_type = format!("object {}\ntype file\ntag v1\n\n", tree_id);
+        assert_eq!(
+            check(ObjectType::Tag, bad_type),
+            vec![FsckIssueKind::BadTag]
+        );
+    }
+}
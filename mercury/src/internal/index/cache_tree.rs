@@ -0,0 +1,174 @@
+//! The cached tree extension (`TREE`) of the index: the tree ids of the directories whose
+//! entries haven't changed since their trees were written.
+
+use std::io::BufRead;
+
+use crate::errors::GitError;
+use crate::hash::{HashKind, SHA1};
+use crate::utils;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CacheTree {
+    /// the name of the directory in its parent, empty for the root
+    pub name: String,
+    /// the number of index entries under the directory and the id of its tree,
+    /// `None` once an entry under it changes
+    pub valid: Option<(usize, SHA1)>,
+    pub subtrees: Vec<CacheTree>,
+}
+
+impl CacheTree {
+    pub fn new(name: &str) -> Self {
+        CacheTree {
+            name: name.to_string(),
+            valid: None,
+            subtrees: Vec::new(),
+        }
+    }
+
+    /// Parse the data of a `TREE` extension.
+    pub fn from_bytes(data: &[u8], kind: HashKind) -> Result<Self, GitError> {
+        let mut reader = data;
+        let tree = Self::read(&mut reader, kind)?;
+        if !reader.is_empty() {
+            return Err(GitError::InvalidIndexFile(
+                "unexpected data after the cached tree".to_string(),
+            ));
+        }
+        Ok(tree)
+    }
+
+    fn read(reader: &mut &[u8], kind: HashKind) -> Result<Self, GitError> {
+        let invalid = || GitError::InvalidIndexFile("invalid cached tree".to_string());
+        let mut name = Vec::new();
+        reader.read_until(0, &mut name)?;
+        if name.pop() != Some(0) {
+            return Err(invalid());
+        }
+        let mut counts = Vec::new();
+        reader.read_until(b'\n', &mut counts)?;
+        if counts.pop() != Some(b'\n') {
+            return Err(invalid());
+        }
+        let counts = String::from_utf8(counts)?;
+        let (entry_count, subtree_count) = counts.split_once(' ').ok_or_else(invalid)?;
+        let entry_count: i64 = entry_count.parse().map_err(|_| invalid())?;
+        let subtree_count: usize = subtree_count.parse().map_err(|_| invalid())?;
+        // an invalidated tree has -1 entries and no id
+        let valid = if entry_count >= 0 {
+            Some((entry_count as usize, utils::read_hash(reader, kind)?))
+        } else {
+            None
+        };
+        // each subtree takes some bytes, so a count past the data is invalid and not reserved
+        let mut subtrees = Vec::with_capacity(subtree_count.min(reader.len()));
+        for _ in 0..subtree_count {
+            subtrees.push(Self::read(reader, kind)?);
+        }
+        Ok(CacheTree {
+            name: String::from_utf8(name)?,
+            valid,
+            subtrees,
+        })
+    }
+
+    /// Serialize as the data of a `TREE` extension.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut data = Vec::new();
+        self.write(&mut data);
+        data
+    }
+
+    fn write(&self, data: &mut Vec<u8>) {
+        data.extend(self.name.as_bytes());
+        data.push(0);
+        let entry_count = self.valid.map_or(-1, |(count, _)| count as i64);
+        data.extend(format!("{} {}\n", entry_count, self.subtrees.len()).as_bytes());
+        if let Some((_, hash)) = self.valid {
+            data.extend(hash.as_bytes());
+        }
+        for subtree in &self.subtrees {
+            subtree.write(data);
+        }
+    }
+
+    /// Invalidate the trees of the directories containing `path`, after its entry changed.
+    pub fn invalidate(&mut self, path: &str) {
+        self.valid = None;
+        if let Some((dir, rest)) = path.split_once('/') {
+            if let Some(subtree) = self.subtrees.iter_mut().find(|t| t.name == dir) {
+                subtree.invalidate(rest);
+            }
+        }
+    }
+
+    /// Find the cached tree of `dir`, a path relative to this tree, empty for itself.
+    pub fn get(&self, dir: &str) -> Option<&CacheTree> {
+        if dir.is_empty() {
+            return Some(self);
+        }
+        let (name, rest) = dir.split_once('/').unwrap_or((dir, ""));
+        self.subtrees
+            .iter()
+            .find(|t| t.name == name)
+            .and_then(|t| t.get(rest))
+    }
+
+    /// The tree id of `dir` if it is still valid.
+    pub fn tree_id(&self, dir: &str) -> Option<SHA1> {
+        self.get(dir)?.valid.map(|(_, hash)| hash)
+    }
+
+    /// Record the tree of `dir` with `entry_count` index entries under it.
+    pub fn insert(&mut self, dir: &str, entry_count: usize, hash: SHA1) {
+        if dir.is_empty() {
+            self.valid = Some((entry_count, hash));
+            return;
+        }
+        let (name, rest) = dir.split_once('/').unwrap_or((dir, ""));
+        // git keeps the subtrees ordered by the length of their names first
+        let key = |t: &CacheTree| (t.name.len(), t.name.clone());
+        let position = self
+            .subtrees
+            .binary_search_by_key(&(name.len(), name.to_string()), key);
+        let position = match position {
+            Ok(position) => position,
+            Err(position) => {
+                self.subtrees.insert(position, CacheTree::new(name));
+                position
+            }
+        };
+        self.subtrees[position].insert(rest, entry_count, hash);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cache_tree() {
+        let hash = |s: &str| SHA1::new(s.as_bytes());
+        let mut tree = CacheTree::new("");
+        tree.insert("src/index", 2, hash("index"));
+        tree.insert("src", 3, hash("src"));
+        tree.insert("", 4, hash("root"));
+        tree.insert("doc", 1, hash("doc"));
+        assert_eq!(tree.subtrees[0].name, "doc");
+        assert_eq!(tree.tree_id("src/index"), Some(hash("index")));
+
+        let data = tree.to_bytes();
+        assert_eq!(CacheTree::from_bytes(&data, HashKind::Sha1).unwrap(), tree);
+
+        tree.invalidate("src/index/mod.rs");
+        assert_eq!(tree.tree_id(""), None);
+        assert_eq!(tree.tree_id("src"), None);
+        assert_eq!(tree.tree_id("src/index"), None);
+        assert_eq!(tree.tree_id("doc"), Some(hash("doc")));
+        let data = tree.to_bytes();
+        assert_eq!(CacheTree::from_bytes(&data, HashKind::Sha1).unwrap(), tree);
+
+        let data = format!("\0-1 {}\n", usize::MAX);
+        assert!(CacheTree::from_bytes(data.as_bytes(), HashKind::Sha1).is_err());
+    }
+}
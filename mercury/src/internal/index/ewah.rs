@@ -0,0 +1,92 @@
+//! The EWAH compressed bitmaps of the untracked cache extension,
+//! see [ewah](https://github.com/git/git/blob/master/Documentation/technical/bitmap-format.txt).
+
+use std::io::Write;
+
+use byteorder::{BigEndian, ReadBytesExt, WriteBytesExt};
+
+use crate::errors::GitError;
+
+/// Read a serialized EWAH bitmap, returns the indexes of the set bits in ascending order.
+pub(crate) fn read_ewah(data: &mut &[u8]) -> Result<Vec<usize>, GitError> {
+    let bit_size = data.read_u32::<BigEndian>()? as usize;
+    let word_count = data.read_u32::<BigEndian>()? as usize;
+    // a count past the data fails on reading, don't reserve for it
+    let mut words = Vec::with_capacity(word_count.min(data.len() / 8));
+    for _ in 0..word_count {
+        words.push(data.read_u64::<BigEndian>()?);
+    }
+    let _rlw_position = data.read_u32::<BigEndian>()?;
+
+    let mut bits = Vec::new();
+    let mut position = 0; // index of the current uncompressed word
+    let mut i = 0;
+    while i < words.len() {
+        // a run length word: the running bit, the number of words of it, then literal words
+        let rlw = words[i];
+        let running_bit = rlw & 1 == 1;
+        let running_len = ((rlw >> 1) & 0xFFFF_FFFF) as usize;
+        let literal_count = (rlw >> 33) as usize;
+        if running_bit {
+            bits.extend(position * 64..(position + running_len) * 64);
+        }
+        position += running_len;
+        let literals = words
+            .get(i + 1..i + 1 + literal_count)
+            .ok_or_else(|| GitError::InvalidIndexFile("EWAH bitmap is truncated".to_string()))?;
+        for word in literals {
+            bits.extend(
+                (0..64)
+                    .filter(|b| word >> b & 1 == 1)
+                    .map(|b| position * 64 + b),
+            );
+            position += 1;
+        }
+        i += 1 + literal_count;
+    }
+    bits.retain(|&bit| bit < bit_size);
+    Ok(bits)
+}
+
+/// Write a bitmap of `bit_size` bits with `bits` set as an EWAH bitmap of literal words.
+pub(crate) fn write_ewah(
+    out: &mut impl Write,
+    bit_size: usize,
+    bits: impl IntoIterator<Item = usize>,
+) -> Result<(), GitError> {
+    let mut literals = vec![0u64; bit_size.div_ceil(64)];
+    for bit in bits {
+        literals[bit / 64] |= 1 << (bit % 64);
+    }
+    out.write_u32::<BigEndian>(bit_size as u32)?;
+    out.write_u32::<BigEndian>(literals.len() as u32 + 1)?;
+    out.write_u64::<BigEndian>((literals.len() as u64) << 33)?;
+    for word in literals {
+        out.write_u64::<BigEndian>(word)?;
+    }
+    out.write_u32::<BigEndian>(0)?; // the only run length word is the first one
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ewah() {
+        let bits = vec![0, 3, 63, 64, 130];
+        let mut data = Vec::new();
+        write_ewah(&mut data, 131, bits.clone()).unwrap();
+        assert_eq!(read_ewah(&mut data.as_slice()).unwrap(), bits);
+
+        // a run of 2 words of ones, then a literal word
+        let mut data = Vec::new();
+        data.write_u32::<BigEndian>(130).unwrap();
+        data.write_u32::<BigEndian>(2).unwrap();
+        data.write_u64::<BigEndian>(1 | 2 << 1 | 1 << 33).unwrap();
+        data.write_u64::<BigEndian>(0b11).unwrap();
+        data.write_u32::<BigEndian>(0).unwrap();
+        let read = read_ewah(&mut data.as_slice()).unwrap();
+        assert_eq!(read, (0..130).collect::<Vec<_>>());
+    }
+}
@@ -0,0 +1,831 @@
+use byteorder::{BigEndian, ReadBytesExt, WriteBytesExt};
+use std::collections::BTreeMap;
+use std::fmt::{Display, Formatter};
+use std::fs::{self, File};
+use std::io;
+use std::io::{BufRead, BufReader, Read, Write};
+#[cfg(unix)]
+use std::os::unix::fs::MetadataExt;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::utils;
+use crate::errors::GitError;
+use crate::hash::{HashKind, ObjectHasher, SHA1};
+use crate::internal::pack::utils::read_offset_encoding;
+use crate::internal::pack::wrapper::Wrapper;
+
+pub mod cache_tree;
+mod ewah;
+pub mod untracked_cache;
+
+use cache_tree::CacheTree;
+use untracked_cache::UntrackedCache;
+
+/// the extended flags of v3, in the 16 bits after the flags
+const SKIP_WORKTREE: u16 = 0x4000;
+const INTENT_TO_ADD: u16 = 0x2000;
+
+#[derive(PartialEq, Eq, Debug, Clone)]
+pub struct Time {
+    seconds: u32,
+    nanos: u32,
+}
+impl Time {
+    pub fn from_stream(stream: &mut impl Read) -> Result<Self, GitError> {
+        let seconds = stream.read_u32::<BigEndian>()?;
+        let nanos = stream.read_u32::<BigEndian>()?;
+        Ok(Time { seconds, nanos })
+    }
+
+    #[allow(dead_code)]
+    fn to_system_time(&self) -> SystemTime {
+        UNIX_EPOCH + std::time::Duration::new(self.seconds.into(), self.nanos)
+    }
+
+    fn from_system_time(system_time: SystemTime) -> Self {
+        match system_time.duration_since(UNIX_EPOCH) {
+            Ok(duration) => {
+                let seconds = duration
+                    .as_secs()
+                    .try_into()
+                    .expect("Time is too far in the future");
+                let nanos = duration.subsec_nanos();
+                Time { seconds, nanos }
+            }
+            Err(_) => panic!("Time is before the UNIX epoch"),
+        }
+    }
+}
+impl Display for Time {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}:{}", self.seconds, self.nanos)
+    }
+}
+
+/// 16 bits, and 16 bits of extended flags since v3
+#[derive(Debug)]
+pub struct Flags {
+    pub assume_valid: bool,
+    pub extended: bool,      // must be 0 in v2
+    pub stage: u8,           // 2-bit during merge
+    pub name_length: u16,    // 12-bit
+    pub skip_worktree: bool, // extended, used by sparse checkout
+    pub intent_to_add: bool, // extended, `add -N`
+}
+// TODO From Trait
+impl Flags {
+    pub fn new(name_len: u16) -> Self {
+        Flags {
+            assume_valid: true,
+            extended: false,
+            stage: 0,
+            name_length: name_len,
+            skip_worktree: false,
+            intent_to_add: false,
+        }
+    }
+
+    pub fn from_u16(flags: u16) -> Self {
+        Flags {
+            assume_valid: flags & 0x8000 != 0,
+            extended: flags & 0x4000 != 0,
+            stage: ((flags & 0x3000) >> 12) as u8,
+            name_length: flags & 0xFFF,
+            skip_worktree: false,
+            intent_to_add: false,
+        }
+    }
+
+    /// The `extended` bit is set if any extended flag is set.
+    /// Names of 0xFFF bytes or longer are stored with a length of 0xFFF.
+    pub fn to_u16(&self) -> u16 {
+        let mut flags = 0u16;
+        if self.assume_valid {
+            flags |= 0x8000;
+        }
+        if self.has_extended() {
+            flags |= 0x4000;
+        }
+        flags |= (self.stage as u16) << 12;
+        flags |= self.name_length.min(0xFFF);
+        flags
+    }
+
+    pub fn has_extended(&self) -> bool {
+        self.skip_worktree || self.intent_to_add
+    }
+
+    pub fn set_extended_u16(&mut self, flags: u16) {
+        self.skip_worktree = flags & SKIP_WORKTREE != 0;
+        self.intent_to_add = flags & INTENT_TO_ADD != 0;
+    }
+
+    pub fn extended_u16(&self) -> u16 {
+        let mut flags = 0u16;
+        if self.skip_worktree {
+            flags |= SKIP_WORKTREE;
+        }
+        if self.intent_to_add {
+            flags |= INTENT_TO_ADD;
+        }
+        flags
+    }
+}
+
+pub struct IndexEntry {
+    pub ctime: Time,
+    pub mtime: Time,
+    pub dev: u32,  // 0 for windows
+    pub ino: u32,  // 0 for windows
+    pub mode: u32, // 0o100644 // 4-bit object type + 3-bit unused + 9-bit unix permission
+    pub uid: u32,  // 0 for windows
+    pub gid: u32,  // 0 for windows
+    pub size: u32,
+    pub hash: SHA1,
+    pub flags: Flags,
+    pub name: String,
+}
+impl Display for IndexEntry {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "IndexEntry {{ ctime: {}, mtime: {}, dev: {}, ino: {}, mode: {:o}, uid: {}, gid: {}, size: {}, hash: {}, flags: {:?}, name: {} }}",
+               self.ctime, self.mtime, self.dev, self.ino, self.mode, self.uid, self.gid, self.size, self.hash, self.flags, self.name)
+    }
+}
+
+impl IndexEntry {
+    /** Metadata must be got by [fs::symlink_metadata] to avoid following symlink */
+    pub fn new(meta: &fs::Metadata, hash: SHA1, name: String) -> Self {
+        let mut entry = IndexEntry {
+            ctime: Time::from_system_time(meta.created().unwrap()),
+            mtime: Time::from_system_time(meta.modified().unwrap()),
+            dev: 0,
+            ino: 0,
+            uid: 0,
+            gid: 0,
+            size: meta.len() as u32,
+            hash,
+            flags: Flags::new(name.len().min(0xFFF) as u16),
+            name,
+            mode: 0o100644,
+        };
+        #[cfg(unix)]
+        {
+            entry.dev = meta.dev() as u32;
+            entry.ino = meta.ino() as u32;
+            entry.uid = meta.uid();
+            entry.gid = meta.gid();
+
+            entry.mode = match meta.mode() & 0o170000/* file mode */ {
+                0o100000 => {
+                    match meta.mode() & 0o111 {
+                        0 => 0o100644, // no execute permission
+                        _ => 0o100755, // with execute permission
+                    }
+                }
+                0o120000 => 0o120000, // symlink
+                _ =>  entry.mode, // keep the original mode
+            }
+        }
+        #[cfg(windows)]
+        {
+            if meta.is_symlink() {
+                entry.mode = 0o120000;
+            }
+        }
+        entry
+    }
+
+    /// - `file`: **to workdir path**
+    /// - `workdir`: absolute or relative path
+    pub fn new_from_file(file: &Path, hash: SHA1, workdir: &Path) -> io::Result<Self> {
+        let name = file.to_str().unwrap().to_string();
+        let file_abs = workdir.join(file);
+        let meta = fs::symlink_metadata(file_abs)?; // without following symlink
+        let index = IndexEntry::new(&meta, hash, name);
+        Ok(index)
+    }
+
+    pub fn new_from_blob(name: String, hash: SHA1, size: u32) -> Self {
+        IndexEntry {
+            ctime: Time { seconds: 0, nanos: 0 },
+            mtime: Time { seconds: 0, nanos: 0 },
+            dev: 0,
+            ino: 0,
+            mode: 0o100644,
+            uid: 0,
+            gid: 0,
+            size,
+            hash,
+            flags: Flags::new(name.len().min(0xFFF) as u16),
+            name,
+        }
+    }
+}
+
+/// see [index-format](https://git-scm.com/docs/index-format)
+/// <br> to Working Dir relative path
+pub struct Index {
+    /// 2, 3 or 4, see [`Index::set_version`]
+    version: u32,
+    entries: BTreeMap<(String, u8), IndexEntry>,
+    cache_tree: Option<CacheTree>,
+    untracked_cache: Option<UntrackedCache>,
+}
+
+impl Index {
+    /// Returns the version and the number of entries
+    fn check_header(file: &mut impl Read) -> Result<(u32, u32), GitError> {
+        let mut magic = [0; 4];
+        file.read_exact(&mut magic)?;
+        if magic != *b"DIRC" {
+            return Err(GitError::InvalidIndexHeader(
+                String::from_utf8_lossy(&magic).to_string(),
+            ));
+        }
+
+        let version = file.read_u32::<BigEndian>()?;
+        if !(2..=4).contains(&version) {
+            return Err(GitError::InvalidIndexHeader(version.to_string()));
+        }
+
+        let entries = file.read_u32::<BigEndian>()?;
+        Ok((version, entries))
+    }
+
+    pub fn new() -> Self {
+        Index {
+            version: 2,
+            entries: BTreeMap::new(),
+            cache_tree: None,
+            untracked_cache: None,
+        }
+    }
+
+    pub fn size(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Read an index of a repository of the `kind` object format
+    pub fn from_file(path: impl AsRef<Path>, kind: HashKind) -> Result<Self, GitError> {
+        let file = File::open(path.as_ref())?; // read-only
+        let total_size = file.metadata()?.len();
+        let file = &mut Wrapper::with_hash_kind(BufReader::new(file), kind); // TODO move Wrapper & utils to a common module
+
+        let (version, num) = Index::check_header(file)?;
+        let mut index = Index::new();
+        index.version = version;
+        let mut previous_name = Vec::new(); // names are prefix compressed since v4
+
+        for _ in 0..num {
+            let mut entry = IndexEntry {
+                ctime: Time::from_stream(file)?,
+                mtime: Time::from_stream(file)?,
+                dev: file.read_u32::<BigEndian>()?, //utils::read_u32_be(file)?,
+                ino: file.read_u32::<BigEndian>()?,
+                mode: file.read_u32::<BigEndian>()?,
+                uid: file.read_u32::<BigEndian>()?,
+                gid: file.read_u32::<BigEndian>()?,
+                size: file.read_u32::<BigEndian>()?,
+                hash: utils::read_hash(file, kind)?,
+                flags: Flags::from_u16(file.read_u16::<BigEndian>()?),
+                name: String::new(),
+            };
+            let mut flags_len = 2;
+            if entry.flags.extended {
+                if version < 3 {
+                    return Err(GitError::InvalidIndexFile(format!(
+                        "extended flags of {} in index v{}",
+                        entry.hash, version
+                    )));
+                }
+                entry.flags.set_extended_u16(file.read_u16::<BigEndian>()?);
+                flags_len += 2;
+            }
+
+            let name = if version >= 4 {
+                // the number of bytes to remove from the previous name, then the rest of the name
+                let strip = read_varint(file)?;
+                if strip > previous_name.len() {
+                    return Err(GitError::InvalidIndexFile(format!(
+                        "can't strip {} bytes from the previous name",
+                        strip
+                    )));
+                }
+                previous_name.truncate(previous_name.len() - strip);
+                read_nul_terminated(file, &mut previous_name)?;
+                previous_name.clone()
+            } else {
+                let name_len = entry.flags.name_length as usize;
+                let mut name = vec![0; name_len];
+                file.read_exact(&mut name)?;
+                let mut consumed = name_len;
+                if name_len == 0xFFF {
+                    // the name is too long for the flags, read the rest up to the NUL
+                    read_nul_terminated(file, &mut name)?;
+                    consumed = name.len() + 1;
+                }
+                // 1-8 nul bytes as necessary to pad the entry to a multiple of eight bytes
+                // while keeping the name NUL-terminated. // so at least 1 byte nul
+                let padding = 8 - ((flags_len + kind.size() + name.len()) % 8); // hash + flags, others are 40 % 8 == 0
+                utils::read_bytes(file, padding + name.len() - consumed)?;
+                name
+            };
+            // The exact encoding is undefined, but the '.' and '/' characters are encoded in 7-bit ASCII
+            entry.name = String::from_utf8(name)?; // TODO check the encoding
+            index.entries.insert((entry.name.clone(), entry.flags.stage), entry);
+        }
+
+        // Extensions
+        while file.bytes_read() + kind.size() < total_size as usize {
+            // The remaining 20 bytes (32 for SHA-256) must be checksum
+            let sign = utils::read_bytes(file, 4)?;
+            let size = file.read_u32::<BigEndian>()?;
+            match &sign[..] {
+                b"TREE" => {
+                    let data = utils::read_bytes(file, size as usize)?;
+                    index.cache_tree = Some(CacheTree::from_bytes(&data, kind)?);
+                }
+                b"UNTR" => {
+                    let data = utils::read_bytes(file, size as usize)?;
+                    index.untracked_cache = Some(UntrackedCache::from_bytes(&data, kind)?);
+                }
+                // If the first byte is 'A'...'Z' the extension is optional and can be ignored.
+                _ if sign[0].is_ascii_uppercase() => {
+                    utils::read_bytes(file, size as usize)?;
+                }
+                // 'link' or 'sdir' extension
+                _ => {
+                    return Err(GitError::InvalidIndexFile(format!(
+                        "Unsupported extension {}",
+                        String::from_utf8_lossy(&sign)
+                    )));
+                }
+            }
+        }
+
+        // check sum
+        let file_hash = file.final_hash();
+        let check_sum = utils::read_hash(file, kind)?;
+        if file_hash != check_sum {
+            return Err(GitError::InvalidIndexFile("Check sum failed".to_string()));
+        }
+        // entries of the same name and stage replace each other
+        if index.size() != num as usize {
+            return Err(GitError::InvalidIndexFile(format!(
+                "{} entries expected, {} distinct read",
+                num,
+                index.size()
+            )));
+        }
+        Ok(index)
+    }
+
+    /// Write the index of a repository of the `kind` object format,
+    /// as v3 if v2 can't store the extended flags of the entries
+    pub fn to_file(&self, path: impl AsRef<Path>, kind: HashKind) -> Result<(), GitError> {
+        let mut file = File::create(path)?;
+        let mut hash = ObjectHasher::new(kind);
+
+        let extended = self
+            .entries
+            .values()
+            .any(|entry| entry.flags.has_extended());
+        let version = match self.version {
+            4 => 4,
+            _ if extended => 3,
+            _ => 2,
+        };
+        let mut header = Vec::new();
+        header.write_all(b"DIRC")?;
+        header.write_u32::<BigEndian>(version)?;
+        header.write_u32::<BigEndian>(self.entries.len() as u32)?;
+        file.write_all(&header)?;
+        hash.update(&header);
+
+        let mut previous_name: &[u8] = &[];
+        for (_, entry) in self.entries.iter() {
+            let mut entry_bytes = Vec::new();
+            entry_bytes.write_u32::<BigEndian>(entry.ctime.seconds)?;
+            entry_bytes.write_u32::<BigEndian>(entry.ctime.nanos)?;
+            entry_bytes.write_u32::<BigEndian>(entry.mtime.seconds)?;
+            entry_bytes.write_u32::<BigEndian>(entry.mtime.nanos)?;
+            entry_bytes.write_u32::<BigEndian>(entry.dev)?;
+            entry_bytes.write_u32::<BigEndian>(entry.ino)?;
+            entry_bytes.write_u32::<BigEndian>(entry.mode)?;
+            entry_bytes.write_u32::<BigEndian>(entry.uid)?;
+            entry_bytes.write_u32::<BigEndian>(entry.gid)?;
+            entry_bytes.write_u32::<BigEndian>(entry.size)?;
+            entry_bytes.write_all(entry.hash.as_bytes())?;
+            entry_bytes.write_u16::<BigEndian>(entry.flags.to_u16())?;
+            let mut flags_len = 2;
+            if entry.flags.has_extended() {
+                entry_bytes.write_u16::<BigEndian>(entry.flags.extended_u16())?;
+                flags_len += 2;
+            }
+            let name = entry.name.as_bytes();
+            if version >= 4 {
+                let common = name
+                    .iter()
+                    .zip(previous_name)
+                    .take_while(|(a, b)| a == b)
+                    .count();
+                write_varint(&mut entry_bytes, previous_name.len() - common)?;
+                entry_bytes.write_all(&name[common..])?;
+                entry_bytes.write_all(&[0])?;
+                previous_name = name;
+            } else {
+                entry_bytes.write_all(name)?;
+                let padding = 8 - ((flags_len + kind.size() + name.len()) % 8);
+                entry_bytes.write_all(&vec![0; padding])?;
+            }
+
+            file.write_all(&entry_bytes)?;
+            hash.update(&entry_bytes);
+        }
+
+        // Extensions
+        let mut extensions = Vec::new();
+        if let Some(cache_tree) = &self.cache_tree {
+            write_extension(&mut extensions, b"TREE", &cache_tree.to_bytes())?;
+        }
+        if let Some(untracked_cache) = &self.untracked_cache {
+            write_extension(&mut extensions, b"UNTR", &untracked_cache.to_bytes()?)?;
+        }
+        file.write_all(&extensions)?;
+        hash.update(&extensions);
+
+        // check sum
+        let file_hash = hash.finalize();
+        file.write_all(file_hash.as_bytes())?;
+        Ok(())
+    }
+}
+
+impl Index {
+    /// Load index, if not exist, return an empty index
+    pub fn load(index_file: impl AsRef<Path>, kind: HashKind) -> Result<Self, GitError> {
+        let path = index_file.as_ref();
+        if !path.exists() {
+            return Ok(Index::new());
+        }
+        Index::from_file(path, kind)
+    }
+
+    pub fn update(&mut self, entry: IndexEntry) {
+        self.add(entry)
+    }
+
+    pub fn add(&mut self, entry: IndexEntry) {
+        let key = (entry.name.clone(), entry.flags.stage);
+        match self.entries.get(&key) {
+            Some(old) if old.hash == entry.hash && old.mode == entry.mode => {}
+            old => {
+                let new_file = old.is_none();
+                self.invalidate(&entry.name, new_file);
+            }
+        }
+        self.entries.insert(key, entry);
+    }
+
+    pub fn remove(&mut self, name: &str, stage: u8) -> Option<IndexEntry> {
+        let removed = self.entries.remove(&(name.to_string(), stage));
+        if removed.is_some() {
+            self.invalidate(name, true);
+        }
+        removed
+    }
+
+    /// Invalidate the cached trees containing `name`,
+    /// and its untracked cache if it is added to or removed from the index.
+    fn invalidate(&mut self, name: &str, tracked_changed: bool) {
+        if let Some(cache_tree) = &mut self.cache_tree {
+            cache_tree.invalidate(name);
+        }
+        if tracked_changed {
+            if let Some(untracked_cache) = &mut self.untracked_cache {
+                untracked_cache.invalidate(name);
+            }
+        }
+    }
+
+    pub fn version(&self) -> u32 {
+        self.version
+    }
+
+    /// Set the version to write, 2, 3 or 4 (prefix compressed names).
+    /// v2 and v3 are written as v3 only if an entry has extended flags.
+    pub fn set_version(&mut self, version: u32) -> Result<(), GitError> {
+        if !(2..=4).contains(&version) {
+            return Err(GitError::InvalidIndexHeader(version.to_string()));
+        }
+        self.version = version;
+        Ok(())
+    }
+
+    /// The tree ids of the directories unchanged since they were last written
+    pub fn cache_tree(&self) -> Option<&CacheTree> {
+        self.cache_tree.as_ref()
+    }
+
+    pub fn set_cache_tree(&mut self, cache_tree: Option<CacheTree>) {
+        self.cache_tree = cache_tree;
+    }
+
+    /// The untracked files of the directories, see [`UntrackedCache`]
+    pub fn untracked_cache(&self) -> Option<&UntrackedCache> {
+        self.untracked_cache.as_ref()
+    }
+
+    pub fn set_untracked_cache(&mut self, untracked_cache: Option<UntrackedCache>) {
+        self.untracked_cache = untracked_cache;
+    }
+
+    pub fn get(&self, name: &str, stage: u8) -> Option<&IndexEntry> {
+        self.entries.get(&(name.to_string(), stage))
+    }
+
+    pub fn tracked(&self, name: &str, stage: u8) -> bool {
+        self.entries.contains_key(&(name.to_string(), stage))
+    }
+
+    pub fn get_hash(&self, file: &str, stage: u8) -> Option<SHA1> {
+        self.get(file, stage).map(|entry| entry.hash)
+    }
+
+    pub fn verify_hash(&self, file: &str, stage: u8, hash: &SHA1) -> bool {
+        let inner_hash = self.get_hash(file, stage);
+        if let Some(inner_hash) = inner_hash {
+            &inner_hash == hash
+        } else {
+            false
+        }
+    }
+    /// Whether the entry is outside the sparse checkout, so it has no file in the working tree
+    pub fn skip_worktree(&self, name: &str, stage: u8) -> bool {
+        self.get(name, stage).is_some_and(|entry| entry.flags.skip_worktree)
+    }
+
+    /// Mark the entry as outside the sparse checkout or not
+    /// - return false if the entry is not in the index
+    pub fn set_skip_worktree(&mut self, name: &str, stage: u8, skip: bool) -> bool {
+        match self.entries.get_mut(&(name.to_string(), stage)) {
+            Some(entry) => {
+                entry.flags.skip_worktree = skip;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// is file modified after last `add` (need hash to confirm content change)
+    /// - `workdir` is used to rebuild absolute file path
+    /// - an entry with the skip-worktree bit is never modified, its file is not checked out
+    pub fn is_modified(&self, file: &str, stage: u8, workdir: &Path) -> bool {
+        if let Some(entry) = self.get(file, stage) {
+            if entry.flags.skip_worktree {
+                return false;
+            }
+            let path_abs = workdir.join(file);
+            let meta = path_abs.symlink_metadata().unwrap();
+            // TODO more fields
+            let same = entry.ctime == Time::from_system_time(meta.created().unwrap_or(SystemTime::now()))
+            && entry.mtime == Time::from_system_time(meta.modified().unwrap_or(SystemTime::now()))
+            && entry.size == meta.len() as u32;
+
+            !same
+        } else {
+            panic!("File not found in index");
+        }
+    }
+
+    /// Get all entries with the same stage
+    pub fn tracked_entries(&self, stage: u8) -> Vec<&IndexEntry> {
+        // ? should use stage or not
+        self.entries
+            .iter()
+            .filter(|(_, entry)| entry.flags.stage == stage)
+            .map(|(_, entry)| entry)
+            .collect()
+    }
+
+    /// Get all tracked files(stage = 0)
+    pub fn tracked_files(&self) -> Vec<PathBuf> {
+        self.tracked_entries(0).iter().map(|entry| PathBuf::from(&entry.name)).collect()
+    }
+
+    /// Judge if the file(s) of `dir` is in the index
+    /// - false if `dir` is a file
+    pub fn contains_dir_file(&self, dir: &str) -> bool {
+        let dir = Path::new(dir);
+        self.entries.iter().any(|((name, _), _)| {
+            let path = Path::new(name);
+            path.starts_with(dir) && path != dir // TODO change to is_sub_path!
+        })
+    }
+
+    /// remove all files in `dir` from index
+    /// - do nothing if `dir` is a file
+    pub fn remove_dir_files(&mut self, dir: &str) -> Vec<String> {
+        let dir = Path::new(dir);
+        let mut removed = Vec::new();
+        self.entries.retain(|(name, _), _| {
+            let path = Path::new(name);
+            if path.starts_with(dir) && path != dir {
+                removed.push(name.clone());
+                false
+            } else {
+                true
+            }
+        });
+        for name in &removed {
+            self.invalidate(name, true);
+        }
+        removed
+    }
+
+    /// saved to index file
+    pub fn save(&self, index_file: impl AsRef<Path>, kind: HashKind) -> Result<(), GitError> {
+        self.to_file(index_file, kind)
+    }
+}
+
+/// Read a variable length integer of the index, encoded like the offset of an `OffsetDelta`
+pub(crate) fn read_varint(data: &mut impl Read) -> Result<usize, GitError> {
+    Ok(read_offset_encoding(data)?.0 as usize)
+}
+
+pub(crate) fn write_varint(out: &mut impl Write, mut value: usize) -> Result<(), GitError> {
+    let mut bytes = vec![(value & 0x7F) as u8];
+    value >>= 7;
+    while value != 0 {
+        value -= 1;
+        bytes.push(0x80 | (value & 0x7F) as u8);
+        value >>= 7;
+    }
+    bytes.reverse();
+    out.write_all(&bytes)?;
+    Ok(())
+}
+
+/// Append the bytes up to the next NUL to `buf`, the NUL is consumed but not appended
+fn read_nul_terminated(file: &mut impl BufRead, buf: &mut Vec<u8>) -> Result<(), GitError> {
+    file.read_until(0, buf)?;
+    if buf.pop() != Some(0) {
+        return Err(GitError::InvalidIndexFile(
+            "unterminated entry name".to_string(),
+        ));
+    }
+    Ok(())
+}
+
+fn write_extension(out: &mut Vec<u8>, sign: &[u8; 4], data: &[u8]) -> Result<(), GitError> {
+    out.write_all(sign)?;
+    out.write_u32::<BigEndian>(data.len() as u32)?;
+    out.write_all(data)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_time() {
+        let time = Time {
+            seconds: 0,
+            nanos: 0,
+        };
+        let system_time = time.to_system_time();
+        let new_time = Time::from_system_time(system_time);
+        assert_eq!(time, new_time);
+    }
+
+    #[test]
+    fn test_check_header() {
+        let file = File::open("../tests/data/index/index-2").unwrap();
+        let (version, entries) = Index::check_header(&mut BufReader::new(file)).unwrap();
+        assert_eq!(version, 2);
+        assert_eq!(entries, 2);
+    }
+
+    #[test]
+    fn test_index() {
+        let index = Index::from_file("../tests/data/index/index-760", HashKind::Sha1).unwrap();
+        assert_eq!(index.size(), 760);
+        for (_, entry) in index.entries.iter() {
+            println!("{}", entry);
+        }
+    }
+
+    #[test]
+    fn test_index_to_file() {
+        let index = Index::from_file("../tests/data/index/index-760", HashKind::Sha1).unwrap();
+        index.to_file("/tmp/index-760", HashKind::Sha1).unwrap();
+        let new_index = Index::from_file("/tmp/index-760", HashKind::Sha1).unwrap();
+        assert_eq!(index.size(), new_index.size());
+    }
+
+    #[test]
+    fn test_index_v4() {
+        // written by git with skip-worktree, intent-to-add, TREE and UNTR
+        let mut index = Index::from_file("../tests/data/index/index-v4", HashKind::Sha1).unwrap();
+        assert_eq!(index.version(), 4);
+        assert_eq!(index.size(), 6);
+        assert!(index.get("doc/d.md", 0).unwrap().flags.skip_worktree);
+        assert!(index.get("new.txt", 0).unwrap().flags.intent_to_add);
+        assert_eq!(index.get("src/util/e.rs", 0).unwrap().flags.name_length, 13);
+        let cache_tree = index.cache_tree().unwrap();
+        assert!(cache_tree.tree_id("src/util").is_some());
+        let untracked = index.untracked_cache().unwrap().root.as_ref().unwrap();
+        assert!(untracked.untracked.contains(&"untracked.txt".to_string()));
+
+        for version in [4, 2] {
+            index.set_version(version).unwrap();
+            index.to_file("/tmp/index-v4", HashKind::Sha1).unwrap();
+            let new_index = Index::from_file("/tmp/index-v4", HashKind::Sha1).unwrap();
+            // v2 can't store the extended flags
+            assert_eq!(new_index.version(), version.max(3));
+            assert_eq!(new_index.tracked_files(), index.tracked_files());
+            assert!(new_index.get("doc/d.md", 0).unwrap().flags.skip_worktree);
+            assert_eq!(new_index.cache_tree(), index.cache_tree());
+            assert_eq!(new_index.untracked_cache(), index.untracked_cache());
+        }
+
+        index.add(IndexEntry::new_from_blob(
+            "src/util/f.rs".to_string(),
+            SHA1::from_bytes(&[0; 20]).unwrap(),
+            0,
+        ));
+        assert!(index.cache_tree().unwrap().tree_id("src/util").is_none());
+        assert!(index.cache_tree().unwrap().tree_id("doc").is_some());
+    }
+
+    #[test]
+    fn test_skip_worktree() {
+        let hash = SHA1::from_bytes(&[0; 20]).unwrap();
+        let mut index = Index::new();
+        index.add(IndexEntry::new_from_blob("sparse/a.txt".to_string(), hash, 0));
+        assert!(index.set_skip_worktree("sparse/a.txt", 0, true));
+        assert!(!index.set_skip_worktree("missing.txt", 0, true));
+        // the file is not checked out, so it is not looked at
+        assert!(!index.is_modified("sparse/a.txt", 0, Path::new("/nonexistent")));
+
+        index.to_file("/tmp/index-skip-worktree", HashKind::Sha1).unwrap();
+        let mut new_index = Index::from_file("/tmp/index-skip-worktree", HashKind::Sha1).unwrap();
+        assert_eq!(new_index.version(), 3);
+        assert!(new_index.skip_worktree("sparse/a.txt", 0));
+        new_index.set_skip_worktree("sparse/a.txt", 0, false);
+        assert!(!new_index.skip_worktree("sparse/a.txt", 0));
+    }
+
+    #[test]
+    fn test_index_long_name() {
+        let name = "a/".repeat(0x1000) + "b";
+        let hash = SHA1::from_bytes(&[0; 20]).unwrap();
+        let mut index = Index::new();
+        index.add(IndexEntry::new_from_blob(name.clone(), hash, 0));
+        index.add(IndexEntry::new_from_blob("c".to_string(), hash, 0));
+        index.to_file("/tmp/index-long-name", HashKind::Sha1).unwrap();
+        let new_index = Index::from_file("/tmp/index-long-name", HashKind::Sha1).unwrap();
+        assert!(new_index.tracked(&name, 0));
+        assert!(new_index.tracked("c", 0));
+    }
+
+    #[test]
+    fn test_index_duplicate_entries() {
+        let hash = SHA1::from_bytes(&[0; 20]).unwrap();
+        let mut index = Index::new();
+        index.add(IndexEntry::new_from_blob("a.txt".to_string(), hash, 0));
+        index.add(IndexEntry::new_from_blob("b.txt".to_string(), hash, 0));
+        index.to_file("/tmp/index-duplicate", HashKind::Sha1).unwrap();
+
+        // rename the second entry as the first one, and sign the index again
+        let mut data = std::fs::read("/tmp/index-duplicate").unwrap();
+        let at = data.windows(5).position(|w| w == b"b.txt").unwrap();
+        data[at] = b'a';
+        let end = data.len() - 20;
+        let check_sum = SHA1::new(&data[..end]);
+        data[end..].copy_from_slice(&check_sum.to_data());
+        std::fs::write("/tmp/index-duplicate", data).unwrap();
+        assert!(Index::from_file("/tmp/index-duplicate", HashKind::Sha1).is_err());
+    }
+
+    #[test]
+    fn test_varint() {
+        for value in [0, 1, 127, 128, 16383, 16384, 1 << 30] {
+            let mut data = Vec::new();
+            write_varint(&mut data, value).unwrap();
+            assert_eq!(read_varint(&mut data.as_slice()).unwrap(), value);
+        }
+    }
+
+    #[test]
+    fn test_index_entry_create() {
+        let file = Path::new("Cargo.toml"); // use as a normal file
+        let hash = SHA1::from_bytes(&[0; 20]).unwrap();
+        let workdir = Path::new("../");
+        let entry = IndexEntry::new_from_file(file, hash, workdir).unwrap();
+        println!("{}", entry);
+    }
+}
@@ -0,0 +1,367 @@
+//! The untracked cache extension (`UNTR`) of the index: the untracked files of every directory,
+//! with the stat data of the directory, so unchanged directories don't need to be read again.
+
+use std::fs;
+use std::io::{BufRead, Read, Write};
+#[cfg(unix)]
+use std::os::unix::fs::MetadataExt;
+use std::time::SystemTime;
+
+use byteorder::{BigEndian, ReadBytesExt, WriteBytesExt};
+
+use super::ewah::{read_ewah, write_ewah};
+use super::{read_varint, write_varint, Time};
+use crate::errors::GitError;
+use crate::hash::{HashKind, SHA1};
+use crate::utils;
+
+/// The stat data of a file or directory, as stored in the index.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StatData {
+    pub ctime: Time,
+    pub mtime: Time,
+    pub dev: u32,
+    pub ino: u32,
+    pub uid: u32,
+    pub gid: u32,
+    pub size: u32,
+}
+
+impl StatData {
+    pub fn from_metadata(meta: &fs::Metadata) -> Self {
+        #[allow(unused_mut)]
+        let mut stat = StatData {
+            ctime: Time::from_system_time(meta.created().unwrap_or(SystemTime::UNIX_EPOCH)),
+            mtime: Time::from_system_time(meta.modified().unwrap_or(SystemTime::UNIX_EPOCH)),
+            dev: 0,
+            ino: 0,
+            uid: 0,
+            gid: 0,
+            size: meta.len() as u32,
+        };
+        #[cfg(unix)]
+        {
+            stat.dev = meta.dev() as u32;
+            stat.ino = meta.ino() as u32;
+            stat.uid = meta.uid();
+            stat.gid = meta.gid();
+        }
+        stat
+    }
+
+    fn read(data: &mut impl Read) -> Result<Self, GitError> {
+        Ok(StatData {
+            ctime: Time::from_stream(data)?,
+            mtime: Time::from_stream(data)?,
+            dev: data.read_u32::<BigEndian>()?,
+            ino: data.read_u32::<BigEndian>()?,
+            uid: data.read_u32::<BigEndian>()?,
+            gid: data.read_u32::<BigEndian>()?,
+            size: data.read_u32::<BigEndian>()?,
+        })
+    }
+
+    fn write(&self, out: &mut impl Write) -> Result<(), GitError> {
+        for value in [
+            self.ctime.seconds,
+            self.ctime.nanos,
+            self.mtime.seconds,
+            self.mtime.nanos,
+            self.dev,
+            self.ino,
+            self.uid,
+            self.gid,
+            self.size,
+        ] {
+            out.write_u32::<BigEndian>(value)?;
+        }
+        Ok(())
+    }
+
+    fn zero() -> Self {
+        StatData {
+            ctime: Time {
+                seconds: 0,
+                nanos: 0,
+            },
+            mtime: Time {
+                seconds: 0,
+                nanos: 0,
+            },
+            dev: 0,
+            ino: 0,
+            uid: 0,
+            gid: 0,
+            size: 0,
+        }
+    }
+}
+
+/// The untracked files of a directory, and the directories in it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UntrackedDir {
+    /// the name of the directory in its parent, empty for the root
+    pub name: String,
+    /// the names of the untracked files, directories end with '/'
+    pub untracked: Vec<String>,
+    pub dirs: Vec<UntrackedDir>,
+    /// `untracked` is up to date, unless the stat data of the directory changed
+    pub valid: bool,
+    pub check_only: bool,
+    /// the stat data of the directory and the id of its exclude file
+    pub stat: Option<(StatData, SHA1)>,
+}
+
+impl UntrackedDir {
+    pub fn new(name: &str) -> Self {
+        UntrackedDir {
+            name: name.to_string(),
+            untracked: Vec::new(),
+            dirs: Vec::new(),
+            valid: false,
+            check_only: false,
+            stat: None,
+        }
+    }
+
+    /// Invalidate the directory containing `path`, after its entry was added or removed.
+    pub fn invalidate(&mut self, path: &str) {
+        match path.split_once('/') {
+            Some((dir, rest)) => {
+                if let Some(sub) = self.dirs.iter_mut().find(|d| d.name == dir) {
+                    sub.invalidate(rest);
+                }
+            }
+            None => self.valid = false,
+        }
+    }
+
+    fn count(&self) -> usize {
+        1 + self.dirs.iter().map(UntrackedDir::count).sum::<usize>()
+    }
+
+    /// The directories in depth-first order, the order of their blocks in the extension.
+    fn flatten<'a>(&'a self, dirs: &mut Vec<&'a UntrackedDir>) {
+        dirs.push(self);
+        for dir in &self.dirs {
+            dir.flatten(dirs);
+        }
+    }
+
+    fn read_block(data: &mut &[u8], dirs: &mut usize) -> Result<Self, GitError> {
+        *dirs += 1;
+        let untracked_count = read_varint(data)?;
+        let dir_count = read_varint(data)?;
+        let mut dir = UntrackedDir::new(&read_string(data)?);
+        for _ in 0..untracked_count {
+            dir.untracked.push(read_string(data)?);
+        }
+        for _ in 0..dir_count {
+            dir.dirs.push(Self::read_block(data, dirs)?);
+        }
+        Ok(dir)
+    }
+
+    /// Visit the directories in depth-first order.
+    fn for_each_mut(&mut self, f: &mut impl FnMut(&mut UntrackedDir)) {
+        f(self);
+        for dir in &mut self.dirs {
+            dir.for_each_mut(f);
+        }
+    }
+}
+
+/// The untracked cache of an index.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UntrackedCache {
+    /// describes where the cache can be used, like the location of the worktree
+    pub ident: Vec<u8>,
+    pub info_exclude: (StatData, SHA1),
+    pub excludes_file: (StatData, SHA1),
+    pub dir_flags: u32,
+    /// the name of the exclude file of each directory, usually `.gitignore`
+    pub exclude_per_dir: String,
+    pub root: Option<UntrackedDir>,
+}
+
+impl UntrackedCache {
+    pub fn new(ident: &[u8], kind: HashKind) -> Self {
+        UntrackedCache {
+            ident: ident.to_vec(),
+            info_exclude: (StatData::zero(), SHA1::zero(kind)),
+            excludes_file: (StatData::zero(), SHA1::zero(kind)),
+            dir_flags: 0,
+            exclude_per_dir: ".gitignore".to_string(),
+            root: None,
+        }
+    }
+
+    /// Invalidate the directory containing `path`, after its entry was added or removed.
+    pub fn invalidate(&mut self, path: &str) {
+        if let Some(root) = &mut self.root {
+            root.invalidate(path);
+        }
+    }
+
+    /// Parse the data of an `UNTR` extension.
+    pub fn from_bytes(data: &[u8], kind: HashKind) -> Result<Self, GitError> {
+        let mut data = data;
+        let ident_len = read_varint(&mut data)?;
+        let ident = utils::read_bytes(&mut data, ident_len)?;
+        let info_exclude_stat = StatData::read(&mut data)?;
+        let excludes_file_stat = StatData::read(&mut data)?;
+        let dir_flags = data.read_u32::<BigEndian>()?;
+        let info_exclude = (info_exclude_stat, utils::read_hash(&mut data, kind)?);
+        let excludes_file = (excludes_file_stat, utils::read_hash(&mut data, kind)?);
+        let exclude_per_dir = read_string(&mut data)?;
+        let mut cache = UntrackedCache {
+            ident,
+            info_exclude,
+            excludes_file,
+            dir_flags,
+            exclude_per_dir,
+            root: None,
+        };
+        let dir_count = read_varint(&mut data)?;
+        if dir_count == 0 {
+            return Ok(cache);
+        }
+
+        let mut read_count = 0;
+        let mut root = UntrackedDir::read_block(&mut data, &mut read_count)?;
+        if read_count != dir_count {
+            return Err(GitError::InvalidIndexFile(format!(
+                "untracked cache has {} directories, expected {}",
+                read_count, dir_count
+            )));
+        }
+        let valid = read_ewah(&mut data)?;
+        let check_only = read_ewah(&mut data)?;
+        let stat_valid = read_ewah(&mut data)?;
+        if [&valid, &check_only, &stat_valid]
+            .iter()
+            .any(|bits| bits.last().is_some_and(|&bit| bit >= dir_count))
+        {
+            return Err(GitError::InvalidIndexFile(
+                "untracked cache bitmap is larger than its directories".to_string(),
+            ));
+        }
+        let mut stats = Vec::with_capacity(stat_valid.len());
+        for _ in 0..stat_valid.len() {
+            stats.push(StatData::read(&mut data)?);
+        }
+        let mut hashes = Vec::with_capacity(stat_valid.len());
+        for _ in 0..stat_valid.len() {
+            hashes.push(utils::read_hash(&mut data, kind)?);
+        }
+        let mut stats = stat_valid
+            .into_iter()
+            .zip(stats.into_iter().zip(hashes))
+            .peekable();
+        let mut i = 0;
+        root.for_each_mut(&mut |dir| {
+            dir.valid = valid.binary_search(&i).is_ok();
+            dir.check_only = check_only.binary_search(&i).is_ok();
+            if stats.peek().is_some_and(|(bit, _)| *bit == i) {
+                dir.stat = stats.next().map(|(_, stat)| stat);
+            }
+            i += 1;
+        });
+        cache.root = Some(root);
+        Ok(cache)
+    }
+
+    /// Serialize as the data of an `UNTR` extension.
+    pub fn to_bytes(&self) -> Result<Vec<u8>, GitError> {
+        let mut out = Vec::new();
+        write_varint(&mut out, self.ident.len())?;
+        out.write_all(&self.ident)?;
+        self.info_exclude.0.write(&mut out)?;
+        self.excludes_file.0.write(&mut out)?;
+        out.write_u32::<BigEndian>(self.dir_flags)?;
+        out.write_all(self.info_exclude.1.as_bytes())?;
+        out.write_all(self.excludes_file.1.as_bytes())?;
+        write_string(&mut out, &self.exclude_per_dir)?;
+        let Some(root) = &self.root else {
+            write_varint(&mut out, 0)?;
+            return Ok(out);
+        };
+
+        write_varint(&mut out, root.count())?;
+        let mut dirs = Vec::new();
+        root.flatten(&mut dirs);
+        for dir in &dirs {
+            write_varint(&mut out, dir.untracked.len())?;
+            write_varint(&mut out, dir.dirs.len())?;
+            write_string(&mut out, &dir.name)?;
+            for name in &dir.untracked {
+                write_string(&mut out, name)?;
+            }
+        }
+        let bits = |f: fn(&UntrackedDir) -> bool| {
+            dirs.iter()
+                .enumerate()
+                .filter(move |(_, d)| f(d))
+                .map(|(i, _)| i)
+        };
+        write_ewah(&mut out, dirs.len(), bits(|d| d.valid))?;
+        write_ewah(&mut out, dirs.len(), bits(|d| d.check_only))?;
+        write_ewah(&mut out, dirs.len(), bits(|d| d.stat.is_some()))?;
+        for (stat, _) in dirs.iter().filter_map(|d| d.stat.as_ref()) {
+            stat.write(&mut out)?;
+        }
+        for (_, hash) in dirs.iter().filter_map(|d| d.stat.as_ref()) {
+            out.write_all(hash.as_bytes())?;
+        }
+        out.push(0);
+        Ok(out)
+    }
+}
+
+fn read_string(data: &mut &[u8]) -> Result<String, GitError> {
+    let mut bytes = Vec::new();
+    data.read_until(0, &mut bytes)?;
+    if bytes.pop() != Some(0) {
+        return Err(GitError::InvalidIndexFile(
+            "unterminated string in the untracked cache".to_string(),
+        ));
+    }
+    Ok(String::from_utf8(bytes)?)
+}
+
+fn write_string(out: &mut Vec<u8>, s: &str) -> Result<(), GitError> {
+    out.write_all(s.as_bytes())?;
+    out.push(0);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_untracked_cache() {
+        let kind = HashKind::Sha1;
+        let stat = StatData::from_metadata(&fs::metadata(".").unwrap());
+        let mut cache = UntrackedCache::new(b"libra /tmp\0", kind);
+        let mut root = UntrackedDir::new("");
+        root.valid = true;
+        root.stat = Some((stat.clone(), SHA1::zero(kind)));
+        root.untracked.push("a.txt".to_string());
+        let mut src = UntrackedDir::new("src");
+        src.untracked.push("b.rs".to_string());
+        src.stat = Some((stat, SHA1::new(b"gitignore")));
+        root.dirs.push(src);
+        root.dirs.push(UntrackedDir::new("doc"));
+        cache.root = Some(root);
+
+        let data = cache.to_bytes().unwrap();
+        assert_eq!(UntrackedCache::from_bytes(&data, kind).unwrap(), cache);
+
+        cache.invalidate("a.txt");
+        assert!(!cache.root.as_ref().unwrap().valid);
+        let empty = UntrackedCache::new(b"", kind);
+        let data = empty.to_bytes().unwrap();
+        assert_eq!(UntrackedCache::from_bytes(&data, kind).unwrap(), empty);
+    }
+}
@@ -3,3 +3,5 @@ pub mod object;
 pub mod pack;
 pub mod zlib;
 pub mod index;
+pub mod fsck;
+pub mod tree_diff;
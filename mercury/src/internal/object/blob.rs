@@ -30,7 +30,7 @@
 use std::fmt::Display;
 
 use crate::errors::GitError;
-use crate::hash::SHA1;
+use crate::hash::{HashKind, SHA1};
 use crate::internal::object::types::ObjectType;
 use crate::internal::object::ObjectTrait;
 
@@ -94,6 +94,19 @@ impl Blob {
             data: content,
         }
     }
+
+    /// Create a blob from raw bytes, the content needn't be valid utf-8.
+    pub fn from_content_bytes(content: Vec<u8>) -> Self {
+        Self::from_content_with_kind(content, HashKind::Sha1)
+    }
+
+    /// Create a blob of a repository of the `kind` object format.
+    pub fn from_content_with_kind(content: Vec<u8>, kind: HashKind) -> Self {
+        Blob {
+            id: SHA1::from_type_and_data_with_kind(kind, ObjectType::Blob, &content),
+            data: content,
+        }
+    }
 }
 
 #[cfg(test)]
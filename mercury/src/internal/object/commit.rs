@@ -77,7 +77,12 @@ impl Commit {
             committer,
             message: message.to_string(),
         };
-        let hash = SHA1::from_type_and_data(ObjectType::Commit, &commit.to_data().unwrap());
+        // the id has the same hash algorithm as the tree
+        let hash = SHA1::from_type_and_data_with_kind(
+            commit.tree_id.kind(),
+            ObjectType::Commit,
+            &commit.to_data().unwrap(),
+        );
         commit.id = hash;
         commit
     }
@@ -9,11 +9,9 @@ pub mod utils;
 use std::{
     fmt::Display,
     io::{BufRead, Read},
-    str::FromStr,
 };
 
 use callisto::{git_blob, git_commit, git_tag, git_tree, mega_blob, mega_commit, mega_tag, mega_tree, raw_blob};
-use sha1::Digest;
 
 use crate::internal::object::types::ObjectType;
 use crate::internal::object::{blob::Blob, commit::Commit, tag::Tag, tree::Tree};
@@ -35,9 +33,8 @@ pub trait ObjectTrait: Send + Sync + Display {
     {
         let mut content: Vec<u8> = Vec::with_capacity(size);
         read.read_to_end(&mut content).unwrap();
-        let h = read.hash.clone();
-        let hash_str = h.finalize();
-        Self::from_bytes(&content, SHA1::from_str(&format!("{:x}", hash_str)).unwrap()).unwrap()
+        let hash = read.hash.clone().finalize();
+        Self::from_bytes(&content, hash).unwrap()
     }
 
     /// Returns the type of the object.
@@ -17,6 +17,7 @@
 use std::fmt::Display;
 
 use colored::Colorize;
+use serde::{Deserialize, Serialize};
 
 use crate::errors::GitError;
 use crate::hash::SHA1;
@@ -28,7 +29,7 @@ use crate::internal::object::ObjectType;
 /// type of the object. The first digit specifies the object type, and the remaining two digits
 /// specify the file mode or permissions.
 #[allow(unused)]
-#[derive(PartialEq, Eq, Hash, Ord, PartialOrd, Debug, Clone, Copy)]
+#[derive(PartialEq, Eq, Hash, Ord, PartialOrd, Debug, Clone, Copy, Serialize, Deserialize)]
 pub enum TreeItemMode {
     Blob,
     BlobExecutable,
@@ -189,7 +190,7 @@ impl TreeItem {
 
         Ok(TreeItem {
             mode: TreeItemMode::tree_item_type_from_bytes(mode)?,
-            id: SHA1::from_bytes(id),
+            id: SHA1::from_bytes(id)?,
             name: String::from_utf8(name.to_vec())?,
         })
     }
@@ -255,8 +256,10 @@ impl Tree {
             data.extend_from_slice(item.to_data().as_slice());
         }
 
+        // the id has the same hash algorithm as the items
+        let kind = tree_items[0].id.kind();
         Ok(Tree {
-            id: SHA1::from_type_and_data(ObjectType::Tree, &data),
+            id: SHA1::from_type_and_data_with_kind(kind, ObjectType::Tree, &data),
             tree_items,
         })
     }
@@ -272,8 +275,11 @@ impl ObjectTrait for Tree {
         while i < data.len() {
             // Find the position of the null byte (0x00)
             if let Some(index) = memchr::memchr(0x00, &data[i..]) {
-                // Calculate the next position
-                let next = i + index + 21;
+                // Calculate the next position, ids of items have the same algorithm as the tree
+                let next = i + index + 1 + hash.size();
+                if next > data.len() {
+                    return Err(GitError::InvalidTreeObject);
+                }
 
                 // Extract the bytes and create a TreeItem
                 let item_data = &data[i..next];
@@ -319,8 +325,10 @@ mod tests {
 
     use std::str::FromStr;
 
-    use crate::hash::SHA1;
+    use crate::hash::{HashKind, SHA1};
     use crate::internal::object::tree::{Tree, TreeItem, TreeItemMode};
+    use crate::internal::object::types::ObjectType;
+    use crate::internal::object::ObjectTrait;
 
     #[test]
     fn test_tree_item_new() {
@@ -385,4 +393,19 @@ mod tests {
             tree.id.to_plain_str()
         );
     }
+
+    #[test]
+    fn test_tree_sha256() {
+        let id = SHA1::from_type_and_data_with_kind(HashKind::Sha256, ObjectType::Blob, &[]);
+        let items = vec![
+            TreeItem::new(TreeItemMode::Blob, id, String::from("a.txt")),
+            TreeItem::new(TreeItemMode::Blob, id, String::from("b.txt")),
+        ];
+        let tree = Tree::from_tree_items(items).unwrap();
+        assert_eq!(tree.id.kind(), HashKind::Sha256);
+
+        let parsed = Tree::from_bytes(&tree.to_data().unwrap(), tree.id).unwrap();
+        assert_eq!(parsed, tree);
+        assert_eq!(parsed.tree_items[1].id, id);
+    }
 }
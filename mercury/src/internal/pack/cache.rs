@@ -1,7 +1,7 @@
 
 use std::path::Path;
 use std::path::PathBuf;
-use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
 use std::sync::{Arc, Mutex};
 use std::thread::sleep;
 use std::{fs, io};
@@ -29,6 +29,44 @@ pub trait _Cache {
     fn clear(&self);
 }
 
+/// Counters of the objects spilled to the temp dir of [Caches] when the memory cache is full.
+#[derive(Debug, Default)]
+pub struct SpillStats {
+    spilled_objects: AtomicUsize,
+    spilled_bytes: AtomicUsize,
+    loaded_objects: AtomicUsize,
+}
+
+/// A snapshot of [SpillStats].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct SpillMetrics {
+    /// objects written to the temp dir
+    pub spilled_objects: usize,
+    /// heap size of the objects written to the temp dir
+    pub spilled_bytes: usize,
+    /// objects read back from the temp dir
+    pub loaded_objects: usize,
+}
+
+impl SpillStats {
+    pub fn record_spill(&self, bytes: usize) {
+        self.spilled_objects.fetch_add(1, Ordering::Relaxed);
+        self.spilled_bytes.fetch_add(bytes, Ordering::Relaxed);
+    }
+
+    pub fn record_load(&self) {
+        self.loaded_objects.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn snapshot(&self) -> SpillMetrics {
+        SpillMetrics {
+            spilled_objects: self.spilled_objects.load(Ordering::Relaxed),
+            spilled_bytes: self.spilled_bytes.load(Ordering::Relaxed),
+            loaded_objects: self.loaded_objects.load(Ordering::Relaxed),
+        }
+    }
+}
+
 pub struct Caches {
     map_offset: DashMap<usize, SHA1>, // offset to hash
     hash_set: DashSet<SHA1>,          // item in the cache
@@ -41,6 +79,7 @@ pub struct Caches {
     tmp_path: PathBuf,
     pool: Arc<ThreadPool>,
     complete_signal: Arc<AtomicBool>,
+    spill_stats: Arc<SpillStats>,
 }
 
 impl Caches {
@@ -67,6 +106,8 @@ impl Caches {
             }
         };
 
+        self.spill_stats.record_load();
+
         let mut map = self.lru_cache.lock().unwrap();
         let obj = Arc::new(obj);
         let mut x = ArcWrapper::new(
@@ -75,6 +116,7 @@ impl Caches {
             Some(self.pool.clone()),
         );
         x.set_store_path(Caches::generate_temp_path(&self.tmp_path, hash));
+        x.set_spill_stats(self.spill_stats.clone());
         let _ = map.insert(hash.to_plain_str(), x); // handle the error
         Ok(obj)
     }
@@ -99,6 +141,11 @@ impl Caches {
         Ok(obj)
     }
 
+    /// The objects spilled to and read back from the temp dir so far.
+    pub fn spill_metrics(&self) -> SpillMetrics {
+        self.spill_stats.snapshot()
+    }
+
     pub fn queued_tasks(&self) -> usize {
         self.pool.queued_count()
     }
@@ -139,6 +186,7 @@ impl _Cache for Caches {
             tmp_path,
             pool: Arc::new(ThreadPool::new(thread_num)),
             complete_signal: Arc::new(AtomicBool::new(false)),
+            spill_stats: Arc::new(SpillStats::default()),
         }
     }
 
@@ -157,6 +205,7 @@ impl _Cache for Caches {
                 Some(self.pool.clone()),
             );
             a_obj.set_store_path(Caches::generate_temp_path(&self.tmp_path, hash));
+            a_obj.set_spill_stats(self.spill_stats.clone());
             let _ = map.insert(hash.to_plain_str(), a_obj);
         }
         //order maters as for reading in 'get_by_offset()'
@@ -5,11 +5,15 @@ use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
 use std::{fs, io};
 use std::{ops::Deref, sync::Arc};
 
+use crate::internal::pack::cache::SpillStats;
 use crate::internal::pack::utils;
 use lru_mem::{HeapSize, MemSize};
 use serde::{Deserialize, Serialize};
 use threadpool::ThreadPool;
-use crate::{hash::SHA1, internal::object::types::ObjectType};
+use crate::{
+    hash::{HashKind, SHA1},
+    internal::object::types::ObjectType,
+};
 use crate::internal::pack::entry::Entry;
 
 /// record heap-size of all CacheObjects, used for memory limit.
@@ -144,9 +148,9 @@ impl MemSizeRecorder for CacheObject {
 }
 
 impl CacheObject {
-    /// Create a new CacheObject witch is not offset_delta or hash_delta
-    pub fn new_for_undeltified(obj_type: ObjectType, data: Vec<u8>, offset: usize) -> Self {
-        let hash = utils::calculate_object_hash(obj_type, &data);
+    /// Create a new CacheObject witch is not offset_delta or hash_delta, its id is a hash of `kind`
+    pub fn new_for_undeltified(obj_type: ObjectType, data: Vec<u8>, offset: usize, kind: HashKind) -> Self {
+        let hash = utils::calculate_object_hash(kind, obj_type, &data);
         CacheObject {
             data_decompress: data,
             obj_type,
@@ -188,6 +192,7 @@ pub struct ArcWrapper<T: ArcWrapperBounds> {
     complete_signal: Arc<AtomicBool>,
     pool: Option<Arc<ThreadPool>>,
     pub store_path: Option<PathBuf>, // path to store when drop
+    spill_stats: Option<Arc<SpillStats>>,
 }
 impl<T: ArcWrapperBounds> ArcWrapper<T> {
     /// Create a new ArcWrapper
@@ -197,11 +202,16 @@ impl<T: ArcWrapperBounds> ArcWrapper<T> {
             complete_signal: share_flag,
             pool,
             store_path: None,
+            spill_stats: None,
         }
     }
     pub fn set_store_path(&mut self, path: PathBuf) {
         self.store_path = Some(path);
     }
+    /// Count the spill of the data to `store_path` in `stats`.
+    pub fn set_spill_stats(&mut self, stats: Arc<SpillStats>) {
+        self.spill_stats = Some(stats);
+    }
 }
 
 impl<T: ArcWrapperBounds> HeapSize for ArcWrapper<T> {
@@ -218,6 +228,7 @@ impl<T: ArcWrapperBounds> Clone for ArcWrapper<T> {
             complete_signal: self.complete_signal.clone(),
             pool: self.pool.clone(),
             store_path: None,
+            spill_stats: None,
         }
     }
 }
@@ -228,6 +239,18 @@ impl<T: ArcWrapperBounds> Deref for ArcWrapper<T> {
         &self.data
     }
 }
+/// Save `data` to `path` unless it is already there, and count it in `stats`.
+fn spill<T: ArcWrapperBounds>(data: &T, path: &Path, stats: Option<&SpillStats>) -> io::Result<()> {
+    if path.exists() {
+        return Ok(());
+    }
+    data.f_save(path)?;
+    if let Some(stats) = stats {
+        stats.record_spill(data.heap_size());
+    }
+    Ok(())
+}
+
 impl<T: ArcWrapperBounds> Drop for ArcWrapper<T> {
     // `drop` will be called in `lru_cache.insert()` when cache full & eject the LRU
     // `lru_cache.insert()` is protected by Mutex
@@ -239,6 +262,7 @@ impl<T: ArcWrapperBounds> Drop for ArcWrapper<T> {
                         let data_copy = self.data.clone();
                         let path_copy = path.clone();
                         let complete_signal = self.complete_signal.clone();
+                        let spill_stats = self.spill_stats.clone();
                         // block entire process, wait for IO, Control Memory
                         // queue size will influence the Memory usage
                         while pool.queued_count() > 2000 {
@@ -246,7 +270,7 @@ impl<T: ArcWrapperBounds> Drop for ArcWrapper<T> {
                         }
                         pool.execute(move || {
                             if !complete_signal.load(Ordering::SeqCst) {
-                                let res = data_copy.f_save(&path_copy);
+                                let res = spill(&*data_copy, &path_copy, spill_stats.as_deref());
                                 if let Err(e) = res {
                                     println!("[f_save] {:?} error: {:?}", path_copy, e);
                                 }
@@ -254,7 +278,7 @@ impl<T: ArcWrapperBounds> Drop for ArcWrapper<T> {
                         });
                     }
                     None => {
-                        let res = self.data.f_save(path);
+                        let res = spill(&*self.data, path, self.spill_stats.as_deref());
                         if let Err(e) = res {
                             println!("[f_save] {:?} error: {:?}", path, e);
                         }
@@ -293,11 +317,11 @@ mod test {
     fn test_cache_object_with_same_size() {
         let a = CacheObject {
             base_offset: 0,
-            base_ref: SHA1::new(&vec![0; 20]),
+            base_ref: SHA1::new(&[0; 20]),
             data_decompress: vec![0; 1024],
             obj_type: ObjectType::Blob,
             offset: 0,
-            hash: SHA1::new(&vec![0; 20]),
+            hash: SHA1::new(&[0; 20]),
             mem_recorder: None,
         };
         assert!(a.heap_size() == 1024);
@@ -311,22 +335,22 @@ mod test {
         let mut cache = LruCache::new(2048);
         let a = CacheObject {
             base_offset: 0,
-            base_ref: SHA1::new(&vec![0; 20]),
+            base_ref: SHA1::new(&[0; 20]),
             data_decompress: vec![0; 1024],
             obj_type: ObjectType::Blob,
             offset: 0,
-            hash: SHA1::new(&vec![0; 20]),
+            hash: SHA1::new(&[0; 20]),
             mem_recorder: None,
         };
         println!("a.heap_size() = {}", a.heap_size());
 
         let b = CacheObject {
             base_offset: 0,
-            base_ref: SHA1::new(&vec![0; 20]),
+            base_ref: SHA1::new(&[0; 20]),
             data_decompress: vec![0; (1024.0 * 1.5) as usize],
             obj_type: ObjectType::Blob,
             offset: 0,
-            hash: SHA1::new(&vec![1; 20]),
+            hash: SHA1::new(&[1; 20]),
             mem_recorder: None,
         };
         {
@@ -426,11 +450,11 @@ mod test {
     fn test_cache_object_serialize() {
         let a = CacheObject {
             base_offset: 0,
-            base_ref: SHA1::new(&vec![0; 20]),
+            base_ref: SHA1::new(&[0; 20]),
             data_decompress: vec![0; 1024],
             obj_type: ObjectType::Blob,
             offset: 0,
-            hash: SHA1::new(&vec![0; 20]),
+            hash: SHA1::new(&[0; 20]),
             mem_recorder: None,
         };
         let s = bincode::serialize(&a).unwrap();
@@ -1,31 +1,43 @@
 use std::io;
 use std::io::{BufRead, Read};
-use std::sync::mpsc::Receiver;
+
+use tokio::sync::mpsc::Receiver;
 
 /// Custom BufRead implementation that reads from the channel
+///
+/// The channel is bounded and filled by an async task, so a slow reader holds back the
+/// sender. It must be read in a blocking context, like `tokio::task::spawn_blocking`.
+/// An error sent through the channel is returned by the next read.
 pub(crate) struct ChannelReader {
-    receiver: Receiver<Vec<u8>>,
+    receiver: Receiver<io::Result<Vec<u8>>>,
     buffer: io::Cursor<Vec<u8>>,
 }
 
 impl ChannelReader {
-    pub(crate) fn new(receiver: Receiver<Vec<u8>>) -> Self {
+    pub(crate) fn new(receiver: Receiver<io::Result<Vec<u8>>>) -> Self {
         ChannelReader {
             receiver,
             buffer: io::Cursor::new(Vec::new()),
         }
     }
+
+    /// Receive the next chunk if the buffer has been read completely.
+    /// Returns `false` if the channel is closed.
+    fn fill_buffer(&mut self) -> io::Result<bool> {
+        while self.buffer.position() as usize == self.buffer.get_ref().len() {
+            match self.receiver.blocking_recv() {
+                Some(data) => self.buffer = io::Cursor::new(data?),
+                None => return Ok(false), // Channel is closed
+            }
+        }
+        Ok(true)
+    }
 }
 
 impl Read for ChannelReader {
     fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
-        if self.buffer.position() as usize == self.buffer.get_ref().len() { // buffer has been read completely
-            match self.receiver.recv() {
-                Ok(data) => {
-                    self.buffer = io::Cursor::new(data);
-                }
-                Err(_) => return Ok(0), // Channel is closed
-            }
+        if !self.fill_buffer()? {
+            return Ok(0);
         }
         self.buffer.read(buf)
     }
@@ -33,13 +45,8 @@ impl Read for ChannelReader {
 
 impl BufRead for ChannelReader {
     fn fill_buf(&mut self) -> io::Result<&[u8]> {
-        if self.buffer.position() as usize == self.buffer.get_ref().len() {
-            match self.receiver.recv() {
-                Ok(data) => {
-                    self.buffer = io::Cursor::new(data);
-                }
-                Err(_) => return Ok(&[]), // Channel is closed
-            }
+        if !self.fill_buffer()? {
+            return Ok(&[]);
         }
         self.buffer.fill_buf()
     }
@@ -47,4 +54,4 @@ impl BufRead for ChannelReader {
     fn consume(&mut self, amt: usize) {
         self.buffer.consume(amt);
     }
-}
\ No newline at end of file
+}
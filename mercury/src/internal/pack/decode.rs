@@ -1,10 +1,10 @@
 use std::io::{self, BufRead, Cursor, ErrorKind, Read};
 use std::path::PathBuf;
-use std::sync::atomic::{AtomicUsize, Ordering};
-use std::sync::{Arc, mpsc};
-use std::sync::mpsc::{Receiver, Sender};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Condvar, Mutex};
+use std::sync::mpsc::SyncSender;
 use std::thread::{self, JoinHandle};
-use std::time::Instant;
+use std::time::{Duration, Instant};
 use axum::Error;
 use bytes::Bytes;
 
@@ -13,26 +13,56 @@ use futures_util::{Stream, StreamExt};
 use threadpool::ThreadPool;
 
 use crate::errors::GitError;
-use crate::hash::SHA1;
+use crate::hash::{HashKind, SHA1};
 use crate::internal::object::types::ObjectType;
 
 use super::cache::_Cache;
-use crate::internal::pack::cache::Caches;
+use crate::internal::pack::cache::{Caches, SpillMetrics};
 use crate::internal::pack::cache_object::{CacheObject, MemSizeRecorder};
 use crate::internal::pack::waitlist::Waitlist;
 use crate::internal::pack::wrapper::Wrapper;
-use crate::internal::pack::{utils, Pack, DEFAULT_TMP_DIR};
+use crate::internal::pack::{utils, ObjectLookup, Pack, DEFAULT_TMP_DIR};
 use uuid::Uuid;
 use crate::internal::pack::channel_reader::ChannelReader;
 use crate::internal::pack::entry::Entry;
 
+/// The maximum number of tasks queued in the thread pool while decoding, to limit memory
+const MAX_QUEUED_TASKS: usize = 2000;
+
+/// The number of chunks of a stream buffered before the decoder, see [Pack::decode_stream]
+const STREAM_BUFFER_CHUNKS: usize = 64;
+
 /// For Convenient to pass Params
 struct SharedParams {
     pub pool: Arc<ThreadPool>,
     pub waitlist: Arc<Waitlist>,
     pub caches: Arc<Caches>,
     pub cache_objs_mem_size: Arc<AtomicUsize>,
-    pub callback: Arc<dyn Fn(Entry, usize) + Sync + Send>
+    pub callback: Arc<dyn Fn(Entry, usize) + Sync + Send>,
+    pub task_done: Arc<TaskSignal>,
+}
+
+/// Wakes the decoding thread when a task of the pool finishes, so it can wait for memory to
+/// be released instead of spinning.
+#[derive(Default)]
+struct TaskSignal {
+    lock: Mutex<()>,
+    cond: Condvar,
+}
+
+impl TaskSignal {
+    fn notify(&self) {
+        self.cond.notify_all();
+    }
+
+    /// Block until `ready` returns true. It is checked again when a task finishes, and at
+    /// least every 10 ms, as memory is also released by the threads of [Caches].
+    fn wait_until(&self, mut ready: impl FnMut() -> bool) {
+        let mut guard = self.lock.lock().unwrap();
+        while !ready() {
+            guard = self.cond.wait_timeout(guard, Duration::from_millis(10)).unwrap().0;
+        }
+    }
 }
 
 impl Drop for Pack {
@@ -77,9 +107,27 @@ impl Pack {
             mem_limit: mem_limit.unwrap_or(usize::MAX),
             cache_objs_mem: Arc::new(AtomicUsize::default()),
             clean_tmp,
+            base_lookup: None,
+            thin_bases: Vec::new(),
+            hash_kind: HashKind::default(),
         }
     }
 
+    /// Decode packs of repositories of the `kind` object format,
+    /// SHA-1 by default.
+    pub fn with_hash_kind(mut self, kind: HashKind) -> Self {
+        self.hash_kind = kind;
+        self.signature = SHA1::zero(kind);
+        self
+    }
+
+    /// Decode thin packs: the bases of ref deltas which are not in the pack are found by `lookup`.
+    /// Found bases are recorded in [`Pack::thin_bases`], but not passed to the callback.
+    pub fn with_base_lookup(mut self, lookup: ObjectLookup) -> Self {
+        self.base_lookup = Some(lookup);
+        self
+    }
+
     /// Checks and reads the header of a Git pack file.
     ///
     /// This function reads the first 12 bytes of a pack file, which include the "PACK" magic identifier,
@@ -272,10 +320,10 @@ impl Pack {
             ObjectType::Commit | ObjectType::Tree | ObjectType::Blob | ObjectType::Tag => {
                 let (data, raw_size) = self.decompress_data(pack, size)?;
                 *offset += raw_size;
-                Ok(CacheObject::new_for_undeltified(t, data, init_offset))
+                Ok(CacheObject::new_for_undeltified(t, data, init_offset, self.hash_kind))
             },
             ObjectType::OffsetDelta => {
-                let (delta_offset, bytes) = utils::read_offset_encoding(pack).unwrap();
+                let (delta_offset, bytes) = utils::read_offset_encoding(pack)?;
                 *offset += bytes;
 
                 let (data, raw_size) = self.decompress_data(pack, size)?;
@@ -286,8 +334,7 @@ impl Pack {
                     .checked_sub(delta_offset as usize)
                     .ok_or_else(|| {
                         GitError::InvalidObjectInfo("Invalid OffsetDelta offset".to_string())
-                    })
-                    .unwrap();
+                    })?;
 
                 Ok(CacheObject {
                     base_offset,
@@ -299,12 +346,11 @@ impl Pack {
                 })
             },
             ObjectType::HashDelta => {
-                // Read 20 bytes to get the reference object SHA1 hash
-                let mut buf_ref = [0; 20];
-                pack.read_exact(&mut buf_ref).unwrap();
-                let ref_sha1 = SHA1::from_bytes(buf_ref.as_ref()); //TODO SHA1::from_stream()
-                // Offset is incremented by 20 bytes
-                *offset += 20; //TODO 改为常量
+                // Read 20 bytes (32 for SHA-256) to get the reference object hash
+                let mut buf_ref = vec![0; self.hash_kind.size()];
+                pack.read_exact(&mut buf_ref)?;
+                let ref_sha1 = SHA1::from_bytes(&buf_ref)?;
+                *offset += buf_ref.len();
 
                 let (data, raw_size) = self.decompress_data(pack, size)?;
                 *offset += raw_size;
@@ -325,21 +371,33 @@ impl Pack {
     ///
     ///
     pub fn decode<F>(&mut self, pack: &mut (impl BufRead + Send), callback: F) -> Result<(), GitError>
+    where
+        F: Fn(Entry, usize) + Sync + Send + 'static
+    {
+        self.decode_until(pack, callback, Arc::new(AtomicBool::new(false)))
+    }
+
+    /// Decode the pack like [`Pack::decode`], but stop with an error once `stopped` is set,
+    /// e.g. by the callback when no one receives the entries any more.
+    fn decode_until<F>(&mut self, pack: &mut (impl BufRead + Send), callback: F, stopped: Arc<AtomicBool>) -> Result<(), GitError>
     where
         F: Fn(Entry, usize) + Sync + Send + 'static
     {
         let time = Instant::now();
         let mut last_update_time = time.elapsed().as_millis();
         let log_info = |_i: usize, pack: &Pack| {
-            tracing::info!("time {:.2} s \t decode: {:?} \t dec-num: {} \t cah-num: {} \t Objs: {} MB \t CacheUsed: {} MB",
+            let spill = pack.spill_metrics();
+            tracing::info!("time {:.2} s \t decode: {:?} \t dec-num: {} \t cah-num: {} \t Objs: {} MB \t CacheUsed: {} MB \t Spilled: {} ({} MB) \t Loaded: {}",
                 time.elapsed().as_millis() as f64 / 1000.0, _i, pack.pool.queued_count(), pack.caches.queued_tasks(),
                 pack.cache_objs_mem_used() / 1024 / 1024,
-                pack.caches.memory_used() / 1024 / 1024);
+                pack.caches.memory_used() / 1024 / 1024,
+                spill.spilled_objects, spill.spilled_bytes / 1024 / 1024, spill.loaded_objects);
         };
-        let callback = Arc::new(callback);
+        let callback: Arc<dyn Fn(Entry, usize) + Sync + Send> = Arc::new(callback);
+        let task_done = Arc::new(TaskSignal::default());
 
         let caches = self.caches.clone();
-        let mut reader = Wrapper::new(io::BufReader::new(pack));
+        let mut reader = Wrapper::with_hash_kind(io::BufReader::new(pack), self.hash_kind);
 
         let result = Pack::check_header(&mut reader);
         match result {
@@ -364,8 +422,12 @@ impl Pack {
             }
             // 3 parts: Waitlist + TheadPool + Caches
             // hardcode the limit of the tasks of threads_pool queue, to limit memory
-            while self.memory_used() > self.mem_limit || self.pool.queued_count() > 2000 {
-                thread::yield_now();
+            task_done.wait_until(|| {
+                self.memory_used() <= self.mem_limit && self.pool.queued_count() <= MAX_QUEUED_TASKS
+            });
+            if stopped.load(Ordering::Relaxed) {
+                self.pool.join();
+                return Err(GitError::CustomError("the receiver of the entries is closed".to_string()));
             }
             let r: Result<CacheObject, GitError> = self.decode_pack_object(&mut reader, &mut offset);
             match r {
@@ -379,12 +441,14 @@ impl Pack {
                         waitlist: self.waitlist.clone(),
                         caches: self.caches.clone(),
                         cache_objs_mem_size: self.cache_objs_mem.clone(),
-                        callback: callback.clone()
+                        callback: callback.clone(),
+                        task_done: task_done.clone(),
                     });
 
                     let caches = caches.clone();
                     let waitlist = self.waitlist.clone();
                     self.pool.execute(move || {
+                        let task_done = params.task_done.clone();
                         match obj.obj_type {
                             ObjectType::Commit | ObjectType::Tree | ObjectType::Blob | ObjectType::Tag => {
                                 Self::cache_obj_and_process_waitlist(params, obj);
@@ -415,6 +479,7 @@ impl Pack {
                                 }
                             }
                         }
+                        task_done.notify();
                     });
                 },
                 Err(e) => {
@@ -425,9 +490,9 @@ impl Pack {
         }
         log_info(i, self);
         let render_hash = reader.final_hash();
-        let mut trailer_buf = [0; 20];
-        reader.read_exact(&mut trailer_buf).unwrap();
-        self.signature = SHA1::from_bytes(trailer_buf.as_ref());
+        let mut trailer_buf = vec![0; self.hash_kind.size()];
+        reader.read_exact(&mut trailer_buf)?;
+        self.signature = SHA1::from_bytes(&trailer_buf)?;
 
         if render_hash != self.signature {
             return Err(GitError::InvalidPackFile(format!(
@@ -445,12 +510,15 @@ impl Pack {
         }
 
         self.pool.join(); // wait for all threads to finish
+        if !self.waitlist.map_ref.is_empty() {
+            self.resolve_thin_bases(callback)?;
+        }
         // !Attention: Caches threadpool may not stop, but it's not a problem (garbage file data)
         // So that files != self.number
         assert_eq!(self.waitlist.map_offset.len(), 0);
-        assert_eq!(self.waitlist.map_ref.len(), 0);
         assert_eq!(self.number, caches.total_inserted());
-        tracing::info!("The pack file has been decoded successfully, takes: [ {:?} ]", time.elapsed());
+        tracing::info!("The pack file has been decoded successfully, takes: [ {:?} ], spill: {:?}",
+            time.elapsed(), self.spill_metrics());
         self.caches.clear(); // clear cached objects & stop threads
         assert_eq!(self.cache_objs_mem_used(), 0); // all the objs should be dropped until here
 
@@ -462,40 +530,109 @@ impl Pack {
         Ok(())
     }
 
+    /// Rebuild the ref deltas whose bases are not in the pack, with the bases found by `base_lookup`.
+    fn resolve_thin_bases(&mut self, callback: Arc<dyn Fn(Entry, usize) + Sync + Send>) -> Result<(), GitError> {
+        let missing: Vec<SHA1> = self.waitlist.map_ref.iter().map(|item| *item.key()).collect();
+        let lookup = self.base_lookup.clone().ok_or_else(|| {
+            GitError::InvalidPackFile(format!("thin pack, the delta base {} is missing", missing[0]))
+        })?;
+        let params = Arc::new(SharedParams {
+            pool: self.pool.clone(),
+            waitlist: self.waitlist.clone(),
+            caches: self.caches.clone(),
+            cache_objs_mem_size: self.cache_objs_mem.clone(),
+            callback,
+            task_done: Arc::new(TaskSignal::default()),
+        });
+        for hash in missing {
+            let entry = lookup(hash).ok_or_else(|| {
+                GitError::InvalidPackFile(format!("thin pack, the delta base {} is missing", hash))
+            })?;
+            // offset 0 is the pack header, so no delta waits for it
+            let base = CacheObject::new_for_undeltified(entry.obj_type, entry.data, 0, hash.kind());
+            if base.hash != hash {
+                return Err(GitError::InvalidPackFile(format!("the delta base {} is corrupted", hash)));
+            }
+            self.thin_bases.push(hash);
+            Self::process_waitlist(params.clone(), Arc::new(base));
+        }
+        self.pool.join();
+        Ok(())
+    }
+
     /// Decode Pack in a new thread and send the CacheObjects while decoding.
     /// <br> Attention: It will consume the `pack` and return in JoinHandle
-    pub fn decode_async(mut self, mut pack: (impl BufRead + Send + 'static), sender: Sender<Entry>) -> JoinHandle<Pack> {
+    /// <br> `sender` should be bounded, decoding waits for the receiver when it is full,
+    /// and stops with an error once the receiver is dropped.
+    pub fn decode_async(mut self, mut pack: (impl BufRead + Send + 'static), sender: SyncSender<Entry>) -> JoinHandle<Result<Pack, GitError>> {
         thread::spawn(move || {
-            self.decode(&mut pack, move |entry, _| {
-                sender.send(entry).unwrap();
-            }).unwrap();
-            self
+            let stopped = Arc::new(AtomicBool::new(false));
+            let stopped_c = stopped.clone();
+            self.decode_until(&mut pack, move |entry, _| {
+                // stop decoding when the receiver is gone
+                if sender.send(entry).is_err() {
+                    stopped_c.store(true, Ordering::Relaxed);
+                }
+            }, stopped)?;
+            Ok(self)
         })
     }
 
     /// Decode `Pack` with inputting a `Stream` of `Bytes`, and send the `Entry` while decoding.
+    ///
+    /// The stream is only polled as fast as the pack is decoded, and decoding waits for the
+    /// receiver of the bounded `sender`, and stops once it is dropped. A failure of the stream is
+    /// returned as a [GitError::NetworkError].
     pub async fn decode_stream(mut self,
                                mut stream: impl Stream<Item = Result<Bytes, Error>> + Unpin + Send + 'static,
-                               sender: Sender<Entry>)
-        -> Self
+                               sender: SyncSender<Entry>)
+        -> Result<Self, GitError>
     {
-        let (tx, rx): (Sender<Vec<u8>>, Receiver<Vec<u8>>) = mpsc::channel();
+        let (tx, rx) = tokio::sync::mpsc::channel(STREAM_BUFFER_CHUNKS);
         let mut reader = ChannelReader::new(rx);
+        let stream_error = Arc::new(Mutex::new(None));
+        let stream_error_c = stream_error.clone();
         tokio::spawn(async move {
             // use Channel to connect `async` & `sync`
             while let Some(chunk) = stream.next().await {
-                let data = chunk.unwrap().to_vec();
-                tx.send(data).unwrap();
+                let data = match chunk {
+                    Ok(data) => Ok(data.to_vec()),
+                    Err(e) => {
+                        *stream_error_c.lock().unwrap() = Some(e.to_string());
+                        Err(io::Error::new(ErrorKind::ConnectionAborted, e))
+                    }
+                };
+                let failed = data.is_err();
+                // the decoder has stopped if the channel is closed
+                if tx.send(data).await.is_err() || failed {
+                    break;
+                }
             }
         });
         // CPU-bound task, so use spawn_blocking
         // DO NOT use thread::spawn, because it will block tokio runtime (if single-threaded runtime, like in tests)
-        tokio::task::spawn_blocking(move || {
-            self.decode(&mut reader, move |entry, _| {
-                sender.send(entry).unwrap();
-            }).unwrap();
-            self
-        }).await.unwrap()
+        let result = tokio::task::spawn_blocking(move || {
+            let stopped = Arc::new(AtomicBool::new(false));
+            let stopped_c = stopped.clone();
+            self.decode_until(&mut reader, move |entry, _| {
+                if sender.send(entry).is_err() {
+                    stopped_c.store(true, Ordering::Relaxed);
+                }
+            }, stopped)?;
+            Ok(self)
+        })
+        .await
+        .map_err(|e| GitError::CustomError(format!("pack decoding panicked: {}", e)))?;
+        let stream_error = stream_error.lock().unwrap().take();
+        match stream_error {
+            Some(err) if result.is_err() => Err(GitError::NetworkError(err)),
+            _ => result,
+        }
+    }
+
+    /// The objects spilled to the temp dir of the caches so far, when they exceed the memory limit.
+    pub fn spill_metrics(&self) -> SpillMetrics {
+        self.caches.spill_metrics()
     }
 
     /// CacheObjects + Index size of Caches
@@ -512,10 +649,12 @@ impl Pack {
     /// <br> This function must be *static*, because [&self] can't be moved into a new thread.
     fn process_delta(shared_params: Arc<SharedParams>, delta_obj: CacheObject, base_obj: Arc<CacheObject>) {
         shared_params.pool.clone().execute(move || {
+            let task_done = shared_params.task_done.clone();
             let mut new_obj = Pack::rebuild_delta(delta_obj, base_obj);
             new_obj.set_mem_recorder(shared_params.cache_objs_mem_size.clone());
             new_obj.record_mem_size();
             Self::cache_obj_and_process_waitlist(shared_params, new_obj); //Indirect Recursion
+            task_done.notify();
         });
     }
 
@@ -607,7 +746,8 @@ impl Pack {
         }
         assert_eq!(result_size, result.len() as u64);
 
-        let hash = utils::calculate_object_hash(base_obj.obj_type, &result);
+        // the object has the same hash algorithm as its base
+        let hash = utils::calculate_object_hash(base_obj.hash.kind(), base_obj.obj_type, &result);
         // create new obj from `delta_obj` & `result` instead of modifying `delta_obj` for heap-size recording
         CacheObject {
             data_decompress: result,
@@ -626,6 +766,7 @@ mod tests {
     use std::io::prelude::*;
     use std::io::BufReader;
     use std::io::Cursor;
+    use std::io::{self, ErrorKind};
     use std::{env, path::PathBuf};
     use std::sync::Arc;
     use std::sync::atomic::{AtomicUsize, Ordering};
@@ -635,7 +776,9 @@ mod tests {
     use tokio_util::io::ReaderStream;
     use tracing_subscriber::util::SubscriberInitExt;
 
+    use crate::errors::GitError;
     use crate::internal::pack::Pack;
+    use bytes::Bytes;
     use futures_util::TryStreamExt;
 
     fn init_logger() {
@@ -753,7 +896,7 @@ mod tests {
         });
         let p = Pack::new(Some(20), Some(1024*1024*1024*2), Some(tmp.clone()), true);
 
-        let (tx, rx) = std::sync::mpsc::channel();
+        let (tx, rx) = std::sync::mpsc::sync_channel(1000);
         let handle = tokio::spawn(async move {
             p.decode_stream(stream, tx).await
         });
@@ -769,10 +912,28 @@ mod tests {
             tracing::info!("Received: {}", cnt);
             count_c.store(cnt, Ordering::Relaxed);
         }).await.unwrap();
-        let p = handle.await.unwrap();
+        let p = handle.await.unwrap().unwrap();
         assert_eq!(count.load(Ordering::Relaxed), p.number);
     }
 
+    #[tokio::test]
+    async fn test_decode_stream_error() {
+        // the header of a pack with one object, then the stream fails or ends
+        let header = Bytes::from_static(b"PACK\x00\x00\x00\x02\x00\x00\x00\x01");
+        let error = axum::Error::new(io::Error::new(ErrorKind::ConnectionReset, "connection reset"));
+        let decode = |chunks: Vec<Result<Bytes, axum::Error>>| async move {
+            let tmp = PathBuf::from("/tmp/.cache_temp_stream_error");
+            let p = Pack::new(Some(2), Some(1024 * 1024), Some(tmp), true);
+            let (tx, _rx) = std::sync::mpsc::sync_channel(10);
+            p.decode_stream(futures_util::stream::iter(chunks), tx).await
+        };
+
+        let result = decode(vec![Ok(header.clone()), Err(error)]).await;
+        assert!(matches!(result, Err(GitError::NetworkError(_))));
+        let result = decode(vec![Ok(header)]).await;
+        assert!(result.is_err());
+    }
+
     #[test]
     fn test_decode_large_file_async() {
         let mut source = PathBuf::from(env::current_dir().unwrap().parent().unwrap());
@@ -783,16 +944,32 @@ mod tests {
         let buffered = BufReader::new(f);
         let p = Pack::new(Some(20), Some(1024*1024*1024*2), Some(tmp.clone()), true);
 
-        let (tx, rx) = std::sync::mpsc::channel();
+        let (tx, rx) = std::sync::mpsc::sync_channel(1000);
         let handle = p.decode_async(buffered, tx); // new thread
         let mut cnt = 0;
         for _entry in rx {
             cnt += 1; //use entry here
         }
-        let p = handle.join().unwrap();
+        let p = handle.join().unwrap().unwrap();
         assert_eq!(cnt, p.number);
     }
 
+    #[test]
+    fn test_decode_async_receiver_closed() {
+        let mut source = PathBuf::from(env::current_dir().unwrap().parent().unwrap());
+        source.push("tests/data/packs/git-2d187177923cd618a75da6c6db45bb89d92bd504.pack");
+
+        let tmp = PathBuf::from("/tmp/.cache_temp_receiver_closed");
+        let f = fs::File::open(source).unwrap();
+        let buffered = BufReader::new(f);
+        let p = Pack::new(Some(2), Some(1024*1024*1024), Some(tmp), true);
+
+        let (tx, rx) = std::sync::mpsc::sync_channel(10);
+        drop(rx);
+        let handle = p.decode_async(buffered, tx);
+        assert!(handle.join().unwrap().is_err());
+    }
+
     #[test]
     fn test_pack_decode_with_delta_without_ref() {
         let mut source = PathBuf::from(env::current_dir().unwrap().parent().unwrap());
@@ -1,41 +1,242 @@
-use std::collections::VecDeque;
-use std::io::Write;
+use std::collections::{HashMap, VecDeque};
+use std::fs::{self, File};
+use std::io::{BufReader, BufWriter, Cursor, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
 
+use async_trait::async_trait;
+use delta::DeltaIndex;
 use flate2::write::ZlibEncoder;
-use sha1::{Digest, Sha1};
+use serde::{Deserialize, Serialize};
 use tokio::sync::mpsc;
 use tokio::task::JoinHandle;
 
+use crate::hash::{HashKind, ObjectHasher, SHA1};
+use crate::internal::object::blob::Blob;
+use crate::internal::object::commit::Commit;
+use crate::internal::object::tag::Tag;
+use crate::internal::object::tree::Tree;
 use crate::internal::object::types::ObjectType;
-use crate::{errors::GitError, hash::SHA1, internal::pack::entry::Entry};
+use crate::internal::pack::utils::read_varint_le;
+use crate::{errors::GitError, internal::pack::entry::Entry};
 
-const MIN_DELTA_RATE: f64 = 0.5; // minimum delta rate can accept
+/// Default maximum length of delta chains, the same as git's `pack.depth`.
+pub const DEFAULT_DELTA_DEPTH: usize = 50;
+
+/// Objects larger than this are never deltified, nor used as delta bases.
+const MAX_DELTA_OBJECT_SIZE: usize = 64 * 1024 * 1024;
+
+/// Objects are buffered and sorted for delta search until they take this much memory.
+const SORT_BATCH_MEMORY: usize = 256 * 1024 * 1024;
+
+/// Default memory for the objects held before the pack header is written, the same as
+/// [`PackEncoder::with_spill`] with 1 GB.
+pub const DEFAULT_ENCODE_MEM_SIZE: usize = 1024 * 1024 * 1024;
+
+/// An object to be packed, with the hash of the path it was found at.
+///
+/// Objects are sorted by the name hash before the delta search, so that versions of the same
+/// file end up in the same window. Objects without a known path have a hash of zero.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct EncodeEntry {
+    pub entry: Entry,
+    pub name_hash: u32,
+    /// The receiver of a thin pack has this object already, so it is only used as a delta base,
+    /// and deltas against it are written as ref deltas.
+    pub thin_base: bool,
+}
+
+impl EncodeEntry {
+    pub fn with_name(entry: Entry, name: &str) -> Self {
+        EncodeEntry {
+            entry,
+            name_hash: name_hash(name),
+            thin_base: false,
+        }
+    }
+
+    /// An object which the receiver has, see [`EncodeEntry::thin_base`].
+    /// It isn't counted in the number of objects of the pack.
+    pub fn thin_base(entry: Entry, name: &str) -> Self {
+        EncodeEntry {
+            thin_base: true,
+            ..EncodeEntry::with_name(entry, name)
+        }
+    }
+}
+
+impl From<Entry> for EncodeEntry {
+    fn from(entry: Entry) -> Self {
+        EncodeEntry {
+            entry,
+            name_hash: 0,
+            thin_base: false,
+        }
+    }
+}
+
+impl From<Blob> for EncodeEntry {
+    fn from(value: Blob) -> Self {
+        Entry::from(value).into()
+    }
+}
+
+impl From<Tree> for EncodeEntry {
+    fn from(value: Tree) -> Self {
+        Entry::from(value).into()
+    }
+}
+
+impl From<Commit> for EncodeEntry {
+    fn from(value: Commit) -> Self {
+        Entry::from(value).into()
+    }
+}
+
+impl From<Tag> for EncodeEntry {
+    fn from(value: Tag) -> Self {
+        Entry::from(value).into()
+    }
+}
+
+/// A delta of an object as it is stored, e.g. in a pack received before.
+pub struct StoredDelta {
+    /// the object the delta applies to
+    pub base: SHA1,
+    /// the delta instructions, uncompressed
+    pub data: Vec<u8>,
+}
+
+/// Where the encoder finds the deltas it can send again instead of searching new ones.
+#[async_trait]
+pub trait DeltaStore: Send + Sync {
+    /// Called with the ids of each batch of objects before their deltas are asked for, so
+    /// the store can look them up at once. Only the objects of the batches are asked for.
+    async fn prepare(&self, _ids: &[SHA1]) {}
+
+    /// The stored delta of the object `id`, `None` if it isn't stored as a delta.
+    async fn stored_delta(&self, id: &SHA1) -> Option<StoredDelta>;
+}
+
+/// Hash of a file name which sorts files with the same suffix close to each other,
+/// the same as `pack_name_hash` of git: the last characters take the most significant bits.
+pub fn name_hash(name: &str) -> u32 {
+    name.bytes()
+        .filter(|c| !c.is_ascii_whitespace())
+        .fold(0u32, |hash, c| (hash >> 2).wrapping_add((c as u32) << 24))
+}
+
+/// An object written recently, which may be the base of a delta.
+struct WindowEntry {
+    entry: Entry,
+    /// `None` for thin bases, which aren't written
+    offset: Option<usize>,
+    /// length of the delta chain of this object, 0 if it isn't a delta
+    depth: usize,
+    /// built when the object is first tried as a base
+    index: Option<DeltaIndex>,
+}
+
+/// A sorted batch of objects, waiting for the header of the pack to be written.
+enum Batch {
+    Memory(Vec<EncodeEntry>),
+    /// spilled to a file as bincode encoded entries, with the number of entries
+    File(PathBuf, usize),
+}
 
 /// A encoder for generating pack files with delta objects.
+///
+/// The number of objects is only known once all of them are received, so they are gathered
+/// before the header is written, and spilled to disk past the memory bound of
+/// [`PackEncoder::with_spill`].
+///
+/// With a window size of 0, objects are written in the order they are received. Otherwise
+/// they are sorted by type, name hash and size, and each one is tried as a delta against the
+/// objects in the window, i.e. the last `window_size` objects written. With a
+/// [`DeltaStore`], the stored delta of an object is sent as it is when its base is written
+/// before it, and the window is only searched for the other objects.
 pub struct PackEncoder {
     object_number: usize,
-    process_index: usize,
     window_size: usize,
-    window: VecDeque<(Entry, usize)>, // entry and offset
+    max_depth: usize,
+    window: VecDeque<WindowEntry>,
+    delta_store: Option<Arc<dyn DeltaStore>>,
+    /// offset and delta depth of the objects written, kept for the stored deltas
+    written: HashMap<SHA1, (Option<usize>, usize)>,
+    pending: Vec<EncodeEntry>,
+    pending_size: usize,
+    batches: Vec<Batch>,
+    /// memory taken by the batches in `batches` which aren't spilled
+    batch_memory: usize,
+    mem_limit: usize,
+    spill_path: PathBuf,
     sender: Option<mpsc::Sender<Vec<u8>>>,
-    inner_offset: usize, // offset of current entry
-    inner_hash: Sha1,    // Not SHA1 because need update trait
+    inner_offset: usize,      // offset of current entry
+    inner_hash: ObjectHasher, // Not SHA1 because need update trait
     final_hash: Option<SHA1>,
     start_encoding: bool,
 }
 
 /// Encode header of pack file (12 byte)<br>
 /// Content: 'PACK', Version(2), number of objects
-fn encode_header(object_number: usize) -> Vec<u8> {
+fn encode_header(object_number: usize) -> Result<Vec<u8>, GitError> {
+    let object_number = u32::try_from(object_number).map_err(|_| {
+        GitError::PackEncodeError(format!(
+            "a pack can't hold {} objects, the limit is {}",
+            object_number,
+            u32::MAX
+        ))
+    })?;
     let mut result: Vec<u8> = vec![
         b'P', b'A', b'C', b'K', // The logotype of the Pack File
         0, 0, 0, 2, // generates version 2 only.
     ];
-    assert_ne!(object_number, 0); // guarantee self.number_of_objects!=0
-    assert!(object_number < (1 << 32));
-    //TODO: GitError:numbers of objects should < 4G ,
-    result.append((object_number as u32).to_be_bytes().to_vec().as_mut()); // to 4 bytes (network byte order aka. big-endian)
-    result
+    result.extend(object_number.to_be_bytes()); // to 4 bytes (network byte order aka. big-endian)
+    Ok(result)
+}
+
+/// Base of a delta object.
+enum DeltaBase {
+    /// relative offset of a base in the pack
+    Offset(usize),
+    /// hash of a base which isn't in the pack
+    Hash(SHA1),
+}
+
+/// Encode the type and size header of an object
+fn encode_object_header(obj_type: ObjectType, size: usize) -> Vec<u8> {
+    let mut header_data = vec![(0x80 | (obj_type.to_u8() << 4)) + (size & 0x0f) as u8];
+    let mut size = size >> 4; // 4 bit has been used in first byte
+    if size > 0 {
+        while size > 0 {
+            if size >> 7 > 0 {
+                header_data.push((0x80 | size) as u8);
+                size >>= 7;
+            } else {
+                header_data.push(size as u8);
+                break;
+            }
+        }
+    } else {
+        header_data.push(0);
+    }
+    header_data
+}
+
+fn zlib_compress(data: &[u8]) -> Vec<u8> {
+    let mut inflate = ZlibEncoder::new(Vec::new(), flate2::Compression::default());
+    inflate
+        .write_all(data)
+        .expect("zlib compress should never failed");
+    inflate.flush().expect("zlib flush should never failed");
+    inflate.finish().expect("zlib compress should never failed")
+}
+
+/// Encode a whole, undeltified object as it is stored in a pack file.
+pub fn encode_pack_object(entry: &Entry) -> Vec<u8> {
+    let mut data = encode_object_header(entry.obj_type, entry.data.len());
+    data.extend(zlib_compress(&entry.data));
+    data
 }
 
 /// Encode offset of delta object
@@ -59,28 +260,66 @@ fn encode_offset(mut value: usize) -> Vec<u8> {
 }
 
 impl PackEncoder {
-    pub fn new(object_number: usize, window_size: usize, sender: mpsc::Sender<Vec<u8>>) -> Self {
+    pub fn new(window_size: usize, sender: mpsc::Sender<Vec<u8>>) -> Self {
         PackEncoder {
-            object_number,
+            object_number: 0,
             window_size,
-            process_index: 0,
+            max_depth: DEFAULT_DELTA_DEPTH,
             window: VecDeque::with_capacity(window_size),
+            delta_store: None,
+            written: HashMap::new(),
+            pending: Vec::new(),
+            pending_size: 0,
+            batches: Vec::new(),
+            batch_memory: 0,
+            mem_limit: DEFAULT_ENCODE_MEM_SIZE,
+            spill_path: std::env::temp_dir().join("mercury-encode"),
             sender: Some(sender),
             inner_offset: 12, // 12 bytes header
-            inner_hash: Sha1::new(),
+            inner_hash: ObjectHasher::new(HashKind::default()),
             final_hash: None,
             start_encoding: false,
         }
     }
 
+    /// Write the checksum of the pack with the algorithm of `kind`,
+    /// SHA-1 by default.
+    pub fn with_hash_kind(mut self, kind: HashKind) -> Self {
+        self.inner_hash = ObjectHasher::new(kind);
+        self
+    }
+
+    /// Set the maximum length of delta chains, [`DEFAULT_DELTA_DEPTH`] by default.
+    pub fn with_max_depth(mut self, max_depth: usize) -> Self {
+        self.max_depth = max_depth;
+        self
+    }
+
+    /// Send the deltas of `store` again instead of searching new ones, see [`DeltaStore`].
+    pub fn with_delta_store(mut self, store: Arc<dyn DeltaStore>) -> Self {
+        self.delta_store = Some(store);
+        self
+    }
+
+    /// Hold at most `mem_size` bytes of objects in memory while gathering them, the others
+    /// are spilled to files in `spill_path`.
+    pub fn with_spill(mut self, mem_size: usize, spill_path: PathBuf) -> Self {
+        self.mem_limit = mem_size;
+        self.spill_path = spill_path;
+        self
+    }
+
     pub fn drop_sender(&mut self) {
         self.sender.take(); // Take the sender out, dropping it
     }
 
-    pub async fn send_data(&mut self, data: Vec<u8>) {
+    pub async fn send_data(&mut self, data: Vec<u8>) -> Result<(), GitError> {
         if let Some(sender) = &self.sender {
-            sender.send(data).await.unwrap();
+            sender.send(data).await.map_err(|_| {
+                GitError::PackEncodeError("the receiver of the pack is closed".to_string())
+            })?;
         }
+        Ok(())
     }
 
     /// Get the hash of the pack file. if the pack file is not finished, return None
@@ -89,157 +328,321 @@ impl PackEncoder {
     }
 
     /// Encodes entries into a pack file with delta objects and outputs them through the specified writer.
+    ///
+    /// Nothing is written until `entry_rx` is closed, as the header holds the number of objects.
     /// # Arguments
-    /// - `rx` - A receiver channel (`mpsc::Receiver<Entry>`) from which entries to be encoded are received.
+    /// - `rx` - A receiver channel (`mpsc::Receiver<Entry>` or `mpsc::Receiver<EncodeEntry>`) from which entries to be encoded are received.
     /// # Returns
     /// Returns `Ok(())` if encoding is successful, or a `GitError` in case of failure.
     /// - Returns a `GitError` if there is a failure during the encoding process.
     /// - Returns `PackEncodeError` if an encoding operation is already in progress.
-    pub async fn encode(&mut self, mut entry_rx: mpsc::Receiver<Entry>) -> Result<(), GitError> {
-        let head = encode_header(self.object_number);
-        self.send_data(head.clone()).await;
-        self.inner_hash.update(&head);
-
+    pub async fn encode<T: Into<EncodeEntry>>(
+        &mut self,
+        mut entry_rx: mpsc::Receiver<T>,
+    ) -> Result<(), GitError> {
         // ensure only one decode can only invoke once
         if self.start_encoding {
             return Err(GitError::PackEncodeError(
                 "encoding operation is already in progress".to_string(),
             ));
         }
-        loop {
-            match entry_rx.recv().await {
-                Some(entry) => {
-                    self.process_index += 1;
-                    // push window after encode to void diff by self
-                    let offset = self.inner_offset;
-                    self.encode_one_object(&entry).await?;
-                    self.window.push_back((entry, offset));
-                    if self.window.len() > self.window_size {
-                        self.window.pop_front();
-                    }
-                }
-                None => {
-                    if self.process_index != self.object_number {
-                        panic!(
-                            "not all objects are encoded, process:{}, total:{}",
-                            self.process_index, self.object_number
-                        );
-                    }
-                    break;
-                }
+        self.start_encoding = true;
+
+        let batch_limit = SORT_BATCH_MEMORY.min(self.mem_limit);
+        while let Some(entry) = entry_rx.recv().await {
+            let entry: EncodeEntry = entry.into();
+            if !entry.thin_base {
+                self.object_number += 1;
+            } else if self.window_size == 0 && self.delta_store.is_none() {
+                // thin bases are only used by the delta search
+                continue;
+            }
+            self.pending_size += entry.entry.data.len();
+            self.pending.push(entry);
+            if self.pending_size >= batch_limit {
+                self.finish_batch()?;
             }
         }
+        self.finish_batch()?;
+
+        let head = encode_header(self.object_number)?;
+        self.inner_hash.update(&head);
+        self.send_data(head).await?;
+        while !self.batches.is_empty() {
+            let entries = match self.batches.remove(0) {
+                Batch::Memory(entries) => entries,
+                Batch::File(path, len) => {
+                    let entries = read_batch(&path, len);
+                    fs::remove_file(&path)?;
+                    entries?
+                }
+            };
+            self.encode_batch(entries).await?;
+        }
 
         // hash signature
         let hash_result = self.inner_hash.clone().finalize();
-        self.final_hash = Some(SHA1::from_bytes(&hash_result));
-        self.send_data((hash_result).to_vec()).await;
+        self.final_hash = Some(hash_result);
+        self.send_data(hash_result.to_data()).await?;
         self.drop_sender();
         Ok(())
     }
 
-    /// Try to encode as delta using objects in window
-    /// # Returns
-    /// - Return (delta entry, offset) if success make delta
-    /// - Return (origin Entry,None) if didn't delta,
-    fn try_as_offset_delta(&mut self, entry: &Entry) -> (Entry, Option<usize>) {
-        let mut best_base: Option<&(Entry, usize)> = None;
-        let mut best_rate: f64 = 0.0;
-        for try_base in self.window.iter() {
-            if try_base.0.obj_type != entry.obj_type {
+    /// Sort the buffered objects, and keep them in memory or spill them to a file.
+    ///
+    /// Similar objects end up next to each other: same type, same file name, and larger ones
+    /// first, as a delta which removes data is smaller than one which adds it. Thin bases go
+    /// before the objects of the same name, as they can only be used as bases.
+    fn finish_batch(&mut self) -> Result<(), GitError> {
+        if self.pending.is_empty() {
+            return Ok(());
+        }
+        let mut pending = std::mem::take(&mut self.pending);
+        let size = std::mem::take(&mut self.pending_size);
+        if self.window_size > 0 {
+            pending.sort_by(|a, b| {
+                a.entry
+                    .obj_type
+                    .to_u8()
+                    .cmp(&b.entry.obj_type.to_u8())
+                    .then(a.name_hash.cmp(&b.name_hash))
+                    .then(b.thin_base.cmp(&a.thin_base))
+                    .then(b.entry.data.len().cmp(&a.entry.data.len()))
+            });
+        }
+        if self.batch_memory + size <= self.mem_limit {
+            self.batch_memory += size;
+            self.batches.push(Batch::Memory(pending));
+            return Ok(());
+        }
+        fs::create_dir_all(&self.spill_path)?;
+        let path = self
+            .spill_path
+            .join(format!("{}.batch", uuid::Uuid::new_v4()));
+        // pushed first, so the file is removed on drop even if writing fails
+        self.batches.push(Batch::File(path.clone(), pending.len()));
+        let mut writer = BufWriter::new(File::create(&path)?);
+        for entry in &pending {
+            bincode::serialize_into(&mut writer, entry)
+                .map_err(|e| GitError::PackEncodeError(e.to_string()))?;
+        }
+        writer.flush()?;
+        Ok(())
+    }
+
+    /// Encode a sorted batch of objects with delta search.
+    async fn encode_batch(&mut self, batch: Vec<EncodeEntry>) -> Result<(), GitError> {
+        if let Some(store) = &self.delta_store {
+            let ids: Vec<SHA1> = batch
+                .iter()
+                .filter(|item| !item.thin_base)
+                .map(|item| item.entry.hash)
+                .collect();
+            store.prepare(&ids).await;
+        }
+        for item in batch {
+            let entry = item.entry;
+            let offset = self.inner_offset;
+            let depth = if item.thin_base {
+                0
+            } else if let Some((base, depth, delta)) = self.reuse_delta(&entry).await {
+                let obj_type = match base {
+                    DeltaBase::Offset(_) => ObjectType::OffsetDelta,
+                    DeltaBase::Hash(_) => ObjectType::HashDelta,
+                };
+                self.write_object(obj_type, &delta, Some(base)).await?;
+                depth
+            } else {
+                match self.find_delta(&entry) {
+                    Some((base, delta)) => {
+                        let base = &self.window[base];
+                        let depth = base.depth + 1;
+                        match base.offset {
+                            Some(base_offset) => {
+                                let base = DeltaBase::Offset(offset - base_offset);
+                                self.write_object(ObjectType::OffsetDelta, &delta, Some(base))
+                                    .await?
+                            }
+                            None => {
+                                let base = DeltaBase::Hash(base.entry.hash);
+                                self.write_object(ObjectType::HashDelta, &delta, Some(base))
+                                    .await?
+                            }
+                        }
+                        depth
+                    }
+                    None => {
+                        self.write_object(entry.obj_type, &entry.data, None).await?;
+                        0
+                    }
+                }
+            };
+            if self.delta_store.is_some() {
+                let offset = (!item.thin_base).then_some(offset);
+                self.written.insert(entry.hash, (offset, depth));
+            }
+            if self.window_size == 0 {
                 continue;
             }
-            let rate = delta::encode_rate(&try_base.0.data, &entry.data);
-            if rate > MIN_DELTA_RATE && rate > best_rate {
-                best_rate = rate;
-                best_base = Some(try_base);
+            // push window after encode to void diff by self
+            self.window.push_back(WindowEntry {
+                entry,
+                offset: (!item.thin_base).then_some(offset),
+                depth,
+                index: None,
+            });
+            if self.window.len() > self.window_size {
+                self.window.pop_front();
             }
         }
-        if best_rate > 0.0 {
-            let best_base = best_base.unwrap(); // must some if best rate > 0
-            let delta = delta::encode(&best_base.0.data, &entry.data);
-            let offset = self.inner_offset - best_base.1;
-            (
-                Entry {
-                    data: delta,
-                    obj_type: ObjectType::OffsetDelta,
-                    ..entry.clone()
-                },
-                Some(offset),
-            )
-        } else {
-            (entry.clone(), None)
+        Ok(())
+    }
+
+    /// The stored delta of `entry`, if its base is written already and the delta chain
+    /// stays below the maximum depth.
+    /// # Returns
+    /// - Return (base of the delta, depth of the delta, delta data) if the delta can be sent
+    async fn reuse_delta(&mut self, entry: &Entry) -> Option<(DeltaBase, usize, Vec<u8>)> {
+        let store = self.delta_store.as_ref()?;
+        let stored = store.stored_delta(&entry.hash).await?;
+        let &(base_offset, base_depth) = self.written.get(&stored.base)?;
+        if base_depth >= self.max_depth {
+            return None;
+        }
+        // the size of the base, then the size of the object
+        let mut header = Cursor::new(&stored.data);
+        read_varint_le(&mut header).ok()?;
+        let (size, _) = read_varint_le(&mut header).ok()?;
+        if size != entry.data.len() as u64 {
+            tracing::warn!("the stored delta of {} has a wrong size", entry.hash);
+            return None;
+        }
+        let base = match base_offset {
+            Some(base_offset) => DeltaBase::Offset(self.inner_offset - base_offset),
+            None => DeltaBase::Hash(stored.base),
+        };
+        Some((base, base_depth + 1, stored.data))
+    }
+
+    /// Find the base in the window which gives the smallest delta.
+    /// # Returns
+    /// - Return (index of the base in window, delta data) if a delta is worth it
+    /// - Return None if the object should be stored as it is
+    fn find_delta(&mut self, entry: &Entry) -> Option<(usize, Vec<u8>)> {
+        let size = entry.data.len();
+        if size > MAX_DELTA_OBJECT_SIZE {
+            return None;
+        }
+        let max_depth = self.max_depth;
+        let mut best: Option<(usize, Vec<u8>)> = None;
+        // the most recent objects are the most similar ones after sorting
+        for (i, base) in self.window.iter_mut().enumerate().rev() {
+            if base.entry.obj_type != entry.obj_type
+                || base.depth >= max_depth
+                || base.entry.data.len() > MAX_DELTA_OBJECT_SIZE
+            {
+                continue;
+            }
+            // a delta must save at least 1/8 of the object, and be smaller than the best one
+            let max_size = match &best {
+                Some((_, delta)) => delta.len() - 1,
+                None => size - size / 8,
+            };
+            // the grown part of the object can only be inserted
+            if size.saturating_sub(base.entry.data.len()) >= max_size {
+                continue;
+            }
+            let index = base
+                .index
+                .get_or_insert_with(|| DeltaIndex::new(&base.entry.data));
+            if let Some(delta) = index.encode(&base.entry.data, &entry.data, max_size) {
+                best = Some((i, delta));
+            }
         }
+        best
     }
 
     /// Write data to writer and update hash & offset
-    async fn write_all_and_update(&mut self, data: &[u8]) {
+    async fn write_all_and_update(&mut self, data: &[u8]) -> Result<(), GitError> {
         self.inner_hash.update(data);
         self.inner_offset += data.len();
-        self.send_data(data.to_vec()).await;
+        self.send_data(data.to_vec()).await
     }
 
-    /// Encode one object, and update the hash
-    async fn encode_one_object(&mut self, entry: &Entry) -> Result<(), GitError> {
-        // try encode as delta
-        let (entry, offset) = self.try_as_offset_delta(entry);
-        let obj_data = entry.data;
-        let obj_data_len = obj_data.len();
-        let obj_type_number = entry.obj_type.to_u8();
-
+    /// Write one object, `base` is the base of delta objects.
+    async fn write_object(
+        &mut self,
+        obj_type: ObjectType,
+        obj_data: &[u8],
+        base: Option<DeltaBase>,
+    ) -> Result<(), GitError> {
         // **header** encoding
-        let mut header_data = vec![(0x80 | (obj_type_number << 4)) + (obj_data_len & 0x0f) as u8];
-        let mut size = obj_data_len >> 4; // 4 bit has been used in first byte
-        if size > 0 {
-            while size > 0 {
-                if size >> 7 > 0 {
-                    header_data.push((0x80 | size) as u8);
-                    size >>= 7;
-                } else {
-                    header_data.push(size as u8);
-                    break;
-                }
-            }
-        } else {
-            header_data.push(0);
-        }
-        self.write_all_and_update(&header_data).await;
+        let header_data = encode_object_header(obj_type, obj_data.len());
+        self.write_all_and_update(&header_data).await?;
 
-        // **offset** encoding
-        if entry.obj_type == ObjectType::OffsetDelta {
-            let offset_data = encode_offset(offset.unwrap());
-            self.write_all_and_update(&offset_data).await;
-        } else if entry.obj_type == ObjectType::HashDelta {
-            unreachable!("unsupported type")
+        // **base** encoding
+        match base {
+            Some(DeltaBase::Offset(offset)) => {
+                self.write_all_and_update(&encode_offset(offset)).await?
+            }
+            Some(DeltaBase::Hash(hash)) => self.write_all_and_update(hash.as_bytes()).await?,
+            None => {}
         }
 
         // **data** encoding, need zlib compress
-        let mut inflate = ZlibEncoder::new(Vec::new(), flate2::Compression::default());
-        inflate
-            .write_all(&obj_data)
-            .expect("zlib compress should never failed");
-        inflate.flush().expect("zlib flush should never failed");
-        let compressed_data = inflate.finish().expect("zlib compress should never failed");
-        self.write_all_and_update(&compressed_data).await;
-        Ok(())
+        let compressed_data = zlib_compress(obj_data);
+        self.write_all_and_update(&compressed_data).await
     }
 
     /// async version of encode, result data will be returned by JoinHandle.
     /// It will consume PackEncoder, so you can't use it after calling this function.
-    pub async fn encode_async(
+    ///
+    /// If encoding fails the error is logged, and the pack stream ends without a checksum.
+    pub async fn encode_async<T>(
         mut self,
-        rx: mpsc::Receiver<Entry>,
-    ) -> Result<JoinHandle<()>, GitError> {
-        Ok(tokio::spawn(async move { self.encode(rx).await.unwrap() }))
+        rx: mpsc::Receiver<T>,
+    ) -> Result<JoinHandle<Result<(), GitError>>, GitError>
+    where
+        T: Into<EncodeEntry> + Send + 'static,
+    {
+        Ok(tokio::spawn(async move {
+            let res = self.encode(rx).await;
+            if let Err(err) = &res {
+                tracing::error!("failed to encode pack: {}", err);
+            }
+            res
+        }))
     }
 }
 
+impl Drop for PackEncoder {
+    fn drop(&mut self) {
+        for batch in &self.batches {
+            if let Batch::File(path, _) = batch {
+                let _ = fs::remove_file(path);
+            }
+        }
+    }
+}
+
+/// Read back a batch spilled by [`PackEncoder::finish_batch`].
+fn read_batch(path: &Path, len: usize) -> Result<Vec<EncodeEntry>, GitError> {
+    let mut reader = BufReader::new(File::open(path)?);
+    (0..len)
+        .map(|_| {
+            bincode::deserialize_from(&mut reader)
+                .map_err(|e| GitError::PackEncodeError(e.to_string()))
+        })
+        .collect()
+}
+
 #[cfg(test)]
 mod tests {
+    use std::sync::{Arc, Mutex};
     use std::{io::Cursor, path::PathBuf};
 
     use crate::internal::object::blob::Blob;
-    use crate::internal::pack::Pack;
+    use crate::internal::pack::{ObjectLookup, Pack};
 
     use super::*;
 
@@ -251,7 +654,7 @@ mod tests {
 
             // make some different objects, or decode will fail
             let str_vec = vec!["hello, code,", "hello, world.", "!", "123141251251"];
-            let encoder = PackEncoder::new(str_vec.len(), window_size, tx);
+            let encoder = PackEncoder::new(window_size, tx);
             encoder.encode_async(entry_rx).await.unwrap();
 
             for str in str_vec {
@@ -290,6 +693,282 @@ mod tests {
         check_format(pack_with_delta);
     }
 
+    #[tokio::test]
+    async fn test_pack_encoder_delta_search() {
+        async fn encode_entries(entries: Vec<EncodeEntry>, window_size: usize) -> Vec<u8> {
+            let (tx, mut rx) = mpsc::channel(100);
+            let (entry_tx, entry_rx) = mpsc::channel::<EncodeEntry>(100);
+            let encoder = PackEncoder::new(window_size, tx).with_max_depth(2);
+            encoder.encode_async(entry_rx).await.unwrap();
+            for entry in entries {
+                entry_tx.send(entry).await.unwrap();
+            }
+            drop(entry_tx);
+            let mut result = Vec::new();
+            while let Some(chunk) = rx.recv().await {
+                result.extend(chunk);
+            }
+            result
+        }
+
+        // versions of two files, interleaved so that similar objects are not adjacent
+        let content: String = (0..2000).map(|i| format!("line {}\n", i)).collect();
+        let mut entries = vec![];
+        for i in 0..6 {
+            let file = Blob::from_content(&format!("{}changed {}\n", content, i));
+            entries.push(EncodeEntry::with_name(file.into(), "src/file.txt"));
+            let other = Blob::from_content(&format!("{}other {}\n", content.to_uppercase(), i));
+            entries.push(EncodeEntry::with_name(other.into(), "src/other.txt"));
+        }
+        let mut expected: Vec<SHA1> = entries.iter().map(|e| e.entry.hash).collect();
+        expected.sort();
+
+        let pack_without_delta = encode_entries(entries.clone(), 0).await;
+        let pack_with_delta = encode_entries(entries, 10).await;
+        assert!(pack_with_delta.len() * 3 < pack_without_delta.len());
+
+        let decoded = Arc::new(Mutex::new(vec![]));
+        let decoded_c = decoded.clone();
+        let cache_path = PathBuf::from("/tmp/.cache_temp_delta_search");
+        let mut p = Pack::new(None, Some(1024 * 1024), Some(cache_path), true);
+        p.decode(&mut Cursor::new(pack_with_delta), move |entry, _| {
+            decoded_c.lock().unwrap().push(entry.hash);
+        })
+        .expect("pack file format error");
+        let mut decoded = decoded.lock().unwrap().clone();
+        decoded.sort();
+        assert_eq!(decoded, expected);
+    }
+
+    #[tokio::test]
+    async fn test_pack_encoder_reuse_delta() {
+        struct Store(Blob, Blob, Mutex<Vec<SHA1>>);
+
+        #[async_trait]
+        impl DeltaStore for Store {
+            async fn prepare(&self, ids: &[SHA1]) {
+                self.2.lock().unwrap().extend_from_slice(ids);
+            }
+
+            async fn stored_delta(&self, id: &SHA1) -> Option<StoredDelta> {
+                // only the objects of a batch are asked for, once it is prepared
+                if *id != self.1.id || !self.2.lock().unwrap().contains(id) {
+                    return None;
+                }
+                let index = DeltaIndex::new(&self.0.data);
+                let data = index.encode(&self.0.data, &self.1.data, usize::MAX)?;
+                Some(StoredDelta {
+                    base: self.0.id,
+                    data,
+                })
+            }
+        }
+
+        let content: String = (0..2000).map(|i| format!("line {}\n", i)).collect();
+        let base = Blob::from_content(&content);
+        let target = Blob::from_content(&format!("{}appended\n", content));
+        let store = Arc::new(Store(base.clone(), target.clone(), Mutex::new(vec![])));
+
+        // no delta search, the target is only a delta if the stored one is sent
+        let (tx, mut rx) = mpsc::channel(100);
+        let (entry_tx, entry_rx) = mpsc::channel::<Entry>(100);
+        let encoder = PackEncoder::new(0, tx).with_delta_store(store);
+        encoder.encode_async(entry_rx).await.unwrap();
+        entry_tx.send(base.clone().into()).await.unwrap();
+        entry_tx.send(target.clone().into()).await.unwrap();
+        drop(entry_tx);
+        let mut pack_data = Vec::new();
+        while let Some(chunk) = rx.recv().await {
+            pack_data.extend(chunk);
+        }
+        let base_size = zlib_compress(&base.data).len();
+        assert!(pack_data.len() < base_size + base_size / 10);
+
+        let decoded = Arc::new(Mutex::new(vec![]));
+        let decoded_c = decoded.clone();
+        let cache_path = PathBuf::from("/tmp/.cache_temp_reuse_delta");
+        let mut p = Pack::new(None, Some(1024 * 1024), Some(cache_path), true);
+        p.decode(&mut Cursor::new(pack_data), move |entry, _| {
+            decoded_c.lock().unwrap().push(entry.hash);
+        })
+        .expect("pack file format error");
+        let mut decoded = decoded.lock().unwrap().clone();
+        decoded.sort();
+        let mut expected = vec![base.id, target.id];
+        expected.sort();
+        assert_eq!(decoded, expected);
+    }
+
+    #[tokio::test]
+    async fn test_pack_encoder_thin_pack() {
+        let content: String = (0..2000).map(|i| format!("line {}\n", i)).collect();
+        let base = Blob::from_content(&content);
+        let target = Blob::from_content(&format!("{}appended\n", content));
+
+        let (tx, mut rx) = mpsc::channel(100);
+        let (entry_tx, entry_rx) = mpsc::channel::<EncodeEntry>(100);
+        let encoder = PackEncoder::new(10, tx);
+        encoder.encode_async(entry_rx).await.unwrap();
+        let base_entry = EncodeEntry::thin_base(base.clone().into(), "file.txt");
+        entry_tx.send(base_entry).await.unwrap();
+        let target_entry = EncodeEntry::with_name(target.clone().into(), "file.txt");
+        entry_tx.send(target_entry).await.unwrap();
+        drop(entry_tx);
+        let mut pack_data = Vec::new();
+        while let Some(chunk) = rx.recv().await {
+            pack_data.extend(chunk);
+        }
+        // the base is not in the pack
+        assert!(pack_data.len() < content.len() / 10);
+
+        let decode = |lookup: Option<ObjectLookup>| {
+            let decoded = Arc::new(Mutex::new(vec![]));
+            let decoded_c = decoded.clone();
+            let cache_path = PathBuf::from("/tmp/.cache_temp_thin_pack");
+            let mut p = Pack::new(None, Some(1024 * 1024), Some(cache_path), true);
+            if let Some(lookup) = lookup {
+                p = p.with_base_lookup(lookup);
+            }
+            p.decode(&mut Cursor::new(pack_data.clone()), move |entry, _| {
+                decoded_c.lock().unwrap().push(entry.hash);
+            })?;
+            assert_eq!(p.thin_bases, vec![base.id]);
+            let decoded = decoded.lock().unwrap().clone();
+            Ok::<_, GitError>(decoded)
+        };
+        assert!(decode(None).is_err());
+        let base_c = base.clone();
+        let lookup: ObjectLookup =
+            Arc::new(move |hash| (hash == base_c.id).then(|| base_c.clone().into()));
+        assert_eq!(decode(Some(lookup)).unwrap(), vec![target.id]);
+    }
+
+    #[tokio::test]
+    async fn test_pack_encoder_sha256() {
+        let blob = |content: String| {
+            let data = content.into_bytes();
+            Entry {
+                obj_type: ObjectType::Blob,
+                hash: SHA1::from_type_and_data_with_kind(HashKind::Sha256, ObjectType::Blob, &data),
+                data,
+            }
+        };
+        let content: String = (0..2000).map(|i| format!("line {}\n", i)).collect();
+        let base = blob(content.clone());
+        let targets = [
+            blob(format!("{}appended\n", content)),
+            blob(format!("{}appended again\n", content)),
+        ];
+
+        let (tx, mut rx) = mpsc::channel(100);
+        let (entry_tx, entry_rx) = mpsc::channel::<EncodeEntry>(100);
+        let encoder = PackEncoder::new(10, tx).with_hash_kind(HashKind::Sha256);
+        encoder.encode_async(entry_rx).await.unwrap();
+        let base_entry = EncodeEntry::thin_base(base.clone(), "file.txt");
+        entry_tx.send(base_entry).await.unwrap();
+        for target in &targets {
+            let entry = EncodeEntry::with_name(target.clone(), "file.txt");
+            entry_tx.send(entry).await.unwrap();
+        }
+        drop(entry_tx);
+        let mut pack_data = Vec::new();
+        while let Some(chunk) = rx.recv().await {
+            pack_data.extend(chunk);
+        }
+        // both are deltas: a ref delta to the 32-byte id of the base, then an offset delta
+        assert!(pack_data.len() < content.len() / 10);
+
+        let decoded = Arc::new(Mutex::new(vec![]));
+        let decoded_c = decoded.clone();
+        let base_c = base.clone();
+        let lookup: ObjectLookup =
+            Arc::new(move |hash| (hash == base_c.hash).then(|| base_c.clone()));
+        let cache_path = PathBuf::from("/tmp/.cache_temp_sha256");
+        let mut p = Pack::new(None, Some(1024 * 1024), Some(cache_path), true)
+            .with_hash_kind(HashKind::Sha256)
+            .with_base_lookup(lookup);
+        p.decode(&mut Cursor::new(pack_data), move |entry, _| {
+            decoded_c.lock().unwrap().push(entry.hash);
+        })
+        .expect("pack file format error");
+        assert_eq!(p.signature.kind(), HashKind::Sha256);
+        assert_eq!(p.thin_bases, vec![base.hash]);
+        let mut decoded = decoded.lock().unwrap().clone();
+        decoded.sort();
+        let mut expected: Vec<SHA1> = targets.iter().map(|e| e.hash).collect();
+        expected.sort();
+        assert_eq!(decoded, expected);
+    }
+
+    #[tokio::test]
+    async fn test_pack_encoder_spill() {
+        let content: String = (0..2000).map(|i| format!("line {}\n", i)).collect();
+        let entries: Vec<Entry> = (0..5)
+            .map(|i| Blob::from_content(&format!("{}changed {}\n", content, i)).into())
+            .collect();
+        let mut expected: Vec<SHA1> = entries.iter().map(|e| e.hash).collect();
+        expected.sort();
+
+        // every object is spilled as soon as it is received
+        let spill_path = PathBuf::from("/tmp/.encode_spill_temp");
+        let (tx, mut rx) = mpsc::channel(100);
+        let (entry_tx, entry_rx) = mpsc::channel::<Entry>(100);
+        let encoder = PackEncoder::new(10, tx).with_spill(0, spill_path.clone());
+        let handle = encoder.encode_async(entry_rx).await.unwrap();
+        for entry in entries {
+            entry_tx.send(entry).await.unwrap();
+        }
+        drop(entry_tx);
+        let mut pack_data = Vec::new();
+        while let Some(chunk) = rx.recv().await {
+            pack_data.extend(chunk);
+        }
+        handle.await.unwrap().unwrap();
+        assert_eq!(fs::read_dir(&spill_path).unwrap().count(), 0);
+        // the window is kept across batches
+        assert!(pack_data.len() < content.len());
+
+        let decoded = Arc::new(Mutex::new(vec![]));
+        let decoded_c = decoded.clone();
+        let cache_path = PathBuf::from("/tmp/.cache_temp_spill");
+        let mut p = Pack::new(None, Some(1024 * 1024), Some(cache_path), true);
+        p.decode(&mut Cursor::new(pack_data), move |entry, _| {
+            decoded_c.lock().unwrap().push(entry.hash);
+        })
+        .expect("pack file format error");
+        let mut decoded = decoded.lock().unwrap().clone();
+        decoded.sort();
+        assert_eq!(decoded, expected);
+    }
+
+    #[tokio::test]
+    async fn test_pack_encoder_closed_receiver() {
+        let (tx, rx) = mpsc::channel(100);
+        drop(rx);
+        let (entry_tx, entry_rx) = mpsc::channel::<Entry>(100);
+        let encoder = PackEncoder::new(10, tx);
+        let handle = encoder.encode_async(entry_rx).await.unwrap();
+        entry_tx
+            .send(Blob::from_content("hello").into())
+            .await
+            .unwrap();
+        drop(entry_tx);
+        assert!(matches!(
+            handle.await.unwrap(),
+            Err(GitError::PackEncodeError(_))
+        ));
+    }
+
+    #[test]
+    fn test_name_hash() {
+        // files with the same suffix are close to each other
+        let a = name_hash("a.rs");
+        let b = name_hash("b.rs");
+        let c = name_hash("a.md");
+        assert!(a.abs_diff(b) < a.abs_diff(c));
+        assert_eq!(name_hash(" a.rs"), a);
+    }
+
     #[test]
     fn test_encode_offset() {
         let value = 11013;
@@ -0,0 +1,553 @@
+//!
+//! Pack index (`.idx`) and reverse index (`.rev`) files, see [pack-format](https://git-scm.com/docs/pack-format).
+//!
+use std::collections::BTreeMap;
+use std::io::Read;
+use std::path::Path;
+
+use crate::errors::GitError;
+use crate::hash::{HashKind, ObjectHash, SHA1};
+
+/// Magic number of index files of version 2 and later, `\377tOc`.
+const IDX_SIGNATURE: [u8; 4] = [0xff, b't', b'O', b'c'];
+
+/// Magic number of reverse index files.
+const RIDX_SIGNATURE: &[u8; 4] = b"RIDX";
+
+/// Size of the fan-out table.
+const FANOUT: usize = 256 * 4;
+
+/// Offsets of version 2 which don't fit in 31 bits are stored in a table of 8-byte offsets.
+const LARGE_OFFSET_FLAG: u32 = 0x8000_0000;
+
+/// The fan-out table of objects sorted by id.
+/// N-th entry records the number of objects whose first byte is less than or equal to N.
+pub(crate) fn fan_out<'a>(hashes: impl Iterator<Item = &'a SHA1>) -> Vec<u8> {
+    let mut fan_out = [0u32; 256];
+    for hash in hashes {
+        fan_out[hash.as_bytes()[0] as usize] += 1;
+    }
+    let mut data = Vec::with_capacity(FANOUT);
+    let mut cnt = 0;
+    for n in fan_out {
+        cnt += n;
+        data.extend_from_slice(&cnt.to_be_bytes());
+    }
+    data
+}
+
+/// Append the checksum of all of the data.
+pub(crate) fn append_checksum(data: &mut Vec<u8>, kind: HashKind) {
+    let checksum = ObjectHash::new_with_kind(kind, data);
+    data.extend_from_slice(checksum.as_bytes());
+}
+
+/// Check the checksum at the end of the data.
+pub(crate) fn verify_checksum(data: &[u8], kind: HashKind) -> Result<(), GitError> {
+    let (content, checksum) = data.split_at(data.len() - kind.size());
+    if ObjectHash::new_with_kind(kind, content).as_bytes() != checksum {
+        return Err(GitError::InvalidIdxFile("checksum mismatch".to_string()));
+    }
+    Ok(())
+}
+
+/// The id of the hash function in `.rev` and `multi-pack-index` files.
+pub(crate) fn hash_function_id(kind: HashKind) -> u8 {
+    match kind {
+        HashKind::Sha1 => 1,
+        HashKind::Sha256 => 2,
+    }
+}
+
+pub(crate) fn read_u32(data: &[u8], pos: usize) -> u32 {
+    u32::from_be_bytes(data[pos..pos + 4].try_into().unwrap())
+}
+
+pub(crate) fn read_u64(data: &[u8], pos: usize) -> u64 {
+    u64::from_be_bytes(data[pos..pos + 8].try_into().unwrap())
+}
+
+/// Build a version 1 index for a pack.
+///
+/// `objects` maps every object id in the pack to its offset from the beginning of the pack file,
+/// `pack_hash` is the checksum at the end of the pack file.
+/// Offsets of version 1 are 32 bits, so the pack must be smaller than 4 GiB.
+/// Ids are of the algorithm of `pack_hash`.
+pub fn build_index_v1(objects: &BTreeMap<SHA1, usize>, pack_hash: &SHA1) -> Vec<u8> {
+    let hash_size = pack_hash.size();
+    let mut index = Vec::with_capacity(FANOUT + objects.len() * (4 + hash_size) + 2 * hash_size);
+
+    // fan-out table
+    // The header consists of 256 4-byte network byte order integers.
+    // N-th entry of this table records the number of objects in the corresponding pack,
+    // the first byte of whose object name is less than or equal to N.
+    // This is called the first-level fan-out table.
+    index.extend(fan_out(objects.keys()));
+
+    // 4-byte network byte order integer, recording where the
+    // object is stored in the pack-file as the offset from the beginning.
+    // one object name of the appropriate size (20 bytes).
+    for (hash, offset) in objects {
+        index.extend_from_slice(&(*offset as u32).to_be_bytes());
+        index.extend_from_slice(hash.as_bytes());
+    }
+
+    // A copy of the pack checksum at the end of the corresponding pack-file.
+    index.extend_from_slice(pack_hash.as_bytes());
+    // Index checksum of all of the above.
+    append_checksum(&mut index, pack_hash.kind());
+    index
+}
+
+/// Build a version 2 index for a pack, which addresses packs of any size.
+///
+/// `objects` and `pack_hash` are the same as for [`build_index_v1`]. The pack file of
+/// `pack_size` bytes is read from `pack`, to compute the CRC32 of every packed object.
+pub fn build_index_v2(
+    objects: &BTreeMap<SHA1, usize>,
+    pack_hash: &SHA1,
+    pack: impl Read,
+    pack_size: usize,
+) -> Result<Vec<u8>, GitError> {
+    let kind = pack_hash.kind();
+    let end = pack_size
+        .checked_sub(kind.size())
+        .ok_or_else(|| GitError::InvalidPackFile("pack is truncated".to_string()))?;
+    let crc32 = packed_object_crc32(objects, pack, end)?;
+
+    let mut index =
+        Vec::with_capacity(8 + FANOUT + objects.len() * (8 + kind.size()) + 2 * kind.size());
+    index.extend_from_slice(&IDX_SIGNATURE);
+    index.extend_from_slice(&2u32.to_be_bytes());
+    index.extend(fan_out(objects.keys()));
+    for hash in objects.keys() {
+        index.extend_from_slice(hash.as_bytes());
+    }
+    for hash in objects.keys() {
+        index.extend_from_slice(&crc32[hash].to_be_bytes());
+    }
+    // offsets of 31 bits, or the position in the table of 8-byte offsets with the MSB set
+    let mut large_offsets = Vec::new();
+    for offset in objects.values() {
+        let offset = *offset as u64;
+        if offset < LARGE_OFFSET_FLAG as u64 {
+            index.extend_from_slice(&(offset as u32).to_be_bytes());
+        } else {
+            let position = (large_offsets.len() / 8) as u32;
+            index.extend_from_slice(&(LARGE_OFFSET_FLAG | position).to_be_bytes());
+            large_offsets.extend_from_slice(&offset.to_be_bytes());
+        }
+    }
+    index.extend(large_offsets);
+    index.extend_from_slice(pack_hash.as_bytes());
+    append_checksum(&mut index, kind);
+    Ok(index)
+}
+
+/// CRC32 of the packed data of every object, which spans from its offset to the next object,
+/// or to the checksum at the end of the pack, at `end`.
+fn packed_object_crc32(
+    objects: &BTreeMap<SHA1, usize>,
+    mut pack: impl Read,
+    end: usize,
+) -> Result<BTreeMap<SHA1, u32>, GitError> {
+    let mut by_offset: Vec<(usize, &SHA1)> = objects.iter().map(|(h, o)| (*o, h)).collect();
+    by_offset.sort();
+
+    let mut header = [0u8; 12];
+    pack.read_exact(&mut header)?;
+    let mut position = header.len();
+    let mut crc32 = BTreeMap::new();
+    let mut buf = vec![0u8; 64 * 1024];
+    for (i, (offset, hash)) in by_offset.iter().enumerate() {
+        let next = by_offset.get(i + 1).map_or(end, |(next, _)| *next);
+        if *offset != position || next <= *offset {
+            return Err(GitError::InvalidPackFile(format!(
+                "object {} at offset {} doesn't follow the previous one",
+                hash, offset
+            )));
+        }
+        let mut hasher = crc32fast::Hasher::new();
+        let mut remaining = next - offset;
+        while remaining > 0 {
+            let n = remaining.min(buf.len());
+            pack.read_exact(&mut buf[..n])?;
+            hasher.update(&buf[..n]);
+            remaining -= n;
+        }
+        position = next;
+        crc32.insert(**hash, hasher.finalize());
+    }
+    Ok(crc32)
+}
+
+/// A pack index of version 1 or 2, read into memory.
+///
+/// Index files don't record the hash algorithm, it's the object format of the repository.
+pub struct PackIndex {
+    data: Vec<u8>,
+    version: u32,
+    num_objects: usize,
+    kind: HashKind,
+}
+
+impl PackIndex {
+    /// Read the index of a pack of the `kind` object format.
+    pub fn open(path: &Path, kind: HashKind) -> Result<Self, GitError> {
+        Self::from_bytes_with_kind(std::fs::read(path)?, kind)
+    }
+
+    /// Read an index of a SHA-1 pack, see [`PackIndex::from_bytes_with_kind`] for the others.
+    pub fn from_bytes(data: Vec<u8>) -> Result<Self, GitError> {
+        Self::from_bytes_with_kind(data, HashKind::Sha1)
+    }
+
+    pub fn from_bytes_with_kind(data: Vec<u8>, kind: HashKind) -> Result<Self, GitError> {
+        let hash_size = kind.size();
+        let version = if data.len() >= 8 && data[..4] == IDX_SIGNATURE {
+            read_u32(&data, 4)
+        } else {
+            1
+        };
+        let (fanout_start, entry_size) = match version {
+            1 => (0, 4 + hash_size),
+            2 => (8, 8 + hash_size),
+            _ => {
+                return Err(GitError::InvalidIdxFile(format!(
+                    "unsupported index version {}",
+                    version
+                )))
+            }
+        };
+        if data.len() < fanout_start + FANOUT + 2 * hash_size {
+            return Err(GitError::InvalidIdxFile("index is truncated".to_string()));
+        }
+        let num_objects = read_u32(&data, fanout_start + 255 * 4) as usize;
+        let min_size = fanout_start + FANOUT + num_objects * entry_size + 2 * hash_size;
+        let valid_size = match version {
+            1 => data.len() == min_size,
+            // followed by the 8-byte offsets
+            _ => data.len() >= min_size && (data.len() - min_size).is_multiple_of(8),
+        };
+        if !valid_size {
+            return Err(GitError::InvalidIdxFile(format!(
+                "index of {} objects has a wrong size {}",
+                num_objects,
+                data.len()
+            )));
+        }
+        verify_checksum(&data, kind)?;
+        Ok(PackIndex {
+            data,
+            version,
+            num_objects,
+            kind,
+        })
+    }
+
+    pub fn version(&self) -> u32 {
+        self.version
+    }
+
+    pub fn hash_kind(&self) -> HashKind {
+        self.kind
+    }
+
+    /// Number of objects in the pack.
+    pub fn len(&self) -> usize {
+        self.num_objects
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.num_objects == 0
+    }
+
+    /// The checksum of the pack file which this index is built for.
+    pub fn pack_hash(&self) -> SHA1 {
+        let hash_size = self.kind.size();
+        let end = self.data.len() - hash_size;
+        SHA1::from_bytes(&self.data[end - hash_size..end]).unwrap()
+    }
+
+    fn fanout(&self, n: usize) -> usize {
+        let start = if self.version == 1 { 0 } else { 8 };
+        read_u32(&self.data, start + n * 4) as usize
+    }
+
+    /// The id of the object at position `n`, objects are sorted by id.
+    pub fn hash(&self, n: usize) -> SHA1 {
+        let hash_size = self.kind.size();
+        let pos = match self.version {
+            1 => FANOUT + n * (4 + hash_size) + 4,
+            _ => 8 + FANOUT + n * hash_size,
+        };
+        SHA1::from_bytes(&self.data[pos..pos + hash_size]).unwrap()
+    }
+
+    /// The offset in the pack of the object at position `n`.
+    pub fn offset(&self, n: usize) -> u64 {
+        let hash_size = self.kind.size();
+        if self.version == 1 {
+            return read_u32(&self.data, FANOUT + n * (4 + hash_size)) as u64;
+        }
+        let offsets_start = 8 + FANOUT + self.num_objects * (4 + hash_size);
+        let offset = read_u32(&self.data, offsets_start + n * 4);
+        if offset & LARGE_OFFSET_FLAG == 0 {
+            return offset as u64;
+        }
+        let large_start = offsets_start + self.num_objects * 4;
+        let position = (offset & !LARGE_OFFSET_FLAG) as usize;
+        read_u64(&self.data, large_start + position * 8)
+    }
+
+    /// The CRC32 of the packed data of the object at position `n`, only in version 2.
+    pub fn crc32(&self, n: usize) -> Option<u32> {
+        let crc32_start = 8 + FANOUT + self.num_objects * self.kind.size();
+        (self.version == 2).then(|| read_u32(&self.data, crc32_start + n * 4))
+    }
+
+    /// The position of the object in the index, found by a binary search.
+    pub fn position(&self, hash: &SHA1) -> Option<usize> {
+        let first_byte = hash.as_bytes()[0] as usize;
+        let mut low = if first_byte == 0 {
+            0
+        } else {
+            self.fanout(first_byte - 1)
+        };
+        let mut high = self.fanout(first_byte);
+        while low < high {
+            let mid = (low + high) / 2;
+            match self.hash(mid).cmp(hash) {
+                std::cmp::Ordering::Equal => return Some(mid),
+                std::cmp::Ordering::Less => low = mid + 1,
+                std::cmp::Ordering::Greater => high = mid,
+            }
+        }
+        None
+    }
+
+    /// The offset of the object in the pack.
+    pub fn find(&self, hash: &SHA1) -> Option<u64> {
+        self.position(hash).map(|n| self.offset(n))
+    }
+
+    /// The ids of all objects, sorted.
+    pub fn hashes(&self) -> impl Iterator<Item = SHA1> + '_ {
+        (0..self.num_objects).map(|n| self.hash(n))
+    }
+}
+
+/// The reverse index of a pack: the positions in the index of the objects, sorted by offset.
+///
+/// It gives the object following an object in the pack, so the size of packed objects.
+pub struct ReverseIndex {
+    positions: Vec<u32>,
+}
+
+impl ReverseIndex {
+    pub fn build(index: &PackIndex) -> Self {
+        let mut positions: Vec<u32> = (0..index.len() as u32).collect();
+        positions.sort_by_key(|n| index.offset(*n as usize));
+        ReverseIndex { positions }
+    }
+
+    pub fn open(path: &Path, index: &PackIndex) -> Result<Self, GitError> {
+        Self::from_bytes(&std::fs::read(path)?, index)
+    }
+
+    /// Read a `.rev` file of the pack of `index`.
+    pub fn from_bytes(data: &[u8], index: &PackIndex) -> Result<Self, GitError> {
+        let kind = index.hash_kind();
+        let hash_size = kind.size();
+        if data.len() != 12 + index.len() * 4 + 2 * hash_size || &data[..4] != RIDX_SIGNATURE {
+            return Err(GitError::InvalidIdxFile(
+                "invalid reverse index".to_string(),
+            ));
+        }
+        if read_u32(data, 4) != 1 || read_u32(data, 8) != hash_function_id(kind) as u32 {
+            return Err(GitError::InvalidIdxFile(
+                "unsupported reverse index version or hash".to_string(),
+            ));
+        }
+        verify_checksum(data, kind)?;
+        let pack_hash = &data[data.len() - 2 * hash_size..data.len() - hash_size];
+        if pack_hash != index.pack_hash().as_bytes() {
+            return Err(GitError::InvalidIdxFile(
+                "reverse index is built for another pack".to_string(),
+            ));
+        }
+        let positions = (0..index.len())
+            .map(|n| read_u32(data, 12 + n * 4))
+            .collect();
+        Ok(ReverseIndex { positions })
+    }
+
+    /// Encode as a `.rev` file, `pack_hash` is the checksum of the pack.
+    pub fn to_bytes(&self, pack_hash: &SHA1) -> Vec<u8> {
+        let kind = pack_hash.kind();
+        let mut data = Vec::with_capacity(12 + self.positions.len() * 4 + 2 * kind.size());
+        data.extend_from_slice(RIDX_SIGNATURE);
+        data.extend_from_slice(&1u32.to_be_bytes()); // version
+        data.extend_from_slice(&(hash_function_id(kind) as u32).to_be_bytes());
+        for position in &self.positions {
+            data.extend_from_slice(&position.to_be_bytes());
+        }
+        data.extend_from_slice(pack_hash.as_bytes());
+        append_checksum(&mut data, kind);
+        data
+    }
+
+    /// The position in the index of the `n`-th object in the pack.
+    pub fn position(&self, n: usize) -> usize {
+        self.positions[n] as usize
+    }
+
+    /// The size of the packed object at position `position` in `index`,
+    /// `pack_size` is the size of the pack file.
+    pub fn packed_size(&self, index: &PackIndex, position: usize, pack_size: u64) -> u64 {
+        let offset = index.offset(position);
+        let rank = self
+            .positions
+            .partition_point(|n| index.offset(*n as usize) <= offset);
+        let next = match self.positions.get(rank) {
+            Some(n) => index.offset(*n as usize),
+            None => pack_size - index.hash_kind().size() as u64,
+        };
+        next - offset
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+    use std::io::Cursor;
+    use std::str::FromStr;
+
+    use crate::hash::{HashKind, ObjectHash, SHA1};
+
+    use super::{build_index_v1, build_index_v2, PackIndex, ReverseIndex};
+
+    fn objects() -> BTreeMap<SHA1, usize> {
+        let mut objects = BTreeMap::new();
+        objects.insert(
+            SHA1::from_str("8ab686eafeb1f44702738c8b0f24f2567c36da6d").unwrap(),
+            12,
+        );
+        objects.insert(
+            SHA1::from_str("01b9e7f7bd4b7e4a5a1bd1f2c3d4e5f60718293a").unwrap(),
+            140,
+        );
+        objects
+    }
+
+    #[test]
+    fn test_build_index_v1() {
+        let objects = objects();
+        let pack_hash = SHA1::new(b"pack");
+        let index = build_index_v1(&objects, &pack_hash);
+
+        assert_eq!(index.len(), 256 * 4 + 2 * 24 + 40);
+        // fan-out: one object starts with 0x01, the other with 0x8a
+        assert_eq!(&index[0..4], &0u32.to_be_bytes());
+        assert_eq!(&index[4..8], &1u32.to_be_bytes());
+        assert_eq!(&index[0x8a * 4..0x8a * 4 + 4], &2u32.to_be_bytes());
+        assert_eq!(&index[255 * 4..256 * 4], &2u32.to_be_bytes());
+        // entries are sorted by object id
+        assert_eq!(&index[1024..1028], &140u32.to_be_bytes());
+        assert_eq!(&index[1048..1052], &12u32.to_be_bytes());
+        assert_eq!(
+            &index[index.len() - 40..index.len() - 20],
+            pack_hash.as_bytes()
+        );
+
+        let index = PackIndex::from_bytes(index).unwrap();
+        assert_eq!(index.version(), 1);
+        assert_eq!(index.len(), 2);
+        for (hash, offset) in &objects {
+            assert_eq!(index.find(hash), Some(*offset as u64));
+        }
+        assert_eq!(index.pack_hash(), pack_hash);
+    }
+
+    #[test]
+    fn test_build_index_v2() {
+        let objects = objects();
+        // a fake pack: the objects span 12..140 and 140..200
+        let pack: Vec<u8> = (0..220).map(|i| i as u8).collect();
+        let pack_hash = SHA1::from_bytes(&pack[200..]).unwrap();
+        let index = build_index_v2(&objects, &pack_hash, Cursor::new(&pack), pack.len()).unwrap();
+        let index = PackIndex::from_bytes(index).unwrap();
+        assert_eq!(index.version(), 2);
+        assert_eq!(index.pack_hash(), pack_hash);
+
+        let first = objects.keys().next().unwrap();
+        let position = index.position(first).unwrap();
+        assert_eq!(index.offset(position), 140);
+        assert_eq!(
+            index.crc32(position),
+            Some(crc32fast::hash(&pack[140..200]))
+        );
+        assert_eq!(
+            index.hashes().collect::<Vec<_>>(),
+            objects.keys().cloned().collect::<Vec<_>>()
+        );
+        assert_eq!(index.find(&SHA1::new(b"missing")), None);
+
+        let rev = ReverseIndex::build(&index);
+        let rev = ReverseIndex::from_bytes(&rev.to_bytes(&pack_hash), &index).unwrap();
+        // the object at offset 12 comes first in the pack
+        assert_eq!(index.offset(rev.position(0)), 12);
+        assert_eq!(rev.packed_size(&index, position, pack.len() as u64), 60);
+        assert_eq!(
+            rev.packed_size(&index, rev.position(0), pack.len() as u64),
+            128
+        );
+    }
+
+    #[test]
+    fn test_build_index_v2_large_offset() {
+        let hash = SHA1::from_str("8ab686eafeb1f44702738c8b0f24f2567c36da6d").unwrap();
+        let offset: u64 = 5 << 30;
+        // build by hand, as a pack of 5 GiB is too large for a test
+        let mut data = vec![0xff, b't', b'O', b'c', 0, 0, 0, 2];
+        for n in 0..256 {
+            let count: u32 = if n >= 0x8a { 1 } else { 0 };
+            data.extend_from_slice(&count.to_be_bytes());
+        }
+        data.extend_from_slice(hash.as_bytes());
+        data.extend_from_slice(&0u32.to_be_bytes());
+        data.extend_from_slice(&0x8000_0000u32.to_be_bytes());
+        data.extend_from_slice(&offset.to_be_bytes());
+        data.extend_from_slice(&[0; 20]);
+        super::append_checksum(&mut data, HashKind::Sha1);
+
+        let index = PackIndex::from_bytes(data).unwrap();
+        assert_eq!(index.find(&hash), Some(offset));
+    }
+
+    #[test]
+    fn test_build_index_v2_sha256() {
+        let objects: BTreeMap<SHA1, usize> = [(b"one", 12), (b"two", 100)]
+            .into_iter()
+            .map(|(data, offset)| (ObjectHash::new_with_kind(HashKind::Sha256, data), offset))
+            .collect();
+        let pack: Vec<u8> = (0..200).map(|i| i as u8).collect();
+        let pack_hash = SHA1::from_bytes(&pack[168..]).unwrap();
+        assert_eq!(pack_hash.kind(), HashKind::Sha256);
+        let data = build_index_v2(&objects, &pack_hash, Cursor::new(&pack), pack.len()).unwrap();
+
+        // the hash size isn't in the file, so it's read as an index of another format
+        assert!(PackIndex::from_bytes(data.clone()).is_err());
+        let index = PackIndex::from_bytes_with_kind(data, HashKind::Sha256).unwrap();
+        assert_eq!(index.pack_hash(), pack_hash);
+        for (hash, offset) in &objects {
+            assert_eq!(index.find(hash), Some(*offset as u64));
+        }
+        let rev = ReverseIndex::build(&index);
+        let rev = ReverseIndex::from_bytes(&rev.to_bytes(&pack_hash), &index).unwrap();
+        assert_eq!(
+            rev.packed_size(&index, rev.position(1), pack.len() as u64),
+            68
+        );
+    }
+}
@@ -10,18 +10,25 @@ pub mod cache;
 pub mod waitlist;
 pub mod cache_object;
 pub mod entry;
+pub mod index;
+pub mod multi_pack_index;
 pub mod channel_reader;
 
-use crate::hash::SHA1;
+use crate::hash::{HashKind, SHA1};
 use threadpool::ThreadPool;
 use std::sync::Arc;
 use std::sync::atomic::AtomicUsize;
 use crate::internal::object::ObjectTrait;
+use crate::internal::pack::entry::Entry;
 use crate::internal::pack::waitlist::Waitlist;
 
 use self::cache::Caches;
 
 const DEFAULT_TMP_DIR: &str = "./.cache_temp";
+
+/// Look up an object outside of the pack, for the bases of ref deltas in a thin pack.
+pub type ObjectLookup = Arc<dyn Fn(SHA1) -> Option<Entry> + Send + Sync>;
+
 pub struct Pack {
     pub number: usize,
     pub signature: SHA1,
@@ -32,6 +39,11 @@ pub struct Pack {
     pub mem_limit: usize,
     pub cache_objs_mem: Arc<AtomicUsize>, // the memory size of CacheObjects in this Pack
     pub clean_tmp: bool,
+    pub base_lookup: Option<ObjectLookup>,
+    /// The delta bases which were not in the pack, but found by `base_lookup`
+    pub thin_bases: Vec<SHA1>,
+    /// The hash algorithm of the object ids and the checksum of the pack
+    pub hash_kind: HashKind,
 }
 
 #[cfg(test)]
@@ -0,0 +1,314 @@
+//!
+//! Multi-pack-index (`multi-pack-index`) files, which index the objects of many packs at once,
+//! see [pack-format](https://git-scm.com/docs/gitformat-pack#_multi_pack_index_midx_files_have_the_following_format).
+//!
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use crate::errors::GitError;
+use crate::hash::{HashKind, SHA1};
+use crate::internal::pack::index::{
+    append_checksum, fan_out, hash_function_id, read_u32, read_u64, verify_checksum, PackIndex,
+};
+
+const MIDX_SIGNATURE: &[u8; 4] = b"MIDX";
+const HEADER_SIZE: usize = 12;
+const CHUNK_LOOKUP_ENTRY: usize = 12;
+
+const CHUNK_PACK_NAMES: &[u8; 4] = b"PNAM";
+const CHUNK_OID_FANOUT: &[u8; 4] = b"OIDF";
+const CHUNK_OID_LOOKUP: &[u8; 4] = b"OIDL";
+const CHUNK_OBJECT_OFFSETS: &[u8; 4] = b"OOFF";
+const CHUNK_LARGE_OFFSETS: &[u8; 4] = b"LOFF";
+
+const LARGE_OFFSET_FLAG: u32 = 0x8000_0000;
+
+/// Build a multi-pack-index of `packs`, which are the names of the `.idx` files and the indexes.
+///
+/// An object stored in several packs is looked up in the first one, in the order of names.
+pub fn build_multi_pack_index(packs: &[(String, &PackIndex)]) -> Vec<u8> {
+    let mut packs: Vec<&(String, &PackIndex)> = packs.iter().collect();
+    packs.sort_by(|a, b| a.0.cmp(&b.0));
+    let kind = packs
+        .first()
+        .map_or_else(HashKind::default, |(_, index)| index.hash_kind());
+
+    let mut objects: BTreeMap<SHA1, (u32, u64)> = BTreeMap::new();
+    for (pack_id, (_, index)) in packs.iter().enumerate() {
+        for n in 0..index.len() {
+            objects
+                .entry(index.hash(n))
+                .or_insert((pack_id as u32, index.offset(n)));
+        }
+    }
+
+    let mut pack_names = Vec::new();
+    for (name, _) in &packs {
+        pack_names.extend_from_slice(name.as_bytes());
+        pack_names.push(0);
+    }
+    pack_names.resize(pack_names.len().next_multiple_of(4), 0);
+
+    let mut oid_lookup = Vec::with_capacity(objects.len() * kind.size());
+    let mut object_offsets = Vec::with_capacity(objects.len() * 8);
+    let mut large_offsets = Vec::new();
+    for (hash, (pack_id, offset)) in &objects {
+        oid_lookup.extend_from_slice(hash.as_bytes());
+        object_offsets.extend_from_slice(&pack_id.to_be_bytes());
+        if *offset < LARGE_OFFSET_FLAG as u64 {
+            object_offsets.extend_from_slice(&(*offset as u32).to_be_bytes());
+        } else {
+            let position = (large_offsets.len() / 8) as u32;
+            object_offsets.extend_from_slice(&(LARGE_OFFSET_FLAG | position).to_be_bytes());
+            large_offsets.extend_from_slice(&offset.to_be_bytes());
+        }
+    }
+
+    let mut chunks: Vec<(&[u8; 4], Vec<u8>)> = vec![
+        (CHUNK_PACK_NAMES, pack_names),
+        (CHUNK_OID_FANOUT, fan_out(objects.keys())),
+        (CHUNK_OID_LOOKUP, oid_lookup),
+        (CHUNK_OBJECT_OFFSETS, object_offsets),
+    ];
+    if !large_offsets.is_empty() {
+        chunks.push((CHUNK_LARGE_OFFSETS, large_offsets));
+    }
+
+    let mut data = Vec::new();
+    data.extend_from_slice(MIDX_SIGNATURE);
+    data.push(1); // version
+    data.push(hash_function_id(kind)); // object id version
+    data.push(chunks.len() as u8);
+    data.push(0); // number of base multi-pack-index files
+    data.extend_from_slice(&(packs.len() as u32).to_be_bytes());
+
+    // chunk lookup table, ended by a zero id and the end of the last chunk
+    let mut offset = (HEADER_SIZE + (chunks.len() + 1) * CHUNK_LOOKUP_ENTRY) as u64;
+    for (id, chunk) in &chunks {
+        data.extend_from_slice(*id);
+        data.extend_from_slice(&offset.to_be_bytes());
+        offset += chunk.len() as u64;
+    }
+    data.extend_from_slice(&[0; 4]);
+    data.extend_from_slice(&offset.to_be_bytes());
+
+    for (_, chunk) in chunks {
+        data.extend(chunk);
+    }
+    append_checksum(&mut data, kind);
+    data
+}
+
+/// A multi-pack-index, read into memory.
+pub struct MultiPackIndex {
+    data: Vec<u8>,
+    pack_names: Vec<String>,
+    num_objects: usize,
+    oid_fanout: usize,
+    oid_lookup: usize,
+    object_offsets: usize,
+    large_offsets: Option<usize>,
+    kind: HashKind,
+}
+
+impl MultiPackIndex {
+    pub fn open(path: &Path) -> Result<Self, GitError> {
+        Self::from_bytes(std::fs::read(path)?)
+    }
+
+    pub fn from_bytes(data: Vec<u8>) -> Result<Self, GitError> {
+        let invalid = |msg: &str| GitError::InvalidIdxFile(format!("multi-pack-index: {}", msg));
+        if data.len() < HEADER_SIZE + CHUNK_LOOKUP_ENTRY + 20 || &data[..4] != MIDX_SIGNATURE {
+            return Err(invalid("bad signature"));
+        }
+        let kind = [HashKind::Sha1, HashKind::Sha256]
+            .into_iter()
+            .find(|kind| hash_function_id(*kind) == data[5]);
+        let kind = match kind {
+            Some(kind) if data[4] == 1 => kind,
+            _ => return Err(invalid("unsupported version")),
+        };
+        if data[7] != 0 {
+            return Err(invalid("incremental multi-pack-index is not supported"));
+        }
+        let hash_size = kind.size();
+        if data.len() < HEADER_SIZE + CHUNK_LOOKUP_ENTRY + hash_size {
+            return Err(invalid("truncated"));
+        }
+        verify_checksum(&data, kind)?;
+        let num_chunks = data[6] as usize;
+        let num_packs = read_u32(&data, 8) as usize;
+        let lookup_end = HEADER_SIZE + (num_chunks + 1) * CHUNK_LOOKUP_ENTRY;
+        if data.len() < lookup_end + hash_size {
+            return Err(invalid("truncated chunk lookup"));
+        }
+
+        // chunk id to (start, end)
+        let mut chunks: BTreeMap<[u8; 4], (usize, usize)> = BTreeMap::new();
+        for n in 0..num_chunks {
+            let pos = HEADER_SIZE + n * CHUNK_LOOKUP_ENTRY;
+            let id: [u8; 4] = data[pos..pos + 4].try_into().unwrap();
+            let start = read_u64(&data, pos + 4) as usize;
+            let end = read_u64(&data, pos + 4 + CHUNK_LOOKUP_ENTRY) as usize;
+            if start > end || end > data.len() - hash_size {
+                return Err(invalid("chunk out of range"));
+            }
+            chunks.insert(id, (start, end));
+        }
+        let chunk = |id: &[u8; 4]| {
+            chunks
+                .get(id)
+                .copied()
+                .ok_or_else(|| invalid(&format!("missing chunk {}", String::from_utf8_lossy(id))))
+        };
+
+        let (names_start, names_end) = chunk(CHUNK_PACK_NAMES)?;
+        let pack_names: Vec<String> = data[names_start..names_end]
+            .split(|b| *b == 0)
+            .filter(|name| !name.is_empty())
+            .map(|name| String::from_utf8_lossy(name).into_owned())
+            .collect();
+        if pack_names.len() != num_packs {
+            return Err(invalid("wrong number of pack names"));
+        }
+
+        let (oid_fanout, fanout_end) = chunk(CHUNK_OID_FANOUT)?;
+        if fanout_end - oid_fanout != 256 * 4 {
+            return Err(invalid("wrong size of the fan-out"));
+        }
+        let num_objects = read_u32(&data, oid_fanout + 255 * 4) as usize;
+        let (oid_lookup, lookup_end) = chunk(CHUNK_OID_LOOKUP)?;
+        let (object_offsets, offsets_end) = chunk(CHUNK_OBJECT_OFFSETS)?;
+        if lookup_end - oid_lookup != num_objects * hash_size
+            || offsets_end - object_offsets != num_objects * 8
+        {
+            return Err(invalid("wrong number of objects"));
+        }
+        let large_offsets = chunks.get(CHUNK_LARGE_OFFSETS).map(|(start, _)| *start);
+
+        Ok(MultiPackIndex {
+            data,
+            pack_names,
+            num_objects,
+            oid_fanout,
+            oid_lookup,
+            object_offsets,
+            large_offsets,
+            kind,
+        })
+    }
+
+    /// Names of the `.idx` files of the packs, in the order of the pack ids.
+    pub fn pack_names(&self) -> &[String] {
+        &self.pack_names
+    }
+
+    /// Number of distinct objects in all packs.
+    pub fn len(&self) -> usize {
+        self.num_objects
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.num_objects == 0
+    }
+
+    fn fanout(&self, n: usize) -> usize {
+        read_u32(&self.data, self.oid_fanout + n * 4) as usize
+    }
+
+    /// The id of the object at position `n`, objects are sorted by id.
+    pub fn hash(&self, n: usize) -> SHA1 {
+        let hash_size = self.kind.size();
+        let pos = self.oid_lookup + n * hash_size;
+        SHA1::from_bytes(&self.data[pos..pos + hash_size]).unwrap()
+    }
+
+    /// The pack id and the offset in that pack of the object at position `n`.
+    fn location(&self, n: usize) -> (usize, u64) {
+        let pos = self.object_offsets + n * 8;
+        let pack_id = read_u32(&self.data, pos) as usize;
+        let offset = read_u32(&self.data, pos + 4);
+        if offset & LARGE_OFFSET_FLAG == 0 {
+            return (pack_id, offset as u64);
+        }
+        let large = self.large_offsets.expect("large offset without its chunk");
+        let position = (offset & !LARGE_OFFSET_FLAG) as usize;
+        (pack_id, read_u64(&self.data, large + position * 8))
+    }
+
+    /// Find an object with a single binary search.
+    /// Returns the index of its pack in [`MultiPackIndex::pack_names`] and its offset there.
+    pub fn find(&self, hash: &SHA1) -> Option<(usize, u64)> {
+        let first_byte = hash.as_bytes()[0] as usize;
+        let mut low = if first_byte == 0 {
+            0
+        } else {
+            self.fanout(first_byte - 1)
+        };
+        let mut high = self.fanout(first_byte);
+        while low < high {
+            let mid = (low + high) / 2;
+            match self.hash(mid).cmp(hash) {
+                std::cmp::Ordering::Equal => return Some(self.location(mid)),
+                std::cmp::Ordering::Less => low = mid + 1,
+                std::cmp::Ordering::Greater => high = mid,
+            }
+        }
+        None
+    }
+
+    /// The ids of all objects, sorted.
+    pub fn hashes(&self) -> impl Iterator<Item = SHA1> + '_ {
+        (0..self.num_objects).map(|n| self.hash(n))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use crate::hash::SHA1;
+    use crate::internal::pack::index::{build_index_v1, PackIndex};
+
+    use super::{build_multi_pack_index, MultiPackIndex};
+
+    fn pack_index(objects: &[(&str, usize)]) -> PackIndex {
+        let objects: BTreeMap<SHA1, usize> = objects
+            .iter()
+            .map(|(content, offset)| (SHA1::new(content.as_bytes()), *offset))
+            .collect();
+        let index = build_index_v1(&objects, &SHA1::new(b"pack"));
+        PackIndex::from_bytes(index).unwrap()
+    }
+
+    #[test]
+    fn test_multi_pack_index() {
+        let a = pack_index(&[("one", 12), ("two", 100), ("shared", 200)]);
+        let b = pack_index(&[("three", 12), ("shared", 50)]);
+        let data = build_multi_pack_index(&[
+            ("pack-b.idx".to_string(), &b),
+            ("pack-a.idx".to_string(), &a),
+        ]);
+        let midx = MultiPackIndex::from_bytes(data).unwrap();
+
+        assert_eq!(midx.pack_names(), &["pack-a.idx", "pack-b.idx"]);
+        assert_eq!(midx.len(), 4);
+        let hash = |content: &str| SHA1::new(content.as_bytes());
+        assert_eq!(midx.find(&hash("two")), Some((0, 100)));
+        assert_eq!(midx.find(&hash("three")), Some((1, 12)));
+        // the first pack by name wins
+        assert_eq!(midx.find(&hash("shared")), Some((0, 200)));
+        assert_eq!(midx.find(&hash("missing")), None);
+        let mut hashes: Vec<SHA1> = midx.hashes().collect();
+        hashes.dedup();
+        assert_eq!(hashes.len(), 4);
+    }
+
+    #[test]
+    fn test_multi_pack_index_corrupted() {
+        let a = pack_index(&[("one", 12)]);
+        let mut data = build_multi_pack_index(&[("pack-a.idx".to_string(), &a)]);
+        data[20] ^= 0xff;
+        assert!(MultiPackIndex::from_bytes(data).is_err());
+    }
+}
@@ -1,8 +1,7 @@
 use std::fs;
 use std::io::{self, Read};
 use std::path::Path;
-use sha1::{Digest, Sha1};
-use crate::hash::SHA1;
+use crate::hash::{HashKind, SHA1};
 use crate::internal::object::types::ObjectType;
 
 /// Checks if the reader has reached EOF (end of file).
@@ -235,22 +234,11 @@ pub fn read_partial_int<R: Read>(
     Ok(value)
 }
 
-/// Calculate the SHA1 hash of the given object.
+/// Calculate the hash of the given object, with the algorithm of `kind`.
 /// <br> "`<type> <size>\0<content>`"
 /// <br> data: The decompressed content of the object
-pub fn calculate_object_hash(obj_type: ObjectType, data: &Vec<u8>) -> SHA1 {
-    let mut hash = Sha1::new();
-    // Header: "<type> <size>\0"
-    hash.update(obj_type.to_bytes());
-    hash.update(b" ");
-    hash.update(data.len().to_string());
-    hash.update(b"\0");
-
-    // Decompressed data(raw content)
-    hash.update(data);
-
-    let re: [u8; 20] = hash.finalize().into();
-    SHA1(re)
+pub fn calculate_object_hash(kind: HashKind, obj_type: ObjectType, data: &[u8]) -> SHA1 {
+    SHA1::from_type_and_data_with_kind(kind, obj_type, data)
 }
 /// Create an empty directory or clear the existing directory.
 pub fn create_empty_dir<P: AsRef<Path>>(path: P) -> io::Result<()> {
@@ -305,7 +293,7 @@ mod tests {
 
     #[test]
     fn test_calc_obj_hash() {
-        let hash = calculate_object_hash(ObjectType::Blob, &b"a".to_vec());
+        let hash = calculate_object_hash(HashKind::Sha1, ObjectType::Blob, b"a");
         assert_eq!(hash.to_plain_str(), "2e65efe2a145dda7ee51d1741299f848e5bf752e");
     }
 
@@ -1,8 +1,6 @@
 use std::io::{self, Read, BufRead};
 
-use sha1::{Sha1, Digest};
-
-use crate::hash::SHA1;
+use crate::hash::{HashKind, ObjectHasher, SHA1};
 
 /// `Wrapper` is a wrapper around a reader that also computes the SHA1 hash of the data read.
 ///
@@ -10,11 +8,11 @@ use crate::hash::SHA1;
 ///
 /// Fields:
 /// * `inner`: The inner reader.
-/// * `hash`: The SHA1 hash state.
+/// * `hash`: The hash state, SHA-1 or SHA-256.
 /// * `count_hash`: A flag to indicate whether to compute the hash while reading.
 pub struct Wrapper<R> {
     inner: R,
-    hash: Sha1,
+    hash: ObjectHasher,
     bytes_read: usize,
 }
 
@@ -28,9 +26,14 @@ where
     /// * `inner`: The reader to wrap.
     /// * `count_hash`: If `true`, the hash is computed while reading; otherwise, it is not.
     pub fn new(inner: R) -> Self {
+        Self::with_hash_kind(inner, HashKind::Sha1)
+    }
+
+    /// Constructs a new `Wrapper` which computes the hash with the algorithm of `kind`.
+    pub fn with_hash_kind(inner: R, kind: HashKind) -> Self {
         Self {
             inner,
-            hash: Sha1::new(), // Initialize a new SHA1 hasher
+            hash: ObjectHasher::new(kind),
             bytes_read: 0,
         }
     }
@@ -43,8 +46,7 @@ where
     ///
     /// This is a clone of the internal hash state finalized into a SHA1 hash.
     pub fn final_hash(&self) -> SHA1 {
-        let re: [u8; 20] = self.hash.clone().finalize().into(); // Clone, finalize, and convert the hash into bytes
-        SHA1(re)
+        self.hash.clone().finalize() // Clone and finalize, so that reading can go on
     }
 }
 
@@ -126,7 +128,7 @@ mod tests {
         hasher.update(data);
         let expected_hash: [u8; 20] = hasher.finalize().into();
 
-        assert_eq!(hash_result.0, expected_hash);
+        assert_eq!(hash_result.as_bytes(), expected_hash);
         Ok(())
     }
 }
\ No newline at end of file
@@ -0,0 +1,693 @@
+//! Recursive diff of two trees, like `git diff-tree -r`, with rename and copy detection.
+//!
+//! Trees are loaded through a [`TreeStore`] only when their ids differ, so unchanged
+//! directories are never read, and blobs only when renames are detected by content.
+//! A pathspec limits the diff to some files or directories.
+
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{HashMap, HashSet};
+use std::hash::{Hash, Hasher};
+
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+
+use crate::errors::GitError;
+use crate::hash::SHA1;
+use crate::internal::object::tree::{Tree, TreeItem, TreeItemMode};
+
+/// Where a diff reads the trees and blobs it compares.
+#[async_trait]
+pub trait TreeStore: Send + Sync {
+    async fn load_tree(&self, id: &SHA1) -> Result<Tree, GitError>;
+
+    /// The content of a blob, read only to detect renames and copies of modified files.
+    async fn load_blob(&self, id: &SHA1) -> Result<Vec<u8>, GitError>;
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum ChangeKind {
+    Added,
+    Deleted,
+    Modified,
+    /// a file became a link or a submodule, or the other way around
+    TypeChanged,
+    Renamed,
+    Copied,
+}
+
+/// One side of a change.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct DiffEntry {
+    /// the path from the root of the compared trees, separated by `/`
+    pub path: String,
+    pub mode: TreeItemMode,
+    pub id: SHA1,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TreeChange {
+    pub kind: ChangeKind,
+    /// `None` for an added file
+    pub old: Option<DiffEntry>,
+    /// `None` for a deleted file
+    pub new: Option<DiffEntry>,
+    /// how similar the files of a rename or copy are, from 0 to 100
+    pub similarity: Option<u8>,
+}
+
+impl TreeChange {
+    /// The path of the file after the change, or before it if it was deleted.
+    pub fn path(&self) -> &str {
+        match (&self.new, &self.old) {
+            (Some(entry), _) | (None, Some(entry)) => &entry.path,
+            (None, None) => "",
+        }
+    }
+
+    fn added(entry: DiffEntry) -> Self {
+        TreeChange {
+            kind: ChangeKind::Added,
+            old: None,
+            new: Some(entry),
+            similarity: None,
+        }
+    }
+
+    fn deleted(entry: DiffEntry) -> Self {
+        TreeChange {
+            kind: ChangeKind::Deleted,
+            old: Some(entry),
+            new: None,
+            similarity: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct DiffOptions {
+    /// Only compare these files and the files under these directories, all when empty.
+    pub pathspecs: Vec<String>,
+    /// Pair deleted and added files with similar content as renames.
+    pub detect_renames: bool,
+    /// Also pair added files with modified files as copies.
+    pub detect_copies: bool,
+    /// The minimum similarity of a rename or copy, 50 like git.
+    pub rename_threshold: u8,
+    /// Don't compare contents when there are more added or source files than this,
+    /// only exact renames are found then, like `diff.renameLimit`.
+    pub rename_limit: usize,
+    /// Report the changed directories too, before the changes under them, like `git diff-tree -t`.
+    pub include_trees: bool,
+}
+
+impl Default for DiffOptions {
+    fn default() -> Self {
+        DiffOptions {
+            pathspecs: Vec::new(),
+            detect_renames: true,
+            detect_copies: false,
+            rename_threshold: 50,
+            rename_limit: 1000,
+            include_trees: false,
+        }
+    }
+}
+
+impl DiffOptions {
+    /// A diff without rename detection, only additions, deletions and modifications.
+    pub fn plain() -> Self {
+        DiffOptions {
+            detect_renames: false,
+            ..Default::default()
+        }
+    }
+
+    /// Whether a path, of a directory if `is_tree`, is compared.
+    fn matches(&self, path: &str, is_tree: bool) -> bool {
+        self.pathspecs.is_empty()
+            || self.pathspecs.iter().any(|spec| {
+                let spec = spec.trim_matches('/');
+                spec.is_empty()
+                    || is_sub_path(path, spec)
+                    // a directory containing a pathspec is read to find it
+                    || (is_tree && is_sub_path(spec, path))
+            })
+    }
+}
+
+/// Whether `path` is `dir` or under it.
+fn is_sub_path(path: &str, dir: &str) -> bool {
+    path.strip_prefix(dir)
+        .is_some_and(|rest| rest.is_empty() || rest.starts_with('/'))
+}
+
+/// Compare the trees `old` and `new`, `None` is an empty tree.
+///
+/// The changes are sorted by path, a rename or copy by its new path.
+pub async fn diff_trees<S: TreeStore + ?Sized>(
+    store: &S,
+    old: Option<SHA1>,
+    new: Option<SHA1>,
+    options: &DiffOptions,
+) -> Result<Vec<TreeChange>, GitError> {
+    let mut changes = Vec::new();
+    let mut pending = vec![(old, new, String::new())];
+    while let Some((old, new, dir)) = pending.pop() {
+        if old == new {
+            continue;
+        }
+        let old_items = match old {
+            Some(id) => store.load_tree(&id).await?.tree_items,
+            None => Vec::new(),
+        };
+        let new_items = match new {
+            Some(id) => store.load_tree(&id).await?.tree_items,
+            None => Vec::new(),
+        };
+        let mut old_by_name: HashMap<String, TreeItem> = old_items
+            .into_iter()
+            .map(|item| (item.name.clone(), item))
+            .collect();
+        let mut pairs: Vec<(String, Option<TreeItem>, Option<TreeItem>)> = new_items
+            .into_iter()
+            .map(|item| {
+                (
+                    item.name.clone(),
+                    old_by_name.remove(&item.name),
+                    Some(item),
+                )
+            })
+            .collect();
+        pairs.extend(
+            old_by_name
+                .into_iter()
+                .map(|(name, item)| (name, Some(item), None)),
+        );
+
+        for (name, old_item, new_item) in pairs {
+            if old_item == new_item {
+                continue;
+            }
+            let path = if dir.is_empty() {
+                name
+            } else {
+                format!("{}/{}", dir, name)
+            };
+            let is_tree = |item: &Option<TreeItem>| {
+                item.as_ref()
+                    .is_some_and(|item| item.mode == TreeItemMode::Tree)
+            };
+            let (old_is_tree, new_is_tree) = (is_tree(&old_item), is_tree(&new_item));
+            if !options.matches(&path, old_is_tree || new_is_tree) {
+                continue;
+            }
+            let entry = |item: &TreeItem| DiffEntry {
+                path: path.clone(),
+                mode: item.mode,
+                id: item.id,
+            };
+
+            if old_is_tree || new_is_tree {
+                let old_tree = old_item.as_ref().filter(|_| old_is_tree);
+                let new_tree = new_item.as_ref().filter(|_| new_is_tree);
+                if options.include_trees {
+                    changes.push(match (old_tree, new_tree) {
+                        (Some(old), Some(new)) => TreeChange {
+                            kind: ChangeKind::Modified,
+                            old: Some(entry(old)),
+                            new: Some(entry(new)),
+                            similarity: None,
+                        },
+                        (Some(old), None) => TreeChange::deleted(entry(old)),
+                        (None, new) => TreeChange::added(entry(new.unwrap())),
+                    });
+                }
+                pending.push((
+                    old_tree.map(|item| item.id),
+                    new_tree.map(|item| item.id),
+                    path.clone(),
+                ));
+            }
+            // the files replacing or replaced by a directory
+            let old_file = old_item.as_ref().filter(|_| !old_is_tree);
+            let new_file = new_item.as_ref().filter(|_| !new_is_tree);
+            match (old_file, new_file) {
+                (Some(old), Some(new)) => {
+                    let kind = if file_type(old.mode) == file_type(new.mode) {
+                        ChangeKind::Modified
+                    } else {
+                        ChangeKind::TypeChanged
+                    };
+                    changes.push(TreeChange {
+                        kind,
+                        old: Some(entry(old)),
+                        new: Some(entry(new)),
+                        similarity: None,
+                    });
+                }
+                (Some(old), None) => changes.push(TreeChange::deleted(entry(old))),
+                (None, Some(new)) => changes.push(TreeChange::added(entry(new))),
+                (None, None) => {}
+            }
+        }
+    }
+
+    if options.detect_renames || options.detect_copies {
+        changes = detect_renames(store, changes, options).await?;
+    }
+    // a deleted file before the directory added in its place
+    changes.sort_by(|a, b| {
+        a.path()
+            .cmp(b.path())
+            .then(b.old.is_some().cmp(&a.old.is_some()))
+    });
+    Ok(changes)
+}
+
+/// Files, links and submodules can't be renamed into each other.
+fn file_type(mode: TreeItemMode) -> TreeItemMode {
+    match mode {
+        TreeItemMode::BlobExecutable => TreeItemMode::Blob,
+        mode => mode,
+    }
+}
+
+fn base_name(path: &str) -> &str {
+    path.rsplit('/').next().unwrap_or(path)
+}
+
+/// Pair the added files with deleted files, or modified files for copies: first the files
+/// with the same content, then the most similar ones.
+async fn detect_renames<S: TreeStore + ?Sized>(
+    store: &S,
+    changes: Vec<TreeChange>,
+    options: &DiffOptions,
+) -> Result<Vec<TreeChange>, GitError> {
+    let is_file = |entry: &DiffEntry| entry.mode != TreeItemMode::Tree;
+    let added: Vec<usize> = (0..changes.len())
+        .filter(|&i| {
+            changes[i].kind == ChangeKind::Added && is_file(changes[i].new.as_ref().unwrap())
+        })
+        .collect();
+    // the deleted files can be renamed, and with copies the old side of modified files copied
+    let sources: Vec<usize> = (0..changes.len())
+        .filter(|&i| match changes[i].kind {
+            ChangeKind::Deleted => is_file(changes[i].old.as_ref().unwrap()),
+            ChangeKind::Modified => options.detect_copies,
+            _ => false,
+        })
+        .collect();
+    if added.is_empty() || sources.is_empty() {
+        return Ok(changes);
+    }
+    let old_of = |i: usize| changes[i].old.as_ref().unwrap();
+    let new_of = |i: usize| changes[i].new.as_ref().unwrap();
+
+    // (added, source, similarity)
+    let mut pairs: Vec<(usize, usize, u8)> = Vec::new();
+    let mut paired = HashSet::new();
+    // exact renames first, preferring a source of the same name
+    let mut sources_by_id: HashMap<SHA1, Vec<usize>> = HashMap::new();
+    for &source in &sources {
+        sources_by_id
+            .entry(old_of(source).id)
+            .or_default()
+            .push(source);
+    }
+    for &dest in &added {
+        let new = new_of(dest);
+        if let Some(candidates) = sources_by_id.get(&new.id) {
+            let candidates = candidates
+                .iter()
+                .filter(|&&source| file_type(old_of(source).mode) == file_type(new.mode));
+            let best = candidates
+                .min_by_key(|&&source| base_name(&old_of(source).path) != base_name(&new.path));
+            if let Some(&source) = best {
+                pairs.push((dest, source, 100));
+                paired.insert(dest);
+            }
+        }
+    }
+
+    // then by content, unless there are too many files to compare
+    let dests: Vec<usize> = added.into_iter().filter(|i| !paired.contains(i)).collect();
+    if !dests.is_empty() && dests.len().max(sources.len()) <= options.rename_limit {
+        let mut signatures = HashMap::new();
+        let mut candidates = Vec::new();
+        for &dest in &dests {
+            let new = new_of(dest);
+            for &source in &sources {
+                let old = old_of(source);
+                if file_type(old.mode) != TreeItemMode::Blob
+                    || file_type(new.mode) != TreeItemMode::Blob
+                {
+                    continue;
+                }
+                for id in [old.id, new.id] {
+                    if let std::collections::hash_map::Entry::Vacant(e) = signatures.entry(id) {
+                        e.insert(Signature::new(&store.load_blob(&id).await?));
+                    }
+                }
+                let score = signatures[&old.id].similarity(&signatures[&new.id]);
+                if score >= options.rename_threshold {
+                    candidates.push((dest, source, score));
+                }
+            }
+        }
+        // the most similar pairs first
+        candidates.sort_by(|a, b| b.2.cmp(&a.2).then(a.0.cmp(&b.0)).then(a.1.cmp(&b.1)));
+        for candidate in candidates {
+            if paired.insert(candidate.0) {
+                pairs.push(candidate);
+            }
+        }
+    }
+
+    // a deleted file is renamed once, its other pairs are copies which need `detect_copies`
+    pairs.sort_by(|a, b| b.2.cmp(&a.2).then(a.0.cmp(&b.0)));
+    let mut renamed = HashSet::new();
+    let mut removed = HashSet::new();
+    let mut results = Vec::new();
+    for (dest, source, score) in pairs {
+        let kind = if changes[source].kind == ChangeKind::Deleted && renamed.insert(source) {
+            removed.insert(source);
+            ChangeKind::Renamed
+        } else if options.detect_copies {
+            ChangeKind::Copied
+        } else {
+            continue;
+        };
+        removed.insert(dest);
+        results.push(TreeChange {
+            kind,
+            old: Some(old_of(source).clone()),
+            new: Some(new_of(dest).clone()),
+            similarity: Some(score),
+        });
+    }
+    results.extend(
+        changes
+            .into_iter()
+            .enumerate()
+            .filter(|(i, _)| !removed.contains(i))
+            .map(|(_, change)| change),
+    );
+    Ok(results)
+}
+
+/// The bytes of each chunk of a file, the chunks being its lines or 64 bytes, like the
+/// similarity estimate of git's `diffcore-delta`.
+struct Signature {
+    size: usize,
+    chunks: HashMap<u64, usize>,
+}
+
+impl Signature {
+    fn new(data: &[u8]) -> Self {
+        let mut chunks = HashMap::new();
+        let mut start = 0;
+        for i in 0..data.len() {
+            if data[i] == b'\n' || i + 1 - start == 64 || i + 1 == data.len() {
+                let mut hasher = DefaultHasher::new();
+                data[start..=i].hash(&mut hasher);
+                *chunks.entry(hasher.finish()).or_default() += i + 1 - start;
+                start = i + 1;
+            }
+        }
+        Signature {
+            size: data.len(),
+            chunks,
+        }
+    }
+
+    /// The percentage of the bigger file found in the other one.
+    fn similarity(&self, other: &Signature) -> u8 {
+        let max = self.size.max(other.size);
+        if max == 0 {
+            // empty files are only renamed when they are the same blob
+            return 0;
+        }
+        let common: usize = self
+            .chunks
+            .iter()
+            .map(|(hash, size)| (*size).min(other.chunks.get(hash).copied().unwrap_or(0)))
+            .sum();
+        (common * 100 / max) as u8
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex;
+
+    use crate::internal::object::blob::Blob;
+
+    use super::*;
+
+    #[derive(Default)]
+    struct MemoryStore {
+        trees: HashMap<SHA1, Tree>,
+        blobs: HashMap<SHA1, Vec<u8>>,
+        loaded_trees: Mutex<Vec<SHA1>>,
+    }
+
+    #[async_trait]
+    impl TreeStore for MemoryStore {
+        async fn load_tree(&self, id: &SHA1) -> Result<Tree, GitError> {
+            self.loaded_trees.lock().unwrap().push(*id);
+            self.trees
+                .get(id)
+                .cloned()
+                .ok_or(GitError::ObjectNotFound(id.to_string()))
+        }
+
+        async fn load_blob(&self, id: &SHA1) -> Result<Vec<u8>, GitError> {
+            self.blobs
+                .get(id)
+                .cloned()
+                .ok_or(GitError::ObjectNotFound(id.to_string()))
+        }
+    }
+
+    impl MemoryStore {
+        fn blob(&mut self, content: &str) -> SHA1 {
+            let blob = Blob::from_content(content);
+            self.blobs.insert(blob.id, blob.data);
+            blob.id
+        }
+
+        fn tree(&mut self, items: Vec<(TreeItemMode, &str, SHA1)>) -> SHA1 {
+            let items = items
+                .into_iter()
+                .map(|(mode, name, id)| TreeItem::new(mode, id, name.to_string()))
+                .collect();
+            let tree = Tree::from_tree_items(items).unwrap();
+            let id = tree.id;
+            self.trees.insert(id, tree);
+            id
+        }
+    }
+
+    fn summary(changes: &[TreeChange]) -> Vec<(ChangeKind, String)> {
+        changes
+            .iter()
+            .map(|c| {
+                let path = match (&c.old, &c.new) {
+                    (Some(old), Some(new)) if old.path != new.path => {
+                        format!("{} -> {}", old.path, new.path)
+                    }
+                    _ => c.path().to_string(),
+                };
+                (c.kind, path)
+            })
+            .collect()
+    }
+
+    fn lines(count: usize, prefix: &str) -> String {
+        (0..count)
+            .map(|i| format!("{} line {}\n", prefix, i))
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn test_diff_trees() {
+        let mut store = MemoryStore::default();
+        let main = store.blob("fn main() {}\n");
+        let lib = store.blob(&lines(20, "lib"));
+        let readme = store.blob("readme\n");
+        let delete = store.blob("delete me\n");
+        let cargo = store.blob("[package]\n");
+        let src = store.tree(vec![
+            (TreeItemMode::Blob, "main.rs", main),
+            (TreeItemMode::Blob, "lib.rs", lib),
+        ]);
+        let mega = store.tree(vec![(TreeItemMode::Blob, "README.md", readme)]);
+        let changed_readme = store.blob("changed readme\n");
+        let mega_new = store.tree(vec![(TreeItemMode::Blob, "README.md", changed_readme)]);
+        let old = store.tree(vec![
+            (TreeItemMode::Tree, "src", src),
+            (TreeItemMode::Tree, "mega", mega),
+            (TreeItemMode::Blob, "delete.txt", delete),
+            (TreeItemMode::Blob, "README.md", readme),
+        ]);
+        // `src` moved, `mega` modified, `delete.txt` deleted and `Cargo.toml` added
+        let new = store.tree(vec![
+            (TreeItemMode::Tree, "src_new", src),
+            (TreeItemMode::Tree, "mega", mega_new),
+            (TreeItemMode::Blob, "Cargo.toml", cargo),
+            (TreeItemMode::Blob, "README.md", readme),
+        ]);
+
+        let changes = diff_trees(&store, Some(old), Some(new), &DiffOptions::plain())
+            .await
+            .unwrap();
+        assert_eq!(
+            summary(&changes),
+            vec![
+                (ChangeKind::Added, "Cargo.toml".to_string()),
+                (ChangeKind::Deleted, "delete.txt".to_string()),
+                (ChangeKind::Modified, "mega/README.md".to_string()),
+                (ChangeKind::Deleted, "src/lib.rs".to_string()),
+                (ChangeKind::Deleted, "src/main.rs".to_string()),
+                (ChangeKind::Added, "src_new/lib.rs".to_string()),
+                (ChangeKind::Added, "src_new/main.rs".to_string()),
+            ]
+        );
+
+        let changes = diff_trees(&store, Some(old), Some(new), &DiffOptions::default())
+            .await
+            .unwrap();
+        assert_eq!(
+            summary(&changes),
+            vec![
+                (ChangeKind::Added, "Cargo.toml".to_string()),
+                (ChangeKind::Deleted, "delete.txt".to_string()),
+                (ChangeKind::Modified, "mega/README.md".to_string()),
+                (
+                    ChangeKind::Renamed,
+                    "src/lib.rs -> src_new/lib.rs".to_string()
+                ),
+                (
+                    ChangeKind::Renamed,
+                    "src/main.rs -> src_new/main.rs".to_string()
+                ),
+            ]
+        );
+        assert_eq!(changes[3].similarity, Some(100));
+
+        let options = DiffOptions {
+            pathspecs: vec!["mega".to_string()],
+            ..Default::default()
+        };
+        store.loaded_trees.lock().unwrap().clear();
+        let changes = diff_trees(&store, Some(old), Some(new), &options)
+            .await
+            .unwrap();
+        assert_eq!(
+            summary(&changes),
+            vec![(ChangeKind::Modified, "mega/README.md".to_string())]
+        );
+        assert!(!store.loaded_trees.lock().unwrap().contains(&src));
+    }
+
+    #[tokio::test]
+    async fn test_diff_trees_similar_renames_and_copies() {
+        let mut store = MemoryStore::default();
+        let content = lines(20, "shared");
+        let original = store.blob(&content);
+        let edited = store.blob(&format!("{}one more line\n", content));
+        let unrelated = store.blob(&lines(20, "other"));
+        let link = store.blob("target");
+        let old = store.tree(vec![
+            (TreeItemMode::Blob, "a.txt", original),
+            (TreeItemMode::Blob, "gone.txt", unrelated),
+            (TreeItemMode::Link, "link", link),
+        ]);
+        let fresh = store.blob(&lines(20, "fresh"));
+        let new = store.tree(vec![
+            (TreeItemMode::Blob, "a.txt", edited),
+            (TreeItemMode::Blob, "b.txt", original),
+            (TreeItemMode::Blob, "new.txt", fresh),
+            (TreeItemMode::Blob, "link", link),
+        ]);
+        let changes = diff_trees(&store, Some(old), Some(new), &DiffOptions::default())
+            .await
+            .unwrap();
+        assert_eq!(
+            summary(&changes),
+            vec![
+                (ChangeKind::Modified, "a.txt".to_string()),
+                (ChangeKind::Added, "b.txt".to_string()),
+                (ChangeKind::Deleted, "gone.txt".to_string()),
+                (ChangeKind::TypeChanged, "link".to_string()),
+                (ChangeKind::Added, "new.txt".to_string()),
+            ]
+        );
+
+        let options = DiffOptions {
+            detect_copies: true,
+            ..Default::default()
+        };
+        let changes = diff_trees(&store, Some(old), Some(new), &options)
+            .await
+            .unwrap();
+        assert_eq!(
+            summary(&changes),
+            vec![
+                (ChangeKind::Modified, "a.txt".to_string()),
+                (ChangeKind::Copied, "a.txt -> b.txt".to_string()),
+                (ChangeKind::Deleted, "gone.txt".to_string()),
+                (ChangeKind::TypeChanged, "link".to_string()),
+                (ChangeKind::Added, "new.txt".to_string()),
+            ]
+        );
+
+        // an edited file is still renamed
+        let old = store.tree(vec![(TreeItemMode::Blob, "a.txt", original)]);
+        let new = store.tree(vec![(TreeItemMode::Blob, "c.txt", edited)]);
+        let changes = diff_trees(&store, Some(old), Some(new), &DiffOptions::default())
+            .await
+            .unwrap();
+        assert_eq!(
+            summary(&changes),
+            vec![(ChangeKind::Renamed, "a.txt -> c.txt".to_string())]
+        );
+        let similarity = changes[0].similarity.unwrap();
+        assert!((90..100).contains(&similarity), "{}", similarity);
+    }
+
+    #[tokio::test]
+    async fn test_diff_trees_include_trees() {
+        let mut store = MemoryStore::default();
+        let file = store.blob("file\n");
+        let dir = store.tree(vec![(TreeItemMode::Blob, "file", file)]);
+        let old = store.tree(vec![(TreeItemMode::Blob, "dir", file)]);
+        let new = store.tree(vec![(TreeItemMode::Tree, "dir", dir)]);
+        let options = DiffOptions {
+            include_trees: true,
+            ..DiffOptions::plain()
+        };
+        let changes = diff_trees(&store, Some(old), Some(new), &options)
+            .await
+            .unwrap();
+        let kinds: Vec<_> = changes
+            .iter()
+            .map(|c| (c.kind, c.path(), c.new.as_ref().map(|e| e.mode)))
+            .collect();
+        assert_eq!(
+            kinds,
+            vec![
+                (ChangeKind::Deleted, "dir", None),
+                (ChangeKind::Added, "dir", Some(TreeItemMode::Tree)),
+                (ChangeKind::Added, "dir/file", Some(TreeItemMode::Blob)),
+            ]
+        );
+        let changes = diff_trees(&store, None, Some(new), &DiffOptions::plain())
+            .await
+            .unwrap();
+        assert_eq!(
+            summary(&changes),
+            vec![(ChangeKind::Added, "dir/file".to_string())]
+        );
+    }
+}
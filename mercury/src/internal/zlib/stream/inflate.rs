@@ -1,8 +1,7 @@
 use std::{io, io::BufRead};
 
 use flate2::{Decompress, FlushDecompress, Status};
-use sha1::{digest::core_api::CoreWrapper, Digest, Sha1};
-
+use crate::hash::{HashKind, ObjectHasher};
 use crate::internal::object::types::ObjectType;
 
 /// ReadBoxed is to unzip information from a  DEFLATE stream,
@@ -17,7 +16,7 @@ pub struct ReadBoxed<R> {
     pub decompressor: Box<Decompress>,
     /// the [`count_hash`] decide whether to calculate the hash value in the [`read`] method
     count_hash: bool,
-    pub hash: CoreWrapper<sha1::Sha1Core>,
+    pub hash: ObjectHasher,
 }
 impl<R> ReadBoxed<R>
 where
@@ -25,8 +24,9 @@ where
 {
     /// Nen a ReadBoxed for zlib read, the Output ReadBoxed is for the Common Object,
     /// but not for the Delta Object,if that ,see new_for_delta method below.
-    pub fn new(inner: R, obj_type: ObjectType, size: usize) -> Self {
-        let mut hash = sha1::Sha1::new();
+    /// The id of the object is hashed with the algorithm of `kind`.
+    pub fn new(inner: R, obj_type: ObjectType, size: usize, kind: HashKind) -> Self {
+        let mut hash = ObjectHasher::new(kind);
         hash.update(obj_type.to_bytes());
         hash.update(b" ");
         hash.update(size.to_string());
@@ -42,7 +42,7 @@ where
     pub fn new_for_delta(inner: R) -> Self {
         ReadBoxed {
             inner,
-            hash: Sha1::new(),
+            hash: ObjectHasher::new(HashKind::default()), // not counted
             count_hash: false,
             decompressor: Box::new(Decompress::new(true)),
         }
@@ -1,7 +1,7 @@
 use std::io;
 use std::io::Read;
 
-use crate::hash::SHA1;
+use crate::hash::{HashKind, SHA1};
 
 pub const SHA1_SIZE: usize = 20;
 
@@ -12,7 +12,12 @@ pub fn read_bytes(file: &mut impl Read, len: usize) -> io::Result<Vec<u8>> {
 }
 
 pub fn read_sha1(file: &mut impl Read) -> io::Result<SHA1> {
-    let mut buf = [0; 20];
+    read_hash(file, HashKind::Sha1)
+}
+
+/// Read a binary object id of `kind`
+pub fn read_hash(file: &mut impl Read, kind: HashKind) -> io::Result<SHA1> {
+    let mut buf = vec![0; kind.size()];
     file.read_exact(&mut buf)?;
-    Ok(SHA1::from_bytes(&buf))
+    SHA1::from_bytes(&buf).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
 }
\ No newline at end of file
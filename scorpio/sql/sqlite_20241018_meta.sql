@@ -0,0 +1,35 @@
+-- the inodes of each Dicfuse root, so a mount finds them again without asking the server
+CREATE TABLE IF NOT EXISTS `inode` (
+    `root` TEXT NOT NULL,
+    `inode` INTEGER NOT NULL,
+    -- 0 for the root
+    `parent` INTEGER NOT NULL,
+    `path` TEXT NOT NULL,
+    -- the id of the blob or tree, '' for the root
+    `oid` TEXT NOT NULL,
+    `mode` TEXT NOT NULL,
+    `content_type` TEXT NOT NULL CHECK (content_type IN ('file', 'directory')),
+    `mtime` INTEGER NOT NULL,
+    -- whether the entries of the directory are in the table
+    `loaded` BOOLEAN NOT NULL,
+    PRIMARY KEY (`root`, `inode`)
+);
+-- the entries of the trees, which never change for a tree id
+CREATE TABLE IF NOT EXISTS `tree_entry` (
+    `tree` TEXT NOT NULL,
+    `name` TEXT NOT NULL,
+    `oid` TEXT NOT NULL,
+    `mode` TEXT NOT NULL,
+    `content_type` TEXT NOT NULL CHECK (content_type IN ('file', 'directory')),
+    PRIMARY KEY (`tree`, `name`)
+);
+-- the commit of the root ref the inodes of a root were checked at
+CREATE TABLE IF NOT EXISTS `head` (
+    `root` TEXT PRIMARY KEY,
+    `commit` TEXT NOT NULL
+);
+-- the sizes of the blobs sent in the tree listings, so a file isn't downloaded to be stat'ed
+CREATE TABLE IF NOT EXISTS `blob_size` (
+    `oid` TEXT PRIMARY KEY,
+    `size` INTEGER NOT NULL
+);
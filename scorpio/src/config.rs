@@ -0,0 +1,74 @@
+//! The configuration of scorpio, read from a TOML file like `scorpio.toml`:
+//!
+//! ```toml
+//! server = "localhost:8000"
+//! cache_dir = "/var/cache/scorpio"
+//! workspace_root = "/var/lib/scorpio"
+//! poll_interval = 30
+//! ```
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(default)]
+pub struct Config {
+    /// the address of the Mega server
+    pub server: String,
+    /// where the contents of the monorepo files are cached
+    pub cache_dir: PathBuf,
+    /// where the workspaces keep their changes, and the daemon its state and control socket
+    pub workspace_root: PathBuf,
+    /// how often the root ref of the monorepo is checked for changes, in seconds
+    pub poll_interval: u64,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        let base = std::env::temp_dir().join("scorpio");
+        Config {
+            server: "localhost:8000".to_string(),
+            cache_dir: base.join("cache"),
+            workspace_root: base.join("workspaces"),
+            poll_interval: 30,
+        }
+    }
+}
+
+impl Config {
+    /// Read the configuration at `path`, the missing fields have their default value.
+    pub fn load(path: &Path) -> io::Result<Config> {
+        let content = fs::read_to_string(path)?;
+        toml::from_str(&content).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e.to_string()))
+    }
+
+    /// The control socket of the daemon.
+    pub fn socket(&self) -> PathBuf {
+        self.workspace_root.join("scorpio.sock")
+    }
+
+    /// The workspaces mounted by the daemon, kept across restarts.
+    pub fn state_file(&self) -> PathBuf {
+        self.workspace_root.join("workspaces.json")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_load() {
+        let path = std::env::temp_dir().join(format!("scorpio-config-{}.toml", std::process::id()));
+        fs::write(&path, "server = \"mega.example.com:8000\"\nworkspace_root = \"/srv/scorpio\"\n").unwrap();
+        let config = Config::load(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(config.server, "mega.example.com:8000");
+        assert_eq!(config.workspace_root, PathBuf::from("/srv/scorpio"));
+        assert_eq!(config.cache_dir, Config::default().cache_dir);
+        assert_eq!(config.socket(), PathBuf::from("/srv/scorpio/scorpio.sock"));
+    }
+}
@@ -0,0 +1,287 @@
+//! The scorpio daemon: it keeps the workspaces mounted and answers on a control socket.
+//!
+//! A request and its response are one line of JSON each, so other tools can query the
+//! workspaces with a few lines of code, e.g. `{"command":"list"}`.
+
+use std::collections::HashMap;
+use std::fs;
+use std::io::{BufRead, BufReader, Error, ErrorKind, Result, Write};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+use serde::{Deserialize, Serialize};
+
+use crate::config::Config;
+use crate::dicfuse::store::MonorepoSource;
+use crate::store::{Mount, WorkSpace};
+
+/// A workspace: a monorepo directory mounted at a mountpoint.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct WorkspaceInfo {
+    /// the name of the workspace, and of the directory keeping its changes
+    pub name: String,
+    /// the monorepo directory, like `/third-party/mega`
+    pub path: String,
+    pub mountpoint: PathBuf,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct WorkspaceStatus {
+    #[serde(flatten)]
+    pub info: WorkspaceInfo,
+    /// whether the daemon serves it now, a failed mount is kept to be retried
+    pub mounted: bool,
+    /// the number of files written or deleted in the workspace
+    pub changed_files: usize,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(tag = "command", rename_all = "snake_case")]
+pub enum Request {
+    Mount(WorkspaceInfo),
+    Umount { name: String },
+    List,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum Response {
+    Done,
+    Workspaces(Vec<WorkspaceStatus>),
+    Error(String),
+}
+
+/// Send a request to the daemon listening on `socket`.
+pub fn request(socket: &Path, request: &Request) -> Result<Response> {
+    let mut stream = UnixStream::connect(socket)
+        .map_err(|e| Error::new(e.kind(), format!("can't connect to the daemon at {:?}: {}", socket, e)))?;
+    writeln!(stream, "{}", serde_json::to_string(request)?)?;
+    let mut line = String::new();
+    BufReader::new(stream).read_line(&mut line)?;
+    Ok(serde_json::from_str(&line)?)
+}
+
+/// The workspaces in `state_file`, none if it doesn't exist yet.
+pub fn load_workspaces(state_file: &Path) -> Result<Vec<WorkspaceInfo>> {
+    match fs::read(state_file) {
+        Ok(content) => Ok(serde_json::from_slice(&content)?),
+        Err(e) if e.kind() == ErrorKind::NotFound => Ok(Vec::new()),
+        Err(e) => Err(e),
+    }
+}
+
+/// The number of files in the upper directory, whiteouts included.
+fn count_files(dir: &Path) -> usize {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return 0;
+    };
+    entries
+        .filter_map(|entry| entry.ok())
+        .map(|entry| match entry.file_type() {
+            Ok(t) if t.is_dir() => count_files(&entry.path()),
+            _ => 1,
+        })
+        .sum()
+}
+
+pub struct Daemon {
+    config: Config,
+    source: Arc<dyn MonorepoSource>,
+    workspaces: Mutex<HashMap<String, (WorkspaceInfo, Option<Mount>)>>,
+}
+
+impl Daemon {
+    pub fn new(config: Config, source: Arc<dyn MonorepoSource>) -> Self {
+        Daemon {
+            config,
+            source,
+            workspaces: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn workspace(&self, info: &WorkspaceInfo) -> WorkSpace {
+        WorkSpace::new(&info.path, &self.config.workspace_root.join(&info.name))
+    }
+
+    /// Mount the workspaces of the last run again.
+    pub fn restore(&self) -> Result<()> {
+        for info in load_workspaces(&self.config.state_file())? {
+            let mount = self
+                .mount_workspace(&info)
+                .inspect_err(|e| error!("failed to mount {}: {}", info.name, e))
+                .ok();
+            self.workspaces.lock().unwrap().insert(info.name.clone(), (info, mount));
+        }
+        Ok(())
+    }
+
+    fn save(&self, workspaces: &HashMap<String, (WorkspaceInfo, Option<Mount>)>) -> Result<()> {
+        let mut infos: Vec<&WorkspaceInfo> = workspaces.values().map(|(info, _)| info).collect();
+        infos.sort_by(|a, b| a.name.cmp(&b.name));
+        fs::create_dir_all(&self.config.workspace_root)?;
+        let tmp = self.config.state_file().with_extension("tmp");
+        fs::write(&tmp, serde_json::to_vec_pretty(&infos)?)?;
+        fs::rename(tmp, self.config.state_file())
+    }
+
+    fn mount_workspace(&self, info: &WorkspaceInfo) -> Result<Mount> {
+        fs::create_dir_all(&info.mountpoint)?;
+        self.workspace(info)
+            .mount(self.source.clone(), &self.config, &info.mountpoint)
+    }
+
+    pub fn mount(&self, info: WorkspaceInfo) -> Result<()> {
+        if info.name.is_empty() || info.name.starts_with('.') || info.name.contains('/') {
+            return Err(Error::new(ErrorKind::InvalidInput, format!("invalid workspace name {:?}", info.name)));
+        }
+        let mut workspaces = self.workspaces.lock().unwrap();
+        if workspaces.contains_key(&info.name) {
+            return Err(Error::new(ErrorKind::AlreadyExists, format!("workspace {} exists", info.name)));
+        }
+        if let Some((other, _)) = workspaces.values().find(|(other, _)| other.mountpoint == info.mountpoint) {
+            return Err(Error::new(
+                ErrorKind::AlreadyExists,
+                format!("{:?} is the mountpoint of {}", info.mountpoint, other.name),
+            ));
+        }
+        let mount = self.mount_workspace(&info)?;
+        workspaces.insert(info.name.clone(), (info, Some(mount)));
+        self.save(&workspaces)
+    }
+
+    /// Unmount a workspace, its changes are kept until it is mounted again.
+    pub fn umount(&self, name: &str) -> Result<()> {
+        let mut workspaces = self.workspaces.lock().unwrap();
+        let (_, mount) = workspaces
+            .remove(name)
+            .ok_or_else(|| Error::new(ErrorKind::NotFound, format!("no workspace {}", name)))?;
+        self.save(&workspaces)?;
+        mount.map_or(Ok(()), Mount::umount)
+    }
+
+    pub fn list(&self) -> Vec<WorkspaceStatus> {
+        let workspaces = self.workspaces.lock().unwrap();
+        let mut list: Vec<WorkspaceStatus> = workspaces
+            .values()
+            .map(|(info, mount)| WorkspaceStatus {
+                info: info.clone(),
+                mounted: mount.is_some(),
+                changed_files: count_files(&self.workspace(info).upper),
+            })
+            .collect();
+        list.sort_by(|a, b| a.info.name.cmp(&b.info.name));
+        list
+    }
+
+    /// Unmount all the workspaces before exiting, they are mounted again by [`Daemon::restore`].
+    pub fn shutdown(&self) {
+        for (name, (_, mount)) in self.workspaces.lock().unwrap().iter_mut() {
+            if let Some(mount) = mount.take() {
+                if let Err(e) = mount.umount() {
+                    error!("failed to unmount {}: {}", name, e);
+                }
+            }
+        }
+    }
+
+    pub fn handle(&self, request: Request) -> Response {
+        let result = match request {
+            Request::Mount(info) => self.mount(info),
+            Request::Umount { name } => self.umount(&name),
+            Request::List => return Response::Workspaces(self.list()),
+        };
+        match result {
+            Ok(()) => Response::Done,
+            Err(e) => Response::Error(e.to_string()),
+        }
+    }
+
+    fn serve_client(&self, stream: UnixStream) -> Result<()> {
+        let mut writer = stream.try_clone()?;
+        for line in BufReader::new(stream).lines() {
+            let response = match serde_json::from_str(&line?) {
+                Ok(request) => self.handle(request),
+                Err(e) => Response::Error(format!("invalid request: {}", e)),
+            };
+            writeln!(writer, "{}", serde_json::to_string(&response)?)?;
+        }
+        Ok(())
+    }
+
+    /// Answer the requests on the control socket, until the process exits.
+    pub fn serve(self: Arc<Self>, listener: UnixListener) -> Result<()> {
+        for stream in listener.incoming() {
+            let stream = stream?;
+            let daemon = self.clone();
+            thread::spawn(move || {
+                if let Err(e) = daemon.serve_client(stream) {
+                    warn!("control connection failed: {}", e);
+                }
+            });
+        }
+        Ok(())
+    }
+
+    /// Listen on the control socket of the configuration, replacing the one of a dead daemon.
+    pub fn bind(&self) -> Result<UnixListener> {
+        let socket = self.config.socket();
+        fs::create_dir_all(&self.config.workspace_root)?;
+        if UnixStream::connect(&socket).is_ok() {
+            return Err(Error::new(ErrorKind::AddrInUse, format!("a daemon is listening on {:?}", socket)));
+        }
+        let _ = fs::remove_file(&socket);
+        UnixListener::bind(socket)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::store::local::LocalSource;
+
+    #[test]
+    fn test_control_socket() {
+        let root = std::env::temp_dir().join(format!("scorpio-daemon-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        let config = Config {
+            workspace_root: root.clone(),
+            cache_dir: root.join("cache"),
+            ..Default::default()
+        };
+        let daemon = Arc::new(Daemon::new(config.clone(), Arc::new(LocalSource::new(&root))));
+        let listener = daemon.bind().unwrap();
+        assert!(daemon.bind().is_err());
+        thread::spawn(move || daemon.serve(listener));
+
+        let socket = config.socket();
+        assert_eq!(request(&socket, &Request::List).unwrap(), Response::Workspaces(vec![]));
+        let umount = Request::Umount { name: "ws".to_string() };
+        assert!(matches!(request(&socket, &umount).unwrap(), Response::Error(_)));
+        let mount = Request::Mount(WorkspaceInfo {
+            name: "../ws".to_string(),
+            path: "/".to_string(),
+            mountpoint: root.join("mnt"),
+        });
+        assert!(matches!(request(&socket, &mount).unwrap(), Response::Error(_)));
+
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn test_request_format() {
+        let request: Request = serde_json::from_str(r#"{"command":"umount","name":"ws"}"#).unwrap();
+        assert_eq!(request, Request::Umount { name: "ws".to_string() });
+        let request: Request =
+            serde_json::from_str(r#"{"command":"mount","name":"ws","path":"/a","mountpoint":"/mnt"}"#).unwrap();
+        assert_eq!(
+            request,
+            Request::Mount(WorkspaceInfo {
+                name: "ws".to_string(),
+                path: "/a".to_string(),
+                mountpoint: PathBuf::from("/mnt"),
+            })
+        );
+    }
+}
@@ -3,6 +3,8 @@ use std::time::Duration;
 use libc::stat64;
 use fuse_backend_rs::{abi::fuse_abi::Attr, api::filesystem::Entry};
 const BLOCK_SIZE: u32 = 512;
+/// How long the kernel keeps entries and attributes, short enough to see a refresh soon.
+const TTL: Duration = Duration::from_secs(1);
 fn default_stat64(inode:u64) -> stat64 {
     let t = Attr{
         ino: inode,                       // Default inode number
@@ -26,29 +28,28 @@ fn default_stat64(inode:u64) -> stat64 {
     t.into()
 }
 
-pub fn default_file_entry(inode:u64) -> Entry {
+/// The entry of a file or directory, `st_mode` has its type and permissions.
+pub fn item_entry(inode:u64, st_mode:u32, size:u64, mtime:u64) -> Entry {
+    let mut attr = default_stat64(inode);
+    attr.st_mode = st_mode as _;
+    attr.st_size = size as _;
+    attr.st_blocks = size.div_ceil(BLOCK_SIZE as u64) as _;
+    attr.st_atime = mtime as _;
+    attr.st_mtime = mtime as _;
+    attr.st_ctime = mtime as _;
+    if st_mode & libc::S_IFMT == libc::S_IFDIR {
+        attr.st_nlink = 2;
+    }
     Entry{
         inode,
         generation: 0,
-        attr:  default_stat64(inode),
+        attr,
         attr_flags: 0,
-        attr_timeout: Duration::from_secs(u64::MAX),
-        entry_timeout: Duration::from_secs(u64::MAX),
-    } // Return a default Entry instance
+        attr_timeout: TTL,
+        entry_timeout: TTL,
+    }
 }
 
-pub fn default_dic_entry(inode:u64) -> Entry {
-    let mut d = default_stat64(inode);
-    d.st_mode = 0o555;
-    Entry{
-        inode,
-        generation: 0,
-        attr:  d,
-        attr_flags: 0,
-        attr_timeout: Duration::from_secs(u64::MAX),
-        entry_timeout: Duration::from_secs(u64::MAX),
-    } // Return a default Dictionary Entry instance
-}
 // pub struct stat64 {
 //     pub st_dev: ::dev_t,          // Device ID of the device containing the file
 //     pub st_ino: ::ino64_t,        // Inode number of the file
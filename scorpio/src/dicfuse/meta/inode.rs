@@ -0,0 +1,21 @@
+use sea_orm::entity::prelude::*;
+
+#[derive(Clone, Debug, PartialEq, DeriveEntityModel, Eq)]
+#[sea_orm(table_name = "inode")]
+pub struct Model {
+    #[sea_orm(primary_key, auto_increment = false)]
+    pub root: String,
+    #[sea_orm(primary_key, auto_increment = false)]
+    pub inode: i64,
+    pub parent: i64,
+    pub path: String,
+    pub oid: String,
+    pub mode: String,
+    pub content_type: String,
+    pub mtime: i64,
+    pub loaded: bool,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {}
+impl ActiveModelBehavior for ActiveModel {}
@@ -0,0 +1,179 @@
+//! The metadata of Dicfuse kept in sqlite, next to the cached blobs.
+//!
+//! The inodes of a root are kept so a mount finds them again without asking the server,
+//! and the entries of the trees by their ids, so a directory is loaded from the server once
+//! for each id it takes. The sizes of the blobs are kept by their ids too, a file is only
+//! downloaded when it is read.
+
+pub mod blob_size;
+pub mod head;
+pub mod inode;
+pub mod tree_entry;
+
+use std::future::Future;
+use std::io;
+use std::path::Path;
+
+use sea_orm::sea_query::OnConflict;
+use sea_orm::{
+    ActiveValue::Set, ColumnTrait, ConnectOptions, ConnectionTrait, Database, DatabaseConnection,
+    DbErr, EntityTrait, IntoActiveModel, QueryFilter, QueryOrder, Statement, TransactionTrait,
+};
+
+/// sqlite allows 32766 variables in a statement
+const BATCH_SIZE: usize = 1000;
+
+fn db_error(e: DbErr) -> io::Error {
+    io::Error::other(format!("metadata cache error: {}", e))
+}
+
+/// The metadata of the Dicfuse root `root`, its calls block on a runtime of its own.
+pub struct MetaStore {
+    runtime: tokio::runtime::Runtime,
+    conn: DatabaseConnection,
+    root: String,
+}
+
+impl MetaStore {
+    /// Open the database at `db_path`, it is created when it doesn't exist.
+    pub fn open(db_path: &Path, root: &str) -> io::Result<MetaStore> {
+        let runtime = tokio::runtime::Builder::new_multi_thread()
+            .worker_threads(1)
+            .enable_all()
+            .build()?;
+        let mut option = ConnectOptions::new(format!("sqlite://{}?mode=rwc", db_path.display()));
+        option.sqlx_logging(false);
+        let conn = runtime.block_on(async {
+            let conn = Database::connect(option).await?;
+            // `include_str!` will expand the file while compiling, so `.sql` is not needed after that
+            const SETUP_SQL: &str = include_str!("../../../sql/sqlite_20241018_meta.sql");
+            let backend = conn.get_database_backend();
+            conn.execute(Statement::from_string(backend, SETUP_SQL)).await?;
+            Ok(conn)
+        });
+        Ok(MetaStore {
+            runtime,
+            conn: conn.map_err(db_error)?,
+            root: root.to_owned(),
+        })
+    }
+
+    fn block_on<T>(&self, future: impl Future<Output = Result<T, DbErr>>) -> io::Result<T> {
+        self.runtime.block_on(future).map_err(db_error)
+    }
+
+    /// The inodes of the root, ordered by number so the parents come first.
+    pub fn inodes(&self) -> io::Result<Vec<inode::Model>> {
+        self.block_on(
+            inode::Entity::find()
+                .filter(inode::Column::Root.eq(&self.root))
+                .order_by_asc(inode::Column::Inode)
+                .all(&self.conn),
+        )
+    }
+
+    /// Delete the inodes `removed` and write `saved`, at once.
+    pub fn update_inodes(&self, removed: &[u64], saved: Vec<inode::Model>) -> io::Result<()> {
+        if removed.is_empty() && saved.is_empty() {
+            return Ok(());
+        }
+        self.block_on(async {
+            let txn = self.conn.begin().await?;
+            for chunk in removed.chunks(BATCH_SIZE) {
+                inode::Entity::delete_many()
+                    .filter(inode::Column::Root.eq(&self.root))
+                    .filter(inode::Column::Inode.is_in(chunk.iter().map(|i| *i as i64)))
+                    .exec(&txn)
+                    .await?;
+            }
+            for chunk in saved.chunks(BATCH_SIZE) {
+                inode::Entity::insert_many(chunk.iter().cloned().map(IntoActiveModel::into_active_model))
+                    .on_conflict(
+                        OnConflict::columns([inode::Column::Root, inode::Column::Inode])
+                            .update_columns([
+                                inode::Column::Parent,
+                                inode::Column::Path,
+                                inode::Column::Oid,
+                                inode::Column::Mode,
+                                inode::Column::ContentType,
+                                inode::Column::Mtime,
+                                inode::Column::Loaded,
+                            ])
+                            .to_owned(),
+                    )
+                    .exec_without_returning(&txn)
+                    .await?;
+            }
+            txn.commit().await
+        })
+    }
+
+    /// The entries of the tree `oid`, `None` when it was never read.
+    pub fn tree(&self, oid: &str) -> io::Result<Option<Vec<tree_entry::Model>>> {
+        let entries = self.block_on(
+            tree_entry::Entity::find()
+                .filter(tree_entry::Column::Tree.eq(oid))
+                .all(&self.conn),
+        )?;
+        // git keeps no empty tree, so it is one never read
+        Ok(Some(entries).filter(|e| !e.is_empty()))
+    }
+
+    pub fn save_tree(&self, entries: Vec<tree_entry::Model>) -> io::Result<()> {
+        self.block_on(async {
+            for chunk in entries.chunks(BATCH_SIZE) {
+                tree_entry::Entity::insert_many(chunk.iter().cloned().map(IntoActiveModel::into_active_model))
+                    .on_conflict(
+                        OnConflict::columns([tree_entry::Column::Tree, tree_entry::Column::Name])
+                            .do_nothing()
+                            .to_owned(),
+                    )
+                    .exec_without_returning(&self.conn)
+                    .await?;
+            }
+            Ok(())
+        })
+    }
+
+    /// The size of the blob `oid`, `None` when the server didn't send it.
+    pub fn blob_size(&self, oid: &str) -> io::Result<Option<u64>> {
+        let size = self.block_on(blob_size::Entity::find_by_id(oid.to_owned()).one(&self.conn))?;
+        Ok(size.map(|s| s.size as u64))
+    }
+
+    pub fn save_blob_sizes(&self, sizes: Vec<blob_size::Model>) -> io::Result<()> {
+        self.block_on(async {
+            for chunk in sizes.chunks(BATCH_SIZE) {
+                blob_size::Entity::insert_many(chunk.iter().cloned().map(IntoActiveModel::into_active_model))
+                    .on_conflict(OnConflict::column(blob_size::Column::Oid).do_nothing().to_owned())
+                    .exec_without_returning(&self.conn)
+                    .await?;
+            }
+            Ok(())
+        })
+    }
+
+    /// The commit of the root ref when the inodes were checked last.
+    pub fn head(&self) -> io::Result<Option<String>> {
+        let head = self.block_on(head::Entity::find_by_id(self.root.clone()).one(&self.conn))?;
+        Ok(head.map(|h| h.commit))
+    }
+
+    pub fn set_head(&self, commit: &str) -> io::Result<()> {
+        let head = head::ActiveModel {
+            root: Set(self.root.clone()),
+            commit: Set(commit.to_owned()),
+        };
+        self.block_on(async {
+            head::Entity::insert(head)
+                .on_conflict(
+                    OnConflict::column(head::Column::Root)
+                        .update_column(head::Column::Commit)
+                        .to_owned(),
+                )
+                .exec_without_returning(&self.conn)
+                .await?;
+            Ok(())
+        })
+    }
+}
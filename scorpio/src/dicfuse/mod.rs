@@ -1,26 +1,37 @@
 
+pub mod meta;
 pub mod model;
-mod store;
+pub mod store;
 mod fuse;
 
 use std::{sync::Arc, time::Duration};
-use std::io::Result;
-use fuse_backend_rs::{abi::fuse_abi::FsOptions, api::filesystem::{Context, Entry, FileSystem}};
+use std::io::{Result, Write};
+use fuse_backend_rs::{abi::fuse_abi::FsOptions, api::filesystem::{Context, DirEntry, Entry, FileSystem, Layer, ZeroCopyWriter}};
 use tokio::task::JoinHandle;
 
-use store::{DictionaryStore, IntoEntry};
+use store::{DicItem, DictionaryStore};
+use crate::config::Config;
 
-struct Dicfuse{
+pub struct Dicfuse{
     store: Arc<DictionaryStore>,
     //runtime: Arc<tokio::runtime::Runtime>,
 }
 #[allow(unused)]
 impl Dicfuse{
-    pub fn new() -> Self {
-        Self {
-            store: DictionaryStore::new().into(), // Assuming DictionaryStore has a new() method
+    pub fn new(config: &Config) -> Result<Self> {
+        let dicfuse = Self {
+            store: DictionaryStore::new(config)?.into(),
             //runtime: tokio::runtime::Runtime::new().unwrap().into(), // Create a new runtime
-        }
+        };
+        dicfuse.watch(Duration::from_secs(config.poll_interval));
+        Ok(dicfuse)
+    }
+    pub fn with_store(store: DictionaryStore) -> Self {
+        Self { store: store.into() }
+    }
+    /// Follow the changes of the monorepo, checking its root ref every `interval`.
+    pub fn watch(&self, interval: Duration) {
+        self.store.watch(interval);
     }
     fn spawn<F, Fut, O>(&self, f: F) -> JoinHandle<O>
     where
@@ -31,6 +42,34 @@ impl Dicfuse{
         let inner = self.store.clone();
         tokio::task::spawn(f(inner))
     }
+    /// The entries of a directory with `.` and `..` first, and their offsets.
+    fn dir_entries(&self, inode: u64, offset: u64) -> Result<Vec<(u64, Arc<DicItem>, String)>> {
+        let item = self.store.get_inode(inode)?;
+        let mut entries = vec![
+            (item.clone(), ".".to_string()),
+            (self.store.get_inode(item.get_parent())?, "..".to_string()),
+        ];
+        entries.extend(self.store.read_dir(inode)?.into_iter().map(|c| {
+            let name = c.get_name();
+            (c, name)
+        }));
+        Ok(entries
+            .into_iter()
+            .enumerate()
+            .skip(offset as usize)
+            .map(|(i, (item, name))| (i as u64 + 1, item, name))
+            .collect())
+    }
+}
+
+fn dir_entry<'a>(offset: u64, item: &DicItem, name: &'a str) -> DirEntry<'a> {
+    DirEntry {
+        ino: item.get_inode(),
+        offset,
+        // the file type bits of the mode, as a `DT_*` value
+        type_: (item.st_mode() & libc::S_IFMT) >> 12,
+        name: name.as_bytes(),
+    }
 }
 
 
@@ -41,7 +80,7 @@ impl FileSystem for Dicfuse{
     type Handle = u64;
     
     fn init(&self, capable:FsOptions) -> Result<FsOptions> {
-        self.store.import();
+        // the directories are loaded when they are read
         Ok(fuse_backend_rs::abi::fuse_abi::FsOptions::empty())
     }
     
@@ -49,11 +88,8 @@ impl FileSystem for Dicfuse{
     
     fn lookup(&self, ctx: &Context, parent: Self::Inode, name: &std::ffi::CStr) -> Result<Entry> {
         let store = self.store.clone();
-        let mut ppath  = store.find_path(parent).ok_or_else(|| std::io::Error::from_raw_os_error(libc::ENODATA))?;
-        let pitem  = store.get_inode(parent)?;
-        ppath.push(name.to_string_lossy().into_owned());
-        let chil = store.get_by_path(&ppath.to_string())?;
-        Ok(chil.into_entry())
+        let chil = store.lookup(parent, &name.to_string_lossy())?;
+        store.entry(&chil)
     }
     
 
@@ -72,9 +108,8 @@ impl FileSystem for Dicfuse{
         handle: Option<Self::Handle>,
     ) -> std::io::Result<(libc::stat64, std::time::Duration)> {
         let store = self.store.clone();
-        let i = store.find_path(inode).ok_or_else(|| std::io::Error::from_raw_os_error(libc::ENODATA))?;
-        let entry  = fuse::default_file_entry(inode);
-        Ok((entry.attr,Duration::from_secs(u64::MAX)))
+        let entry = store.entry(&store.get_inode(inode)?)?;
+        Ok((entry.attr, entry.attr_timeout))
     }
     
     fn setattr(
@@ -149,6 +184,9 @@ impl FileSystem for Dicfuse{
         flags: u32,
         fuse_flags: u32,
     ) -> std::io::Result<(Option<Self::Handle>, fuse_backend_rs::abi::fuse_abi::OpenOptions, Option<u32>)> {
+        if flags & libc::O_ACCMODE as u32 != libc::O_RDONLY as u32 {
+            return Err(std::io::Error::from_raw_os_error(libc::EROFS));
+        }
         // Matches the behavior of libfuse.
         Ok((None, fuse_backend_rs::abi::fuse_abi::OpenOptions::empty(), None))
     }
@@ -205,7 +243,8 @@ impl FileSystem for Dicfuse{
         flock_release: bool,
         lock_owner: Option<u64>,
     ) -> std::io::Result<()> {
-        Err(std::io::Error::from_raw_os_error(libc::ENOSYS))
+        // nothing is kept open
+        Ok(())
     }
     
     fn statfs(&self, ctx: &Context, inode: Self::Inode) -> std::io::Result<libc::statvfs64> {
@@ -236,7 +275,8 @@ impl FileSystem for Dicfuse{
         name: &std::ffi::CStr,
         size: u32,
     ) -> std::io::Result<fuse_backend_rs::api::filesystem::GetxattrReply> {
-        Err(std::io::Error::from_raw_os_error(libc::ENOSYS))
+        // no attribute is set, so an overlay sees no opaque directory here
+        Err(std::io::Error::from_raw_os_error(libc::ENODATA))
     }
     
     fn listxattr(
@@ -267,7 +307,13 @@ impl FileSystem for Dicfuse{
         offset: u64,
         add_entry: &mut dyn FnMut(fuse_backend_rs::api::filesystem::DirEntry) -> std::io::Result<usize>,
     ) -> std::io::Result<()> {
-        Err(std::io::Error::from_raw_os_error(libc::ENOSYS))
+        for (offset, item, name) in self.dir_entries(inode, offset)? {
+            // the buffer is full
+            if add_entry(dir_entry(offset, &item, &name))? == 0 {
+                break;
+            }
+        }
+        Ok(())
     }
     
     fn readdirplus(
@@ -279,24 +325,78 @@ impl FileSystem for Dicfuse{
         offset: u64,
         add_entry: &mut dyn FnMut(fuse_backend_rs::api::filesystem::DirEntry, Entry) -> std::io::Result<usize>,
     ) -> std::io::Result<()> {
-        Err(std::io::Error::from_raw_os_error(libc::ENOSYS))
+        for (offset, item, name) in self.dir_entries(inode, offset)? {
+            let entry = self.store.entry(&item)?;
+            if add_entry(dir_entry(offset, &item, &name), entry)? == 0 {
+                break;
+            }
+        }
+        Ok(())
     }
-    
+
+    fn read(
+        &self,
+        ctx: &Context,
+        inode: Self::Inode,
+        handle: Self::Handle,
+        w: &mut dyn ZeroCopyWriter,
+        size: u32,
+        offset: u64,
+        lock_owner: Option<u64>,
+        flags: u32,
+    ) -> std::io::Result<usize> {
+        let data = self.store.read_file(inode, offset, size)?;
+        w.write_all(&data)?;
+        Ok(data.len())
+    }
+
+    fn readlink(&self, ctx: &Context, inode: Self::Inode) -> std::io::Result<Vec<u8>> {
+        let item = self.store.get_inode(inode)?;
+        if !item.is_link() {
+            return Err(std::io::Error::from_raw_os_error(libc::EINVAL));
+        }
+        self.store.read_file(inode, 0, u32::MAX)
+    }
+
     fn access(&self, ctx: &Context, inode: Self::Inode, mask: u32) -> std::io::Result<()> {
-        Err(std::io::Error::from_raw_os_error(libc::ENOSYS))
+        let item = self.store.get_inode(inode)?;
+        if mask & libc::W_OK as u32 != 0 {
+            return Err(std::io::Error::from_raw_os_error(libc::EROFS));
+        }
+        // everyone may read, and execute what has an exec bit
+        if mask & libc::X_OK as u32 != 0 && item.st_mode() & 0o111 == 0 {
+            return Err(std::io::Error::from_raw_os_error(libc::EACCES));
+        }
+        Ok(())
     }
     
 
 }
 
+/// Dicfuse is the read-only lower layer of a workspace overlay.
+impl Layer for Dicfuse {
+    fn root_inode(&self) -> Self::Inode {
+        1
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use std::{io, path::Path, sync::Arc,thread};
+    use std::collections::HashMap;
+    use std::ffi::CString;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Mutex;
 
+    use fuse_backend_rs::abi::fuse_abi::FsOptions;
+    use fuse_backend_rs::api::filesystem::{Context, FileSystem, ZeroCopyWriter};
+    use fuse_backend_rs::file_traits::FileReadWriteVolatile;
     use fuse_backend_rs::{api::server::Server, transport::{FuseChannel, FuseSession}};
     use signal_hook::{consts::TERM_SIGNALS, iterator::Signals};
 
     use super::Dicfuse;
+    use crate::config::Config;
+    use super::store::{DictionaryStore, Item, MonorepoSource};
 
 
     pub struct DicFuseServer {
@@ -340,7 +440,7 @@ mod tests {
 
     #[test]
     fn test_svc_loop_success() {
-        let dicfuse = Arc::new(Dicfuse::new());
+        let dicfuse = Arc::new(Dicfuse::new(&Config::default()).unwrap());
         // Create fuse session
         let mut se = FuseSession::new(Path::new(&"/home/luxian/ccode/mega/dictest"), "dic", "", true).unwrap();
         se.mount().unwrap();
@@ -369,5 +469,300 @@ mod tests {
         let _ = handle.join();
     }
 
+    /// A monorepo in memory, counting the directories and blobs read from it.
+    #[derive(Default)]
+    struct MemorySource {
+        dirs: Mutex<HashMap<String, Vec<Item>>>,
+        /// the entries of the directories by the ids they had
+        trees: Mutex<HashMap<String, Vec<Item>>>,
+        blobs: Mutex<HashMap<String, Vec<u8>>>,
+        head: Mutex<Option<String>>,
+        next_oid: AtomicUsize,
+        lists: AtomicUsize,
+        reads: AtomicUsize,
+    }
+
+    fn split(path: &str) -> (&str, &str) {
+        let (parent, name) = path.rsplit_once('/').unwrap();
+        (if parent.is_empty() { "/" } else { parent }, name)
+    }
+
+    impl MemorySource {
+        fn new_oid(&self) -> String {
+            format!("{:040x}", self.next_oid.fetch_add(1, Ordering::SeqCst) + 1)
+        }
+
+        fn add(&self, path: &str, mode: &str, content: Option<&str>) {
+            let (parent, name) = split(path);
+            let oid = self.new_oid();
+            let item = Item {
+                name: name.to_string(),
+                path: path.to_string(),
+                content_type: if mode == "40000" { "directory" } else { "file" }.to_string(),
+                oid: oid.clone(),
+                mode: mode.to_string(),
+                size: content.map(|c| c.len() as u64),
+                mtime: 1_700_000_000,
+            };
+            self.dirs.lock().unwrap().entry(parent.to_string()).or_default().push(item);
+            if let Some(content) = content {
+                self.blobs.lock().unwrap().insert(oid, content.as_bytes().to_vec());
+            }
+            self.rehash(parent);
+        }
+
+        fn remove(&self, path: &str) {
+            let (parent, _) = split(path);
+            self.dirs.lock().unwrap().get_mut(parent).unwrap().retain(|item| item.path != path);
+            self.rehash(parent);
+        }
+
+        /// Give new ids to the directory `path` and the ones above it, like a commit does.
+        fn rehash(&self, mut path: &str) {
+            while path != "/" {
+                let (parent, _) = split(path);
+                let oid = self.new_oid();
+                let mut dirs = self.dirs.lock().unwrap();
+                if let Some(item) = dirs.get_mut(parent).and_then(|items| items.iter_mut().find(|i| i.path == path)) {
+                    item.oid = oid.clone();
+                }
+                let items = dirs.get(path).cloned().unwrap_or_default();
+                self.trees.lock().unwrap().insert(oid, items);
+                path = parent;
+            }
+        }
+
+        fn set_head(&self, commit: &str) {
+            *self.head.lock().unwrap() = Some(commit.to_string());
+        }
+    }
+
+    impl MonorepoSource for MemorySource {
+        fn list(&self, path: &str) -> io::Result<Vec<Item>> {
+            self.lists.fetch_add(1, Ordering::SeqCst);
+            Ok(self.dirs.lock().unwrap().get(path).cloned().unwrap_or_default())
+        }
+
+        fn list_tree(&self, _path: &str, oid: &str) -> io::Result<Vec<Item>> {
+            self.lists.fetch_add(1, Ordering::SeqCst);
+            self.trees
+                .lock()
+                .unwrap()
+                .get(oid)
+                .cloned()
+                .ok_or(io::Error::from(io::ErrorKind::NotFound))
+        }
+
+        fn read_blob(&self, _path: &str, oid: &str) -> io::Result<Vec<u8>> {
+            self.reads.fetch_add(1, Ordering::SeqCst);
+            self.blobs
+                .lock()
+                .unwrap()
+                .get(oid)
+                .cloned()
+                .ok_or(io::Error::from(io::ErrorKind::NotFound))
+        }
+
+        fn head(&self) -> io::Result<Option<String>> {
+            Ok(self.head.lock().unwrap().clone())
+        }
+    }
+
+    struct VecWriter(Vec<u8>);
+
+    impl io::Write for VecWriter {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl ZeroCopyWriter for VecWriter {
+        fn write_from(
+            &mut self,
+            _f: &mut dyn FileReadWriteVolatile,
+            _count: usize,
+            _off: u64,
+        ) -> io::Result<usize> {
+            // Dicfuse writes the data it read, never from a file
+            Err(io::Error::from_raw_os_error(libc::ENOSYS))
+        }
+
+        fn available_bytes(&self) -> usize {
+            usize::MAX
+        }
+    }
+
+    fn read_dir(dicfuse: &Dicfuse, inode: u64, offset: u64) -> Vec<String> {
+        let mut names = vec![];
+        dicfuse
+            .readdir(&Context::new(), inode, 0, 4096, offset, &mut |entry| {
+                names.push(String::from_utf8(entry.name.to_vec()).unwrap());
+                Ok(1)
+            })
+            .unwrap();
+        names
+    }
+
+    #[test]
+    fn test_read_only_view() {
+        let source = MemorySource::default();
+        source.add("/README.md", "100644", Some("hello\n"));
+        source.add("/bin", "40000", None);
+        source.add("/bin/run.sh", "100755", Some("#!/bin/sh\n"));
+        source.add("/link", "120000", Some("README.md"));
+        source.add("/sub", "160000", None);
+        let source = Arc::new(source);
+        let cache_dir = std::env::temp_dir().join(format!("scorpio-dicfuse-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&cache_dir);
+        let dicfuse = Dicfuse::with_store(DictionaryStore::with_source(source.clone(), cache_dir.clone(), "/").unwrap());
+        let ctx = Context::new();
+        dicfuse.init(FsOptions::empty()).unwrap();
+
+        assert_eq!(read_dir(&dicfuse, 1, 0), [".", "..", "README.md", "bin", "link", "sub"]);
+        // a listing goes on from the offset of the last entry
+        assert_eq!(read_dir(&dicfuse, 1, 3), ["bin", "link", "sub"]);
+
+        let lookup = |parent: u64, name: &str| dicfuse.lookup(&ctx, parent, &CString::new(name).unwrap());
+        let readme = lookup(1, "README.md").unwrap();
+        assert_eq!(readme.attr.st_mode, libc::S_IFREG | 0o444);
+        assert_eq!(readme.attr.st_size, 6);
+        assert_eq!(readme.attr.st_mtime, 1_700_000_000);
+        let bin = lookup(1, "bin").unwrap();
+        assert_eq!(bin.attr.st_mode, libc::S_IFDIR | 0o555);
+        assert_eq!(read_dir(&dicfuse, bin.inode, 0), [".", "..", "run.sh"]);
+        let run = lookup(bin.inode, "run.sh").unwrap();
+        assert_eq!(run.attr.st_mode, libc::S_IFREG | 0o555);
+        let link = lookup(1, "link").unwrap();
+        assert_eq!(link.attr.st_mode, libc::S_IFLNK | 0o777);
+        assert_eq!(dicfuse.readlink(&ctx, link.inode).unwrap(), b"README.md");
+        let sub = lookup(1, "sub").unwrap();
+        assert_eq!(sub.attr.st_mode, libc::S_IFDIR | 0o555);
+        assert_eq!(read_dir(&dicfuse, sub.inode, 0), [".", ".."]);
+        let missing = lookup(1, "missing").unwrap_err();
+        assert_eq!(missing.raw_os_error(), Some(libc::ENOENT));
+
+        let (attr, _) = dicfuse.getattr(&ctx, run.inode, None).unwrap();
+        assert_eq!(attr.st_size, 10);
+        let mut w = VecWriter(vec![]);
+        let read = dicfuse.read(&ctx, readme.inode, 0, &mut w, 3, 1, None, 0).unwrap();
+        assert_eq!((read, w.0.as_slice()), (3, b"ell".as_slice()));
+
+        let err = dicfuse.access(&ctx, readme.inode, libc::W_OK as u32).unwrap_err();
+        assert_eq!(err.raw_os_error(), Some(libc::EROFS));
+        let err = dicfuse.access(&ctx, readme.inode, libc::X_OK as u32).unwrap_err();
+        assert_eq!(err.raw_os_error(), Some(libc::EACCES));
+        dicfuse.access(&ctx, run.inode, (libc::R_OK | libc::X_OK) as u32).unwrap();
+        let err = dicfuse.open(&ctx, readme.inode, libc::O_RDWR as u32, 0).unwrap_err();
+        assert_eq!(err.raw_os_error(), Some(libc::EROFS));
+
+        // the sizes come from the listings, only the blobs read are downloaded, once, and found
+        // in the cache by another mount
+        assert_eq!(source.reads.load(Ordering::SeqCst), 2);
+        let dicfuse = Dicfuse::with_store(DictionaryStore::with_source(source.clone(), cache_dir.clone(), "/").unwrap());
+        dicfuse.init(FsOptions::empty()).unwrap();
+        let readme = dicfuse.lookup(&ctx, 1, &CString::new("README.md").unwrap()).unwrap();
+        assert_eq!(readme.attr.st_size, 6);
+        let mut w = VecWriter(vec![]);
+        dicfuse.read(&ctx, readme.inode, 0, &mut w, 6, 0, None, 0).unwrap();
+        assert_eq!(source.reads.load(Ordering::SeqCst), 2);
+        std::fs::remove_dir_all(&cache_dir).unwrap();
+    }
+
+    #[test]
+    fn test_cached_and_refreshed_view() {
+        let source = Arc::new(MemorySource::default());
+        source.add("/README.md", "100644", Some("hello\n"));
+        source.add("/bin", "40000", None);
+        source.add("/bin/run.sh", "100755", Some("#!/bin/sh\n"));
+        source.add("/docs", "40000", None);
+        source.add("/docs/a.md", "100644", Some("a\n"));
+        source.set_head("c1");
+        let cache_dir = std::env::temp_dir().join(format!("scorpio-dicfuse-meta-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&cache_dir);
+        let open = || DictionaryStore::with_source(source.clone(), cache_dir.clone(), "/").unwrap();
+        let ctx = Context::new();
+        let lookup = |dicfuse: &Dicfuse, parent: u64, name: &str| {
+            dicfuse.lookup(&ctx, parent, &CString::new(name).unwrap()).unwrap()
+        };
+        let content = |dicfuse: &Dicfuse, inode: u64| {
+            let mut w = VecWriter(vec![]);
+            dicfuse.read(&ctx, inode, 0, &mut w, 100, 0, None, 0).unwrap();
+            String::from_utf8(w.0).unwrap()
+        };
+        let lists = || source.lists.load(Ordering::SeqCst);
+
+        let dicfuse = Dicfuse::with_store(open());
+        dicfuse.init(FsOptions::empty()).unwrap();
+        // only what is read is loaded
+        assert_eq!(lists(), 0);
+        let bin = lookup(&dicfuse, 1, "bin");
+        assert_eq!(read_dir(&dicfuse, bin.inode, 0), [".", "..", "run.sh"]);
+        assert_eq!(lists(), 2);
+
+        // another mount finds the inodes in the cache
+        let dicfuse = Dicfuse::with_store(open());
+        assert_eq!(lookup(&dicfuse, 1, "bin").inode, bin.inode);
+        assert_eq!(read_dir(&dicfuse, bin.inode, 0), [".", "..", "run.sh"]);
+        assert_eq!(lists(), 2);
+        // the first check reads the root only, bin didn't change
+        assert!(dicfuse.store.refresh().unwrap());
+        assert_eq!(lists(), 3);
+        assert!(!dicfuse.store.refresh().unwrap());
+        assert_eq!(lists(), 3);
+
+        let before = source.dirs.lock().unwrap().clone();
+        source.remove("/README.md");
+        source.add("/NEW.md", "100644", Some("new\n"));
+        source.remove("/bin/run.sh");
+        source.add("/bin/run.sh", "100755", Some("#!/bin/bash\n"));
+        source.set_head("c2");
+        assert!(dicfuse.store.refresh().unwrap());
+        // the root and bin, docs was never read
+        assert_eq!(lists(), 5);
+        assert_eq!(read_dir(&dicfuse, 1, 0), [".", "..", "NEW.md", "bin", "docs"]);
+        let missing = dicfuse.lookup(&ctx, 1, &CString::new("README.md").unwrap()).unwrap_err();
+        assert_eq!(missing.raw_os_error(), Some(libc::ENOENT));
+        // the changed directory and file keep their inodes
+        assert_eq!(lookup(&dicfuse, 1, "bin").inode, bin.inode);
+        let run = lookup(&dicfuse, bin.inode, "run.sh");
+        assert_eq!(content(&dicfuse, run.inode), "#!/bin/bash\n");
+
+        // the trees of a reverted commit are found by their ids
+        *source.dirs.lock().unwrap() = before;
+        source.set_head("c3");
+        assert!(dicfuse.store.refresh().unwrap());
+        assert_eq!(lists(), 6);
+        assert_eq!(read_dir(&dicfuse, 1, 0), [".", "..", "README.md", "bin", "docs"]);
+        assert_eq!(lookup(&dicfuse, bin.inode, "run.sh").inode, run.inode);
+        assert_eq!(content(&dicfuse, run.inode), "#!/bin/sh\n");
+
+        // and a new mount sees the refreshed tree
+        let dicfuse = Dicfuse::with_store(open());
+        assert_eq!(read_dir(&dicfuse, 1, 0), [".", "..", "README.md", "bin", "docs"]);
+        assert!(!dicfuse.store.refresh().unwrap());
+        assert_eq!(lists(), 6);
+        std::fs::remove_dir_all(&cache_dir).unwrap();
+    }
+
+    #[test]
+    fn test_listing_by_tree_id() {
+        let source = Arc::new(MemorySource::default());
+        source.add("/bin", "40000", None);
+        source.add("/bin/run.sh", "100755", Some("#!/bin/sh\n"));
+        let cache_dir = std::env::temp_dir().join(format!("scorpio-dicfuse-tree-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&cache_dir);
+        let dicfuse = Dicfuse::with_store(DictionaryStore::with_source(source.clone(), cache_dir.clone(), "/").unwrap());
+        dicfuse.init(FsOptions::empty()).unwrap();
+        let bin = dicfuse.lookup(&Context::new(), 1, &CString::new("bin").unwrap()).unwrap();
 
+        // a commit lands after the root was listed, bin is still the tree the root named
+        source.add("/bin/new.sh", "100755", Some("#!/bin/sh\n"));
+        assert_eq!(read_dir(&dicfuse, bin.inode, 0), [".", "..", "run.sh"]);
+        std::fs::remove_dir_all(&cache_dir).unwrap();
+    }
 }
@@ -1,29 +1,66 @@
-
 use fuse_backend_rs::api::filesystem::Entry;
 /// Read only file system for obtaining and displaying monorepo directory information
 use reqwest::Client;
 // Import Response explicitly
 use serde::{Deserialize, Serialize};
-use std::io;
-use std::sync::atomic::AtomicU64;
+use std::fs::{self, File};
+use std::future::Future;
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::{collections::HashMap, error::Error};
 use std::collections::VecDeque;
-use once_cell::sync::Lazy;
+use std::thread;
+use std::time::Duration;
+use base64::Engine;
 use radix_trie::{self, TrieCommon};
 use std::sync::{Arc,Mutex};
 
 
 use super::fuse;
+use crate::config::Config;
+use super::meta::{blob_size, inode, tree_entry, MetaStore};
 use super::model::GPath;
-const MEGA_TREE_URL: &str = "localhost:8000";//TODO: make it configable
 const UNKNOW_INODE: u64 = 0; // illegal inode number;
+const ROOT_INODE: u64 = 1;
+/// the metadata cache in the cache directory, shared by the stores of all the roots
+const META_DB: &str = "meta.db";
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
 pub struct Item {
-    name: String,
-    path: String,
-    content_type: String,
+    pub name: String,
+    pub path: String,
+    pub content_type: String,
+    /// the id of the blob or tree, the key of the content cache
+    #[serde(default)]
+    pub oid: String,
+    /// the git mode, like `100755` for an executable or `120000` for a symlink
+    #[serde(default)]
+    pub mode: String,
+    /// the size of the content of a file, older servers don't send it
+    #[serde(default)]
+    pub size: Option<u64>,
+    /// the time of the last commit of the monorepo, the tree API doesn't send it
+    #[serde(skip)]
+    pub mtime: u64,
+}
+
+/// Where the store reads the monorepo: the Mega API, or a fake one in the tests.
+pub trait MonorepoSource: Send + Sync {
+    /// The entries of the directory at `path`.
+    fn list(&self, path: &str) -> io::Result<Vec<Item>>;
+    /// The entries of the tree `oid`, the directory at `path` in some commit.
+    ///
+    /// Unlike the ones of [`list`](Self::list), they never change.
+    fn list_tree(&self, path: &str, oid: &str) -> io::Result<Vec<Item>>;
+    /// The content of the file at `path`, the blob `oid`.
+    fn read_blob(&self, path: &str, oid: &str) -> io::Result<Vec<u8>>;
+    /// The commit of the root ref, `None` when the source has no history to compare.
+    fn head(&self) -> io::Result<Option<String>> {
+        Ok(None)
+    }
 }
+
 #[allow(unused)]
 pub struct DicItem{
     inode:u64,
@@ -31,6 +68,9 @@ pub struct DicItem{
     content_type: Mutex<ContentType>,
     children:Mutex<HashMap<String, Arc<DicItem>>>,
     parent:u64,
+    oid: String,
+    mode: String,
+    mtime: u64,
 }
 
 #[allow(unused)]
@@ -44,14 +84,19 @@ impl DicItem {
     pub fn new(inode:u64,parent:u64, item:Item) -> Self {
         DicItem {
             inode,
-            name: item.name.into(), // Assuming GPath can be created from String
-            content_type: match item.content_type.as_str() {
-                "file" => ContentType::File.into(),
-                "directory" => ContentType::Dictionary(false).into(),
+            name: item.path.into(), // Assuming GPath can be created from String
+            content_type: match (item.content_type.as_str(), item.mode.as_str()) {
+                // a submodule is shown as an empty directory, like git does
+                (_, "160000") => ContentType::Dictionary(true).into(),
+                ("file", _) => ContentType::File.into(),
+                ("directory", _) => ContentType::Dictionary(false).into(),
                 _ => panic!("Unknown content type"),
             },
             children: Mutex::new(HashMap::new()),
             parent,
+            oid: item.oid,
+            mode: item.mode,
+            mtime: item.mtime,
         }
     }
     //get the total path
@@ -66,23 +111,71 @@ impl DicItem {
     pub fn push_children(&self,children:Arc<DicItem>){
         self.children.lock().unwrap().insert(children.get_path(), children);
     }
-    // get the inode 
+    // get the inode
     pub fn get_inode(&self)-> u64{
         self.inode
     }
-}
-
-pub trait IntoEntry {
-    fn into_entry(self) -> Entry;
-}
-
-impl IntoEntry for Arc<DicItem> {
-    fn into_entry(self) -> Entry {
-        match *self.content_type.lock().unwrap() {
-            ContentType::File => fuse::default_file_entry(self.inode),
-            ContentType::Dictionary(_) => fuse::default_dic_entry(self.inode),
+    // get the inode of the parent directory, the root is its own parent
+    pub fn get_parent(&self) -> u64 {
+        if self.parent == UNKNOW_INODE {
+            self.inode
+        } else {
+            self.parent
+        }
+    }
+    pub fn is_dir(&self) -> bool {
+        *self.content_type.lock().unwrap() != ContentType::File
+    }
+    pub fn is_link(&self) -> bool {
+        self.mode == "120000"
+    }
+    /// The file type and permissions, read only: `r-x` for directories and executables.
+    pub fn st_mode(&self) -> u32 {
+        if self.is_dir() {
+            return libc::S_IFDIR | 0o555;
+        }
+        match self.mode.as_str() {
+            "100755" => libc::S_IFREG | 0o555,
+            "120000" => libc::S_IFLNK | 0o777,
+            _ => libc::S_IFREG | 0o444,
+        }
+    }
+    fn from_model(model: &inode::Model) -> Self {
+        DicItem {
+            inode: model.inode as u64,
+            name: model.path.clone().into(),
+            content_type: match model.content_type.as_str() {
+                "file" => ContentType::File,
+                _ => ContentType::Dictionary(model.loaded),
+            }
+            .into(),
+            children: Mutex::new(HashMap::new()),
+            parent: model.parent as u64,
+            oid: model.oid.clone(),
+            mode: model.mode.clone(),
+            mtime: model.mtime as u64,
         }
     }
+    fn to_model(&self, root: &str) -> inode::Model {
+        let content_type = self.content_type.lock().unwrap();
+        inode::Model {
+            root: root.to_owned(),
+            inode: self.inode as i64,
+            parent: self.parent as i64,
+            path: self.get_path(),
+            oid: self.oid.clone(),
+            mode: self.mode.clone(),
+            content_type: if *content_type == ContentType::File { "file" } else { "directory" }.to_string(),
+            mtime: self.mtime as i64,
+            loaded: *content_type == ContentType::Dictionary(true),
+        }
+    }
+    /// The children sorted by name, so the offsets of `readdir` stay the same.
+    pub fn get_children(&self) -> Vec<Arc<DicItem>> {
+        let mut children: Vec<_> = self.children.lock().unwrap().values().cloned().collect();
+        children.sort_by_key(|c| c.get_name());
+        children
+    }
 }
 
 #[derive(Serialize, Deserialize, Debug,Default)]
@@ -97,16 +190,165 @@ impl Iterator for ApiResponse{
         self.data.pop()
     }
 }
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+struct BlobBatchResponse {
+    req_result: bool,
+    data: Option<BlobBatch>,
+    err_message: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+struct BlobBatch {
+    blobs: Vec<BlobData>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+struct BlobData {
+    oid: String,
+    /// the content, in base64
+    data: String,
+}
+
+#[derive(Deserialize, Debug)]
+struct TreeListingResponse {
+    req_result: bool,
+    data: Option<TreeListing>,
+    err_message: String,
+}
+
+/// A directory at a commit or by the id of its tree.
+#[derive(Deserialize, Debug)]
+pub(crate) struct TreeListing {
+    /// the id of the tree
+    pub oid: String,
+    pub items: Vec<Item>,
+}
+
+#[derive(Deserialize, Debug)]
+struct LatestCommit {
+    oid: String,
+    date: String,
+}
+
 // Get Mega dictionary tree from server
-async fn fetch_tree(path: &str) -> Result<ApiResponse, Box<dyn Error>> {
-    static CLIENT: Lazy<Client> = Lazy::new(Client::new);
-    let client = CLIENT.clone();
-    let url = format!("http://{}/api/v1/tree?path={}", MEGA_TREE_URL, path);
-    let  resp:ApiResponse = client.get(&url).send().await?.json().await?;
-    if resp.req_result {   
+async fn fetch_tree(client: &Client, url: &str, path: &str) -> Result<ApiResponse, Box<dyn Error>> {
+    let url = format!("http://{}/api/v1/tree", url);
+    let resp: ApiResponse = client.get(&url).query(&[("path", path)]).send().await?.json().await?;
+    if resp.req_result {
         Ok(resp)
-    }else{
-        todo!();
+    } else {
+        Err(resp.err_message.into())
+    }
+}
+
+// Get a directory from server, at a commit or by its tree id with `tree`, without the subdirectories
+pub(crate) async fn fetch_tree_at(
+    client: &Client,
+    url: &str,
+    path: &str,
+    at: (&str, &str),
+) -> Result<TreeListing, Box<dyn Error>> {
+    let url = format!("http://{}/api/v1/tree/at", url);
+    let query = [("path", path), at, ("depth", "1")];
+    let resp: TreeListingResponse = client.get(&url).query(&query).send().await?.json().await?;
+    match resp.data {
+        Some(listing) if resp.req_result => Ok(listing),
+        _ => Err(resp.err_message.into()),
+    }
+}
+
+// Get the content of a blob from server, by its id
+async fn fetch_blob(client: &Client, url: &str, oid: &str) -> Result<Vec<u8>, Box<dyn Error>> {
+    let url = format!("http://{}/api/v1/blobs", url);
+    let body = serde_json::json!({ "oids": [oid] });
+    let resp: BlobBatchResponse = client.post(&url).json(&body).send().await?.json().await?;
+    if !resp.req_result {
+        return Err(resp.err_message.into());
+    }
+    let blob = resp
+        .data
+        .and_then(|batch| batch.blobs.into_iter().find(|b| b.oid == oid))
+        .ok_or_else(|| format!("the server has no blob {}", oid))?;
+    Ok(base64::engine::general_purpose::STANDARD.decode(blob.data)?)
+}
+
+// Get the last commit of a directory from server
+async fn fetch_latest_commit(client: &Client, url: &str, path: &str) -> Result<LatestCommit, Box<dyn Error>> {
+    let url = format!("http://{}/api/v1/latest-commit", url);
+    Ok(client.get(&url).query(&[("path", path)]).send().await?.json().await?)
+}
+
+/// The monorepo of a Mega server, through its `/api/v1` routes.
+///
+/// Its calls block on a runtime of its own, with one client to reuse the connections.
+pub struct MegaSource {
+    url: String,
+    runtime: tokio::runtime::Runtime,
+    client: Client,
+    /// the time of the last commit of the monorepo, 0 until it is known
+    mtime: AtomicU64,
+}
+
+impl MegaSource {
+    pub fn new(url: &str) -> io::Result<Self> {
+        let runtime = tokio::runtime::Builder::new_multi_thread()
+            .worker_threads(1)
+            .enable_all()
+            .build()?;
+        Ok(MegaSource {
+            url: url.to_owned(),
+            runtime,
+            client: Client::new(),
+            mtime: AtomicU64::new(0),
+        })
+    }
+
+    fn block_on<T>(&self, future: impl Future<Output = Result<T, Box<dyn Error>>>) -> io::Result<T> {
+        self.runtime.block_on(future).map_err(|e| io::Error::other(e.to_string()))
+    }
+
+    /// The entries are as old as the last commit of the monorepo, checked with the root ref.
+    fn with_mtime(&self, mut items: Vec<Item>) -> Vec<Item> {
+        if self.mtime.load(Ordering::Relaxed) == 0 {
+            if let Err(e) = self.latest_commit() {
+                warn!("failed to get the last commit: {}", e);
+            }
+        }
+        let mtime = self.mtime.load(Ordering::Relaxed);
+        for item in items.iter_mut() {
+            item.mtime = mtime;
+        }
+        items
+    }
+
+    fn latest_commit(&self) -> io::Result<LatestCommit> {
+        let commit = self.block_on(fetch_latest_commit(&self.client, &self.url, "/"))?;
+        match commit.date.parse() {
+            Ok(mtime) => self.mtime.store(mtime, Ordering::Relaxed),
+            Err(e) => warn!("bad date {:?} of the last commit: {}", commit.date, e),
+        }
+        Ok(commit)
+    }
+}
+
+impl MonorepoSource for MegaSource {
+    fn list(&self, path: &str) -> io::Result<Vec<Item>> {
+        let items = self.block_on(fetch_tree(&self.client, &self.url, path))?.collect();
+        Ok(self.with_mtime(items))
+    }
+
+    fn list_tree(&self, path: &str, oid: &str) -> io::Result<Vec<Item>> {
+        let listing = self.block_on(fetch_tree_at(&self.client, &self.url, path, ("tree", oid)))?;
+        Ok(self.with_mtime(listing.items))
+    }
+
+    fn read_blob(&self, _path: &str, oid: &str) -> io::Result<Vec<u8>> {
+        self.block_on(fetch_blob(&self.client, &self.url, oid))
+    }
+
+    fn head(&self) -> io::Result<Option<String>> {
+        Ok(Some(self.latest_commit()?.oid))
     }
 }
 
@@ -114,91 +356,341 @@ async fn fetch_tree(path: &str) -> Result<ApiResponse, Box<dyn Error>> {
 pub struct DictionaryStore {
     inodes: Arc<Mutex<HashMap<u64, Arc<DicItem>>>>,
     next_inode: AtomicU64,
-    queue: Arc<Mutex<VecDeque<u64>>>,
     radix_trie: Arc<Mutex<radix_trie::Trie<String, u64>>>,
+    source: Arc<dyn MonorepoSource>,
+    // the contents of the files, in files named by their blob ids
+    cache_dir: PathBuf,
+    // the inodes and the trees, kept across mounts
+    meta: MetaStore,
 }
 
 
 #[allow(unused)]
 impl DictionaryStore {
-    pub fn new() -> Self {
-        let mut init = DictionaryStore {
+    pub fn new(config: &Config) -> io::Result<Self> {
+        Self::with_source(
+            Arc::new(MegaSource::new(&config.server)?),
+            config.cache_dir.clone(),
+            "/",
+        )
+    }
+    /// A store of the directory `root` of the monorepo, `/` for all of it.
+    ///
+    /// The inodes of the last mount of `root` are read from the metadata cache in `cache_dir`.
+    pub fn with_source(source: Arc<dyn MonorepoSource>, cache_dir: PathBuf, root: &str) -> io::Result<Self> {
+        fs::create_dir_all(&cache_dir)?;
+        let meta = MetaStore::open(&cache_dir.join(META_DB), root)?;
+        let init = DictionaryStore {
             next_inode: AtomicU64::new(2),
             inodes: Arc::new(Mutex::new(HashMap::new())),
             radix_trie: Arc::new(Mutex::new(radix_trie::Trie::new())),
-            queue: Arc::new(Mutex::new(VecDeque::new())),
-        };
-        let root_item = DicItem{
-            inode: 1,
-            name: GPath::new(),
-            content_type: ContentType::Dictionary(false).into(),
-            children: Mutex::new(HashMap::new()),
-            parent: UNKNOW_INODE, //  root dictory has no parent
+            source,
+            cache_dir,
+            meta,
         };
-        init
-    }
-    fn update_inode(&self,pitem:Option<Arc<DicItem>>,item:Item){
-        self.next_inode.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
-        let alloc_inode = self.next_inode.load(std::sync::atomic::Ordering::Relaxed);
-        self.radix_trie.lock().unwrap().insert(item.path.clone(), alloc_inode);
-
-        if let Some(parent) = pitem{
-            let newitem = Arc::new(DicItem::new(alloc_inode, parent.get_inode(),item));
-            parent.push_children(newitem.clone());
-            self.inodes.lock().unwrap().insert(alloc_inode, newitem);
-        }else{
-            self.inodes.lock().unwrap().insert(alloc_inode, Arc::new(DicItem::new(alloc_inode, UNKNOW_INODE,item)));
-        }
-       
-        self.queue.lock().unwrap().push_back(alloc_inode);
-    }
-    pub fn import(&self){
-        const ROOT_DIR: &str ="/";
-        let mut queue = VecDeque::new();
-        let items: Vec<Item> = tokio::runtime::Runtime::new().unwrap().block_on(fetch_tree(ROOT_DIR)).unwrap().collect();//todo: can't tokio
-        for it in items{
-            self.update_inode(None,it);
-        }
-        while !queue.is_empty() {//BFS to look up all dictionary
-            let one_inode = queue.pop_back().unwrap();
-            let mut new_items = Vec::new();
-            {
-                let inodes_lock = self.inodes.lock().unwrap();
-                let it = inodes_lock.get(&one_inode).unwrap();
-                if let ContentType::Dictionary(load) = *it.content_type.lock().unwrap(){
-                    if !load{
-                        let path = it.get_path();
-                        new_items = tokio::runtime::Runtime::new().unwrap().block_on(fetch_tree(&path)).unwrap().collect();
+        let rows = init.meta.inodes()?;
+        if rows.is_empty() {
+            let root_item = DicItem{
+                inode: ROOT_INODE,
+                name: GPath::from(root.to_string()),
+                content_type: ContentType::Dictionary(false).into(),
+                children: Mutex::new(HashMap::new()),
+                parent: UNKNOW_INODE, //  root dictory has no parent
+                oid: String::new(),
+                mode: String::new(),
+                mtime: 0,
+            };
+            init.meta.update_inodes(&[], vec![root_item.to_model(root)])?;
+            init.add_inode(Arc::new(root_item));
+        } else {
+            for row in rows {
+                let item = Arc::new(DicItem::from_model(&row));
+                if item.parent != UNKNOW_INODE {
+                    match init.get_inode(item.parent) {
+                        Ok(parent) => parent.push_children(item.clone()),
+                        Err(_) => {
+                            warn!("{} in the metadata cache has no parent", item.get_path());
+                            continue;
+                        }
                     }
-                   
                 }
-                let mut pc = it.clone();
-                for newit in new_items {
-                    self.update_inode(Some(pc.clone()),newit); // Await the update_inode call
+                init.next_inode.fetch_max(item.inode, Ordering::Relaxed);
+                init.add_inode(item);
+            }
+        }
+        Ok(init)
+    }
+    fn root(&self) -> String {
+        self.get_inode(ROOT_INODE).map(|r| r.get_path()).unwrap_or_default()
+    }
+    fn add_inode(&self, item: Arc<DicItem>) {
+        self.radix_trie.lock().unwrap().insert(item.get_path(), item.inode);
+        self.inodes.lock().unwrap().insert(item.inode, item);
+    }
+    fn update_inode(&self,parent:Arc<DicItem>,item:Item) -> Arc<DicItem> {
+        let alloc_inode = self.next_inode.fetch_add(1, Ordering::Relaxed) + 1;
+        let newitem = Arc::new(DicItem::new(alloc_inode, parent.get_inode(),item));
+        parent.push_children(newitem.clone());
+        self.add_inode(newitem.clone());
+        newitem
+    }
+    /// Forget an item and all it holds, their inodes are added to `removed`.
+    fn remove_inode(&self, item: &DicItem, removed: &mut Vec<u64>) {
+        for child in item.get_children() {
+            self.remove_inode(&child, removed);
+        }
+        self.radix_trie.lock().unwrap().remove(&item.get_path());
+        self.inodes.lock().unwrap().remove(&item.inode);
+        removed.push(item.inode);
+    }
+    /// Put `item` in the place of `old` with the same inode, so the kernel sees a file with
+    /// new content. The children of a loaded directory are kept, to be checked by the caller.
+    fn replace_inode(&self, parent: &DicItem, old: &DicItem, item: Item, removed: &mut Vec<u64>) -> Arc<DicItem> {
+        let mut newitem = DicItem::new(old.inode, parent.get_inode(), item);
+        let keep = old.is_dir()
+            && *newitem.content_type.lock().unwrap() == ContentType::Dictionary(false)
+            && *old.content_type.lock().unwrap() == ContentType::Dictionary(true);
+        if keep {
+            newitem.content_type = ContentType::Dictionary(true).into();
+            newitem.children = Mutex::new(old.children.lock().unwrap().clone());
+        } else {
+            for child in old.get_children() {
+                self.remove_inode(&child, removed);
+            }
+        }
+        let newitem = Arc::new(newitem);
+        parent.push_children(newitem.clone());
+        self.add_inode(newitem.clone());
+        newitem
+    }
+    /// The entries of a directory, read from the server once for each tree id.
+    fn list_dir(&self, item: &DicItem) -> io::Result<Vec<Item>> {
+        let path = item.get_path();
+        if !item.oid.is_empty() {
+            if let Some(entries) = self.meta.tree(&item.oid)? {
+                return Ok(entries
+                    .into_iter()
+                    .map(|e| Item {
+                        path: if path.ends_with('/') {
+                            format!("{}{}", path, e.name)
+                        } else {
+                            format!("{}/{}", path, e.name)
+                        },
+                        name: e.name,
+                        content_type: e.content_type,
+                        oid: e.oid,
+                        mode: e.mode,
+                        // the size is kept by the blob id
+                        size: None,
+                        // the server sends the time of the last commit of the directory
+                        mtime: item.mtime,
+                    })
+                    .collect());
+            }
+        }
+        if item.oid.is_empty() {
+            let items = self.source.list(&path)?;
+            self.save_sizes(&items)?;
+            return Ok(items);
+        }
+        // by the id, the latest directory at `path` may be another tree already
+        let items = self.source.list_tree(&path, &item.oid)?;
+        self.save_sizes(&items)?;
+        self.meta.save_tree(
+            items
+                .iter()
+                .map(|i| tree_entry::Model {
+                    tree: item.oid.clone(),
+                    name: i.name.clone(),
+                    oid: i.oid.clone(),
+                    mode: i.mode.clone(),
+                    content_type: i.content_type.clone(),
+                })
+                .collect(),
+        )?;
+        Ok(items)
+    }
+    fn save_sizes(&self, items: &[Item]) -> io::Result<()> {
+        self.meta.save_blob_sizes(
+            items
+                .iter()
+                .filter_map(|i| {
+                    Some(blob_size::Model {
+                        oid: i.oid.clone(),
+                        size: i.size? as i64,
+                    })
+                })
+                .collect(),
+        )
+    }
+    /// Read the entries of a directory, once.
+    pub fn load_dir(&self, inode: u64) -> io::Result<()> {
+        let item = self.get_inode(inode)?;
+        let mut content_type = item.content_type.lock().unwrap();
+        if *content_type != ContentType::Dictionary(false) {
+            return Ok(());
+        }
+        let mut rows: Vec<inode::Model> = Vec::new();
+        let root = self.root();
+        for newit in self.list_dir(&item)? {
+            rows.push(self.update_inode(item.clone(), newit).to_model(&root));
+        }
+        *content_type = ContentType::Dictionary(true);
+        drop(content_type);
+        rows.push(item.to_model(&root));
+        self.meta.update_inodes(&[], rows)
+    }
+    /// Load all the directories, instead of when they are read.
+    pub fn import(&self) -> io::Result<()> {
+        let mut queue = VecDeque::from([ROOT_INODE]);
+        while let Some(inode) = queue.pop_front() {//BFS to look up all dictionary
+            self.load_dir(inode)?;
+            let children = self.get_inode(inode)?.get_children();
+            queue.extend(children.iter().filter(|c| c.is_dir()).map(|c| c.get_inode()));
+        }
+        Ok(())
+    }
+    /// Check the root ref of the monorepo, and reload what changed since the last check.
+    ///
+    /// A directory with the same tree id is skipped with all it holds, so only the changed
+    /// directories are read. Returns whether the root ref moved.
+    pub fn refresh(&self) -> io::Result<bool> {
+        let head = self.source.head()?;
+        if head.is_some() && head == self.meta.head()? {
+            return Ok(false);
+        }
+        self.refresh_dir(&self.get_inode(ROOT_INODE)?)?;
+        if let Some(head) = head {
+            self.meta.set_head(&head)?;
+        }
+        Ok(true)
+    }
+    fn refresh_dir(&self, dir: &Arc<DicItem>) -> io::Result<()> {
+        // a directory never read has nothing to refresh, nor has a submodule
+        if *dir.content_type.lock().unwrap() != ContentType::Dictionary(true) || dir.mode == "160000" {
+            return Ok(());
+        }
+        let root = self.root();
+        let mut old = dir.children.lock().unwrap().clone();
+        let mut removed = Vec::new();
+        let mut saved = Vec::new();
+        let mut changed_dirs = Vec::new();
+        for item in self.list_dir(dir)? {
+            match old.remove(&item.path) {
+                Some(child) if child.oid == item.oid && child.mode == item.mode => {}
+                Some(child) => {
+                    let newitem = self.replace_inode(dir, &child, item, &mut removed);
+                    saved.push(newitem.to_model(&root));
+                    changed_dirs.push(newitem);
                 }
-                let mut content_type = pc.content_type.lock().unwrap();
-                *content_type = ContentType::Dictionary(true);
+                None => saved.push(self.update_inode(dir.clone(), item).to_model(&root)),
             }
-            new_items = Vec::new();
         }
-        //queue.clear();
+        for (path, child) in old {
+            dir.children.lock().unwrap().remove(&path);
+            self.remove_inode(&child, &mut removed);
+        }
+        self.meta.update_inodes(&removed, saved)?;
+        for child in changed_dirs {
+            self.refresh_dir(&child)?;
+        }
+        Ok(())
+    }
+    /// Refresh the store every `interval` in a thread, until the store is dropped.
+    pub fn watch(self: &Arc<Self>, interval: Duration) -> thread::JoinHandle<()> {
+        let store = Arc::downgrade(self);
+        thread::spawn(move || loop {
+            let Some(store) = store.upgrade() else {
+                return;
+            };
+            if let Err(e) = store.refresh() {
+                warn!("failed to refresh {}: {}", store.root(), e);
+            }
+            drop(store);
+            thread::sleep(interval);
+        })
     }
 
-    
+
     pub fn find_path(&self,inode :u64)-> Option<GPath>{
         self.inodes.lock().unwrap().get(&inode).map(|item| item.name.clone())
     }
     pub fn get_inode(&self,inode: u64) -> Result<Arc<DicItem>, io::Error> {
         match self.inodes.lock().unwrap().get(&inode) {
             Some(item) => Ok(item.clone()),
-            None=>Err(io::Error::new(io::ErrorKind::NotFound, "inode not found"))
+            None=>Err(io::Error::from_raw_os_error(libc::ENOENT))
         }
-       
+
     }
     pub fn get_by_path(&self, path: &str) -> Result<Arc<DicItem>, io::Error> {
-        let binding = self.radix_trie.lock().unwrap();
-        let inode = binding.get(path).ok_or(io::Error::new(io::ErrorKind::NotFound, "path not found"))?;
-        self.get_inode(*inode)
+        let inode = *self.radix_trie.lock().unwrap().get(path).ok_or(io::Error::from_raw_os_error(libc::ENOENT))?;
+        self.get_inode(inode)
+    }
+    /// The entry called `name` in the directory `parent`.
+    pub fn lookup(&self, parent: u64, name: &str) -> io::Result<Arc<DicItem>> {
+        let pitem = self.get_inode(parent)?;
+        if !pitem.is_dir() {
+            return Err(io::Error::from_raw_os_error(libc::ENOTDIR));
+        }
+        self.load_dir(parent)?;
+        let ppath = pitem.get_path();
+        let path = if ppath.ends_with('/') {
+            format!("{}{}", ppath, name)
+        } else {
+            format!("{}/{}", ppath, name)
+        };
+        self.get_by_path(&path)
+    }
+    /// The entries of the directory `inode`, sorted by name.
+    pub fn read_dir(&self, inode: u64) -> io::Result<Vec<Arc<DicItem>>> {
+        let item = self.get_inode(inode)?;
+        if !item.is_dir() {
+            return Err(io::Error::from_raw_os_error(libc::ENOTDIR));
+        }
+        self.load_dir(inode)?;
+        Ok(item.get_children())
+    }
+    /// The path of the content of a file in the cache, downloaded when it isn't there.
+    fn cached_blob(&self, item: &DicItem) -> io::Result<PathBuf> {
+        if item.oid.is_empty() {
+            return Err(io::Error::new(io::ErrorKind::NotFound, "the server sent no blob id"));
+        }
+        let path = self.cache_dir.join(&item.oid);
+        if !path.exists() {
+            let data = self.source.read_blob(&item.get_path(), &item.oid)?;
+            fs::create_dir_all(&self.cache_dir)?;
+            // write another file first, so a failed download never looks cached
+            let tmp = self.cache_dir.join(format!("{}.{}.tmp", item.oid, std::process::id()));
+            File::create(&tmp)?.write_all(&data)?;
+            fs::rename(&tmp, &path)?;
+        }
+        Ok(path)
+    }
+    /// The entry of an item, with the size of its content for a file.
+    ///
+    /// The size comes from the tree listing, a file is only downloaded for it when the server
+    /// didn't send it.
+    pub fn entry(&self, item: &DicItem) -> io::Result<Entry> {
+        let size = if item.is_dir() {
+            0
+        } else if let Some(size) = self.meta.blob_size(&item.oid)? {
+            size
+        } else {
+            fs::metadata(self.cached_blob(item)?)?.len()
+        };
+        Ok(fuse::item_entry(item.inode, item.st_mode(), size, item.mtime))
+    }
+    /// Read `size` bytes from `offset` of the file `inode`.
+    pub fn read_file(&self, inode: u64, offset: u64, size: u32) -> io::Result<Vec<u8>> {
+        let item = self.get_inode(inode)?;
+        if item.is_dir() {
+            return Err(io::Error::from_raw_os_error(libc::EISDIR));
+        }
+        let mut file = File::open(self.cached_blob(&item)?)?;
+        file.seek(SeekFrom::Start(offset))?;
+        let mut data = Vec::with_capacity(size as usize);
+        file.take(size as u64).read_to_end(&mut data)?;
+        Ok(data)
     }
     fn find_children(&self,parent: u64) -> Result<DicItem,io::Error>{
         let path = self.inodes.lock().unwrap().get(&parent).map(|item| item.name.clone());
@@ -220,7 +712,7 @@ mod tests {
     async fn test_fetch_tree_success() {
         let path: &str = "/third-part/mega";
 
-        let result = fetch_tree(path).await.unwrap();
+        let result = fetch_tree(&Client::new(), &Config::default().server, path).await.unwrap();
         println!("result: {:?}", result);
 
     }
@@ -242,4 +734,3 @@ mod tests {
         )
     }
 }
-
@@ -3,6 +3,9 @@
 extern crate log;
 mod passthrough;
 mod overlayfs;
-//mod store;
+pub mod store;
 mod fuse;
-mod dicfuse;
\ No newline at end of file
+pub mod dicfuse;
+pub mod config;
+pub mod daemon;
+
@@ -1,3 +1,156 @@
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::thread;
+
+use clap::{Parser, Subcommand};
+use signal_hook::consts::TERM_SIGNALS;
+use signal_hook::iterator::Signals;
+
+use scorpio::config::Config;
+use scorpio::daemon::{self, Daemon, Request, Response, WorkspaceInfo};
+use scorpio::dicfuse::store::MegaSource;
+use scorpio::store::{commit, WorkSpace};
+
+#[derive(Parser, Debug)]
+#[command(about = "Mount directories of a Mega monorepo and send their changes back")]
+struct Cli {
+    /// the configuration file, `scorpio.toml` in the current directory by default
+    #[arg(short, long)]
+    config: Option<PathBuf>,
+    #[command(subcommand)]
+    command: Commands,
+}
+
+#[derive(Subcommand, Debug)]
+enum Commands {
+    /// Serve the workspaces, until a termination signal
+    Daemon,
+    /// Mount a monorepo directory as a new workspace
+    Mount {
+        /// the monorepo directory, like `/third-party/mega`
+        path: String,
+        mountpoint: PathBuf,
+        /// the name of the workspace, the last component of the path by default
+        #[arg(long)]
+        name: Option<String>,
+    },
+    /// Unmount a workspace, its changes are kept
+    Umount { name: String },
+    /// List the workspaces
+    List,
+    /// Commit the changes of a workspace and push them to Mega, which opens a merge request
+    Commit {
+        /// the name of the workspace
+        name: String,
+        /// the commit message
+        #[arg(short, long)]
+        message: String,
+    },
+}
+
+fn load_config(path: Option<PathBuf>) -> Config {
+    let path = match path {
+        Some(path) => path,
+        None => {
+            let path = PathBuf::from("scorpio.toml");
+            if !path.exists() {
+                return Config::default();
+            }
+            path
+        }
+    };
+    Config::load(&path).unwrap_or_else(|e| fatal(format!("can't read {:?}: {}", path, e)))
+}
+
+fn fatal(message: impl std::fmt::Display) -> ! {
+    eprintln!("fatal: {}", message);
+    std::process::exit(1);
+}
+
+fn send(config: &Config, request: Request) -> Response {
+    match daemon::request(&config.socket(), &request) {
+        Ok(Response::Error(e)) => fatal(e),
+        Ok(response) => response,
+        Err(e) => fatal(e),
+    }
+}
+
+fn run_daemon(config: Config) {
+    let source = Arc::new(MegaSource::new(&config.server).unwrap_or_else(|e| fatal(e)));
+    let daemon = Arc::new(Daemon::new(config.clone(), source));
+    let listener = daemon.bind().unwrap_or_else(|e| fatal(e));
+    daemon.restore().unwrap_or_else(|e| fatal(e));
+
+    let mut signals = Signals::new(TERM_SIGNALS).unwrap();
+    let server = daemon.clone();
+    thread::spawn(move || server.serve(listener));
+    if let Some(signal) = signals.forever().next() {
+        eprintln!("received signal {}, unmounting the workspaces", signal);
+    }
+    daemon.shutdown();
+    let _ = std::fs::remove_file(config.socket());
+}
+
 fn main() {
-    println!("Hello, world!");
-}
\ No newline at end of file
+    let cli = Cli::parse();
+    let config = load_config(cli.config);
+    match cli.command {
+        Commands::Daemon => run_daemon(config),
+        Commands::Mount {
+            path,
+            mountpoint,
+            name,
+        } => {
+            let name = name.unwrap_or_else(|| match path.trim_end_matches('/').rsplit('/').next() {
+                Some(last) if !last.is_empty() => last.to_string(),
+                _ => "root".to_string(),
+            });
+            // the daemon runs elsewhere, so the mountpoint must be absolute
+            let mountpoint = std::path::absolute(mountpoint).unwrap_or_else(|e| fatal(e));
+            send(&config, Request::Mount(WorkspaceInfo { name, path, mountpoint }));
+        }
+        Commands::Umount { name } => {
+            send(&config, Request::Umount { name });
+        }
+        Commands::List => {
+            if let Response::Workspaces(workspaces) = send(&config, Request::List) {
+                for ws in workspaces {
+                    println!(
+                        "{}\t{}\t{}\t{}\t{} changed",
+                        ws.info.name,
+                        ws.info.path,
+                        ws.info.mountpoint.display(),
+                        if ws.mounted { "mounted" } else { "unmounted" },
+                        ws.changed_files
+                    );
+                }
+            }
+        }
+        Commands::Commit { name, message } => {
+            let info = daemon::load_workspaces(&config.state_file())
+                .unwrap_or_else(|e| fatal(e))
+                .into_iter()
+                .find(|info| info.name == name)
+                .unwrap_or_else(|| fatal(format!("no workspace {}", name)));
+            let workspace = WorkSpace::new(&info.path, &config.workspace_root.join(&info.name));
+            let source = MegaSource::new(&config.server).unwrap_or_else(|e| fatal(e));
+            let runtime = tokio::runtime::Runtime::new().unwrap();
+            let base = runtime
+                .block_on(commit::path_ref(&config.server, &info.path))
+                .unwrap_or_else(|e| fatal(e));
+            // the source blocks on its own runtime, so the tree is built outside of this one
+            let changes = match workspace.changes(&source, base.tree) {
+                Ok(Some(changes)) => changes,
+                Ok(None) => {
+                    println!("nothing to commit in {}", info.path);
+                    return;
+                }
+                Err(e) => fatal(e),
+            };
+            match runtime.block_on(commit::push_commit(&config.server, &info.path, &base, changes, &message)) {
+                Ok(id) => println!("[{}] {}", id.to_plain_str(), message),
+                Err(e) => fatal(e),
+            }
+        }
+    }
+}
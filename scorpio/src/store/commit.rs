@@ -0,0 +1,484 @@
+//! Turn the upper layer of a workspace into a commit of its monorepo path.
+//!
+//! The upper directory holds what was written through the overlay: new and changed files,
+//! whiteouts for deleted ones, and opaque directories replacing a lower directory. Its
+//! trees are merged with the trees of the commit of the path ref, read by their ids from a
+//! [`MonorepoSource`], so only the changed directories are read and the commit is pushed
+//! onto that ref like `git push` does.
+
+use std::collections::HashMap;
+use std::ffi::CString;
+use std::fs;
+use std::io;
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::fs::{FileTypeExt, MetadataExt};
+use std::path::Path;
+use std::str::FromStr;
+
+use bytes::{BufMut, BytesMut};
+use mercury::hash::SHA1;
+use mercury::internal::object::blob::Blob;
+use mercury::internal::object::commit::Commit;
+use mercury::internal::object::tree::{Tree, TreeItem, TreeItemMode};
+use mercury::internal::pack::encode::PackEncoder;
+use mercury::internal::pack::entry::Entry;
+use reqwest::Client;
+use tokio::sync::mpsc;
+
+use crate::dicfuse::store::{fetch_tree_at, MonorepoSource};
+
+/// The whiteout file marking a directory opaque, in the style of aufs.
+const OPAQUE_WHITEOUT: &str = ".wh..wh..opq";
+/// The prefix of a whiteout file in the style of aufs, `.wh.name` hides `name`.
+const WHITEOUT_PREFIX: &str = ".wh.";
+/// The extended attributes marking a directory opaque, set to `y`.
+const OPAQUE_XATTRS: [&str; 3] = [
+    "trusted.overlay.opaque",
+    "user.overlay.opaque",
+    "user.fuseoverlayfs.opaque",
+];
+
+/// The ref of a monorepo directory, which a commit of its workspace goes onto.
+pub struct PathRef {
+    pub name: String,
+    /// the commit of the ref, the parent of the new one and the old id of the ref update
+    pub commit: SHA1,
+    /// the tree of the commit, the lower layer the changes are merged with
+    pub tree: SHA1,
+}
+
+/// The new tree of a workspace and the objects the server doesn't have.
+pub struct Changes {
+    pub tree: SHA1,
+    /// the new blobs and trees
+    pub objects: Vec<Entry>,
+}
+
+fn other_error(e: impl ToString) -> io::Error {
+    io::Error::other(e.to_string())
+}
+
+/// Join a monorepo path and a name.
+pub(crate) fn join_path(path: &str, name: &str) -> String {
+    if path.ends_with('/') {
+        format!("{}{}", path, name)
+    } else {
+        format!("{}/{}", path, name)
+    }
+}
+
+/// A tree of `items` in git order, where a directory sorts as if its name ended with `/`.
+pub(crate) fn git_tree(mut items: Vec<TreeItem>) -> io::Result<Tree> {
+    let key = |item: &TreeItem| {
+        let mut key = item.name.as_bytes().to_vec();
+        if item.mode == TreeItemMode::Tree {
+            key.push(b'/');
+        }
+        key
+    };
+    items.sort_by_key(key);
+    Tree::from_tree_items(items).map_err(other_error)
+}
+
+/// The blob and mode of a file or symlink, `None` for what git can't store, like sockets.
+pub(crate) fn file_blob(path: &Path, meta: &fs::Metadata) -> io::Result<Option<(Blob, TreeItemMode)>> {
+    let file_type = meta.file_type();
+    if file_type.is_symlink() {
+        let target = fs::read_link(path)?;
+        let blob = Blob::from_content_bytes(target.as_os_str().as_bytes().to_vec());
+        Ok(Some((blob, TreeItemMode::Link)))
+    } else if file_type.is_file() {
+        let blob = Blob::from_content_bytes(fs::read(path)?);
+        let mode = if meta.mode() & 0o111 != 0 {
+            TreeItemMode::BlobExecutable
+        } else {
+            TreeItemMode::Blob
+        };
+        Ok(Some((blob, mode)))
+    } else {
+        Ok(None)
+    }
+}
+
+/// A whiteout of the kernel overlayfs: a character device with the device number 0.
+fn is_whiteout(meta: &fs::Metadata) -> bool {
+    meta.file_type().is_char_device() && meta.rdev() == 0
+}
+
+fn is_opaque(dir: &Path) -> bool {
+    if dir.join(OPAQUE_WHITEOUT).exists() {
+        return true;
+    }
+    let Ok(cpath) = CString::new(dir.as_os_str().as_bytes()) else {
+        return false;
+    };
+    OPAQUE_XATTRS.iter().any(|name| {
+        let cname = CString::new(*name).unwrap();
+        let mut value = [0u8; 1];
+        // Safe because the buffer is as long as the length given.
+        let len = unsafe {
+            libc::getxattr(
+                cpath.as_ptr(),
+                cname.as_ptr(),
+                value.as_mut_ptr() as *mut libc::c_void,
+                value.len(),
+            )
+        };
+        len == 1 && value[0] == b'y'
+    })
+}
+
+/// The items of the lower directory at `path`, the tree `oid`, by name.
+fn lower_items(source: &dyn MonorepoSource, path: &str, oid: SHA1) -> io::Result<HashMap<String, TreeItem>> {
+    let mut items = HashMap::new();
+    for item in source.list_tree(path, &oid.to_plain_str())? {
+        let mode = TreeItemMode::tree_item_type_from_bytes(item.mode.as_bytes()).map_err(other_error)?;
+        let id = SHA1::from_str(&item.oid).map_err(other_error)?;
+        items.insert(item.name.clone(), TreeItem::new(mode, id, item.name));
+    }
+    Ok(items)
+}
+
+/// The items of the directory `path` with the changes of `upper`, and the new objects.
+///
+/// `lower` is the tree of the lower directory, `None` when the upper one hides it.
+fn merge_dir(
+    source: &dyn MonorepoSource,
+    path: &str,
+    upper: &Path,
+    lower: Option<SHA1>,
+    objects: &mut Vec<Entry>,
+) -> io::Result<Vec<TreeItem>> {
+    let mut items = match lower {
+        Some(oid) => lower_items(source, path, oid)?,
+        None => HashMap::new(),
+    };
+    for entry in fs::read_dir(upper)? {
+        let entry = entry?;
+        let name = entry
+            .file_name()
+            .into_string()
+            .map_err(|name| other_error(format!("invalid file name {:?}", name)))?;
+        let meta = fs::symlink_metadata(entry.path())?;
+        if name == OPAQUE_WHITEOUT {
+            continue;
+        }
+        if is_whiteout(&meta) {
+            items.remove(&name);
+            continue;
+        }
+        if let Some(hidden) = name.strip_prefix(WHITEOUT_PREFIX) {
+            items.remove(hidden);
+            continue;
+        }
+
+        let old_id = items.get(&name).map(|item| item.id);
+        if meta.is_dir() {
+            // a directory replacing a file, or an opaque one, hides the lower layer
+            let lower = items
+                .get(&name)
+                .filter(|item| item.mode == TreeItemMode::Tree && !is_opaque(&entry.path()))
+                .map(|item| item.id);
+            let sub_items = merge_dir(source, &join_path(path, &name), &entry.path(), lower, objects)?;
+            // git doesn't keep empty directories
+            if sub_items.is_empty() {
+                items.remove(&name);
+                continue;
+            }
+            let tree = git_tree(sub_items)?;
+            items.insert(name.clone(), TreeItem::new(TreeItemMode::Tree, tree.id, name));
+            if old_id != Some(tree.id) {
+                objects.push(tree.into());
+            }
+        } else if let Some((blob, mode)) = file_blob(&entry.path(), &meta)? {
+            items.insert(name.clone(), TreeItem::new(mode, blob.id, name));
+            if old_id != Some(blob.id) {
+                objects.push(blob.into());
+            }
+        } else {
+            warn!("skip {:?}, it isn't a file, a directory or a symlink", entry.path());
+        }
+    }
+    Ok(items.into_values().collect())
+}
+
+/// Build the tree of the monorepo directory `path` with the changes in `upper` over the tree
+/// `lower`, the one of the commit the changes go onto.
+///
+/// Returns `None` when the tree is the same as `lower`.
+pub fn build_tree(source: &dyn MonorepoSource, path: &str, upper: &Path, lower: SHA1) -> io::Result<Option<Changes>> {
+    let mut objects = Vec::new();
+    let items = merge_dir(source, path, upper, (!is_opaque(upper)).then_some(lower), &mut objects)?;
+    if items.is_empty() {
+        return Err(other_error(format!("nothing would be left in {}", path)));
+    }
+    let tree = git_tree(items)?;
+    if tree.id == lower {
+        return Ok(None);
+    }
+    let id = tree.id;
+    objects.push(tree.into());
+    Ok(Some(Changes { tree: id, objects }))
+}
+
+fn add_pkt_line(buf: &mut BytesMut, line: &str) {
+    buf.put(format!("{:04x}", line.len() + 4).as_bytes());
+    buf.put(line.as_bytes());
+}
+
+/// The lines of a pkt-line stream, without the flush packets.
+fn read_pkt_lines(mut data: &[u8]) -> io::Result<Vec<String>> {
+    let mut lines = Vec::new();
+    while data.len() >= 4 {
+        let len = std::str::from_utf8(&data[..4])
+            .ok()
+            .and_then(|len| usize::from_str_radix(len, 16).ok())
+            .ok_or_else(|| other_error("invalid pkt-line"))?;
+        if len == 0 {
+            data = &data[4..];
+            continue;
+        }
+        if len < 4 || len > data.len() {
+            return Err(other_error("truncated pkt-line"));
+        }
+        lines.push(String::from_utf8_lossy(&data[4..len]).into_owned());
+        data = &data[len..];
+    }
+    Ok(lines)
+}
+
+fn repo_url(url: &str, path: &str) -> String {
+    format!("http://{}/{}.git", url, path.trim_matches('/'))
+}
+
+/// The ref the Mega server at `url` advertises for the monorepo directory `path`, and its tree.
+pub async fn path_ref(url: &str, path: &str) -> io::Result<PathRef> {
+    let client = Client::new();
+    let refs = client
+        .get(format!("{}/info/refs", repo_url(url, path)))
+        .query(&[("service", "git-receive-pack")])
+        .send()
+        .await
+        .map_err(other_error)?
+        .error_for_status()
+        .map_err(other_error)?
+        .bytes()
+        .await
+        .map_err(other_error)?;
+    // `<id> <ref>\0<capabilities>` for the first ref, after the service line
+    let head = read_pkt_lines(&refs)?
+        .into_iter()
+        .find(|line| !line.starts_with('#'))
+        .ok_or_else(|| other_error(format!("{} has no ref", path)))?;
+    let head = head.split('\0').next().unwrap().trim_end();
+    let (commit, name) = head
+        .split_once(' ')
+        .ok_or_else(|| other_error(format!("invalid ref line {:?}", head)))?;
+    // an empty path only advertises the capabilities, with the zero id
+    if commit.bytes().all(|b| b == b'0') {
+        return Err(other_error(format!("{} has no ref to commit onto", path)));
+    }
+    let commit = SHA1::from_str(commit).map_err(other_error)?;
+    // the commit of a path ref has the tree of the path
+    let tree = fetch_tree_at(&client, url, "/", ("commit", &commit.to_plain_str()))
+        .await
+        .map_err(other_error)?;
+    Ok(PathRef {
+        name: name.to_owned(),
+        commit,
+        tree: SHA1::from_str(&tree.oid).map_err(other_error)?,
+    })
+}
+
+/// Commit `changes` onto `base`, the ref they were built from, and push it to the monorepo
+/// directory `path` of the Mega server at `url`, which opens a merge request for it.
+///
+/// The commit of the ref is also the old id of the ref update, so the server can reject the
+/// push as a non-fast-forward if the ref moved since, instead of the commit reverting the
+/// changes made in the meantime.
+///
+/// Returns the id of the commit.
+pub async fn push_commit(url: &str, path: &str, base: &PathRef, changes: Changes, message: &str) -> io::Result<SHA1> {
+    let client = Client::new();
+    let parent = base.commit;
+    let commit = Commit::from_tree_id(changes.tree, vec![parent], &format!("\n{}", message));
+    let commit_id = commit.id;
+    let mut body = BytesMut::new();
+    add_pkt_line(
+        &mut body,
+        &format!(
+            "{} {} {}\0report-status\n",
+            parent.to_plain_str(),
+            commit_id.to_plain_str(),
+            base.name
+        ),
+    );
+    body.put(&b"0000"[..]);
+
+    let (entry_tx, entry_rx) = mpsc::channel::<Entry>(changes.objects.len() + 1);
+    let (stream_tx, mut stream_rx) = mpsc::channel(1024);
+    let encoder = PackEncoder::new(10, stream_tx);
+    let encoding = encoder.encode_async(entry_rx).await.map_err(other_error)?;
+    for entry in changes.objects.into_iter().chain([commit.into()]) {
+        entry_tx.send(entry).await.map_err(other_error)?;
+    }
+    drop(entry_tx);
+    while let Some(chunk) = stream_rx.recv().await {
+        body.extend_from_slice(&chunk);
+    }
+    encoding.await.map_err(other_error)?.map_err(other_error)?;
+
+    let res = client
+        .post(format!("{}/git-receive-pack", repo_url(url, path)))
+        .header("Content-Type", "application/x-git-receive-pack-request")
+        .body(body.freeze())
+        .send()
+        .await
+        .map_err(other_error)?
+        .error_for_status()
+        .map_err(other_error)?
+        .bytes()
+        .await
+        .map_err(other_error)?;
+    let status = read_pkt_lines(&res)?;
+    match status.as_slice() {
+        [unpack, update, ..] if unpack == "unpack ok\n" && update.starts_with("ok ") => Ok(commit_id),
+        _ => Err(other_error(format!("push rejected: {:?}", status))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::os::unix::fs::{symlink, PermissionsExt};
+    use std::path::PathBuf;
+
+    use mercury::internal::object::ObjectTrait;
+    use mercury::internal::object::types::ObjectType;
+
+    use super::*;
+    use crate::store::local::LocalSource;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("scorpio-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn write(dir: &Path, path: &str, content: &str) {
+        let path = dir.join(path);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, content).unwrap();
+    }
+
+    /// The files of the new tree and their content, from the objects or the lower directory.
+    fn files(changes: &Changes, lower: &Path) -> Vec<(String, String)> {
+        let objects: HashMap<SHA1, &Entry> = changes.objects.iter().map(|e| (e.hash, e)).collect();
+        let mut files = Vec::new();
+        let mut pending = vec![(String::new(), changes.tree)];
+        while let Some((dir, id)) = pending.pop() {
+            let tree = match objects.get(&id) {
+                Some(entry) => Tree::from_bytes(&entry.data, id).unwrap(),
+                // unchanged, the lower directory has it
+                None => {
+                    let local = LocalSource::new(lower);
+                    git_tree(lower_items(&local, &format!("/{}", dir), id).unwrap().into_values().collect()).unwrap()
+                }
+            };
+            for item in tree.tree_items {
+                let path = if dir.is_empty() { item.name.clone() } else { format!("{}/{}", dir, item.name) };
+                if item.mode == TreeItemMode::Tree {
+                    pending.push((path, item.id));
+                } else {
+                    let content = match objects.get(&item.id) {
+                        Some(entry) => String::from_utf8(entry.data.clone()).unwrap(),
+                        None => fs::read_to_string(lower.join(&path)).unwrap(),
+                    };
+                    files.push((path, content));
+                }
+            }
+        }
+        files.sort();
+        files
+    }
+
+    #[test]
+    fn test_build_tree() {
+        let lower = temp_dir("lower");
+        write(&lower, "README.md", "readme");
+        write(&lower, "src/main.rs", "fn main() {}");
+        write(&lower, "src/lib.rs", "lib");
+        write(&lower, "docs/a.md", "a");
+        write(&lower, "docs/b.md", "b");
+        write(&lower, "old/x.txt", "x");
+        let upper = temp_dir("upper");
+        let source = LocalSource::new(&lower);
+        let lower_root = source.tree("/").unwrap().unwrap();
+
+        assert!(build_tree(&source, "/", &upper, lower_root).unwrap().is_none());
+
+        // a changed file, a new file in a new directory and an executable
+        write(&upper, "src/main.rs", "fn main() { println!(); }");
+        write(&upper, "tools/run.sh", "#!/bin/sh");
+        fs::set_permissions(upper.join("tools/run.sh"), fs::Permissions::from_mode(0o755)).unwrap();
+        symlink("README.md", upper.join("link")).unwrap();
+        // deletions with a whiteout of each style
+        write(&upper, "docs/.wh.a.md", "");
+        write(&upper, "docs/.wh.b.md", "");
+        // a directory replaced by an opaque one
+        write(&upper, "old/.wh..wh..opq", "");
+        write(&upper, "old/y.txt", "y");
+
+        let changes = build_tree(&source, "/", &upper, lower_root).unwrap().unwrap();
+        assert_eq!(
+            files(&changes, &lower),
+            [
+                ("README.md", "readme"),
+                ("link", "README.md"),
+                ("old/y.txt", "y"),
+                ("src/lib.rs", "lib"),
+                ("src/main.rs", "fn main() { println!(); }"),
+                ("tools/run.sh", "#!/bin/sh"),
+            ]
+            .map(|(path, content)| (path.to_string(), content.to_string()))
+        );
+        // the unchanged files aren't sent again
+        assert!(changes
+            .objects
+            .iter()
+            .filter(|e| e.obj_type == ObjectType::Blob)
+            .all(|e| e.data != b"lib" && e.data != b"readme"));
+        let root = changes.objects.iter().find(|e| e.hash == changes.tree).unwrap();
+        let root = Tree::from_bytes(&root.data, changes.tree).unwrap();
+        let modes: HashMap<_, _> = root.tree_items.iter().map(|i| (i.name.as_str(), i.mode)).collect();
+        assert_eq!(modes["link"], TreeItemMode::Link);
+        assert!(!modes.contains_key("docs"));
+        let tools = root.tree_items.iter().find(|i| i.name == "tools").unwrap();
+        let tools = changes.objects.iter().find(|e| e.hash == tools.id).unwrap();
+        let tools = Tree::from_bytes(&tools.data, tools.hash).unwrap();
+        assert_eq!(tools.tree_items[0].mode, TreeItemMode::BlobExecutable);
+
+        // the same tree is built from a workspace of a subdirectory
+        let upper_src = temp_dir("upper-src");
+        write(&upper_src, "main.rs", "fn main() { println!(); }");
+        let src = source.tree("/src").unwrap().unwrap();
+        let changes = build_tree(&source, "/src", &upper_src, src).unwrap().unwrap();
+        assert_eq!(changes.objects.len(), 2);
+
+        fs::remove_dir_all(lower).unwrap();
+        fs::remove_dir_all(upper).unwrap();
+        fs::remove_dir_all(upper_src).unwrap();
+    }
+
+    #[test]
+    fn test_read_pkt_lines() {
+        let mut buf = BytesMut::new();
+        add_pkt_line(&mut buf, "# service=git-receive-pack\n");
+        buf.put(&b"0000"[..]);
+        add_pkt_line(&mut buf, "abc refs/heads/main\0report-status\n");
+        buf.put(&b"0000"[..]);
+        let lines = read_pkt_lines(&buf).unwrap();
+        assert_eq!(lines, ["# service=git-receive-pack\n", "abc refs/heads/main\0report-status\n"]);
+        assert!(read_pkt_lines(b"00ffabc").is_err());
+    }
+}
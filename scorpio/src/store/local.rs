@@ -0,0 +1,107 @@
+//! A monorepo read from a local directory, for the tests and for mounting a checkout.
+
+use std::fs;
+use std::io;
+use std::os::unix::ffi::OsStrExt;
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+use mercury::hash::SHA1;
+use mercury::internal::object::tree::{TreeItem, TreeItemMode};
+
+use super::commit::{file_blob, git_tree, join_path};
+use crate::dicfuse::store::{Item, MonorepoSource};
+
+/// Serves the directory `root` as the monorepo, with the ids git would give its files.
+pub struct LocalSource {
+    root: PathBuf,
+}
+
+impl LocalSource {
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        LocalSource { root: root.into() }
+    }
+
+    fn local_path(&self, path: &str) -> PathBuf {
+        self.root.join(path.trim_start_matches('/'))
+    }
+
+    /// The id of the tree of the directory at `path`, `None` if git wouldn't keep it.
+    pub fn tree(&self, path: &str) -> io::Result<Option<SHA1>> {
+        let dir = self.local_path(path);
+        if !dir.is_dir() {
+            return Ok(None);
+        }
+        Ok(Self::object(&dir)?.map(|(id, _)| id))
+    }
+
+    /// The id and mode of the file or directory at `path`, `None` if git wouldn't keep it.
+    fn object(path: &Path) -> io::Result<Option<(SHA1, TreeItemMode)>> {
+        let meta = fs::symlink_metadata(path)?;
+        if !meta.is_dir() {
+            return Ok(file_blob(path, &meta)?.map(|(blob, mode)| (blob.id, mode)));
+        }
+        let mut items = Vec::new();
+        for entry in fs::read_dir(path)? {
+            let entry = entry?;
+            if let Some((id, mode)) = Self::object(&entry.path())? {
+                items.push(TreeItem::new(mode, id, entry.file_name().to_string_lossy().into_owned()));
+            }
+        }
+        if items.is_empty() {
+            return Ok(None);
+        }
+        Ok(Some((git_tree(items)?.id, TreeItemMode::Tree)))
+    }
+}
+
+impl MonorepoSource for LocalSource {
+    fn list(&self, path: &str) -> io::Result<Vec<Item>> {
+        let dir = self.local_path(path);
+        if !dir.is_dir() {
+            return Ok(Vec::new());
+        }
+        let mut items = Vec::new();
+        for entry in fs::read_dir(dir)? {
+            let entry = entry?;
+            let Some((id, mode)) = Self::object(&entry.path())? else {
+                continue;
+            };
+            let name = entry.file_name().to_string_lossy().into_owned();
+            let mtime = entry
+                .metadata()?
+                .modified()?
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |d| d.as_secs());
+            items.push(Item {
+                path: join_path(path, &name),
+                name,
+                content_type: if mode == TreeItemMode::Tree { "directory" } else { "file" }.to_string(),
+                oid: id.to_plain_str(),
+                mode: String::from_utf8_lossy(mode.to_bytes()).into_owned(),
+                size: None,
+                mtime,
+            });
+        }
+        Ok(items)
+    }
+
+    /// Only the tree of the directory as it is now can be listed.
+    fn list_tree(&self, path: &str, oid: &str) -> io::Result<Vec<Item>> {
+        match self.tree(path)? {
+            Some(id) if id.to_plain_str() == oid => self.list(path),
+            _ => Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("{} isn't the tree of {}", oid, path),
+            )),
+        }
+    }
+
+    fn read_blob(&self, path: &str, _oid: &str) -> io::Result<Vec<u8>> {
+        let path = self.local_path(path);
+        if fs::symlink_metadata(&path)?.file_type().is_symlink() {
+            return Ok(fs::read_link(path)?.as_os_str().as_bytes().to_vec());
+        }
+        fs::read(path)
+    }
+}
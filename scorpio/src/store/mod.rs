@@ -1,23 +1,147 @@
+pub mod commit;
+pub mod local;
 
-#[allow(unused)]
-const READONLY_INODE: u64 = 0xffff_ffff;
+use std::fs;
+use std::io::{Error, Result};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use fuse_backend_rs::api::server::Server;
+use fuse_backend_rs::transport::{FuseChannel, FuseSession};
+use mercury::hash::SHA1;
+
+use crate::dicfuse::store::{DictionaryStore, MonorepoSource};
+use crate::dicfuse::Dicfuse;
+use crate::config::Config;
+use crate::overlayfs::config::Config as OverlayConfig;
+use crate::overlayfs::{BoxedLayer, OverlayFs};
+use crate::passthrough::passthrough::{self, PassthroughFs};
+
+use commit::Changes;
 
 #[allow(unused)]
 pub trait RepoStore {}
-#[allow(unused)]
-struct WorkSpace {
-    inode: u64,
-    path: String ,
+
+/// A writable view of a monorepo directory, like a client of Piper.
+///
+/// The monorepo is the read-only lower layer, served by [`Dicfuse`], and what is written
+/// goes to the `upper` directory. [`WorkSpace::changes`] turns it into a commit.
+pub struct WorkSpace {
+    /// the monorepo directory, like `/third-party/mega`
+    pub path: String,
+    /// the changes of the workspace
+    pub upper: PathBuf,
+    /// the scratch directory of the overlay
+    pub work: PathBuf,
 }
-#[allow(unused)]
+
 impl WorkSpace {
-    pub fn init(path:String ) {
-      
-        let ovl = WorkSpace::new(path, READONLY_INODE);
+    /// The workspace of `path`, kept in the directory `dir`.
+    pub fn new(path: &str, dir: &Path) -> WorkSpace {
+        WorkSpace {
+            path: path.to_owned(),
+            upper: dir.join("upper"),
+            work: dir.join("work"),
+        }
     }
-    pub fn new(path: String, inode: u64) -> WorkSpace {
-        WorkSpace { inode, path }
+
+    pub fn init(&self) -> Result<()> {
+        fs::create_dir_all(&self.upper)?;
+        fs::create_dir_all(&self.work)
+    }
+
+    /// The overlay of the upper directory on the monorepo read from `source`.
+    pub fn overlay(&self, source: Arc<dyn MonorepoSource>, config: &Config, mountpoint: &Path) -> Result<OverlayFs> {
+        let upper_config = passthrough::Config {
+            root_dir: self.upper.to_string_lossy().into_owned(),
+            // the whiteouts of opaque directories are xattrs
+            xattr: true,
+            do_import: true,
+            ..Default::default()
+        };
+        let upper = Box::new(PassthroughFs::<()>::new(upper_config)?);
+        upper.import()?;
+
+        let store = DictionaryStore::with_source(source, config.cache_dir.clone(), &self.path)?;
+        let lower = Box::new(Dicfuse::with_store(store));
+        lower.watch(Duration::from_secs(config.poll_interval));
+
+        let overlay_config = OverlayConfig {
+            work: self.work.to_string_lossy().into_owned(),
+            mountpoint: mountpoint.to_string_lossy().into_owned(),
+            do_import: true,
+            ..Default::default()
+        };
+        let overlay = OverlayFs::new(
+            Some(Arc::new(upper as BoxedLayer)),
+            vec![Arc::new(lower as BoxedLayer)],
+            overlay_config,
+        )?;
+        overlay.import()?;
+        Ok(overlay)
+    }
+
+    /// Mount the workspace at `mountpoint`, until [`Mount::umount`].
+    pub fn mount(&self, source: Arc<dyn MonorepoSource>, config: &Config, mountpoint: &Path) -> Result<Mount> {
+        self.init()?;
+        let overlay = self.overlay(source, config, mountpoint)?;
+        let mut session = FuseSession::new(mountpoint, "scorpio", "", false)
+            .map_err(|e| Error::other(e.to_string()))?;
+        session
+            .mount()
+            .map_err(|e| Error::other(e.to_string()))?;
+        let channel = session
+            .new_channel()
+            .map_err(|e| Error::other(e.to_string()))?;
+        let server = Arc::new(Server::new(Arc::new(overlay)));
+        let thread = thread::spawn(move || svc_loop(server, channel));
+        Ok(Mount { session, thread })
+    }
+
+    /// The tree of the workspace over the tree `lower` and its new objects, `None` if nothing changed.
+    pub fn changes(&self, source: &dyn MonorepoSource, lower: SHA1) -> Result<Option<Changes>> {
+        commit::build_tree(source, &self.path, &self.upper, lower)
     }
 }
-pub struct FileStore {}
 
+/// A mounted workspace.
+pub struct Mount {
+    session: FuseSession,
+    thread: JoinHandle<Result<()>>,
+}
+
+impl Mount {
+    pub fn umount(mut self) -> Result<()> {
+        self.session
+            .umount()
+            .map_err(|e| Error::other(e.to_string()))?;
+        self.session
+            .wake()
+            .map_err(|e| Error::other(e.to_string()))?;
+        self.thread
+            .join()
+            .map_err(|_| Error::other("the fuse server panicked"))?
+    }
+}
+
+fn svc_loop(server: Arc<Server<Arc<OverlayFs>>>, mut channel: FuseChannel) -> Result<()> {
+    loop {
+        let Some((reader, writer)) = channel
+            .get_request()
+            .map_err(|_| Error::from_raw_os_error(libc::EINVAL))?
+        else {
+            info!("fuse server exits");
+            return Ok(());
+        };
+        if let Err(e) = server.handle_message(reader, writer.into(), None, None) {
+            match e {
+                fuse_backend_rs::Error::EncodeMessage(_) => return Ok(()),
+                e => warn!("handling fuse message failed: {}", e),
+            }
+        }
+    }
+}
+
+pub struct FileStore {}
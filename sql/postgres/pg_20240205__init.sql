@@ -1,7 +1,7 @@
 CREATE TABLE IF NOT EXISTS "mega_commit" (
   "id" BIGINT PRIMARY KEY,
-  "commit_id" VARCHAR(40) NOT NULL,
-  "tree" VARCHAR(40) NOT NULL,
+  "commit_id" VARCHAR(64) NOT NULL,
+  "tree" VARCHAR(64) NOT NULL,
   "parents_id" JSON NOT NULL,  -- for compatibility with sqlite, DO NOT use Array Type
   "author" TEXT,
   "committer" TEXT,
@@ -12,17 +12,17 @@ CREATE TABLE IF NOT EXISTS "mega_commit" (
 CREATE INDEX "idx_mc_git_id" ON "mega_commit" ("commit_id");
 CREATE TABLE IF NOT EXISTS "mega_tree" (
   "id" BIGINT PRIMARY KEY,
-  "tree_id" VARCHAR(40) NOT NULL,
+  "tree_id" VARCHAR(64) NOT NULL,
   "sub_trees" BYTEA NOT NULL,
   "size" INT NOT NULL,
-  "commit_id" VARCHAR(40) NOT NULL,
+  "commit_id" VARCHAR(64) NOT NULL,
   "created_at" TIMESTAMP NOT NULL
 );
 CREATE INDEX "idx_mt_git_id" ON "mega_tree" ("tree_id");
 CREATE TABLE IF NOT EXISTS "mega_blob" (
   "id" BIGINT PRIMARY KEY,
-  "blob_id" VARCHAR(40) NOT NULL,
-  "commit_id" VARCHAR(40) NOT NULL,
+  "blob_id" VARCHAR(64) NOT NULL,
+  "commit_id" VARCHAR(64) NOT NULL,
   "name" TEXT NOT NULL,
   "size" INT NOT NULL,
   "created_at" TIMESTAMP NOT NULL
@@ -30,8 +30,8 @@ CREATE TABLE IF NOT EXISTS "mega_blob" (
 CREATE INDEX "idx_mb_git_id" ON "mega_blob" ("blob_id");
 CREATE TABLE IF NOT EXISTS "mega_tag" (
   "id" BIGINT PRIMARY KEY,
-  "tag_id" VARCHAR(40) NOT NULL,
-  "object_id" VARCHAR(40) NOT NULL,
+  "tag_id" VARCHAR(64) NOT NULL,
+  "object_id" VARCHAR(64) NOT NULL,
   "object_type" VARCHAR(20) NOT NULL,
   "tag_name" TEXT NOT NULL,
   "tagger" TEXT NOT NULL,
@@ -41,12 +41,12 @@ CREATE TABLE IF NOT EXISTS "mega_tag" (
 );
 CREATE TABLE IF NOT EXISTS "mega_mr" (
   "id" BIGINT PRIMARY KEY,
-  "mr_link" VARCHAR(40) NOT NULL,
+  "mr_link" VARCHAR(64) NOT NULL,
   "merge_date" TIMESTAMP,
   "status" VARCHAR(20) NOT NULL,
   "path" TEXT NOT NULL,
-  "from_hash" VARCHAR(40) NOT NULL,
-  "to_hash" VARCHAR(40) NOT NULL,
+  "from_hash" VARCHAR(64) NOT NULL,
+  "to_hash" VARCHAR(64) NOT NULL,
   "created_at" TIMESTAMP NOT NULL,
   "updated_at" TIMESTAMP NOT NULL
 );
@@ -85,8 +85,8 @@ CREATE TABLE IF NOT EXISTS "mega_issue" (
 CREATE TABLE IF NOT EXISTS "mega_refs" (
   "id" BIGINT PRIMARY KEY,
   "path" TEXT NOT NULL,
-  "ref_commit_hash" VARCHAR(40) NOT NULL,
-  "ref_tree_hash" VARCHAR(40) NOT NULL,
+  "ref_commit_hash" VARCHAR(64) NOT NULL,
+  "ref_tree_hash" VARCHAR(64) NOT NULL,
   "created_at" TIMESTAMP NOT NULL,
   "updated_at" TIMESTAMP NOT NULL,
   CONSTRAINT uniq_mref_path UNIQUE (path)
@@ -95,7 +95,7 @@ CREATE TABLE IF NOT EXISTS "import_refs" (
   "id" BIGINT PRIMARY KEY,
   "repo_id" BIGINT NOT NULL,
   "ref_name" TEXT NOT NULL,
-  "ref_git_id" VARCHAR(40) NOT NULL,
+  "ref_git_id" VARCHAR(64) NOT NULL,
   "ref_type" VARCHAR(20) NOT NULL,
   "default_branch" BOOLEAN NOT NULL,
   "created_at" TIMESTAMP NOT NULL,
@@ -115,8 +115,8 @@ CREATE INDEX "idx_ir_repo_path" ON "git_repo" ("repo_path");
 CREATE TABLE IF NOT EXISTS "git_commit" (
   "id" BIGINT PRIMARY KEY,
   "repo_id" BIGINT NOT NULL,
-  "commit_id" VARCHAR(40) NOT NULL,
-  "tree" VARCHAR(40) NOT NULL,
+  "commit_id" VARCHAR(64) NOT NULL,
+  "tree" VARCHAR(64) NOT NULL,
   "parents_id" JSON NOT NULL,
   "author" TEXT,
   "committer" TEXT,
@@ -129,10 +129,10 @@ CREATE INDEX "idx_ic_repo_id" ON "git_commit" ("repo_id");
 CREATE TABLE IF NOT EXISTS "git_tree" (
   "id" BIGINT PRIMARY KEY,
   "repo_id" BIGINT NOT NULL,
-  "tree_id" VARCHAR(40) NOT NULL,
+  "tree_id" VARCHAR(64) NOT NULL,
   "sub_trees" BYTEA NOT NULL,
   "size" INT NOT NULL,
-  "commit_id" VARCHAR(40) NOT NULL,
+  "commit_id" VARCHAR(64) NOT NULL,
   "created_at" TIMESTAMP NOT NULL,
   CONSTRAINT uniq_t_git_repo UNIQUE (repo_id, tree_id)
 );
@@ -141,10 +141,10 @@ CREATE INDEX "idx_t_repo_id" ON "git_tree" ("repo_id");
 CREATE TABLE IF NOT EXISTS "git_blob" (
   "id" BIGINT PRIMARY KEY,
   "repo_id" BIGINT NOT NULL,
-  "blob_id" VARCHAR(40) NOT NULL,
+  "blob_id" VARCHAR(64) NOT NULL,
   "name" VARCHAR(128),
   "size" INT NOT NULL,
-  "commit_id" VARCHAR(40) NOT NULL,
+  "commit_id" VARCHAR(64) NOT NULL,
   "created_at" TIMESTAMP NOT NULL,
   CONSTRAINT uniq_b_git_repo UNIQUE (repo_id, blob_id)
 );
@@ -152,8 +152,8 @@ CREATE INDEX "idx_b_git_id" ON "git_blob" ("blob_id");
 CREATE TABLE IF NOT EXISTS "git_tag" (
   "id" BIGINT PRIMARY KEY,
   "repo_id" BIGINT NOT NULL,
-  "tag_id" VARCHAR(40) NOT NULL,
-  "object_id" VARCHAR(40) NOT NULL,
+  "tag_id" VARCHAR(64) NOT NULL,
+  "object_id" VARCHAR(64) NOT NULL,
   "object_type" VARCHAR(20) NOT NULL,
   "tag_name" TEXT NOT NULL,
   "tagger" TEXT NOT NULL,
@@ -161,9 +161,104 @@ CREATE TABLE IF NOT EXISTS "git_tag" (
   "created_at" TIMESTAMP NOT NULL,
   CONSTRAINT uniq_gtag_tag_id UNIQUE (tag_id)
 );
+CREATE TABLE IF NOT EXISTS "git_pack" (
+  "id" BIGINT PRIMARY KEY,
+  "repo_id" BIGINT NOT NULL,
+  "pack_id" VARCHAR(64) NOT NULL,
+  "object_count" BIGINT NOT NULL,
+  "size" BIGINT NOT NULL,
+  "created_at" TIMESTAMP NOT NULL,
+  CONSTRAINT uniq_gpack_repo UNIQUE (repo_id, pack_id)
+);
+CREATE TABLE IF NOT EXISTS "git_pack_object" (
+  "id" BIGINT PRIMARY KEY,
+  "repo_id" BIGINT NOT NULL,
+  "pack_id" VARCHAR(64) NOT NULL,
+  "object_id" VARCHAR(64) NOT NULL,
+  "pack_offset" BIGINT NOT NULL,
+  CONSTRAINT uniq_gpo_pack_obj UNIQUE (repo_id, pack_id, object_id)
+);
+CREATE INDEX "idx_gpo_object_id" ON "git_pack_object" ("repo_id", "object_id");
+CREATE TABLE IF NOT EXISTS "commit_graph" (
+  "id" BIGINT PRIMARY KEY,
+  "repo_id" BIGINT NOT NULL,
+  "commit_id" VARCHAR(64) NOT NULL,
+  "generation" BIGINT NOT NULL,
+  "parents_id" JSON NOT NULL,
+  CONSTRAINT uniq_cg_repo_commit UNIQUE (repo_id, commit_id)
+);
+CREATE INDEX "idx_cg_repo_generation" ON "commit_graph" ("repo_id", "generation");
+CREATE TABLE IF NOT EXISTS "bitmap_object" (
+  "id" BIGINT PRIMARY KEY,
+  "repo_id" BIGINT NOT NULL,
+  "object_id" VARCHAR(64) NOT NULL,
+  "position" BIGINT NOT NULL,
+  CONSTRAINT uniq_bo_repo_obj UNIQUE (repo_id, object_id),
+  CONSTRAINT uniq_bo_repo_pos UNIQUE (repo_id, position)
+);
+CREATE TABLE IF NOT EXISTS "reachability_bitmap" (
+  "id" BIGINT PRIMARY KEY,
+  "repo_id" BIGINT NOT NULL,
+  "commit_id" VARCHAR(64) NOT NULL,
+  "bitmap" BYTEA NOT NULL,
+  "object_count" BIGINT NOT NULL,
+  "created_at" TIMESTAMP NOT NULL,
+  CONSTRAINT uniq_rb_repo_commit UNIQUE (repo_id, commit_id)
+);
+CREATE TABLE IF NOT EXISTS "search_head" (
+  "id" BIGINT PRIMARY KEY,
+  "path" TEXT NOT NULL,
+  "commit_id" VARCHAR(64) NOT NULL,
+  "tree_id" VARCHAR(64) NOT NULL,
+  "updated_at" TIMESTAMP NOT NULL,
+  CONSTRAINT uniq_sh_path UNIQUE (path)
+);
+CREATE TABLE IF NOT EXISTS "search_file" (
+  "id" BIGINT PRIMARY KEY,
+  "path" TEXT NOT NULL,
+  "blob_id" VARCHAR(64) NOT NULL,
+  "language" VARCHAR(32),
+  CONSTRAINT uniq_sf_path UNIQUE (path)
+);
+CREATE INDEX "idx_sf_blob_id" ON "search_file" ("blob_id");
+CREATE TABLE IF NOT EXISTS "search_blob" (
+  "id" BIGINT PRIMARY KEY,
+  "blob_id" VARCHAR(64) NOT NULL,
+  "size" BIGINT NOT NULL,
+  "is_text" BOOLEAN NOT NULL,
+  CONSTRAINT uniq_sb_blob_id UNIQUE (blob_id)
+);
+CREATE TABLE IF NOT EXISTS "search_trigram" (
+  "id" BIGINT PRIMARY KEY,
+  "trigram" INTEGER NOT NULL,
+  "blob_id" VARCHAR(64) NOT NULL,
+  CONSTRAINT uniq_st_trigram_blob UNIQUE (trigram, blob_id)
+);
+CREATE INDEX "idx_st_blob_id" ON "search_trigram" ("blob_id");
+CREATE TABLE IF NOT EXISTS "search_symbol" (
+  "id" BIGINT PRIMARY KEY,
+  "blob_id" VARCHAR(64) NOT NULL,
+  "name" TEXT NOT NULL,
+  "kind" VARCHAR(32) NOT NULL,
+  "line" INTEGER NOT NULL
+);
+CREATE INDEX "idx_ss_name" ON "search_symbol" ("name");
+CREATE INDEX "idx_ss_blob_id" ON "search_symbol" ("blob_id");
+CREATE TABLE IF NOT EXISTS "conventional_commit" (
+  "id" BIGINT PRIMARY KEY,
+  "commit_id" VARCHAR(64) NOT NULL,
+  "commit_type" VARCHAR(32) NOT NULL,
+  "scope" TEXT,
+  "breaking" BOOLEAN NOT NULL,
+  "description" TEXT NOT NULL,
+  "body" TEXT,
+  "footers" TEXT NOT NULL,
+  "created_at" TIMESTAMP NOT NULL,
+  CONSTRAINT uniq_cc_commit_id UNIQUE (commit_id)
+);
 CREATE TABLE IF NOT EXISTS "raw_blob" (
   "id" BIGINT PRIMARY KEY,
-  "sha1" VARCHAR(40) NOT NULL,
+  "sha1" VARCHAR(64) NOT NULL,
   "content" TEXT,
   "file_type" VARCHAR(20),
   "storage_type" VARCHAR(20) NOT NULL,
@@ -209,14 +304,15 @@ CREATE TABLE IF NOT EXISTS "git_issue" (
   "repo_id" BIGINT NOT NULL
 );
 CREATE TABLE IF NOT EXISTS "lfs_locks" (
-  "id" VARCHAR(40) PRIMARY KEY,
+  "id" VARCHAR(64) PRIMARY KEY,
   "data" TEXT NOT NULL
 );
 CREATE TABLE IF NOT EXISTS "lfs_objects" (
   "oid" VARCHAR(64) PRIMARY KEY,
   "size" BIGINT NOT NULL,
   "exist" BOOLEAN NOT NULL,
-  "splited" BOOLEAN NOT NULL
+  "splited" BOOLEAN NOT NULL,
+  "created_at" TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP
 );
 CREATE TABLE IF NOT EXISTS "lfs_split_relations" (
     "ori_oid" VARCHAR(64) NOT NULL,
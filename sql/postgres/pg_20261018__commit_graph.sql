@@ -0,0 +1,29 @@
+-- Create the commit graph and the reachability bitmaps of the import repos,
+-- run it on the databases created before, `pg_20240205__init.sql` creates them already.
+
+CREATE TABLE IF NOT EXISTS "commit_graph" (
+  "id" BIGINT PRIMARY KEY,
+  "repo_id" BIGINT NOT NULL,
+  "commit_id" VARCHAR(64) NOT NULL,
+  "generation" BIGINT NOT NULL,
+  "parents_id" JSON NOT NULL,
+  CONSTRAINT uniq_cg_repo_commit UNIQUE (repo_id, commit_id)
+);
+CREATE INDEX IF NOT EXISTS "idx_cg_repo_generation" ON "commit_graph" ("repo_id", "generation");
+CREATE TABLE IF NOT EXISTS "bitmap_object" (
+  "id" BIGINT PRIMARY KEY,
+  "repo_id" BIGINT NOT NULL,
+  "object_id" VARCHAR(64) NOT NULL,
+  "position" BIGINT NOT NULL,
+  CONSTRAINT uniq_bo_repo_obj UNIQUE (repo_id, object_id),
+  CONSTRAINT uniq_bo_repo_pos UNIQUE (repo_id, position)
+);
+CREATE TABLE IF NOT EXISTS "reachability_bitmap" (
+  "id" BIGINT PRIMARY KEY,
+  "repo_id" BIGINT NOT NULL,
+  "commit_id" VARCHAR(64) NOT NULL,
+  "bitmap" BYTEA NOT NULL,
+  "object_count" BIGINT NOT NULL,
+  "created_at" TIMESTAMP NOT NULL,
+  CONSTRAINT uniq_rb_repo_commit UNIQUE (repo_id, commit_id)
+);
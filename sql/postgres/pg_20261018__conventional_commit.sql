@@ -0,0 +1,15 @@
+-- Create the parsed conventional commit messages of the monorepo,
+-- run it on the databases created before, `pg_20240205__init.sql` creates it already.
+
+CREATE TABLE IF NOT EXISTS "conventional_commit" (
+  "id" BIGINT PRIMARY KEY,
+  "commit_id" VARCHAR(64) NOT NULL,
+  "commit_type" VARCHAR(32) NOT NULL,
+  "scope" TEXT,
+  "breaking" BOOLEAN NOT NULL,
+  "description" TEXT NOT NULL,
+  "body" TEXT,
+  "footers" TEXT NOT NULL,
+  "created_at" TIMESTAMP NOT NULL,
+  CONSTRAINT uniq_cc_commit_id UNIQUE (commit_id)
+);
@@ -0,0 +1,21 @@
+-- Create the tables of the received pack files, kept when `pack.store_import_packs` is on,
+-- run it on the databases created before, `pg_20240205__init.sql` creates them already.
+
+CREATE TABLE IF NOT EXISTS "git_pack" (
+  "id" BIGINT PRIMARY KEY,
+  "repo_id" BIGINT NOT NULL,
+  "pack_id" VARCHAR(64) NOT NULL,
+  "object_count" BIGINT NOT NULL,
+  "size" BIGINT NOT NULL,
+  "created_at" TIMESTAMP NOT NULL,
+  CONSTRAINT uniq_gpack_repo UNIQUE (repo_id, pack_id)
+);
+CREATE TABLE IF NOT EXISTS "git_pack_object" (
+  "id" BIGINT PRIMARY KEY,
+  "repo_id" BIGINT NOT NULL,
+  "pack_id" VARCHAR(64) NOT NULL,
+  "object_id" VARCHAR(64) NOT NULL,
+  "pack_offset" BIGINT NOT NULL,
+  CONSTRAINT uniq_gpo_pack_obj UNIQUE (repo_id, pack_id, object_id)
+);
+CREATE INDEX IF NOT EXISTS "idx_gpo_object_id" ON "git_pack_object" ("repo_id", "object_id");
@@ -0,0 +1,6 @@
+-- Add the creation time of the LFS objects, which the garbage collector keeps for `lfs.gc_grace_period`,
+-- run it on the databases created before, `pg_20240205__init.sql` creates the column already.
+-- The objects stored before get the time of the migration, so they are all kept for a grace period.
+
+ALTER TABLE "lfs_objects"
+  ADD COLUMN IF NOT EXISTS "created_at" TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP;
@@ -0,0 +1,42 @@
+-- Create the tables of the code search index,
+-- run it on the databases created before, `pg_20240205__init.sql` creates them already.
+
+CREATE TABLE IF NOT EXISTS "search_head" (
+  "id" BIGINT PRIMARY KEY,
+  "path" TEXT NOT NULL,
+  "commit_id" VARCHAR(64) NOT NULL,
+  "tree_id" VARCHAR(64) NOT NULL,
+  "updated_at" TIMESTAMP NOT NULL,
+  CONSTRAINT uniq_sh_path UNIQUE (path)
+);
+CREATE TABLE IF NOT EXISTS "search_file" (
+  "id" BIGINT PRIMARY KEY,
+  "path" TEXT NOT NULL,
+  "blob_id" VARCHAR(64) NOT NULL,
+  "language" VARCHAR(32),
+  CONSTRAINT uniq_sf_path UNIQUE (path)
+);
+CREATE INDEX IF NOT EXISTS "idx_sf_blob_id" ON "search_file" ("blob_id");
+CREATE TABLE IF NOT EXISTS "search_blob" (
+  "id" BIGINT PRIMARY KEY,
+  "blob_id" VARCHAR(64) NOT NULL,
+  "size" BIGINT NOT NULL,
+  "is_text" BOOLEAN NOT NULL,
+  CONSTRAINT uniq_sb_blob_id UNIQUE (blob_id)
+);
+CREATE TABLE IF NOT EXISTS "search_trigram" (
+  "id" BIGINT PRIMARY KEY,
+  "trigram" INTEGER NOT NULL,
+  "blob_id" VARCHAR(64) NOT NULL,
+  CONSTRAINT uniq_st_trigram_blob UNIQUE (trigram, blob_id)
+);
+CREATE INDEX IF NOT EXISTS "idx_st_blob_id" ON "search_trigram" ("blob_id");
+CREATE TABLE IF NOT EXISTS "search_symbol" (
+  "id" BIGINT PRIMARY KEY,
+  "blob_id" VARCHAR(64) NOT NULL,
+  "name" TEXT NOT NULL,
+  "kind" VARCHAR(32) NOT NULL,
+  "line" INTEGER NOT NULL
+);
+CREATE INDEX IF NOT EXISTS "idx_ss_name" ON "search_symbol" ("name");
+CREATE INDEX IF NOT EXISTS "idx_ss_blob_id" ON "search_symbol" ("blob_id");
@@ -0,0 +1,56 @@
+-- Widen the object and ref id columns to hold the 64 characters of a SHA-256 id,
+-- run it on the databases created before, `pg_20240205__init.sql` creates them wide already.
+-- Only the tables of the first release are altered, the later migrations create theirs wide.
+
+ALTER TABLE "mega_commit"
+  ALTER COLUMN "commit_id" TYPE VARCHAR(64),
+  ALTER COLUMN "tree" TYPE VARCHAR(64);
+ALTER TABLE "mega_tree"
+  ALTER COLUMN "tree_id" TYPE VARCHAR(64),
+  ALTER COLUMN "commit_id" TYPE VARCHAR(64);
+ALTER TABLE "mega_blob"
+  ALTER COLUMN "blob_id" TYPE VARCHAR(64),
+  ALTER COLUMN "commit_id" TYPE VARCHAR(64);
+ALTER TABLE "mega_tag"
+  ALTER COLUMN "tag_id" TYPE VARCHAR(64),
+  ALTER COLUMN "object_id" TYPE VARCHAR(64);
+ALTER TABLE "mega_mr"
+  ALTER COLUMN "mr_link" TYPE VARCHAR(64),
+  ALTER COLUMN "from_hash" TYPE VARCHAR(64),
+  ALTER COLUMN "to_hash" TYPE VARCHAR(64);
+ALTER TABLE "mega_refs"
+  ALTER COLUMN "ref_commit_hash" TYPE VARCHAR(64),
+  ALTER COLUMN "ref_tree_hash" TYPE VARCHAR(64);
+ALTER TABLE "import_refs"
+  ALTER COLUMN "ref_git_id" TYPE VARCHAR(64);
+ALTER TABLE "git_commit"
+  ALTER COLUMN "commit_id" TYPE VARCHAR(64),
+  ALTER COLUMN "tree" TYPE VARCHAR(64);
+ALTER TABLE "git_tree"
+  ALTER COLUMN "tree_id" TYPE VARCHAR(64),
+  ALTER COLUMN "commit_id" TYPE VARCHAR(64);
+ALTER TABLE "git_blob"
+  ALTER COLUMN "blob_id" TYPE VARCHAR(64),
+  ALTER COLUMN "commit_id" TYPE VARCHAR(64);
+ALTER TABLE "git_tag"
+  ALTER COLUMN "tag_id" TYPE VARCHAR(64),
+  ALTER COLUMN "object_id" TYPE VARCHAR(64);
+ALTER TABLE "raw_blob"
+  ALTER COLUMN "sha1" TYPE VARCHAR(64);
+ALTER TABLE "lfs_locks"
+  ALTER COLUMN "id" TYPE VARCHAR(64);
+ALTER TABLE "lfs_objects"
+  ALTER COLUMN "oid" TYPE VARCHAR(64);
+ALTER TABLE "lfs_split_relations"
+  ALTER COLUMN "ori_oid" TYPE VARCHAR(64),
+  ALTER COLUMN "sub_oid" TYPE VARCHAR(64);
+ALTER TABLE "ztm_node"
+  ALTER COLUMN "peer_id" TYPE VARCHAR(64),
+  ALTER COLUMN "hub" TYPE VARCHAR(64),
+  ALTER COLUMN "agent_name" TYPE VARCHAR(64),
+  ALTER COLUMN "service_name" TYPE VARCHAR(64),
+  ALTER COLUMN "type" TYPE VARCHAR(64);
+ALTER TABLE "ztm_repo_info"
+  ALTER COLUMN "name" TYPE VARCHAR(64),
+  ALTER COLUMN "origin" TYPE VARCHAR(64),
+  ALTER COLUMN "commit" TYPE VARCHAR(64);
@@ -161,6 +161,101 @@ CREATE TABLE IF NOT EXISTS "git_tag" (
   "created_at" TEXT NOT NULL,
   CONSTRAINT uniq_gtag_tag_id UNIQUE (tag_id)
 );
+CREATE TABLE IF NOT EXISTS "git_pack" (
+  "id" INTEGER PRIMARY KEY,
+  "repo_id" INTEGER NOT NULL,
+  "pack_id" TEXT NOT NULL,
+  "object_count" INTEGER NOT NULL,
+  "size" INTEGER NOT NULL,
+  "created_at" TEXT NOT NULL,
+  CONSTRAINT uniq_gpack_repo UNIQUE (repo_id, pack_id)
+);
+CREATE TABLE IF NOT EXISTS "git_pack_object" (
+  "id" INTEGER PRIMARY KEY,
+  "repo_id" INTEGER NOT NULL,
+  "pack_id" TEXT NOT NULL,
+  "object_id" TEXT NOT NULL,
+  "pack_offset" INTEGER NOT NULL,
+  CONSTRAINT uniq_gpo_pack_obj UNIQUE (repo_id, pack_id, object_id)
+);
+CREATE INDEX "idx_gpo_object_id" ON "git_pack_object" ("repo_id", "object_id");
+CREATE TABLE IF NOT EXISTS "commit_graph" (
+  "id" INTEGER PRIMARY KEY,
+  "repo_id" INTEGER NOT NULL,
+  "commit_id" TEXT NOT NULL,
+  "generation" INTEGER NOT NULL,
+  "parents_id" TEXT NOT NULL,
+  CONSTRAINT uniq_cg_repo_commit UNIQUE (repo_id, commit_id)
+);
+CREATE INDEX "idx_cg_repo_generation" ON "commit_graph" ("repo_id", "generation");
+CREATE TABLE IF NOT EXISTS "bitmap_object" (
+  "id" INTEGER PRIMARY KEY,
+  "repo_id" INTEGER NOT NULL,
+  "object_id" TEXT NOT NULL,
+  "position" INTEGER NOT NULL,
+  CONSTRAINT uniq_bo_repo_obj UNIQUE (repo_id, object_id),
+  CONSTRAINT uniq_bo_repo_pos UNIQUE (repo_id, position)
+);
+CREATE TABLE IF NOT EXISTS "reachability_bitmap" (
+  "id" INTEGER PRIMARY KEY,
+  "repo_id" INTEGER NOT NULL,
+  "commit_id" TEXT NOT NULL,
+  "bitmap" BLOB NOT NULL,
+  "object_count" INTEGER NOT NULL,
+  "created_at" TEXT NOT NULL,
+  CONSTRAINT uniq_rb_repo_commit UNIQUE (repo_id, commit_id)
+);
+CREATE TABLE IF NOT EXISTS "search_head" (
+  "id" INTEGER PRIMARY KEY,
+  "path" TEXT NOT NULL,
+  "commit_id" TEXT NOT NULL,
+  "tree_id" TEXT NOT NULL,
+  "updated_at" TEXT NOT NULL,
+  CONSTRAINT uniq_sh_path UNIQUE (path)
+);
+CREATE TABLE IF NOT EXISTS "search_file" (
+  "id" INTEGER PRIMARY KEY,
+  "path" TEXT NOT NULL,
+  "blob_id" TEXT NOT NULL,
+  "language" TEXT,
+  CONSTRAINT uniq_sf_path UNIQUE (path)
+);
+CREATE INDEX "idx_sf_blob_id" ON "search_file" ("blob_id");
+CREATE TABLE IF NOT EXISTS "search_blob" (
+  "id" INTEGER PRIMARY KEY,
+  "blob_id" TEXT NOT NULL,
+  "size" INTEGER NOT NULL,
+  "is_text" INTEGER NOT NULL,
+  CONSTRAINT uniq_sb_blob_id UNIQUE (blob_id)
+);
+CREATE TABLE IF NOT EXISTS "search_trigram" (
+  "id" INTEGER PRIMARY KEY,
+  "trigram" INTEGER NOT NULL,
+  "blob_id" TEXT NOT NULL,
+  CONSTRAINT uniq_st_trigram_blob UNIQUE (trigram, blob_id)
+);
+CREATE INDEX "idx_st_blob_id" ON "search_trigram" ("blob_id");
+CREATE TABLE IF NOT EXISTS "search_symbol" (
+  "id" INTEGER PRIMARY KEY,
+  "blob_id" TEXT NOT NULL,
+  "name" TEXT NOT NULL,
+  "kind" TEXT NOT NULL,
+  "line" INTEGER NOT NULL
+);
+CREATE INDEX "idx_ss_name" ON "search_symbol" ("name");
+CREATE INDEX "idx_ss_blob_id" ON "search_symbol" ("blob_id");
+CREATE TABLE IF NOT EXISTS "conventional_commit" (
+  "id" INTEGER PRIMARY KEY,
+  "commit_id" TEXT NOT NULL,
+  "commit_type" TEXT NOT NULL,
+  "scope" TEXT,
+  "breaking" INTEGER NOT NULL,
+  "description" TEXT NOT NULL,
+  "body" TEXT,
+  "footers" TEXT NOT NULL,
+  "created_at" TEXT NOT NULL,
+  CONSTRAINT uniq_cc_commit_id UNIQUE (commit_id)
+);
 CREATE TABLE IF NOT EXISTS "raw_blob" (
   "id" INTEGER PRIMARY KEY,
   "sha1" TEXT NOT NULL,
@@ -216,7 +311,8 @@ CREATE TABLE IF NOT EXISTS "lfs_objects" (
   "oid" TEXT PRIMARY KEY,
   "size" INTEGER NOT NULL,
   "exist" INTEGER NOT NULL,
-  "splited" INTEGER NOT NULL
+  "splited" INTEGER NOT NULL,
+  "created_at" TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP
 );
 CREATE TABLE IF NOT EXISTS "lfs_split_relations" (
   "ori_oid" TEXT NOT NULL,
@@ -0,0 +1,29 @@
+-- Create the commit graph and the reachability bitmaps of the import repos,
+-- run it on the databases created before, `sqlite_20240711_init.sql` creates them already.
+
+CREATE TABLE IF NOT EXISTS "commit_graph" (
+  "id" INTEGER PRIMARY KEY,
+  "repo_id" INTEGER NOT NULL,
+  "commit_id" TEXT NOT NULL,
+  "generation" INTEGER NOT NULL,
+  "parents_id" TEXT NOT NULL,
+  CONSTRAINT uniq_cg_repo_commit UNIQUE (repo_id, commit_id)
+);
+CREATE INDEX IF NOT EXISTS "idx_cg_repo_generation" ON "commit_graph" ("repo_id", "generation");
+CREATE TABLE IF NOT EXISTS "bitmap_object" (
+  "id" INTEGER PRIMARY KEY,
+  "repo_id" INTEGER NOT NULL,
+  "object_id" TEXT NOT NULL,
+  "position" INTEGER NOT NULL,
+  CONSTRAINT uniq_bo_repo_obj UNIQUE (repo_id, object_id),
+  CONSTRAINT uniq_bo_repo_pos UNIQUE (repo_id, position)
+);
+CREATE TABLE IF NOT EXISTS "reachability_bitmap" (
+  "id" INTEGER PRIMARY KEY,
+  "repo_id" INTEGER NOT NULL,
+  "commit_id" TEXT NOT NULL,
+  "bitmap" BLOB NOT NULL,
+  "object_count" INTEGER NOT NULL,
+  "created_at" TEXT NOT NULL,
+  CONSTRAINT uniq_rb_repo_commit UNIQUE (repo_id, commit_id)
+);
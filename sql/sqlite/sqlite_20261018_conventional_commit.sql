@@ -0,0 +1,15 @@
+-- Create the parsed conventional commit messages of the monorepo,
+-- run it on the databases created before, `sqlite_20240711_init.sql` creates it already.
+
+CREATE TABLE IF NOT EXISTS "conventional_commit" (
+  "id" INTEGER PRIMARY KEY,
+  "commit_id" TEXT NOT NULL,
+  "commit_type" TEXT NOT NULL,
+  "scope" TEXT,
+  "breaking" INTEGER NOT NULL,
+  "description" TEXT NOT NULL,
+  "body" TEXT,
+  "footers" TEXT NOT NULL,
+  "created_at" TEXT NOT NULL,
+  CONSTRAINT uniq_cc_commit_id UNIQUE (commit_id)
+);
@@ -0,0 +1,21 @@
+-- Create the tables of the received pack files, kept when `pack.store_import_packs` is on,
+-- run it on the databases created before, `sqlite_20240711_init.sql` creates them already.
+
+CREATE TABLE IF NOT EXISTS "git_pack" (
+  "id" INTEGER PRIMARY KEY,
+  "repo_id" INTEGER NOT NULL,
+  "pack_id" TEXT NOT NULL,
+  "object_count" INTEGER NOT NULL,
+  "size" INTEGER NOT NULL,
+  "created_at" TEXT NOT NULL,
+  CONSTRAINT uniq_gpack_repo UNIQUE (repo_id, pack_id)
+);
+CREATE TABLE IF NOT EXISTS "git_pack_object" (
+  "id" INTEGER PRIMARY KEY,
+  "repo_id" INTEGER NOT NULL,
+  "pack_id" TEXT NOT NULL,
+  "object_id" TEXT NOT NULL,
+  "pack_offset" INTEGER NOT NULL,
+  CONSTRAINT uniq_gpo_pack_obj UNIQUE (repo_id, pack_id, object_id)
+);
+CREATE INDEX IF NOT EXISTS "idx_gpo_object_id" ON "git_pack_object" ("repo_id", "object_id");
@@ -0,0 +1,8 @@
+-- Add the creation time of the LFS objects, which the garbage collector keeps for `lfs.gc_grace_period`,
+-- run it on the databases created before, `sqlite_20240711_init.sql` creates the column already.
+-- SQLite can't add a column defaulting to the current time, so the objects stored before
+-- are given the time of the migration afterwards, they are all kept for a grace period.
+
+ALTER TABLE "lfs_objects"
+  ADD COLUMN "created_at" TEXT NOT NULL DEFAULT '1970-01-01 00:00:00';
+UPDATE "lfs_objects" SET "created_at" = CURRENT_TIMESTAMP;
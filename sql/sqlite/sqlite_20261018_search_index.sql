@@ -0,0 +1,42 @@
+-- Create the tables of the code search index,
+-- run it on the databases created before, `sqlite_20240711_init.sql` creates them already.
+
+CREATE TABLE IF NOT EXISTS "search_head" (
+  "id" INTEGER PRIMARY KEY,
+  "path" TEXT NOT NULL,
+  "commit_id" TEXT NOT NULL,
+  "tree_id" TEXT NOT NULL,
+  "updated_at" TEXT NOT NULL,
+  CONSTRAINT uniq_sh_path UNIQUE (path)
+);
+CREATE TABLE IF NOT EXISTS "search_file" (
+  "id" INTEGER PRIMARY KEY,
+  "path" TEXT NOT NULL,
+  "blob_id" TEXT NOT NULL,
+  "language" TEXT,
+  CONSTRAINT uniq_sf_path UNIQUE (path)
+);
+CREATE INDEX IF NOT EXISTS "idx_sf_blob_id" ON "search_file" ("blob_id");
+CREATE TABLE IF NOT EXISTS "search_blob" (
+  "id" INTEGER PRIMARY KEY,
+  "blob_id" TEXT NOT NULL,
+  "size" INTEGER NOT NULL,
+  "is_text" INTEGER NOT NULL,
+  CONSTRAINT uniq_sb_blob_id UNIQUE (blob_id)
+);
+CREATE TABLE IF NOT EXISTS "search_trigram" (
+  "id" INTEGER PRIMARY KEY,
+  "trigram" INTEGER NOT NULL,
+  "blob_id" TEXT NOT NULL,
+  CONSTRAINT uniq_st_trigram_blob UNIQUE (trigram, blob_id)
+);
+CREATE INDEX IF NOT EXISTS "idx_st_blob_id" ON "search_trigram" ("blob_id");
+CREATE TABLE IF NOT EXISTS "search_symbol" (
+  "id" INTEGER PRIMARY KEY,
+  "blob_id" TEXT NOT NULL,
+  "name" TEXT NOT NULL,
+  "kind" TEXT NOT NULL,
+  "line" INTEGER NOT NULL
+);
+CREATE INDEX IF NOT EXISTS "idx_ss_name" ON "search_symbol" ("name");
+CREATE INDEX IF NOT EXISTS "idx_ss_blob_id" ON "search_symbol" ("blob_id");
@@ -1,7 +1,7 @@
 use serde::{Deserialize, Serialize};
 
 use callisto::{db_enums::RefType, import_refs};
-use common::utils::{generate_id, ZERO_ID};
+use common::utils::{generate_id, is_zero_id};
 
 ///
 /// Represent the references(all branches and tags) in protocol transfer
@@ -53,9 +53,9 @@ impl RefCommand {
     const FAILED_STATUS: &'static str = "ng";
 
     pub fn new(old_id: String, new_id: String, ref_name: String) -> Self {
-        let command_type = if ZERO_ID == old_id {
+        let command_type = if is_zero_id(&old_id) {
             CommandType::Create
-        } else if ZERO_ID == new_id {
+        } else if is_zero_id(&new_id) {
             CommandType::Delete
         } else {
             CommandType::Update
@@ -111,3 +111,21 @@ impl From<RefCommand> for import_refs::Model {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{CommandType, RefCommand};
+
+    #[test]
+    fn test_command_type() {
+        let id = |c: char, len: usize| c.to_string().repeat(len);
+        for len in [40, 64] {
+            let create = RefCommand::new(id('0', len), id('a', len), "refs/heads/main".into());
+            assert_eq!(create.command_type, CommandType::Create);
+            let delete = RefCommand::new(id('a', len), id('0', len), "refs/heads/main".into());
+            assert_eq!(delete.command_type, CommandType::Delete);
+            let update = RefCommand::new(id('a', len), id('b', len), "refs/heads/main".into());
+            assert_eq!(update.command_type, CommandType::Update);
+        }
+    }
+}